@@ -8,6 +8,7 @@ use bio::io::fasta as bio_fasta;
 use bio::io::fastq as bio_fastq;
 
 use entab::compression::decompress;
+use entab::filetype::{FileType, MAGIC_LEN};
 use entab::parsers::fasta::{FastaReader, FastaRecord};
 use entab::parsers::fastq::{FastqReader, FastqRecord};
 use entab::readers::init_state;
@@ -30,6 +31,19 @@ const BAM_PATH: &str = "../entab/tests/data/test.bam";
 const FASTA_PATH: &str = "../entab/tests/data/sequence.fasta";
 const FASTQ_PATH: &str = "../entab/tests/data/test.fastq";
 const SAM_PATH: &str = "../entab/tests/data/test.sam";
+const SNIFF_PATHS: &[&str] = &[
+    "../entab/tests/data/HTS_BD_LSR_II_Mixed_Specimen_001_D6_D06.fcs",
+    "../entab/tests/data/b3_alkanes.dxf",
+    "../entab/tests/data/bmp_24.png",
+    "../entab/tests/data/sequence.fasta",
+    "../entab/tests/data/small.RAW",
+    "../entab/tests/data/test-0000.cf",
+    "../entab/tests/data/test.bam",
+    "../entab/tests/data/test.csv.zst",
+    "../entab/tests/data/test.fastq",
+    "../entab/tests/data/test.sam",
+    "../entab/tests/data/test_fid.ch",
+];
 
 fn benchmark_bam(c: &mut Criterion) {
     let mut bam = c.benchmark_group("bam");
@@ -303,11 +317,34 @@ fn benchmark_sam(c: &mut Criterion) {
     });
 }
 
+fn benchmark_sniff(c: &mut Criterion) {
+    let mut sniff = c.benchmark_group("sniff");
+    sniff.significance_level(0.01).sample_size(100);
+
+    // detection only looks at the start of each file
+    let magics: Vec<Vec<u8>> = SNIFF_PATHS
+        .iter()
+        .map(|path| {
+            let mut magic = std::fs::read(path).unwrap();
+            magic.truncate(MAGIC_LEN);
+            magic
+        })
+        .collect();
+    sniff.bench_function("entab", |b| {
+        b.iter(|| {
+            for magic in &magics {
+                black_box(FileType::from_magic(black_box(magic)));
+            }
+        });
+    });
+}
+
 criterion_group!(
     benches,
     benchmark_bam,
     benchmark_fasta,
     benchmark_fastq,
-    benchmark_sam
+    benchmark_sam,
+    benchmark_sniff
 );
 criterion_main!(benches);
@@ -0,0 +1,47 @@
+#ifndef ENTAB_H
+#define ENTAB_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* A parser that data is pushed into as it arrives */
+typedef struct EntabPushParser EntabPushParser;
+
+/* Called with each record as a JSON array of its values (and the length of the JSON in
+ * bytes); returning anything but 0 stops parsing */
+typedef int (*EntabRecordCallback)(void *user_data, const char *record, size_t len);
+
+/* Create a parser; `parser` (e.g. "fastq") and `params` (a JSON object of parser options)
+ * can be NULL to detect the parser and use the defaults. Returns NULL if either can't be
+ * read. */
+EntabPushParser *entab_push_new(const char *parser, const char *params,
+                                EntabRecordCallback on_record, void *user_data);
+
+/* Add data and call the callback with every record it completes; returns 0 on success or
+ * -1 on an error (see entab_push_error) */
+int entab_push(EntabPushParser *parser, const uint8_t *data, size_t len);
+
+/* Mark the end of the data and call the callback with the rest of the records; returns 0
+ * on success or -1 on an error (see entab_push_error) */
+int entab_push_finish(EntabPushParser *parser);
+
+/* The headers as a JSON array of strings (NULL until they've been read); valid until the
+ * next call with this parser */
+const char *entab_push_headers(EntabPushParser *parser);
+
+/* The error from the last entab_push or entab_push_finish (NULL if it succeeded); valid
+ * until the next call with this parser */
+const char *entab_push_error(const EntabPushParser *parser);
+
+/* Free a parser */
+void entab_push_free(EntabPushParser *parser);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
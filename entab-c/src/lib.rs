@@ -0,0 +1,261 @@
+//! A C interface to entab's `PushParser` for programs (e.g. instrument acquisition software)
+//! that own their I/O loop and hand data to entab as it arrives.
+//!
+//! Each record is passed to a callback as a JSON array of its values (in the same order as the
+//! headers from `entab_push_headers`). The functions don't share any global state so separate
+//! parsers can be used from separate threads; see `include/entab.h` for the C declarations.
+use std::collections::BTreeMap;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+use std::slice;
+
+use entab_base::error::EtError;
+use entab_base::push::PushParser;
+use entab_base::record::Value;
+
+/// Called with the `user_data` given to `entab_push_new` and the JSON (and its length in bytes,
+/// not counting the trailing NUL) of each record; returning anything but 0 stops parsing.
+pub type EntabRecordCallback =
+    extern "C" fn(user_data: *mut c_void, record: *const c_char, len: usize) -> c_int;
+
+/// A `PushParser` along with the callback its records go to
+pub struct EntabPushParser {
+    parser: PushParser,
+    on_record: EntabRecordCallback,
+    user_data: *mut c_void,
+    /// The last error (kept here so the pointer handed out stays valid until the next call)
+    error: Option<CString>,
+    /// The headers as JSON (kept here for the same reason)
+    headers: Option<CString>,
+}
+
+impl EntabPushParser {
+    /// Send each record to the callback
+    fn send(&mut self, records: Vec<Vec<Value<'static>>>) -> Result<(), EtError> {
+        for record in records {
+            // JSON escapes any NULs in strings so it can always be made into a `CString`
+            let json = serde_json::to_string(&record).map_err(|e| e.to_string())?;
+            let json = CString::new(json).map_err(|e| e.to_string())?;
+            let len = json.as_bytes().len();
+            if (self.on_record)(self.user_data, json.as_ptr(), len) != 0 {
+                return Err("Parsing was stopped by the record callback".into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Turn the result of a push into a status code, keeping the error message if there is one
+    fn status(&mut self, result: Result<(), EtError>) -> c_int {
+        match result {
+            Ok(()) => {
+                self.error = None;
+                0
+            }
+            Err(e) => {
+                self.error = CString::new(e.to_string().replace('\0', "")).ok();
+                -1
+            }
+        }
+    }
+}
+
+/// Read a NUL-terminated UTF-8 string that may be NULL
+///
+/// # Safety
+/// `string` must be NULL or point to a NUL-terminated string.
+unsafe fn optional_str<'a>(string: *const c_char) -> Result<Option<&'a str>, EtError> {
+    if string.is_null() {
+        return Ok(None);
+    }
+    Ok(Some(CStr::from_ptr(string).to_str()?))
+}
+
+/// Create a parser that calls `on_record` with each record as it's parsed out of the pushed
+/// data. `parser` (the parser's name, e.g. "fastq") and `params` (a JSON object of parser
+/// options, e.g. `{"comment_char": "#"}`) can be NULL to detect the parser and use the defaults.
+///
+/// Returns NULL if `parser` or `params` can't be read.
+///
+/// # Safety
+/// `parser` and `params` must each be NULL or point to a NUL-terminated string, and
+/// `user_data` must stay valid for as long as `on_record` uses it.
+#[no_mangle]
+pub unsafe extern "C" fn entab_push_new(
+    parser: *const c_char,
+    params: *const c_char,
+    on_record: EntabRecordCallback,
+    user_data: *mut c_void,
+) -> *mut EntabPushParser {
+    let parser_name = match optional_str(parser) {
+        Ok(parser_name) => parser_name,
+        Err(_) => return ptr::null_mut(),
+    };
+    let params = match optional_str(params) {
+        Ok(Some(params)) => match serde_json::from_str::<BTreeMap<String, Value<'_>>>(params) {
+            Ok(params) => Some(params),
+            Err(_) => return ptr::null_mut(),
+        },
+        Ok(None) => None,
+        Err(_) => return ptr::null_mut(),
+    };
+    Box::into_raw(Box::new(EntabPushParser {
+        parser: PushParser::new(parser_name, params),
+        on_record,
+        user_data,
+        error: None,
+        headers: None,
+    }))
+}
+
+/// Add `len` bytes of `data` to the parser and call the callback with every record it
+/// completes.
+///
+/// Returns 0 on success or -1 if the data couldn't be parsed, the callback stopped parsing, or
+/// the parser's been finished (see `entab_push_error` for why).
+///
+/// # Safety
+/// `parser` must come from `entab_push_new` (and not have been freed) and `data` must point to
+/// at least `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn entab_push(
+    parser: *mut EntabPushParser,
+    data: *const u8,
+    len: usize,
+) -> c_int {
+    let parser = &mut *parser;
+    let data = if len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(data, len)
+    };
+    let result = parser
+        .parser
+        .push(data)
+        .and_then(|records| parser.send(records));
+    parser.status(result)
+}
+
+/// Mark the end of the data and call the callback with the records that were waiting on it.
+///
+/// Returns 0 on success or -1 if the data ended partway through a record (see
+/// `entab_push_error` for why).
+///
+/// # Safety
+/// `parser` must come from `entab_push_new` (and not have been freed).
+#[no_mangle]
+pub unsafe extern "C" fn entab_push_finish(parser: *mut EntabPushParser) -> c_int {
+    let parser = &mut *parser;
+    let result = parser
+        .parser
+        .finish()
+        .and_then(|records| parser.send(records));
+    parser.status(result)
+}
+
+/// The headers of the records as a JSON array of strings, or NULL if not enough data has been
+/// pushed to read them yet. The string is valid until the next call with this parser.
+///
+/// # Safety
+/// `parser` must come from `entab_push_new` (and not have been freed).
+#[no_mangle]
+pub unsafe extern "C" fn entab_push_headers(parser: *mut EntabPushParser) -> *const c_char {
+    let parser = &mut *parser;
+    if parser.parser.parser_name().is_none() {
+        return ptr::null();
+    }
+    parser.headers = serde_json::to_string(&parser.parser.headers())
+        .ok()
+        .and_then(|headers| CString::new(headers).ok());
+    parser.headers.as_ref().map_or(ptr::null(), |h| h.as_ptr())
+}
+
+/// The message for the error from the last `entab_push` or `entab_push_finish`, or NULL if it
+/// succeeded. The string is valid until the next call with this parser.
+///
+/// # Safety
+/// `parser` must come from `entab_push_new` (and not have been freed).
+#[no_mangle]
+pub unsafe extern "C" fn entab_push_error(parser: *const EntabPushParser) -> *const c_char {
+    (*parser).error.as_ref().map_or(ptr::null(), |e| e.as_ptr())
+}
+
+/// Free a parser made by `entab_push_new`.
+///
+/// # Safety
+/// `parser` must be NULL or come from `entab_push_new` (and not have been freed already).
+#[no_mangle]
+pub unsafe extern "C" fn entab_push_free(parser: *mut EntabPushParser) {
+    if !parser.is_null() {
+        drop(Box::from_raw(parser));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern "C" fn collect(user_data: *mut c_void, record: *const c_char, len: usize) -> c_int {
+        let records = unsafe { &mut *(user_data as *mut Vec<String>) };
+        let record = unsafe { CStr::from_ptr(record) }.to_str().unwrap();
+        assert_eq!(record.len(), len);
+        records.push(record.to_string());
+        0
+    }
+
+    extern "C" fn stop(_user_data: *mut c_void, _record: *const c_char, _len: usize) -> c_int {
+        1
+    }
+
+    #[test]
+    fn test_push_callbacks() {
+        let mut records: Vec<String> = Vec::new();
+        let params = CString::new(r##"{"comment_char": "#"}"##).unwrap();
+        unsafe {
+            let parser = entab_push_new(
+                b"tsv\0".as_ptr() as *const c_char,
+                params.as_ptr(),
+                collect,
+                &mut records as *mut Vec<String> as *mut c_void,
+            );
+            assert!(!parser.is_null());
+            assert!(entab_push_headers(parser).is_null());
+            let data = b"# run 1\nx\ty\n1\t2\n3\t";
+            assert_eq!(entab_push(parser, data.as_ptr(), data.len()), 0);
+            let headers = CStr::from_ptr(entab_push_headers(parser));
+            assert_eq!(headers.to_str().unwrap(), r#"["x","y"]"#);
+            assert_eq!(entab_push(parser, b"4\n".as_ptr(), 2), 0);
+            assert_eq!(entab_push_finish(parser), 0);
+            assert!(entab_push_error(parser).is_null());
+            assert_eq!(entab_push(parser, b"5\t6\n".as_ptr(), 4), -1);
+            assert!(!entab_push_error(parser).is_null());
+            entab_push_free(parser);
+        }
+        assert_eq!(records, ["[1,2]", "[3,4]"]);
+    }
+
+    #[test]
+    fn test_push_errors() {
+        unsafe {
+            let bad_params = b"{\0".as_ptr() as *const c_char;
+            assert!(entab_push_new(ptr::null(), bad_params, stop, ptr::null_mut()).is_null());
+
+            let parser = entab_push_new(ptr::null(), ptr::null(), stop, ptr::null_mut());
+            let data = b">seq\nACGT\n>seq2\n";
+            assert_eq!(entab_push(parser, data.as_ptr(), data.len()), -1);
+            let error = CStr::from_ptr(entab_push_error(parser));
+            assert!(error.to_str().unwrap().contains("stopped"));
+            entab_push_free(parser);
+
+            let parser = entab_push_new(
+                b"fastq\0".as_ptr() as *const c_char,
+                ptr::null(),
+                stop,
+                ptr::null_mut(),
+            );
+            assert_eq!(entab_push(parser, b"@id\nAC".as_ptr(), 6), 0);
+            assert_eq!(entab_push_finish(parser), -1);
+            entab_push_free(parser);
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use entab::readers::get_reader;
+use entab::writers::TsvWriter;
+use entab_cli::run;
+
+/// Number of FASTQ records to generate (~2 GB); can be lowered with `ENTAB_BENCH_RECORDS` for
+/// quick runs
+const DEFAULT_RECORDS: usize = 10_000_000;
+
+/// Write out a synthetic FASTQ (if it doesn't already exist) and return its path.
+///
+/// The file is written under a temporary name and only moved into place once it's complete so
+/// an interrupted run doesn't leave a truncated file behind to be benchmarked later.
+fn synthetic_fastq(n_records: usize) -> PathBuf {
+    let path = env::temp_dir().join(format!("entab-bench-{}.fastq", n_records));
+    if path.exists() {
+        return path;
+    }
+    let partial = tempfile::NamedTempFile::new_in(env::temp_dir()).unwrap();
+    let mut file = BufWriter::new(partial.as_file());
+    let bases = b"ACGT";
+    let mut seq = [b'A'; 100];
+    for i in 0..n_records {
+        for (j, base) in seq.iter_mut().enumerate() {
+            *base = bases[(i + j * 7) % 4];
+        }
+        writeln!(file, "@read_{} length=100", i).unwrap();
+        file.write_all(&seq).unwrap();
+        file.write_all(b"\n+\n").unwrap();
+        file.write_all(&[b'I'; 100]).unwrap();
+        file.write_all(b"\n").unwrap();
+    }
+    file.flush().unwrap();
+    drop(file);
+    let _ = partial.persist(&path).unwrap();
+    path
+}
+
+/// Convert the FASTQ the way the CLI used to: streaming it in from the file, but writing each
+/// field (and delimiter) straight to an unbuffered file.
+fn unbuffered_fastq_to_tsv(input: &Path, output: &Path) {
+    let (mut reader, _) = get_reader(File::open(input).unwrap(), Some("fastq"), None).unwrap();
+    let mut formatter = TsvWriter::new(Vec::new());
+    let mut writer = File::create(output).unwrap();
+    writer
+        .write_all(reader.headers().join("\t").as_bytes())
+        .unwrap();
+    writer.write_all(b"\n").unwrap();
+    while let Some(fields) = reader.next_record().unwrap() {
+        for (ix, field) in fields.iter().enumerate() {
+            if ix > 0 {
+                writer.write_all(b"\t").unwrap();
+            }
+            let text = formatter.format_field(field).unwrap();
+            writer.write_all(text.as_bytes()).unwrap();
+        }
+        writer.write_all(b"\n").unwrap();
+    }
+}
+
+fn benchmark_cli(c: &mut Criterion) {
+    let n_records = env::var("ENTAB_BENCH_RECORDS")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(DEFAULT_RECORDS);
+    let input = synthetic_fastq(n_records);
+    let output = tempfile::NamedTempFile::new().unwrap();
+    let output_path = output.path().to_path_buf();
+
+    let mut cli = c.benchmark_group("cli");
+    cli.sample_size(10);
+    cli.bench_function(
+        format!("fastq to tsv, field by field ({} records)", n_records),
+        |b| b.iter(|| unbuffered_fastq_to_tsv(&input, &output_path)),
+    );
+    cli.bench_function(format!("fastq to tsv ({} records)", n_records), |b| {
+        b.iter(|| {
+            run(
+                [
+                    "entab",
+                    "-i",
+                    input.to_str().unwrap(),
+                    "-o",
+                    output_path.to_str().unwrap(),
+                ],
+                io::empty(),
+                io::sink(),
+            )
+            .unwrap();
+        })
+    });
+    cli.finish();
+}
+
+criterion_group!(benches, benchmark_cli);
+criterion_main!(benches);
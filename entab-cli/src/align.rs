@@ -0,0 +1,67 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use entab::align::{AlignMethod, AlignedReader, Alignment, Trace};
+use entab::readers::RecordReader;
+use entab::EtError;
+
+use crate::open_path;
+
+/// Read the trace in each file at `paths` and find how to align it to the first one (the
+/// reference) with `method`, only looking for shifts of up to `max_shift` minutes.
+///
+/// # Errors
+/// If a file can't be read, doesn't have `time` and `intensity` columns, or can't be aligned,
+/// an error is returned.
+pub fn find_alignments(
+    paths: &[&Path],
+    method: AlignMethod,
+    max_shift: f64,
+) -> Result<Vec<Alignment>, EtError> {
+    let mut traces = Vec::with_capacity(paths.len());
+    for path in paths {
+        let (mut reader, _) = open_path(path, BTreeMap::new())?;
+        let trace = Trace::from_reader(&mut *reader)
+            .map_err(|e| EtError::from(format!("{}: {}", path.display(), e)))?;
+        traces.push(trace);
+    }
+    let reference = match traces.first() {
+        Some(reference) => reference,
+        None => return Ok(Vec::new()),
+    };
+    let mut alignments = vec![Alignment::identity()];
+    for (path, trace) in paths.iter().zip(&traces).skip(1) {
+        let alignment = Alignment::find(reference, trace, method, max_shift)
+            .map_err(|e| EtError::from(format!("{}: {}", path.display(), e)))?;
+        alignments.push(alignment);
+    }
+    Ok(alignments)
+}
+
+/// Open the file at `path` again to read its records with an `aligned_time` column
+pub fn aligned_reader(path: &Path, alignment: Alignment) -> Result<Box<dyn RecordReader>, EtError> {
+    let (reader, _) = open_path(path, BTreeMap::new())?;
+    Ok(Box::new(AlignedReader::new(reader, alignment)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_alignments() -> Result<(), EtError> {
+        let path = Path::new("../entab/tests/data/test_fid.ch");
+        let alignments = find_alignments(&[path, path], AlignMethod::CrossCorrelation, 0.5)?;
+        assert_eq!(alignments.len(), 2);
+        assert!(alignments[1].start_shift().abs() < 1e-6);
+
+        let mut reader = aligned_reader(path, alignments[1].clone())?;
+        assert_eq!(reader.headers(), ["time", "aligned_time", "intensity"]);
+        let record = reader.next_record()?.unwrap();
+        assert_eq!(record[0], record[1]);
+
+        let fasta = Path::new("../entab/tests/data/sequence.fasta");
+        assert!(find_alignments(&[path, fasta], AlignMethod::Peaks, 0.5).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+
+use entab::EtError;
+
+use crate::watch::convert_file;
+
+/// Convert each of `paths` into a TSV (and a JSON file of its metadata) in `output_dir`, using
+/// up to `jobs` threads that each open their own files; conversions don't depend on each other
+/// so files are handed out to whichever thread is free next.
+///
+/// `on_converted` is called (from the thread that did the conversion) with each path and the
+/// result of converting it as soon as it's done. Returns the results in the same order as
+/// `paths`.
+///
+/// # Errors
+/// If two inputs would be written to the same output file, an error is returned before any are
+/// converted, and if a conversion thread panics an error is returned after the others finish.
+/// Errors converting individual files are returned in the results instead.
+pub fn convert_files<F>(
+    paths: &[PathBuf],
+    output_dir: &Path,
+    parser: Option<&str>,
+    jobs: usize,
+    on_converted: F,
+) -> Result<Vec<Result<PathBuf, EtError>>, EtError>
+where
+    F: Fn(&Path, &Result<PathBuf, EtError>) + Sync,
+{
+    // outputs are named after the input's file name so inputs from different directories can
+    // collide
+    let mut names: BTreeMap<_, &Path> = BTreeMap::new();
+    for path in paths {
+        let name = path
+            .file_name()
+            .ok_or_else(|| format!("{} has no file name", path.display()))?;
+        if let Some(other) = names.insert(name, path) {
+            return Err(format!(
+                "{} and {} would both be written to {}.tsv",
+                other.display(),
+                path.display(),
+                name.to_string_lossy()
+            )
+            .into());
+        }
+    }
+
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<Result<PathBuf, EtError>>>> =
+        Mutex::new(paths.iter().map(|_| None).collect());
+    // join the threads ourselves so a panic in one comes back as an error instead of
+    // propagating out of the scope
+    let panicked = thread::scope(|scope| {
+        let handles: Vec<_> = (0..jobs.clamp(1, paths.len().max(1)))
+            .map(|_| {
+                scope.spawn(|| loop {
+                    let ix = next.fetch_add(1, Ordering::Relaxed);
+                    let path = match paths.get(ix) {
+                        Some(path) => path,
+                        None => break,
+                    };
+                    let result = convert_file(path, output_dir, parser);
+                    on_converted(path, &result);
+                    if let Ok(mut results) = results.lock() {
+                        results[ix] = Some(result);
+                    }
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join())
+            .filter(Result::is_err)
+            .count()
+    });
+    if panicked > 0 {
+        return Err(format!("{} conversion thread(s) panicked", panicked).into());
+    }
+    results
+        .into_inner()
+        .unwrap_or_else(PoisonError::into_inner)
+        .into_iter()
+        .map(|result| result.ok_or_else(|| EtError::from("A file was never converted")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_convert_files() -> Result<(), EtError> {
+        let dir = tempfile::tempdir()?;
+        let out_dir = dir.path();
+        let paths = vec![
+            PathBuf::from("../entab/tests/data/test_fid.ch"),
+            PathBuf::from("../entab/tests/data/sequence.fasta"),
+            PathBuf::from("../entab/tests/data/not_a_file.ch"),
+            PathBuf::from("../entab/tests/data/test.sam"),
+        ];
+        let seen = AtomicUsize::new(0);
+        let results = convert_files(&paths, out_dir, None, 3, |_, _| {
+            let _ = seen.fetch_add(1, Ordering::Relaxed);
+        })?;
+        assert_eq!(seen.into_inner(), 4);
+        assert_eq!(results.len(), 4);
+        assert!(fs::read(results[0].as_ref().unwrap())?.starts_with(b"time\tintensity\n"));
+        assert!(fs::read(results[1].as_ref().unwrap())?.starts_with(b"id\tsequence\n"));
+        assert!(results[2].is_err());
+        assert!(results[3].as_ref().unwrap().ends_with("test.sam.tsv"));
+
+        let clashing = [paths[0].clone(), PathBuf::from("other/test_fid.ch")];
+        assert!(convert_files(&clashing, out_dir, None, 2, |_, _| {}).is_err());
+
+        // a panicking callback is reported instead of unwinding through the caller
+        let err = convert_files(&paths[..1], out_dir, None, 1, |_, _| panic!("oops")).unwrap_err();
+        assert_eq!(err.msg, "1 conversion thread(s) panicked");
+        Ok(())
+    }
+}
@@ -0,0 +1,322 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str;
+use std::time::Duration;
+
+use clap::ArgMatches;
+
+use entab::aggregate::parse_bin_width;
+use entab::align::AlignMethod;
+use entab::join::{JoinKind, JoinReader};
+use entab::readers::{get_reader, RecordReader};
+use entab::record::Value;
+use entab::segments::extract_segment;
+use entab::summary::RunSummary;
+use entab::writers::{RecordWriter, SequenceFormat, TsvWriter};
+use entab::EtError;
+
+use crate::demultiplex::Barcodes;
+#[cfg(feature = "tui")]
+use crate::tui;
+use crate::{align, batch, demultiplex, diff, summary, watch, write_lines};
+
+/// Convert files as they show up in a directory (`entab watch`)
+pub(crate) fn run_watch(matches: &ArgMatches) -> Result<(), EtError> {
+    let settle = *matches.get_one::<f64>("settle").unwrap_or(&2.);
+    if !(settle >= 0. && settle.is_finite()) {
+        return Err("--settle must be a non-negative number of seconds".into());
+    }
+    watch::watch(
+        Path::new(matches.get_one::<String>("dir").unwrap()),
+        Path::new(matches.get_one::<String>("output_dir").unwrap()),
+        Duration::from_secs_f64(settle),
+        |path, result| {
+            match result {
+                Ok(out) => eprintln!("Converted {} to {}", path.display(), out.display()),
+                Err(e) => eprintln!("Could not convert {}: {}", path.display(), e),
+            }
+            true
+        },
+    )
+}
+
+/// Convert many files in parallel (`entab batch`)
+pub(crate) fn run_batch(matches: &ArgMatches) -> Result<(), EtError> {
+    let jobs = *matches.get_one::<usize>("jobs").unwrap_or(&1);
+    if jobs == 0 {
+        return Err("--jobs must be at least 1".into());
+    }
+    let paths: Vec<PathBuf> = matches
+        .get_many::<String>("inputs")
+        .unwrap()
+        .map(PathBuf::from)
+        .collect();
+    let output_dir = Path::new(matches.get_one::<String>("output_dir").unwrap());
+    std::fs::create_dir_all(output_dir)?;
+    let results = batch::convert_files(
+        &paths,
+        output_dir,
+        matches.get_one::<String>("parser").map(String::as_str),
+        jobs,
+        |path, result| match result {
+            Ok(out) => eprintln!("Converted {} to {}", path.display(), out.display()),
+            Err(e) => eprintln!("Could not convert {}: {}", path.display(), e),
+        },
+    )?;
+    let n_failed = results.iter().filter(|r| r.is_err()).count();
+    if n_failed > 0 {
+        return Err(format!(
+            "{} of {} files could not be converted",
+            n_failed,
+            paths.len()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Browse a file interactively (`entab tui`)
+#[cfg(feature = "tui")]
+pub(crate) fn run_tui(matches: &ArgMatches) -> Result<(), EtError> {
+    let path = matches.get_one::<String>("input").unwrap();
+    let mut params = BTreeMap::new();
+    let _ = params.insert("filename".to_string(), Value::from(path.clone()));
+    let parser = matches.get_one::<String>("parser").map(String::as_str);
+    let (reader, parser_used) = get_reader(File::open(path)?, parser, Some(params))?;
+    let name = Path::new(path)
+        .file_name()
+        .map_or_else(|| path.clone(), |n| n.to_string_lossy().into_owned());
+    tui::explore(reader, parser_used, &name)
+}
+
+/// Write a table summarizing each file (`entab summary`)
+pub(crate) fn run_summary<W: io::Write>(matches: &ArgMatches, stdout: W) -> Result<(), EtError> {
+    let output: Box<dyn io::Write> = match matches.get_one::<String>("output") {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(stdout),
+    };
+    let mut writer = BufWriter::new(output);
+    let mut tsv_writer = TsvWriter::new(Vec::new());
+    let mut headers = vec!["file".to_string()];
+    headers.extend(RunSummary::HEADERS.iter().map(|h| h.to_string()));
+    tsv_writer.write_headers(&headers)?;
+    write_lines(&mut tsv_writer, &mut writer)?;
+    for input in matches.get_many::<String>("input").unwrap() {
+        for (path, summary) in summary::summarize(Path::new(input))? {
+            let path = path.to_string_lossy();
+            let mut fields = vec![Value::from(path.as_ref())];
+            fields.extend(summary.to_record());
+            tsv_writer.write_record(&fields)?;
+            write_lines(&mut tsv_writer, &mut writer)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write out a raw segment of a file (`entab extract-segment`)
+pub(crate) fn run_extract_segment<W: io::Write>(
+    matches: &ArgMatches,
+    stdout: W,
+) -> Result<(), EtError> {
+    let segment = extract_segment(
+        File::open(matches.get_one::<String>("input").unwrap())?,
+        matches.get_one::<String>("parser").map(String::as_str),
+        matches.get_one::<String>("segment").unwrap(),
+    )?;
+    let mut output: Box<dyn io::Write> = match matches.get_one::<String>("output") {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(stdout),
+    };
+    output.write_all(&segment)?;
+    output.flush()?;
+    Ok(())
+}
+
+/// Split reads into a file per sample by their barcodes (`entab demultiplex`)
+pub(crate) fn run_demultiplex<W: io::Write>(
+    matches: &ArgMatches,
+    stdout: W,
+) -> Result<(), EtError> {
+    let mismatches = *matches.get_one::<usize>("mismatches").unwrap();
+    let barcodes = Barcodes::parse(
+        &std::fs::read_to_string(matches.get_one::<String>("barcodes").unwrap())?,
+        mismatches,
+    )?;
+    let mut params = BTreeMap::new();
+    let barcode_column = match matches.get_one::<String>("barcode") {
+        Some(spec) => {
+            // the barcode is extracted as a tag; it's called `barcode` unless it's named
+            let spec = if spec.contains('=') {
+                spec.clone()
+            } else {
+                format!("barcode={}", spec)
+            };
+            let name = spec.split('=').next().unwrap_or_default().to_string();
+            let _ = params.insert("tags".to_string(), Value::String(spec.into()));
+            name
+        }
+        None => matches.get_one::<String>("barcode_column").unwrap().clone(),
+    };
+    let input = Path::new(matches.get_one::<String>("input").unwrap());
+    let _ = params.insert(
+        "filename".to_string(),
+        Value::String(input.to_string_lossy().into_owned().into()),
+    );
+    let (mut reader, parser) = get_reader(
+        File::open(input)?,
+        matches.get_one::<String>("parser").map(String::as_str),
+        Some(params),
+    )?;
+    let barcode_col = reader
+        .headers()
+        .iter()
+        .position(|h| *h == barcode_column)
+        .ok_or_else(|| {
+            format!(
+                "{} does not have a {} column",
+                input.display(),
+                barcode_column
+            )
+        })?;
+    let format = match matches
+        .get_one::<String>("output_format")
+        .map(String::as_str)
+    {
+        Some("fasta") => Some(SequenceFormat::Fasta),
+        Some("fastq") => Some(SequenceFormat::Fastq),
+        Some(_) => None,
+        None if parser == "fastq" => Some(SequenceFormat::Fastq),
+        None => None,
+    };
+    let output_dir = Path::new(matches.get_one::<String>("output_dir").unwrap());
+    std::fs::create_dir_all(output_dir)?;
+    let counts =
+        demultiplex::demultiplex(&mut *reader, barcode_col, &barcodes, output_dir, format)?;
+
+    let mut writer = BufWriter::new(stdout);
+    writer.write_all(b"sample\trecords\n")?;
+    for (sample, records) in counts {
+        writeln!(writer, "{}\t{}", sample, records)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Align the retention times of several runs and write them as one table (`entab align`)
+pub(crate) fn run_align<W: io::Write>(matches: &ArgMatches, stdout: W) -> Result<(), EtError> {
+    let paths: Vec<&Path> = matches
+        .get_many::<String>("input")
+        .unwrap()
+        .map(Path::new)
+        .collect();
+    let method: AlignMethod = matches.get_one::<String>("method").unwrap().parse()?;
+    let max_shift = matches.get_one::<String>("max_shift").unwrap();
+    let max_shift = parse_bin_width(max_shift)
+        .map_err(|_| EtError::from(format!("Bad maximum shift {}", max_shift)))?;
+    let alignments = align::find_alignments(&paths, method, max_shift)?;
+
+    let output: Box<dyn io::Write> = match matches.get_one::<String>("output") {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(stdout),
+    };
+    let mut writer = BufWriter::new(output);
+    let mut tsv_writer = TsvWriter::new(Vec::new());
+    let mut headers = None;
+    for (path, alignment) in paths.into_iter().zip(alignments) {
+        let mut reader = align::aligned_reader(path, alignment)?;
+        let file_headers = reader.headers();
+        match &headers {
+            None => {
+                let mut all_headers = vec!["file".to_string()];
+                all_headers.extend(file_headers.iter().cloned());
+                tsv_writer.write_headers(&all_headers)?;
+                write_lines(&mut tsv_writer, &mut writer)?;
+                headers = Some(file_headers);
+            }
+            Some(h) if *h != file_headers => {
+                return Err(format!(
+                    "{} has different columns ({}) than the reference ({})",
+                    path.display(),
+                    file_headers.join(", "),
+                    h.join(", ")
+                )
+                .into());
+            }
+            Some(_) => {}
+        }
+        let file = path.to_string_lossy();
+        while let Some(record) = reader.next_record()? {
+            let mut fields = vec![Value::from(file.as_ref())];
+            fields.extend(record);
+            tsv_writer.write_record(&fields)?;
+            write_lines(&mut tsv_writer, &mut writer)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Join the records of two sorted files (`entab join`)
+pub(crate) fn run_join<W: io::Write>(matches: &ArgMatches, stdout: W) -> Result<(), EtError> {
+    let parser = matches.get_one::<String>("parser").map(String::as_str);
+    let open = |name: &str| {
+        let mut params = BTreeMap::new();
+        let _ = params.insert("filename".to_string(), Value::String(name.into()));
+        get_reader(File::open(name)?, parser, Some(params)).map(|(reader, _)| reader)
+    };
+    let left = open(matches.get_one::<String>("a").unwrap())?;
+    let right = open(matches.get_one::<String>("b").unwrap())?;
+    let kind: JoinKind = matches.get_one::<String>("how").unwrap().parse()?;
+    let mut reader = JoinReader::new(left, right, matches.get_one::<String>("on").unwrap(), kind)?;
+
+    let output: Box<dyn io::Write> = match matches.get_one::<String>("output") {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(stdout),
+    };
+    let mut writer = BufWriter::new(output);
+    let mut tsv_writer = TsvWriter::new(Vec::new());
+    tsv_writer.write_headers(&reader.headers())?;
+    write_lines(&mut tsv_writer, &mut writer)?;
+    while let Some(record) = reader.next_record()? {
+        tsv_writer.write_record(&record)?;
+        write_lines(&mut tsv_writer, &mut writer)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write the records that differ between two files (`entab diff`)
+pub(crate) fn run_diff<W: io::Write>(matches: &ArgMatches, stdout: W) -> Result<(), EtError> {
+    let output: Box<dyn io::Write> = match matches.get_one::<String>("output") {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(stdout),
+    };
+    let mut writer = BufWriter::new(output);
+    // the header can't be written until the files are opened, so buffer the changes
+    let mut changes = Vec::new();
+    let headers = diff::diff(
+        Path::new(matches.get_one::<String>("a").unwrap()),
+        Path::new(matches.get_one::<String>("b").unwrap()),
+        matches.get_one::<String>("key").unwrap(),
+        |change, record| {
+            let mut fields = vec![Value::from(change.as_str())];
+            fields.extend(record.iter().map(|v| v.clone().into_owned()));
+            changes.push(fields);
+            Ok(())
+        },
+    )?;
+    let mut tsv_writer = TsvWriter::new(Vec::new());
+    let mut all_headers = vec!["change".to_string()];
+    all_headers.extend(headers);
+    tsv_writer.write_headers(&all_headers)?;
+    for fields in changes {
+        tsv_writer.write_record(&fields)?;
+    }
+    writer.write_all(tsv_writer.get_mut())?;
+    writer.flush()?;
+    Ok(())
+}
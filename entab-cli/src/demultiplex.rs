@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use entab::readers::RecordReader;
+use entab::record::Value;
+use entab::writers::{RecordWriter, SequenceFormat, SequenceWriter, TsvWriter};
+use entab::EtError;
+
+/// The name of the output for records whose barcode doesn't match any sample (or matches more
+/// than one equally well)
+pub const UNDETERMINED: &str = "undetermined";
+
+/// A map of barcodes to the samples they were used for, which reads are assigned to allowing for
+/// a number of sequencing errors in the barcode.
+#[derive(Clone, Debug, Default)]
+pub struct Barcodes {
+    /// Each barcode (uppercased) and the index of its sample
+    barcodes: Vec<(Vec<u8>, usize)>,
+    exact: HashMap<Vec<u8>, usize>,
+    samples: Vec<String>,
+    max_mismatches: usize,
+}
+
+impl Barcodes {
+    /// Create an empty set of barcodes that matches reads with up to `max_mismatches` bases
+    /// different from a barcode
+    #[must_use]
+    pub fn new(max_mismatches: usize) -> Self {
+        Barcodes {
+            max_mismatches,
+            ..Barcodes::default()
+        }
+    }
+
+    /// Read barcodes from lines of `barcode<tab or comma>sample`; blank lines, lines starting
+    /// with `#`, and a header line (if the first barcode isn't made of bases) are skipped.
+    ///
+    /// # Errors
+    /// If a line doesn't have a barcode and sample or a barcode is listed twice, an error is
+    /// returned.
+    pub fn parse(text: &str, max_mismatches: usize) -> Result<Self, EtError> {
+        let mut barcodes = Barcodes::new(max_mismatches);
+        for (ix, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (barcode, sample) = line
+                .split_once(['\t', ','])
+                .ok_or_else(|| format!("Barcode line {} must be barcode,sample", ix + 1))?;
+            let barcode = barcode.trim();
+            let is_bases = barcode
+                .bytes()
+                .all(|b| b"ACGTNacgtn".contains(&b) || b == b'-' || b == b'+');
+            if barcodes.barcodes.is_empty() && !is_bases {
+                continue;
+            }
+            barcodes.add(barcode, sample.trim())?;
+        }
+        if barcodes.barcodes.is_empty() {
+            return Err("No barcodes were found".into());
+        }
+        Ok(barcodes)
+    }
+
+    /// Assign reads with `barcode` to `sample`; a sample can have more than one barcode
+    ///
+    /// # Errors
+    /// If the barcode is empty or already used or the sample can't be used as a file name, an
+    /// error is returned.
+    pub fn add(&mut self, barcode: &str, sample: &str) -> Result<(), EtError> {
+        if barcode.is_empty() {
+            return Err(format!("Sample {} has an empty barcode", sample).into());
+        }
+        if sample.is_empty()
+            || sample == UNDETERMINED
+            || sample.starts_with('.')
+            || sample.contains(['/', '\\'])
+        {
+            return Err(format!("{:?} can not be used as a sample name", sample).into());
+        }
+        let barcode = barcode.to_ascii_uppercase().into_bytes();
+        if self.exact.contains_key(&barcode) {
+            return Err(format!(
+                "Barcode {} is listed more than once",
+                String::from_utf8_lossy(&barcode)
+            )
+            .into());
+        }
+        let sample_ix = match self.samples.iter().position(|s| s == sample) {
+            Some(ix) => ix,
+            None => {
+                self.samples.push(sample.to_string());
+                self.samples.len() - 1
+            }
+        };
+        let _ = self.exact.insert(barcode.clone(), sample_ix);
+        self.barcodes.push((barcode, sample_ix));
+        Ok(())
+    }
+
+    /// The names of the samples, in the order they were first listed
+    #[must_use]
+    pub fn samples(&self) -> &[String] {
+        &self.samples
+    }
+
+    /// The index of the sample `barcode` belongs to, if it's within `max_mismatches` of only one
+    /// sample's barcodes (`N`s in the read count as mismatches)
+    #[must_use]
+    pub fn assign(&self, barcode: &[u8]) -> Option<usize> {
+        let barcode = barcode.to_ascii_uppercase();
+        if let Some(ix) = self.exact.get(&barcode) {
+            return Some(*ix);
+        }
+        // the closest sample (and whether another sample was just as close)
+        let mut best: Option<(usize, usize, bool)> = None;
+        for (known, sample_ix) in &self.barcodes {
+            if known.len() != barcode.len() {
+                continue;
+            }
+            let mismatches = known
+                .iter()
+                .zip(&barcode)
+                .filter(|(k, b)| k != b || **b == b'N')
+                .count();
+            if mismatches > self.max_mismatches {
+                continue;
+            }
+            best = match best {
+                Some((d, ix, tied)) if d < mismatches || (d == mismatches && ix == *sample_ix) => {
+                    Some((d, ix, tied))
+                }
+                Some((d, _, _)) if d == mismatches => Some((d, *sample_ix, true)),
+                _ => Some((mismatches, *sample_ix, false)),
+            };
+        }
+        match best {
+            Some((_, ix, false)) => Some(ix),
+            _ => None,
+        }
+    }
+}
+
+/// An output file for one sample
+#[derive(Debug)]
+struct Output {
+    writer: BufWriter<File>,
+    records: usize,
+}
+
+/// Split the records from `reader` into one file per sample in `output_dir` (named after the
+/// sample, e.g. `sample1.fastq`) by the barcode in their `barcode_col`; records that can't be
+/// assigned to a sample are written to `undetermined`. Records are written as TSVs or, if
+/// `format` is set, as FASTA or FASTQ records.
+///
+/// Returns the number of records written for every sample (and `undetermined`).
+///
+/// # Errors
+/// If a record can't be read or written, an error is returned.
+pub fn demultiplex(
+    reader: &mut dyn RecordReader,
+    barcode_col: usize,
+    barcodes: &Barcodes,
+    output_dir: &Path,
+    format: Option<SequenceFormat>,
+) -> Result<Vec<(String, usize)>, EtError> {
+    let headers = reader.headers();
+    let mut sequence_writer = format
+        .map(|format| {
+            // each record's written into the `Vec` and then moved into the sample's output
+            let mut sequence_writer = SequenceWriter::new(Vec::new(), format);
+            sequence_writer.write_headers(&headers)?;
+            Ok::<_, EtError>(sequence_writer)
+        })
+        .transpose()?;
+    let extension = match format {
+        Some(SequenceFormat::Fasta) => "fasta",
+        Some(SequenceFormat::Fastq) => "fastq",
+        None => "tsv",
+    };
+    let mut tsv_writer = TsvWriter::new(Vec::new());
+    tsv_writer.write_headers(&headers)?;
+    let header_line = std::mem::take(tsv_writer.get_mut());
+    let mut names: Vec<&str> = barcodes.samples().iter().map(String::as_str).collect();
+    names.push(UNDETERMINED);
+    // outputs are only created once there's a record to write to them
+    let mut outputs: Vec<Option<Output>> = names.iter().map(|_| None).collect();
+    let mut line = Vec::new();
+    while let Some(record) = reader.next_record()? {
+        let sample_ix = match record.get(barcode_col) {
+            Some(Value::String(barcode)) => barcodes.assign(barcode.as_bytes()),
+            _ => None,
+        }
+        .unwrap_or(names.len() - 1);
+        let output = match &mut outputs[sample_ix] {
+            Some(output) => output,
+            empty => {
+                let path = output_dir.join(format!("{}.{}", names[sample_ix], extension));
+                let mut writer = BufWriter::new(File::create(path)?);
+                if sequence_writer.is_none() {
+                    writer.write_all(&header_line)?;
+                }
+                empty.insert(Output { writer, records: 0 })
+            }
+        };
+        line.clear();
+        match &mut sequence_writer {
+            Some(sequence_writer) => {
+                sequence_writer.write_record(&record)?;
+                line.append(sequence_writer.get_mut());
+            }
+            None => {
+                tsv_writer.write_record(&record)?;
+                line.append(tsv_writer.get_mut());
+            }
+        }
+        output.writer.write_all(&line)?;
+        output.records += 1;
+    }
+
+    let mut counts = Vec::with_capacity(names.len());
+    for (name, output) in names.into_iter().zip(outputs) {
+        let records = match output {
+            Some(mut output) => {
+                output.writer.flush()?;
+                output.records
+            }
+            None => 0,
+        };
+        counts.push((name.to_string(), records));
+    }
+    Ok(counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use entab::readers::get_reader;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_assign() -> Result<(), EtError> {
+        let barcodes = Barcodes::parse(
+            "barcode,sample\nACGTACGT,s1\nTTTTCCCC,s2\n# more\nacgtaccc,s3\n",
+            1,
+        )?;
+        assert_eq!(barcodes.samples(), ["s1", "s2", "s3"]);
+        assert_eq!(barcodes.assign(b"ACGTACGT"), Some(0));
+        assert_eq!(barcodes.assign(b"acgtacgA"), Some(0));
+        assert_eq!(barcodes.assign(b"ACGTACGN"), Some(0));
+        assert_eq!(barcodes.assign(b"TTTTCCCA"), Some(1));
+        // too many mismatches
+        assert_eq!(barcodes.assign(b"ACGTAAAA"), None);
+        // one mismatch away from both s1 and s3
+        assert_eq!(barcodes.assign(b"ACGTACCT"), None);
+        assert_eq!(barcodes.assign(b"ACGT"), None);
+
+        assert!(Barcodes::parse("AAAA,s1\nAAAA,s2\n", 0).is_err());
+        assert!(Barcodes::parse("AAAA,../s1\n", 0).is_err());
+        assert!(Barcodes::parse("AAAA\n", 0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_demultiplex() -> Result<(), EtError> {
+        let dir = tempfile::tempdir()?;
+        let output_dir = dir.path();
+        let data: &[u8] =
+            b"@r1\nACGTTTTT\n+\nIIIIIIII\n@r2\nCCCCGGGG\n+\nIIIIIIII\n@r3\nACGATTGG\n+\nIIIIIIII\n";
+        let mut params = BTreeMap::new();
+        let _ = params.insert("tags".to_string(), "barcode=read[0:4]".into());
+        let (mut reader, _) = get_reader(data, Some("fastq"), Some(params))?;
+        let barcodes = Barcodes::parse("ACGT\ts1\nGGGG\ts2\n", 1)?;
+        let counts = demultiplex(
+            &mut *reader,
+            3,
+            &barcodes,
+            output_dir,
+            Some(SequenceFormat::Fastq),
+        )?;
+        assert_eq!(
+            counts,
+            [
+                ("s1".to_string(), 2),
+                ("s2".to_string(), 0),
+                ("undetermined".to_string(), 1)
+            ]
+        );
+        assert_eq!(
+            std::fs::read_to_string(output_dir.join("s1.fastq"))?,
+            "@r1\nACGTTTTT\n+\nIIIIIIII\n@r3\nACGATTGG\n+\nIIIIIIII\n"
+        );
+        assert!(!output_dir.join("s2.fastq").exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::Path;
+use std::slice;
+
+use entab::hash::{hash_record, HASH_HEADER};
+use entab::readers::RecordReader;
+use entab::record::Value;
+use entab::EtError;
+
+use crate::open_path;
+
+/// Whether a record is only in the second file or only in the first
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Change {
+    /// The record is only in the second file
+    Added,
+    /// The record is only in the first file
+    Removed,
+}
+
+impl Change {
+    /// The name of the change (as written in the `change` column)
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Change::Added => "added",
+            Change::Removed => "removed",
+        }
+    }
+}
+
+/// Open the file at `path`, adding a `_hash` column if that's the `key`, and find the `key`
+/// column
+fn open_keyed(path: &Path, key: &str) -> Result<(Box<dyn RecordReader>, usize), EtError> {
+    let mut params = BTreeMap::new();
+    if key == HASH_HEADER {
+        let _ = params.insert("hash".to_string(), Value::Boolean(true));
+    }
+    let (reader, _) = open_path(path, params)?;
+    let key_col = reader
+        .headers()
+        .iter()
+        .position(|h| h == key)
+        .ok_or_else(|| {
+            EtError::from(format!("{} does not have a {} column", path.display(), key))
+        })?;
+    Ok((reader, key_col))
+}
+
+/// Compare the records in the files at `a` and `b` by their `key` column (`_hash` to compare
+/// the records' entire contents) and call `on_change` with every record that's only in one of
+/// them; a key that's in `a` twice and in `b` once counts as one removed record.
+///
+/// The records added in `b` are reported (in the order they're in `b`) before the records
+/// removed from `a` (in the order they're in `a`). Returns the headers of the records passed to
+/// `on_change`.
+///
+/// # Errors
+/// If either file can't be read, doesn't have the `key` column, or the files have different
+/// columns, an error is returned.
+pub fn diff<F>(a: &Path, b: &Path, key: &str, mut on_change: F) -> Result<Vec<String>, EtError>
+where
+    F: FnMut(Change, &[Value]) -> Result<(), EtError>,
+{
+    let (mut a_reader, key_col) = open_keyed(a, key)?;
+    let (mut b_reader, _) = open_keyed(b, key)?;
+    let headers = a_reader.headers();
+    let b_headers = b_reader.headers();
+    if headers != b_headers {
+        return Err(format!(
+            "{} and {} have different columns ({} vs {})",
+            a.display(),
+            b.display(),
+            headers.join(","),
+            b_headers.join(","),
+        )
+        .into());
+    }
+
+    // the records in `a` that haven't been matched yet, looked up by the hash of their key
+    let mut unmatched: Vec<Option<Vec<Value<'static>>>> = Vec::new();
+    let mut by_key: HashMap<u64, VecDeque<usize>> = HashMap::new();
+    while let Some(record) = a_reader.next_record()? {
+        let key_hash = hash_record(slice::from_ref(&record[key_col]));
+        by_key
+            .entry(key_hash)
+            .or_default()
+            .push_back(unmatched.len());
+        unmatched.push(Some(record.into_iter().map(Value::into_owned).collect()));
+    }
+
+    while let Some(record) = b_reader.next_record()? {
+        let key_hash = hash_record(slice::from_ref(&record[key_col]));
+        // match against the earliest remaining copy in `a`
+        match by_key.get_mut(&key_hash).and_then(VecDeque::pop_front) {
+            Some(ix) => unmatched[ix] = None,
+            None => on_change(Change::Added, &record)?,
+        }
+    }
+    for record in unmatched.into_iter().flatten() {
+        on_change(Change::Removed, &record)?;
+    }
+    Ok(headers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff() -> Result<(), EtError> {
+        let dir = tempfile::tempdir()?;
+        let a = dir.path().join("a.fa");
+        let b = dir.path().join("b.fa");
+        std::fs::write(&a, b">x\nACGT\n>y\nTT\n>y\nTT\n")?;
+        std::fs::write(&b, b">y\nTT\n>x\nACGA\n>z\nGG\n")?;
+
+        let mut changes = Vec::new();
+        let headers = diff(&a, &b, "_hash", |change, record| {
+            changes.push((change, record[0].clone().into_owned()));
+            Ok(())
+        })?;
+        assert_eq!(headers, ["id", "sequence", "_hash"]);
+        assert_eq!(
+            changes,
+            [
+                (Change::Added, "x".into()),
+                (Change::Added, "z".into()),
+                (Change::Removed, "x".into()),
+                (Change::Removed, "y".into()),
+            ]
+        );
+
+        // by id, only z is new (and one of the ys is gone)
+        let mut changes = Vec::new();
+        let _ = diff(&a, &b, "id", |change, record| {
+            changes.push((change, record[0].clone().into_owned()));
+            Ok(())
+        })?;
+        assert_eq!(
+            changes,
+            [(Change::Added, "z".into()), (Change::Removed, "y".into())]
+        );
+
+        assert!(diff(&a, &b, "nope", |_, _| Ok(())).is_err());
+        Ok(())
+    }
+}
@@ -1,21 +1,63 @@
-mod tsv_params;
+pub mod align;
+pub mod batch;
+mod commands;
+pub mod demultiplex;
+pub mod diff;
+mod msgpack_output;
+#[cfg(feature = "parquet")]
+mod parquet_output;
+mod pipeline;
+pub mod preview;
+mod schema;
+#[cfg(feature = "script")]
+mod script;
+pub mod summary;
+mod transform;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod watch;
 
 use std::collections::BTreeMap;
+use std::env;
 use std::ffi::OsString;
-use std::fs::File;
+use std::fs::{File, OpenOptions};
 use std::io;
+use std::io::{BufRead, BufReader, BufWriter, IsTerminal, Read, Seek, SeekFrom, Write};
+use std::path::Path;
 use std::str;
 
 use clap::error::ErrorKind;
-use clap::{crate_authors, crate_version, Arg, Command};
+use clap::parser::ValueSource;
+use clap::{crate_authors, crate_version, Arg, ArgMatches, Command};
 #[cfg(feature = "mmap")]
 use memmap2::Mmap;
 
-use entab::readers::get_reader;
+use entab::build_info::build_info;
+use entab::error::EtErrorKind;
+#[cfg(feature = "mmap")]
+use entab::filetype::FileType;
+use entab::lint::Linter;
+use entab::parsers::agilent::run::{run_signal_paths, AgilentRunReader};
+use entab::parsers::waters::{data_path, is_run};
+use entab::readers::{get_reader, get_reader_threaded, EmptyReader, RaggedReader, RecordReader};
 use entab::record::Value;
+use entab::report::Report;
+use entab::writers::{LineWrap, RecordWriter, SequenceFormat, SequenceWriter, TsvWriter};
 use entab::EtError;
 
-use crate::tsv_params::TsvParams;
+use crate::msgpack_output::{write_message, SchemaUpdate, StreamSchema};
+#[cfg(feature = "parquet")]
+use crate::parquet_output::ParquetWriter;
+use crate::schema::{InvalidAction, Schema};
+
+/// The formats records can be written out in (parquet needs the `parquet` feature)
+const OUTPUT_FORMATS: [&str; 5] = ["tsv", "fasta", "fastq", "msgpack-stream", "parquet"];
+
+/// The size of the buffer used for writing output
+const WRITE_BUFFER_SIZE: usize = 1 << 20;
+
+/// With `--warn`, how many warnings of each kind are printed before only counting them
+const MAX_PRINTED_WARNINGS: usize = 10;
 
 /// Parse the provided `stdin` using `args` and write results to `stdout`.
 ///
@@ -28,7 +70,115 @@ where
     R: io::Read,
     W: io::Write,
 {
-    let clap_result = Command::new("entab")
+    run_with(args, Unthreaded(stdin), stdout, io::stderr())
+}
+
+/// Like `run`, but compressed `stdin` is decompressed on a background thread so inflating it
+/// overlaps with parsing (which is why `stdin` has to be `Send` and `'static`).
+///
+/// # Errors
+/// If there are any issues, an `EtError` will be returned.
+pub fn run_threaded<I, T, R, W>(args: I, stdin: R, stdout: W) -> Result<(), EtError>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+    R: io::Read + Send + 'static,
+    W: io::Write,
+{
+    run_with(args, Threaded(stdin), stdout, io::stderr())
+}
+
+/// Data that can only be read through once (`stdin` or a pipe) and the way it's opened
+trait StreamInput<'r> {
+    /// Open a reader for the data
+    fn open<'n>(
+        self,
+        parser: Option<&'n str>,
+        params: BTreeMap<String, Value<'_>>,
+    ) -> Result<(Box<dyn RecordReader + 'r>, &'n str), EtError>;
+}
+
+/// Input that's read and decompressed on the parsing thread
+struct Unthreaded<R>(R);
+
+impl<'r, R: io::Read + 'r> StreamInput<'r> for Unthreaded<R> {
+    fn open<'n>(
+        self,
+        parser: Option<&'n str>,
+        params: BTreeMap<String, Value<'_>>,
+    ) -> Result<(Box<dyn RecordReader + 'r>, &'n str), EtError> {
+        let buffer: Box<dyn io::Read + 'r> = Box::new(self.0);
+        get_reader(buffer, parser, Some(params))
+    }
+}
+
+/// Input that's read and decompressed on a background thread
+struct Threaded<R>(R);
+
+impl<'r, R: io::Read + Send + 'static> StreamInput<'r> for Threaded<R> {
+    fn open<'n>(
+        self,
+        parser: Option<&'n str>,
+        params: BTreeMap<String, Value<'_>>,
+    ) -> Result<(Box<dyn RecordReader + 'r>, &'n str), EtError> {
+        let (reader, parser) = get_reader_threaded(self.0, parser, Some(params))?;
+        Ok((reader, parser))
+    }
+}
+
+/// Shared implementation of `run` and `run_threaded`; warnings and reports from converting are
+/// written to `stderr`
+fn run_with<'r, I, T, S, W, E>(args: I, stdin: S, stdout: W, stderr: E) -> Result<(), EtError>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+    S: StreamInput<'r>,
+    W: io::Write,
+    E: io::Write,
+{
+    let clap_result = command().try_get_matches_from(args);
+
+    let matches = match clap_result {
+        Ok(d) => d,
+        Err(e) => {
+            if e.kind() == ErrorKind::DisplayHelp || e.kind() == ErrorKind::DisplayVersion {
+                e.print()?;
+                return Ok(());
+            }
+            return Err(e.to_string().into());
+        }
+    };
+
+    if matches.get_flag("version_json") {
+        let mut info = serde_json::to_value(build_info())
+            .map_err(|e| EtError::from(format!("Could not write build info: {}", e)))?;
+        info["cli_version"] = crate_version!().into();
+        let mut writer = BufWriter::new(stdout);
+        serde_json::to_writer_pretty(&mut writer, &info)
+            .map_err(|e| EtError::from(format!("Could not write build info: {}", e)))?;
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+        return Ok(());
+    }
+
+    match matches.subcommand() {
+        Some(("watch", matches)) => commands::run_watch(matches),
+        Some(("batch", matches)) => commands::run_batch(matches),
+        #[cfg(feature = "tui")]
+        Some(("tui", matches)) => commands::run_tui(matches),
+        Some(("summary", matches)) => commands::run_summary(matches, stdout),
+        Some(("extract-segment", matches)) => commands::run_extract_segment(matches, stdout),
+        Some(("demultiplex", matches)) => commands::run_demultiplex(matches, stdout),
+        Some(("align", matches)) => commands::run_align(matches, stdout),
+        Some(("join", matches)) => commands::run_join(matches, stdout),
+        Some(("diff", matches)) => commands::run_diff(matches, stdout),
+        _ => convert(&matches, stdin, stdout, stderr),
+    }
+}
+
+/// The arguments and subcommands of the CLI
+fn command() -> Command {
+    let command = Command::new("entab")
         .about("Turn anything into a TSV")
         .author(crate_authors!())
         .version(crate_version!())
@@ -44,6 +194,14 @@ where
                 .help("Path to write to; if not provided stdout will be used")
                 .num_args(1),
         )
+        .arg(
+            Arg::new("append")
+                .long("append")
+                .help("Add rows to the end of the TSV output file (if its header matches) instead of overwriting it")
+                .action(clap::ArgAction::SetTrue)
+                .requires("output")
+                .conflicts_with("metadata"),
+        )
         .arg(
             Arg::new("parser")
                 .short('p')
@@ -57,117 +215,2391 @@ where
                 .help("Reports metadata about the file instead of the data itself")
                 .action(clap::ArgAction::SetTrue),
         )
-        .try_get_matches_from(args);
-
-    let matches = match clap_result {
-        Ok(d) => d,
-        Err(e) => {
-            if e.kind() == ErrorKind::DisplayHelp || e.kind() == ErrorKind::DisplayVersion {
-                e.print()?;
-                return Ok(());
-            }
-            return Err(e.to_string().into());
-        }
-    };
-
-    // TODO: map/reduce/filter options?
-    // every column should either have a reduction set or it'll be dropped from
-    // the result? reductions can be e.g. sum,average,count or group or column
-    // (where column is the same as a pivot); this might be more useful as
-    // another tool?
+        .arg(
+            Arg::new("metadata_format")
+                .long("metadata-format")
+                .help("Format to write metadata in; json and yaml keep nested values and lists")
+                .value_parser(["tsv", "json", "yaml"])
+                .default_value("tsv")
+                .requires("metadata"),
+        )
+        .arg(
+            Arg::new("schema")
+                .long("schema")
+                .help("JSON Table Schema-like file to validate each record against")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("on_invalid")
+                .long("on-invalid")
+                .help("What to do with records that don't match the schema")
+                .value_parser(["report", "drop", "fail"])
+                .default_value("report")
+                .requires("schema"),
+        )
+        .arg(
+            Arg::new("warn")
+                .long("warn")
+                .help("Report suspicious values (e.g. negative or out of order times) to stderr, with counts at the end")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with("ragged"),
+        )
+        .arg(
+            Arg::new("report")
+                .long("report")
+                .help("Print a summary of the conversion (records, bytes read, time taken, and nulls in each column) to stderr as JSON")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("aggregate")
+                .long("aggregate")
+                .help("How to combine the intensities of trace data within each time bin")
+                .value_parser(["mean", "max", "min", "sum"])
+                .requires("bin"),
+        )
+        .arg(
+            Arg::new("bin")
+                .long("bin")
+                .help("Width of the time bins to downsample trace data into (e.g. 0.1min or 6s)")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("ragged")
+                .long("ragged")
+                .help("Allow records to have different fields; missing fields are written as null")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("on_new_columns")
+                .long("on-new-columns")
+                .help("With --ragged, how to handle fields that first appear partway through: buffer reads every record before writing any (the default for tsv), fail stops with an error, and restart writes a new header line or MessagePack schema (the default for msgpack-stream)")
+                .value_parser(["buffer", "fail", "restart"])
+                .requires("ragged"),
+        )
+        .args(pipeline::args())
+        .arg(
+            Arg::new("output_format")
+                .short('f')
+                .long("output-format")
+                .help("Format to write records in; fasta and fastq need id and sequence columns, msgpack-stream writes length-prefixed MessagePack messages, and parquet (the default for -o paths ending in .parquet) needs -o")
+                .value_parser(OUTPUT_FORMATS)
+                .default_value("tsv")
+                .conflicts_with("metadata"),
+        )
+        .arg(
+            Arg::new("line_width")
+                .long("line-width")
+                .help("When writing FASTA, the number of bases per line (0 to not wrap) or preserve to split them like the input")
+                .default_value("60"),
+        )
+        .arg(
+            Arg::new("embed_quality")
+                .long("embed-quality")
+                .help("When writing FASTA, keep the quality scores in the header instead of dropping them")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("default_quality")
+                .long("default-quality")
+                .help("When writing FASTQ, the quality score for bases without one")
+                .default_value("I"),
+        )
+        .arg(
+            Arg::new("canonical_units")
+                .long("canonical-units")
+                .help("Convert Chemstation intensities into a standard unit for their detector (mAU, pA, mV, or nRIU)")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("raw_values")
+                .long("raw-values")
+                .help("Write uncorrected Chemstation detector values as the intensity (with the corrected values in a corrected_intensity column)")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("integer_values")
+                .long("integer-values")
+                .help("Write Chemstation MS times and m/z's as the integers they're stored as (the column metadata has the scale to convert them)")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("alignment_stats")
+                .long("alignment-stats")
+                .help("Add ref_end, matches, mismatches, and soft-clip length columns to SAM and BAM alignments")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("kmer_size")
+                .long("kmer-size")
+                .help("With -p kmers, the length of each kmer [default: 21]")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("canonical_kmers")
+                .long("canonical-kmers")
+                .help("With -p kmers, use the lesser of each kmer and its reverse complement")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("minimizer_window")
+                .long("minimizer-window")
+                .help("With -p kmers, only write the minimizer of each window of this many kmers")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("kmer_counts")
+                .long("kmer-counts")
+                .help("With -p kmers, write how many times each kmer occurs instead of every position")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("abs_time")
+                .long("abs-time")
+                .help("Add an abs_time column with the clock time of each FCS event")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("gap_threshold")
+                .long("gap-threshold")
+                .help("With --stream events_gaps, the shortest pause in seconds between FCS events to report (default 1)"),
+        )
+        .arg(
+            Arg::new("stream")
+                .long("stream")
+                .help("Which kind of records to read from files that have more than one (e.g. references for BAM, events_gaps for FCS)"),
+        )
+        .arg(
+            Arg::new("strict_dates")
+                .long("strict-dates")
+                .help("Report FCS and Chemstation dates that are missing or can't be parsed as null instead of a default date")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("default_date")
+                .long("default-date")
+                .help("The date to report for FCS and Chemstation files without a parseable one (e.g. 2000-01-01)"),
+        )
+        .arg(
+            Arg::new("delimiter")
+                .long("delimiter")
+                .help("The character separating fields in CSV/TSV input (\\t for tabs); detected if not given"),
+        )
+        .arg(
+            Arg::new("quote_char")
+                .long("quote-char")
+                .help("The character quoting fields in CSV/TSV input"),
+        )
+        .arg(
+            Arg::new("comment_char")
+                .long("comment-char")
+                .help("Skip lines of CSV/TSV input starting with this character"),
+        )
+        .arg(
+            Arg::new("skip_lines")
+                .long("skip-lines")
+                .help("The number of lines of CSV/TSV input to skip before the header; detected if not given"),
+        )
+        .arg(
+            Arg::new("no_header")
+                .long("no-header")
+                .help("CSV/TSV input has no header line (columns are named column_1, column_2, etc.)")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("no_infer_types")
+                .long("no-infer-types")
+                .help("Read every CSV/TSV field as text instead of detecting numbers and booleans")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("hash")
+                .long("hash")
+                .help("Add a _hash column with a stable hash of each record (e.g. for deduplication)")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("nan_value")
+                .long("nan-value")
+                .help("Text to write for NaN values (e.g. an empty string or null)")
+                .default_value("NaN"),
+        )
+        .arg(
+            Arg::new("inf_value")
+                .long("inf-value")
+                .help("Text to write for infinite values (prefixed with - if negative)")
+                .default_value("inf"),
+        )
+        .arg(
+            Arg::new("preview")
+                .long("preview")
+                .help("Print the first N records as an aligned table with the type of each column")
+                .value_name("N")
+                .num_args(0..=1)
+                .default_missing_value("10")
+                .value_parser(clap::value_parser!(usize))
+                .conflicts_with_all(["metadata", "append", "ragged", "schema", "warn"]),
+        )
+        .arg(
+            Arg::new("color")
+                .long("color")
+                .help("When to style output for the terminal; auto uses color if writing to a terminal and NO_COLOR isn't set")
+                .value_parser(["auto", "always", "never"])
+                .default_value("auto"),
+        )
+        .arg(
+            Arg::new("version_json")
+                .long("version-json")
+                .help("Print the versions of entab and each of its parsers as JSON")
+                .action(clap::ArgAction::SetTrue)
+                .exclusive(true),
+        )
+        .subcommand(
+            Command::new("watch")
+                .about(
+                    "Convert new files in a directory to TSVs (with JSON metadata) as they appear",
+                )
+                .arg(
+                    Arg::new("dir")
+                        .help("Directory to watch for new files")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("output_dir")
+                        .long("output-dir")
+                        .help("Directory to write the converted files into")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("settle")
+                        .long("settle")
+                        .help("Seconds a file has to be unchanged before it's converted")
+                        .value_parser(clap::value_parser!(f64))
+                        .default_value("2"),
+                ),
+        )
+        .subcommand(
+            Command::new("batch")
+                .about("Convert files to TSVs (with JSON metadata), several at a time")
+                .arg(
+                    Arg::new("inputs")
+                        .help("Files to convert")
+                        .num_args(1..)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("output_dir")
+                        .long("output-dir")
+                        .help("Directory to write the converted files into")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("parser")
+                        .short('p')
+                        .help("Parser to use for every file [if not specified, it will be auto-detected]")
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new("jobs")
+                        .short('j')
+                        .long("jobs")
+                        .help("Number of files to convert at once")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("1"),
+                ),
+        )
+        .subcommand(
+            Command::new("summary")
+                .about("Write one row per input with its format, record count, time/m/z ranges, and sample info")
+                .arg(
+                    Arg::new("input")
+                        .short('i')
+                        .help("File or run directory (e.g. an Agilent .d) to summarize; can be repeated")
+                        .action(clap::ArgAction::Append)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .help("Path to write to; if not provided stdout will be used")
+                        .num_args(1),
+                ),
+        )
+        .subcommand(
+            Command::new("extract-segment")
+                .about("Write the raw bytes of a segment embedded in a file (an FCS header/text/data/analysis segment, a PNG chunk type, or a BAM header) out without parsing them")
+                .arg(
+                    Arg::new("input")
+                        .short('i')
+                        .help("Path to read")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("parser")
+                        .short('p')
+                        .help("Parser to use [if not specified, it will be auto-detected]")
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new("segment")
+                        .long("segment")
+                        .help("Segment to extract, e.g. analysis for FCS files or tEXt for PNGs")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .help("Path to write to; if not provided stdout will be used")
+                        .num_args(1),
+                ),
+        )
+        .subcommand(
+            Command::new("demultiplex")
+                .about("Split reads into one file per sample by their barcodes")
+                .arg(
+                    Arg::new("input")
+                        .short('i')
+                        .help("Path to read")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("parser")
+                        .short('p')
+                        .help("Parser to use [if not specified, it will be auto-detected]")
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new("barcodes")
+                        .long("barcodes")
+                        .help("File with a barcode and its sample's name (separated by a tab or comma) on each line")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("barcode")
+                        .long("barcode")
+                        .help("Where the barcode is in each FASTQ read, e.g. read[0:8] or header/:/9")
+                        .required_unless_present("barcode_column"),
+                )
+                .arg(
+                    Arg::new("barcode_column")
+                        .long("barcode-column")
+                        .help("Column with the barcode of each record (for inputs that already have one)")
+                        .conflicts_with("barcode"),
+                )
+                .arg(
+                    Arg::new("mismatches")
+                        .long("mismatches")
+                        .help("How many bases of a barcode can be wrong and still match")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("1"),
+                )
+                .arg(
+                    Arg::new("output_dir")
+                        .long("output-dir")
+                        .help("Directory to write a file for each sample (and undetermined reads) into")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("output_format")
+                        .long("output-format")
+                        .help("Format to write each sample's records in [default: fastq for FASTQ input, otherwise tsv]")
+                        .value_parser(["tsv", "fasta", "fastq"]),
+                ),
+        )
+        .subcommand(
+            Command::new("align")
+                .about("Align the retention times of chromatograms to the first one and write out all of their records with raw and aligned times")
+                .arg(
+                    Arg::new("input")
+                        .short('i')
+                        .help("Trace (e.g. an FID or MWD file) to align; the first is the reference and the option can be repeated")
+                        .action(clap::ArgAction::Append)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("method")
+                        .long("method")
+                        .help("xcorr shifts each trace by the offset that best correlates it with the reference; peaks matches up the largest peaks and stretches the times between them")
+                        .value_parser(["xcorr", "peaks"])
+                        .default_value("xcorr"),
+                )
+                .arg(
+                    Arg::new("max_shift")
+                        .long("max-shift")
+                        .help("The largest shift to look for, e.g. 0.5min or 30s (minutes if no unit is given)")
+                        .default_value("0.5min"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .help("Path to write to; if not provided stdout will be used")
+                        .num_args(1),
+                ),
+        )
+        .subcommand(
+            Command::new("diff")
+                .about("Write the records that were added or removed between two files")
+                .arg(Arg::new("a").help("The original file").required(true))
+                .arg(Arg::new("b").help("The file to compare to it").required(true))
+                .arg(
+                    Arg::new("key")
+                        .long("key")
+                        .help("Column to match records by; _hash matches on the whole record")
+                        .default_value("_hash"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .help("Path to write to; if not provided stdout will be used")
+                        .num_args(1),
+                ),
+        )
+        .subcommand(
+            Command::new("join")
+                .about("Join the records of two files that are both sorted by a key column")
+                .arg(Arg::new("a").help("The left file").required(true))
+                .arg(Arg::new("b").help("The right file").required(true))
+                .arg(
+                    Arg::new("on")
+                        .long("on")
+                        .help("Column to match records by (both files have to be sorted by it)")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("how")
+                        .long("how")
+                        .help("inner to only write records with a key in both files; left to write every record in the left file")
+                        .value_parser(["inner", "left"])
+                        .default_value("inner"),
+                )
+                .arg(
+                    Arg::new("parser")
+                        .short('p')
+                        .help("Parser to use for both files [if not specified, it will be auto-detected]")
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .help("Path to write to; if not provided stdout will be used")
+                        .num_args(1),
+                ),
+        );
+    #[cfg(feature = "tui")]
+    let command = command.subcommand(
+        Command::new("tui")
+            .about("Browse a file's format, metadata, and records interactively")
+            .arg(Arg::new("input").help("Path to read").required(true))
+            .arg(
+                Arg::new("parser")
+                    .short('p')
+                    .help("Parser to use [if not specified, it will be auto-detected]")
+                    .num_args(1),
+            ),
+    );
+    command
+}
 
+/// Convert the input (a file or `stdin`) into the output format and write it to the output file
+/// or `stdout`
+fn convert<'r, S, W, E>(
+    matches: &ArgMatches,
+    stdin: S,
+    stdout: W,
+    mut stderr: E,
+) -> Result<(), EtError>
+where
+    S: StreamInput<'r>,
+    W: io::Write,
+    E: io::Write,
+{
     #[cfg(feature = "mmap")]
     let mmap: Mmap;
 
     let mut parse_params = BTreeMap::new();
-    let parser = matches.get_one::<&str>("parser").copied();
-    let (mut rec_reader, _) = if let Some(&i) = matches.get_one::<&str>("input") {
-        parse_params.insert("filename".to_string(), Value::String(i.into()));
-        let file = File::open(i)?;
-        #[cfg(feature = "mmap")]
+    if let Some(aggregate) = matches.get_one::<String>("aggregate") {
+        parse_params.insert("aggregate".to_string(), Value::String(aggregate.into()));
+    }
+    if let Some(bin) = matches.get_one::<String>("bin") {
+        parse_params.insert("bin".to_string(), Value::String(bin.into()));
+    }
+    let output_path = matches.get_one::<String>("output");
+    let output_format = match matches.get_one::<String>("output_format") {
+        Some(format) if matches.value_source("output_format") == Some(ValueSource::CommandLine) => {
+            format.as_str()
+        }
+        _ if !matches.get_flag("metadata")
+            && output_path.is_some_and(|p| p.ends_with(".parquet")) =>
         {
-            mmap = unsafe { Mmap::map(&file)? };
-            get_reader(mmap.as_ref(), parser, Some(parse_params))?
+            if cfg!(feature = "parquet") {
+                "parquet"
+            } else {
+                writeln!(
+                    stderr,
+                    "Warning: Parquet output is not available in this build of entab (rebuild \
+                    it with `--features parquet` to write Parquet files); writing TSV instead"
+                )?;
+                "tsv"
+            }
+        }
+        format => format.map_or("tsv", String::as_str),
+    };
+    if cfg!(not(feature = "parquet")) && output_format == "parquet" {
+        return Err(
+            "Parquet output is not available in this build of entab; rebuild it with \
+            `--features parquet` to write Parquet files"
+                .into(),
+        );
+    }
+    let line_wrap: LineWrap = matches
+        .get_one::<String>("line_width")
+        .map_or(Ok(LineWrap::default()), |w| w.parse())?;
+    if line_wrap == LineWrap::Preserve && output_format == "fasta" {
+        // have the FASTA parser record how each sequence was split over lines
+        parse_params.insert("line_lengths".to_string(), Value::Boolean(true));
+    }
+    if matches.get_flag("hash") {
+        parse_params.insert("hash".to_string(), Value::Boolean(true));
+    }
+    if matches.get_flag("canonical_units") {
+        parse_params.insert("canonical_units".to_string(), Value::Boolean(true));
+    }
+    if matches.get_flag("raw_values") {
+        parse_params.insert("raw_values".to_string(), Value::Boolean(true));
+    }
+    if matches.get_flag("integer_values") {
+        parse_params.insert("integer_values".to_string(), Value::Boolean(true));
+    }
+    if matches.get_flag("alignment_stats") {
+        parse_params.insert("alignment_stats".to_string(), Value::Boolean(true));
+    }
+    for key in ["kmer_size", "minimizer_window"] {
+        if let Some(value) = matches.get_one::<String>(key) {
+            parse_params.insert(key.to_string(), Value::String(value.clone().into()));
+        }
+    }
+    for key in ["canonical_kmers", "kmer_counts"] {
+        if matches.get_flag(key) {
+            parse_params.insert(key.to_string(), Value::Boolean(true));
+        }
+    }
+    if matches.get_flag("abs_time") {
+        parse_params.insert("abs_time".to_string(), Value::Boolean(true));
+    }
+    if let Some(threshold) = matches.get_one::<String>("gap_threshold") {
+        parse_params.insert(
+            "gap_threshold".to_string(),
+            Value::String(threshold.clone().into()),
+        );
+    }
+    if let Some(stream) = matches.get_one::<String>("stream") {
+        parse_params.insert("stream".to_string(), Value::String(stream.clone().into()));
+    }
+    if matches.get_flag("strict_dates") {
+        parse_params.insert("strict_dates".to_string(), Value::Boolean(true));
+    }
+    if let Some(date) = matches.get_one::<String>("default_date") {
+        parse_params.insert(
+            "default_date".to_string(),
+            Value::String(date.clone().into()),
+        );
+    }
+    for key in ["delimiter", "quote_char", "comment_char", "skip_lines"] {
+        if let Some(value) = matches.get_one::<String>(key) {
+            parse_params.insert(key.to_string(), Value::String(value.clone().into()));
+        }
+    }
+    if matches.get_flag("no_header") {
+        parse_params.insert("has_header".to_string(), Value::Boolean(false));
+    }
+    if matches.get_flag("no_infer_types") {
+        parse_params.insert("infer_types".to_string(), Value::Boolean(false));
+    }
+    let mut parser = matches.get_one::<String>("parser").map(String::as_str);
+    // Chemstation `.D` runs are directories too, but all of their signals are read together
+    let agilent_run = match matches.get_one::<String>("input").map(Path::new) {
+        Some(_) if parser == Some("agilent_run") => true,
+        Some(i) if parser.is_none() && i.is_dir() => {
+            let is_d = i
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("d"));
+            is_d && run_signal_paths(i).is_ok()
+        }
+        _ => false,
+    };
+    let input = match matches.get_one::<String>("input") {
+        // Waters runs are directories, so read the data of their first function
+        Some(i) if !agilent_run && is_run(Path::new(i)) => {
+            parser = parser.or(Some("waters"));
+            Some(data_path(Path::new(i))?.to_string_lossy().into_owned())
+        }
+        Some(i) if !agilent_run && Path::new(i).is_dir() => {
+            return Err(format!(
+                "{} is a directory, but not an Agilent .D or a Waters .raw run",
+                i
+            )
+            .into());
+        }
+        i => i.cloned(),
+    };
+    let (mut rec_reader, _) = if let (true, Some(i)) = (agilent_run, &input) {
+        let reader: Box<dyn RecordReader> =
+            Box::new(AgilentRunReader::new(Path::new(i), parse_params)?);
+        (reader, "agilent_run")
+    } else if let Some(i) = &input {
+        parse_params.insert("filename".to_string(), Value::String(i.clone().into()));
+        let file = File::open(i)?;
+        // named pipes (e.g. from `-i <(zcat x.gz)`) and devices can't be mapped or read twice
+        // so they have to be streamed like stdin
+        if !file.metadata()?.is_file() {
+            stream_reader(Threaded(file), parser, parse_params)?
+        } else {
+            #[cfg(feature = "mmap")]
+            {
+                mmap = unsafe { Mmap::map(&file)? };
+                if FileType::from_magic(&mmap).is_compression() {
+                    // the data has to be streamed through the decompressor anyways so do that
+                    // on another thread instead of reading from the map
+                    get_reader_threaded(file, parser, Some(parse_params))?
+                } else {
+                    get_reader(mmap.as_ref(), parser, Some(parse_params))?
+                }
+            }
+            #[cfg(not(feature = "mmap"))]
+            get_reader_threaded(file, parser, Some(parse_params))?
         }
-        #[cfg(not(feature = "mmap"))]
-        get_reader(file, parser, Some(parse_params))?
     } else {
-        let buffer: Box<dyn io::Read> = Box::new(stdin);
-        get_reader(buffer, parser, Some(parse_params))?
+        stream_reader(stdin, parser, parse_params)?
+    };
+    rec_reader = pipeline::transform(rec_reader, matches)?;
+    // the report is kept here (and not with a `ReportReader`) so it only counts the records
+    // that are actually written out
+    let mut report = matches.get_flag("report").then(Report::new);
+    // TODO: allow user to set the other output params (e.g. the null value and delimiters)
+    let nan_value = matches
+        .get_one::<String>("nan_value")
+        .map_or("NaN", String::as_str);
+    let inf_value = matches
+        .get_one::<String>("inf_value")
+        .map_or("inf", String::as_str);
+    // each line's written into the `Vec` and then moved into the output
+    let new_tsv_writer = || {
+        TsvWriter::new(Vec::new())
+            .nan_value(nan_value)
+            .inf_value(inf_value)
     };
-    // TODO: allow user to set these
-    let params = TsvParams::default();
 
-    let mut writer: Box<dyn io::Write> = if let Some(&i) = matches.get_one::<&str>("output") {
-        Box::new(File::create(i)?)
+    if matches.get_flag("append") && output_format != "tsv" {
+        return Err(format!(
+            "Can only append to TSV output ({} files don't have a header to check)",
+            output_format
+        )
+        .into());
+    }
+    // when appending, the header line already in the file (if there is one) and if its last
+    // line needs to be ended before the new rows are added
+    let mut existing_header = None;
+    let mut unterminated = false;
+    #[cfg(feature = "parquet")]
+    let mut parquet_file = None;
+    let output: Box<dyn io::Write> = if let Some(i) = output_path {
+        if output_format == "parquet" {
+            #[cfg(feature = "parquet")]
+            {
+                // the file's read back if a column's type has to be widened
+                parquet_file = Some(
+                    OpenOptions::new()
+                        .read(true)
+                        .write(true)
+                        .create(true)
+                        .truncate(true)
+                        .open(i)?,
+                );
+            }
+            Box::new(io::sink())
+        } else if matches.get_flag("append") {
+            existing_header = read_header(Path::new(i), b"\n")?;
+            unterminated = existing_header.is_some() && !ends_with_delimiter(Path::new(i), b"\n")?;
+            Box::new(OpenOptions::new().append(true).create(true).open(i)?)
+        } else {
+            Box::new(File::create(i)?)
+        }
+    } else if output_format == "parquet" {
+        return Err("Parquet can only be written to a file (with -o)".into());
     } else {
         Box::new(stdout)
     };
+    let mut writer = BufWriter::with_capacity(WRITE_BUFFER_SIZE, output);
 
     if matches.get_flag("metadata") {
-        writer.write_all(b"key")?;
-        writer.write_all(&[params.main_delimiter])?;
-        writer.write_all(b"value")?;
-        writer.write_all(&params.line_delimiter)?;
-        for (key, value) in rec_reader.metadata() {
-            params.write_str(key.as_bytes(), &mut writer)?;
-            writer.write_all(&[params.main_delimiter])?;
-            params.write_value(&value, &mut writer)?;
-            writer.write_all(&params.line_delimiter)?;
+        let metadata = rec_reader.metadata();
+        match matches
+            .get_one::<String>("metadata_format")
+            .map(String::as_str)
+        {
+            Some("json") => {
+                serde_json::to_writer_pretty(&mut writer, &metadata)
+                    .map_err(|e| EtError::from(format!("Could not write metadata: {}", e)))?;
+                writer.write_all(b"\n")?;
+                writer.flush()?;
+                return Ok(());
+            }
+            Some("yaml") => {
+                serde_yaml::to_writer(&mut writer, &metadata)
+                    .map_err(|e| EtError::from(format!("Could not write metadata: {}", e)))?;
+                writer.flush()?;
+                return Ok(());
+            }
+            _ => {}
         }
+        let mut tsv_writer = new_tsv_writer();
+        tsv_writer.write_headers(&["key".into(), "value".into()])?;
+        for (key, value) in metadata {
+            tsv_writer.write_record(&[Value::from(key), value])?;
+        }
+        writer.write_all(tsv_writer.get_mut())?;
+        writer.flush()?;
         return Ok(());
     }
-    writer.write_all(
-        rec_reader
-            .headers()
-            .join(str::from_utf8(&[params.main_delimiter])?)
-            .as_bytes(),
-    )?;
-    writer.write_all(&params.line_delimiter)?;
 
-    while let Some(fields) = rec_reader.next_record()? {
-        params.write_value(&fields[0], &mut writer)?;
-        for field in fields.iter().skip(1) {
-            writer.write_all(&[params.main_delimiter])?;
-            params.write_value(field, &mut writer)?;
-        }
-        writer.write_all(&params.line_delimiter)?;
+    if let Some(n_records) = matches.get_one::<usize>("preview") {
+        let color = match matches.get_one::<String>("color").map(String::as_str) {
+            Some("always") => true,
+            Some("never") => false,
+            _ => {
+                matches.get_one::<String>("output").is_none()
+                    && io::stdout().is_terminal()
+                    && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+            }
+        };
+        return preview::write_preview(&mut *rec_reader, *n_records, color, writer);
     }
-    writer.flush()?;
 
-    Ok(())
-}
+    let on_new_columns = match matches.get_one::<String>("on_new_columns") {
+        Some(action) => action.as_str(),
+        // MessagePack readers can pick up new headers partway through the stream
+        None if output_format == "msgpack-stream" => "restart",
+        None => "buffer",
+    };
+    if on_new_columns != "buffer" && !["tsv", "msgpack-stream"].contains(&output_format) {
+        return Err(format!(
+            "--on-new-columns {} can only be used with tsv or msgpack-stream output",
+            on_new_columns
+        )
+        .into());
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    // ragged records can add new columns at any point, so they either all have to be read
+    // before we know what the headers are or the headers have to change as they're written
+    let mut ragged_records = None;
+    let mut streaming_ragged = None;
+    let mut headers = if matches.get_flag("ragged") && on_new_columns != "buffer" {
+        let mut ragged_reader = RaggedReader::new(rec_reader);
+        // read the first record to find the headers to start with
+        let first = next_ragged_record(&mut ragged_reader, false, 1)?;
+        ragged_records = Some(first.into_iter().collect::<Vec<_>>().into_iter());
+        let headers = ragged_reader.headers().to_vec();
+        // the reader is put back once all the records have been written
+        rec_reader = Box::new(EmptyReader::new(headers.clone()));
+        streaming_ragged = Some(ragged_reader);
+        headers
+    } else if matches.get_flag("ragged") {
+        let mut ragged_reader = RaggedReader::new(rec_reader);
+        let mut records = Vec::new();
+        while let Some(fields) = ragged_reader.next_fields()? {
+            records.push(
+                fields
+                    .into_iter()
+                    .map(|(name, value)| (name, value.into_owned()))
+                    .collect::<Vec<_>>(),
+            );
+        }
+        let filled: Vec<Vec<Value<'static>>> = records
+            .into_iter()
+            .map(|fields| ragged_reader.fill(fields))
+            .collect();
+        ragged_records = Some(filled.into_iter());
+        let headers = ragged_reader.headers().to_vec();
+        rec_reader = ragged_reader.into_inner();
+        headers
+    } else {
+        rec_reader.headers()
+    };
+    let schema = match matches.get_one::<String>("schema") {
+        Some(path) => Some(Schema::from_reader(File::open(path)?, &headers)?),
+        None => None,
+    };
+    let mut linter = if matches.get_flag("warn") {
+        Some(Linter::for_reader(&*rec_reader))
+    } else {
+        None
+    };
+    let on_invalid: InvalidAction = matches
+        .get_one::<String>("on_invalid")
+        .map_or(Ok(InvalidAction::Report), |a| a.parse())?;
 
-    #[test]
-    fn test_version() -> Result<(), EtError> {
-        let mut out = Vec::new();
-        assert!(run(["entab", "--version"], &b""[..], io::Cursor::new(&mut out)).is_ok());
-        assert_eq!(&out[..], b"");
-        Ok(())
+    let mut sequence_writer = match output_format {
+        "fasta" => Some(SequenceFormat::Fasta),
+        "fastq" => Some(SequenceFormat::Fastq),
+        _ => None,
     }
+    .map(|format| {
+        let default_quality = match matches
+            .get_one::<String>("default_quality")
+            .map(String::as_bytes)
+        {
+            Some(&[c]) if c.is_ascii_graphic() => c,
+            _ => {
+                return Err(EtError::from(
+                    "--default-quality must be a single character",
+                ))
+            }
+        };
+        // each record's written into the `Vec` and then moved into the output
+        let mut sequence_writer = SequenceWriter::new(Vec::new(), format)
+            .line_wrap(line_wrap)
+            .embed_quality(matches.get_flag("embed_quality"))
+            .default_quality(default_quality);
+        sequence_writer.write_headers(&headers)?;
+        Ok(sequence_writer)
+    })
+    .transpose()?;
 
-    #[test]
-    fn test_output() -> Result<(), EtError> {
-        let mut out = Vec::new();
-        assert!(run(["entab"], &b">test\nACGT"[..], io::Cursor::new(&mut out)).is_ok());
-        println!("{}", std::str::from_utf8(&out).unwrap());
-        assert_eq!(&out[..], b"id\tsequence\ntest\tACGT\n");
-        Ok(())
+    #[cfg(feature = "parquet")]
+    let mut parquet_writer = parquet_file
+        .map(|file| ParquetWriter::new(file, &headers, &rec_reader.metadata()))
+        .transpose()?;
+    let msgpack = output_format == "msgpack-stream";
+    let mut tsv_writer = new_tsv_writer();
+    tsv_writer.write_headers(&headers)?;
+    let mut header_line = std::mem::take(tsv_writer.get_mut());
+    let _ = header_line.pop();
+    match existing_header {
+        // sequence files don't have a header (and Parquet files store it themselves)
+        _ if sequence_writer.is_some() || output_format == "parquet" => {}
+        None if msgpack => {
+            let mut schema = Vec::new();
+            write_message(
+                &StreamSchema {
+                    headers: &headers,
+                    metadata: match &streaming_ragged {
+                        Some(ragged_reader) => ragged_reader.metadata(),
+                        None => rec_reader.metadata(),
+                    },
+                },
+                &mut schema,
+            )?;
+            writer.write_all(&schema)?;
+        }
+        Some(existing) if existing != header_line => {
+            return Err(format!(
+                "Can not append; the output's header ({}) does not match the input's ({})",
+                String::from_utf8_lossy(&existing),
+                String::from_utf8_lossy(&header_line),
+            )
+            .into());
+        }
+        // don't glue the first new row onto the end of the file's last line
+        Some(_) if unterminated => writer.write_all(b"\n")?,
+        Some(_) => {}
+        // an empty input doesn't have any columns to write a header line for
+        None if headers.is_empty() => {}
+        None => {
+            writer.write_all(&header_line)?;
+            writer.write_all(b"\n")?;
+        }
     }
 
-    #[test]
-    fn test_metadata() -> Result<(), EtError> {
-        let mut out = Vec::new();
-        run(
-            ["entab", "--metadata"],
-            &b">test\nACGT"[..],
-            io::Cursor::new(&mut out),
-        )?;
+    // serialize each record into a reusable buffer so there's only one write per record
+    let mut line = Vec::new();
+    let mut record_num = 0;
+    loop {
+        let fields = match (
+            ragged_records.as_mut().map(Iterator::next),
+            &mut streaming_ragged,
+        ) {
+            (Some(Some(fields)), _) => Some(fields),
+            (Some(None), Some(ragged_reader)) => {
+                let fail = on_new_columns == "fail";
+                let fields = next_ragged_record(ragged_reader, fail, record_num + 1)?;
+                if fields.is_some() && !ragged_reader.new_headers().is_empty() {
+                    // restart with the wider header before the record
+                    headers = ragged_reader.headers().to_vec();
+                    line.clear();
+                    if msgpack {
+                        write_message(&SchemaUpdate { headers: &headers }, &mut line)?;
+                    } else {
+                        tsv_writer = new_tsv_writer();
+                        tsv_writer.write_headers(&headers)?;
+                        line.append(tsv_writer.get_mut());
+                    }
+                    writer.write_all(&line)?;
+                }
+                fields
+            }
+            (Some(None), None) => None,
+            (None, _) => rec_reader.next_record()?,
+        };
+        let fields = match fields {
+            Some(fields) => fields,
+            None => break,
+        };
+        record_num += 1;
+        if let Some(linter) = &mut linter {
+            for warning in linter.check(&fields) {
+                if linter.counts()[warning.check] <= MAX_PRINTED_WARNINGS {
+                    writeln!(
+                        stderr,
+                        "Record {}: {} [{}]",
+                        record_num, warning.message, warning.check
+                    )?;
+                }
+            }
+        }
+        if let Some(schema) = &schema {
+            let violations = schema.validate(&fields);
+            if !violations.is_empty() {
+                let message = format!("Record {}: {}", record_num, violations.join("; "));
+                match on_invalid {
+                    InvalidAction::Fail => return Err(message.into()),
+                    InvalidAction::Drop => {
+                        writeln!(stderr, "{} [dropped]", message)?;
+                        continue;
+                    }
+                    InvalidAction::Report => writeln!(stderr, "{}", message)?,
+                }
+            }
+        }
+        if let Some(report) = &mut report {
+            report.add(&fields);
+        }
+        #[cfg(feature = "parquet")]
+        if let Some(parquet_writer) = &mut parquet_writer {
+            parquet_writer.write_record(&fields)?;
+            continue;
+        }
+        line.clear();
+        match &mut sequence_writer {
+            Some(sequence_writer) => {
+                sequence_writer.write_record(&fields)?;
+                line.append(sequence_writer.get_mut());
+            }
+            None if msgpack => write_message(&fields, &mut line)?,
+            None => {
+                tsv_writer.write_record(&fields)?;
+                line.append(tsv_writer.get_mut());
+            }
+        }
+        writer.write_all(&line)?;
+    }
+    writer.flush()?;
+    if let Some(ragged_reader) = streaming_ragged {
+        rec_reader = ragged_reader.into_inner();
+    }
+    #[cfg(feature = "parquet")]
+    if let Some(parquet_writer) = parquet_writer {
+        parquet_writer.finish()?;
+    }
+    if let Some(linter) = &linter {
+        for (check, count) in linter.counts() {
+            writeln!(stderr, "Warning: {} record(s) with {}", count, check)?;
+        }
+    }
+    if let Some(mut report) = report {
+        report.finish();
+        let report = serde_json::to_string(&report.to_value(headers, rec_reader.bytes_read()))
+            .map_err(|e| EtError::from(format!("Could not write report: {}", e)))?;
+        writeln!(stderr, "{}", report)?;
+    }
+
+    Ok(())
+}
+
+/// Read the next record from a `RaggedReader` lined up with its headers (including any new ones
+/// the record added, unless `fail_on_new` is set in which case that's an error)
+fn next_ragged_record(
+    ragged_reader: &mut RaggedReader<'_>,
+    fail_on_new: bool,
+    record_num: usize,
+) -> Result<Option<Vec<Value<'static>>>, EtError> {
+    let fields: Vec<_> = match ragged_reader.next_fields()? {
+        Some(fields) => fields
+            .into_iter()
+            .map(|(name, value)| (name, value.into_owned()))
+            .collect(),
+        None => return Ok(None),
+    };
+    let new_headers = ragged_reader.new_headers();
+    if fail_on_new && !new_headers.is_empty() {
+        return Err(format!(
+            "Record {} has field(s) that aren't in the header: {}",
+            record_num,
+            new_headers.join(", ")
+        )
+        .into());
+    }
+    Ok(Some(ragged_reader.fill(fields)))
+}
+
+/// Open the file at `path` (passing its name along with `params` to the reader) and detect
+/// its parser
+pub(crate) fn open_path(
+    path: &Path,
+    mut params: BTreeMap<String, Value<'static>>,
+) -> Result<(Box<dyn RecordReader>, &'static str), EtError> {
+    let _ = params.insert(
+        "filename".to_string(),
+        Value::String(path.to_string_lossy().into_owned().into()),
+    );
+    get_reader(File::open(path)?, None, Some(params))
+}
+
+/// Open a reader for data that can only be read through once (stdin or a pipe); the format of
+/// these can only be detected from the start of the data so if that fails the error suggests
+/// choosing the parser instead.
+fn stream_reader<'r, 'n, S: StreamInput<'r>>(
+    data: S,
+    parser: Option<&'n str>,
+    params: BTreeMap<String, Value<'_>>,
+) -> Result<(Box<dyn RecordReader + 'r>, &'n str), EtError> {
+    data.open(parser, params).map_err(|e| {
+        if parser.is_none() && e.kind == EtErrorKind::UnknownFormat {
+            EtError::from(format!(
+                "{}; the format of piped data can only be detected from its contents, so try passing the parser with -p",
+                e.msg
+            ))
+        } else {
+            e
+        }
+    })
+}
+
+/// Read the first line of the file at `path` (without its line ending); returns `None` if the
+/// file doesn't exist or is empty.
+fn read_header(path: &Path, line_delimiter: &[u8]) -> Result<Option<Vec<u8>>, EtError> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let mut header = Vec::new();
+    let last = *line_delimiter.last().unwrap_or(&b'\n');
+    let _ = BufReader::new(file).read_until(last, &mut header)?;
+    if header.is_empty() {
+        return Ok(None);
+    }
+    if header.ends_with(line_delimiter) {
+        header.truncate(header.len() - line_delimiter.len());
+    }
+    if header.last() == Some(&b'\r') {
+        let _ = header.pop();
+    }
+    Ok(Some(header))
+}
+
+/// Move the lines `tsv_writer` has written so far into `writer`
+pub(crate) fn write_lines<W: Write>(
+    tsv_writer: &mut TsvWriter<Vec<u8>>,
+    writer: &mut W,
+) -> Result<(), EtError> {
+    writer.write_all(tsv_writer.get_mut())?;
+    tsv_writer.get_mut().clear();
+    Ok(())
+}
+
+/// Check if the file at `path` ends with the line delimiter (so rows can be appended to it)
+fn ends_with_delimiter(path: &Path, line_delimiter: &[u8]) -> Result<bool, EtError> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let delimiter_len = line_delimiter.len() as u64;
+    if len < delimiter_len {
+        return Ok(false);
+    }
+    let _ = file.seek(SeekFrom::Start(len - delimiter_len))?;
+    let mut end = vec![0; line_delimiter.len()];
+    file.read_exact(&mut end)?;
+    Ok(end == line_delimiter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version() -> Result<(), EtError> {
+        let mut out = Vec::new();
+        assert!(run(["entab", "--version"], &b""[..], io::Cursor::new(&mut out)).is_ok());
+        assert_eq!(&out[..], b"");
+        Ok(())
+    }
+
+    #[test]
+    fn test_version_json() -> Result<(), EtError> {
+        let mut out = Vec::new();
+        run(
+            ["entab", "--version-json"],
+            &b""[..],
+            io::Cursor::new(&mut out),
+        )?;
+        let info: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(info["cli_version"], crate_version!());
+        assert_eq!(info["version"], build_info().version);
+        assert_eq!(info["parsers"]["fasta"], 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_preview() -> Result<(), EtError> {
+        let mut out = Vec::new();
+        run(
+            ["entab", "--preview", "1", "--color", "never"],
+            &b">a\nACGT\n>b\nTT\n"[..],
+            io::Cursor::new(&mut out),
+        )?;
+        assert_eq!(
+            &out[..],
+            &b"id      sequence\nstring  string\na       ACGT\n(showing the first 1 records)\n"[..]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_output() -> Result<(), EtError> {
+        let mut out = Vec::new();
+        assert!(run(["entab"], &b">test\nACGT"[..], io::Cursor::new(&mut out)).is_ok());
+        println!("{}", std::str::from_utf8(&out).unwrap());
+        assert_eq!(&out[..], b"id\tsequence\ntest\tACGT\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_args() -> Result<(), EtError> {
+        let out_file = tempfile::NamedTempFile::new()?;
+        let out_path = out_file.path();
+        run(
+            [
+                "entab",
+                "-i",
+                "../entab/tests/data/sequence.fasta",
+                "-p",
+                "fasta",
+                "-o",
+                out_path.to_str().unwrap(),
+            ],
+            &b""[..],
+            io::sink(),
+        )?;
+        let out = std::fs::read(out_path)?;
+        assert!(out.starts_with(b"id\tsequence\n"));
+        assert_eq!(out.iter().filter(|c| **c == b'\n').count(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_schema() -> Result<(), EtError> {
+        let schema_file = tempfile::NamedTempFile::new()?;
+        let schema_path = schema_file.path();
+        std::fs::write(
+            schema_path,
+            br#"{"fields": [{"name": "sequence", "constraints": {"pattern": "[ACGT]+"}}]}"#,
+        )?;
+        let schema = schema_path.to_str().unwrap();
+
+        let mut out = Vec::new();
+        run(
+            ["entab", "--schema", schema, "--on-invalid", "drop"],
+            &b">a\nACGT\n>b\nACGU\n"[..],
+            io::Cursor::new(&mut out),
+        )?;
+        assert_eq!(&out[..], b"id\tsequence\na\tACGT\n");
+
+        let result = run(
+            ["entab", "--schema", schema, "--on-invalid", "fail"],
+            &b">a\nACGT\n>b\nACGU\n"[..],
+            io::sink(),
+        );
+        assert!(result.unwrap_err().to_string().contains("Record 2"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_aggregate() -> Result<(), EtError> {
+        let mut out = Vec::new();
+        run(
+            ["entab", "-p", "tsv", "--aggregate", "max", "--bin", "1min"],
+            &b"time\tintensity\n0.1\t2\n0.5\t3\n1.5\t1\n"[..],
+            io::Cursor::new(&mut out),
+        )?;
+        assert_eq!(&out[..], b"time\tintensity\n0\t3\n1\t1\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_compressed_inputs() -> Result<(), EtError> {
+        let mut expected = Vec::new();
+        run(
+            [
+                "entab",
+                "-i",
+                "../entab/tests/data/test.csv.bz2",
+                "-p",
+                "csv",
+            ],
+            io::empty(),
+            io::Cursor::new(&mut expected),
+        )?;
+        for path in [
+            "../entab/tests/data/test.csv.xz",
+            "../entab/tests/data/test.csv.zst",
+        ] {
+            let mut out = Vec::new();
+            run(
+                ["entab", "-i", path, "-p", "csv"],
+                io::empty(),
+                io::Cursor::new(&mut out),
+            )?;
+            assert_eq!(out, expected);
+
+            // and when piped in
+            let mut out = Vec::new();
+            run(
+                ["entab", "-p", "csv"],
+                File::open(path)?,
+                io::Cursor::new(&mut out),
+            )?;
+            assert_eq!(out, expected);
+        }
+        assert!(expected.starts_with(b"Test Title\tValue\tValue2\n"));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_named_pipe() -> Result<(), EtError> {
+        let dir = tempfile::tempdir()?;
+        let run_fifo = |name: &str, data: &'static [u8], args: &[&str]| {
+            let path = dir.path().join(name);
+            let status = std::process::Command::new("mkfifo").arg(&path).status()?;
+            assert!(status.success());
+            let mut all_args = vec!["entab", "-i", path.to_str().unwrap()];
+            all_args.extend(args);
+            let all_args: Vec<String> = all_args.into_iter().map(String::from).collect();
+            // a bug in the pipe handling would block forever, so read from another thread and
+            // give up on it after a while
+            let (sender, receiver) = std::sync::mpsc::channel();
+            let _ = std::thread::spawn(move || {
+                // opening the pipe blocks until the other end's opened so write from another
+                // thread
+                let writer_path = path.clone();
+                let writer = std::thread::spawn(move || {
+                    let mut fifo = OpenOptions::new().write(true).open(writer_path)?;
+                    // the reader may stop before reading everything
+                    let _ = fifo.write_all(data);
+                    Ok::<(), io::Error>(())
+                });
+                let mut out = Vec::new();
+                let result = run(all_args, io::empty(), io::Cursor::new(&mut out));
+                let written = writer.join();
+                let _ = std::fs::remove_file(&path);
+                let _ = sender.send((result.map(|()| out), written));
+            });
+            let (result, written) = receiver
+                .recv_timeout(std::time::Duration::from_secs(30))
+                .expect("reading from the named pipe timed out");
+            written.unwrap()?;
+            result
+        };
+
+        let out = run_fifo("entab-cli-test-fifo.fa", b">id\nACGT\n", &[])?;
+        assert_eq!(&out[..], b"id\tsequence\nid\tACGT\n");
+
+        // delimited text is detected from its start too
+        let data = b"x\ty\n1\t2\n";
+        let out = run_fifo("entab-cli-test-fifo.txt", data, &[])?;
+        assert_eq!(&out[..], b"x\ty\n1\t2\n");
+
+        // without a recognizable start, the error should point to -p
+        let data = b"x y\n1 2\n";
+        let err = run_fifo("entab-cli-test-fifo.txt", data, &[]).unwrap_err();
+        assert!(err.msg.contains("-p"));
+        let out = run_fifo("entab-cli-test-fifo.txt", data, &["-p", "tsv"])?;
+        assert_eq!(&out[..], b"x y\n1 2\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_input() -> Result<(), EtError> {
+        let mut out = Vec::new();
+        run(["entab"], io::empty(), io::Cursor::new(&mut out))?;
+        assert!(out.is_empty());
+
+        // formats that always have the same columns still get a header
+        let mut out = Vec::new();
+        run(
+            ["entab", "-p", "fastq"],
+            io::empty(),
+            io::Cursor::new(&mut out),
+        )?;
+        assert_eq!(&out[..], b"id\tsequence\tquality\n");
+
+        let mut out = Vec::new();
+        run(
+            ["entab", "-p", "flow"],
+            io::empty(),
+            io::Cursor::new(&mut out),
+        )?;
+        assert!(out.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_select_agg() -> Result<(), EtError> {
+        let data = &b"sample\ttime\tintensity\na1\t0.5\t10\nb2\t1.0\t20\na1\t1.5\t30\n"[..];
+        let mut out = Vec::new();
+        run(
+            [
+                "entab",
+                "-p",
+                "tsv",
+                "--filter",
+                "intensity >= 20",
+                "--select",
+                "time,sample",
+            ],
+            data,
+            io::Cursor::new(&mut out),
+        )?;
+        assert_eq!(&out[..], b"time\tsample\n1\tb2\n1.5\ta1\n");
+
+        let mut out = Vec::new();
+        run(
+            [
+                "entab",
+                "-p",
+                "tsv",
+                "--agg",
+                "sum:intensity,count:time",
+                "--group-by",
+                "sample",
+            ],
+            data,
+            io::Cursor::new(&mut out),
+        )?;
+        assert_eq!(
+            &out[..],
+            b"sample\tsum_intensity\tcount_time\na1\t40\t2\nb2\t20\t1\n"
+        );
+
+        let mut out = Vec::new();
+        assert!(run(
+            ["entab", "-p", "tsv", "--filter", "missing == 1"],
+            data,
+            io::Cursor::new(&mut out),
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_abs_time() -> Result<(), EtError> {
+        let mut out = Vec::new();
+        run(
+            [
+                "entab",
+                "-i",
+                "../entab/tests/data/HTS_BD_LSR_II_Mixed_Specimen_001_D6_D06.fcs",
+                "--abs-time",
+            ],
+            io::empty(),
+            io::Cursor::new(&mut out),
+        )?;
+        let out = String::from_utf8(out)?;
+        let mut lines = out.lines();
+        assert!(lines.next().unwrap().ends_with("\tTime\tabs_time"));
+        assert!(lines
+            .next()
+            .unwrap()
+            .ends_with("\t0.20000000298023224\t2012-10-26T18:08:10.002"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_fcs_gaps() -> Result<(), EtError> {
+        let mut out = Vec::new();
+        run(
+            [
+                "entab",
+                "-i",
+                "../entab/tests/data/HTS_BD_LSR_II_Mixed_Specimen_001_D6_D06.fcs",
+                "--stream",
+                "events_gaps",
+                "--gap-threshold",
+                "0.007",
+            ],
+            io::empty(),
+            io::Cursor::new(&mut out),
+        )?;
+        let out = String::from_utf8(out)?;
+        let mut lines = out.lines();
+        assert_eq!(lines.next(), Some("event\tstart\tend\tduration"));
+        assert!(lines.next().is_some());
+
+        assert!(run(
+            [
+                "entab",
+                "-i",
+                "../entab/tests/data/HTS_BD_LSR_II_Mixed_Specimen_001_D6_D06.fcs",
+                "--stream",
+                "pauses"
+            ],
+            io::empty(),
+            io::sink(),
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_tsv_options() -> Result<(), EtError> {
+        let mut out = Vec::new();
+        run(
+            [
+                "entab",
+                "-p",
+                "csv",
+                "--delimiter",
+                ";",
+                "--comment-char",
+                "#",
+                "--no-header",
+                "--no-infer-types",
+            ],
+            io::Cursor::new(b"# exported\nx;01\ny;2\n"),
+            io::Cursor::new(&mut out),
+        )?;
+        assert_eq!(String::from_utf8(out)?, "column_1\tcolumn_2\nx\t01\ny\t2\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_agilent_run() -> Result<(), EtError> {
+        let mut out = Vec::new();
+        run(
+            ["entab", "-i", "../entab/tests/data/chemstation_mwd.d"],
+            io::empty(),
+            io::Cursor::new(&mut out),
+        )?;
+        let out = String::from_utf8(out)?;
+        let mut lines = out.lines();
+        assert_eq!(lines.next(), Some("signal\ttime\twavelength\tintensity"));
+        assert!(lines.next().unwrap().starts_with("mwd1A\t"));
+        assert!(lines.last().unwrap().starts_with("mwd1E\t"));
+
+        // any file in the run can be used to read all of it
+        let mut out = Vec::new();
+        run(
+            [
+                "entab",
+                "-i",
+                "../entab/tests/data/chemstation_mwd.d/mwd1C.ch",
+                "-p",
+                "agilent_run",
+                "--metadata",
+            ],
+            io::empty(),
+            io::Cursor::new(&mut out),
+        )?;
+        let out = String::from_utf8(out)?;
+        assert!(out.contains("\nsample\tCytochrome C\n"));
+        assert!(out.contains("\nsignals\tmwd1A,mwd1B,mwd1C,mwd1D,mwd1E\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_kmers() -> Result<(), EtError> {
+        let mut out = Vec::new();
+        run(
+            ["entab", "-p", "kmers", "--kmer-size", "3"],
+            &b">a\nACGTA\n"[..],
+            io::Cursor::new(&mut out),
+        )?;
+        assert_eq!(
+            &out[..],
+            b"id\tpos\tkmer\na\t0\tACG\na\t1\tCGT\na\t2\tGTA\n"
+        );
+
+        let mut out = Vec::new();
+        run(
+            [
+                "entab",
+                "-p",
+                "kmers",
+                "--kmer-size",
+                "3",
+                "--canonical-kmers",
+                "--minimizer-window",
+                "2",
+                "--kmer-counts",
+            ],
+            &b">a\nACGTA\n>b\nTACGT\n"[..],
+            io::Cursor::new(&mut out),
+        )?;
+        assert_eq!(&out[..], b"kmer\tcount\nACG\t3\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_waters_dir() -> Result<(), EtError> {
+        let mut out = Vec::new();
+        run(
+            ["entab", "-i", "../entab/tests/data/waters_example.raw"],
+            io::empty(),
+            io::Cursor::new(&mut out),
+        )?;
+        let out = String::from_utf8(out)?;
+        let mut lines = out.lines();
+        assert_eq!(lines.next(), Some("time\tmz\tintensity"));
+        assert_eq!(lines.next(), Some("0.5\t100.5\t1000"));
+        assert_eq!(lines.count(), 2);
+
+        // other functions can be read with the waters parser
+        let mut out = Vec::new();
+        run(
+            [
+                "entab",
+                "-p",
+                "waters",
+                "-i",
+                "../entab/tests/data/waters_example.raw/_FUNC002.DAT",
+            ],
+            io::empty(),
+            io::Cursor::new(&mut out),
+        )?;
+        assert_eq!(String::from_utf8(out)?.lines().count(), 4);
+
+        // a directory that isn't a run can't be read
+        let err = run(
+            ["entab", "-i", "../entab/tests/data"],
+            io::empty(),
+            io::sink(),
+        )
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("not an Agilent .D or a Waters .raw run"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_warn() -> Result<(), EtError> {
+        // warnings go to stderr and don't change the output
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        run_with(
+            ["entab", "-p", "tsv", "--warn"],
+            Unthreaded(&b"time\tintensity\n-0.1\t2\n0.5\t3\n0.2\t1\n"[..]),
+            io::Cursor::new(&mut out),
+            io::Cursor::new(&mut err),
+        )?;
+        assert_eq!(&out[..], b"time\tintensity\n-0.1\t2\n0.5\t3\n0.2\t1\n");
+        assert_eq!(
+            str::from_utf8(&err)?,
+            "Record 1: time -0.1 is negative [negative_time]\n\
+            Record 3: time 0.2 is before the previous time 0.5 [non_monotonic_time]\n\
+            Warning: 1 record(s) with negative_time\n\
+            Warning: 1 record(s) with non_monotonic_time\n"
+        );
+        assert!(run(["entab", "--warn", "--ragged"], &b">a\nA\n"[..], io::sink()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_report() -> Result<(), EtError> {
+        // the report goes to stderr and doesn't change the output
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        run_with(
+            ["entab", "-p", "tsv", "--report", "--select", "intensity"],
+            Unthreaded(&b"time\tintensity\n0.1\t2\n0.5\t\n"[..]),
+            io::Cursor::new(&mut out),
+            io::Cursor::new(&mut err),
+        )?;
+        assert_eq!(&out[..], b"intensity\n2\n\n");
+        let report: serde_json::Value = serde_json::from_slice(&err).unwrap();
+        assert_eq!(report["n_records"], 2);
+        assert_eq!(report["bytes_read"], 26);
+        assert!(report["wall_time"].as_f64().is_some_and(|t| t >= 0.));
+        // (empty TSV fields are empty strings and not nulls)
+        assert_eq!(report["null_counts"], serde_json::json!({"intensity": 0}));
+
+        // records dropped for not matching the schema aren't counted
+        let schema_file = tempfile::NamedTempFile::new()?;
+        std::fs::write(
+            schema_file.path(),
+            r#"{"fields": [{"name": "intensity", "constraints": {"minLength": 1}}]}"#,
+        )?;
+        let schema = schema_file.path().to_str().unwrap();
+        let mut err = Vec::new();
+        run_with(
+            [
+                "entab",
+                "-p",
+                "tsv",
+                "--report",
+                "--schema",
+                schema,
+                "--on-invalid",
+                "drop",
+            ],
+            Unthreaded(&b"time\tintensity\n0.1\t2\n0.5\t\n"[..]),
+            io::sink(),
+            io::Cursor::new(&mut err),
+        )?;
+        let err = str::from_utf8(&err)?;
+        let (dropped, report) = err.split_once('\n').unwrap();
+        assert!(dropped.ends_with("[dropped]"));
+        let report: serde_json::Value = serde_json::from_str(report).unwrap();
+        assert_eq!(report["n_records"], 1);
+        assert_eq!(
+            report["null_counts"],
+            serde_json::json!({"time": 0, "intensity": 0})
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_non_finite() -> Result<(), EtError> {
+        let mut out = Vec::new();
+        run(
+            [
+                "entab",
+                "-p",
+                "tsv",
+                "--nan-value",
+                "",
+                "--inf-value",
+                "Infinity",
+            ],
+            &b"a\tb\n1\tnan\n-inf\t2.5\n"[..],
+            io::Cursor::new(&mut out),
+        )?;
+        assert_eq!(&out[..], b"a\tb\n1\t\n-Infinity\t2.5\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_tsv_quoting() -> Result<(), EtError> {
+        // the output's quoted the same way the library's TsvWriter does it
+        let data = &b"id,note\na,\"says \"\"hi\"\"\"\nb,\"x\ty\"\n"[..];
+        let mut out = Vec::new();
+        run(["entab", "-p", "csv"], data, io::Cursor::new(&mut out))?;
+        assert_eq!(&out[..], b"id\tnote\na\t\"says \"\"hi\"\"\"\nb\t\"x\ty\"\n");
+
+        let (mut reader, _) = get_reader(data, Some("csv"), None)?;
+        let mut writer = TsvWriter::new(Vec::new());
+        let _ = entab::writers::copy_records(&mut *reader, &mut writer)?;
+        assert_eq!(out, writer.into_inner());
+        Ok(())
+    }
+
+    #[test]
+    fn test_ragged() -> Result<(), EtError> {
+        let mut out = Vec::new();
+        run(
+            ["entab", "--ragged"],
+            &b">a\nACGT\n>b\nTT\n"[..],
+            io::Cursor::new(&mut out),
+        )?;
+        assert_eq!(&out[..], b"id\tsequence\na\tACGT\nb\tTT\n");
+
+        // records without new fields are written the same way as they're read
+        let mut out = Vec::new();
+        run(
+            ["entab", "--ragged", "--on-new-columns", "fail"],
+            &b">a\nACGT\n>b\nTT\n"[..],
+            io::Cursor::new(&mut out),
+        )?;
+        assert_eq!(&out[..], b"id\tsequence\na\tACGT\nb\tTT\n");
+
+        // but only TSV and MessagePack outputs can change their headers partway through
+        assert!(run(
+            [
+                "entab",
+                "--ragged",
+                "-f",
+                "fasta",
+                "--on-new-columns",
+                "restart"
+            ],
+            &b">a\nACGT\n"[..],
+            io::sink(),
+        )
+        .is_err());
+        Ok(())
+    }
+
+    /// A reader with an `attrs` column that has different keys in each record
+    #[derive(Debug)]
+    struct SparseReader(Vec<BTreeMap<String, Value<'static>>>);
+
+    impl RecordReader for SparseReader {
+        fn next_record(&mut self) -> Result<Option<Vec<Value<'_>>>, EtError> {
+            if self.0.is_empty() {
+                return Ok(None);
+            }
+            Ok(Some(vec!["x".into(), Value::Record(self.0.remove(0))]))
+        }
+
+        fn headers(&self) -> Vec<String> {
+            vec!["id".into(), "attrs".into()]
+        }
+
+        fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+            BTreeMap::new()
+        }
+    }
+
+    #[test]
+    fn test_next_ragged_record() -> Result<(), EtError> {
+        let attrs = |key: &str| BTreeMap::from([(key.to_string(), Value::Integer(1))]);
+        let sparse = SparseReader(vec![attrs("a"), attrs("a"), attrs("b")]);
+        let mut ragged_reader = RaggedReader::new(Box::new(sparse));
+        assert_eq!(
+            next_ragged_record(&mut ragged_reader, false, 1)?,
+            Some(vec!["x".into(), 1.into()])
+        );
+        assert!(next_ragged_record(&mut ragged_reader, true, 2)?.is_some());
+        let err = next_ragged_record(&mut ragged_reader, true, 3).unwrap_err();
+        assert_eq!(
+            err.msg,
+            "Record 3 has field(s) that aren't in the header: attrs.b"
+        );
+
+        let sparse = SparseReader(vec![attrs("a"), attrs("b")]);
+        let mut ragged_reader = RaggedReader::new(Box::new(sparse));
+        let _ = next_ragged_record(&mut ragged_reader, false, 1)?;
+        assert_eq!(
+            next_ragged_record(&mut ragged_reader, false, 2)?,
+            Some(vec!["x".into(), Value::Null, 1.into()])
+        );
+        assert_eq!(ragged_reader.new_headers(), ["attrs.b"]);
+        assert_eq!(next_ragged_record(&mut ragged_reader, false, 3)?, None);
+        Ok(())
+    }
+
+    #[cfg(feature = "script")]
+    #[test]
+    fn test_ragged_new_columns() -> Result<(), EtError> {
+        // the second record gets an `attrs.x` field
+        let script = "record.attrs = if record.id == \"b\" { #{x: 1} } else { #{} };";
+        let script_file = tempfile::Builder::new().suffix(".rhai").tempfile()?;
+        std::fs::write(script_file.path(), script)?;
+        let script_path = script_file.path().to_str().unwrap();
+        let fasta = &b">a\nACGT\n>b\nTT\n"[..];
+
+        let mut out = Vec::new();
+        run(
+            ["entab", "--ragged", "--script", script_path],
+            fasta,
+            io::Cursor::new(&mut out),
+        )?;
+        assert_eq!(
+            &out[..],
+            b"id\tsequence\tattrs.x\na\tACGT\tnull\nb\tTT\t1\n"
+        );
+
+        let mut out = Vec::new();
+        let args = ["--ragged", "--script", script_path, "--on-new-columns"];
+        run(
+            [&["entab"][..], &args, &["restart"]].concat(),
+            fasta,
+            io::Cursor::new(&mut out),
+        )?;
+        assert_eq!(
+            &out[..],
+            b"id\tsequence\na\tACGT\nid\tsequence\tattrs.x\nb\tTT\t1\n"
+        );
+
+        let err = run(
+            [&["entab"][..], &args, &["fail"]].concat(),
+            fasta,
+            io::sink(),
+        )
+        .unwrap_err();
+        assert!(err.msg.contains("attrs.x"));
+
+        // MessagePack streams get a new schema message before the record
+        let mut out = Vec::new();
+        run(
+            [
+                "entab",
+                "--ragged",
+                "--script",
+                script_path,
+                "-f",
+                "msgpack-stream",
+            ],
+            fasta,
+            io::Cursor::new(&mut out),
+        )?;
+        let mut messages = Vec::new();
+        let mut rest = &out[..];
+        while !rest.is_empty() {
+            let len = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+            messages.push(&rest[4..4 + len]);
+            rest = &rest[4 + len..];
+        }
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[1], b"\x92\xa1a\xa4ACGT");
+        assert_eq!(
+            messages[2],
+            b"\x81\xa7headers\x93\xa2id\xa8sequence\xa7attrs.x"
+        );
+        assert_eq!(messages[3], b"\x93\xa1b\xa2TT\x01");
+        Ok(())
+    }
+
+    #[test]
+    fn test_metadata() -> Result<(), EtError> {
+        let mut out = Vec::new();
+        run(
+            ["entab", "--metadata"],
+            &b">test\nACGT"[..],
+            io::Cursor::new(&mut out),
+        )?;
         assert_eq!(&out[..], b"key\tvalue\n");
+
+        // transformed records say how they were made
+        let mut out = Vec::new();
+        run(
+            ["entab", "-p", "tsv", "--hash", "--metadata"],
+            &b"a\tb\n1\t2\n"[..],
+            io::Cursor::new(&mut out),
+        )?;
+        let out = String::from_utf8(out)?;
+        // (the steps are JSON, so they're quoted like any other field with quotes in it)
+        assert!(out.contains(
+            "\nprovenance\t\"{\"\"params\"\":{},\"\"parser\"\":\"\"tsv\"\",\"\"step\"\":\"\"parse\"\","
+        ));
+        assert!(out.contains("{\"\"params\"\":{},\"\"step\"\":\"\"hash\"\"}\"\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_metadata_formats() -> Result<(), EtError> {
+        let mut out = Vec::new();
+        run(
+            [
+                "entab",
+                "-i",
+                "../entab/tests/data/test_fid.ch",
+                "-m",
+                "--metadata-format",
+                "json",
+            ],
+            &b""[..],
+            io::Cursor::new(&mut out),
+        )?;
+        let metadata: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(metadata["method"], "EVAL.M");
+        assert_eq!(metadata["run_date"], "2020-08-20T14:00:32");
+
+        let mut out = Vec::new();
+        run(
+            [
+                "entab",
+                "-i",
+                "../entab/tests/data/test_fid.ch",
+                "-m",
+                "--metadata-format",
+                "yaml",
+            ],
+            &b""[..],
+            io::Cursor::new(&mut out),
+        )?;
+        let metadata: serde_yaml::Value = serde_yaml::from_slice(&out).unwrap();
+        assert_eq!(metadata["method"], "EVAL.M");
+
+        // the format only applies to metadata
+        assert!(run(
+            ["entab", "--metadata-format", "json"],
+            &b">test\nACGT"[..],
+            io::Cursor::new(Vec::new()),
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_append() -> Result<(), EtError> {
+        let dir = tempfile::tempdir()?;
+        let out_path = dir.path().join("append.tsv");
+        let out = out_path.to_str().unwrap();
+        run(
+            ["entab", "--append", "-o", out],
+            &b">a\nACGT\n"[..],
+            io::sink(),
+        )?;
+        run(
+            ["entab", "--append", "-o", out],
+            &b">b\nTT\n"[..],
+            io::sink(),
+        )?;
+        assert_eq!(std::fs::read(&out_path)?, b"id\tsequence\na\tACGT\nb\tTT\n");
+
+        // different columns can't be added to the same table
+        let result = run(
+            ["entab", "--append", "-o", out],
+            &b"@c\nAC\n+\nII\n"[..],
+            io::sink(),
+        );
+        let contents = std::fs::read(&out_path)?;
+        assert!(result.unwrap_err().to_string().contains("does not match"));
+        assert_eq!(contents, b"id\tsequence\na\tACGT\nb\tTT\n");
+        assert!(run(["entab", "--append"], &b">a\nA\n"[..], io::sink()).is_err());
+
+        // a file without a newline at the end gets one before the new rows
+        std::fs::write(&out_path, b"id\tsequence\na\tACGT")?;
+        run(
+            ["entab", "--append", "-o", out],
+            &b">b\nTT\n"[..],
+            io::sink(),
+        )?;
+        assert_eq!(std::fs::read(&out_path)?, b"id\tsequence\na\tACGT\nb\tTT\n");
+
+        // only TSVs have a header to check
+        let result = run(
+            ["entab", "--append", "-f", "fasta", "-o", out],
+            &b">c\nA\n"[..],
+            io::sink(),
+        );
+        assert!(result.unwrap_err().to_string().contains("Can only append"));
+        Ok(())
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_parquet_output() -> Result<(), EtError> {
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+
+        let out_file = tempfile::Builder::new().suffix(".parquet").tempfile()?;
+        let out_path = out_file.path();
+        let out = out_path.to_str().unwrap();
+        // the format comes from the extension
+        run(["entab", "-o", out], &b">a\nACGT\n>b\nTT\n"[..], io::sink())?;
+        let reader = SerializedFileReader::new(File::open(out_path)?)
+            .map_err(|e| EtError::from(e.to_string()))?;
+        let metadata = reader.metadata().file_metadata();
+        assert_eq!(metadata.num_rows(), 2);
+        assert_eq!(metadata.schema_descr().column(1).name(), "sequence");
+
+        // or it can be set explicitly (and it works without any records)
+        let tsv_file = tempfile::Builder::new().suffix(".tsv").tempfile()?;
+        let tsv = tsv_file.path().to_str().unwrap();
+        run(
+            ["entab", "-p", "tsv", "-f", "parquet", "-o", tsv],
+            &b"time\tintensity\n"[..],
+            io::sink(),
+        )?;
+        let reader = SerializedFileReader::new(File::open(tsv_file.path())?)
+            .map_err(|e| EtError::from(e.to_string()))?;
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 0);
+
+        // Parquet can't be written to stdout or appended to
+        assert!(run(["entab", "-f", "parquet"], &b">a\nA\n"[..], io::sink()).is_err());
+        assert!(run(
+            ["entab", "--append", "-o", out],
+            &b">a\nA\n"[..],
+            io::sink()
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[cfg(not(feature = "parquet"))]
+    #[test]
+    fn test_parquet_output_disabled() -> Result<(), EtError> {
+        let err = run(["entab", "-f", "parquet"], &b">a\nA\n"[..], io::sink()).unwrap_err();
+        assert!(err.to_string().contains("--features parquet"));
+
+        // a .parquet path falls back to TSV (with a warning about how to get Parquet)
+        let out_file = tempfile::Builder::new().suffix(".parquet").tempfile()?;
+        let out = out_file.path().to_str().unwrap();
+        let mut err = Vec::new();
+        run_with(
+            ["entab", "-o", out],
+            Unthreaded(&b">a\nA\n"[..]),
+            io::sink(),
+            io::Cursor::new(&mut err),
+        )?;
+        assert_eq!(std::fs::read(out_file.path())?, b"id\tsequence\na\tA\n");
+        assert!(str::from_utf8(&err)?.contains("--features parquet"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_align() -> Result<(), EtError> {
+        let path = "../entab/tests/data/test_fid.ch";
+        let mut out = Vec::new();
+        run(
+            [
+                "entab", "align", "-i", path, "-i", path, "--method", "peaks",
+            ],
+            io::empty(),
+            io::Cursor::new(&mut out),
+        )?;
+        let out = String::from_utf8(out).unwrap();
+        let mut lines = out.lines();
+        assert_eq!(lines.next(), Some("file\ttime\taligned_time\tintensity"));
+        let n_lines = out.lines().count();
+        assert_eq!((n_lines - 1) % 2, 0);
+        // both files are the same so the times shouldn't move
+        for line in lines {
+            let fields: Vec<&str> = line.split('\t').collect();
+            assert_eq!(fields[0], path);
+            let (time, aligned): (f64, f64) = (fields[1].parse()?, fields[2].parse()?);
+            assert!((time - aligned).abs() < 1e-6);
+        }
+
+        assert!(run(
+            ["entab", "align", "-i", path, "--max-shift", "-1s"],
+            io::empty(),
+            io::sink(),
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_summary() -> Result<(), EtError> {
+        let mut out = Vec::new();
+        run(
+            [
+                "entab",
+                "summary",
+                "-i",
+                "../entab/tests/data/test_fid.ch",
+                "-i",
+                "../entab/tests/data/sequence.fasta",
+            ],
+            &b""[..],
+            io::Cursor::new(&mut out),
+        )?;
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("file\tparser\tn_records\ttime_min"));
+        assert!(lines[1].contains("\tchemstation_fid\t"));
+        assert!(lines[1].ends_with("\t5970 mix 10nG\trjb\tEVAL.M"));
+        assert!(lines[2].ends_with("\tfasta\t1\tnull\tnull\tnull\tnull\tnull\tnull\tnull"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_join() -> Result<(), EtError> {
+        let dir = tempfile::tempdir()?;
+        let a_path = dir.path().join("a.tsv");
+        let b_path = dir.path().join("b.tsv");
+        std::fs::write(&a_path, "id\tlength\nr1\t10\nr2\t20\n")?;
+        std::fs::write(&b_path, "id\tmapq\nr2\t60\nr3\t5\n")?;
+        let (a, b) = (a_path.to_str().unwrap(), b_path.to_str().unwrap());
+
+        let mut out = Vec::new();
+        run(
+            ["entab", "join", "-p", "tsv", a, b, "--on", "id"],
+            io::empty(),
+            io::Cursor::new(&mut out),
+        )?;
+        assert_eq!(&out[..], b"id\tlength\tmapq\nr2\t20\t60\n");
+        let mut out = Vec::new();
+        run(
+            [
+                "entab", "join", "-p", "tsv", a, b, "--on", "id", "--how", "left",
+            ],
+            io::empty(),
+            io::Cursor::new(&mut out),
+        )?;
+        assert_eq!(&out[..], b"id\tlength\tmapq\nr1\t10\tnull\nr2\t20\t60\n");
+        assert!(run(
+            ["entab", "join", "-p", "tsv", a, b, "--on", "name"],
+            io::empty(),
+            io::sink()
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_hash_and_diff() -> Result<(), EtError> {
+        let mut out = Vec::new();
+        run(
+            ["entab", "--hash"],
+            &b">a\nACGT\n"[..],
+            io::Cursor::new(&mut out),
+        )?;
+        assert!(out.starts_with(b"id\tsequence\t_hash\na\tACGT\t"));
+
+        let mut out = Vec::new();
+        run(
+            [
+                "entab",
+                "diff",
+                "../entab/tests/data/sequence.fasta",
+                "../entab/tests/data/sequence.fasta",
+            ],
+            &b""[..],
+            io::Cursor::new(&mut out),
+        )?;
+        assert_eq!(&out[..], b"change\tid\tsequence\t_hash\n");
+
+        // the files need to have the same columns
+        assert!(run(
+            [
+                "entab",
+                "diff",
+                "../entab/tests/data/sequence.fasta",
+                "../entab/tests/data/test.fastq",
+                "--key",
+                "id",
+            ],
+            &b""[..],
+            io::sink(),
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_extract_segment() -> Result<(), EtError> {
+        let mut out = Vec::new();
+        run(
+            [
+                "entab",
+                "extract-segment",
+                "-i",
+                "../entab/tests/data/HTS_BD_LSR_II_Mixed_Specimen_001_D6_D06.fcs",
+                "--segment",
+                "header",
+            ],
+            io::empty(),
+            io::Cursor::new(&mut out),
+        )?;
+        assert_eq!(out.len(), 58);
+        assert!(out.starts_with(b"FCS3.0"));
+
+        assert!(run(
+            [
+                "entab",
+                "extract-segment",
+                "-i",
+                "../entab/tests/data/HTS_BD_LSR_II_Mixed_Specimen_001_D6_D06.fcs",
+                "--segment",
+                "analysis",
+            ],
+            io::empty(),
+            io::Cursor::new(&mut Vec::new()),
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_demultiplex() -> Result<(), EtError> {
+        let temp_dir = tempfile::tempdir()?;
+        let dir = temp_dir.path();
+        let input = dir.join("reads.tsv");
+        let barcodes = dir.join("barcodes.csv");
+        std::fs::write(&input, b"id\tindex\na\tAACC\nb\tGGTT\nc\tGGTA\n")?;
+        std::fs::write(&barcodes, b"AACC,s1\nGGTT,s2\n")?;
+        let mut out = Vec::new();
+        run(
+            [
+                "entab",
+                "demultiplex",
+                "-i",
+                input.to_str().unwrap(),
+                "--barcodes",
+                barcodes.to_str().unwrap(),
+                "-p",
+                "tsv",
+                "--barcode-column",
+                "index",
+                "--mismatches",
+                "0",
+                "--output-dir",
+                dir.join("out").to_str().unwrap(),
+            ],
+            &b""[..],
+            io::Cursor::new(&mut out),
+        )?;
+        assert_eq!(
+            &out[..],
+            b"sample\trecords\ns1\t1\ns2\t1\nundetermined\t1\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.join("out").join("s2.tsv"))?,
+            "id\tindex\nb\tGGTT\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_batch() -> Result<(), EtError> {
+        let dir = tempfile::tempdir()?;
+        let out_dir = dir.path().to_str().unwrap();
+        run(
+            [
+                "entab",
+                "batch",
+                "--jobs",
+                "2",
+                "--output-dir",
+                out_dir,
+                "../entab/tests/data/test_fid.ch",
+                "../entab/tests/data/sequence.fasta",
+            ],
+            &b""[..],
+            io::sink(),
+        )?;
+        assert!(dir.path().join("test_fid.ch.tsv").exists());
+        assert!(dir.path().join("sequence.fasta.metadata.json").exists());
+
+        // every file is still converted when one fails
+        let err = run(
+            [
+                "entab",
+                "batch",
+                "--output-dir",
+                out_dir,
+                "../entab/tests/data/missing.fa",
+                "../entab/tests/data/test.sam",
+            ],
+            &b""[..],
+            io::sink(),
+        )
+        .unwrap_err();
+        assert_eq!(err.msg, "1 of 2 files could not be converted");
+        assert!(dir.path().join("test.sam.tsv").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sequence_output() -> Result<(), EtError> {
+        let mut out = Vec::new();
+        run(
+            ["entab", "--output-format", "fasta"],
+            &b"@a\nACGT\n+\nIIII\n"[..],
+            io::Cursor::new(&mut out),
+        )?;
+        assert_eq!(&out[..], b">a\nACGT\n");
+
+        let mut out = Vec::new();
+        run(
+            ["entab", "--output-format", "fasta", "--embed-quality"],
+            &b"@a\nACGT\n+\nII#I\n"[..],
+            io::Cursor::new(&mut out),
+        )?;
+        assert_eq!(&out[..], b">a quality=II#I\nACGT\n");
+
+        let mut out = Vec::new();
+        run(
+            [
+                "entab",
+                "--output-format",
+                "fastq",
+                "--default-quality",
+                "5",
+            ],
+            &b">a\nACGT\n>b\nTT\n"[..],
+            io::Cursor::new(&mut out),
+        )?;
+        assert_eq!(&out[..], b"@a\nACGT\n+\n5555\n@b\nTT\n+\n55\n");
+
+        // records without sequences can't be written as them
+        assert!(run(
+            ["entab", "-p", "tsv", "--output-format", "fasta"],
+            &b"a\tb\n1\t2\n"[..],
+            io::sink(),
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_msgpack_output() -> Result<(), EtError> {
+        let mut out = Vec::new();
+        run(
+            ["entab", "--output-format", "msgpack-stream"],
+            &b">a\nACGT\n>b\nTT\n"[..],
+            io::Cursor::new(&mut out),
+        )?;
+        let mut messages = Vec::new();
+        let mut rest = &out[..];
+        while !rest.is_empty() {
+            let len = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+            messages.push(&rest[4..4 + len]);
+            rest = &rest[4 + len..];
+        }
+        assert_eq!(messages.len(), 3);
+        assert_eq!(
+            messages[0],
+            b"\x82\xa7headers\x92\xa2id\xa8sequence\xa8metadata\x80"
+        );
+        assert_eq!(messages[1], b"\x92\xa1a\xa4ACGT");
+        assert_eq!(messages[2], b"\x92\xa1b\xa2TT");
+
+        // streams can't be appended to
+        let out_file = tempfile::NamedTempFile::new()?;
+        let out_path = out_file.path();
+        std::fs::write(out_path, &out)?;
+        assert!(run(
+            [
+                "entab",
+                "--output-format",
+                "msgpack-stream",
+                "--append",
+                "-o",
+                out_path.to_str().unwrap(),
+            ],
+            &b">a\nACGT\n"[..],
+            io::Cursor::new(Vec::new()),
+        )
+        .is_err());
+        assert_eq!(std::fs::read(out_path)?, out);
+        Ok(())
+    }
+
+    #[test]
+    fn test_fasta_line_width() -> Result<(), EtError> {
+        let fasta = b">a\nACGTA\nCG\n>b\nTT\n\nA\n>c\nAC\nGTA\n";
+        let mut out = Vec::new();
+        run(
+            [
+                "entab",
+                "--output-format",
+                "fasta",
+                "--line-width",
+                "preserve",
+            ],
+            &fasta[..],
+            io::Cursor::new(&mut out),
+        )?;
+        assert_eq!(&out[..], &fasta[..]);
+
+        let mut out = Vec::new();
+        run(
+            ["entab", "--output-format", "fasta", "--line-width", "4"],
+            &fasta[..],
+            io::Cursor::new(&mut out),
+        )?;
+        assert_eq!(&out[..], b">a\nACGT\nACG\n>b\nTTA\n>c\nACGT\nA\n");
+
+        let mut out = Vec::new();
+        run(
+            ["entab", "--output-format", "fasta", "--line-width", "0"],
+            &fasta[..],
+            io::Cursor::new(&mut out),
+        )?;
+        assert_eq!(&out[..], b">a\nACGTACG\n>b\nTTA\n>c\nACGTA\n");
         Ok(())
     }
 }
@@ -1,13 +1,13 @@
 use std::env::args_os;
 use std::io;
 
-use entab_cli::run;
+use entab_cli::run_threaded;
 
 pub fn main() {
     let stdin = io::stdin();
     let stdout = io::stdout();
 
-    if let Err(e) = run(args_os(), stdin.lock(), stdout.lock()) {
+    if let Err(e) = run_threaded(args_os(), stdin, stdout.lock()) {
         eprintln!("##### AN ERROR OCCURRED ####");
         eprintln!("{}", e);
         eprintln!("#####");
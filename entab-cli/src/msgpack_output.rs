@@ -0,0 +1,75 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+use serde::Serialize;
+
+use entab::error::EtError;
+use entab::record::Value;
+
+/// The message at the start of a MessagePack stream that describes the records after it
+#[derive(Debug, Serialize)]
+pub struct StreamSchema<'a> {
+    /// The name of each field in the records
+    pub headers: &'a [String],
+    /// The metadata of the file the records were read from
+    pub metadata: BTreeMap<String, Value<'a>>,
+}
+
+/// A message partway through a MessagePack stream of ragged records saying that the records
+/// after it have more fields (which are always added after the existing ones)
+#[derive(Debug, Serialize)]
+pub struct SchemaUpdate<'a> {
+    /// The name of each field in the records from here on
+    pub headers: &'a [String],
+}
+
+/// Serialize `message` as MessagePack onto the end of `buffer`, preceded by its length in bytes
+/// (as a big-endian u32) so a reader knows how much to read for each message without having to
+/// parse it incrementally.
+///
+/// A stream is a `StreamSchema` map followed by each record as an array of values (in the same
+/// order as the headers) and ends when the data does; with ragged records, a `SchemaUpdate` map
+/// comes before the first record with new fields.
+pub fn write_message<T>(message: &T, buffer: &mut Vec<u8>) -> Result<(), EtError>
+where
+    T: Serialize + ?Sized,
+{
+    let start = buffer.len();
+    buffer.extend_from_slice(&[0; 4]);
+    rmp_serde::encode::write_named(buffer, message)
+        .map_err(|e| EtError::from(format!("Could not write MessagePack: {}", e)))?;
+    let len = u32::try_from(buffer.len() - start - 4)
+        .map_err(|_| EtError::from("Record is too large to write as MessagePack"))?;
+    buffer[start..start + 4].copy_from_slice(&len.to_be_bytes());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_message() -> Result<(), EtError> {
+        let mut buffer = Vec::new();
+        let headers = ["id".to_string(), "length".to_string()];
+        write_message(
+            &StreamSchema {
+                headers: &headers,
+                metadata: BTreeMap::new(),
+            },
+            &mut buffer,
+        )?;
+        write_message(&[Value::from("a"), Value::Integer(4)], &mut buffer)?;
+        assert_eq!(
+            buffer,
+            [
+                &[0, 0, 0, 30][..],
+                b"\x82\xa7headers\x92\xa2id\xa6length\xa8metadata\x80",
+                &[0, 0, 0, 4],
+                b"\x92\xa1a\x04",
+            ]
+            .concat()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Seek, SeekFrom};
+use std::sync::Arc;
+
+use arrow_schema::SchemaRef;
+
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ArrowWriter;
+use parquet::file::metadata::KeyValue;
+use parquet::file::properties::WriterProperties;
+
+use entab::error::EtError;
+use entab::record::Value;
+use entab::writers::{cast_batch, empty_batch, ArrowRecordBatchWriter, RecordWriter};
+
+/// The key in the Parquet file's metadata that the input file's metadata is stored under (as
+/// JSON)
+pub const METADATA_KEY: &str = "entab.metadata";
+
+/// Turn a Parquet error into an `EtError`
+fn parquet_error(error: parquet::errors::ParquetError) -> EtError {
+    format!("Could not write Parquet: {}", error).into()
+}
+
+/// Writes records into a Parquet file
+///
+/// The records are collected into Arrow batches and each batch is written out as a row group
+/// once it's full. If a later batch widens a column's type (e.g. a float turns up in a column
+/// of integers), the row groups already in the file are read back and rewritten with the wider
+/// types.
+#[derive(Debug)]
+pub struct ParquetWriter {
+    batches: ArrowRecordBatchWriter,
+    file: Option<File>,
+    writer: Option<(ArrowWriter<File>, SchemaRef)>,
+    properties: WriterProperties,
+}
+
+impl ParquetWriter {
+    /// Start writing records with these headers into `file`; the `metadata` is kept in the
+    /// Parquet file's key-value metadata.
+    ///
+    /// `file` has to be opened for reading as well as writing so that it can be rewritten if a
+    /// column's type is widened.
+    pub fn new(
+        file: File,
+        headers: &[String],
+        metadata: &BTreeMap<String, Value>,
+    ) -> Result<Self, EtError> {
+        let metadata = serde_json::to_string(metadata)
+            .map_err(|e| EtError::from(format!("Could not write metadata: {}", e)))?;
+        let properties = WriterProperties::builder()
+            .set_key_value_metadata(Some(vec![KeyValue::new(
+                METADATA_KEY.to_string(),
+                metadata,
+            )]))
+            .build();
+        let mut batches = ArrowRecordBatchWriter::new();
+        batches.write_headers(headers)?;
+        Ok(ParquetWriter {
+            batches,
+            file: Some(file),
+            writer: None,
+            properties,
+        })
+    }
+
+    /// Start writing into `file` with the column types in `schema`
+    fn start(&self, file: File, schema: SchemaRef) -> Result<ArrowWriter<File>, EtError> {
+        ArrowWriter::try_new(file, schema, Some(self.properties.clone())).map_err(parquet_error)
+    }
+
+    /// Rewrite the row groups that `writer` has written with the wider types in `schema`
+    fn widen(
+        &self,
+        writer: ArrowWriter<File>,
+        schema: SchemaRef,
+    ) -> Result<ArrowWriter<File>, EtError> {
+        let mut file = writer.into_inner().map_err(parquet_error)?;
+        let written = ParquetRecordBatchReaderBuilder::try_new(file.try_clone()?)
+            .and_then(|builder| builder.build())
+            .map_err(parquet_error)?
+            .collect::<Result<Vec<_>, _>>()?;
+        file.set_len(0)?;
+        let _ = file.seek(SeekFrom::Start(0))?;
+        let mut writer = self.start(file, Arc::clone(&schema))?;
+        for batch in written {
+            writer
+                .write(&cast_batch(&batch, Arc::clone(&schema))?)
+                .map_err(parquet_error)?;
+        }
+        Ok(writer)
+    }
+
+    /// Write out any batches that are done (starting the file once the schema's known)
+    fn write_batches(&mut self) -> Result<(), EtError> {
+        while let Some(batch) = self.batches.next_batch() {
+            let schema = batch.schema();
+            let mut writer = match (self.writer.take(), self.file.take()) {
+                (Some((writer, written)), _) if written == schema => writer,
+                (Some((writer, _)), _) => self.widen(writer, Arc::clone(&schema))?,
+                (None, Some(file)) => self.start(file, Arc::clone(&schema))?,
+                (None, None) => return Err("The Parquet file was already closed".into()),
+            };
+            writer.write(&batch).map_err(parquet_error)?;
+            self.writer = Some((writer, schema));
+        }
+        Ok(())
+    }
+
+    /// Add a record to the file
+    pub fn write_record(&mut self, record: &[Value]) -> Result<(), EtError> {
+        self.batches.write_record(record)?;
+        self.write_batches()
+    }
+
+    /// Write out the last records and the Parquet footer
+    pub fn finish(mut self) -> Result<(), EtError> {
+        self.batches.finish()?;
+        self.write_batches()?;
+        let writer = match (self.writer, self.file) {
+            (Some((writer, _)), _) => writer,
+            // there weren't any records, but the file still needs the columns
+            (None, Some(file)) => {
+                let schema = self
+                    .batches
+                    .schema()
+                    .ok_or("Records were never written to the Parquet file")?;
+                let mut writer =
+                    ArrowWriter::try_new(file, Arc::clone(&schema), Some(self.properties))
+                        .map_err(parquet_error)?;
+                writer.write(&empty_batch(schema)?).map_err(parquet_error)?;
+                writer
+            }
+            (None, None) => return Err("The Parquet file was already closed".into()),
+        };
+        let _ = writer.close().map_err(parquet_error)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use entab::writers::DEFAULT_BATCH_SIZE;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    #[test]
+    fn test_parquet_writer() -> Result<(), EtError> {
+        let file = tempfile::Builder::new().suffix(".parquet").tempfile()?;
+        let path = file.path();
+        let mut metadata = BTreeMap::new();
+        let _ = metadata.insert("sample".to_string(), Value::from("a1"));
+        let mut writer = ParquetWriter::new(
+            File::options().read(true).write(true).open(path)?,
+            &["time".to_string(), "intensity".to_string()],
+            &metadata,
+        )?;
+        writer.write_record(&[Value::from(0.5), Value::Integer(10)])?;
+        writer.write_record(&[Value::from(1.0), Value::Null])?;
+        writer.finish()?;
+
+        let reader = SerializedFileReader::new(File::open(path)?).map_err(parquet_error)?;
+        let file_metadata = reader.metadata().file_metadata();
+        assert_eq!(file_metadata.num_rows(), 2);
+        let schema = file_metadata.schema_descr();
+        assert_eq!(schema.column(0).name(), "time");
+        assert_eq!(schema.column(1).name(), "intensity");
+        let key_values = file_metadata.key_value_metadata().unwrap();
+        let stored = key_values
+            .iter()
+            .find(|kv| kv.key == METADATA_KEY)
+            .and_then(|kv| kv.value.as_deref());
+        assert_eq!(stored, Some("{\"sample\":\"a1\"}"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parquet_widening() -> Result<(), EtError> {
+        let file = tempfile::Builder::new().suffix(".parquet").tempfile()?;
+        let path = file.path();
+        let mut writer = ParquetWriter::new(
+            File::options().read(true).write(true).open(path)?,
+            &["n".to_string()],
+            &BTreeMap::new(),
+        )?;
+        // the first row group is written as integers before the float turns up
+        for i in 0..DEFAULT_BATCH_SIZE {
+            writer.write_record(&[Value::Integer(i as i64)])?;
+        }
+        writer.write_record(&[Value::from(0.5)])?;
+        writer.finish()?;
+
+        let reader = SerializedFileReader::new(File::open(path)?).map_err(parquet_error)?;
+        let metadata = reader.metadata();
+        assert_eq!(
+            metadata.file_metadata().num_rows(),
+            DEFAULT_BATCH_SIZE as i64 + 1
+        );
+        let column = metadata.file_metadata().schema_descr().column(0);
+        assert_eq!(column.physical_type(), parquet::basic::Type::DOUBLE);
+        Ok(())
+    }
+}
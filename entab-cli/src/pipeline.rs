@@ -0,0 +1,76 @@
+use clap::{Arg, ArgAction, ArgMatches};
+
+use entab::readers::RecordReader;
+use entab::EtError;
+
+#[cfg(feature = "script")]
+use crate::script::ScriptReader;
+use crate::transform::{AggReader, FilterReader, SelectReader};
+
+/// The options for transforming records between reading and writing them
+pub(crate) fn args() -> Vec<Arg> {
+    let args = vec![
+        Arg::new("filter")
+            .long("filter")
+            .help("Only keep records where a column matches, e.g. \"sample == a1\" or \"intensity > 100\" (can be given more than once)")
+            .action(ArgAction::Append)
+            .conflicts_with("ragged"),
+        Arg::new("select")
+            .long("select")
+            .help("Only write these columns (comma-separated), in this order")
+            .conflicts_with("ragged"),
+        Arg::new("agg")
+            .long("agg")
+            .help("Combine all the records into one with e.g. sum:intensity (sum, mean, min, max, or count; can be comma-separated or given more than once)")
+            .action(ArgAction::Append)
+            .conflicts_with("ragged"),
+        Arg::new("group_by")
+            .long("group-by")
+            .help("With --agg, combine records separately for each value of these columns (comma-separated)")
+            .requires("agg"),
+    ];
+    #[cfg(feature = "script")]
+    let args = {
+        let mut args = args;
+        args.push(
+            Arg::new("script")
+                .long("script")
+                .help("Rhai script to run on each record (as `record`); it can change the record's fields or drop it by evaluating to false")
+                .num_args(1),
+        );
+        args
+    };
+    args
+}
+
+/// Wrap `reader` in the transforms `matches` asks for; records are filtered first, then run
+/// through the script, then aggregated, and then only the selected columns are kept.
+pub(crate) fn transform<'r>(
+    mut reader: Box<dyn RecordReader + 'r>,
+    matches: &ArgMatches,
+) -> Result<Box<dyn RecordReader + 'r>, EtError> {
+    if let Some(filters) = matches.get_many::<String>("filter") {
+        let filters = filters.map(|f| f.parse()).collect::<Result<_, _>>()?;
+        reader = Box::new(FilterReader::new(reader, filters)?);
+    }
+    #[cfg(feature = "script")]
+    if let Some(path) = matches.get_one::<String>("script") {
+        let script = std::fs::read_to_string(path)?;
+        reader = Box::new(ScriptReader::new(reader, &script)?);
+    }
+    if let Some(aggs) = matches.get_many::<String>("agg") {
+        let aggs = aggs
+            .flat_map(|a| a.split(','))
+            .map(str::parse)
+            .collect::<Result<_, _>>()?;
+        let group_by: Vec<&str> = matches
+            .get_one::<String>("group_by")
+            .map_or_else(Vec::new, |g| g.split(',').map(str::trim).collect());
+        reader = Box::new(AggReader::new(reader, &group_by, aggs)?);
+    }
+    if let Some(select) = matches.get_one::<String>("select") {
+        let columns: Vec<&str> = select.split(',').map(str::trim).collect();
+        reader = Box::new(SelectReader::new(reader, &columns)?);
+    }
+    Ok(reader)
+}
@@ -0,0 +1,201 @@
+use std::io::Write;
+
+use entab::readers::RecordReader;
+use entab::record::Value;
+use entab::writers::TsvWriter;
+use entab::EtError;
+
+/// The most characters of a value shown in a preview before it's cut off
+const MAX_WIDTH: usize = 40;
+
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+/// The name of the type of `value` (as shown under the headers of a preview)
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Boolean(_) => "boolean",
+        Value::Datetime(_) => "datetime",
+        Value::Float(_) => "float",
+        Value::Integer(_) => "integer",
+        Value::List(_) => "list",
+        Value::Record(_) => "record",
+        Value::String(_) => "string",
+    }
+}
+
+/// Format `value` to fit on one line of a preview
+pub(crate) fn display_value(
+    value: &Value,
+    formatter: &mut TsvWriter<Vec<u8>>,
+) -> Result<String, EtError> {
+    let mut text: String = formatter
+        .format_field(value)?
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect();
+    if let Some((cut, _)) = text.char_indices().nth(MAX_WIDTH - 1) {
+        text.truncate(cut);
+        text.push('…');
+    }
+    Ok(text)
+}
+
+/// Write one line of a preview, padding each cell out to its column's width (on the left for
+/// numeric columns)
+fn write_row<W: Write>(
+    writer: &mut W,
+    cells: &[&str],
+    widths: &[usize],
+    numeric: &[bool],
+) -> Result<(), EtError> {
+    for (ix, (width, right_align)) in widths.iter().zip(numeric).enumerate() {
+        let text = cells.get(ix).copied().unwrap_or("");
+        if ix > 0 {
+            writer.write_all(b"  ")?;
+        }
+        if *right_align {
+            write!(writer, "{:>width$}", text, width = width)?;
+        } else if ix + 1 == widths.len() {
+            // don't leave trailing spaces at the end of the line
+            writer.write_all(text.as_bytes())?;
+        } else {
+            write!(writer, "{:<width$}", text, width = width)?;
+        }
+    }
+    Ok(())
+}
+
+/// Write the first `n_records` records from `reader` as a table with aligned columns and the
+/// type of each column under its header; with `color`, the headers are bold and the types (and
+/// the note that there are more records) are dimmed.
+///
+/// # Errors
+/// If the records can't be read or written, an error is returned.
+pub fn write_preview<W: Write>(
+    reader: &mut dyn RecordReader,
+    n_records: usize,
+    color: bool,
+    mut writer: W,
+) -> Result<(), EtError> {
+    let headers = reader.headers();
+    let mut formatter = TsvWriter::new(Vec::new());
+    // the types seen in each column (nulls aside)
+    let mut types: Vec<Option<&'static str>> = vec![None; headers.len()];
+    let mut rows = Vec::new();
+    while rows.len() < n_records {
+        let record = match reader.next_record()? {
+            Some(record) => record,
+            None => break,
+        };
+        let mut row = Vec::with_capacity(record.len());
+        for (ix, value) in record.iter().enumerate() {
+            if ix >= types.len() {
+                types.push(None);
+            }
+            types[ix] = match (types[ix], type_name(value)) {
+                (seen, "null") => seen,
+                (None, name) => Some(name),
+                (Some(seen), name) if seen == name => Some(seen),
+                (Some("float"), "integer") | (Some("integer"), "float") => Some("float"),
+                _ => Some("mixed"),
+            };
+            row.push(display_value(value, &mut formatter)?);
+        }
+        rows.push(row);
+    }
+    let more = reader.next_record()?.is_some();
+
+    let types: Vec<&str> = types.into_iter().map(|t| t.unwrap_or("null")).collect();
+    let widths: Vec<usize> = types
+        .iter()
+        .enumerate()
+        .map(|(ix, t)| {
+            let header = headers.get(ix).map_or(0, |h| h.chars().count());
+            rows.iter()
+                .filter_map(|row| row.get(ix))
+                .map(|v| v.chars().count())
+                .fold(header.max(t.len()), usize::max)
+        })
+        .collect();
+    let numeric: Vec<bool> = types
+        .iter()
+        .map(|t| *t == "integer" || *t == "float")
+        .collect();
+
+    let (bold, dim, reset) = if color {
+        (BOLD, DIM, RESET)
+    } else {
+        ("", "", "")
+    };
+    let header_cells: Vec<&str> = headers.iter().map(String::as_str).collect();
+    writer.write_all(bold.as_bytes())?;
+    write_row(&mut writer, &header_cells, &widths, &numeric)?;
+    writer.write_all(reset.as_bytes())?;
+    writer.write_all(b"\n")?;
+    writer.write_all(dim.as_bytes())?;
+    write_row(&mut writer, &types, &widths, &numeric)?;
+    writer.write_all(reset.as_bytes())?;
+    writer.write_all(b"\n")?;
+    for row in &rows {
+        let cells: Vec<&str> = row.iter().map(String::as_str).collect();
+        write_row(&mut writer, &cells, &widths, &numeric)?;
+        writer.write_all(b"\n")?;
+    }
+    if more {
+        writeln!(
+            writer,
+            "{}(showing the first {} records){}",
+            dim,
+            rows.len(),
+            reset
+        )?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use entab::readers::get_reader;
+
+    #[test]
+    fn test_preview() -> Result<(), EtError> {
+        let data: &[u8] = b"name\tcount\tscore\tnote\nalpha\t1\t2.5\tx\nb\t10\t1\ty\nc\t3\t1\tz\n";
+        let (mut reader, _) = get_reader(data, Some("tsv"), None)?;
+        let mut out = Vec::new();
+        write_preview(&mut *reader, 2, false, &mut out)?;
+        assert_eq!(
+            String::from_utf8_lossy(&out),
+            "name      count  score  note\n\
+             string  integer  float  string\n\
+             alpha         1    2.5  x\n\
+             b            10      1  y\n\
+             (showing the first 2 records)\n"
+        );
+
+        let (mut reader, _) = get_reader(data, Some("tsv"), None)?;
+        let mut out = Vec::new();
+        write_preview(&mut *reader, 10, true, &mut out)?;
+        let out = String::from_utf8_lossy(&out);
+        assert!(out.starts_with("\x1b[1mname  "));
+        assert!(!out.contains("showing"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_long_values() {
+        let mut formatter = TsvWriter::new(Vec::new());
+        let long: String = "A".repeat(100);
+        let text = display_value(&long.as_str().into(), &mut formatter).unwrap();
+        assert_eq!(text.chars().count(), MAX_WIDTH);
+        assert!(text.ends_with('…'));
+        assert_eq!(
+            display_value(&"a\tb".into(), &mut formatter).unwrap(),
+            "a b".to_string()
+        );
+    }
+}
@@ -0,0 +1,263 @@
+use std::io::Read;
+use std::str::FromStr;
+
+use regex::Regex;
+use serde::Deserialize;
+
+use entab::error::EtError;
+use entab::record::Value;
+
+/// The types a column can be constrained to.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    #[default]
+    Any,
+    String,
+    Integer,
+    Number,
+    Boolean,
+    Datetime,
+}
+
+/// Checks on a column's values beyond its type (named as in the JSON Table Schema spec).
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Constraints {
+    #[serde(default)]
+    pub required: bool,
+    pub minimum: Option<f64>,
+    pub maximum: Option<f64>,
+    pub min_length: Option<usize>,
+    pub max_length: Option<usize>,
+    pub pattern: Option<String>,
+    #[serde(rename = "enum")]
+    pub allowed: Option<Vec<serde_json::Value>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SchemaField {
+    pub name: String,
+    #[serde(default, rename = "type")]
+    pub field_type: FieldType,
+    #[serde(default)]
+    pub constraints: Constraints,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawSchema {
+    fields: Vec<SchemaField>,
+}
+
+/// A field from the schema matched up to its column in the file being read.
+#[derive(Debug)]
+struct BoundField {
+    column: usize,
+    field: SchemaField,
+    pattern: Option<Regex>,
+}
+
+/// A set of per-column checks to run on every record.
+#[derive(Debug)]
+pub struct Schema {
+    fields: Vec<BoundField>,
+}
+
+impl Schema {
+    /// Read a JSON schema (e.g. `{"fields": [{"name": "id", "type": "string"}]}`) and match its
+    /// fields up with the columns in `headers`.
+    pub fn from_reader<R: Read>(reader: R, headers: &[String]) -> Result<Self, EtError> {
+        let raw: RawSchema = serde_json::from_reader(reader)
+            .map_err(|e| EtError::from(format!("Could not read schema: {}", e)))?;
+        let mut fields = Vec::with_capacity(raw.fields.len());
+        for field in raw.fields {
+            let column = headers
+                .iter()
+                .position(|h| h == &field.name)
+                .ok_or_else(|| format!("Schema field {} is not in the file", field.name))?;
+            let pattern = match &field.constraints.pattern {
+                // patterns have to match the whole value
+                Some(p) => Some(Regex::new(&format!("^(?:{})$", p)).map_err(|e| {
+                    EtError::from(format!("Bad pattern for {}: {}", field.name, e))
+                })?),
+                None => None,
+            };
+            fields.push(BoundField {
+                column,
+                field,
+                pattern,
+            });
+        }
+        Ok(Schema { fields })
+    }
+
+    /// Check a record against the schema, returning a description of every problem found.
+    pub fn validate(&self, record: &[Value<'_>]) -> Vec<String> {
+        let mut violations = Vec::new();
+        for BoundField {
+            column,
+            field,
+            pattern,
+        } in &self.fields
+        {
+            let value = match record.get(*column) {
+                Some(Value::Null) | None => {
+                    if field.constraints.required {
+                        violations.push(format!("{} is required", field.name));
+                    }
+                    continue;
+                }
+                Some(value) => value,
+            };
+            let type_matches = matches!(
+                (field.field_type, value),
+                (FieldType::Any, _)
+                    | (FieldType::String, Value::String(_))
+                    | (FieldType::Integer, Value::Integer(_))
+                    | (FieldType::Number, Value::Integer(_) | Value::Float(_))
+                    | (FieldType::Boolean, Value::Boolean(_))
+                    | (FieldType::Datetime, Value::Datetime(_))
+            );
+            if !type_matches {
+                violations.push(format!(
+                    "{} should be {:?}, but was {:?}",
+                    field.name, field.field_type, value
+                ));
+                continue;
+            }
+
+            let constraints = &field.constraints;
+            let number = match value {
+                Value::Integer(i) => Some(*i as f64),
+                Value::Float(f) => Some(*f),
+                _ => None,
+            };
+            if let (Some(n), Some(min)) = (number, constraints.minimum) {
+                if n < min {
+                    violations.push(format!(
+                        "{} is {}, below the minimum {}",
+                        field.name, n, min
+                    ));
+                }
+            }
+            if let (Some(n), Some(max)) = (number, constraints.maximum) {
+                if n > max {
+                    violations.push(format!(
+                        "{} is {}, above the maximum {}",
+                        field.name, n, max
+                    ));
+                }
+            }
+
+            let length = match value {
+                Value::String(s) => Some(s.chars().count()),
+                Value::List(l) => Some(l.len()),
+                _ => None,
+            };
+            if let (Some(len), Some(min)) = (length, constraints.min_length) {
+                if len < min {
+                    violations.push(format!("{} has length {}, below {}", field.name, len, min));
+                }
+            }
+            if let (Some(len), Some(max)) = (length, constraints.max_length) {
+                if len > max {
+                    violations.push(format!("{} has length {}, above {}", field.name, len, max));
+                }
+            }
+
+            if let (Some(re), Value::String(s)) = (pattern, value) {
+                if !re.is_match(s) {
+                    violations.push(format!("{} does not match the pattern {}", field.name, re));
+                }
+            }
+
+            if let Some(allowed) = &constraints.allowed {
+                let in_enum = allowed.iter().any(|a| match (a, value) {
+                    (serde_json::Value::String(a), Value::String(s)) => a == s,
+                    (serde_json::Value::Bool(a), Value::Boolean(b)) => a == b,
+                    (serde_json::Value::Number(a), Value::Integer(i)) => a.as_i64() == Some(*i),
+                    (serde_json::Value::Number(a), Value::Float(f)) => a.as_f64() == Some(*f),
+                    _ => false,
+                });
+                if !in_enum {
+                    violations.push(format!("{} is not one of the allowed values", field.name));
+                }
+            }
+        }
+        violations
+    }
+}
+
+/// What to do with records that don't match the schema.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum InvalidAction {
+    /// Print the problems to stderr, but keep the record
+    Report,
+    /// Print the problems to stderr and leave the record out of the output
+    Drop,
+    /// Stop with an error
+    Fail,
+}
+
+impl FromStr for InvalidAction {
+    type Err = EtError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "report" => Ok(InvalidAction::Report),
+            "drop" => Ok(InvalidAction::Drop),
+            "fail" => Ok(InvalidAction::Fail),
+            x => Err(format!("Unknown action for invalid records: {}", x).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMA: &[u8] = br#"{"fields": [
+        {"name": "id", "type": "string", "constraints": {"required": true, "pattern": "r[0-9]+"}},
+        {"name": "score", "type": "number", "constraints": {"minimum": 0, "maximum": 1}},
+        {"name": "kind", "constraints": {"enum": ["a", "b"], "maxLength": 1}}
+    ]}"#;
+
+    #[test]
+    fn test_schema_validation() -> Result<(), EtError> {
+        let headers = ["id".to_string(), "score".to_string(), "kind".to_string()];
+        let schema = Schema::from_reader(SCHEMA, &headers)?;
+
+        assert!(schema
+            .validate(&["r1".into(), 0.5.into(), "a".into()])
+            .is_empty());
+        assert!(schema
+            .validate(&["r2".into(), 1.into(), Value::Null])
+            .is_empty());
+
+        let violations = schema.validate(&[Value::Null, 2.into(), "c".into()]);
+        assert_eq!(violations.len(), 3);
+        assert_eq!(violations[0], "id is required");
+
+        let violations = schema.validate(&["x1".into(), "high".into(), "ab".into()]);
+        assert_eq!(violations.len(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_bad_schemas() {
+        let headers = ["id".to_string()];
+        assert!(Schema::from_reader(&b"{}"[..], &headers).is_err());
+        assert!(Schema::from_reader(&br#"{"fields": [{"name": "x"}]}"#[..], &headers).is_err());
+        assert!(Schema::from_reader(
+            &br#"{"fields": [{"name": "id", "type": "text"}]}"#[..],
+            &headers
+        )
+        .is_err());
+        assert!(Schema::from_reader(
+            &br#"{"fields": [{"name": "id", "constraints": {"pattern": "("}}]}"#[..],
+            &headers
+        )
+        .is_err());
+        assert!("ignore".parse::<InvalidAction>().is_err());
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use rhai::{Array, Dynamic, Engine, ImmutableString, Map, Scope, AST, FLOAT, INT};
+
+use entab::capabilities::Capabilities;
+use entab::error::EtError;
+use entab::readers::RecordReader;
+use entab::record::Value;
+
+/// A datetime passed through a script (Rhai doesn't have a datetime type, so scripts can only
+/// pass these along or turn them into text with `to_string`)
+#[derive(Clone, Debug)]
+struct Datetime(Value<'static>);
+
+/// Convert a value into its Rhai equivalent
+fn to_dynamic(value: Value<'_>) -> Dynamic {
+    match value {
+        Value::Null => Dynamic::UNIT,
+        Value::Boolean(b) => b.into(),
+        Value::Datetime(d) => Dynamic::from(Datetime(Value::Datetime(d))),
+        Value::Float(f) => f.into(),
+        Value::Integer(i) => i.into(),
+        Value::String(s) => s.into_owned().into(),
+        Value::List(l) => Dynamic::from_array(l.into_iter().map(to_dynamic).collect()),
+        Value::Record(r) => Dynamic::from_map(
+            r.into_iter()
+                .map(|(key, value)| (key.into(), to_dynamic(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// Convert a value from a script back into a `Value`
+fn from_dynamic(value: Dynamic) -> Result<Value<'static>, EtError> {
+    if value.is_unit() {
+        return Ok(Value::Null);
+    }
+    let value = match value.try_cast_result::<bool>() {
+        Ok(b) => return Ok(Value::Boolean(b)),
+        Err(v) => v,
+    };
+    let value = match value.try_cast_result::<INT>() {
+        Ok(i) => return Ok(Value::Integer(i)),
+        Err(v) => v,
+    };
+    let value = match value.try_cast_result::<FLOAT>() {
+        Ok(f) => return Ok(Value::Float(f)),
+        Err(v) => v,
+    };
+    let value = match value.try_cast_result::<ImmutableString>() {
+        Ok(s) => return Ok(Value::String(s.to_string().into())),
+        Err(v) => v,
+    };
+    let value = match value.try_cast_result::<char>() {
+        Ok(c) => return Ok(Value::String(c.to_string().into())),
+        Err(v) => v,
+    };
+    let value = match value.try_cast_result::<Datetime>() {
+        Ok(Datetime(d)) => return Ok(d),
+        Err(v) => v,
+    };
+    let value = match value.try_cast_result::<Array>() {
+        Ok(a) => {
+            return Ok(Value::List(
+                a.into_iter().map(from_dynamic).collect::<Result<_, _>>()?,
+            ))
+        }
+        Err(v) => v,
+    };
+    match value.try_cast_result::<Map>() {
+        Ok(m) => Ok(Value::Record(
+            m.into_iter()
+                .map(|(key, value)| Ok((key.to_string(), from_dynamic(value)?)))
+                .collect::<Result<_, EtError>>()?,
+        )),
+        Err(v) => Err(format!("Scripts can't return values of type {}", v.type_name()).into()),
+    }
+}
+
+/// Wraps a `RecordReader` to run a Rhai script on each of its records.
+///
+/// The script sees each record as a `record` object map (e.g. `record.time`) that it can
+/// change, add fields to, or remove fields from; if the script evaluates to `false` the record
+/// is dropped. Fields the script adds to the first record it keeps become new columns (after
+/// the existing ones) and fields that are removed become nulls.
+pub struct ScriptReader<'r> {
+    reader: Box<dyn RecordReader + 'r>,
+    engine: Engine,
+    ast: AST,
+    headers: Vec<String>,
+    /// The first kept record (which has to be read ahead to find the headers)
+    first: Option<Vec<Value<'static>>>,
+}
+
+impl<'r> fmt::Debug for ScriptReader<'r> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScriptReader")
+            .field("reader", &self.reader)
+            .field("headers", &self.headers)
+            .finish()
+    }
+}
+
+impl<'r> ScriptReader<'r> {
+    /// Create a new `ScriptReader` that runs `script` on each record from `reader`
+    pub fn new(reader: Box<dyn RecordReader + 'r>, script: &str) -> Result<Self, EtError> {
+        let mut engine = Engine::new();
+        // stdout has the records on it, so anything the script prints goes to stderr
+        engine.on_print(|text| eprintln!("{}", text));
+        engine.on_debug(|text, _, pos| eprintln!("{:?} {}", pos, text));
+        let _ = engine
+            .register_type_with_name::<Datetime>("Datetime")
+            .register_fn("to_string", |d: &mut Datetime| match &d.0 {
+                Value::Datetime(d) => d.to_string(),
+                _ => String::new(),
+            });
+        let ast = engine
+            .compile(script)
+            .map_err(|e| EtError::from(format!("Could not compile script: {}", e)))?;
+        let headers = reader.headers();
+        let mut script_reader = ScriptReader {
+            reader,
+            engine,
+            ast,
+            headers,
+            first: None,
+        };
+        let first = match script_reader.next_map()? {
+            Some(first) => first,
+            None => return Ok(script_reader),
+        };
+        for key in first.keys() {
+            if !script_reader.headers.iter().any(|h| h == key.as_str()) {
+                script_reader.headers.push(key.to_string());
+            }
+        }
+        script_reader.first = Some(script_reader.to_record(first)?);
+        Ok(script_reader)
+    }
+
+    /// Run the script on records until it keeps one and return that record's fields
+    fn next_map(&mut self) -> Result<Option<Map>, EtError> {
+        let headers = self.reader.headers();
+        loop {
+            let record = match self.reader.next_record()? {
+                Some(record) => record,
+                None => return Ok(None),
+            };
+            let map: Map = headers
+                .iter()
+                .zip(record)
+                .map(|(header, value)| (header.into(), to_dynamic(value)))
+                .collect();
+            let mut scope = Scope::new();
+            let _ = scope.push("record", map);
+            let result: Dynamic = self
+                .engine
+                .eval_ast_with_scope(&mut scope, &self.ast)
+                .map_err(|e| EtError::from(format!("Script failed: {}", e)))?;
+            if result.as_bool() == Ok(false) {
+                continue;
+            }
+            return match scope
+                .remove::<Dynamic>("record")
+                .map(Dynamic::try_cast::<Map>)
+            {
+                Some(Some(map)) => Ok(Some(map)),
+                _ => Err("Scripts can't replace the record with something that isn't a map".into()),
+            };
+        }
+    }
+
+    /// Put the fields in `map` into the order of the headers
+    fn to_record(&self, mut map: Map) -> Result<Vec<Value<'static>>, EtError> {
+        let record = self
+            .headers
+            .iter()
+            .map(|header| {
+                map.remove(header.as_str())
+                    .map_or(Ok(Value::Null), from_dynamic)
+            })
+            .collect::<Result<_, _>>()?;
+        if let Some(key) = map.keys().next() {
+            return Err(format!(
+                "Script added a {} field after the first record (new fields have to be in every record)",
+                key
+            )
+            .into());
+        }
+        Ok(record)
+    }
+}
+
+impl<'r> RecordReader for ScriptReader<'r> {
+    fn next_record(&mut self) -> Result<Option<Vec<Value<'_>>>, EtError> {
+        if let Some(first) = self.first.take() {
+            return Ok(Some(first));
+        }
+        match self.next_map()? {
+            Some(map) => Ok(Some(self.to_record(map)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn bytes_read(&self) -> Option<u64> {
+        self.reader.bytes_read()
+    }
+
+    fn headers(&self) -> Vec<String> {
+        self.headers.clone()
+    }
+
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        self.reader.metadata()
+    }
+
+    fn column_metadata(&self) -> BTreeMap<String, BTreeMap<String, Value<'_>>> {
+        self.reader.column_metadata()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        // we can't know how many records the script will drop
+        self.reader.capabilities() - Capabilities::SIZED
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use entab::readers::get_reader;
+
+    const DATA: &[u8] = b"sample\ttime\tintensity\na1\t0.5\t10\nb2\t1.0\t\na1\t1.5\t30\n";
+
+    fn read_all(mut reader: impl RecordReader) -> Result<Vec<Vec<Value<'static>>>, EtError> {
+        let mut records = Vec::new();
+        while let Some(record) = reader.next_record()? {
+            records.push(record.into_iter().map(Value::into_owned).collect());
+        }
+        Ok(records)
+    }
+
+    #[test]
+    fn test_script() -> Result<(), EtError> {
+        let script = r#"
+            if record.intensity == "" { return false; }
+            record.intensity = parse_float(record.intensity) * 2.0;
+            record.sample = record.sample.sub_string(1);
+            record.label = record.sample + "-" + record.time;
+            record.remove("time");
+        "#;
+        let (reader, _) = get_reader(DATA, Some("tsv"), None)?;
+        let reader = ScriptReader::new(reader, script)?;
+        assert_eq!(reader.headers(), ["sample", "time", "intensity", "label"]);
+        assert_eq!(
+            read_all(reader)?,
+            [
+                ["1".into(), Value::Null, Value::Float(20.), "1-0.5".into()],
+                ["1".into(), Value::Null, Value::Float(60.), "1-1.5".into()],
+            ]
+        );
+
+        let (reader, _) = get_reader(DATA, Some("tsv"), None)?;
+        assert!(ScriptReader::new(reader, "record.intensity = ").is_err());
+        let (reader, _) = get_reader(DATA, Some("tsv"), None)?;
+        assert!(ScriptReader::new(reader, "record = 2;").is_err());
+        // fields can only be added to every record
+        let (reader, _) = get_reader(DATA, Some("tsv"), None)?;
+        let reader = ScriptReader::new(reader, r#"if record.sample == "b2" { record.x = 1; }"#)?;
+        assert!(read_all(reader).is_err());
+        Ok(())
+    }
+}
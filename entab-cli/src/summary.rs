@@ -0,0 +1,72 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use entab::summary::RunSummary;
+use entab::EtError;
+
+use crate::open_path;
+
+/// Summarize the file at `path`; if `path` is a directory (e.g. an Agilent `.d` folder), every
+/// file directly inside it with a time column (i.e. the traces or spectra of the run, and not
+/// logs or settings files) is summarized instead.
+///
+/// # Errors
+/// If the file can't be read or the directory has no files that can be summarized, an error is
+/// returned.
+pub fn summarize(path: &Path) -> Result<Vec<(PathBuf, RunSummary)>, EtError> {
+    if !path.is_dir() {
+        let (mut reader, parser) = open_path(path, BTreeMap::new())?;
+        return Ok(vec![(
+            path.to_path_buf(),
+            RunSummary::from_reader(&mut *reader, parser)?,
+        )]);
+    }
+
+    let mut paths = fs::read_dir(path)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.sort();
+    let mut summaries = Vec::new();
+    for file_path in paths {
+        if !file_path.is_file() {
+            continue;
+        }
+        let (mut reader, parser) = match open_path(&file_path, BTreeMap::new()) {
+            Ok(opened) => opened,
+            Err(_) => continue,
+        };
+        let time_col = reader.summary_fields().time;
+        if !reader.headers().iter().any(|h| h == time_col) {
+            continue;
+        }
+        let summary = RunSummary::from_reader(&mut *reader, parser).map_err(|e| {
+            EtError::from(format!("Could not read {}: {}", file_path.display(), e.msg))
+        })?;
+        summaries.push((file_path, summary));
+    }
+    if summaries.is_empty() {
+        return Err(format!("No data files found in {}", path.display()).into());
+    }
+    Ok(summaries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summarize_dir() -> Result<(), EtError> {
+        let summaries = summarize(Path::new("../entab/tests/data/chemstation_mwd.d"))?;
+        assert_eq!(summaries.len(), 5);
+        assert!(summaries[0].0.ends_with("mwd1A.ch"));
+        assert_eq!(summaries[0].1.parser, "chemstation_mwd");
+        assert!(summaries[0].1.time_range.is_some());
+
+        assert!(summarize(Path::new(
+            "../entab/tests/data/masshunter_example/nonexistent"
+        ))
+        .is_err());
+        Ok(())
+    }
+}
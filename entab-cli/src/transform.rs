@@ -0,0 +1,554 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use entab::capabilities::Capabilities;
+use entab::error::EtError;
+use entab::readers::RecordReader;
+use entab::record::Value;
+
+/// Find the position of `column` in `headers`
+fn column_index(headers: &[String], column: &str) -> Result<usize, EtError> {
+    headers
+        .iter()
+        .position(|h| h == column)
+        .ok_or_else(|| format!("There is no {} column", column).into())
+}
+
+/// The text of a value as it's compared in filters and grouped by (like it's written in TSVs)
+fn value_text(value: &Value<'_>) -> Option<String> {
+    Some(match value {
+        Value::Null => "null".to_string(),
+        Value::Boolean(b) => b.to_string(),
+        Value::Datetime(d) => format!("{:?}", d),
+        Value::Float(f) => f.to_string(),
+        Value::Integer(i) => i.to_string(),
+        Value::String(s) => s.to_string(),
+        Value::List(_) | Value::Record(_) => return None,
+    })
+}
+
+/// The value as a number, if it is one (text columns, e.g. from TSVs, are parsed)
+fn value_number(value: &Value<'_>) -> Option<f64> {
+    match value {
+        Value::Float(f) => Some(*f),
+        #[allow(clippy::cast_precision_loss)]
+        Value::Integer(i) => Some(*i as f64),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// How a filter compares a column to its value
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    /// Does `ordering` (of the column's value against the filter's) satisfy this comparison?
+    fn matches(self, ordering: std::cmp::Ordering) -> bool {
+        use std::cmp::Ordering::{Equal, Greater, Less};
+        match self {
+            Comparison::Eq => ordering == Equal,
+            Comparison::Ne => ordering != Equal,
+            Comparison::Lt => ordering == Less,
+            Comparison::Le => ordering != Greater,
+            Comparison::Gt => ordering == Greater,
+            Comparison::Ge => ordering != Less,
+        }
+    }
+}
+
+/// A condition on a column, e.g. `intensity > 100` or `sample == a1`.
+///
+/// Columns holding numbers (or text that can be read as one) are compared numerically if the
+/// value is a number; anything else is compared as text (so e.g. ISO dates can be compared with `<`).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Filter {
+    column: String,
+    comparison: Comparison,
+    value: String,
+    number: Option<f64>,
+}
+
+impl Filter {
+    /// Does the value in this filter's column (at `ix`) of `record` match?
+    fn matches(&self, ix: usize, record: &[Value<'_>]) -> bool {
+        let value = match record.get(ix) {
+            Some(value) => value,
+            None => return false,
+        };
+        if let (Some(number), Some(value)) = (self.number, value_number(value)) {
+            return value
+                .partial_cmp(&number)
+                .is_some_and(|o| self.comparison.matches(o));
+        }
+        match value_text(value) {
+            Some(text) => self.comparison.matches(text.as_str().cmp(&self.value)),
+            None => false,
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = EtError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // the two character operators come first so e.g. `<=` isn't read as `<`
+        let operators = [
+            ("==", Comparison::Eq),
+            ("!=", Comparison::Ne),
+            ("<=", Comparison::Le),
+            (">=", Comparison::Ge),
+            ("<", Comparison::Lt),
+            (">", Comparison::Gt),
+            ("=", Comparison::Eq),
+        ];
+        // split on the first operator in the filter so the value can have others in it
+        let (ix, op, comparison) = s
+            .char_indices()
+            .find_map(|(ix, _)| {
+                operators
+                    .iter()
+                    .find(|(op, _)| s[ix..].starts_with(op))
+                    .map(|(op, comparison)| (ix, *op, *comparison))
+            })
+            .ok_or_else(|| {
+                EtError::from(format!(
+                    "Filter {:?} needs a comparison (==, !=, <, <=, >, or >=)",
+                    s
+                ))
+            })?;
+        let column = s[..ix].trim();
+        if column.is_empty() {
+            return Err(format!("Filter {:?} needs a column name", s).into());
+        }
+        let mut value = s[ix + op.len()..].trim();
+        // quotes are optional, but allow values with spaces at their ends
+        for quote in ['"', '\''] {
+            if value.len() >= 2 && value.starts_with(quote) && value.ends_with(quote) {
+                value = &value[1..value.len() - 1];
+            }
+        }
+        Ok(Filter {
+            column: column.to_string(),
+            comparison,
+            value: value.to_string(),
+            number: value.parse().ok(),
+        })
+    }
+}
+
+/// Wraps a `RecordReader` to only return the records that match every filter
+#[derive(Debug)]
+pub struct FilterReader<'r> {
+    reader: Box<dyn RecordReader + 'r>,
+    filters: Vec<(usize, Filter)>,
+}
+
+impl<'r> FilterReader<'r> {
+    /// Create a new `FilterReader`
+    pub fn new(reader: Box<dyn RecordReader + 'r>, filters: Vec<Filter>) -> Result<Self, EtError> {
+        let headers = reader.headers();
+        let filters = filters
+            .into_iter()
+            .map(|f| Ok((column_index(&headers, &f.column)?, f)))
+            .collect::<Result<_, EtError>>()?;
+        Ok(FilterReader { reader, filters })
+    }
+}
+
+impl<'r> RecordReader for FilterReader<'r> {
+    fn next_record(&mut self) -> Result<Option<Vec<Value<'_>>>, EtError> {
+        loop {
+            let record = match self.reader.next_record()? {
+                Some(record) => record,
+                None => return Ok(None),
+            };
+            if self.filters.iter().all(|(ix, f)| f.matches(*ix, &record)) {
+                // the record has to be owned to return it from inside the loop
+                return Ok(Some(record.into_iter().map(Value::into_owned).collect()));
+            }
+        }
+    }
+
+    fn bytes_read(&self) -> Option<u64> {
+        self.reader.bytes_read()
+    }
+
+    fn headers(&self) -> Vec<String> {
+        self.reader.headers()
+    }
+
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        self.reader.metadata()
+    }
+
+    fn column_metadata(&self) -> BTreeMap<String, BTreeMap<String, Value<'_>>> {
+        self.reader.column_metadata()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        // we can't know how many records will be filtered out
+        self.reader.capabilities() - Capabilities::SIZED
+    }
+}
+
+/// Wraps a `RecordReader` to only return some of its columns (in the order given)
+#[derive(Debug)]
+pub struct SelectReader<'r> {
+    reader: Box<dyn RecordReader + 'r>,
+    columns: Vec<usize>,
+}
+
+impl<'r> SelectReader<'r> {
+    /// Create a new `SelectReader` that returns `columns` from `reader`
+    pub fn new(reader: Box<dyn RecordReader + 'r>, columns: &[&str]) -> Result<Self, EtError> {
+        let headers = reader.headers();
+        let columns = columns
+            .iter()
+            .map(|c| column_index(&headers, c))
+            .collect::<Result<_, _>>()?;
+        Ok(SelectReader { reader, columns })
+    }
+}
+
+impl<'r> RecordReader for SelectReader<'r> {
+    fn next_record(&mut self) -> Result<Option<Vec<Value<'_>>>, EtError> {
+        let record = match self.reader.next_record()? {
+            Some(record) => record,
+            None => return Ok(None),
+        };
+        Ok(Some(
+            self.columns.iter().map(|&ix| record[ix].clone()).collect(),
+        ))
+    }
+
+    fn skip_records(&mut self, n: usize) -> Result<usize, EtError> {
+        self.reader.skip_records(n)
+    }
+
+    fn bytes_read(&self) -> Option<u64> {
+        self.reader.bytes_read()
+    }
+
+    fn headers(&self) -> Vec<String> {
+        let headers = self.reader.headers();
+        self.columns.iter().map(|&ix| headers[ix].clone()).collect()
+    }
+
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        self.reader.metadata()
+    }
+
+    fn column_metadata(&self) -> BTreeMap<String, BTreeMap<String, Value<'_>>> {
+        let headers = self.headers();
+        let mut column_metadata = self.reader.column_metadata();
+        column_metadata.retain(|k, _| headers.contains(k));
+        column_metadata
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.reader.size_hint()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.reader.capabilities()
+    }
+}
+
+/// How to combine the values in a column
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Reduction {
+    Sum,
+    Mean,
+    Min,
+    Max,
+    Count,
+}
+
+/// A reduction of a column into a single value, e.g. `sum:intensity`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Agg {
+    reduction: Reduction,
+    column: String,
+}
+
+impl FromStr for Agg {
+    type Err = EtError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (reduction, column) = s
+            .split_once(':')
+            .ok_or_else(|| EtError::from(format!("Aggregation {:?} must be like sum:column", s)))?;
+        let reduction = match reduction.trim() {
+            "sum" => Reduction::Sum,
+            "mean" => Reduction::Mean,
+            "min" => Reduction::Min,
+            "max" => Reduction::Max,
+            "count" => Reduction::Count,
+            r => {
+                return Err(format!(
+                    "Unknown aggregation {}; it must be sum, mean, min, max, or count",
+                    r
+                )
+                .into())
+            }
+        };
+        Ok(Agg {
+            reduction,
+            column: column.trim().to_string(),
+        })
+    }
+}
+
+/// The running totals for one aggregation in one group
+#[derive(Clone, Copy, Debug)]
+struct Accumulator {
+    count: usize,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Default for Accumulator {
+    fn default() -> Self {
+        Accumulator {
+            count: 0,
+            sum: 0.,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+}
+
+/// Wraps a `RecordReader` to combine all of its records (or all of the records with the same
+/// values in the `group_by` columns) into one.
+///
+/// The records are all read when the first one is asked for; each result has the `group_by`
+/// columns and then a column for each aggregation (e.g. `sum_intensity`), in the order the
+/// groups were first seen. Nulls and empty strings are skipped and any other values that aren't
+/// numbers are errors (except when counting).
+#[derive(Debug)]
+pub struct AggReader<'r> {
+    reader: Box<dyn RecordReader + 'r>,
+    group_by: Vec<usize>,
+    aggs: Vec<(usize, Agg)>,
+    results: Option<std::vec::IntoIter<Vec<Value<'static>>>>,
+}
+
+impl<'r> AggReader<'r> {
+    /// Create a new `AggReader`
+    pub fn new(
+        reader: Box<dyn RecordReader + 'r>,
+        group_by: &[&str],
+        aggs: Vec<Agg>,
+    ) -> Result<Self, EtError> {
+        let headers = reader.headers();
+        let group_by = group_by
+            .iter()
+            .map(|c| column_index(&headers, c))
+            .collect::<Result<_, _>>()?;
+        let aggs = aggs
+            .into_iter()
+            .map(|a| Ok((column_index(&headers, &a.column)?, a)))
+            .collect::<Result<_, EtError>>()?;
+        Ok(AggReader {
+            reader,
+            group_by,
+            aggs,
+            results: None,
+        })
+    }
+
+    /// Read every record and combine them into the results
+    fn aggregate(&mut self) -> Result<Vec<Vec<Value<'static>>>, EtError> {
+        let headers = self.reader.headers();
+        // the values of the group by columns for each group (and their totals)
+        let mut groups: Vec<(Vec<Value<'static>>, Vec<Accumulator>)> = Vec::new();
+        let mut group_ixs: BTreeMap<Vec<Option<String>>, usize> = BTreeMap::new();
+        let (reader, group_by, aggs) = (&mut self.reader, &self.group_by, &self.aggs);
+        while let Some(record) = reader.next_record()? {
+            let key: Vec<_> = group_by.iter().map(|&ix| value_text(&record[ix])).collect();
+            let group_ix = *group_ixs.entry(key).or_insert_with(|| {
+                let values = group_by
+                    .iter()
+                    .map(|&ix| record[ix].clone().into_owned())
+                    .collect();
+                groups.push((values, vec![Accumulator::default(); aggs.len()]));
+                groups.len() - 1
+            });
+            for ((ix, agg), acc) in aggs.iter().zip(&mut groups[group_ix].1) {
+                let value = &record[*ix];
+                if value == &Value::Null || value == &Value::from("") {
+                    continue;
+                }
+                acc.count += 1;
+                if agg.reduction == Reduction::Count {
+                    continue;
+                }
+                let number = value_number(value).ok_or_else(|| {
+                    EtError::from(format!(
+                        "Can not aggregate {} in column {}; it is not a number",
+                        value_text(value).unwrap_or_else(|| "a list".to_string()),
+                        headers[*ix]
+                    ))
+                })?;
+                acc.sum += number;
+                acc.min = acc.min.min(number);
+                acc.max = acc.max.max(number);
+            }
+        }
+        // with no grouping there's always one result, even if there were no records
+        if groups.is_empty() && self.group_by.is_empty() {
+            groups.push((Vec::new(), vec![Accumulator::default(); self.aggs.len()]));
+        }
+
+        Ok(groups
+            .into_iter()
+            .map(|(mut values, accs)| {
+                for ((_, agg), acc) in self.aggs.iter().zip(accs) {
+                    values.push(match agg.reduction {
+                        Reduction::Count => Value::Integer(acc.count as i64),
+                        _ if acc.count == 0 => Value::Null,
+                        Reduction::Sum => acc.sum.into(),
+                        #[allow(clippy::cast_precision_loss)]
+                        Reduction::Mean => (acc.sum / acc.count as f64).into(),
+                        Reduction::Min => acc.min.into(),
+                        Reduction::Max => acc.max.into(),
+                    });
+                }
+                values
+            })
+            .collect())
+    }
+}
+
+impl<'r> RecordReader for AggReader<'r> {
+    fn next_record(&mut self) -> Result<Option<Vec<Value<'_>>>, EtError> {
+        if self.results.is_none() {
+            self.results = Some(self.aggregate()?.into_iter());
+        }
+        Ok(self.results.as_mut().and_then(Iterator::next))
+    }
+
+    fn bytes_read(&self) -> Option<u64> {
+        self.reader.bytes_read()
+    }
+
+    fn headers(&self) -> Vec<String> {
+        let headers = self.reader.headers();
+        let mut names: Vec<String> = self
+            .group_by
+            .iter()
+            .map(|&ix| headers[ix].clone())
+            .collect();
+        for (ix, agg) in &self.aggs {
+            let reduction = match agg.reduction {
+                Reduction::Sum => "sum",
+                Reduction::Mean => "mean",
+                Reduction::Min => "min",
+                Reduction::Max => "max",
+                Reduction::Count => "count",
+            };
+            names.push(format!("{}_{}", reduction, headers[*ix]));
+        }
+        names
+    }
+
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        self.reader.metadata()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use entab::readers::get_reader;
+
+    const DATA: &[u8] =
+        b"sample\ttime\tintensity\na1\t0.5\t10\nb2\t1.0\t\na1\t1.5\t30\nb2\t2.0\t5\n";
+
+    fn read_all(mut reader: impl RecordReader) -> Result<Vec<Vec<Value<'static>>>, EtError> {
+        let mut records = Vec::new();
+        while let Some(record) = reader.next_record()? {
+            records.push(record.into_iter().map(Value::into_owned).collect());
+        }
+        Ok(records)
+    }
+
+    #[test]
+    fn test_parse_filter() -> Result<(), EtError> {
+        let filter: Filter = "intensity >= 10".parse()?;
+        assert_eq!(filter.column, "intensity");
+        assert_eq!(filter.comparison, Comparison::Ge);
+        assert_eq!(filter.number, Some(10.));
+        let filter: Filter = "name == ' a b '".parse()?;
+        assert_eq!(filter.value, " a b ");
+        let filter: Filter = "a<=b=c".parse()?;
+        assert_eq!(filter.column, "a");
+        assert_eq!(filter.comparison, Comparison::Le);
+        assert_eq!(filter.value, "b=c");
+        let filter: Filter = "formula = C6H12O6>0".parse()?;
+        assert_eq!(filter.comparison, Comparison::Eq);
+        assert_eq!(filter.value, "C6H12O6>0");
+        assert!("intensity".parse::<Filter>().is_err());
+        assert!("== 2".parse::<Filter>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_select() -> Result<(), EtError> {
+        let (reader, _) = get_reader(DATA, Some("tsv"), None)?;
+        let reader = FilterReader::new(reader, vec!["sample == a1".parse()?, "time < 1".parse()?])?;
+        let reader = SelectReader::new(Box::new(reader), &["intensity", "sample"])?;
+        assert_eq!(reader.headers(), ["intensity", "sample"]);
+        assert_eq!(read_all(reader)?, [["10".into(), "a1".into()]]);
+
+        let (reader, _) = get_reader(DATA, Some("tsv"), None)?;
+        assert!(FilterReader::new(reader, vec!["missing == 1".parse()?]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_agg() -> Result<(), EtError> {
+        let aggs = || -> Result<Vec<Agg>, EtError> {
+            Ok(vec![
+                "sum:intensity".parse()?,
+                "count:intensity".parse()?,
+                "max:time".parse()?,
+            ])
+        };
+        let (reader, _) = get_reader(DATA, Some("tsv"), None)?;
+        let reader = AggReader::new(reader, &[], aggs()?)?;
+        assert_eq!(
+            reader.headers(),
+            ["sum_intensity", "count_intensity", "max_time"]
+        );
+        assert_eq!(
+            read_all(reader)?,
+            [[45.0.into(), Value::Integer(3), 2.0.into()]]
+        );
+
+        let (reader, _) = get_reader(DATA, Some("tsv"), None)?;
+        let reader = AggReader::new(reader, &["sample"], aggs()?)?;
+        assert_eq!(
+            read_all(reader)?,
+            [
+                ["a1".into(), 40.0.into(), Value::Integer(2), 1.5.into()],
+                ["b2".into(), 5.0.into(), Value::Integer(1), 2.0.into()],
+            ]
+        );
+
+        let (reader, _) = get_reader(DATA, Some("tsv"), None)?;
+        let mut reader = AggReader::new(reader, &[], vec!["mean:sample".parse()?])?;
+        assert!(reader.next_record().is_err());
+        assert!("median:intensity".parse::<Agg>().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,301 @@
+use std::convert::TryFrom;
+
+use entab::readers::RecordReader;
+use entab::writers::TsvWriter;
+use entab::EtError;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Paragraph, Row, Table, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::preview::display_value;
+
+/// How many more records are read than fit on the screen, so scrolling down doesn't stop to
+/// parse after every line
+const READ_AHEAD: usize = 100;
+
+const HELP: &str = "↑/↓ scroll  PgUp/PgDn page  ←/→ columns  Home/End jump  q quit";
+
+/// The state of the explorer: everything shown on the screen and the reader more records are
+/// pulled from as the preview is scrolled.
+#[derive(Debug)]
+pub struct Explorer {
+    name: String,
+    parser: String,
+    reader: Box<dyn RecordReader>,
+    metadata: Vec<(String, String)>,
+    headers: Vec<String>,
+    rows: Vec<Vec<String>>,
+    /// Set once the reader has no more records (or couldn't read one)
+    finished: bool,
+    /// Why the reader stopped early, if it did
+    error: Option<String>,
+    /// The first record and column shown
+    row_offset: usize,
+    col_offset: usize,
+    /// How many records fit on the screen when it was last drawn
+    page_size: usize,
+}
+
+impl Explorer {
+    /// Start exploring the records from `reader` (which was opened from the file `name` with
+    /// `parser`)
+    ///
+    /// # Errors
+    /// If the metadata can't be formatted, an error is returned.
+    pub fn new(reader: Box<dyn RecordReader>, parser: &str, name: &str) -> Result<Self, EtError> {
+        let mut formatter = TsvWriter::new(Vec::new());
+        let metadata = reader
+            .metadata()
+            .iter()
+            .map(|(key, value)| Ok((key.clone(), display_value(value, &mut formatter)?)))
+            .collect::<Result<_, EtError>>()?;
+        Ok(Explorer {
+            name: name.to_string(),
+            parser: parser.to_string(),
+            headers: reader.headers(),
+            reader,
+            metadata,
+            rows: Vec::new(),
+            finished: false,
+            error: None,
+            row_offset: 0,
+            col_offset: 0,
+            page_size: 20,
+        })
+    }
+
+    /// Read records until there are at least `n_rows` (or the reader's finished)
+    fn read_to(&mut self, n_rows: usize) {
+        let mut formatter = TsvWriter::new(Vec::new());
+        while !self.finished && self.rows.len() < n_rows {
+            let row = match self.reader.next_record() {
+                Ok(Some(record)) => record
+                    .iter()
+                    .map(|value| display_value(value, &mut formatter))
+                    .collect::<Result<Vec<_>, EtError>>(),
+                Ok(None) => {
+                    self.finished = true;
+                    break;
+                }
+                Err(e) => Err(e),
+            };
+            match row {
+                Ok(row) => self.rows.push(row),
+                Err(e) => {
+                    self.error = Some(e.msg.into_owned());
+                    self.finished = true;
+                }
+            }
+        }
+    }
+
+    /// Move around the preview for a key press; returns `true` if the explorer should close
+    pub fn handle_key(&mut self, key: KeyCode) -> bool {
+        let last_row = |explorer: &Self| explorer.rows.len().saturating_sub(explorer.page_size);
+        match key {
+            KeyCode::Char('q') | KeyCode::Esc => return true,
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.read_to(self.row_offset + self.page_size + 1);
+                self.row_offset = (self.row_offset + 1).min(last_row(self));
+            }
+            KeyCode::Up | KeyCode::Char('k') => self.row_offset = self.row_offset.saturating_sub(1),
+            KeyCode::PageDown | KeyCode::Char(' ') => {
+                self.read_to(self.row_offset + 2 * self.page_size);
+                self.row_offset = (self.row_offset + self.page_size).min(last_row(self));
+            }
+            KeyCode::PageUp => self.row_offset = self.row_offset.saturating_sub(self.page_size),
+            KeyCode::Home | KeyCode::Char('g') => self.row_offset = 0,
+            // only jump to the end of what's been read so far, since the file could be huge
+            KeyCode::End | KeyCode::Char('G') => self.row_offset = last_row(self),
+            KeyCode::Right | KeyCode::Char('l') => {
+                self.col_offset = (self.col_offset + 1).min(self.headers.len().saturating_sub(1));
+            }
+            KeyCode::Left | KeyCode::Char('h') => {
+                self.col_offset = self.col_offset.saturating_sub(1);
+            }
+            _ => {}
+        }
+        false
+    }
+
+    /// Draw the file's format, its metadata, and a page of its records
+    pub fn draw(&mut self, frame: &mut Frame) {
+        let [title_area, main_area, help_area] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(3),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let metadata_width = if self.metadata.is_empty() { 0 } else { 40 };
+        let [metadata_area, records_area] =
+            Layout::horizontal([Constraint::Length(metadata_width), Constraint::Min(10)])
+                .areas(main_area);
+
+        // the borders and header row take up three lines
+        self.page_size = usize::from(records_area.height.saturating_sub(3)).max(1);
+        self.read_to(self.row_offset + self.page_size + READ_AHEAD);
+
+        let title = format!(
+            "{} · {} parser · {} columns",
+            self.name,
+            self.parser,
+            self.headers.len()
+        );
+        frame.render_widget(
+            Paragraph::new(title).style(Style::default().add_modifier(Modifier::BOLD)),
+            title_area,
+        );
+
+        let metadata: Vec<Line> = self
+            .metadata
+            .iter()
+            .map(|(key, value)| Line::from(format!("{}: {}", key, value)))
+            .collect();
+        frame.render_widget(
+            Paragraph::new(metadata)
+                .wrap(Wrap { trim: false })
+                .block(Block::default().borders(Borders::ALL).title("Metadata")),
+            metadata_area,
+        );
+
+        let columns = self.col_offset..self.headers.len();
+        let widths: Vec<Constraint> = columns
+            .clone()
+            .map(|ix| {
+                let width = self.rows[self.row_offset.min(self.rows.len())..]
+                    .iter()
+                    .take(self.page_size)
+                    .filter_map(|row| row.get(ix))
+                    .map(|value| value.chars().count())
+                    .fold(self.headers[ix].chars().count(), usize::max);
+                Constraint::Length(u16::try_from(width).unwrap_or(u16::MAX))
+            })
+            .collect();
+        let header = Row::new(self.headers[columns.clone()].iter().map(String::as_str))
+            .style(Style::default().add_modifier(Modifier::BOLD));
+        let rows = self
+            .rows
+            .iter()
+            .skip(self.row_offset)
+            .take(self.page_size)
+            .map(|row| Row::new(row.iter().skip(columns.start).map(String::as_str)));
+        let shown = if self.rows.is_empty() {
+            "no records".to_string()
+        } else {
+            format!(
+                "records {}-{} of {}{}",
+                self.row_offset + 1,
+                (self.row_offset + self.page_size).min(self.rows.len()),
+                self.rows.len(),
+                if self.finished { "" } else { "+" }
+            )
+        };
+        frame.render_widget(
+            Table::new(rows, widths).header(header).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!("Records ({})", shown)),
+            ),
+            records_area,
+        );
+
+        let status = match &self.error {
+            Some(e) => format!("Stopped reading: {}", e.trim()),
+            None => HELP.to_string(),
+        };
+        frame.render_widget(
+            Paragraph::new(status).style(Style::default().add_modifier(Modifier::DIM)),
+            help_area,
+        );
+    }
+}
+
+/// Redraw the explorer after every key press until it's closed
+fn event_loop(terminal: &mut DefaultTerminal, explorer: &mut Explorer) -> Result<(), EtError> {
+    loop {
+        let _ = terminal.draw(|frame| explorer.draw(frame))?;
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press && explorer.handle_key(key.code) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Show an interactive view of the records from `reader` in the terminal until the user quits
+///
+/// # Errors
+/// If the terminal can't be set up or drawn to, an error is returned.
+pub fn explore(reader: Box<dyn RecordReader>, parser: &str, name: &str) -> Result<(), EtError> {
+    let mut explorer = Explorer::new(reader, parser, name)?;
+    let mut terminal = ratatui::try_init()?;
+    let result = event_loop(&mut terminal, &mut explorer);
+    ratatui::restore();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use entab::readers::get_reader;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+    use std::io::{Cursor, Read};
+
+    fn screen_text(terminal: &Terminal<TestBackend>) -> String {
+        let buffer = terminal.backend().buffer();
+        let width = usize::from(buffer.area.width);
+        buffer
+            .content()
+            .chunks(width)
+            .map(|line| line.iter().map(|cell| cell.symbol()).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_explorer() -> Result<(), EtError> {
+        let data: Vec<u8> = (0..500)
+            .flat_map(|i| format!(">seq{}\nACGT\n", i).into_bytes())
+            .collect();
+        let stream: Box<dyn Read> = Box::new(Cursor::new(data));
+        let (reader, parser) = get_reader(stream, None, None)?;
+        let mut explorer = Explorer::new(reader, parser, "test.fa")?;
+        let mut terminal = Terminal::new(TestBackend::new(60, 12))?;
+        let _ = terminal.draw(|frame| explorer.draw(frame))?;
+        let text = screen_text(&terminal);
+        assert!(text.contains("test.fa · fasta parser · 2 columns"));
+        assert!(text.contains("id"));
+        assert!(text.contains("seq0"));
+        assert!(!text.contains("seq7 "));
+        // records are only read as they're needed
+        assert!(!explorer.finished);
+
+        assert!(!explorer.handle_key(KeyCode::PageDown));
+        let _ = terminal.draw(|frame| explorer.draw(frame))?;
+        let text = screen_text(&terminal);
+        assert!(text.contains("seq7 "));
+        assert!(!text.contains("seq0 "));
+
+        assert!(!explorer.handle_key(KeyCode::Home));
+        assert_eq!(explorer.row_offset, 0);
+        assert!(explorer.handle_key(KeyCode::Char('q')));
+        Ok(())
+    }
+
+    #[test]
+    fn test_explorer_error() -> Result<(), EtError> {
+        let data: &'static [u8] = b"@a\nACGT\n+\nIIII\n@b\nAC";
+        let (reader, parser) = get_reader(data, Some("fastq"), None)?;
+        let mut explorer = Explorer::new(reader, parser, "bad.fq")?;
+        let mut terminal = Terminal::new(TestBackend::new(80, 10))?;
+        let _ = terminal.draw(|frame| explorer.draw(frame))?;
+        let text = screen_text(&terminal);
+        assert!(text.contains("records 1-1 of 1"));
+        assert!(text.contains("Stopped reading"));
+        Ok(())
+    }
+}
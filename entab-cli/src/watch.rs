@@ -0,0 +1,176 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use notify::{EventKind, RecursiveMode, Watcher};
+
+use entab::readers::get_reader;
+use entab::record::Value;
+use entab::writers::{RecordWriter, TsvWriter};
+use entab::EtError;
+
+use crate::write_lines;
+
+/// Convert the file at `path` into a TSV (and a JSON file of its metadata) in `output_dir`,
+/// returning the path of the TSV. If `parser` isn't given, the file's format is detected.
+///
+/// # Errors
+/// If the file can't be parsed or the outputs can't be written, an error is returned.
+pub fn convert_file(
+    path: &Path,
+    output_dir: &Path,
+    parser: Option<&str>,
+) -> Result<PathBuf, EtError> {
+    let stem = path
+        .file_name()
+        .ok_or_else(|| EtError::from("Path has no file name"))?
+        .to_string_lossy();
+    let mut params = BTreeMap::new();
+    let _ = params.insert(
+        "filename".to_string(),
+        Value::String(path.to_string_lossy().into_owned().into()),
+    );
+    let (mut reader, parser) = get_reader(File::open(path)?, parser, Some(params))?;
+
+    let mut metadata = reader.metadata();
+    let _ = metadata.insert("parser".to_string(), parser.into());
+    let metadata_path = output_dir.join(format!("{}.metadata.json", stem));
+    serde_json::to_writer_pretty(File::create(&metadata_path)?, &metadata)
+        .map_err(|e| EtError::from(format!("Could not write metadata: {}", e)))?;
+
+    let tsv_path = output_dir.join(format!("{}.tsv", stem));
+    let mut writer = BufWriter::new(File::create(&tsv_path)?);
+    let mut tsv_writer = TsvWriter::new(Vec::new());
+    tsv_writer.write_headers(&reader.headers())?;
+    write_lines(&mut tsv_writer, &mut writer)?;
+    while let Some(record) = reader.next_record()? {
+        tsv_writer.write_record(&record)?;
+        write_lines(&mut tsv_writer, &mut writer)?;
+    }
+    writer.flush()?;
+    Ok(tsv_path)
+}
+
+/// Watch `dir` for new files and convert each one into `output_dir` once it hasn't changed for
+/// `settle` (so files that instruments are still writing aren't read half-finished).
+///
+/// `on_converted` is called with the path of each file and the result of converting it; if it
+/// returns `false`, watching stops.
+///
+/// # Errors
+/// If the directory can't be watched, an error is returned. Errors converting individual files
+/// are passed to `on_converted` instead.
+pub fn watch<F>(
+    dir: &Path,
+    output_dir: &Path,
+    settle: Duration,
+    mut on_converted: F,
+) -> Result<(), EtError>
+where
+    F: FnMut(&Path, Result<PathBuf, EtError>) -> bool,
+{
+    fs::create_dir_all(output_dir)?;
+    let output_dir = output_dir.canonicalize()?;
+    if dir.canonicalize()? == output_dir {
+        return Err("The output directory must be different from the watched directory".into());
+    }
+
+    let (tx, rx) = channel();
+    let mut watcher = notify::recommended_watcher(tx)
+        .map_err(|e| EtError::from(format!("Could not start watching: {}", e)))?;
+    watcher
+        .watch(dir, RecursiveMode::NonRecursive)
+        .map_err(|e| EtError::from(format!("Could not watch {}: {}", dir.display(), e)))?;
+
+    // files that have changed recently and when they were last changed
+    let mut pending: BTreeMap<PathBuf, Instant> = BTreeMap::new();
+    loop {
+        match rx.recv_timeout(settle / 4) {
+            Ok(Ok(event)) => {
+                if let EventKind::Create(_) | EventKind::Modify(_) = event.kind {
+                    for path in event.paths {
+                        if !path.starts_with(&output_dir) {
+                            let _ = pending.insert(path, Instant::now());
+                        }
+                    }
+                }
+            }
+            Ok(Err(e)) => eprintln!("Error watching {}: {}", dir.display(), e),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+
+        let settled: Vec<PathBuf> = pending
+            .iter()
+            .filter(|(_, changed)| changed.elapsed() >= settle)
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in settled {
+            let _ = pending.remove(&path);
+            // files can be renamed or deleted before they settle
+            if !path.is_file() {
+                continue;
+            }
+            let result = convert_file(&path, &output_dir, None);
+            if !on_converted(&path, result) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn temp_dir(name: &str) -> Result<PathBuf, EtError> {
+        let dir = std::env::temp_dir().join(format!("entab-cli-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+
+    #[test]
+    fn test_convert_file() -> Result<(), EtError> {
+        let out_dir = temp_dir("convert")?;
+        let tsv_path = convert_file(Path::new("../entab/tests/data/test_fid.ch"), &out_dir, None)?;
+        assert!(fs::read(&tsv_path)?.starts_with(b"time\tintensity\n"));
+        let metadata = fs::read_to_string(out_dir.join("test_fid.ch.metadata.json"))?;
+        assert!(metadata.contains("\"parser\": \"chemstation_fid\""));
+        fs::remove_dir_all(&out_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_watch() -> Result<(), EtError> {
+        let in_dir = temp_dir("watch-in")?;
+        let out_dir = in_dir.join("out");
+        assert!(watch(&in_dir, &in_dir, Duration::from_millis(10), |_, _| true).is_err());
+
+        let writer_dir = in_dir.clone();
+        let _ = thread::spawn(move || {
+            // give the watcher a chance to start
+            thread::sleep(Duration::from_millis(200));
+            fs::write(writer_dir.join("test.fa"), b">a\nACGT\n").unwrap();
+        });
+        let mut converted = Vec::new();
+        watch(
+            &in_dir,
+            &out_dir,
+            Duration::from_millis(100),
+            |path, result| {
+                converted.push((path.to_path_buf(), result));
+                false
+            },
+        )?;
+        let (path, result) = converted.pop().unwrap();
+        assert!(path.ends_with("test.fa"));
+        assert_eq!(fs::read(result?)?, b"id\tsequence\na\tACGT\n");
+        fs::remove_dir_all(&in_dir)?;
+        Ok(())
+    }
+}
@@ -3,12 +3,12 @@ mod utils;
 
 use std::collections::BTreeMap;
 use std::convert::AsRef;
-use std::io::{Cursor, Read};
+use std::io::{self, Cursor, Read};
 
 use entab_base::error::EtError;
 use entab_base::readers::{get_reader, RecordReader};
 use entab_base::record::Value;
-use js_sys::{Array, Object};
+use js_sys::{Array, Function, Object, Reflect, Uint8Array};
 use serde::Serialize;
 use wasm_bindgen::prelude::*;
 
@@ -16,6 +16,49 @@ use wasm_bindgen::prelude::*;
 pub struct NextRecord<'v> {
     value: Option<BTreeMap<&'v str, Value<'v>>>,
     done: bool,
+    /// The number of the record in the file (counting from 0; the number of records read once
+    /// the file's done)
+    index: usize,
+    /// How far into the (decompressed) data the record starts, if the parser knows; this is
+    /// `bytes_read()` from before the record is parsed, so it's the byte the record starts at
+    offset: Option<f64>,
+}
+
+/// Data read by calling a JS function with the number of bytes wanted; it returns a `Uint8Array`
+/// with (up to about) that many bytes or an empty one once there's no more data.
+struct JsStream {
+    read: Function,
+    /// What's left of the last array returned that didn't fit in the buffer being read into
+    leftover: Vec<u8>,
+}
+
+impl Read for JsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.leftover.is_empty() {
+            let chunk = self
+                .read
+                .call1(&JsValue::NULL, &(buf.len() as f64).into())
+                .map_err(|e| {
+                    let msg = e.as_string();
+                    io::Error::other(
+                        msg.unwrap_or_else(|| "Error reading from the stream".to_string()),
+                    )
+                })?;
+            self.leftover = chunk
+                .dyn_into::<Uint8Array>()
+                .map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Stream must return a Uint8Array",
+                    )
+                })?
+                .to_vec();
+        }
+        let n = buf.len().min(self.leftover.len());
+        buf[..n].copy_from_slice(&self.leftover[..n]);
+        drop(self.leftover.drain(..n));
+        Ok(n)
+    }
 }
 
 #[wasm_bindgen]
@@ -23,34 +66,104 @@ pub struct Reader {
     parser: String,
     headers: Vec<String>,
     reader: Box<dyn RecordReader>,
+    /// Reused between calls to `next_batch`
+    batch: Vec<Vec<Value<'static>>>,
+    /// How many records have been read so far
+    n_read: usize,
+    /// An `AbortSignal` that stops the reader once it's aborted
+    signal: Option<JsValue>,
 }
 
 fn to_js(err: EtError) -> JsValue {
-    let res = err.to_string().into();
+    let res = js_sys::Error::new(&err.to_string());
+    res.set_name("EntabError");
+    // where in the file the error happened (or null) so callers don't have to parse the message
+    let position = |pos: Option<u64>| pos.map_or(JsValue::NULL, |p| (p as f64).into());
+    let _ = Reflect::set(&res, &"byte".into(), &position(err.byte()));
+    let _ = Reflect::set(&res, &"record".into(), &position(err.record()));
     // technically we could just take a &EtError, but to have a nice function signature we consume
     // the err so we should also drop it in here to make clippy happy
     drop(err);
-    res
+    res.into()
 }
 
-#[wasm_bindgen]
 impl Reader {
-    #[wasm_bindgen(constructor)]
-    pub fn new(data: Box<[u8]>, parser: Option<String>) -> Result<Reader, JsValue> {
+    fn from_read(
+        stream: Box<dyn Read>,
+        parser: Option<String>,
+        params: Option<JsValue>,
+        signal: Option<JsValue>,
+    ) -> Result<Reader, JsValue> {
         utils::set_panic_hook();
-        if data.is_empty() {
-            return Err(JsValue::from_str("Data is empty or of the wrong type."));
-        }
-        let stream: Box<dyn Read> = Box::new(Cursor::new(data));
 
-        let (reader, parser_used) = get_reader(stream, parser.as_deref(), None).map_err(to_js)?;
+        let params = match params {
+            Some(p) if !p.is_undefined() && !p.is_null() => {
+                let p: BTreeMap<String, String> = serde_wasm_bindgen::from_value(p)
+                    .map_err(|_| JsValue::from_str("Params must be an object of strings"))?;
+                Some(p.into_iter().map(|(k, v)| (k, v.into())).collect())
+            }
+            _ => None,
+        };
+
+        let (reader, parser_used) = get_reader(stream, parser.as_deref(), params).map_err(to_js)?;
         let headers = reader.headers();
         Ok(Reader {
             parser: parser_used.to_string(),
             headers,
             reader,
+            batch: Vec::new(),
+            n_read: 0,
+            signal: signal.filter(|s| !s.is_undefined() && !s.is_null()),
         })
     }
+}
+
+#[wasm_bindgen]
+impl Reader {
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        data: Box<[u8]>,
+        parser: Option<String>,
+        params: Option<JsValue>,
+        signal: Option<JsValue>,
+    ) -> Result<Reader, JsValue> {
+        Reader::from_read(Box::new(Cursor::new(data)), parser, params, signal)
+    }
+
+    /// Read the data a piece at a time by calling `read` with the number of bytes wanted (it
+    /// returns a `Uint8Array` of them, or an empty one at the end) so files don't have to fit
+    /// in memory all at once.
+    pub fn from_stream(
+        read: Function,
+        parser: Option<String>,
+        params: Option<JsValue>,
+        signal: Option<JsValue>,
+    ) -> Result<Reader, JsValue> {
+        let stream = JsStream {
+            read,
+            leftover: Vec::new(),
+        };
+        Reader::from_read(Box::new(stream), parser, params, signal)
+    }
+
+    /// Throw the abort reason (or a "cancelled" error if there isn't one) if the signal has been
+    /// aborted; JS can't abort a signal while a call is running so this is only checked at the
+    /// start of each call.
+    fn check_signal(&self) -> Result<(), JsValue> {
+        let signal = match &self.signal {
+            Some(signal) => signal,
+            None => return Ok(()),
+        };
+        if Reflect::get(signal, &"aborted".into())?.is_falsy() {
+            return Ok(());
+        }
+        let reason = Reflect::get(signal, &"reason".into())?;
+        if reason.is_undefined() {
+            Err(JsValue::from_str("Parsing was cancelled"))
+        } else {
+            Err(reason)
+        }
+    }
 
     #[wasm_bindgen(getter)]
     pub fn parser(&self) -> String {
@@ -66,33 +179,93 @@ impl Reader {
         array.into()
     }
 
+    /// The headers as they are in the file (the same as `headers`, since record keys are never
+    /// renamed in JS; this is here to match the other bindings)
+    #[wasm_bindgen(getter)]
+    pub fn raw_headers(&self) -> JsValue {
+        self.headers()
+    }
+
+    /// The metadata about the file; this is read again each time so it includes anything the
+    /// parser has found since reading started (e.g. Chemstation signal names)
     #[wasm_bindgen(getter)]
     pub fn metadata(&self) -> Result<JsValue, JsValue> {
         serde_wasm_bindgen::to_value(&self.reader.metadata())
             .map_err(|_| JsValue::from_str("Error translating metadata"))
     }
 
+    /// The lower and upper (or `undefined` if unknown) bounds on the number of records left
+    #[wasm_bindgen(getter)]
+    pub fn size_hint(&self) -> JsValue {
+        let (lower, upper) = self.reader.size_hint();
+        let array = Array::new();
+        array.push(&(lower as f64).into());
+        array.push(&upper.map_or(JsValue::UNDEFINED, |u| (u as f64).into()));
+        array.into()
+    }
+
+    /// What the parser can do beyond reading records in order (e.g. `sized` or `has_metadata`)
+    #[wasm_bindgen(getter)]
+    pub fn capabilities(&self) -> JsValue {
+        let array = Array::new();
+        for name in self.reader.capabilities().names() {
+            array.push(&name.into());
+        }
+        array.into()
+    }
+
+    /// Read up to `n` records at once as an array of objects (empty once the file's finished);
+    /// this is much faster than calling `next` for each record.
+    #[wasm_bindgen]
+    pub fn next_batch(&mut self, n: usize) -> Result<JsValue, JsValue> {
+        self.check_signal()?;
+        self.n_read += self.reader.next_batch(&mut self.batch, n).map_err(to_js)?;
+        let records: Vec<BTreeMap<&str, &Value>> = self
+            .batch
+            .iter()
+            .map(|record| self.headers.iter().map(AsRef::as_ref).zip(record).collect())
+            .collect();
+        serde_wasm_bindgen::to_value(&records)
+            .map_err(|_| JsValue::from_str("Error translating records"))
+    }
+
     #[allow(clippy::should_implement_trait)]
     #[wasm_bindgen]
     pub fn next(&mut self) -> Result<JsValue, JsValue> {
+        self.check_signal()?;
+        let index = self.n_read;
+        let offset = self.reader.bytes_read().map(|b| b as f64);
         if let Some(value) = self.reader.next_record().map_err(to_js)? {
+            self.n_read += 1;
             let obj: BTreeMap<&str, Value> =
                 self.headers.iter().map(AsRef::as_ref).zip(value).collect();
             serde_wasm_bindgen::to_value(&NextRecord {
                 value: Some(obj),
                 done: false,
+                index,
+                offset,
             })
             .map_err(|_| JsValue::from_str("Error translating record"))
         } else {
             serde_wasm_bindgen::to_value(&NextRecord {
                 value: None,
                 done: true,
+                index,
+                offset,
             })
             .map_err(|_| JsValue::from_str("Error translating record"))
         }
     }
 }
 
+/// The version of entab, its enabled features, and the version of each parser (so the parser
+/// that produced a table can be recorded along with it)
+#[wasm_bindgen]
+pub fn build_info() -> Result<JsValue, JsValue> {
+    serde_wasm_bindgen::to_value(&entab_base::build_info::build_info())
+        .map_err(|_| JsValue::from_str("Error translating build info"))
+}
+
 #[wasm_bindgen(inline_js = "
   export function make_reader_iter(proto) { proto[Symbol.iterator] = function () { return this; }; }
 ")]
@@ -104,7 +277,12 @@ extern "C" {
 pub fn start() -> Result<(), JsValue> {
     // this is kind of hacky, but we create a simple object and get its prototype so we can add the
     // iterable marker onto it to allow e.g. `for (row of reader) {}`
-    let reader = Reader::new(b"\n".to_vec().into_boxed_slice(), Some("csv".to_string()))?;
+    let reader = Reader::new(
+        b"\n".to_vec().into_boxed_slice(),
+        Some("csv".to_string()),
+        None,
+        None,
+    )?;
     make_reader_iter(&Object::get_prototype_of(&reader.into()));
     Ok(())
 }
@@ -14,8 +14,8 @@ fn create_reader() {
     // doesn't work for obvious reasons, but it'd be nice to test against a Uint8Array
     // let data = Uint8Array::new(&JsValue::from_str(">test\nACGT"));
     let data = b">test\nACGT";
-    let mut reader =
-        Reader::new(data.to_vec().into_boxed_slice(), None).expect("Error creating the reader");
+    let mut reader = Reader::new(data.to_vec().into_boxed_slice(), None, None, None)
+        .expect("Error creating the reader");
     assert_eq!(reader.parser(), "fasta");
     let raw_rec = reader.next().expect("Error reading first record");
     let rec = raw_rec
@@ -31,3 +31,40 @@ fn create_reader() {
     assert_eq!(value.get(&("id".to_string()).into()), "test");
     assert!(value.has(&("sequence".to_string()).into()));
 }
+
+#[wasm_bindgen_test]
+fn error_position() {
+    let data = b"@a\nACGT\n+\n!!!!\nbad";
+    let mut reader = Reader::new(data.to_vec().into_boxed_slice(), None, None, None)
+        .expect("Error creating the reader");
+    assert!(reader.next().is_ok());
+    let err = reader.next().expect_err("the second record is bad");
+    assert_eq!(Reflect::get(&err, &JsValue::from_str("byte")).unwrap(), 15);
+    assert_eq!(Reflect::get(&err, &JsValue::from_str("record")).unwrap(), 1);
+}
+
+#[wasm_bindgen_test]
+fn record_index_and_offset() {
+    let data = b">a\nACGT\n>bb\nCC\nGG\n>c\nT\n";
+    let mut reader = Reader::new(data.to_vec().into_boxed_slice(), None, None, None)
+        .expect("Error creating the reader");
+    // each record's offset is the byte its `>` is at
+    for (index, offset) in [(0, 0), (1, 8), (2, 18)] {
+        let rec = reader.next().expect("Error reading record");
+        let done = Reflect::get(&rec, &JsValue::from_str("done")).unwrap();
+        assert!(done.is_falsy());
+        assert_eq!(
+            Reflect::get(&rec, &JsValue::from_str("index")).unwrap(),
+            index
+        );
+        assert_eq!(
+            Reflect::get(&rec, &JsValue::from_str("offset")).unwrap(),
+            offset
+        );
+    }
+    let rec = reader.next().expect("Error reading the end");
+    assert!(Reflect::get(&rec, &JsValue::from_str("done"))
+        .unwrap()
+        .is_truthy());
+    assert_eq!(Reflect::get(&rec, &JsValue::from_str("index")).unwrap(), 3);
+}
@@ -1,2 +1 @@
-from ._entab import Reader
-
+from ._entab import CancelToken, EntabError, Reader, build_info
@@ -0,0 +1,541 @@
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::os::raw::{c_int, c_void};
+use std::ptr;
+
+use entab_base::columns::{Column as BatchColumn, ColumnValues};
+use entab_base::record::Value;
+use pyo3::exceptions::PyBufferError;
+use pyo3::ffi;
+use pyo3::prelude::*;
+use pyo3::types::PyList;
+
+use crate::py_from_value;
+
+/// Once a string column has this many distinct values, check if it's worth dictionary-encoding
+const MIN_CATEGORIES_CHECKED: usize = 1024;
+
+/// A column of strings (and nulls) with each distinct string stored once and a code for each
+/// row pointing at it, so columns that repeat a few values (e.g. SAM reference names) don't
+/// need a string (or a Python object) for every row
+#[derive(Debug, Default)]
+pub struct StringColumn {
+    codes: Vec<u32>,
+    /// The distinct strings in the order they were first seen (`None` for nulls)
+    categories: Vec<Option<String>>,
+    lookup: HashMap<Option<String>, u32>,
+}
+
+impl StringColumn {
+    /// The code for `value`, adding it to the categories if it's new
+    fn code(&mut self, value: Option<String>) -> u32 {
+        match self.lookup.get(&value) {
+            Some(code) => *code,
+            None => {
+                let code = self.categories.len() as u32;
+                self.categories.push(value.clone());
+                let _ = self.lookup.insert(value, code);
+                code
+            }
+        }
+    }
+
+    fn push(&mut self, value: Option<String>) {
+        let code = self.code(value);
+        self.codes.push(code);
+    }
+
+    /// Add a batch of dictionary-encoded strings, only looking up each distinct string once
+    fn extend(&mut self, codes: Vec<u32>, dictionary: Vec<String>, nulls: &[bool]) {
+        // strings are only added when a row uses them (and not just as a placeholder for nulls)
+        let mut batch_codes: Vec<Option<u32>> = vec![None; dictionary.len()];
+        let mut dictionary: Vec<Option<String>> = dictionary.into_iter().map(Some).collect();
+        self.codes.reserve(codes.len());
+        for (ix, code) in codes.into_iter().enumerate() {
+            let code = if nulls.get(ix).copied().unwrap_or(false) {
+                self.code(None)
+            } else if let Some(code) = batch_codes[code as usize] {
+                code
+            } else {
+                let value = dictionary[code as usize].take();
+                let new_code = self.code(value);
+                batch_codes[code as usize] = Some(new_code);
+                new_code
+            };
+            self.codes.push(code);
+        }
+    }
+
+    /// Are there so many distinct strings that storing them once each isn't saving anything?
+    fn is_mostly_unique(&self) -> bool {
+        self.categories.len() > MIN_CATEGORIES_CHECKED
+            && self.categories.len() * 2 > self.codes.len()
+    }
+
+    /// Are there few enough distinct strings (at most one for every other row) that the column
+    /// is worth keeping dictionary-encoded as a categorical?
+    fn is_categorical(&self) -> bool {
+        let n_levels = self.categories.iter().filter(|c| c.is_some()).count();
+        n_levels > 0 && n_levels * 2 <= self.codes.len()
+    }
+
+    /// The distinct (non-null) strings and a code for each row pointing into them (-1 for nulls)
+    fn into_levels(self) -> (Vec<String>, Vec<i64>) {
+        let mut levels = Vec::with_capacity(self.categories.len());
+        let mut level_codes = Vec::with_capacity(self.categories.len());
+        for category in self.categories {
+            match category {
+                Some(s) => {
+                    level_codes.push(levels.len() as i64);
+                    levels.push(s);
+                }
+                None => level_codes.push(-1),
+            }
+        }
+        let codes = self
+            .codes
+            .into_iter()
+            .map(|code| level_codes[code as usize])
+            .collect();
+        (levels, codes)
+    }
+
+    fn into_values(self) -> Vec<Value<'static>> {
+        let categories: Vec<Value<'static>> = self
+            .categories
+            .into_iter()
+            .map(|c| c.map_or(Value::Null, |s| s.into()))
+            .collect();
+        self.codes
+            .into_iter()
+            .map(|code| categories[code as usize].clone())
+            .collect()
+    }
+}
+
+/// The values from a single column of a file, stored as compactly as their types allow
+#[derive(Debug)]
+pub enum Column {
+    /// Only nulls have been seen so far (this is how many)
+    Empty(usize),
+    Integer(Vec<i64>),
+    /// Also used for integers with missing values (which become NaNs)
+    Float(Vec<f64>),
+    Boolean(Vec<bool>),
+    /// Strings (with or without missing values)
+    Strings(StringColumn),
+    /// Anything else that doesn't fit in a NumPy numeric array (dates, mixed types, etc)
+    Values(Vec<Value<'static>>),
+}
+
+impl Default for Column {
+    fn default() -> Self {
+        Column::Empty(0)
+    }
+}
+
+impl Column {
+    /// Switch to storing `Value`s so anything can be added
+    fn make_values(&mut self) -> &mut Vec<Value<'static>> {
+        let values = match std::mem::take(self) {
+            Column::Empty(n) => vec![Value::Null; n],
+            Column::Integer(v) => v.into_iter().map(Value::Integer).collect(),
+            Column::Float(v) => v.into_iter().map(Value::Float).collect(),
+            Column::Boolean(v) => v.into_iter().map(Value::Boolean).collect(),
+            Column::Strings(v) => v.into_values(),
+            Column::Values(v) => v,
+        };
+        *self = Column::Values(values);
+        match self {
+            Column::Values(v) => v,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Add a value to the end of the column, changing its type if needed
+    pub fn push(&mut self, value: Value<'static>) {
+        match (&mut *self, value) {
+            (Column::Empty(0), Value::Integer(i)) => *self = Column::Integer(vec![i]),
+            (Column::Empty(n), Value::Integer(i)) => {
+                let mut v = vec![f64::NAN; *n];
+                v.push(i as f64);
+                *self = Column::Float(v);
+            }
+            (Column::Empty(n), Value::Float(f)) => {
+                let mut v = vec![f64::NAN; *n];
+                v.push(f);
+                *self = Column::Float(v);
+            }
+            (Column::Empty(0), Value::Boolean(b)) => *self = Column::Boolean(vec![b]),
+            (Column::Empty(n), Value::Null) => *n += 1,
+            (Column::Integer(v), Value::Integer(i)) => v.push(i),
+            (Column::Integer(v), Value::Float(f)) => {
+                let mut floats: Vec<f64> = v.iter().map(|i| *i as f64).collect();
+                floats.push(f);
+                *self = Column::Float(floats);
+            }
+            (Column::Integer(v), Value::Null) => {
+                let mut floats: Vec<f64> = v.iter().map(|i| *i as f64).collect();
+                floats.push(f64::NAN);
+                *self = Column::Float(floats);
+            }
+            (Column::Float(v), Value::Float(f)) => v.push(f),
+            (Column::Float(v), Value::Integer(i)) => v.push(i as f64),
+            (Column::Float(v), Value::Null) => v.push(f64::NAN),
+            (Column::Boolean(v), Value::Boolean(b)) => v.push(b),
+            (Column::Empty(n), Value::String(s)) => {
+                let mut strings = StringColumn::default();
+                for _ in 0..*n {
+                    strings.push(None);
+                }
+                strings.push(Some(s.into_owned()));
+                *self = Column::Strings(strings);
+            }
+            (Column::Strings(v), Value::String(s)) => {
+                v.push(Some(s.into_owned()));
+                if v.is_mostly_unique() {
+                    let _ = self.make_values();
+                }
+            }
+            (Column::Strings(v), Value::Null) => v.push(None),
+            (Column::Values(v), value) => v.push(value),
+            (_, value) => self.make_values().push(value),
+        }
+    }
+
+    /// Add a batch of values from the worker to the end of the column, copying them over all
+    /// at once if they're numbers (or booleans) of the same type and remapping the codes of
+    /// strings (so each distinct string in the batch is only looked up once)
+    pub fn extend(&mut self, batch: BatchColumn) {
+        if matches!(self, Column::Empty(_) | Column::Strings(_))
+            && matches!(batch.values(), ColumnValues::String { .. })
+        {
+            if let Column::Empty(n) = *self {
+                let mut strings = StringColumn::default();
+                for _ in 0..n {
+                    strings.push(None);
+                }
+                *self = Column::Strings(strings);
+            }
+            if let (Column::Strings(v), (ColumnValues::String { codes, dictionary }, nulls)) =
+                (&mut *self, batch.into_parts())
+            {
+                v.extend(codes, dictionary, &nulls);
+                if v.is_mostly_unique() {
+                    let _ = self.make_values();
+                }
+            }
+            return;
+        }
+        // nulls in float batches are already NaNs, but other types need them to be pushed
+        let copyable = match (&*self, batch.values()) {
+            (_, ColumnValues::Float(_)) => matches!(self, Column::Empty(0) | Column::Float(_)),
+            (Column::Empty(0) | Column::Integer(_), ColumnValues::Integer(_))
+            | (Column::Empty(0) | Column::Boolean(_), ColumnValues::Boolean(_)) => {
+                batch.null_mask().is_empty()
+            }
+            _ => false,
+        };
+        if !copyable {
+            for value in batch.into_values() {
+                self.push(value);
+            }
+            return;
+        }
+        match (&mut *self, batch.into_parts().0) {
+            (Column::Integer(v), ColumnValues::Integer(new)) => v.extend(new),
+            (Column::Float(v), ColumnValues::Float(new)) => v.extend(new),
+            (Column::Boolean(v), ColumnValues::Boolean(new)) => v.extend(new),
+            (_, ColumnValues::Integer(new)) => *self = Column::Integer(new),
+            (_, ColumnValues::Float(new)) => *self = Column::Float(new),
+            (_, ColumnValues::Boolean(new)) => *self = Column::Boolean(new),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Turn the column into a NumPy array
+    pub fn into_numpy(self, numpy: &Bound<PyModule>) -> PyResult<PyObject> {
+        let py = numpy.py();
+        let (buffer, dtype) = match self {
+            Column::Empty(n) => (ColumnBuffer::from(vec![f64::NAN; n]), "float64"),
+            Column::Integer(v) => (ColumnBuffer::from(v), "int64"),
+            Column::Float(v) => (ColumnBuffer::from(v), "float64"),
+            Column::Boolean(v) => (ColumnBuffer::from(v), "bool"),
+            Column::Strings(v) => {
+                // create each distinct string once and have NumPy fill the array with references
+                // to them
+                let categories =
+                    PyList::new_bound(py, v.categories.iter().map(|c| c.to_object(py)));
+                let categories = numpy.call_method1("array", (categories, "object"))?;
+                let codes: Vec<i64> = v.codes.into_iter().map(i64::from).collect();
+                let codes = numpy.call_method1(
+                    "frombuffer",
+                    (Py::new(py, ColumnBuffer::from(codes))?, "int64"),
+                )?;
+                return Ok(categories.call_method1("take", (codes,))?.into_py(py));
+            }
+            Column::Values(v) => {
+                let values = v
+                    .into_iter()
+                    .map(|value| py_from_value(value, py))
+                    .collect::<PyResult<Vec<_>>>()?;
+                let list = PyList::new_bound(py, values);
+                return Ok(numpy.call_method1("array", (list, "object"))?.into_py(py));
+            }
+        };
+        Ok(numpy
+            .call_method1("frombuffer", (Py::new(py, buffer)?, dtype))?
+            .into_py(py))
+    }
+}
+
+impl Column {
+    /// Turn the column into something that can go in a pandas DataFrame: strings that repeat
+    /// enough are made into a `Categorical` straight from their codes and every other column is
+    /// a NumPy array (like `into_numpy`).
+    pub fn into_pandas(
+        self,
+        numpy: &Bound<PyModule>,
+        pandas: &Bound<PyModule>,
+    ) -> PyResult<PyObject> {
+        let py = numpy.py();
+        match self {
+            Column::Strings(v) if v.is_categorical() => {
+                let (levels, codes) = v.into_levels();
+                let codes = numpy.call_method1(
+                    "frombuffer",
+                    (Py::new(py, ColumnBuffer::from(codes))?, "int64"),
+                )?;
+                Ok(pandas
+                    .getattr("Categorical")?
+                    .call_method1("from_codes", (codes, levels))?
+                    .into_py(py))
+            }
+            column => column.into_numpy(numpy),
+        }
+    }
+}
+
+/// The typed values backing a `ColumnBuffer`
+#[derive(Debug)]
+enum BufferData {
+    Integer(Vec<i64>),
+    Float(Vec<f64>),
+    Boolean(Vec<bool>),
+}
+
+/// A read-only, one-dimensional array of numbers exposed through Python's buffer protocol so
+/// it can be handed to NumPy (or `memoryview`) without copying
+#[pyclass]
+#[derive(Debug)]
+pub struct ColumnBuffer {
+    data: BufferData,
+    // these have to live as long as the buffer so `shape` and `strides` can point at them
+    n_items: isize,
+    item_size: isize,
+}
+
+impl ColumnBuffer {
+    fn new(data: BufferData) -> Self {
+        let (n_items, item_size) = match &data {
+            BufferData::Integer(v) => (v.len(), std::mem::size_of::<i64>()),
+            BufferData::Float(v) => (v.len(), std::mem::size_of::<f64>()),
+            BufferData::Boolean(v) => (v.len(), std::mem::size_of::<bool>()),
+        };
+        ColumnBuffer {
+            data,
+            n_items: n_items as isize,
+            item_size: item_size as isize,
+        }
+    }
+}
+
+impl From<Vec<i64>> for ColumnBuffer {
+    fn from(v: Vec<i64>) -> Self {
+        ColumnBuffer::new(BufferData::Integer(v))
+    }
+}
+
+impl From<Vec<f64>> for ColumnBuffer {
+    fn from(v: Vec<f64>) -> Self {
+        ColumnBuffer::new(BufferData::Float(v))
+    }
+}
+
+impl From<Vec<bool>> for ColumnBuffer {
+    fn from(v: Vec<bool>) -> Self {
+        ColumnBuffer::new(BufferData::Boolean(v))
+    }
+}
+
+#[pymethods]
+impl ColumnBuffer {
+    fn __len__(&self) -> usize {
+        self.n_items as usize
+    }
+
+    /// # Safety
+    /// `view` must be a valid pointer to a `Py_buffer` (or null).
+    unsafe fn __getbuffer__(
+        slf: Bound<'_, Self>,
+        view: *mut ffi::Py_buffer,
+        flags: c_int,
+    ) -> PyResult<()> {
+        if view.is_null() {
+            return Err(PyBufferError::new_err("View is null"));
+        }
+        if (flags & ffi::PyBUF_WRITABLE) == ffi::PyBUF_WRITABLE {
+            return Err(PyBufferError::new_err("Column buffers are read-only"));
+        }
+        let this = slf.borrow();
+        let (buf, format) = match &this.data {
+            BufferData::Integer(v) => (v.as_ptr() as *mut c_void, "q"),
+            BufferData::Float(v) => (v.as_ptr() as *mut c_void, "d"),
+            BufferData::Boolean(v) => (v.as_ptr() as *mut c_void, "?"),
+        };
+        // the data is owned by `slf` and will live as long as `obj` holds a reference to it
+        (*view).buf = buf;
+        (*view).len = this.n_items * this.item_size;
+        (*view).readonly = 1;
+        (*view).itemsize = this.item_size;
+        (*view).format = if (flags & ffi::PyBUF_FORMAT) == ffi::PyBUF_FORMAT {
+            CString::new(format)?.into_raw()
+        } else {
+            ptr::null_mut()
+        };
+        (*view).ndim = 1;
+        (*view).shape = if (flags & ffi::PyBUF_ND) == ffi::PyBUF_ND {
+            &this.n_items as *const isize as *mut isize
+        } else {
+            ptr::null_mut()
+        };
+        (*view).strides = if (flags & ffi::PyBUF_STRIDES) == ffi::PyBUF_STRIDES {
+            &this.item_size as *const isize as *mut isize
+        } else {
+            ptr::null_mut()
+        };
+        (*view).suboffsets = ptr::null_mut();
+        (*view).internal = ptr::null_mut();
+        drop(this);
+        (*view).obj = slf.into_any().into_ptr();
+        Ok(())
+    }
+
+    /// # Safety
+    /// `view` must be a buffer filled in by `__getbuffer__`.
+    unsafe fn __releasebuffer__(&self, view: *mut ffi::Py_buffer) {
+        if !(*view).format.is_null() {
+            drop(CString::from_raw((*view).format));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_column_types() {
+        let mut column = Column::default();
+        column.push(Value::Null);
+        column.push(1.into());
+        assert!(matches!(&column, Column::Float(v) if v[0].is_nan() && v[1] == 1.));
+
+        let mut column = Column::default();
+        column.push(1.into());
+        column.push(2.into());
+        assert!(matches!(&column, Column::Integer(v) if v == &[1, 2]));
+        column.push(2.5.into());
+        assert!(matches!(&column, Column::Float(v) if v == &[1., 2., 2.5]));
+        column.push("x".into());
+        assert!(matches!(&column, Column::Values(v) if v.len() == 4));
+
+        let mut column = Column::default();
+        column.push(Value::Null);
+        column.push("x".into());
+        column.push("y".into());
+        column.push("x".into());
+        assert!(matches!(&column, Column::Strings(v) if v.codes == [0, 1, 2, 1]));
+        column.push(1.into());
+        assert!(matches!(&column, Column::Values(v) if v[3] == "x".into()));
+
+        // columns of (mostly) unique strings aren't worth dictionary-encoding
+        let mut column = Column::default();
+        for i in 0..2000 {
+            column.push(i.to_string().into());
+        }
+        assert!(matches!(&column, Column::Values(v) if v.len() == 2000));
+
+        let mut column = StringColumn::default();
+        for s in [Some("chr1"), None, Some("chr2"), Some("chr1"), Some("chr1")] {
+            column.push(s.map(String::from));
+        }
+        assert!(column.is_categorical());
+        let (levels, codes) = column.into_levels();
+        assert_eq!(levels, ["chr1", "chr2"]);
+        assert_eq!(codes, [0, -1, 1, 0, 0]);
+        let mut column = StringColumn::default();
+        column.push(Some("a".to_string()));
+        column.push(Some("b".to_string()));
+        column.push(Some("a".to_string()));
+        assert!(!column.is_categorical());
+
+        let mut column = Column::default();
+        column.push(true.into());
+        column.push(Value::Null);
+        assert!(matches!(&column, Column::Values(v) if v == &[true.into(), Value::Null]));
+    }
+
+    #[test]
+    fn test_column_extend() {
+        let mut batch = BatchColumn::default();
+        batch.push(1.into());
+        batch.push(2.into());
+        let mut column = Column::default();
+        column.extend(batch.clone());
+        column.extend(batch);
+        assert!(matches!(&column, Column::Integer(v) if v == &[1, 2, 1, 2]));
+
+        // integers with missing values become floats
+        let mut batch = BatchColumn::default();
+        batch.push(3.into());
+        batch.push(Value::Null);
+        column.extend(batch);
+        assert!(matches!(&column, Column::Float(v) if v.len() == 4 + 2 && v[5].is_nan()));
+
+        let mut batch = BatchColumn::default();
+        batch.push(Value::Null);
+        batch.push("x".into());
+        let mut column = Column::default();
+        column.extend(batch);
+        assert!(matches!(&column, Column::Strings(v) if v.codes == [0, 1]));
+        let mut batch = BatchColumn::default();
+        batch.push("y".into());
+        batch.push("x".into());
+        batch.push(Value::Null);
+        column.extend(batch);
+        assert!(matches!(&column, Column::Strings(v) if v.codes == [0, 1, 2, 1, 0]));
+    }
+
+    #[test]
+    fn test_column_buffer() -> PyResult<()> {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let buffer = Py::new(py, ColumnBuffer::from(vec![1.5, 2.5, -1.]))?;
+            let buffer = pyo3::buffer::PyBuffer::<f64>::get_bound(buffer.bind(py))?;
+            assert_eq!(buffer.to_vec(py)?, vec![1.5, 2.5, -1.]);
+            assert_eq!(buffer.item_count(), 3);
+
+            let buffer = Py::new(py, ColumnBuffer::from(vec![3_i64, 4]))?;
+            let view = py
+                .import_bound("builtins")?
+                .getattr("memoryview")?
+                .call1((buffer,))?;
+            assert_eq!(
+                view.call_method0("tolist")?.extract::<Vec<i64>>()?,
+                vec![3, 4]
+            );
+            Ok(())
+        })
+    }
+}
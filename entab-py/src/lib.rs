@@ -1,30 +1,70 @@
-#![allow(clippy::needless_option_as_deref, clippy::used_underscore_binding)]
+// pyo3's macros trigger `useless_conversion` on methods returning a `PyResult`
+#![allow(
+    clippy::needless_option_as_deref,
+    clippy::used_underscore_binding,
+    clippy::useless_conversion
+)]
+mod columns;
 mod raw_io_wrapper;
+mod worker;
 
 use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::{Cursor, Read};
+use std::time::Duration;
 
+use entab_base::cancel::CancelToken;
 use entab_base::error::EtError;
-use entab_base::readers::{get_reader, RecordReader};
 use entab_base::record::Value;
 use pyo3::prelude::*;
 use pyo3::types::{PyDict, PyList, PyTuple};
 use pyo3::{create_exception, exceptions};
 
+use crate::columns::Column;
 use crate::raw_io_wrapper::RawIoWrapper;
+use crate::worker::{ReaderWorker, SharedWorker};
 
 create_exception!(entab, EntabError, exceptions::PyException);
 
 fn to_py(err: EtError) -> PyErr {
-    // TODO: somehow bind err.byte and err.record in here too?
     let res = EntabError::new_err(err.to_string());
+    // where in the file the error happened (if known) so callers don't have to parse the message
+    Python::with_gil(|py| {
+        let value = res.value_bound(py);
+        let _ = value.setattr("byte", err.byte());
+        let _ = value.setattr("record", err.record());
+    });
     // we could technically just take an `&EtError` here, but the function signature is nicer with
     // a `EtError` so we have to drop it here to make clippy happy
     drop(err);
     res
 }
 
+/// Map a parser param from Python into a `Value`
+fn value_from_py(obj: &Bound<PyAny>) -> PyResult<Value<'static>> {
+    // bools are ints in Python so they have to be checked first
+    if let Ok(b) = obj.downcast::<pyo3::types::PyBool>() {
+        Ok(Value::Boolean(b.is_true()))
+    } else if let Ok(i) = obj.extract::<i64>() {
+        Ok(Value::Integer(i))
+    } else if let Ok(f) = obj.extract::<f64>() {
+        Ok(Value::Float(f))
+    } else if let Ok(s) = obj.extract::<String>() {
+        Ok(Value::String(s.into()))
+    } else if let Ok(l) = obj.downcast::<PyList>() {
+        Ok(Value::List(
+            l.iter()
+                .map(|i| value_from_py(&i))
+                .collect::<PyResult<_>>()?,
+        ))
+    } else {
+        Err(EntabError::new_err(format!(
+            "Can not use {} as a parser param",
+            obj.get_type().name()?
+        )))
+    }
+}
+
 /// Map a Value into a `PyObject`
 fn py_from_value(value: Value, py: Python) -> PyResult<PyObject> {
     Ok(match value {
@@ -49,13 +89,16 @@ fn py_from_value(value: Value, py: Python) -> PyResult<PyObject> {
             }
             list.to_object(py)
         }
-        Value::Record(_) => {
-            return Err(EntabError::new_err("record subelements unimplemented"));
+        Value::Record(r) => {
+            let dict = PyDict::new_bound(py);
+            for (key, item) in r {
+                dict.set_item(key, py_from_value(item, py)?)?;
+            }
+            dict.to_object(py)
         }
     })
 }
 
-// TODO: remove the unsendable; by wrapping reader in an Arc?
 /// A class that parses binary data into an iterator of namedtuples.
 ///
 /// Parameters
@@ -67,15 +110,60 @@ fn py_from_value(value: Value, py: Python) -> PyResult<PyObject> {
 ///   If data is not provided, the filename of the data file to open.
 /// parser: string
 ///   The name of the parser to use to read the file.
+/// params: dict
+///   Extra options for the parser (e.g. `{"aggregate": "max", "bin": "6s"}` to
+///   downsample trace data into the largest intensity every 6 seconds).
+/// delimiter: string
+///   For the `csv` and `tsv` parsers, the character separating fields
+///   (otherwise it's determined from the data).
+/// quote_char: string
+///   For the `csv` and `tsv` parsers, the character used to quote fields.
+/// comment_char: string
+///   For the `csv` and `tsv` parsers, lines starting with this character
+///   are skipped.
+/// prefetch: int
+///   How many batches of records to parse ahead of time on a background
+///   thread. If 0 (the default), records are only parsed as they're needed.
+///   Either way, the GIL is released while parsing.
+/// rename_policy: string
+///   How headers are turned into the field names of the namedtuples:
+///   "underscore" (the default) replaces spaces and dashes with underscores,
+///   "identifier" replaces every character that can't be in a Python name
+///   with an underscore, and "positional" keeps the names that are valid as
+///   they are. With "identifier" and "positional", names that still aren't
+///   valid (e.g. duplicates or keywords) become positional names like `_3`.
+/// timeout: float
+///   How many seconds parsing can take before an EntabError is raised
+///   (counting from when the Reader is created).
+/// cancel_token: CancelToken
+///   A token that can be cancelled (e.g. from another thread) to stop parsing
+///   with an EntabError.
 ///
 /// Attributes
 /// ----------
 /// headers: list
-///   The keys of each namedtuple returned.
+///   The headers as they are in the file (these are also the keys of
+///   `column_metadata` and `to_numpy`).
+/// raw_headers: list
+///   The same as `headers` (to match the other bindings).
+/// fields: list
+///   The keys of each namedtuple returned (the headers renamed as set by
+///   `rename_policy`).
 /// metadata: dict
 ///   Appropriate metadata from the data.
+/// column_metadata: dict
+///   Metadata about individual columns (e.g. detector voltages in FCS
+///   files), keyed by header.
 /// parser: string
 ///   The parser used to read the data.
+/// capabilities: list
+///   What the parser can do beyond reading records in order, out of
+///   "seekable", "sized", "has_metadata", "multi_stream" and
+///   "typed_schema".
+///
+/// Missing values are returned as `None` while NaNs and infinities (which
+/// some instruments write out for glitches) are returned as `float('nan')`
+/// and `float('inf')`.
 ///
 /// Examples
 /// --------
@@ -83,26 +171,71 @@ fn py_from_value(value: Value, py: Python) -> PyResult<PyObject> {
 /// > for record in reader:
 /// >     print(record.id)
 ///
-#[pyclass(unsendable)]
+/// Pressing Ctrl-C while waiting on a record raises KeyboardInterrupt and stops
+/// parsing.
+///
+#[pyclass]
 pub struct Reader {
     #[pyo3(get)]
     parser: String,
     record_class: Py<PyAny>,
-    reader: Box<dyn RecordReader>,
+    /// The field names of `record_class`
+    fields: Vec<String>,
+    headers: Vec<String>,
+    column_metadata: BTreeMap<String, BTreeMap<String, Value<'static>>>,
+    #[pyo3(get)]
+    capabilities: Vec<&'static str>,
+    worker: SharedWorker,
 }
 
 #[pymethods]
 impl Reader {
     #[new]
-    #[pyo3(signature = (data = None, filename = None, parser = None))]
+    #[pyo3(signature = (
+        data = None,
+        filename = None,
+        parser = None,
+        params = None,
+        prefetch = 0,
+        delimiter = None,
+        quote_char = None,
+        comment_char = None,
+        rename_policy = "underscore",
+        timeout = None,
+        cancel_token = None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
     fn new(
         data: Option<&Bound<PyAny>>,
         filename: Option<&str>,
         parser: Option<&str>,
+        params: Option<&Bound<PyDict>>,
+        prefetch: usize,
+        delimiter: Option<&str>,
+        quote_char: Option<&str>,
+        comment_char: Option<&str>,
+        rename_policy: &str,
+        timeout: Option<f64>,
+        cancel_token: Option<PyRef<PyCancelToken>>,
         py: Python,
     ) -> PyResult<Self> {
-        let mut params = BTreeMap::new();
-        let stream: Box<dyn Read> = match (data, filename) {
+        let mut parser_params = BTreeMap::new();
+        if let Some(params) = params {
+            for (key, value) in params {
+                parser_params.insert(key.extract::<String>()?, value_from_py(&value)?);
+            }
+        }
+        for (key, value) in [
+            ("delimiter", delimiter),
+            ("quote_char", quote_char),
+            ("comment_char", comment_char),
+        ] {
+            if let Some(v) = value {
+                parser_params.insert(key.to_string(), Value::String(v.to_string().into()));
+            }
+        }
+        let mut params = parser_params;
+        let stream: Box<dyn Read + Send> = match (data, filename) {
             (Some(d), None) => {
                 if let Ok(bytes) = d.extract::<Vec<u8>>() {
                     Box::new(Cursor::new(bytes))
@@ -117,7 +250,7 @@ impl Reader {
                 }
             }
             (None, Some(f)) => {
-                params.insert("filename".to_string(), Value::String(f.into()));
+                params.insert("filename".to_string(), Value::String(f.to_string().into()));
                 Box::new(File::open(f)?)
             }
             _ => {
@@ -126,52 +259,170 @@ impl Reader {
                 ))
             }
         };
-        let (reader, parser_used) = get_reader(stream, parser, Some(params)).map_err(to_py)?;
+        let mut token = cancel_token.map_or_else(CancelToken::new, |t| t.token.clone());
+        if let Some(seconds) = timeout {
+            let timeout = Duration::try_from_secs_f64(seconds)
+                .map_err(|_| EntabError::new_err("timeout must be a non-negative number"))?;
+            token = token.with_timeout(timeout);
+        }
+        let (worker, info) = ReaderWorker::spawn(
+            py,
+            stream,
+            parser.map(String::from),
+            params,
+            prefetch,
+            token,
+        )
+        .map_err(to_py)?;
 
-        let headers: Vec<String> = reader
-            .headers()
-            .iter()
-            .map(|h| h.replace(" ", "_").replace("-", "_"))
-            .collect();
+        let (record_headers, rename): (Vec<String>, bool) = match rename_policy {
+            "underscore" => (
+                info.headers
+                    .iter()
+                    .map(|h| h.replace(" ", "_").replace("-", "_"))
+                    .collect(),
+                false,
+            ),
+            "identifier" => (
+                info.headers
+                    .iter()
+                    .map(|h| {
+                        h.chars()
+                            .map(|c| if c.is_alphanumeric() { c } else { '_' })
+                            .collect()
+                    })
+                    .collect(),
+                true,
+            ),
+            "positional" => (info.headers.clone(), true),
+            x => {
+                return Err(EntabError::new_err(format!(
+                    "rename_policy must be underscore, identifier or positional, not {}",
+                    x
+                )))
+            }
+        };
         let collections = PyModule::import_bound(py, "collections")?;
+        let kwargs = PyDict::new_bound(py);
+        kwargs.set_item("rename", rename)?;
         let record_class = collections
             .getattr("namedtuple")?
-            .call1(("Record", headers))?
-            .into();
+            .call(("Record", record_headers), Some(&kwargs))?;
+        let fields = record_class.getattr("_fields")?.extract()?;
 
         Ok(Reader {
-            parser: parser_used.to_string(),
-            record_class,
-            reader,
+            parser: info.parser,
+            record_class: record_class.into(),
+            fields,
+            headers: info.headers,
+            column_metadata: info.column_metadata,
+            capabilities: info.capabilities,
+            worker: SharedWorker::new(worker),
         })
     }
 
     #[getter]
     pub fn get_headers(&self) -> PyResult<Vec<String>> {
-        Ok(self.reader.headers())
+        Ok(self.headers.clone())
+    }
+
+    #[getter]
+    pub fn get_raw_headers(&self) -> PyResult<Vec<String>> {
+        Ok(self.headers.clone())
+    }
+
+    #[getter]
+    pub fn get_fields(&self) -> PyResult<Vec<String>> {
+        Ok(self.fields.clone())
     }
 
     #[getter]
     pub fn get_metadata(&self, py: Python) -> PyResult<PyObject> {
         let dict = PyDict::new_bound(py);
-        for (key, value) in self.reader.metadata() {
+        for (key, value) in self.worker.metadata(py) {
             dict.set_item(key, py_from_value(value, py)?)?;
         }
         Ok(dict.into())
     }
 
+    #[getter]
+    pub fn get_column_metadata(&self, py: Python) -> PyResult<PyObject> {
+        let dict = PyDict::new_bound(py);
+        for (header, metadata) in &self.column_metadata {
+            let column = PyDict::new_bound(py);
+            for (key, value) in metadata.clone() {
+                column.set_item(key, py_from_value(value, py)?)?;
+            }
+            dict.set_item(header, column)?;
+        }
+        Ok(dict.into())
+    }
+
     #[getter]
     pub fn get_parser(&self) -> PyResult<String> {
         Ok(self.parser.clone())
     }
 
-    fn __iter__(slf: PyRefMut<Self>, py: Python) -> PyResult<PyObject> {
+    /// The lower and upper (or None if unknown) bounds on the number of records left to read.
+    #[getter]
+    pub fn get_size_hint(&self, py: Python) -> PyResult<(usize, Option<usize>)> {
+        Ok(self.worker.size_hint(py))
+    }
+
+    fn __length_hint__(&self, py: Python) -> PyResult<usize> {
+        let (lower, upper) = self.worker.size_hint(py);
+        Ok(upper.unwrap_or(lower))
+    }
+
+    /// Read all of the remaining records into a dict of NumPy arrays (one per column).
+    ///
+    /// Numeric columns are built without creating any intermediate Python objects; integer
+    /// columns with missing values become floats (with NaNs) and columns that aren't numeric
+    /// are returned as object arrays. Numeric arrays are read-only. Strings that repeat (e.g.
+    /// reference names) are only created once and shared between every row they're in.
+    pub fn to_numpy(&self, py: Python) -> PyResult<PyObject> {
+        let numpy = PyModule::import_bound(py, "numpy")?;
+        let mut columns: Vec<Column> = self.headers.iter().map(|_| Column::default()).collect();
+        while let Some(batch) = self.worker.next_columns(py)? {
+            for (column, values) in columns.iter_mut().zip(batch) {
+                column.extend(values);
+            }
+        }
+        let dict = PyDict::new_bound(py);
+        for (header, column) in self.headers.iter().zip(columns) {
+            dict.set_item(header, column.into_numpy(&numpy)?)?;
+        }
+        Ok(dict.into())
+    }
+
+    /// Read all of the remaining records into a pandas DataFrame.
+    ///
+    /// Columns are built like in `to_numpy`, except that string columns with few distinct values
+    /// (at most one for every other row, e.g. reference or signal names) become categoricals
+    /// directly from their dictionary encoding instead of object arrays.
+    pub fn to_pandas(&self, py: Python) -> PyResult<PyObject> {
+        let numpy = PyModule::import_bound(py, "numpy")?;
+        let pandas = PyModule::import_bound(py, "pandas")?;
+        let mut columns: Vec<Column> = self.headers.iter().map(|_| Column::default()).collect();
+        while let Some(batch) = self.worker.next_columns(py)? {
+            for (column, values) in columns.iter_mut().zip(batch) {
+                column.extend(values);
+            }
+        }
+        let dict = PyDict::new_bound(py);
+        for (header, column) in self.headers.iter().zip(columns) {
+            dict.set_item(header, column.into_pandas(&numpy, &pandas)?)?;
+        }
+        Ok(pandas.getattr("DataFrame")?.call1((dict,))?.into_py(py))
+    }
+
+    fn __iter__(slf: PyRef<Self>, py: Python) -> PyResult<PyObject> {
         let val: PyObject = slf.into_py(py);
         Ok(val.clone_ref(py))
     }
 
-    fn __next__(mut slf: PyRefMut<Self>, py: Python) -> PyResult<Option<Py<PyAny>>> {
-        let rec = if let Some(val) = slf.reader.next_record().map_err(to_py)? {
+    fn __next__(slf: PyRef<Self>, py: Python) -> PyResult<Option<Py<PyAny>>> {
+        let rec = if let Some(val) = slf.worker.next_record(py)? {
             let mut data = Vec::with_capacity(val.len());
             for field in val {
                 data.push(py_from_value(field, py)?);
@@ -189,11 +440,64 @@ impl Reader {
     }
 }
 
+/// A token for stopping one or more Readers, e.g. from another thread or a
+/// GUI's cancel button.
+///
+/// Examples
+/// --------
+/// > token = CancelToken()
+/// > reader = Reader(filename='big.fcs', cancel_token=token)
+/// > # elsewhere
+/// > token.cancel()
+///
+#[pyclass(name = "CancelToken")]
+#[derive(Default)]
+pub struct PyCancelToken {
+    token: CancelToken,
+}
+
+#[pymethods]
+impl PyCancelToken {
+    #[new]
+    fn new() -> Self {
+        PyCancelToken::default()
+    }
+
+    /// Stop any Readers using this token (they raise an EntabError on their
+    /// next record).
+    fn cancel(&self) {
+        self.token.cancel();
+    }
+
+    #[getter]
+    fn get_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("<CancelToken cancelled={}>", self.token.is_cancelled())
+    }
+}
+
+/// The version of entab, its enabled features, and the version of each parser (so the parser
+/// that produced a table can be recorded along with it).
+#[pyfunction]
+fn build_info(py: Python) -> PyResult<PyObject> {
+    py_from_value(entab_base::build_info::build_info().to_value(), py)
+}
+
 /// entab provides interconversion from streaming record formats.
 #[pymodule]
-#[pyo3(name="_entab")]
+#[pyo3(name = "_entab")]
 fn entab(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<Reader>()?;
+    m.add_class::<PyCancelToken>()?;
+    let error = m.py().get_type_bound::<EntabError>();
+    // errors that aren't from parsing a file don't have a position in it
+    error.setattr("byte", m.py().None())?;
+    error.setattr("record", m.py().None())?;
+    m.add("EntabError", error)?;
+    m.add_function(wrap_pyfunction!(build_info, m)?)?;
     Ok(())
 }
 
@@ -208,11 +512,38 @@ mod tests {
         pyo3::prepare_freethreaded_python();
         Python::with_gil(|py| {
             // a filename or data has to be passed in
-            assert!(Reader::new(None, None, None, py).is_err());
+            assert!(Reader::new(
+                None,
+                None,
+                None,
+                None,
+                0,
+                None,
+                None,
+                None,
+                "underscore",
+                None,
+                None,
+                py
+            )
+            .is_err());
 
             // if data's passed in, it works
             let test_data = b">test\nACGT".to_object(py);
-            let reader = Reader::new(Some(test_data.bind(py)), None, None, py)?;
+            let reader = Reader::new(
+                Some(test_data.bind(py)),
+                None,
+                None,
+                None,
+                0,
+                None,
+                None,
+                None,
+                "underscore",
+                None,
+                None,
+                py,
+            )?;
             assert_eq!(&reader.parser, "fasta");
 
             // metadata are available
@@ -227,6 +558,12 @@ mod tests {
         })
     }
 
+    #[test]
+    fn test_reader_is_send() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Reader>();
+    }
+
     #[test]
     fn test_reader_in_python() -> PyResult<()> {
         pyo3::prepare_freethreaded_python();
@@ -239,8 +576,141 @@ mod tests {
                 r#"
 reader = entab.Reader(data=">test\nACGT")
 assert reader.metadata == {}
+assert reader.capabilities == ["typed_schema"]
+assert reader.column_metadata == {}
+
+reader = entab.Reader(filename="../entab/tests/data/HTS_BD_LSR_II_Mixed_Specimen_001_D6_D06.fcs")
+assert reader.column_metadata["FSC-A"]["voltage"] == 611.0
+assert reader.headers[:2] == ["FSC-A", "FSC-H"]
+assert reader.raw_headers[:2] == ["FSC-A", "FSC-H"]
+assert reader.fields[:2] == ["FSC_A", "FSC_H"]
+assert reader.fields[9] == "PE_TxRed_YG_A"
 for record in reader:
     pass
+
+reader = entab.Reader(data="a.b\tc\td\n1\t2\t3\n", parser="tsv", rename_policy="identifier")
+assert reader.fields == ["a_b", "c", "d"]
+reader = entab.Reader(data="a.b\tc\td\n1\t2\t3\n", parser="tsv", rename_policy="positional")
+assert reader.fields == ["_0", "c", "d"]
+assert reader.headers == ["a.b", "c", "d"]
+assert next(reader)._0 == 1
+
+# empty data is just a file without any records
+reader = entab.Reader(data="", parser="fastq")
+assert reader.headers == ["id", "sequence", "quality"]
+assert list(reader) == []
+reader = entab.Reader(data=b"")
+assert reader.headers == []
+assert list(reader) == []
+
+reader = entab.Reader(
+    filename="../entab/tests/data/HTS_BD_LSR_II_Mixed_Specimen_001_D6_D06.fcs",
+    params={"first_event": 100, "sample_n": 10},
+)
+assert reader.size_hint == (10, 10)
+assert "sized" in reader.capabilities and "has_metadata" in reader.capabilities
+assert len(list(reader)) == 10
+assert reader.size_hint == (0, 0)
+
+info = entab.build_info()
+assert info["parsers"]["fasta"] >= 1 and "version" in info
+
+reader = entab.Reader(data=">a\nACGT\n>b\nTT", prefetch=2)
+assert [r.id for r in reader] == ["a", "b"]
+
+reader = entab.Reader(
+    data="time\tintensity\n0.1\t2\n0.5\t4\n1.5\t1\n",
+    parser="tsv",
+    params={"aggregate": "mean", "bin": 1},
+)
+assert [tuple(r) for r in reader] == [(0.0, 3.0), (1.0, 1.0)]
+
+reader = entab.Reader(
+    data="% comment\na;b\n'x;y';1\n",
+    parser="csv",
+    delimiter=";",
+    quote_char="'",
+    comment_char="%",
+)
+assert [tuple(r) for r in reader] == [("x;y", 1)]
+
+import math
+reader = entab.Reader(data="a\tb\n1\tNaN\n2\t-inf\n", parser="tsv")
+values = [r.b for r in reader]
+assert math.isnan(values[0]) and values[1] == float("-inf")
+
+try:
+    import numpy
+except ImportError:
+    numpy = None
+if numpy is not None:
+    arrays = entab.Reader(data="a\tb\tc\n1\t2.5\tx\n3\t\ty\n", parser="tsv").to_numpy()
+    assert arrays["a"].dtype == numpy.int64 and arrays["a"].tolist() == [1, 3]
+    assert arrays["b"].dtype == numpy.float64 and numpy.isnan(arrays["b"][1])
+    assert arrays["c"].tolist() == ["x", "y"]
+    # repeated strings are the same object
+    arrays = entab.Reader(data="a\nx\ny\nx\n", parser="tsv").to_numpy()
+    assert arrays["a"][0] is arrays["a"][2]
+
+try:
+    import pandas
+except ImportError:
+    pandas = None
+if pandas is not None:
+    frame = entab.Reader(data="a\tb\nx\t1\ny\t2\nx\t3\nx\t4\n", parser="tsv").to_pandas()
+    assert list(frame.columns) == ["a", "b"]
+    assert frame["a"].dtype == "category" and list(frame["a"].cat.categories) == ["x", "y"]
+    assert frame["a"].tolist() == ["x", "y", "x", "x"] and frame["b"].dtype == "int64"
+    frame = entab.Reader(data=">a\nACGT\n>b\nTT", parser="fasta").to_pandas()
+    assert frame["id"].dtype == object
+
+data = ">a\nACGT\n" * 2000
+token = entab.CancelToken()
+reader = entab.Reader(data=data, cancel_token=token)
+next(reader)
+token.cancel()
+assert token.cancelled
+try:
+    list(reader)
+    raise AssertionError("a cancelled reader should raise")
+except entab.EntabError as e:
+    assert "cancelled" in str(e)
+try:
+    list(entab.Reader(data=data, timeout=0))
+    raise AssertionError("a reader past its timeout should raise")
+except entab.EntabError as e:
+    assert "timeout" in str(e)
+
+# a reader can be shared by a pool of threads (which each wait without holding the GIL)
+from concurrent.futures import ThreadPoolExecutor
+reader = entab.Reader(data=">a\nACGT\n" * 5000)
+def count(reader):
+    return sum(1 for _ in reader)
+with ThreadPoolExecutor(4) as pool:
+    assert sum(pool.map(count, [reader] * 4)) == 5000
+
+import io
+reader = entab.Reader(data=io.BytesIO(b">a\nACGT\n>b\nTT"))
+assert [r.sequence for r in reader] == ["ACGT", "TT"]
+
+try:
+    list(entab.Reader(data="@a\nACGT\n+\n!!!!\nbad"))
+    raise AssertionError("bad FASTQ should raise")
+except AssertionError:
+    raise
+except Exception:
+    pass
+
+try:
+    list(entab.Reader(data="@a\nACGT\n+\n!!!!\n@b\nAC\n+\n!!\nbad", parser="fastq"))
+    raise AssertionError("bad FASTQ should raise")
+except entab.EntabError as e:
+    assert (e.byte, e.record) == (26, 2)
+try:
+    entab.Reader(data=">a\nACGT", parser="fastq", params={"not_a_param": 1})
+    raise AssertionError("unknown params should raise")
+except entab.EntabError as e:
+    assert e.byte is None and e.record is None
             "#,
                 None,
                 Some(&locals),
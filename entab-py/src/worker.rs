@@ -0,0 +1,307 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::io::Read;
+use std::sync::mpsc::{channel, sync_channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use entab_base::cancel::CancelToken;
+use entab_base::columns::{into_records, push_record, Column};
+use entab_base::error::EtError;
+use entab_base::readers::get_reader_cancellable;
+use entab_base::record::Value;
+use pyo3::prelude::*;
+
+use crate::to_py;
+
+/// How many records are parsed at once on the worker thread
+const BATCH_SIZE: usize = 1024;
+/// How often to check for Ctrl-C (and other signals) while waiting on the worker thread
+const SIGNAL_INTERVAL: Duration = Duration::from_millis(100);
+
+type Metadata = BTreeMap<String, Value<'static>>;
+
+/// What the worker thread knows about the file once it's opened it
+pub struct ReaderInfo {
+    pub parser: String,
+    pub headers: Vec<String>,
+    pub metadata: Metadata,
+    pub column_metadata: BTreeMap<String, Metadata>,
+    pub size_hint: (usize, Option<usize>),
+    pub capabilities: Vec<&'static str>,
+}
+
+/// A chunk of records (as columns) and the metadata as of the last of them from the worker
+/// thread
+struct Batch {
+    columns: Vec<Column>,
+    metadata: Metadata,
+    size_hint: (usize, Option<usize>),
+    done: bool,
+}
+
+fn owned_metadata(metadata: BTreeMap<String, Value<'_>>) -> Metadata {
+    metadata
+        .into_iter()
+        .map(|(k, v)| (k, v.into_owned()))
+        .collect()
+}
+
+/// Parses records on a separate thread so they can be read without holding the GIL.
+///
+/// If `prefetch` is 0, a batch is only parsed when it's requested; otherwise up to `prefetch`
+/// batches are parsed ahead of time in the background.
+pub struct ReaderWorker {
+    /// Used to ask for the next batch (only when not prefetching)
+    requests: Option<Sender<()>>,
+    /// Has a batch been asked for that hasn't been received yet?
+    requested: bool,
+    /// `None` once all the batches have been received
+    batches: Option<Receiver<Result<Batch, EtError>>>,
+    pending: VecDeque<Vec<Value<'static>>>,
+    metadata: Metadata,
+    /// The number of records the worker has left to read (as of the last batch)
+    worker_size_hint: (usize, Option<usize>),
+    /// Stops the worker thread if parsing is interrupted
+    token: CancelToken,
+}
+
+impl ReaderWorker {
+    pub fn spawn(
+        py: Python,
+        stream: Box<dyn Read + Send>,
+        parser: Option<String>,
+        params: Metadata,
+        prefetch: usize,
+        token: CancelToken,
+    ) -> Result<(Self, ReaderInfo), EtError> {
+        let (info_tx, info_rx) = sync_channel(1);
+        let (batch_tx, batch_rx) = sync_channel(prefetch);
+        let (request_tx, request_rx) = channel::<()>();
+        let lazy = prefetch == 0;
+        let worker_token = token.clone();
+
+        let _ = thread::spawn(move || {
+            let (mut reader, parser_used) =
+                match get_reader_cancellable(stream, parser.as_deref(), Some(params), worker_token)
+                {
+                    Ok(r) => r,
+                    Err(e) => {
+                        let _ = info_tx.send(Err(e));
+                        return;
+                    }
+                };
+            let info = ReaderInfo {
+                parser: parser_used.to_string(),
+                headers: reader.headers(),
+                metadata: owned_metadata(reader.metadata()),
+                column_metadata: reader
+                    .column_metadata()
+                    .into_iter()
+                    .map(|(k, v)| (k, owned_metadata(v)))
+                    .collect(),
+                size_hint: reader.size_hint(),
+                capabilities: reader.capabilities().names(),
+            };
+            if info_tx.send(Ok(info)).is_err() {
+                return;
+            }
+            loop {
+                if lazy && request_rx.recv().is_err() {
+                    return;
+                }
+                let mut columns = Vec::new();
+                let (done, error) = match reader.next_columns(&mut columns, BATCH_SIZE) {
+                    Ok(n_read) => (n_read < BATCH_SIZE, None),
+                    Err(e) => (false, Some(e)),
+                };
+                let batch = Batch {
+                    columns,
+                    metadata: owned_metadata(reader.metadata()),
+                    size_hint: reader.size_hint(),
+                    done,
+                };
+                // if the `Reader` has been dropped, there's nothing left to do
+                if batch_tx.send(Ok(batch)).is_err() {
+                    return;
+                }
+                if let Some(e) = error {
+                    let _ = batch_tx.send(Err(e));
+                    return;
+                }
+                if done {
+                    return;
+                }
+            }
+        });
+
+        // the worker may need the GIL to read from a Python file object so let it go while
+        // we wait for the file to be opened
+        let info = py
+            .allow_threads(move || info_rx.recv())
+            .map_err(|_| EtError::from("Parsing thread stopped unexpectedly"))??;
+        let worker = ReaderWorker {
+            requests: if lazy { Some(request_tx) } else { None },
+            requested: false,
+            batches: Some(batch_rx),
+            pending: VecDeque::new(),
+            metadata: info.metadata.clone(),
+            worker_size_hint: info.size_hint,
+            token,
+        };
+        Ok((worker, info))
+    }
+
+    /// The bounds on the number of records left to return (like `Iterator::size_hint`)
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.worker_size_hint;
+        let n_pending = self.pending.len();
+        (lower + n_pending, upper.map(|u| u + n_pending))
+    }
+
+    /// Return the next batch from the worker if it sends one within `timeout`
+    fn poll_batch(&mut self, timeout: Duration) -> Result<Poll<Vec<Column>>, EtError> {
+        let batches = match &self.batches {
+            Some(b) => b,
+            None => return Ok(Poll::Ready(None)),
+        };
+        if let (Some(requests), false) = (&self.requests, self.requested) {
+            // if this fails, the worker's already stopped and `recv` will tell us why
+            let _ = requests.send(());
+            self.requested = true;
+        }
+        match batches.recv_timeout(timeout) {
+            Err(RecvTimeoutError::Timeout) => Ok(Poll::Pending),
+            Ok(Ok(Batch {
+                columns,
+                metadata,
+                size_hint,
+                done,
+            })) => {
+                self.requested = false;
+                self.metadata = metadata;
+                self.worker_size_hint = size_hint;
+                if done {
+                    self.batches = None;
+                }
+                Ok(Poll::Ready(Some(columns)))
+            }
+            // errors are sent after the batch of records before them
+            Ok(Err(e)) => {
+                self.batches = None;
+                Err(e)
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                self.batches = None;
+                Err("Parsing thread stopped unexpectedly".into())
+            }
+        }
+    }
+
+    /// Return the next record if there's one ready or if the worker sends a batch within
+    /// `timeout`.
+    fn poll_record(&mut self, timeout: Duration) -> Result<Poll<Vec<Value<'static>>>, EtError> {
+        loop {
+            if let Some(record) = self.pending.pop_front() {
+                return Ok(Poll::Ready(Some(record)));
+            }
+            match self.poll_batch(timeout)? {
+                Poll::Ready(Some(columns)) => self.pending.extend(into_records(columns)),
+                Poll::Ready(None) => return Ok(Poll::Ready(None)),
+                Poll::Pending => return Ok(Poll::Pending),
+            }
+        }
+    }
+
+    /// Return the next batch of records as columns: first any records left over from reading
+    /// them one at a time and then each batch as it comes from the worker.
+    fn poll_columns(&mut self, timeout: Duration) -> Result<Poll<Vec<Column>>, EtError> {
+        if self.pending.is_empty() {
+            return self.poll_batch(timeout);
+        }
+        let mut columns = Vec::new();
+        for record in self.pending.drain(..) {
+            push_record(&mut columns, record);
+        }
+        Ok(Poll::Ready(Some(columns)))
+    }
+}
+
+/// Whether anything was ready when the worker was polled
+enum Poll<T> {
+    /// The next record or batch (or `None` if they've all been read)
+    Ready(Option<T>),
+    /// The worker is still parsing the next batch
+    Pending,
+}
+
+/// A `ReaderWorker` that can be shared between Python threads.
+///
+/// The worker is only ever locked without the GIL (and never held while waiting for it) so a
+/// thread waiting on the next batch doesn't block other Python threads and two threads reading
+/// from the same `Reader` (e.g. in a thread pool) take turns instead of raising a borrow error.
+pub struct SharedWorker(Mutex<ReaderWorker>);
+
+impl SharedWorker {
+    pub fn new(worker: ReaderWorker) -> Self {
+        SharedWorker(Mutex::new(worker))
+    }
+
+    /// Run `f` on the worker after letting go of the GIL to lock it
+    fn with_worker<T, F>(&self, py: Python, f: F) -> T
+    where
+        T: Send,
+        F: FnOnce(&mut ReaderWorker) -> T + Send,
+    {
+        py.allow_threads(|| {
+            // a panic while parsing is already raised in the thread that hit it
+            let mut worker = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+            f(&mut worker)
+        })
+    }
+
+    /// The metadata as of the last batch of records that was received
+    pub fn metadata(&self, py: Python) -> Metadata {
+        self.with_worker(py, |worker| worker.metadata.clone())
+    }
+
+    /// The bounds on the number of records left to return (like `Iterator::size_hint`)
+    pub fn size_hint(&self, py: Python) -> (usize, Option<usize>) {
+        self.with_worker(py, |worker| worker.size_hint())
+    }
+
+    /// Wait for `poll` to return something from the worker thread (without the GIL).
+    ///
+    /// While waiting, signals are checked every so often so Ctrl-C raises a `KeyboardInterrupt`
+    /// (and stops the worker) instead of hanging until the batch is parsed.
+    fn wait<T: Send>(
+        &self,
+        py: Python,
+        poll: fn(&mut ReaderWorker, Duration) -> Result<Poll<T>, EtError>,
+    ) -> PyResult<Option<T>> {
+        loop {
+            match self.with_worker(py, |worker| poll(worker, SIGNAL_INTERVAL)) {
+                Ok(Poll::Ready(next)) => return Ok(next),
+                Ok(Poll::Pending) => {
+                    if let Err(e) = py.check_signals() {
+                        // the worker stops at its next check (or when it can't send its batch
+                        // because `batches` has been dropped)
+                        self.with_worker(py, |worker| worker.token.cancel());
+                        return Err(e);
+                    }
+                }
+                Err(e) => return Err(to_py(e)),
+            }
+        }
+    }
+
+    /// Return the next record, waiting on the worker thread if needed
+    pub fn next_record(&self, py: Python) -> PyResult<Option<Vec<Value<'static>>>> {
+        self.wait(py, ReaderWorker::poll_record)
+    }
+
+    /// Return the next batch of records as columns, waiting on the worker thread if needed
+    pub fn next_columns(&self, py: Python) -> PyResult<Option<Vec<Column>>> {
+        self.wait(py, ReaderWorker::poll_columns)
+    }
+}
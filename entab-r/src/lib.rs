@@ -1,16 +1,36 @@
-use std::collections::BTreeMap;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
 
+use entab_base::columns::{Column, ColumnValues};
 use entab_base::error::EtError;
 use entab_base::readers::{get_reader, RecordReader};
 use entab_base::record::Value;
 use extendr_api::prelude::*;
 
+/// How many records `as_data_frame` reads at once
+const BATCH_SIZE: usize = 1024;
+
+thread_local! {
+    /// The byte and record the last error from `to_r` happened at, for `last_error_position`
+    static LAST_ERROR: Cell<(Option<u64>, Option<u64>)> = const { Cell::new((None, None)) };
+}
+
 #[allow(clippy::needless_pass_by_value)]
 fn to_r(err: EtError) -> Error {
+    LAST_ERROR.with(|last| last.set((err.byte(), err.record())));
     err.to_string().into()
 }
 
+/// Where the last error happened in the file (with `NULL`s if it didn't happen while parsing);
+/// R errors can only be messages so `entab_call` uses this to build the condition it signals.
+#[extendr]
+fn last_error_position() -> Robj {
+    let (byte, record) = LAST_ERROR.with(|last| last.replace((None, None)));
+    let position = |pos: Option<u64>| pos.map_or_else(|| ().into(), |p| (p as f64).into());
+    list!(byte = position(byte), record = position(record)).into()
+}
+
 fn value_to_robj(value: Value) -> Robj {
     match value {
         Value::Null => ().into(),
@@ -47,7 +67,13 @@ struct Reader {
 #[extendr]
 impl Reader {
     #[allow(clippy::new_ret_no_self)]
-    fn new(filename: &str, parser: &str) -> Result<Robj> {
+    fn new(
+        filename: &str,
+        parser: &str,
+        delimiter: &str,
+        quote_char: &str,
+        comment_char: &str,
+    ) -> Result<Robj> {
         let file = File::open(filename).map_err(|e| Error::from(e.to_string()))?;
         let parser = if parser.is_empty() {
             None
@@ -56,6 +82,15 @@ impl Reader {
         };
         let mut params = BTreeMap::new();
         params.insert("filename".to_string(), Value::String(filename.into()));
+        for (key, value) in [
+            ("delimiter", delimiter),
+            ("quote_char", quote_char),
+            ("comment_char", comment_char),
+        ] {
+            if !value.is_empty() {
+                params.insert(key.to_string(), Value::String(value.into()));
+            }
+        }
         let (reader, parser_used) = get_reader(file, parser, Some(params)).map_err(to_r)?;
         let header_names = reader.headers();
         Ok(Reader {
@@ -74,6 +109,12 @@ impl Reader {
         self.reader.headers()
     }
 
+    /// The headers as they are in the file (`as.data.frame` keeps these as the column names, so
+    /// use this to get them back after e.g. `make.names`)
+    fn raw_headers(&self) -> Vec<String> {
+        self.header_names.clone()
+    }
+
     fn metadata(&self) -> Robj {
         let metadata = self.reader.metadata();
         let mut names = Vec::new();
@@ -98,58 +139,128 @@ impl Reader {
     }
 }
 
-pub enum ValueList {
-    Null(usize),
-    Boolean(Vec<bool>),
-    Float(Vec<f64>),
-    Integer(Vec<i64>),
-    String(Vec<String>),
-    Misc(Vec<Robj>),
+/// A column of strings with each distinct string stored once and a code for each row pointing
+/// at it, so columns that repeat a few values (e.g. reference or signal names) can become
+/// factors without building a string for every row
+#[derive(Default)]
+pub struct StringList {
+    /// The (1-based) index of each row's string in `levels` (`None` for missing values)
+    codes: Vec<Option<i32>>,
+    /// The distinct strings in the order they were first seen
+    levels: Vec<String>,
+    lookup: HashMap<String, i32>,
 }
 
-#[extendr]
-fn as_data_frame(reader: &mut Reader) -> Result<Robj> {
-    let mut data: Vec<ValueList> = Vec::new();
-    if let Some(first) = reader.reader.next_record().map_err(to_r)? {
-        for v in first {
-            data.push(match v {
-                Value::Null => ValueList::Null(1),
-                Value::Boolean(b) => ValueList::Boolean(vec![b]),
-                Value::Float(f) => ValueList::Float(vec![f]),
-                Value::Integer(i) => ValueList::Integer(vec![i]),
-                Value::String(s) => ValueList::String(vec![s.to_string()]),
-                x => ValueList::Misc(vec![value_to_robj(x)]),
-            });
+impl StringList {
+    fn push(&mut self, value: Option<&str>) {
+        let code = value.map(|s| match self.lookup.get(s) {
+            Some(code) => *code,
+            None => {
+                self.levels.push(s.to_string());
+                let code = self.levels.len() as i32;
+                let _ = self.lookup.insert(s.to_string(), code);
+                code
+            }
+        });
+        self.codes.push(code);
+    }
+
+    /// Are there few enough distinct strings (at most one for every other row) that the column
+    /// is worth making into a factor?
+    fn is_categorical(&self) -> bool {
+        !self.levels.is_empty() && self.levels.len() * 2 <= self.codes.len()
+    }
+
+    /// Convert into a factor (if `factors` is set and the column has few enough distinct values)
+    /// or a character vector
+    fn into_robj(self, factors: bool) -> Result<Robj> {
+        if factors && self.is_categorical() {
+            let obj = self.codes.into_iter().collect_robj();
+            obj.set_attrib(levels_symbol(), self.levels.iter().collect_robj())?;
+            obj.set_class(&["factor"])?;
+            return Ok(obj);
         }
-        while let Some(record) = reader.reader.next_record().map_err(to_r)? {
-            for (ix, v) in record.into_iter().enumerate() {
-                match (&mut data[ix], v) {
-                    (ValueList::Null(x), Value::Null) => *x += 1,
-                    (ValueList::Boolean(v), Value::Boolean(b)) => v.push(b),
-                    (ValueList::Float(v), Value::Float(f)) => v.push(f),
-                    (ValueList::Integer(v), Value::Integer(i)) => v.push(i),
-                    (ValueList::String(v), Value::String(s)) => v.push(s.to_string()),
-                    (ValueList::Misc(v), x) => v.push(value_to_robj(x)),
-                    _ => panic!("Tried to append wrong data type"),
+        let levels = &self.levels;
+        Ok(self
+            .codes
+            .iter()
+            .map(|code| code.map(|c| levels[c as usize - 1].as_str()))
+            .collect_robj())
+    }
+}
+
+/// Convert a column into an R vector. Integers with missing values become doubles (so they can
+/// be `NA`) and strings become a factor if `factors` is set and they have few distinct values.
+fn column_to_robj(column: Column, factors: bool) -> Result<Robj> {
+    let nulls = column.null_mask();
+    let is_null = |ix: usize| nulls.get(ix).copied().unwrap_or(false);
+    let robj = match column.values() {
+        ColumnValues::Null => Some(vec![r!(NULL); column.len()].into()),
+        ColumnValues::Boolean(v) if nulls.is_empty() => Some(v.iter().collect_robj()),
+        ColumnValues::Integer(v) if nulls.is_empty() => Some(v.iter().collect_robj()),
+        ColumnValues::Integer(v) => Some(
+            v.iter()
+                .enumerate()
+                .map(|(ix, i)| if is_null(ix) { None } else { Some(*i as f64) })
+                .collect_robj(),
+        ),
+        // missing values are `NA_real_` (while NaNs stay as `NaN`)
+        ColumnValues::Float(v) => Some(
+            v.iter()
+                .enumerate()
+                .map(|(ix, f)| if is_null(ix) { None } else { Some(*f) })
+                .collect_robj(),
+        ),
+        ColumnValues::String { codes, dictionary } => {
+            // the column is already dictionary-encoded so only the strings that are used by
+            // a row (and not just as a placeholder for nulls) need to become levels
+            let mut levels: Vec<Option<i32>> = vec![None; dictionary.len()];
+            let mut strings = StringList::default();
+            for (ix, code) in codes.iter().enumerate() {
+                let code = *code as usize;
+                if is_null(ix) {
+                    strings.codes.push(None);
+                } else if let Some(level) = levels[code] {
+                    strings.codes.push(Some(level));
+                } else {
+                    strings.push(Some(dictionary[code].as_str()));
+                    levels[code] = strings.codes.last().copied().flatten();
                 }
             }
+            Some(strings.into_robj(factors)?)
         }
-    } else {
-        for _ in &reader.header_names {
-            data.push(ValueList::Null(0));
+        _ => None,
+    };
+    Ok(match robj {
+        Some(robj) => robj,
+        None => {
+            let values: Vec<Robj> = column
+                .into_values()
+                .into_iter()
+                .map(value_to_robj)
+                .collect();
+            values.into()
         }
-    }
+    })
+}
+
+/// Read the remaining records into a data.frame; if `factors` is set, string columns with few
+/// distinct values are made into factors directly from their dictionary encoding.
+#[extendr]
+fn as_data_frame(reader: &mut Reader, factors: bool) -> Result<Robj> {
+    let mut columns = Vec::new();
+    while reader
+        .reader
+        .next_columns(&mut columns, BATCH_SIZE)
+        .map_err(to_r)?
+        > 0
+    {}
+    // there's one column for each header (unless a record had more values than there are)
+    columns.resize_with(reader.header_names.len(), Column::default);
 
     let mut vectors: Vec<Robj> = vec![];
-    for v in data {
-        vectors.push(match v {
-            ValueList::Null(x) => vec![r!(NULL); x].into(),
-            ValueList::Boolean(v) => v.iter().collect_robj(),
-            ValueList::Float(v) => v.iter().collect_robj(),
-            ValueList::Integer(v) => v.iter().collect_robj(),
-            ValueList::String(v) => v.iter().collect_robj(),
-            ValueList::Misc(v) => v.into(),
-        });
+    for column in columns {
+        vectors.push(column_to_robj(column, factors)?);
     }
     let obj: Robj = List::from_names_and_values(&reader.header_names, &vectors).into();
     obj.set_attrib(
@@ -160,8 +271,16 @@ fn as_data_frame(reader: &mut Reader) -> Result<Robj> {
     Ok(obj)
 }
 
+/// The version of entab, its enabled features, and the version of each parser
+#[extendr]
+fn build_info() -> Robj {
+    value_to_robj(entab_base::build_info::build_info().to_value())
+}
+
 extendr_module! {
     mod entab;
     impl Reader;
     fn as_data_frame;
+    fn build_info;
+    fn last_error_position;
 }
@@ -1,8 +1,13 @@
 use std::fs::File;
+use std::io::{Cursor, Write};
+use std::sync::Arc;
 
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use flate2::write::GzEncoder;
+use flate2::Compression;
 
-use entab::compression::decompress;
+use entab::columns::push_record;
+use entab::compression::{decompress, decompress_threaded};
 use entab::parsers::agilent::chemstation::ChemstationMsReader;
 use entab::parsers::fasta::FastaReader;
 use entab::parsers::fastq::{FastqReader, FastqRecord, FastqState};
@@ -123,5 +128,121 @@ fn benchmark_generic_readers(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, benchmark_raw_readers, benchmark_generic_readers);
+/// A FASTQ of 200,000 pseudo-random 100 bp reads (~45 MB), so there's enough data to decompress
+/// that the time spent on it (and not on setting up a thread) dominates
+fn synthetic_fastq() -> Vec<u8> {
+    let mut fastq = Vec::new();
+    let mut state: u64 = 0x2545_F491_4F6C_DD1D;
+    let mut next = || {
+        // xorshift, so the reads don't compress any better than real ones
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    for ix in 0..200_000 {
+        fastq.extend(format!("@read_{} length=100\n", ix).as_bytes());
+        fastq.extend((0..100).map(|_| b"ACGT"[(next() % 4) as usize]));
+        fastq.extend(b"\n+\n");
+        fastq.extend((0..100).map(|_| b'!' + (next() % 41) as u8));
+        fastq.push(b'\n');
+    }
+    fastq
+}
+
+fn benchmark_compressed_readers(c: &mut Criterion) {
+    let mut compressed_readers = c.benchmark_group("compressed readers");
+    compressed_readers.significance_level(0.01).sample_size(10);
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&synthetic_fastq()).unwrap();
+    let fastq_gz: Arc<[u8]> = encoder.finish().unwrap().into();
+
+    compressed_readers.bench_function("fastq.gz reader", |b| {
+        b.iter(|| {
+            let (rb, _) = decompress(&fastq_gz[..]).unwrap();
+            let mut reader = FastqReader::new(rb, None).unwrap();
+            while let Some(record) = reader.next().unwrap() {
+                black_box(record);
+            }
+        })
+    });
+
+    // decompression overlaps with parsing on a second thread (if there's more than one core)
+    compressed_readers.bench_function("fastq.gz threaded reader", |b| {
+        b.iter(|| {
+            let (rb, _) = decompress_threaded(Cursor::new(Arc::clone(&fastq_gz))).unwrap();
+            let mut reader = FastqReader::new(rb, None).unwrap();
+            while let Some(record) = reader.next().unwrap() {
+                black_box(record);
+            }
+        })
+    });
+}
+
+/// A little-endian FCS file of 100,000 events with eight 16-bit integer params
+fn int_fcs() -> Vec<u8> {
+    let n_events = 100_000;
+    let n_params = 8;
+    let mut text = String::new();
+    for ix in 1..=n_params {
+        text.push_str(&format!("$P{0}B/16/$P{0}R/65536/$P{0}N/P{0}/", ix));
+    }
+    let data_len = n_events * n_params * 2;
+    let text = |start: usize| {
+        format!(
+            "/$BEGINDATA/{:08}/$ENDDATA/{:08}/$PAR/{}/$TOT/{}/$DATATYPE/I/$BYTEORD/1,2/$MODE/L/{}",
+            start,
+            start + data_len - 1,
+            n_params,
+            n_events,
+            text
+        )
+    };
+    let text_len = text(0).len();
+    let mut file = format!("FCS3.0    {:>8}{:>8}", 58, 57 + text_len).into_bytes();
+    file.extend(format!("{:>8}", 0).repeat(4).as_bytes());
+    file.extend(text(58 + text_len).as_bytes());
+    for ix in 0..n_events * n_params {
+        file.extend(((ix * 7919) as u16).to_le_bytes());
+    }
+    file
+}
+
+fn benchmark_columnar_readers(c: &mut Criterion) {
+    let mut columnar_readers = c.benchmark_group("columnar readers");
+    columnar_readers.significance_level(0.01).sample_size(100);
+
+    let fcs = int_fcs();
+
+    // integer events are cast straight from the buffer into the columns
+    columnar_readers.bench_function("integer flow columns", |b| {
+        b.iter(|| {
+            let (mut reader, _) = get_reader(&fcs[..], Some("flow"), None).unwrap();
+            let mut columns = Vec::new();
+            while reader.next_columns(&mut columns, 10_000).unwrap() > 0 {}
+            black_box(columns);
+        })
+    });
+
+    // versus decoding each event into a record and pushing its values into the columns
+    columnar_readers.bench_function("integer flow records into columns", |b| {
+        b.iter(|| {
+            let (mut reader, _) = get_reader(&fcs[..], Some("flow"), None).unwrap();
+            let mut columns = Vec::new();
+            while let Some(record) = reader.next_record().unwrap() {
+                push_record(&mut columns, record);
+            }
+            black_box(columns);
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    benchmark_raw_readers,
+    benchmark_generic_readers,
+    benchmark_compressed_readers,
+    benchmark_columnar_readers
+);
 criterion_main!(benches);
@@ -0,0 +1,17 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+extern crate entab;
+
+use entab::EtError;
+use entab::parsers::thermo::thermo_raw::ThermoRawReader;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = generate_reader(data);
+});
+
+fn generate_reader(data: &[u8]) -> Result<(), EtError> {
+    let mut reader = ThermoRawReader::new(data, None)?;
+    while let Some(_) = reader.next()? {
+    }
+    Ok(())
+}
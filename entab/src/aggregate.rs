@@ -0,0 +1,339 @@
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+use crate::capabilities::Capabilities;
+use crate::error::EtError;
+use crate::hash::hash_record_except;
+use crate::provenance::{add_step, transform_step};
+use crate::readers::RecordReader;
+use crate::record::Value;
+use crate::summary::SummaryFields;
+
+/// How the intensities that fall into the same bin are combined
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Aggregation {
+    /// The average of the intensities
+    Mean,
+    /// The largest intensity
+    Max,
+    /// The smallest intensity
+    Min,
+    /// The total of the intensities
+    Sum,
+}
+
+impl FromStr for Aggregation {
+    type Err = EtError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mean" => Ok(Aggregation::Mean),
+            "max" => Ok(Aggregation::Max),
+            "min" => Ok(Aggregation::Min),
+            "sum" => Ok(Aggregation::Sum),
+            x => Err(format!("Unknown aggregation {}; expected mean, max, min, or sum", x).into()),
+        }
+    }
+}
+
+impl fmt::Display for Aggregation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Aggregation::Mean => "mean",
+            Aggregation::Max => "max",
+            Aggregation::Min => "min",
+            Aggregation::Sum => "sum",
+        })
+    }
+}
+
+/// Parse a bin width like `0.1min`, `6s`, or `0.1` (which is assumed to be in minutes, the
+/// units entab uses for times) into minutes.
+///
+/// # Errors
+/// If the width isn't a positive number with an optional unit, an error is returned.
+pub fn parse_bin_width(width: &str) -> Result<f64, EtError> {
+    let width = width.trim();
+    let (number, scale) = if let Some(n) = width.strip_suffix("min") {
+        (n, 1.)
+    } else if let Some(n) = width.strip_suffix("ms") {
+        (n, 1. / 60_000.)
+    } else if let Some(n) = width.strip_suffix('s') {
+        (n, 1. / 60.)
+    } else if let Some(n) = width.strip_suffix('h') {
+        (n, 60.)
+    } else {
+        (width, 1.)
+    };
+    let number: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| EtError::from(format!("Bad bin width {}", width)))?;
+    if !(number > 0. && number.is_finite()) {
+        return Err(format!("Bin width {} must be positive", width).into());
+    }
+    Ok(number * scale)
+}
+
+/// The running aggregate for all the records in a bin that share the same non-time,
+/// non-intensity values (e.g. the same wavelength or m/z)
+#[derive(Debug)]
+struct Group {
+    template: Vec<Value<'static>>,
+    total: f64,
+    count: usize,
+    min: f64,
+    max: f64,
+}
+
+/// Wraps a `RecordReader` with `time` and `intensity` columns to combine all of the records
+/// within each time bin into a single record (per distinct value of the other columns).
+///
+/// Records are expected to be sorted by time; the time of each output record is the start of
+/// its bin.
+#[derive(Debug)]
+pub struct AggregateReader<'r> {
+    reader: Box<dyn RecordReader + 'r>,
+    aggregation: Aggregation,
+    bin_width: f64,
+    time_col: usize,
+    intensity_col: usize,
+    current_bin: Option<i64>,
+    groups: Vec<Group>,
+    /// The indices into `groups` of the groups with each hash of their non-time, non-intensity
+    /// values, so each record can find its group without checking all of them
+    group_index: BTreeMap<u64, Vec<usize>>,
+    ready: VecDeque<Vec<Value<'static>>>,
+    done: bool,
+}
+
+impl<'r> AggregateReader<'r> {
+    /// Create a new `AggregateReader` around a `RecordReader`.
+    ///
+    /// # Errors
+    /// If the reader doesn't have `time` and `intensity` columns or the bin width isn't positive,
+    /// an error is returned.
+    pub fn new(
+        reader: Box<dyn RecordReader + 'r>,
+        aggregation: Aggregation,
+        bin_width: f64,
+    ) -> Result<Self, EtError> {
+        if !(bin_width > 0. && bin_width.is_finite()) {
+            return Err("Bin width must be positive".into());
+        }
+        let headers = reader.headers();
+        let find = |name: &str| {
+            headers
+                .iter()
+                .position(|h| h == name)
+                .ok_or_else(|| EtError::from(format!("Aggregation requires a {} column", name)))
+        };
+        let time_col = find("time")?;
+        let intensity_col = find("intensity")?;
+        Ok(AggregateReader {
+            reader,
+            aggregation,
+            bin_width,
+            time_col,
+            intensity_col,
+            current_bin: None,
+            groups: Vec::new(),
+            group_index: BTreeMap::new(),
+            ready: VecDeque::new(),
+            done: false,
+        })
+    }
+
+    /// Move all the groups in the current bin into the output queue.
+    fn flush(&mut self) {
+        let bin_start = match self.current_bin {
+            Some(bin) => bin as f64 * self.bin_width,
+            None => return,
+        };
+        self.group_index.clear();
+        for group in self.groups.drain(..) {
+            let value = match self.aggregation {
+                Aggregation::Mean => group.total / group.count as f64,
+                Aggregation::Max => group.max,
+                Aggregation::Min => group.min,
+                Aggregation::Sum => group.total,
+            };
+            let mut record = group.template;
+            record[self.time_col] = Value::Float(bin_start);
+            record[self.intensity_col] = Value::Float(value);
+            self.ready.push_back(record);
+        }
+    }
+}
+
+fn as_number(value: &Value<'_>, name: &str) -> Result<f64, EtError> {
+    match value {
+        Value::Float(f) => Ok(*f),
+        Value::Integer(i) => Ok(*i as f64),
+        v => Err(format!("Can not aggregate non-numeric {} {:?}", name, v).into()),
+    }
+}
+
+impl<'r> RecordReader for AggregateReader<'r> {
+    fn next_record(&mut self) -> Result<Option<Vec<Value<'_>>>, EtError> {
+        loop {
+            if let Some(record) = self.ready.pop_front() {
+                return Ok(Some(record));
+            }
+            if self.done {
+                return Ok(None);
+            }
+            let record: Vec<Value<'static>> = match self.reader.next_record()? {
+                Some(record) => record.into_iter().map(Value::into_owned).collect(),
+                None => {
+                    self.flush();
+                    self.done = true;
+                    continue;
+                }
+            };
+            let time = as_number(&record[self.time_col], "time")?;
+            let intensity = match &record[self.intensity_col] {
+                // missing points don't count towards the aggregate
+                Value::Null => continue,
+                v => as_number(v, "intensity")?,
+            };
+            let bin = (time / self.bin_width).floor() as i64;
+            if self.current_bin != Some(bin) {
+                self.flush();
+                self.current_bin = Some(bin);
+            }
+
+            let (time_col, intensity_col) = (self.time_col, self.intensity_col);
+            let same_group = |group: &Group| {
+                group
+                    .template
+                    .iter()
+                    .zip(&record)
+                    .enumerate()
+                    .all(|(ix, (a, b))| ix == time_col || ix == intensity_col || a == b)
+            };
+            let groups = &mut self.groups;
+            let candidates = self
+                .group_index
+                .entry(hash_record_except(&record, &[time_col, intensity_col]))
+                .or_default();
+            if let Some(&ix) = candidates.iter().find(|&&ix| same_group(&groups[ix])) {
+                let group = &mut groups[ix];
+                group.total += intensity;
+                group.count += 1;
+                group.min = group.min.min(intensity);
+                group.max = group.max.max(intensity);
+            } else {
+                candidates.push(groups.len());
+                groups.push(Group {
+                    template: record,
+                    total: intensity,
+                    count: 1,
+                    min: intensity,
+                    max: intensity,
+                });
+            }
+        }
+    }
+
+    fn bytes_read(&self) -> Option<u64> {
+        self.reader.bytes_read()
+    }
+
+    fn headers(&self) -> Vec<String> {
+        self.reader.headers()
+    }
+
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        let mut metadata = self.reader.metadata();
+        let mut params = BTreeMap::new();
+        drop(params.insert("aggregate".to_string(), self.aggregation.to_string().into()));
+        // in minutes, like the times
+        drop(params.insert("bin".to_string(), self.bin_width.into()));
+        add_step(&mut metadata, transform_step("aggregate", params));
+        metadata
+    }
+
+    fn column_metadata(&self) -> BTreeMap<String, BTreeMap<String, Value<'_>>> {
+        self.reader.column_metadata()
+    }
+
+    fn summary_fields(&self) -> SummaryFields {
+        self.reader.summary_fields()
+    }
+
+    /// Binning loses track of how many records are left or where they are in the file
+    fn capabilities(&self) -> Capabilities {
+        self.reader.capabilities() - (Capabilities::SEEKABLE | Capabilities::SIZED)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+    use crate::readers::get_reader;
+
+    #[test]
+    fn test_bin_widths() -> Result<(), EtError> {
+        assert!((parse_bin_width("0.1min")? - 0.1).abs() < 1e-12);
+        assert!((parse_bin_width("6s")? - 0.1).abs() < 1e-12);
+        assert!((parse_bin_width("0.5")? - 0.5).abs() < 1e-12);
+        assert!(parse_bin_width("-1s").is_err());
+        assert!(parse_bin_width("fast").is_err());
+        assert!("median".parse::<Aggregation>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_aggregate_reader() -> Result<(), EtError> {
+        let data: &[u8] = b"time\twavelength\tintensity\n0.0\t200\t1\n0.0\t250\t5\n0.05\t200\t3\n0.05\t250\t7\n0.1\t200\t10\n";
+        let (reader, _) = get_reader(data, Some("tsv"), None)?;
+        let mut reader = AggregateReader::new(reader, Aggregation::Mean, 0.1)?;
+        assert_eq!(reader.headers(), ["time", "wavelength", "intensity"]);
+        assert_eq!(
+            reader.next_record()?,
+            Some(vec![0.0.into(), 200.into(), 2.0.into()])
+        );
+        assert_eq!(
+            reader.next_record()?,
+            Some(vec![0.0.into(), 250.into(), 6.0.into()])
+        );
+        assert_eq!(
+            reader.next_record()?,
+            Some(vec![0.1.into(), 200.into(), 10.0.into()])
+        );
+        assert_eq!(reader.next_record()?, None);
+
+        let (reader, _) = get_reader(data, Some("tsv"), None)?;
+        let mut reader = AggregateReader::new(reader, Aggregation::Max, 1.)?;
+        assert_eq!(reader.next_record()?.unwrap()[2], 10.0.into());
+
+        let (reader, _) = get_reader(&b">a\nACGT\n"[..], None, None)?;
+        assert!(AggregateReader::new(reader, Aggregation::Max, 1.).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_aggregate_params() -> Result<(), EtError> {
+        let data: &[u8] = b"time\tintensity\n0.0\t1\n0.01\t3\n0.2\t4\n";
+        let mut params = BTreeMap::new();
+        let _ = params.insert("aggregate".into(), "sum".into());
+        let _ = params.insert("bin".into(), "6s".into());
+        let (mut reader, _) = get_reader(data, Some("tsv"), Some(params))?;
+        assert_eq!(reader.next_record()?.unwrap()[1], 4.0.into());
+        assert_eq!(reader.next_record()?.unwrap()[1], 4.0.into());
+        assert_eq!(reader.next_record()?, None);
+
+        let mut params = BTreeMap::new();
+        let _ = params.insert("aggregate".into(), "sum".into());
+        assert!(get_reader(data, Some("tsv"), Some(params)).is_err());
+        Ok(())
+    }
+}
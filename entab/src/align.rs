@@ -0,0 +1,558 @@
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+use crate::capabilities::Capabilities;
+use crate::error::EtError;
+use crate::provenance::{add_step, transform_step};
+use crate::readers::RecordReader;
+use crate::record::Value;
+use crate::summary::SummaryFields;
+
+/// Peaks smaller than this fraction of the tallest one aren't used as reference peaks
+const MIN_PEAK_FRACTION: f64 = 0.05;
+
+/// How the retention times of a trace are matched up to a reference trace
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AlignMethod {
+    /// Shift the whole trace by the offset that best correlates it with the reference
+    CrossCorrelation,
+    /// Match the tallest peaks in the reference with the tallest nearby peaks in the trace and
+    /// stretch the times between them (so drift that changes over the run can be corrected)
+    Peaks,
+}
+
+impl FromStr for AlignMethod {
+    type Err = EtError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "xcorr" | "cross-correlation" => Ok(AlignMethod::CrossCorrelation),
+            "peaks" => Ok(AlignMethod::Peaks),
+            x => Err(format!("Unknown alignment method {}; expected xcorr or peaks", x).into()),
+        }
+    }
+}
+
+impl fmt::Display for AlignMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AlignMethod::CrossCorrelation => "xcorr",
+            AlignMethod::Peaks => "peaks",
+        })
+    }
+}
+
+fn as_number(value: &Value<'_>, name: &str) -> Result<f64, EtError> {
+    match value {
+        Value::Float(f) => Ok(*f),
+        Value::Integer(i) => Ok(*i as f64),
+        v => Err(format!("Can not align non-numeric {} {:?}", name, v).into()),
+    }
+}
+
+/// The position of the `name` column in `headers`
+fn find_column(headers: &[String], name: &str) -> Result<usize, EtError> {
+    headers
+        .iter()
+        .position(|h| h == name)
+        .ok_or_else(|| format!("Alignment requires a {} column", name).into())
+}
+
+/// A chromatogram as the total intensity at each time
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Trace {
+    /// The times (in minutes), in increasing order
+    pub times: Vec<f64>,
+    /// The intensity at each time
+    pub intensities: Vec<f64>,
+}
+
+impl Trace {
+    /// Read the `time` and `intensity` columns out of all the records in `reader`; the
+    /// intensities of records at the same time (e.g. different wavelengths of a DAD trace) are
+    /// added together.
+    ///
+    /// # Errors
+    /// If the reader doesn't have `time` and `intensity` columns, a time or intensity isn't a
+    /// number, or the records aren't sorted by time, an error is returned.
+    pub fn from_reader(reader: &mut dyn RecordReader) -> Result<Self, EtError> {
+        let headers = reader.headers();
+        let time_col = find_column(&headers, "time")?;
+        let intensity_col = find_column(&headers, "intensity")?;
+        let mut trace = Trace::default();
+        while let Some(record) = reader.next_record()? {
+            let time = as_number(&record[time_col], "time")?;
+            let intensity = match &record[intensity_col] {
+                // missing points don't count towards the trace
+                Value::Null => continue,
+                v => as_number(v, "intensity")?,
+            };
+            match trace.times.last() {
+                Some(&last) if last == time => {
+                    if let Some(total) = trace.intensities.last_mut() {
+                        *total += intensity;
+                    }
+                    continue;
+                }
+                Some(&last) if last > time => {
+                    return Err(format!(
+                        "Records must be sorted by time to align them, but {} comes after {}",
+                        time, last
+                    )
+                    .into());
+                }
+                _ => {}
+            }
+            trace.times.push(time);
+            trace.intensities.push(intensity);
+        }
+        Ok(trace)
+    }
+
+    /// The intensity at `time`, interpolated between the nearest points (or the first or last
+    /// intensity for times outside of the trace); `hint` is the index to start looking from,
+    /// which is updated so stepping through increasing times is fast.
+    fn intensity_at(&self, time: f64, hint: &mut usize) -> f64 {
+        let n = self.times.len();
+        while *hint + 1 < n && self.times[*hint + 1] <= time {
+            *hint += 1;
+        }
+        let ix = *hint;
+        if time <= self.times[0] {
+            return self.intensities[0];
+        }
+        if ix + 1 >= n {
+            return self.intensities[n - 1];
+        }
+        let (t0, t1) = (self.times[ix], self.times[ix + 1]);
+        let frac = (time - t0) / (t1 - t0);
+        self.intensities[ix] + frac * (self.intensities[ix + 1] - self.intensities[ix])
+    }
+
+    /// The intensities on an evenly-spaced grid of `n` times starting at `start`
+    fn resample(&self, start: f64, step: f64, n: usize) -> Vec<f64> {
+        let mut hint = 0;
+        (0..n)
+            .map(|i| self.intensity_at(start + i as f64 * step, &mut hint))
+            .collect()
+    }
+
+    /// The median spacing between the times
+    fn median_step(&self) -> Option<f64> {
+        let mut steps: Vec<f64> = self
+            .times
+            .windows(2)
+            .map(|w| w[1] - w[0])
+            .filter(|s| *s > 0.)
+            .collect();
+        if steps.is_empty() {
+            return None;
+        }
+        steps.sort_by(|a, b| a.partial_cmp(b).unwrap_or(core::cmp::Ordering::Equal));
+        Some(steps[steps.len() / 2])
+    }
+
+    /// The indices of the tallest local maxima (at most `n_peaks` of them, tallest first) that
+    /// are more than `spacing` apart from each other
+    fn tallest_peaks(&self, n_peaks: usize, spacing: f64) -> Vec<usize> {
+        let intensities = &self.intensities;
+        let (min, max) = intensities
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &i| {
+                (lo.min(i), hi.max(i))
+            });
+        let threshold = min + MIN_PEAK_FRACTION * (max - min);
+        let mut candidates: Vec<usize> = (1..intensities.len().saturating_sub(1))
+            .filter(|&i| {
+                intensities[i] > threshold
+                    && intensities[i] > intensities[i - 1]
+                    && intensities[i] >= intensities[i + 1]
+            })
+            .collect();
+        candidates.sort_by(|&a, &b| {
+            intensities[b]
+                .partial_cmp(&intensities[a])
+                .unwrap_or(core::cmp::Ordering::Equal)
+        });
+        let mut peaks: Vec<usize> = Vec::new();
+        for candidate in candidates {
+            if peaks.len() >= n_peaks {
+                break;
+            }
+            let time = self.times[candidate];
+            if peaks
+                .iter()
+                .all(|&p| (self.times[p] - time).abs() > spacing)
+            {
+                peaks.push(candidate);
+            }
+        }
+        peaks
+    }
+
+    /// The time of the top of the peak at (local maximum) index `ix`, refined between samples
+    fn apex_time(&self, ix: usize) -> f64 {
+        let (times, intensities) = (&self.times, &self.intensities);
+        let offset = parabolic_offset(intensities[ix - 1], intensities[ix], intensities[ix + 1]);
+        if offset < 0. {
+            times[ix] + offset * (times[ix] - times[ix - 1])
+        } else {
+            times[ix] + offset * (times[ix + 1] - times[ix])
+        }
+    }
+}
+
+/// The vertex of the parabola through three evenly spaced points, as an offset (between -0.5
+/// and 0.5) from the middle one
+fn parabolic_offset(before: f64, at: f64, after: f64) -> f64 {
+    let denominator = before - 2. * at + after;
+    if denominator >= 0. {
+        return 0.;
+    }
+    (0.5 * (before - after) / denominator).clamp(-0.5, 0.5)
+}
+
+/// A mapping from the raw times of a trace to the times they line up with in the reference.
+///
+/// This is a list of (raw time, aligned time) anchor points with the times in between them
+/// interpolated; times before the first or after the last anchor are shifted by the same amount
+/// as that anchor.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Alignment {
+    anchors: Vec<(f64, f64)>,
+}
+
+impl Alignment {
+    /// An alignment that leaves the times unchanged (e.g. for the reference itself)
+    pub fn identity() -> Self {
+        Self::default()
+    }
+
+    /// An alignment that moves every time by `shift` minutes
+    pub fn shift(shift: f64) -> Self {
+        Alignment {
+            anchors: vec![(0., shift)],
+        }
+    }
+
+    /// Find the alignment of `trace` to `reference` with `method`, only looking for shifts of
+    /// up to `max_shift` minutes.
+    ///
+    /// # Errors
+    /// If either trace is too short to align or (with `AlignMethod::Peaks`) none of the
+    /// reference's peaks were found in `trace`, an error is returned.
+    pub fn find(
+        reference: &Trace,
+        trace: &Trace,
+        method: AlignMethod,
+        max_shift: f64,
+    ) -> Result<Self, EtError> {
+        if !(max_shift > 0. && max_shift.is_finite()) {
+            return Err("The maximum shift must be positive".into());
+        }
+        if reference.times.len() < 3 || trace.times.len() < 3 {
+            return Err("Traces need at least three points to be aligned".into());
+        }
+        match method {
+            AlignMethod::CrossCorrelation => Self::cross_correlation(reference, trace, max_shift),
+            AlignMethod::Peaks => Self::reference_peaks(reference, trace, max_shift),
+        }
+    }
+
+    /// Find the single shift that best lines `trace` up with `reference`
+    fn cross_correlation(
+        reference: &Trace,
+        trace: &Trace,
+        max_shift: f64,
+    ) -> Result<Self, EtError> {
+        let step = reference
+            .median_step()
+            .ok_or("The reference trace doesn't have distinct times")?;
+        let start = reference.times[0];
+        let n = ((reference.times[reference.times.len() - 1] - start) / step) as usize + 1;
+        let center = |mut v: Vec<f64>| {
+            let mean = v.iter().sum::<f64>() / v.len() as f64;
+            v.iter_mut().for_each(|x| *x -= mean);
+            v
+        };
+        let reference = center(reference.resample(start, step, n));
+        let trace = center(trace.resample(start, step, n));
+
+        // a positive lag means the trace's peaks come `lag` points later than the reference's
+        let max_lag = ((max_shift / step) as usize).min(n - 1) as isize;
+        let score = |lag: isize| {
+            let (r, t) = if lag >= 0 {
+                (&reference[..n - lag as usize], &trace[lag as usize..])
+            } else {
+                (&reference[(-lag) as usize..], &trace[..n - (-lag) as usize])
+            };
+            r.iter().zip(t).map(|(a, b)| a * b).sum::<f64>() / r.len() as f64
+        };
+        let scores: Vec<f64> = (-max_lag..=max_lag).map(score).collect();
+        let mut best = 0;
+        for (ix, s) in scores.iter().enumerate() {
+            if *s > scores[best] {
+                best = ix;
+            }
+        }
+        let mut lag = best as f64 - max_lag as f64;
+        if best > 0 && best + 1 < scores.len() {
+            lag += parabolic_offset(scores[best - 1], scores[best], scores[best + 1]);
+        }
+        Ok(Alignment::shift(-lag * step))
+    }
+
+    /// Match up the tallest peaks of `reference` with the tallest peak within `max_shift` of
+    /// each of them in `trace`
+    fn reference_peaks(reference: &Trace, trace: &Trace, max_shift: f64) -> Result<Self, EtError> {
+        let mut anchors = Vec::new();
+        for peak in reference.tallest_peaks(10, 2. * max_shift) {
+            let peak_time = reference.times[peak];
+            let reference_time = reference.apex_time(peak);
+            let window: Vec<usize> = (0..trace.times.len())
+                .filter(|&i| (trace.times[i] - peak_time).abs() <= max_shift)
+                .collect();
+            let apex = match window.iter().copied().max_by(|&a, &b| {
+                trace.intensities[a]
+                    .partial_cmp(&trace.intensities[b])
+                    .unwrap_or(core::cmp::Ordering::Equal)
+            }) {
+                Some(apex) => apex,
+                None => continue,
+            };
+            // if the biggest point is at the edge of the window, the peak isn't in it
+            if apex == 0
+                || apex + 1 >= trace.times.len()
+                || Some(&apex) == window.first()
+                || Some(&apex) == window.last()
+            {
+                continue;
+            }
+            anchors.push((trace.apex_time(apex), reference_time));
+        }
+        anchors.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(core::cmp::Ordering::Equal));
+        // drop any matches that would make the times go backwards
+        let mut monotonic: Vec<(f64, f64)> = Vec::with_capacity(anchors.len());
+        for anchor in anchors {
+            if !matches!(monotonic.last(), Some(last) if anchor.0 <= last.0) {
+                monotonic.push(anchor);
+            }
+        }
+        if monotonic.is_empty() {
+            return Err("None of the reference's peaks were found in the trace".into());
+        }
+        Ok(Alignment { anchors: monotonic })
+    }
+
+    /// The time in the reference that `time` in the trace lines up with
+    pub fn aligned_time(&self, time: f64) -> f64 {
+        let anchors = &self.anchors;
+        let (first, last) = match (anchors.first(), anchors.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return time,
+        };
+        if time <= first.0 {
+            return time + (first.1 - first.0);
+        }
+        if time >= last.0 {
+            return time + (last.1 - last.0);
+        }
+        let ix = anchors.partition_point(|a| a.0 <= time);
+        let ((r0, a0), (r1, a1)) = (anchors[ix - 1], anchors[ix]);
+        a0 + (time - r0) * (a1 - a0) / (r1 - r0)
+    }
+
+    /// How far the start of the trace was moved (in minutes)
+    pub fn start_shift(&self) -> f64 {
+        self.anchors.first().map_or(0., |a| a.1 - a.0)
+    }
+}
+
+/// Wraps a `RecordReader` with a `time` column to add an `aligned_time` column right after it
+/// with each time mapped onto a reference trace by an `Alignment`.
+#[derive(Debug)]
+pub struct AlignedReader<'r> {
+    reader: Box<dyn RecordReader + 'r>,
+    alignment: Alignment,
+    time_col: usize,
+}
+
+impl<'r> AlignedReader<'r> {
+    /// Create a new `AlignedReader` around a `RecordReader`.
+    ///
+    /// # Errors
+    /// If the reader doesn't have a `time` column, an error is returned.
+    pub fn new(reader: Box<dyn RecordReader + 'r>, alignment: Alignment) -> Result<Self, EtError> {
+        let time_col = find_column(&reader.headers(), "time")?;
+        Ok(AlignedReader {
+            reader,
+            alignment,
+            time_col,
+        })
+    }
+}
+
+impl<'r> RecordReader for AlignedReader<'r> {
+    fn next_record(&mut self) -> Result<Option<Vec<Value<'_>>>, EtError> {
+        let mut record = match self.reader.next_record()? {
+            Some(record) => record,
+            None => return Ok(None),
+        };
+        let aligned = match &record[self.time_col] {
+            Value::Null => Value::Null,
+            v => Value::Float(self.alignment.aligned_time(as_number(v, "time")?)),
+        };
+        record.insert(self.time_col + 1, aligned);
+        Ok(Some(record))
+    }
+
+    fn skip_records(&mut self, n: usize) -> Result<usize, EtError> {
+        self.reader.skip_records(n)
+    }
+
+    fn bytes_read(&self) -> Option<u64> {
+        self.reader.bytes_read()
+    }
+
+    fn headers(&self) -> Vec<String> {
+        let mut headers = self.reader.headers();
+        headers.insert(self.time_col + 1, "aligned_time".to_string());
+        headers
+    }
+
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        let mut metadata = self.reader.metadata();
+        let mut params = BTreeMap::new();
+        let anchors: Vec<Value<'static>> = self
+            .alignment
+            .anchors
+            .iter()
+            .map(|&(raw, aligned)| Value::List(vec![raw.into(), aligned.into()]))
+            .collect();
+        drop(params.insert("anchors".to_string(), anchors.into()));
+        add_step(&mut metadata, transform_step("align", params));
+        metadata
+    }
+
+    fn column_metadata(&self) -> BTreeMap<String, BTreeMap<String, Value<'_>>> {
+        self.reader.column_metadata()
+    }
+
+    fn summary_fields(&self) -> SummaryFields {
+        self.reader.summary_fields()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.reader.size_hint()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.reader.capabilities()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::readers::get_reader;
+
+    /// A trace with Gaussian-ish peaks (as triangles) at each of `peaks`
+    fn make_trace(peaks: &[f64]) -> Trace {
+        let times: Vec<f64> = (0..1000).map(|i| i as f64 * 0.01).collect();
+        let intensities = times
+            .iter()
+            .map(|t| {
+                peaks
+                    .iter()
+                    .enumerate()
+                    .map(|(ix, p)| {
+                        let height = 100. * (ix + 1) as f64;
+                        (height - height * (t - p).abs() / 0.1).max(0.)
+                    })
+                    .sum::<f64>()
+                    + 1.
+            })
+            .collect();
+        Trace { times, intensities }
+    }
+
+    #[test]
+    fn test_methods() {
+        assert_eq!(
+            "xcorr".parse::<AlignMethod>().unwrap(),
+            AlignMethod::CrossCorrelation
+        );
+        assert_eq!("peaks".parse::<AlignMethod>().unwrap().to_string(), "peaks");
+        assert!("dtw".parse::<AlignMethod>().is_err());
+    }
+
+    #[test]
+    fn test_cross_correlation() -> Result<(), EtError> {
+        let reference = make_trace(&[2., 4.5, 7.]);
+        let trace = make_trace(&[2.13, 4.63, 7.13]);
+        let alignment = Alignment::find(&reference, &trace, AlignMethod::CrossCorrelation, 0.5)?;
+        assert!((alignment.start_shift() + 0.13).abs() < 0.005);
+        assert!((alignment.aligned_time(4.63) - 4.5).abs() < 0.005);
+
+        // aligning a trace to itself doesn't move it
+        let alignment =
+            Alignment::find(&reference, &reference, AlignMethod::CrossCorrelation, 0.5)?;
+        assert!(alignment.start_shift().abs() < 1e-9);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reference_peaks() -> Result<(), EtError> {
+        let reference = make_trace(&[2., 4.5, 7.]);
+        // the drift gets bigger over the run
+        let trace = make_trace(&[2.05, 4.65, 7.25]);
+        let alignment = Alignment::find(&reference, &trace, AlignMethod::Peaks, 0.4)?;
+        assert!((alignment.aligned_time(2.05) - 2.).abs() < 0.005);
+        assert!((alignment.aligned_time(4.65) - 4.5).abs() < 0.005);
+        assert!((alignment.aligned_time(7.25) - 7.).abs() < 0.005);
+        // before the first peak, the times are shifted like it
+        assert!((alignment.aligned_time(1.05) - 1.).abs() < 0.005);
+
+        let flat = Trace {
+            times: vec![0., 1., 2., 3.],
+            intensities: vec![1., 1., 1., 1.],
+        };
+        assert!(Alignment::find(&reference, &flat, AlignMethod::Peaks, 0.4).is_err());
+        assert!(Alignment::find(&reference, &trace, AlignMethod::Peaks, 0.).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_aligned_reader() -> Result<(), EtError> {
+        let data: &[u8] =
+            b"time\tsignal\tintensity\n0.0\tA\t1\n0.0\tB\t2\n0.5\tA\t3\n0.5\tB\t4\n1.0\tA\t5\n";
+        let (mut reader, _) = get_reader(data, Some("tsv"), None)?;
+        let trace = Trace::from_reader(&mut *reader)?;
+        assert_eq!(trace.times, [0., 0.5, 1.]);
+        assert_eq!(trace.intensities, [3., 7., 5.]);
+
+        let (reader, _) = get_reader(data, Some("tsv"), None)?;
+        let mut reader = AlignedReader::new(reader, Alignment::shift(-0.25))?;
+        assert_eq!(
+            reader.headers(),
+            ["time", "aligned_time", "signal", "intensity"]
+        );
+        assert_eq!(
+            reader.next_record()?,
+            Some(vec![0.0.into(), (-0.25).into(), "A".into(), 1.into()])
+        );
+        assert!(reader.metadata().contains_key("provenance"));
+
+        let (mut reader, _) = get_reader(&b"time\tintensity\n1\t1\n0\t1\n"[..], Some("tsv"), None)?;
+        assert!(Trace::from_reader(&mut *reader).is_err());
+        let (reader, _) = get_reader(&b">a\nACGT\n"[..], None, None)?;
+        assert!(AlignedReader::new(reader, Alignment::identity()).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,498 @@
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::convert::TryFrom;
+use core::fmt;
+use core::str::from_utf8;
+use std::io::{self, Read};
+
+use crate::bom::Bom;
+use crate::buffer::ReadBuffer;
+use crate::capabilities::Capabilities;
+use crate::compression::decompress;
+use crate::error::EtError;
+use crate::filetype::FileType;
+use crate::readers::{_get_reader, sniff_parser, RecordReader};
+use crate::record::Value;
+use crate::summary::SummaryFields;
+
+const BLOCK_SIZE: usize = 512;
+
+/// The underlying tar stream shared between the `TarReader` and the reader of the current member
+struct Archive<'r> {
+    reader: Box<dyn Read + 'r>,
+    /// How much of the current member is left to read
+    remaining: u64,
+    /// How much padding there is after the current member
+    padding: u64,
+}
+
+impl<'r> Archive<'r> {
+    /// Read a header block, returning `None` at the end of the archive
+    fn read_block(&mut self) -> Result<Option<[u8; BLOCK_SIZE]>, EtError> {
+        let mut block = [0; BLOCK_SIZE];
+        let mut len = 0;
+        while len < BLOCK_SIZE {
+            match self.reader.read(&mut block[len..])? {
+                0 if len == 0 => return Ok(None),
+                0 => return Err("Tar archive ended in the middle of a header".into()),
+                n => len += n,
+            }
+        }
+        Ok(Some(block))
+    }
+
+    /// Skip over the rest of the current member and its padding
+    fn skip_member(&mut self) -> Result<(), EtError> {
+        let to_skip = self.remaining + self.padding;
+        let skipped = io::copy(&mut (&mut self.reader).take(to_skip), &mut io::sink())?;
+        if skipped < to_skip {
+            return Err("Tar archive ended in the middle of a file".into());
+        }
+        self.remaining = 0;
+        self.padding = 0;
+        Ok(())
+    }
+
+    /// Read the contents of a (small) metadata member, e.g. a long file name
+    fn read_member(&mut self) -> Result<Vec<u8>, EtError> {
+        let mut data = Vec::new();
+        let _ = (&mut self.reader)
+            .take(self.remaining)
+            .read_to_end(&mut data)?;
+        self.remaining -= data.len() as u64;
+        self.skip_member()?;
+        Ok(data)
+    }
+}
+
+/// A `Read` over a single member of the tar archive
+struct TarMember<'r>(Rc<RefCell<Archive<'r>>>);
+
+impl<'r> Read for TarMember<'r> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut archive = self.0.borrow_mut();
+        let len = buf
+            .len()
+            .min(usize::try_from(archive.remaining).unwrap_or(usize::MAX));
+        let amt_read = archive.reader.read(&mut buf[..len])?;
+        archive.remaining -= amt_read as u64;
+        Ok(amt_read)
+    }
+}
+
+/// Read a NUL-terminated string out of a header field
+fn header_str(field: &[u8]) -> Result<&str, EtError> {
+    let end = field.iter().position(|b| *b == 0).unwrap_or(field.len());
+    Ok(from_utf8(&field[..end])?)
+}
+
+/// Read a numeric header field; these are usually octal, but GNU tar uses big-endian binary for
+/// large values (flagged by setting the high bit of the first byte).
+fn header_number(field: &[u8]) -> Result<u64, EtError> {
+    if field[0] & 0x80 != 0 {
+        let mut number = u64::from(field[0] & 0x7F);
+        for b in &field[1..] {
+            number = number
+                .checked_mul(256)
+                .and_then(|n| n.checked_add(u64::from(*b)))
+                .ok_or("Tar header number is too large")?;
+        }
+        return Ok(number);
+    }
+    let text = header_str(field)?.trim_matches(|c| c == ' ' || c == '\0');
+    if text.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(text, 8).map_err(|_| format!("Invalid tar header number {:?}", text).into())
+}
+
+/// Find the `path` in the records of a pax extended header (e.g. `30 path=some/long/name\n`)
+fn pax_path(data: &[u8]) -> Option<String> {
+    let mut data = data;
+    while !data.is_empty() {
+        let space = data.iter().position(|b| *b == b' ')?;
+        let len: usize = from_utf8(&data[..space]).ok()?.parse().ok()?;
+        let record = data.get(space + 1..len)?.strip_suffix(b"\n")?;
+        if let Some(path) = record.strip_prefix(b"path=") {
+            return from_utf8(path).ok().map(String::from);
+        }
+        data = &data[len..];
+    }
+    None
+}
+
+/// Guess the parser for a member from its name if it can't be detected from its contents
+fn parser_from_name(name: &str) -> Option<&'static str> {
+    let ext = name.rsplit('.').next()?.to_ascii_lowercase();
+    if ext == "csv" {
+        return Some("csv");
+    }
+    FileType::from_extension(&ext)
+        .first()?
+        .to_parser_name(None)
+        .ok()
+}
+
+/// Reads each of the files in a tar archive with the parser appropriate for it and adds a
+/// `member_name` column with the name of the file each record came from.
+///
+/// All of the files in the archive must have the same columns (e.g. a batch of FASTQ or FCS
+/// files). Directories, links, and macOS resource forks (`._` files) are skipped.
+pub struct TarReader<'r> {
+    archive: Rc<RefCell<Archive<'r>>>,
+    params: BTreeMap<String, Value<'static>>,
+    headers: Vec<String>,
+    member_name: String,
+    member: Option<Box<dyn RecordReader + 'r>>,
+}
+
+impl<'r> TarReader<'r> {
+    /// Create a new `TarReader`; `params` are passed to the reader of every member.
+    ///
+    /// # Errors
+    /// If the first member of the archive can't be read, an error is returned.
+    pub fn new(rb: ReadBuffer<'r>, params: BTreeMap<String, Value<'_>>) -> Result<Self, EtError> {
+        let archive = Archive {
+            reader: rb.into_box_read(),
+            remaining: 0,
+            padding: 0,
+        };
+        let mut reader = TarReader {
+            archive: Rc::new(RefCell::new(archive)),
+            params: params
+                .into_iter()
+                .map(|(k, v)| (k, v.into_owned()))
+                .collect(),
+            headers: vec!["member_name".to_string()],
+            member_name: String::new(),
+            member: None,
+        };
+        if reader.next_member()? {
+            if let Some(member) = &reader.member {
+                reader.headers.extend(member.headers());
+            }
+        }
+        Ok(reader)
+    }
+
+    /// Start reading the next file in the archive, returning `false` if there are no more.
+    fn next_member(&mut self) -> Result<bool, EtError> {
+        self.member = None;
+        let mut long_name = None;
+        loop {
+            let mut archive = self.archive.borrow_mut();
+            archive.skip_member()?;
+            let block = match archive.read_block()? {
+                Some(block) if block.iter().any(|b| *b != 0) => block,
+                // the archive ends with (at least) two empty blocks
+                _ => return Ok(false),
+            };
+
+            let checksum = header_number(&block[148..156])?;
+            let actual: u64 = block
+                .iter()
+                .enumerate()
+                .map(|(ix, b)| u64::from(if (148..156).contains(&ix) { b' ' } else { *b }))
+                .sum();
+            if checksum != actual {
+                return Err("Invalid tar header checksum".into());
+            }
+
+            let size = header_number(&block[124..136])?;
+            archive.remaining = size;
+            archive.padding = (BLOCK_SIZE as u64 - size % BLOCK_SIZE as u64) % BLOCK_SIZE as u64;
+            match block[156] {
+                b'0' | b'\0' | b'7' => {}
+                // GNU long names are stored as a separate member before the file
+                b'L' => {
+                    let name = archive.read_member()?;
+                    long_name = Some(header_str(&name)?.to_string());
+                    continue;
+                }
+                // as are pax extended headers (which can also include the path)
+                b'x' => {
+                    let data = archive.read_member()?;
+                    long_name = pax_path(&data).or(long_name);
+                    continue;
+                }
+                _ => {
+                    long_name = None;
+                    continue;
+                }
+            }
+
+            let name = match long_name.take() {
+                Some(name) => name,
+                None => {
+                    let name = header_str(&block[..100])?;
+                    let prefix = if &block[257..262] == b"ustar" {
+                        header_str(&block[345..500])?
+                    } else {
+                        ""
+                    };
+                    if prefix.is_empty() {
+                        name.to_string()
+                    } else {
+                        format!("{}/{}", prefix, name)
+                    }
+                }
+            };
+            if name
+                .rsplit('/')
+                .next()
+                .unwrap_or_default()
+                .starts_with("._")
+            {
+                continue;
+            }
+            drop(archive);
+
+            self.member = Some(self.open_member(&name).map_err(|e| in_member(e, &name))?);
+            self.member_name = name;
+            return Ok(true);
+        }
+    }
+
+    /// Create a reader for the member that the archive is currently at
+    fn open_member(&self, name: &str) -> Result<Box<dyn RecordReader + 'r>, EtError> {
+        let size = self.archive.borrow().remaining;
+        let rb = ReadBuffer::from_reader(Box::new(TarMember(Rc::clone(&self.archive))), None)?
+            .with_total_len(size);
+        let (mut rb, _) = decompress(rb)?;
+        // members that don't look like anything (except behind a byte order mark) are read by
+        // their extension
+        let hint = match rb.sniff_filetype()?.to_parser_name(None) {
+            Err(_) if Bom::detect(rb.as_ref()).is_none() => parser_from_name(name),
+            _ => None,
+        };
+        let (rb, parser) = sniff_parser(rb, hint)?;
+        let mut params = self.params.clone();
+        let _ = params.insert("filename".to_string(), name.to_string().into());
+        let (reader, _) = _get_reader(rb, parser, params)?;
+        Ok(reader)
+    }
+}
+
+/// Note which member of the archive an error came from
+fn in_member(mut err: EtError, name: &str) -> EtError {
+    err.msg = format!("{} (in {})", err.msg, name).into();
+    err
+}
+
+impl<'r> fmt::Debug for TarReader<'r> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TarReader")
+            .field("member_name", &self.member_name)
+            .field("member", &self.member)
+            .finish()
+    }
+}
+
+impl<'r> RecordReader for TarReader<'r> {
+    fn next_record(&mut self) -> Result<Option<Vec<Value<'_>>>, EtError> {
+        loop {
+            let member = match &mut self.member {
+                Some(member) => member,
+                None => return Ok(None),
+            };
+            let member_name = &self.member_name;
+            let record: Option<Vec<Value<'static>>> = member
+                .next_record()
+                .map_err(|e| in_member(e, member_name))?
+                .map(|record| record.into_iter().map(Value::into_owned).collect());
+            let record = match record {
+                Some(record) => record,
+                None => {
+                    if !self.next_member()? {
+                        return Ok(None);
+                    }
+                    if let Some(member) = &self.member {
+                        if member.headers()[..] != self.headers[1..] {
+                            return Err(in_member(
+                                "File has different columns than the first file in the archive"
+                                    .into(),
+                                &self.member_name,
+                            ));
+                        }
+                    }
+                    continue;
+                }
+            };
+            let mut values = Vec::with_capacity(record.len() + 1);
+            values.push(Value::String(Cow::Borrowed(&self.member_name)));
+            values.extend(record);
+            return Ok(Some(values));
+        }
+    }
+
+    fn headers(&self) -> Vec<String> {
+        self.headers.clone()
+    }
+
+    /// The metadata of the file currently being read
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        self.member
+            .as_ref()
+            .map(|m| m.metadata())
+            .unwrap_or_default()
+    }
+
+    fn column_metadata(&self) -> BTreeMap<String, BTreeMap<String, Value<'_>>> {
+        self.member
+            .as_ref()
+            .map(|m| m.column_metadata())
+            .unwrap_or_default()
+    }
+
+    fn summary_fields(&self) -> SummaryFields {
+        self.member
+            .as_ref()
+            .map(|m| m.summary_fields())
+            .unwrap_or_default()
+    }
+
+    /// What the file currently being read can do, except for seeking or knowing how many
+    /// records are left (which only apply to that file)
+    fn capabilities(&self) -> Capabilities {
+        let member = self
+            .member
+            .as_ref()
+            .map(|m| m.capabilities())
+            .unwrap_or_default();
+        (member - (Capabilities::SEEKABLE | Capabilities::SIZED)) | Capabilities::MULTI_STREAM
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Build a tar archive out of (name, contents) pairs
+    fn tar(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for (name, contents) in files {
+            let mut header = [0u8; BLOCK_SIZE];
+            header[..name.len()].copy_from_slice(name.as_bytes());
+            header[100..107].copy_from_slice(b"0000644");
+            header[124..135].copy_from_slice(format!("{:011o}", contents.len()).as_bytes());
+            header[156] = b'0';
+            header[257..263].copy_from_slice(b"ustar\0");
+            header[148..156].copy_from_slice(b"        ");
+            let checksum: u64 = header.iter().map(|b| u64::from(*b)).sum();
+            header[148..155].copy_from_slice(format!("{:06o}\0", checksum).as_bytes());
+            data.extend_from_slice(&header);
+            data.extend_from_slice(contents);
+            data.resize(
+                data.len() + (BLOCK_SIZE - contents.len() % BLOCK_SIZE) % BLOCK_SIZE,
+                0,
+            );
+        }
+        data.resize(data.len() + 2 * BLOCK_SIZE, 0);
+        data
+    }
+
+    fn reader(data: Vec<u8>) -> Result<TarReader<'static>, EtError> {
+        let rb = ReadBuffer::from_reader(Box::new(Cursor::new(data)), None)?;
+        TarReader::new(rb, BTreeMap::new())
+    }
+
+    #[test]
+    fn test_tar_reader() -> Result<(), EtError> {
+        let data = tar(&[
+            ("run1.fq", b"@a\nACGT\n+\n!!!!\n@b\nTT\n+\n!!\n"),
+            ("._run2.fq", b"\x00\x05\x16\x07"),
+            ("run2.fq", b"@c\nGG\n+\n##\n"),
+        ]);
+        let mut reader = reader(data)?;
+        assert_eq!(
+            reader.headers(),
+            ["member_name", "id", "sequence", "quality"]
+        );
+        let record = reader.next_record()?.unwrap();
+        assert_eq!(record[0], "run1.fq".into());
+        assert_eq!(record[1], "a".into());
+        assert_eq!(reader.next_record()?.unwrap()[1], "b".into());
+        let record = reader.next_record()?.unwrap();
+        assert_eq!(record[0], "run2.fq".into());
+        assert_eq!(record[2], "GG".into());
+        assert!(reader.next_record()?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_gzipped_tar() -> Result<(), EtError> {
+        use crate::readers::get_reader;
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&tar(&[("a.fa", b">a\nACGT\n"), ("b.fa", b">b\nTT\n")]))?;
+        let data = encoder.finish()?;
+        let (mut reader, parser) = get_reader(&data[..], None, None)?;
+        assert_eq!(parser, "tar");
+        assert_eq!(reader.headers(), ["member_name", "id", "sequence"]);
+        let mut names = Vec::new();
+        while let Some(record) = reader.next_record()? {
+            names.push(record[0].clone().into_owned());
+        }
+        assert_eq!(names, ["a.fa".into(), "b.fa".into()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tar_by_extension() -> Result<(), EtError> {
+        let data = tar(&[("a.csv", b"x,y\n1,2\n")]);
+        let mut reader = reader(data)?;
+        assert_eq!(reader.headers(), ["member_name", "x", "y"]);
+        assert_eq!(reader.next_record()?.unwrap()[2], 2.into());
+        Ok(())
+    }
+
+    #[test]
+    fn test_bad_tars() -> Result<(), EtError> {
+        let data = tar(&[(">a.fa", b">a\nACGT\n"), ("b.fq", b"@b\nA\n+\n!\n")]);
+        let mut reader = reader(data)?;
+        assert!(reader.next_record()?.is_some());
+        let e = reader.next_record().unwrap_err();
+        assert!(e.msg.contains("(in b.fq)"));
+
+        let mut data = tar(&[("a.fa", b">a\nACGT\n")]);
+        data[0] = b'b';
+        assert!(reader_from(data).is_err());
+
+        let data = tar(&[("a.fa", b">a\nACGT\n")]);
+        assert!(reader_from(data[..600].to_vec()).is_err());
+        Ok(())
+    }
+
+    fn reader_from(data: Vec<u8>) -> Result<Vec<Vec<Value<'static>>>, EtError> {
+        let mut reader = reader(data)?;
+        let mut records = Vec::new();
+        while let Some(record) = reader.next_record()? {
+            records.push(record.into_iter().map(Value::into_owned).collect());
+        }
+        Ok(records)
+    }
+
+    #[test]
+    fn test_header_numbers() -> Result<(), EtError> {
+        assert_eq!(header_number(b"0000644\0")?, 0o644);
+        assert_eq!(header_number(b"   12 \0")?, 0o12);
+        assert_eq!(header_number(b"\x80\0\0\0\0\0\0\x01\0\0\0\0")?, 1 << 32);
+        assert!(header_number(b"9\0").is_err());
+        assert_eq!(
+            pax_path(b"19 path=a/long/nam\n").as_deref(),
+            Some("a/long/nam")
+        );
+        Ok(())
+    }
+}
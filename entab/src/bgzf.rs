@@ -0,0 +1,311 @@
+#[cfg(feature = "std")]
+use alloc::format;
+#[cfg(feature = "std")]
+use alloc::string::ToString;
+#[cfg(feature = "std")]
+use alloc::vec;
+#[cfg(feature = "std")]
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io::{self, Read, Seek, SeekFrom};
+
+#[cfg(feature = "std")]
+use flate2::read::DeflateDecoder;
+#[cfg(feature = "std")]
+use flate2::Crc;
+
+#[cfg(feature = "std")]
+use crate::EtError;
+
+/// The largest a BGZF block can be (compressed or not)
+#[cfg(feature = "std")]
+const MAX_BLOCK_SIZE: usize = 65_536;
+
+/// A position in a BGZF file: the offset of the start of a block in the compressed file (in the
+/// upper 48 bits) and an offset into the decompressed data of that block (in the lower 16).
+///
+/// These are what BAM indices point into the file with.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct VirtualOffset(pub u64);
+
+impl VirtualOffset {
+    /// Create a virtual offset pointing `offset` bytes into the block starting at `block_start`
+    #[must_use]
+    pub fn new(block_start: u64, offset: u16) -> Self {
+        VirtualOffset(block_start << 16 | u64::from(offset))
+    }
+
+    /// Where the block starts in the compressed file
+    #[must_use]
+    pub fn block_start(self) -> u64 {
+        self.0 >> 16
+    }
+
+    /// How far into the block's decompressed data this points
+    #[must_use]
+    pub fn block_offset(self) -> u16 {
+        (self.0 & 0xFFFF) as u16
+    }
+}
+
+/// Reads the decompressed data out of a BGZF ("blocked gzip") file one block at a time, keeping
+/// track of the virtual offset of the data read so far so it can jump to a virtual offset from
+/// e.g. a BAM index with `seek_virtual`.
+///
+/// BGZF files are valid (multi-member) gzip files, so they can also be read with any gzip
+/// decoder when seeking isn't needed.
+#[cfg(feature = "std")]
+pub struct BgzfReader<R> {
+    inner: R,
+    /// Where the current block starts in the compressed file
+    block_start: u64,
+    /// Where the next block starts in the compressed file
+    next_block_start: u64,
+    /// The decompressed data in the current block
+    block: Vec<u8>,
+    /// How much of the current block has been read
+    pos: usize,
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> BgzfReader<R> {
+    /// Create a new `BgzfReader` from a stream that's positioned at the start of a BGZF file.
+    pub fn new(inner: R) -> Self {
+        BgzfReader {
+            inner,
+            block_start: 0,
+            next_block_start: 0,
+            block: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    /// The virtual offset of the next byte that will be read.
+    ///
+    /// Once a block has been read entirely, this points at the start of the next one.
+    #[must_use]
+    pub fn virtual_offset(&self) -> VirtualOffset {
+        if self.pos == self.block.len() {
+            VirtualOffset::new(self.next_block_start, 0)
+        } else {
+            // blocks are never larger than 64 KiB so this always fits
+            VirtualOffset::new(self.block_start, self.pos as u16)
+        }
+    }
+
+    /// Read and decompress the next block; returns `false` at the end of the file.
+    fn read_block(&mut self) -> Result<bool, EtError> {
+        // the fixed part of the gzip header (including the length of the extra fields)
+        let mut header = [0; 12];
+        let mut len = 0;
+        while len < header.len() {
+            match self.inner.read(&mut header[len..])? {
+                0 if len == 0 => return Ok(false),
+                0 => return Err("BGZF block header is truncated".into()),
+                n => len += n,
+            }
+        }
+        if header[..4] != [0x1F, 0x8B, 0x08, 0x04] {
+            return Err(format!(
+                "Invalid BGZF block at byte {} (is the file compressed with bgzip?)",
+                self.next_block_start
+            )
+            .into());
+        }
+        let extra_len = usize::from(u16::from_le_bytes([header[10], header[11]]));
+        let mut extra = vec![0; extra_len];
+        self.inner.read_exact(&mut extra)?;
+
+        // the size of the whole block is in the `BC` extra field
+        let mut block_size = None;
+        let mut field = &extra[..];
+        while field.len() >= 4 {
+            let field_len = usize::from(u16::from_le_bytes([field[2], field[3]]));
+            let data = field
+                .get(4..4 + field_len)
+                .ok_or("BGZF extra field is truncated")?;
+            if field[..2] == *b"BC" && field_len == 2 {
+                block_size = Some(usize::from(u16::from_le_bytes([data[0], data[1]])) + 1);
+            }
+            field = &field[4 + field_len..];
+        }
+        let block_size = block_size.ok_or("BGZF block has no block size")?;
+        let data_len = block_size
+            .checked_sub(header.len() + extra_len + 8)
+            .ok_or("BGZF block size is too small")?;
+        let mut data = vec![0; data_len + 8];
+        self.inner.read_exact(&mut data)?;
+
+        let trailer = &data[data_len..];
+        let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+        let size = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]) as usize;
+        if size > MAX_BLOCK_SIZE {
+            return Err(format!("BGZF block is too large ({} bytes)", size).into());
+        }
+        self.block.clear();
+        self.block.reserve(size);
+        let _ = DeflateDecoder::new(&data[..data_len]).read_to_end(&mut self.block)?;
+        let mut actual = Crc::new();
+        actual.update(&self.block);
+        if self.block.len() != size || actual.sum() != crc {
+            return Err(format!(
+                "BGZF block at byte {} is corrupt (its checksum doesn't match)",
+                self.next_block_start
+            )
+            .into());
+        }
+        self.pos = 0;
+        self.block_start = self.next_block_start;
+        self.next_block_start += block_size as u64;
+        Ok(true)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read + Seek> BgzfReader<R> {
+    /// Jump to a virtual offset (e.g. from a BAM index).
+    ///
+    /// # Errors
+    /// If seeking fails, if there's not a valid block at the offset, or if the offset points
+    /// past the end of the block, an error is returned.
+    pub fn seek_virtual(&mut self, offset: VirtualOffset) -> Result<(), EtError> {
+        let block_start = offset.block_start();
+        if block_start != self.block_start || self.block.is_empty() {
+            let _ = self.inner.seek(SeekFrom::Start(block_start))?;
+            self.next_block_start = block_start;
+            self.block.clear();
+            self.pos = 0;
+            if !self.read_block()? && offset.block_offset() == 0 {
+                // pointing at the end of the file is fine
+                return Ok(());
+            }
+        }
+        let pos = usize::from(offset.block_offset());
+        if pos > self.block.len() {
+            return Err(format!(
+                "Virtual offset {}:{} is past the end of its BGZF block",
+                block_start, pos
+            )
+            .into());
+        }
+        self.pos = pos;
+        Ok(())
+    }
+}
+
+/// Reads never span blocks, so a read that returns less than was asked for doesn't mean the
+/// block is the last one.
+#[cfg(feature = "std")]
+impl<R: Read> Read for BgzfReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // skip over empty blocks (e.g. the one marking the end of the file)
+        while self.pos == self.block.len() {
+            let has_block = self
+                .read_block()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+            if !has_block {
+                return Ok(0);
+            }
+        }
+        let len = buf.len().min(self.block.len() - self.pos);
+        buf[..len].copy_from_slice(&self.block[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R> fmt::Debug for BgzfReader<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "<BgzfReader block={} pos={}/{}>",
+            self.block_start,
+            self.pos,
+            self.block.len()
+        )
+    }
+}
+
+/// Compress `data` into a single BGZF block (for building test files)
+#[cfg(all(test, feature = "std"))]
+pub(crate) fn compress_block(data: &[u8]) -> Vec<u8> {
+    use flate2::write::DeflateEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    let compressed = encoder.finish().unwrap();
+    let mut crc = Crc::new();
+    crc.update(data);
+
+    let mut block = vec![
+        0x1F, 0x8B, 0x08, 0x04, 0, 0, 0, 0, 0, 0xFF, 6, 0, b'B', b'C', 2, 0,
+    ];
+    let block_size = (block.len() + 2 + compressed.len() + 8 - 1) as u16;
+    block.extend_from_slice(&block_size.to_le_bytes());
+    block.extend_from_slice(&compressed);
+    block.extend_from_slice(&crc.sum().to_le_bytes());
+    block.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    block
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_virtual_offset() {
+        let offset = VirtualOffset::new(253, 404);
+        assert_eq!(offset.block_start(), 253);
+        assert_eq!(offset.block_offset(), 404);
+        assert!(offset < VirtualOffset::new(254, 0));
+    }
+
+    #[test]
+    fn test_bgzf_reader() -> Result<(), EtError> {
+        let first = compress_block(b"hello ");
+        let mut data = first.clone();
+        data.extend(compress_block(b"world"));
+        data.extend(compress_block(b""));
+
+        let mut reader = BgzfReader::new(Cursor::new(data.clone()));
+        let mut text = String::new();
+        let _ = reader.read_to_string(&mut text)?;
+        assert_eq!(text, "hello world");
+        assert_eq!(reader.virtual_offset().block_offset(), 0);
+
+        let mut reader = BgzfReader::new(Cursor::new(data));
+        let mut buf = [0; 3];
+        reader.read_exact(&mut buf)?;
+        assert_eq!(reader.virtual_offset(), VirtualOffset::new(0, 3));
+        reader.seek_virtual(VirtualOffset::new(first.len() as u64, 2))?;
+        let mut text = String::new();
+        let _ = reader.read_to_string(&mut text)?;
+        assert_eq!(text, "rld");
+        // and back into the first block
+        reader.seek_virtual(VirtualOffset::new(0, 4))?;
+        reader.read_exact(&mut buf)?;
+        assert_eq!(&buf, b"o w");
+
+        assert!(reader.seek_virtual(VirtualOffset::new(0, 10)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_bgzf_bad_blocks() {
+        let mut block = compress_block(b"hello");
+        let mut reader = BgzfReader::new(Cursor::new(b"not gzipped".to_vec()));
+        assert!(reader.read(&mut [0; 5]).is_err());
+
+        // corrupt the checksum
+        let crc_pos = block.len() - 8;
+        block[crc_pos] ^= 1;
+        let mut reader = BgzfReader::new(Cursor::new(block));
+        assert!(reader.read(&mut [0; 5]).is_err());
+    }
+}
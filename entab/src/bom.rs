@@ -0,0 +1,329 @@
+#[cfg(feature = "std")]
+use alloc::boxed::Box;
+#[cfg(feature = "std")]
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io::{self, Read};
+
+use crate::buffer::ReadBuffer;
+use crate::EtError;
+
+/// The byte order marks text files can start with (mostly from Windows software, which often
+/// writes them at the start of UTF-8 and UTF-16 files)
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Bom {
+    /// `EF BB BF`
+    Utf8,
+    /// `FF FE`
+    Utf16Le,
+    /// `FE FF`
+    Utf16Be,
+}
+
+impl Bom {
+    /// The byte order mark at the start of `data` (if there is one)
+    #[must_use]
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        if data.starts_with(b"\xEF\xBB\xBF") {
+            Some(Bom::Utf8)
+        } else if data.starts_with(b"\xFF\xFE") {
+            Some(Bom::Utf16Le)
+        } else if data.starts_with(b"\xFE\xFF") {
+            Some(Bom::Utf16Be)
+        } else {
+            None
+        }
+    }
+
+    /// The name of the encoding the mark is for (e.g. `UTF-16LE`)
+    #[must_use]
+    pub fn encoding(self) -> &'static str {
+        match self {
+            Bom::Utf8 => "UTF-8",
+            Bom::Utf16Le => "UTF-16LE",
+            Bom::Utf16Be => "UTF-16BE",
+        }
+    }
+}
+
+/// The parsers that read text, so a byte order mark at the start of their data is the text's
+/// encoding and not part of the data (`depth` and `kmers` read SAM and FASTA/FASTQ files).
+const TEXT_PARSERS: &[&str] = &[
+    "csv",
+    "delimited",
+    "depth",
+    "elementar_irms",
+    "fasta",
+    "fastq",
+    "gml",
+    "gpx",
+    "graphml",
+    "kmers",
+    "mztab",
+    "mzxml",
+    "pepxml",
+    "sam",
+    "sensor_log",
+    "sercon_irms",
+    "tsv",
+];
+
+/// Does `parser` read text (so any byte order mark in front of its data should be stripped)?
+///
+/// Binary formats are read as is, since their data can start with the same bytes as a mark.
+#[must_use]
+pub fn is_text_parser(parser: &str) -> bool {
+    TEXT_PARSERS.contains(&parser)
+}
+
+/// Skip over the byte order mark at the start of `rb` (if there is one) so the parsers (and file
+/// type detection) see the text directly; UTF-16 text is converted into UTF-8 as it's read
+/// (replacing any invalid UTF-16 with U+FFFD). The mark found is kept in `rb.bom`.
+///
+/// This should be called before anything is read out of `rb`.
+///
+/// # Errors
+/// If the data is UTF-16, but entab was compiled without `std` (so it can't be converted) an
+/// error is returned.
+pub fn strip_bom(mut rb: ReadBuffer<'_>) -> Result<ReadBuffer<'_>, EtError> {
+    if rb.consumed != 0 || rb.reader_pos != 0 {
+        return Ok(rb);
+    }
+    let bom = match Bom::detect(rb.as_ref()) {
+        Some(bom) => bom,
+        None => return Ok(rb),
+    };
+    let big_endian = match bom {
+        Bom::Utf8 => {
+            rb.consumed = 3;
+            rb.bom = Some(bom);
+            return Ok(rb);
+        }
+        Bom::Utf16Le => false,
+        Bom::Utf16Be => true,
+    };
+    #[cfg(feature = "std")]
+    {
+        let reader = Utf16Reader::new(rb.into_box_read(), big_endian);
+        // the mark itself is converted too, so skip over the UTF-8 version of it
+        let mut rb = ReadBuffer::from_reader(Box::new(reader), None)?;
+        if rb.as_ref().starts_with(b"\xEF\xBB\xBF") {
+            rb.consumed = 3;
+        }
+        rb.bom = Some(bom);
+        Ok(rb)
+    }
+    #[cfg(not(feature = "std"))]
+    {
+        let _ = big_endian;
+        Err("entab was not compiled with support for UTF-16 text".into())
+    }
+}
+
+/// Converts a stream of UTF-16 text into UTF-8
+#[cfg(feature = "std")]
+struct Utf16Reader<R> {
+    reader: R,
+    big_endian: bool,
+    /// Bytes that have been read, but not converted yet (an odd byte or a leading surrogate
+    /// whose pair hasn't been read yet)
+    pending: Vec<u8>,
+    /// Converted text that hasn't been returned yet
+    output: Vec<u8>,
+    out_pos: usize,
+    eof: bool,
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> Utf16Reader<R> {
+    fn new(reader: R, big_endian: bool) -> Self {
+        Utf16Reader {
+            reader,
+            big_endian,
+            pending: Vec::new(),
+            output: Vec::new(),
+            out_pos: 0,
+            eof: false,
+        }
+    }
+
+    /// Read another chunk of the input and convert as much of it as possible
+    fn fill(&mut self) -> io::Result<()> {
+        let start = self.pending.len();
+        self.pending.resize(start + 8192, 0);
+        let amt_read = self.reader.read(&mut self.pending[start..])?;
+        self.pending.truncate(start + amt_read);
+        if amt_read == 0 {
+            self.eof = true;
+        }
+
+        let mut n_units = self.pending.len() / 2;
+        // keep a leading surrogate back until we know what comes after it
+        if !self.eof && n_units > 0 {
+            let ix = 2 * (n_units - 1);
+            let last = to_unit([self.pending[ix], self.pending[ix + 1]], self.big_endian);
+            if (0xD800..0xDC00).contains(&last) {
+                n_units -= 1;
+            }
+        }
+        let big_endian = self.big_endian;
+        let units = self.pending[..2 * n_units]
+            .chunks_exact(2)
+            .map(|c| to_unit([c[0], c[1]], big_endian));
+        self.output.clear();
+        self.out_pos = 0;
+        let mut char_buf = [0; 4];
+        for c in char::decode_utf16(units) {
+            let c = c.unwrap_or(char::REPLACEMENT_CHARACTER);
+            self.output
+                .extend_from_slice(c.encode_utf8(&mut char_buf).as_bytes());
+        }
+        drop(self.pending.drain(..2 * n_units));
+        if self.eof && !self.pending.is_empty() {
+            // a stray byte at the end of the data
+            self.pending.clear();
+            self.output.extend_from_slice(
+                char::REPLACEMENT_CHARACTER
+                    .encode_utf8(&mut char_buf)
+                    .as_bytes(),
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+fn to_unit(bytes: [u8; 2], big_endian: bool) -> u16 {
+    if big_endian {
+        u16::from_be_bytes(bytes)
+    } else {
+        u16::from_le_bytes(bytes)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> Read for Utf16Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.out_pos == self.output.len() {
+            if self.eof {
+                return Ok(0);
+            }
+            self.fill()?;
+        }
+        let len = buf.len().min(self.output.len() - self.out_pos);
+        buf[..len].copy_from_slice(&self.output[self.out_pos..self.out_pos + len]);
+        self.out_pos += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "std")]
+    use crate::readers::{get_reader, sniff_parser};
+    #[cfg(feature = "std")]
+    use crate::record::Value;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(Bom::detect(b"\xEF\xBB\xBF>seq"), Some(Bom::Utf8));
+        assert_eq!(Bom::detect(b"\xFF\xFEa\x00"), Some(Bom::Utf16Le));
+        assert_eq!(Bom::detect(b"\xFE\xFF\x00a"), Some(Bom::Utf16Be));
+        assert_eq!(Bom::detect(b">seq"), None);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_text_parsers() {
+        for parser in TEXT_PARSERS {
+            assert!(
+                crate::build_info::is_parser(parser),
+                "{} isn't a parser",
+                parser
+            );
+        }
+        assert!(is_text_parser("fasta"));
+        assert!(!is_text_parser("flow"));
+    }
+
+    #[test]
+    fn test_strip_utf8_bom() -> Result<(), EtError> {
+        let rb = strip_bom(ReadBuffer::from(&b"\xEF\xBB\xBF>id\nACGT\n"[..]))?;
+        assert_eq!(rb.bom, Some(Bom::Utf8));
+        assert_eq!(&rb.as_ref()[rb.consumed..], b">id\nACGT\n");
+        Ok(())
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_utf16_reader() -> Result<(), EtError> {
+        let text = "a\tb\n\u{e9}\t\u{1F600}\n";
+        for big_endian in [false, true] {
+            let data: Vec<u8> = text
+                .encode_utf16()
+                .flat_map(|u| {
+                    if big_endian {
+                        u.to_be_bytes()
+                    } else {
+                        u.to_le_bytes()
+                    }
+                })
+                .collect();
+            let mut out = Vec::new();
+            let mut reader = Utf16Reader::new(&data[..], big_endian);
+            let _ = reader.read_to_end(&mut out)?;
+            assert_eq!(out, text.as_bytes());
+
+            // reading a byte at a time splits up the surrogate pair
+            out.clear();
+            let mut reader = Utf16Reader::new(SlowReader(&data[..]), big_endian);
+            let _ = reader.read_to_end(&mut out)?;
+            assert_eq!(out, text.as_bytes());
+        }
+        Ok(())
+    }
+
+    /// Returns a single byte from each read
+    #[cfg(feature = "std")]
+    struct SlowReader<R>(R);
+
+    #[cfg(feature = "std")]
+    impl<R: Read> Read for SlowReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(1);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_bom_files() -> Result<(), EtError> {
+        // a UTF-8 BOM in front of a FASTA file shouldn't stop it from being detected
+        let (mut reader, parser) = get_reader(&b"\xEF\xBB\xBF>id\nACGT\n"[..], None, None)?;
+        assert_eq!(parser, "fasta");
+        assert_eq!(reader.metadata()["text_encoding"], "UTF-8".into());
+        assert_eq!(reader.next_record()?.unwrap()[0], "id".into());
+
+        // UTF-16 TSVs are read as if they were UTF-8
+        let data: Vec<u8> = "\u{feff}name\tvalue\nJos\u{e9}\t2\n"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        let (mut reader, _) = get_reader(&data[..], Some("tsv"), None)?;
+        assert_eq!(reader.headers(), ["name", "value"]);
+        assert_eq!(reader.metadata()["text_encoding"], "UTF-16LE".into());
+        assert_eq!(
+            reader.next_record()?.unwrap(),
+            [Value::from("Jos\u{e9}"), Value::Integer(2)]
+        );
+        assert!(reader.next_record()?.is_none());
+
+        // but binary data that happens to start with the same bytes is left alone
+        let (rb, parser) = sniff_parser(ReadBuffer::from(&b"\xFF\xFE\x01\x02"[..]), Some("flow"))?;
+        assert_eq!(parser, "flow");
+        assert_eq!(rb.bom, None);
+        assert_eq!(&rb.as_ref()[rb.consumed..], b"\xFF\xFE\x01\x02");
+        Ok(())
+    }
+}
@@ -1,7 +1,7 @@
 use alloc::borrow::Cow;
 #[cfg(feature = "std")]
 use alloc::boxed::Box;
-#[cfg(feature = "std")]
+use alloc::format;
 use core::convert::TryFrom;
 use core::convert::{AsRef, From};
 #[cfg(feature = "std")]
@@ -11,15 +11,28 @@ use core::ptr;
 #[cfg(feature = "std")]
 use std::fs::File;
 #[cfg(feature = "std")]
-use std::io::{Cursor, Read};
+use std::io::{self, Cursor, Read};
 
-use crate::filetype::FileType;
+use crate::bom::Bom;
+#[cfg(feature = "std")]
+use crate::cancel::CancelToken;
+use crate::filetype::{FileType, MAGIC_PREFIX_LEN};
 use crate::parsers::FromSlice;
 use crate::EtError;
 
 /// Default buffer size
 pub const BUFFER_SIZE: usize = 10_000;
 
+/// The most data a parser can ask to have in the buffer at once (see `EtError::needed`)
+pub const MAX_RESERVATION: usize = 1 << 30;
+
+/// How many records (of the average size seen so far) each refill tries to make room for
+pub const PREFETCH_RECORDS: usize = 16;
+
+/// The largest the buffer will grow to make room for prefetched records (parsers can still ask
+/// for more than this, up to `MAX_RESERVATION`)
+pub const MAX_PREFETCH: usize = 1 << 26;
+
 /// Buffers Read to provide something that can be used for parsing
 pub struct ReadBuffer<'r> {
     #[cfg(feature = "std")]
@@ -31,10 +44,21 @@ pub struct ReadBuffer<'r> {
     pub record_pos: u64,
     /// The amount of this buffer that's been marked as used
     pub consumed: usize,
+    /// How many bytes the reader will return in total, if that's known ahead of time (e.g. from
+    /// a file's metadata or a Content-Length header)
+    pub total_len: Option<u64>,
+    /// The byte order mark the data started with, if it had one (see `strip_bom`)
+    pub bom: Option<Bom>,
+    /// A moving average of the bytes consumed by each record (used to size refills)
+    pub(crate) avg_record_len: usize,
     /// Is this the last chunk before EOF?
     pub eof: bool,
     /// After the parser has had a chance to run through eof, then this will be set to end parsing.
     pub end: bool,
+    /// Checked before each read from the reader so a single record that needs a lot of data
+    /// (or data that's slow to arrive) can still be cancelled
+    #[cfg(feature = "std")]
+    cancel_token: Option<CancelToken>,
 }
 
 impl<'r> ReadBuffer<'r> {
@@ -56,32 +80,114 @@ impl<'r> ReadBuffer<'r> {
             reader_pos: 0,
             record_pos: 0,
             consumed: 0,
+            total_len: None,
+            bom: None,
+            avg_record_len: 0,
             eof: false,
             end: false,
+            cancel_token: None,
         })
     }
 
+    /// Set how many bytes the reader is expected to return in total (e.g. from a file's
+    /// metadata). This is used to report progress and makes a reader that stops short of it an
+    /// error instead of a clean end of the data, but it's only a hint: reading always continues
+    /// until the reader runs out (files in `/proc` report a length of 0, for example).
+    #[must_use]
+    pub fn with_total_len(mut self, total_len: u64) -> Self {
+        self.total_len = Some(total_len);
+        self
+    }
+
+    /// Stop refilling with a `Cancelled` error once `token` is cancelled
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn with_cancel_token(mut self, token: CancelToken) -> Self {
+        self.cancel_token = Some(token);
+        self
+    }
+
+    /// The fraction of the data that's been parsed so far, if its total length is known
+    /// (e.g. for showing progress)
+    #[must_use]
+    pub fn fraction_read(&self) -> Option<f64> {
+        #[allow(clippy::cast_precision_loss)]
+        self.total_len.map(|total| {
+            if total == 0 {
+                1.
+            } else {
+                // the total is only a hint, so there may be more data than it says
+                ((self.reader_pos + self.consumed as u64) as f64 / total as f64).min(1.)
+            }
+        })
+    }
+
+    /// A moving average of how many bytes each record has taken up in the data so far; refills
+    /// make room for `PREFETCH_RECORDS` records of this size so formats with large records (e.g.
+    /// long reads or profile mass spectra) need fewer reads.
+    #[must_use]
+    pub fn avg_record_len(&self) -> usize {
+        self.avg_record_len
+    }
+
+    /// The data in the buffer that hasn't been parsed yet
+    #[must_use]
+    pub fn unparsed(&self) -> &[u8] {
+        &self.buffer[self.consumed..]
+    }
+
+    /// Update the average record length with a record that took up `len` bytes
+    fn record_parsed(&mut self, len: usize) {
+        self.record_pos += 1;
+        self.avg_record_len = if self.avg_record_len == 0 {
+            len
+        } else {
+            // weight the newest record by 1/8 so the average follows changes in record size
+            (self.avg_record_len.saturating_mul(7)).saturating_add(len) / 8
+        };
+    }
+
     /// Given a `ReadBuffer`, guess what kind of file it is.
     ///
     /// # Errors
     /// If an error reading data from the `reader` occurs, an error will be returned.
     pub fn sniff_filetype(&mut self) -> Result<FileType, EtError> {
-        // try to get more if the buffer is *really* short
-        if self.buffer.len() < 8 && !self.eof {
-            let _ = self.refill()?;
+        // make sure there's enough to compare against every prefix in the magic table (the
+        // first read from e.g. a pipe can be shorter)
+        if self.buffer.len() - self.consumed < MAGIC_PREFIX_LEN && !self.eof {
+            let _ = self.refill(MAGIC_PREFIX_LEN)?;
         }
-        Ok(FileType::from_magic(&self.buffer))
+        Ok(FileType::from_magic(&self.buffer[self.consumed..]))
     }
 
-    /// Refill the buffer from the reader.
+    /// Is there nothing left to parse (e.g. because the file or stream was empty)?
+    ///
+    /// # Errors
+    /// If an error reading data from the `reader` occurs, an error will be returned.
+    pub fn is_exhausted(&mut self) -> Result<bool, EtError> {
+        if self.consumed == self.buffer.len() && !self.eof {
+            let _ = self.refill(0)?;
+        }
+        Ok(self.eof && self.consumed == self.buffer.len())
+    }
+
+    /// Refill the buffer from the reader, reading until there are at least `min_len` bytes after
+    /// the consumed part of the buffer (or the reader runs out).
     ///
     /// # Errors
     /// This will fail if there's an error retrieving data from the reader.
     #[cfg(feature = "std")]
-    fn refill(&mut self) -> Result<bool, EtError> {
+    fn refill(&mut self, min_len: usize) -> Result<bool, EtError> {
         if self.eof {
             return Ok(false);
         }
+        if min_len > MAX_RESERVATION {
+            return Err(EtError::from(format!(
+                "Parser needs {} bytes in the buffer, more than the maximum of {}",
+                min_len, MAX_RESERVATION
+            ))
+            .add_context_from_readbuffer(self));
+        }
 
         // pull the buffer out; if self.buffer's Borrowed then eof should
         // always be true above and we shouldn't hit this
@@ -89,17 +195,39 @@ impl<'r> ReadBuffer<'r> {
         swap(&mut self.buffer, &mut tmp_buffer);
         let mut buffer = tmp_buffer.into_owned();
 
+        // if we haven't read anything, but we want more data expand the buffer (based on how
+        // much is buffered so refills that fail and are retried don't keep growing it)
+        let mut additional = if self.consumed == 0 {
+            2 * buffer.len()
+        } else {
+            0
+        };
+        // and make sure there's room for everything the parser asked for (and for a few
+        // more records if they've been large)
+        let prefetch = self
+            .avg_record_len
+            .saturating_mul(PREFETCH_RECORDS)
+            .min(MAX_PREFETCH);
+        let min_capacity = min_len.max(prefetch);
+        additional = additional.max(min_capacity.saturating_sub(buffer.len()));
+        // parsers that need a whole file in memory can be given one that's bigger than can be
+        // allocated (especially on 32-bit and WASM targets) so error instead of aborting
+        if buffer.try_reserve(additional).is_err() {
+            let n_buffered = buffer.len();
+            self.buffer = Cow::Owned(buffer);
+            return Err(EtError::from(format!(
+                "Could not allocate room for {} more bytes of data ({} are buffered already)",
+                additional, n_buffered
+            ))
+            .add_context_from_readbuffer(self));
+        }
+        let capacity = buffer.capacity();
+
         // track how much data was in the reader before the data in the buffer
         self.reader_pos += self.consumed as u64;
 
-        let mut capacity = buffer.capacity();
-        // if we haven't read anything, but we want more data expand the buffer
-        if self.consumed == 0 {
-            buffer.reserve(2 * capacity);
-            capacity = buffer.capacity();
-        };
-
         let len = buffer.len() - self.consumed;
+
         unsafe {
             // copy the old data to the front of the buffer
             let new_ptr = buffer.as_mut_ptr();
@@ -109,23 +237,51 @@ impl<'r> ReadBuffer<'r> {
             // resize the buffer in prep to read in new data
             buffer.set_len(capacity);
         }
-        let amt_read = self
-            .reader
-            .read(&mut buffer[len..])
-            .map_err(|e| EtError::from(e).add_context_from_readbuffer(self))?;
-        buffer.truncate(len + amt_read);
+        let mut filled = len;
+        loop {
+            if let Some(Err(e)) = self.cancel_token.as_ref().map(CancelToken::check) {
+                buffer.truncate(filled);
+                self.consumed = 0;
+                self.buffer = Cow::Owned(buffer);
+                return Err(e.add_context_from_readbuffer(self));
+            }
+            let amt_read = match self.reader.read(&mut buffer[filled..]) {
+                Ok(amt_read) => amt_read,
+                Err(e) => {
+                    buffer.truncate(filled);
+                    self.consumed = 0;
+                    self.buffer = Cow::Owned(buffer);
+                    return Err(EtError::from(e).add_context_from_readbuffer(self));
+                }
+            };
+            filled += amt_read;
+            let pos = self.reader_pos + filled as u64;
+            match self.total_len {
+                Some(total) if amt_read == 0 && pos < total => {
+                    buffer.truncate(filled);
+                    self.consumed = 0;
+                    self.buffer = Cow::Owned(buffer);
+                    return Err(EtError::from(format!("Expected {} bytes of data", total))
+                        .truncated(filled, pos, self.record_pos)
+                        .add_context_from_readbuffer(self));
+                }
+                _ if amt_read == 0 => self.eof = true,
+                _ => {}
+            }
+            if self.eof || filled >= min_len {
+                break;
+            }
+        }
+        buffer.truncate(filled);
         self.consumed = 0;
         swap(&mut Cow::Owned(buffer), &mut self.buffer);
-        if amt_read == 0 {
-            self.eof = true;
-        }
 
         Ok(true)
     }
 
     /// Refill implementation for no_std
     #[cfg(not(feature = "std"))]
-    fn refill(&mut self) -> Result<bool, EtError> {
+    fn refill(&mut self, _min_len: usize) -> Result<bool, EtError> {
         if self.eof {
             return Ok(false);
         }
@@ -140,6 +296,84 @@ impl<'r> ReadBuffer<'r> {
         Box::new(Cursor::new(self.buffer).chain(self.reader))
     }
 
+    /// Convert an "incomplete" error from a parser that started at `start` in the buffer into a
+    /// `Truncated` error (for when there's no more data to read).
+    fn truncated(&self, err: EtError, start: usize) -> EtError {
+        // if the parser wanted more than the known length, the data ends there instead
+        let end = self.reader_pos + self.buffer.len() as u64;
+        let end = self.total_len.map_or(end, |total| end.max(total));
+        err.truncated(self.buffer.len() - start, end, self.record_pos)
+            .add_context_from_readbuffer(self)
+    }
+
+    /// Fill the positional error information for an error from `get`ting the record that was
+    /// just parsed; the error is placed at the start of that record (at `start` in the buffer)
+    /// instead of at its end and it's counted as the record that failed instead of a read one.
+    fn get_error(&self, err: EtError, start: usize) -> EtError {
+        err.add_context(
+            &self.buffer,
+            start,
+            self.record_pos.saturating_sub(1),
+            self.reader_pos,
+        )
+    }
+
+    /// Skip over the next `n_bytes` of data (holding `n_records` records) without parsing
+    /// them; anything past the end of the buffer is read and thrown away without buffering it.
+    ///
+    /// # Errors
+    /// If the data ends before `n_bytes` or there's an error reading it, an error is returned.
+    pub fn skip(&mut self, n_bytes: u64, n_records: usize) -> Result<(), EtError> {
+        let available = self.buffer.len() - self.consumed;
+        match usize::try_from(n_bytes) {
+            Ok(n_bytes) if n_bytes <= available => self.consumed += n_bytes,
+            _ => self.discard(n_bytes - available as u64)?,
+        }
+        self.record_pos += n_records as u64;
+        Ok(())
+    }
+
+    /// Throw away the rest of the buffer and then `len` more bytes from the reader.
+    fn discard(&mut self, len: u64) -> Result<(), EtError> {
+        let available = self.buffer.len() - self.consumed;
+        #[cfg(feature = "std")]
+        if !self.eof {
+            self.reader_pos += self.buffer.len() as u64;
+            self.consumed = 0;
+            // keep the allocation around for the next refill
+            let mut tmp_buffer = Cow::Borrowed(&b""[..]);
+            swap(&mut self.buffer, &mut tmp_buffer);
+            let mut buffer = tmp_buffer.into_owned();
+            buffer.clear();
+            self.buffer = Cow::Owned(buffer);
+
+            let n_read = io::copy(&mut (&mut self.reader).take(len), &mut io::sink())
+                .map_err(|e| EtError::from(e).add_context_from_readbuffer(self))?;
+            self.reader_pos += n_read;
+            if n_read == len {
+                return Ok(());
+            }
+            self.eof = true;
+            return Err(EtError::from(format!(
+                "Data ended {} bytes before the end of the skipped records",
+                len - n_read
+            ))
+            .truncated(0, self.reader_pos, self.record_pos)
+            .add_context_from_readbuffer(self));
+        }
+        self.consumed = self.buffer.len();
+        Err(EtError::from(format!(
+            "Data ended {} bytes before the end of the skipped records",
+            len
+        ))
+        .truncated(
+            available,
+            self.reader_pos + self.buffer.len() as u64,
+            self.record_pos,
+        )
+        .add_context_from_readbuffer(self))
+    }
+
     /// Uses the state to extract a record from the buffer.
     ///
     /// # Errors
@@ -164,20 +398,27 @@ impl<'r> ReadBuffer<'r> {
                 Ok(true) => break,
                 Ok(false) => return Ok(None),
                 Err(e) => {
-                    if !e.incomplete || self.eof {
+                    if !e.incomplete {
                         return Err(e.add_context_from_readbuffer(self));
                     }
-                    if !self.refill()? {
+                    // the parser may have consumed some of the buffer before asking for more
+                    let min_len = e
+                        .needed
+                        .map_or(0, |n| (consumed + n).saturating_sub(self.consumed));
+                    if self.eof {
+                        return Err(self.truncated(e, consumed));
+                    }
+                    if !self.refill(min_len)? {
                         return Ok(None);
                     }
                     consumed = 0;
                 }
             }
         }
-        self.record_pos += 1;
+        self.record_parsed(self.consumed - consumed);
         let mut record = T::default();
         T::get(&mut record, &self.buffer[consumed..self.consumed], state)
-            .map_err(|e| e.add_context_from_readbuffer(self))?;
+            .map_err(|e| self.get_error(e, consumed))?;
         Ok(Some(record))
     }
 
@@ -217,10 +458,17 @@ impl<'r> ReadBuffer<'r> {
                 Ok(true) => break,
                 Ok(false) => return Ok(false),
                 Err(e) => {
-                    if !e.incomplete || self.eof {
+                    if !e.incomplete {
                         return Err(e.add_context_from_readbuffer(self));
                     }
-                    if !self.refill()? {
+                    // the parser may have consumed some of the buffer before asking for more
+                    let min_len = e
+                        .needed
+                        .map_or(0, |n| (consumed + n).saturating_sub(self.consumed));
+                    if self.eof {
+                        return Err(self.truncated(e, consumed));
+                    }
+                    if !self.refill(min_len)? {
                         return Ok(false);
                     }
                     consumed = 0;
@@ -234,9 +482,9 @@ impl<'r> ReadBuffer<'r> {
                 &'s mut <T as FromSlice<'b, 's>>::State,
             >(state)
         };
-        self.record_pos += 1;
+        self.record_parsed(self.consumed - consumed);
         T::get(record, &buffer[consumed..self.consumed], cur_state)
-            .map_err(|e| e.add_context_from_readbuffer(self))?;
+            .map_err(|e| self.get_error(e, consumed))?;
         Ok(true)
     }
 }
@@ -263,8 +511,13 @@ impl<'r> ::core::default::Default for ReadBuffer<'r> {
             reader_pos: 0,
             record_pos: 0,
             consumed: 0,
+            total_len: None,
+            bom: None,
+            avg_record_len: 0,
             eof: true,
             end: false,
+            #[cfg(feature = "std")]
+            cancel_token: None,
         }
     }
 }
@@ -283,7 +536,17 @@ impl<'r> TryFrom<File> for ReadBuffer<'r> {
     type Error = EtError;
 
     fn try_from(reader: File) -> Result<Self, Self::Error> {
-        ReadBuffer::from_reader(Box::new(reader), None)
+        // FIFOs and devices don't have a meaningful length
+        let total_len = reader
+            .metadata()
+            .ok()
+            .filter(std::fs::Metadata::is_file)
+            .map(|m| m.len());
+        let rb = ReadBuffer::from_reader(Box::new(reader), None)?;
+        Ok(match total_len {
+            Some(total_len) => rb.with_total_len(total_len),
+            None => rb,
+        })
     }
 }
 
@@ -296,8 +559,13 @@ impl<'r> From<&'r [u8]> for ReadBuffer<'r> {
             reader_pos: 0,
             record_pos: 0,
             consumed: 0,
+            total_len: Some(buffer.len() as u64),
+            bom: None,
+            avg_record_len: 0,
             eof: true,
             end: false,
+            #[cfg(feature = "std")]
+            cancel_token: None,
         }
     }
 }
@@ -315,7 +583,9 @@ mod test {
     #[cfg(feature = "std")]
     use std::io::Cursor;
 
+    use crate::error::EtErrorKind;
     use crate::parsers::common::{NewLine, SeekPattern};
+    use crate::parsers::Endian;
     use crate::EtError;
 
     use super::ReadBuffer;
@@ -333,7 +603,7 @@ mod test {
 
         assert_eq!(&rb.as_ref()[rb.consumed..], b"123");
         rb.consumed += 3;
-        assert!(rb.refill()?);
+        assert!(rb.refill(0)?);
         assert_eq!(&rb.as_ref()[rb.consumed..], b"456");
         Ok(())
     }
@@ -397,11 +667,29 @@ mod test {
         let reader = Box::new(Cursor::new(b"1234567890"));
         let mut rb = ReadBuffer::from_reader(reader, Some(2))?;
         assert!(rb.as_ref().len() == 2);
-        let _ = rb.refill();
+        let _ = rb.refill(0);
         assert!(rb.as_ref().len() >= 4);
         Ok(())
     }
 
+    #[test]
+    fn test_truncated() {
+        let mut rb = ReadBuffer::from(&b"\x01\x00\x00\x00\x02\x00"[..]);
+        assert_eq!(rb.next::<u32>(&mut Endian::Little).unwrap(), Some(1));
+        let err = rb.next::<u32>(&mut Endian::Little).unwrap_err();
+        assert_eq!(
+            err.kind,
+            EtErrorKind::Truncated {
+                needed: None,
+                available: 2
+            }
+        );
+        assert!(!err.incomplete);
+        assert!(err
+            .msg
+            .starts_with("File appears truncated at byte 6, record 1:"));
+    }
+
     #[test]
     fn test_next_into() -> Result<(), EtError> {
         let mut rb = ReadBuffer::from(&b"1\n2\n3"[..]);
@@ -420,4 +708,111 @@ mod test {
         assert_eq!(ix, 3);
         Ok(())
     }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_total_len() -> Result<(), EtError> {
+        let reader = Box::new(Cursor::new(b"1\n2\n3"));
+        let mut rb = ReadBuffer::from_reader(reader, Some(2))?.with_total_len(5);
+        assert_eq!(rb.fraction_read(), Some(0.));
+        let mut ix = 0;
+        while let Some(NewLine(_)) = rb.next(&mut 0)? {
+            ix += 1;
+        }
+        assert_eq!(ix, 3);
+        assert_eq!(rb.fraction_read(), Some(1.));
+
+        // the length's only a hint so data past it is still read (e.g. `/proc` files say
+        // they're empty)
+        let reader = Box::new(Cursor::new(b"1\n2\n3"));
+        let mut rb = ReadBuffer::from_reader(reader, Some(2))?.with_total_len(0);
+        let mut ix = 0;
+        while let Some(NewLine(_)) = rb.next(&mut 0)? {
+            ix += 1;
+        }
+        assert_eq!(ix, 3);
+        assert_eq!(rb.fraction_read(), Some(1.));
+
+        // and a reader that stops short of the total length is an error instead of the end
+        let reader = Box::new(Cursor::new(b"1\n2\n3"));
+        let mut rb = ReadBuffer::from_reader(reader, Some(2))?.with_total_len(10);
+        let err = loop {
+            match rb.next::<NewLine>(&mut 0) {
+                Ok(Some(_)) => {}
+                Ok(None) => panic!("Reader ended without an error"),
+                Err(e) => break e,
+            }
+        };
+        assert!(matches!(err.kind, EtErrorKind::Truncated { .. }));
+        assert!(err.msg.contains("Expected 10 bytes of data"));
+        Ok(())
+    }
+
+    /// Counts how many times it's read from
+    #[cfg(feature = "std")]
+    struct CountReads<'a>(Cursor<&'a [u8]>, &'a core::cell::Cell<usize>);
+
+    #[cfg(feature = "std")]
+    impl<'a> std::io::Read for CountReads<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.1.set(self.1.get() + 1);
+            self.0.read(buf)
+        }
+    }
+
+    /// Only returns a couple bytes at a time (like a slow pipe)
+    #[cfg(feature = "std")]
+    struct Trickle<'a>(&'a [u8]);
+
+    #[cfg(feature = "std")]
+    impl<'a> std::io::Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = buf.len().min(self.0.len()).min(2);
+            buf[..len].copy_from_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+            Ok(len)
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_sniff_short_reads() -> Result<(), EtError> {
+        let reader = Box::new(Trickle(b"@HD\tVN:1.6\n"));
+        let mut rb = ReadBuffer::from_reader(reader, None)?;
+        assert_eq!(rb.sniff_filetype()?, crate::filetype::FileType::Sam);
+        let reader = Box::new(Trickle(b"\x89PNG\r\n\x1A\n"));
+        let mut rb = ReadBuffer::from_reader(reader, None)?;
+        assert_eq!(rb.sniff_filetype()?, crate::filetype::FileType::Png);
+        Ok(())
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_prefetch() -> Result<(), EtError> {
+        // large records make the buffer read more at once
+        let mut line = alloc::vec![b'A'; 4999];
+        line.push(b'\n');
+        let data = line.repeat(100);
+        let n_reads = core::cell::Cell::new(0);
+        let reader = Box::new(CountReads(Cursor::new(&data), &n_reads));
+        let mut rb = ReadBuffer::from_reader(reader, None)?;
+        let mut ix = 0;
+        while let Some(NewLine(line)) = rb.next(&mut 0)? {
+            assert_eq!(line.len(), 4999);
+            ix += 1;
+        }
+        assert_eq!(ix, 100);
+        assert_eq!(rb.avg_record_len(), 5000);
+        // without prefetching this takes 50+ reads of 10,000 bytes
+        assert!(n_reads.get() < 15);
+
+        // but small ones leave the buffer at its default size
+        let data = b"1234\n".repeat(10_000);
+        let reader = Box::new(Cursor::new(&data));
+        let mut rb = ReadBuffer::from_reader(reader, None)?;
+        while let Some(NewLine(_)) = rb.next(&mut 0)? {}
+        assert_eq!(rb.avg_record_len(), 5);
+        assert!(rb.buffer.len() <= super::BUFFER_SIZE);
+        Ok(())
+    }
 }
@@ -0,0 +1,175 @@
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use serde::Serialize;
+
+use crate::record::Value;
+
+/// Every parser that can be chosen by name in `get_reader`, the version of its output, and
+/// whether it's only available with the `std` feature.
+///
+/// A parser's version is increased whenever a change could alter the records or metadata it
+/// produces for the same file (e.g. a fixed unit conversion or a new column), so a table can be
+/// traced back to exactly how it was made.
+const PARSERS: &[(&str, u32, bool)] = &[
+    ("abif", 1, false),
+    ("bam", 1, false),
+    ("cfb", 1, false),
+    ("chemstation_array", 2, false),
+    ("chemstation_dad", 2, false),
+    ("chemstation_fid", 2, false),
+    ("chemstation_ms", 2, false),
+    ("chemstation_mwd", 2, false),
+    ("chemstation_uv", 2, false),
+    ("csv", 2, false),
+    ("delimited", 1, false),
+    ("depth", 1, false),
+    ("elementar_irms", 1, false),
+    ("fasta", 1, false),
+    ("fastq", 1, false),
+    ("flow", 1, false),
+    ("gml", 1, false),
+    ("gpx", 1, false),
+    ("graphml", 1, false),
+    ("inficon", 1, false),
+    ("kmers", 1, false),
+    ("masshunter_dad", 1, true),
+    ("masshunter_ms", 1, true),
+    ("mztab", 1, false),
+    ("mzxml", 1, true),
+    ("pepxml", 1, false),
+    ("png", 1, true),
+    ("sam", 1, false),
+    ("sensor_log", 1, false),
+    ("sercon_irms", 1, false),
+    ("tar", 1, true),
+    ("thermo_cf", 1, false),
+    ("thermo_dxf", 1, false),
+    ("thermo_raw", 1, false),
+    ("tiff", 1, true),
+    ("tsv", 2, false),
+    ("waters", 1, true),
+];
+
+/// Is `name` a parser that's available in this build?
+pub(crate) fn is_parser(name: &str) -> bool {
+    PARSERS
+        .iter()
+        .any(|&(n, _, needs_std)| n == name && (cfg!(feature = "std") || !needs_std))
+}
+
+/// What this build of entab can do, so a pipeline can record which version of a parser produced
+/// a table.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct BuildInfo {
+    /// The version of the entab crate
+    pub version: &'static str,
+    /// The optional crate features that are enabled
+    pub features: Vec<&'static str>,
+    /// The version of each parser that's available, by the name used to choose it (e.g. in
+    /// `get_reader`)
+    pub parsers: BTreeMap<&'static str, u32>,
+}
+
+impl BuildInfo {
+    /// The version of the parser called `name` (if it's available in this build)
+    #[must_use]
+    pub fn parser_version(&self, name: &str) -> Option<u32> {
+        self.parsers.get(name).copied()
+    }
+
+    /// The build info as a `Value`, for bindings that convert `Value`s into their own types
+    #[must_use]
+    pub fn to_value(&self) -> Value<'static> {
+        let parsers: BTreeMap<String, Value> = self
+            .parsers
+            .iter()
+            .map(|(&name, &version)| (name.to_string(), Value::Integer(version.into())))
+            .collect();
+        let mut info = BTreeMap::new();
+        drop(info.insert("version".to_string(), self.version.into()));
+        drop(info.insert(
+            "features".to_string(),
+            Value::List(self.features.iter().map(|&f| f.into()).collect()),
+        ));
+        drop(info.insert("parsers".to_string(), Value::Record(parsers)));
+        Value::Record(info)
+    }
+}
+
+/// The version, enabled features, and available parsers of this build of entab
+#[must_use]
+pub fn build_info() -> BuildInfo {
+    let mut features = Vec::new();
+    if cfg!(feature = "compression") {
+        features.push("compression");
+    }
+    if cfg!(feature = "std") {
+        features.push("std");
+    }
+    let parsers = PARSERS
+        .iter()
+        .filter(|(_, _, needs_std)| cfg!(feature = "std") || !needs_std)
+        .map(|&(name, version, _)| (name, version))
+        .collect();
+    BuildInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        features,
+        parsers,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::ReadBuffer;
+    use crate::readers::_get_reader;
+
+    #[test]
+    fn test_build_info() {
+        let info = build_info();
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(info.parser_version("fasta"), Some(1));
+        assert_eq!(info.parser_version("not_a_parser"), None);
+        let value = info.to_value();
+        if let Value::Record(info) = value {
+            assert_eq!(info["version"], env!("CARGO_PKG_VERSION").into());
+        } else {
+            panic!("build info should be a record");
+        }
+    }
+
+    #[test]
+    fn test_all_parsers_registered() {
+        let info = build_info();
+        for name in info.parsers.keys() {
+            let rb = ReadBuffer::from(&b""[..]);
+            if let Err(e) = _get_reader(rb, name, BTreeMap::new()) {
+                assert!(
+                    !e.to_string().contains("No parser available"),
+                    "{} is not registered",
+                    name
+                );
+            }
+        }
+
+        // and every parser that can be chosen is listed (the names are the string patterns
+        // matched in `parser_reader`)
+        let source = include_str!("readers.rs");
+        let start = source.find("fn parser_reader").unwrap();
+        let end = start + source[start..].find("No parser available").unwrap();
+        for line in source[start..end].lines() {
+            let arm = match line.trim_start().strip_prefix('"') {
+                Some(arm) if arm.contains("\" =>") => arm,
+                _ => continue,
+            };
+            let name = &arm[..arm.find('"').unwrap()];
+            assert!(
+                PARSERS.iter().any(|&(n, _, _)| n == name),
+                "{} is not in build_info",
+                name
+            );
+        }
+    }
+}
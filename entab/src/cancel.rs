@@ -0,0 +1,216 @@
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+use crate::capabilities::Capabilities;
+use crate::error::{EtError, EtErrorKind};
+use crate::readers::RecordReader;
+use crate::record::Value;
+use crate::summary::SummaryFields;
+
+/// How many records a `CancellableReader` reads between checks of its token
+pub const CHECK_INTERVAL: usize = 256;
+
+/// A flag that can be set from another thread (e.g. by a host application handling Ctrl-C) to
+/// stop a `CancellableReader`; clones of a token share the same flag.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+    #[cfg(feature = "std")]
+    deadline: Option<Instant>,
+}
+
+impl CancelToken {
+    /// Create a new token that hasn't been cancelled
+    #[must_use]
+    pub fn new() -> Self {
+        CancelToken::default()
+    }
+
+    /// Also treat the token as cancelled once `timeout` has passed (counting from now)
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.deadline = Instant::now().checked_add(timeout);
+        self
+    }
+
+    /// Ask any readers using this token (or its clones) to stop
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Has the token been cancelled (or has its timeout passed)?
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.check().is_err()
+    }
+
+    /// Return a `Cancelled` error if the token has been cancelled or its timeout has passed
+    ///
+    /// # Errors
+    /// If the token's been cancelled, an error is returned.
+    pub fn check(&self) -> Result<(), EtError> {
+        let msg = if self.cancelled.load(Ordering::Relaxed) {
+            "Parsing was cancelled"
+        } else if self.timed_out() {
+            "Parsing took longer than its timeout"
+        } else {
+            return Ok(());
+        };
+        let mut err = EtError::new(msg);
+        err.kind = EtErrorKind::Cancelled;
+        Err(err)
+    }
+
+    #[cfg(feature = "std")]
+    fn timed_out(&self) -> bool {
+        self.deadline.is_some_and(|d| Instant::now() >= d)
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn timed_out(&self) -> bool {
+        false
+    }
+}
+
+/// Wraps a `RecordReader` to stop with a `Cancelled` error once its `CancelToken` is cancelled;
+/// the token is checked every `CHECK_INTERVAL` records so it adds almost nothing to parsing.
+#[derive(Debug)]
+pub struct CancellableReader<'r> {
+    reader: Box<dyn RecordReader + 'r>,
+    token: CancelToken,
+    /// Records read since the token was last checked
+    unchecked: usize,
+}
+
+impl<'r> CancellableReader<'r> {
+    /// Create a new `CancellableReader` around a `RecordReader`
+    #[must_use]
+    pub fn new(reader: Box<dyn RecordReader + 'r>, token: CancelToken) -> Self {
+        CancellableReader {
+            reader,
+            token,
+            unchecked: 0,
+        }
+    }
+}
+
+impl<'r> RecordReader for CancellableReader<'r> {
+    fn next_record(&mut self) -> Result<Option<Vec<Value<'_>>>, EtError> {
+        if self.unchecked == 0 {
+            self.token.check()?;
+        }
+        self.unchecked = (self.unchecked + 1) % CHECK_INTERVAL;
+        self.reader.next_record()
+    }
+
+    fn skip_records(&mut self, n: usize) -> Result<usize, EtError> {
+        self.token.check()?;
+        self.reader.skip_records(n)
+    }
+
+    fn bytes_read(&self) -> Option<u64> {
+        self.reader.bytes_read()
+    }
+
+    fn headers(&self) -> Vec<String> {
+        self.reader.headers()
+    }
+
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        self.reader.metadata()
+    }
+
+    fn column_metadata(&self) -> BTreeMap<String, BTreeMap<String, Value<'_>>> {
+        self.reader.column_metadata()
+    }
+
+    fn summary_fields(&self) -> SummaryFields {
+        self.reader.summary_fields()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.reader.size_hint()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.reader.capabilities()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::readers::get_reader;
+
+    #[test]
+    fn test_cancel_reader() -> Result<(), EtError> {
+        let data = ">a\nACGT\n".repeat(2 * CHECK_INTERVAL);
+        let (reader, _) = get_reader(data.as_bytes(), Some("fasta"), None)?;
+        let token = CancelToken::new();
+        let mut reader = CancellableReader::new(reader, token.clone());
+        assert!(reader.next_record()?.is_some());
+        token.cancel();
+        assert!(token.is_cancelled());
+        // the token isn't checked again until the next interval
+        for _ in 1..CHECK_INTERVAL {
+            assert!(reader.next_record()?.is_some());
+        }
+        let err = reader.next_record().unwrap_err();
+        assert_eq!(err.kind, EtErrorKind::Cancelled);
+        Ok(())
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_cancel_refill() -> Result<(), EtError> {
+        use std::io::Read;
+
+        use crate::readers::get_reader_cancellable;
+
+        /// A sequence that never ends (and cancels the token partway through)
+        struct Endless(CancelToken, usize);
+
+        impl Read for Endless {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                self.1 += 1;
+                if self.1 == 10 {
+                    self.0.cancel();
+                }
+                buf.fill(b'A');
+                if self.1 == 1 {
+                    buf[..3].copy_from_slice(b">a\n");
+                }
+                Ok(buf.len())
+            }
+        }
+
+        let token = CancelToken::new();
+        let data = Endless(token.clone(), 0);
+        let (mut reader, _) = get_reader_cancellable(data, Some("fasta"), None, token)?;
+        // the first record never ends, but the token's still checked while reading it
+        let err = reader.next_record().unwrap_err();
+        assert_eq!(err.kind, EtErrorKind::Cancelled);
+        Ok(())
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_cancel_timeout() -> Result<(), EtError> {
+        let token = CancelToken::new().with_timeout(Duration::from_secs(3600));
+        assert!(token.check().is_ok());
+        let token = CancelToken::new().with_timeout(Duration::from_secs(0));
+        let (reader, _) = get_reader(&b">a\nACGT\n"[..], Some("fasta"), None)?;
+        let mut reader = CancellableReader::new(reader, token);
+        let err = reader.next_record().unwrap_err();
+        assert_eq!(err.kind, EtErrorKind::Cancelled);
+        assert!(err.msg.contains("timeout"));
+        Ok(())
+    }
+}
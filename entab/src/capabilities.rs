@@ -0,0 +1,123 @@
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::{BitAnd, BitOr, BitOrAssign, Sub};
+
+/// A set of flags describing what a `RecordReader` can do beyond returning records in order.
+///
+/// Frontends can check these to decide e.g. whether to show a progress bar or a metadata pane
+/// instead of downcasting the reader or trying an operation and handling the error.
+#[derive(Clone, Copy, Default, Eq, Hash, PartialEq)]
+pub struct Capabilities(u8);
+
+impl Capabilities {
+    /// The reader can start from a later record without parsing the ones before it (e.g. with
+    /// `skip_records` or the `first_event` param for FCS files)
+    pub const SEEKABLE: Self = Capabilities(1);
+    /// The reader knows how many records are left (from `size_hint`)
+    pub const SIZED: Self = Capabilities(1 << 1);
+    /// The reader has information about the file in `metadata`
+    pub const HAS_METADATA: Self = Capabilities(1 << 2);
+    /// The records are read from more than one stream (e.g. the files in an archive)
+    pub const MULTI_STREAM: Self = Capabilities(1 << 3);
+    /// The type of every column is set by the format instead of being guessed from the values
+    /// (like for a TSV)
+    pub const TYPED_SCHEMA: Self = Capabilities(1 << 4);
+
+    /// Every flag with its name (in the order they're listed by `names`)
+    const NAMED: [(Self, &'static str); 5] = [
+        (Self::SEEKABLE, "seekable"),
+        (Self::SIZED, "sized"),
+        (Self::HAS_METADATA, "has_metadata"),
+        (Self::MULTI_STREAM, "multi_stream"),
+        (Self::TYPED_SCHEMA, "typed_schema"),
+    ];
+
+    /// No capabilities
+    #[must_use]
+    pub const fn empty() -> Self {
+        Capabilities(0)
+    }
+
+    /// The raw bits of the flags
+    #[must_use]
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Are all of the flags in `other` set?
+    #[must_use]
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Are no flags set?
+    #[must_use]
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// The names of the flags that are set (e.g. for passing to bindings)
+    #[must_use]
+    pub fn names(self) -> Vec<&'static str> {
+        Self::NAMED
+            .iter()
+            .filter(|(flag, _)| self.contains(*flag))
+            .map(|(_, name)| *name)
+            .collect()
+    }
+}
+
+impl BitOr for Capabilities {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Capabilities(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for Capabilities {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl BitAnd for Capabilities {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self {
+        Capabilities(self.0 & rhs.0)
+    }
+}
+
+impl Sub for Capabilities {
+    type Output = Self;
+
+    /// The flags in `self` that aren't in `rhs`
+    fn sub(self, rhs: Self) -> Self {
+        Capabilities(self.0 & !rhs.0)
+    }
+}
+
+impl fmt::Debug for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Capabilities({})", self.names().join(" | "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::format;
+
+    use super::*;
+
+    #[test]
+    fn test_capabilities() {
+        let caps = Capabilities::SIZED | Capabilities::TYPED_SCHEMA;
+        assert!(caps.contains(Capabilities::SIZED));
+        assert!(!caps.contains(Capabilities::SIZED | Capabilities::SEEKABLE));
+        assert_eq!(caps.names(), ["sized", "typed_schema"]);
+        assert_eq!(caps - Capabilities::SIZED, Capabilities::TYPED_SCHEMA);
+        assert!((caps & Capabilities::HAS_METADATA).is_empty());
+        assert_eq!(format!("{:?}", caps), "Capabilities(sized | typed_schema)");
+    }
+}
@@ -0,0 +1,312 @@
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::ops::Range;
+use std::io::{Read, Seek, SeekFrom};
+
+use memchr::memchr;
+
+use crate::error::EtError;
+use crate::filetype::{FileType, MAGIC_LEN};
+use crate::parsers::flow::FcsLayout;
+
+/// How much of the file to look through at a time for the start of a record
+const WINDOW_SIZE: u64 = 64 * 1024;
+
+/// A split of a file into byte ranges that can each be parsed on their own (e.g. by different
+/// workers of a distributed system).
+///
+/// To parse a chunk, read the `header` bytes followed by the chunk's bytes (or use
+/// `read_chunk`, which does this and any fixing up the format needs) and pass them to
+/// `get_reader` with the same parser.
+#[derive(Clone, Debug, Default)]
+pub struct ChunkPlan {
+    /// The name of the parser the chunks are for
+    pub parser: &'static str,
+    /// The part of the file (e.g. column names or the FCS TEXT segment) every chunk needs
+    pub header: Range<u64>,
+    /// Record-aligned byte ranges that together cover all of the records in the file
+    pub chunks: Vec<Range<u64>>,
+    fcs_layout: Option<FcsLayout>,
+}
+
+impl ChunkPlan {
+    /// Read the data for chunk `ix` out of `file` so it can be passed to `get_reader`.
+    ///
+    /// # Errors
+    /// If `ix` is not a chunk in this plan or the data can't be read, an error is returned.
+    pub fn read_chunk<R: Read + Seek>(&self, file: &mut R, ix: usize) -> Result<Vec<u8>, EtError> {
+        let chunk = self
+            .chunks
+            .get(ix)
+            .ok_or_else(|| EtError::from(format!("There is no chunk {}", ix)))?;
+        let mut data = read_range(file, self.header.clone())?;
+        if let Some(layout) = &self.fcs_layout {
+            let n_events = usize::try_from(chunk.end - chunk.start)? / layout.event_size;
+            layout.patch_header(&mut data, n_events)?;
+        }
+        data.extend(read_range(file, chunk.clone())?);
+        Ok(data)
+    }
+}
+
+/// Read the bytes in `range` from `file`
+fn read_range<R: Read + Seek>(file: &mut R, range: Range<u64>) -> Result<Vec<u8>, EtError> {
+    let _ = file.seek(SeekFrom::Start(range.start))?;
+    let mut data = vec![0; usize::try_from(range.end - range.start)?];
+    file.read_exact(&mut data)?;
+    Ok(data)
+}
+
+/// Does a record start at the beginning of `data`? Returns `None` if there's not enough data
+/// to tell (and `eof` is false).
+fn is_record_start(parser: &str, data: &[u8], eof: bool) -> Option<bool> {
+    if data.is_empty() {
+        return if eof { Some(false) } else { None };
+    }
+    if parser != "fastq" {
+        return Some(true);
+    }
+    // a FASTQ record starts with an `@` line and has a `+` line two lines later (quality lines
+    // can also start with `@`, but they're never followed by a `+` line two lines on)
+    if data[0] != b'@' {
+        return Some(false);
+    }
+    let mut pos = 0;
+    for _ in 0..2 {
+        match memchr(b'\n', &data[pos..]) {
+            Some(nl) => pos += nl + 1,
+            None if eof => return Some(false),
+            None => return None,
+        }
+    }
+    match data.get(pos) {
+        Some(b) => Some(*b == b'+'),
+        None if eof => Some(false),
+        None => None,
+    }
+}
+
+/// Find the first record that starts at or after `offset`
+fn next_record_start<R: Read + Seek>(
+    file: &mut R,
+    parser: &str,
+    offset: u64,
+    file_len: u64,
+) -> Result<u64, EtError> {
+    // records have to start at the beginning of a line so include the byte before the offset
+    let start = offset.saturating_sub(1);
+    let mut size = WINDOW_SIZE;
+    'window: loop {
+        let end = file_len.min(start + size);
+        let window = read_range(file, start..end)?;
+        let eof = end == file_len;
+        let mut pos = 0;
+        while let Some(nl) = memchr(b'\n', &window[pos..]) {
+            pos += nl + 1;
+            match is_record_start(parser, &window[pos..], eof) {
+                Some(true) => return Ok(start + pos as u64),
+                Some(false) => {}
+                None => {
+                    size *= 2;
+                    continue 'window;
+                }
+            }
+        }
+        if eof {
+            return Ok(file_len);
+        }
+        size *= 2;
+    }
+}
+
+/// Find the end of the lines at the start of the file that every chunk needs (the column names
+/// for delimited text or the `@` header lines for SAM).
+fn header_end<R: Read + Seek>(file: &mut R, parser: &str, file_len: u64) -> Result<u64, EtError> {
+    let mut offset = 0;
+    loop {
+        let line_end = next_record_start(file, "tsv", offset + 1, file_len)?;
+        if parser != "sam" {
+            return Ok(line_end);
+        }
+        let first = read_range(file, offset..file_len.min(offset + 1))?;
+        if first != b"@" {
+            return Ok(offset);
+        }
+        offset = line_end;
+    }
+}
+
+/// Split the records in `file` into (at most) `n_chunks` similarly-sized byte ranges that can
+/// each be parsed independently. This currently works for FASTQ, CSV/TSV, SAM, and FCS files
+/// (for FCS files with multiple data sets, only the first is split up).
+///
+/// If `parser` is `None`, it's detected from the start of the file.
+///
+/// # Errors
+/// If the file is compressed or in a format that can't be split, an error is returned.
+pub fn plan_chunks<R: Read + Seek>(
+    file: &mut R,
+    parser: Option<&str>,
+    n_chunks: usize,
+) -> Result<ChunkPlan, EtError> {
+    let file_len = file.seek(SeekFrom::End(0))?;
+    let magic = read_range(file, 0..file_len.min(MAGIC_LEN as u64))?;
+    let file_type = FileType::from_magic(&magic);
+    if file_type.is_compression() {
+        return Err("Compressed files can't be split into chunks".into());
+    }
+    let parser = match parser.map_or_else(|| file_type.to_parser_name(None), Ok)? {
+        "csv" => "csv",
+        "fastq" => "fastq",
+        "flow" => "flow",
+        "sam" => "sam",
+        "tsv" => "tsv",
+        x => return Err(format!("{} files can't be split into chunks", x).into()),
+    };
+    let n_chunks = n_chunks.max(1) as u64;
+
+    if parser == "flow" {
+        let mut size = WINDOW_SIZE;
+        let layout = loop {
+            let header = read_range(file, 0..file_len.min(size))?;
+            match FcsLayout::from_header(&header) {
+                Ok(layout) => break layout,
+                Err(e) if e.incomplete && size < file_len => size *= 2,
+                Err(e) => return Err(e),
+            }
+        };
+        let data_start = layout.data_start as u64;
+        let event_size = layout.event_size as u64;
+        let n_events = (layout.data_end - layout.data_start) as u64 / event_size;
+        let mut chunks = Vec::new();
+        for ix in 0..n_chunks {
+            let start = data_start + ix * n_events / n_chunks * event_size;
+            let end = data_start + (ix + 1) * n_events / n_chunks * event_size;
+            if end > start {
+                chunks.push(start..end);
+            }
+        }
+        return Ok(ChunkPlan {
+            parser,
+            header: 0..data_start,
+            chunks,
+            fcs_layout: Some(layout),
+        });
+    }
+
+    let data_start = if parser == "fastq" {
+        0
+    } else {
+        header_end(file, parser, file_len)?
+    };
+    let mut chunks = Vec::new();
+    let mut start = data_start;
+    for ix in 1..=n_chunks {
+        let end = if ix == n_chunks {
+            file_len
+        } else {
+            let target = data_start + ix * (file_len - data_start) / n_chunks;
+            next_record_start(file, parser, target.max(start), file_len)?
+        };
+        if end > start {
+            chunks.push(start..end);
+            start = end;
+        }
+    }
+    Ok(ChunkPlan {
+        parser,
+        header: 0..data_start,
+        chunks,
+        fcs_layout: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::Cursor;
+
+    use crate::readers::get_reader;
+
+    /// Read every record out of the chunks and check they match reading the whole file
+    fn check_chunks<R: Read + Seek>(
+        file: &mut R,
+        plan: &ChunkPlan,
+        data: &[u8],
+    ) -> Result<usize, EtError> {
+        let (mut reader, _) = get_reader(data, Some(plan.parser), None)?;
+        for ix in 0..plan.chunks.len() {
+            let chunk = plan.read_chunk(file, ix)?;
+            let (mut chunk_reader, _) = get_reader(&chunk[..], Some(plan.parser), None)?;
+            assert_eq!(chunk_reader.headers(), reader.headers());
+            while let Some(record) = chunk_reader.next_record()? {
+                assert_eq!(Some(record), reader.next_record()?);
+            }
+        }
+        assert!(reader.next_record()?.is_none());
+        Ok(plan.chunks.len())
+    }
+
+    #[test]
+    fn test_fastq_chunks() -> Result<(), EtError> {
+        let mut data = Vec::new();
+        for ix in 0..100 {
+            // quality lines that start with `@` shouldn't be split on
+            data.extend(format!("@read{}\nACGT\n+\n@@@@\n", ix).as_bytes());
+        }
+        let mut file = Cursor::new(data.clone());
+        let plan = plan_chunks(&mut file, None, 7)?;
+        assert_eq!(plan.parser, "fastq");
+        assert_eq!(plan.header, 0..0);
+        assert_eq!(check_chunks(&mut file, &plan, &data)?, 7);
+        for chunk in &plan.chunks {
+            assert_eq!(
+                &data[chunk.start as usize..chunk.start as usize + 5],
+                b"@read"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_tsv_and_sam_chunks() -> Result<(), EtError> {
+        let mut data = b"id\tvalue\n".to_vec();
+        for ix in 0..50 {
+            data.extend(format!("x{}\t{}\n", ix, ix * 2).as_bytes());
+        }
+        let mut file = Cursor::new(data.clone());
+        let plan = plan_chunks(&mut file, Some("tsv"), 4)?;
+        assert_eq!(plan.header, 0..9);
+        assert_eq!(check_chunks(&mut file, &plan, &data)?, 4);
+
+        let data: &[u8] = include_bytes!("../tests/data/test.sam");
+        let mut file = Cursor::new(data);
+        let plan = plan_chunks(&mut file, None, 3)?;
+        assert_eq!(plan.parser, "sam");
+        assert!(plan.header.end > 0);
+        assert_eq!(check_chunks(&mut file, &plan, data)?, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_fcs_chunks() -> Result<(), EtError> {
+        let path = "tests/data/HTS_BD_LSR_II_Mixed_Specimen_001_D6_D06.fcs";
+        let data = std::fs::read(path)?;
+        let mut file = File::open(path)?;
+        let plan = plan_chunks(&mut file, None, 5)?;
+        assert_eq!(plan.parser, "flow");
+        assert_eq!(check_chunks(&mut file, &plan, &data)?, 5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_unsplittable() {
+        let mut file = Cursor::new(b"\x1F\x8B\x08\x00".to_vec());
+        assert!(plan_chunks(&mut file, None, 2).is_err());
+        let mut file = Cursor::new(b">a\nACGT\n".to_vec());
+        assert!(plan_chunks(&mut file, None, 2).is_err());
+    }
+}
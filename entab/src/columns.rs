@@ -0,0 +1,439 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem;
+
+use crate::record::Value;
+
+/// The values in a `Column`, stored in a vector of their type if they all have the same one
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum ColumnValues {
+    /// Every value so far has been null
+    #[default]
+    Null,
+    /// True/false values
+    Boolean(Vec<bool>),
+    /// Integers
+    Integer(Vec<i64>),
+    /// Floats (and integers, in columns that have both)
+    Float(Vec<f64>),
+    /// Text, with each distinct string stored once so columns that repeat a few values (e.g.
+    /// reference names) don't need a string for every row
+    String {
+        /// The index of each row's string in `dictionary`
+        codes: Vec<u32>,
+        /// The distinct strings in the order they were first seen
+        dictionary: Vec<String>,
+    },
+    /// Values of any other type (e.g. datetimes) or a mix of types
+    Values(Vec<Value<'static>>),
+}
+
+/// The values from one column of a set of records (see `RecordReader::next_columns`).
+///
+/// Nulls are stored as a placeholder in the typed values (`false`, 0, NaN, or an empty string)
+/// and marked in a separate mask so e.g. a column of floats can be handed to NumPy as-is.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Column {
+    values: ColumnValues,
+    /// Which values are null (empty if none are)
+    nulls: Vec<bool>,
+    len: usize,
+    /// The code of each string in a `ColumnValues::String` dictionary
+    lookup: BTreeMap<String, u32>,
+}
+
+/// The code for `value` in `dictionary`, adding it if it's not already there
+fn intern(dictionary: &mut Vec<String>, lookup: &mut BTreeMap<String, u32>, value: &str) -> u32 {
+    if let Some(code) = lookup.get(value) {
+        return *code;
+    }
+    // `push` switches to `ColumnValues::Values` before the dictionary can get this big
+    #[allow(clippy::cast_possible_truncation)]
+    let code = dictionary.len() as u32;
+    dictionary.push(value.into());
+    let _ = lookup.insert(value.into(), code);
+    code
+}
+
+impl Column {
+    /// Create a column of `n` nulls
+    #[must_use]
+    pub fn nulls(n: usize) -> Self {
+        Column {
+            values: ColumnValues::Null,
+            nulls: vec![true; n],
+            len: n,
+            lookup: BTreeMap::new(),
+        }
+    }
+
+    /// The number of values in the column
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Does the column have no values?
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The (non-null) values in the column
+    #[must_use]
+    pub fn values(&self) -> &ColumnValues {
+        &self.values
+    }
+
+    /// Which values are null; empty if none of them are
+    #[must_use]
+    pub fn null_mask(&self) -> &[bool] {
+        &self.nulls
+    }
+
+    /// Is the value at `ix` null?
+    #[must_use]
+    pub fn is_null(&self, ix: usize) -> bool {
+        self.nulls.get(ix).copied().unwrap_or(false)
+    }
+
+    /// Split the column into its values and null mask (which is empty if none are null)
+    #[must_use]
+    pub fn into_parts(self) -> (ColumnValues, Vec<bool>) {
+        (self.values, self.nulls)
+    }
+
+    /// Add a value to the end of the column, changing how the values are stored if it doesn't
+    /// have the same type as the ones before it
+    pub fn push(&mut self, value: Value<'_>) {
+        let is_null = matches!(value, Value::Null);
+        if !self.nulls.is_empty() {
+            self.nulls.push(is_null);
+        } else if is_null {
+            self.nulls = vec![false; self.len];
+            self.nulls.push(true);
+        }
+        let n_before = self.len;
+        self.len += 1;
+        match (&mut self.values, value) {
+            (ColumnValues::Null, Value::Null) => {}
+            (ColumnValues::Null, Value::Boolean(b)) => {
+                let mut v = vec![false; n_before];
+                v.push(b);
+                self.values = ColumnValues::Boolean(v);
+            }
+            (ColumnValues::Null, Value::Integer(i)) => {
+                let mut v = vec![0; n_before];
+                v.push(i);
+                self.values = ColumnValues::Integer(v);
+            }
+            (ColumnValues::Null, Value::Float(f)) => {
+                let mut v = vec![f64::NAN; n_before];
+                v.push(f);
+                self.values = ColumnValues::Float(v);
+            }
+            (ColumnValues::Null, Value::String(s)) => {
+                let mut dictionary = Vec::new();
+                // nulls point at an empty string
+                let mut codes = vec![0; n_before];
+                if n_before > 0 {
+                    let _ = intern(&mut dictionary, &mut self.lookup, "");
+                }
+                codes.push(intern(&mut dictionary, &mut self.lookup, &s));
+                self.values = ColumnValues::String { codes, dictionary };
+            }
+            (ColumnValues::Boolean(v), Value::Boolean(b)) => v.push(b),
+            (ColumnValues::Boolean(v), Value::Null) => v.push(false),
+            (ColumnValues::Integer(v), Value::Integer(i)) => v.push(i),
+            (ColumnValues::Integer(v), Value::Null) => v.push(0),
+            (ColumnValues::Integer(v), Value::Float(f)) => {
+                #[allow(clippy::cast_precision_loss)]
+                let mut floats: Vec<f64> = v
+                    .iter()
+                    .zip(self.nulls.iter().chain(core::iter::repeat(&false)))
+                    .map(|(i, is_null)| if *is_null { f64::NAN } else { *i as f64 })
+                    .collect();
+                floats.push(f);
+                self.values = ColumnValues::Float(floats);
+            }
+            (ColumnValues::Float(v), Value::Float(f)) => v.push(f),
+            #[allow(clippy::cast_precision_loss)]
+            (ColumnValues::Float(v), Value::Integer(i)) => v.push(i as f64),
+            (ColumnValues::Float(v), Value::Null) => v.push(f64::NAN),
+            (ColumnValues::String { codes, dictionary }, Value::String(s))
+                if dictionary.len() < u32::MAX as usize =>
+            {
+                codes.push(intern(dictionary, &mut self.lookup, &s));
+            }
+            (ColumnValues::String { codes, dictionary }, Value::Null)
+                if dictionary.len() < u32::MAX as usize =>
+            {
+                codes.push(intern(dictionary, &mut self.lookup, ""));
+            }
+            (ColumnValues::Values(v), value) => v.push(value.into_owned()),
+            (_, value) => {
+                self.len = n_before;
+                let mut values = self.take_values();
+                self.len += 1;
+                values.push(value.into_owned());
+                self.values = ColumnValues::Values(values);
+            }
+        }
+    }
+
+    /// Add integers to the end of the column in one go (for readers that decode a block of
+    /// records at once), pushing them one at a time if the column isn't all integers
+    pub(crate) fn extend_integers(&mut self, values: impl Iterator<Item = i64>) {
+        if self.len == 0 {
+            if let ColumnValues::Null = self.values {
+                self.values = ColumnValues::Integer(Vec::new());
+            }
+        }
+        if let ColumnValues::Integer(v) = &mut self.values {
+            v.extend(values);
+            self.len = v.len();
+            if !self.nulls.is_empty() {
+                self.nulls.resize(self.len, false);
+            }
+        } else {
+            for value in values {
+                self.push(value.into());
+            }
+        }
+    }
+
+    /// Take the values out of the column as `Value`s (leaving it with only nulls)
+    fn take_values(&mut self) -> Vec<Value<'static>> {
+        let nulls = &self.nulls;
+        let null_or = |ix: usize, value: Value<'static>| {
+            if nulls.get(ix).copied().unwrap_or(false) {
+                Value::Null
+            } else {
+                value
+            }
+        };
+        match mem::take(&mut self.values) {
+            ColumnValues::Null => vec![Value::Null; self.len],
+            ColumnValues::Boolean(v) => v
+                .into_iter()
+                .enumerate()
+                .map(|(ix, b)| null_or(ix, b.into()))
+                .collect(),
+            ColumnValues::Integer(v) => v
+                .into_iter()
+                .enumerate()
+                .map(|(ix, i)| null_or(ix, i.into()))
+                .collect(),
+            ColumnValues::Float(v) => v
+                .into_iter()
+                .enumerate()
+                .map(|(ix, f)| null_or(ix, f.into()))
+                .collect(),
+            ColumnValues::String { codes, dictionary } => {
+                self.lookup.clear();
+                codes
+                    .into_iter()
+                    .enumerate()
+                    .map(|(ix, code)| null_or(ix, dictionary[code as usize].clone().into()))
+                    .collect()
+            }
+            ColumnValues::Values(v) => v,
+        }
+    }
+
+    /// Turn the column back into a `Value` for each row
+    #[must_use]
+    pub fn into_values(mut self) -> Vec<Value<'static>> {
+        self.take_values()
+    }
+}
+
+/// Adds the values of one record onto the ends of a set of columns, one field at a time (see
+/// `PushFields`), so records don't need to be collected into a `Vec` first.
+///
+/// Columns (of nulls for the records before it) are added if the record has more values than
+/// there are columns, and `finish` adds nulls to the columns it doesn't have a value for.
+#[derive(Debug)]
+pub struct ColumnRow<'c> {
+    columns: &'c mut Vec<Column>,
+    n_rows: usize,
+    ix: usize,
+}
+
+impl<'c> ColumnRow<'c> {
+    /// Start a new record at the end of `columns`
+    pub fn new(columns: &'c mut Vec<Column>) -> Self {
+        let n_rows = columns.first().map_or(0, Column::len);
+        ColumnRow {
+            columns,
+            n_rows,
+            ix: 0,
+        }
+    }
+
+    /// Add the value of the next field
+    pub fn push(&mut self, value: Value<'_>) {
+        if self.ix == self.columns.len() {
+            self.columns.push(Column::nulls(self.n_rows));
+        }
+        self.columns[self.ix].push(value);
+        self.ix += 1;
+    }
+
+    /// End the record, filling in nulls for any columns it didn't have a value for
+    pub fn finish(self) {
+        for column in &mut self.columns[self.ix..] {
+            column.push(Value::Null);
+        }
+    }
+}
+
+impl<'a, 'c> Extend<Value<'a>> for ColumnRow<'c> {
+    fn extend<I: IntoIterator<Item = Value<'a>>>(&mut self, values: I) {
+        for value in values {
+            self.push(value);
+        }
+    }
+}
+
+/// Add a record to the end of `columns`, adding columns (of nulls for the records before it) if
+/// it has more values than there are columns and nulls to the columns it doesn't have a value
+/// for.
+pub fn push_record(columns: &mut Vec<Column>, record: Vec<Value<'_>>) {
+    let mut row = ColumnRow::new(columns);
+    row.extend(record);
+    row.finish();
+}
+
+/// Turn `columns` back into a `Vec` of values for each record
+#[must_use]
+pub fn into_records(columns: Vec<Column>) -> Vec<Vec<Value<'static>>> {
+    let n_rows = columns.first().map_or(0, Column::len);
+    let mut records = vec![Vec::with_capacity(columns.len()); n_rows];
+    for column in columns {
+        for (record, value) in records.iter_mut().zip(column.into_values()) {
+            record.push(value);
+        }
+    }
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::EtError;
+    use crate::readers::get_reader;
+
+    #[test]
+    fn test_column_types() {
+        let mut column = Column::default();
+        column.push(Value::Null);
+        column.push(1.into());
+        column.push(2.into());
+        assert_eq!(column.values(), &ColumnValues::Integer(vec![0, 1, 2]));
+        assert_eq!(column.null_mask(), [true, false, false]);
+        column.push(2.5.into());
+        assert!(matches!(column.values(), ColumnValues::Float(v) if v[0].is_nan() && v[3] == 2.5));
+        column.push("x".into());
+        assert_eq!(
+            column.into_values(),
+            [Value::Null, 1.0.into(), 2.0.into(), 2.5.into(), "x".into()]
+        );
+
+        let mut column = Column::default();
+        column.push("a".into());
+        column.push("b".into());
+        assert_eq!(
+            column.values(),
+            &ColumnValues::String {
+                codes: vec![0, 1],
+                dictionary: vec!["a".into(), "b".into()],
+            }
+        );
+        assert!(column.null_mask().is_empty());
+        assert!(!column.is_null(1));
+
+        // each distinct string is only stored once
+        let mut column = Column::default();
+        column.push(Value::Null);
+        for s in ["chr1", "chr2", "chr1"] {
+            column.push(s.into());
+        }
+        column.push(Value::Null);
+        assert_eq!(
+            column.values(),
+            &ColumnValues::String {
+                codes: vec![0, 1, 2, 1, 0],
+                dictionary: vec!["".into(), "chr1".into(), "chr2".into()],
+            }
+        );
+        assert_eq!(
+            column.into_values(),
+            [
+                Value::Null,
+                "chr1".into(),
+                "chr2".into(),
+                "chr1".into(),
+                Value::Null
+            ]
+        );
+    }
+
+    #[test]
+    fn test_next_columns() -> Result<(), EtError> {
+        let data: &[u8] = br#"<gpx><trk><trkseg>
+<trkpt lat="1" lon="2"/><trkpt lat="1.5" lon="2"><ele>3</ele></trkpt><trkpt lat="2" lon="2"/>
+</trkseg></trk></gpx>"#;
+        let (mut reader, _) = get_reader(data, Some("gpx"), None)?;
+        let headers = reader.headers();
+        let ele = headers.iter().position(|h| h == "elevation").unwrap();
+        let mut columns = Vec::new();
+        assert_eq!(reader.next_columns(&mut columns, 2)?, 2);
+        assert_eq!(reader.next_columns(&mut columns, 2)?, 1);
+        assert_eq!(reader.next_columns(&mut columns, 2)?, 0);
+        assert_eq!(columns.len(), headers.len());
+        assert!(matches!(columns[ele].values(), ColumnValues::Float(v) if v[1] == 3.));
+        assert_eq!(columns[ele].null_mask(), [true, false, true]);
+
+        let records = into_records(columns);
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0][ele], Value::Null);
+        assert_eq!(records[1][ele], 3.0.into());
+
+        // records are pushed into the columns a field at a time
+        let data: &[u8] = b"@a\nACGT\n+\n!!!!\n@b\nTT\n+\n##\n";
+        let (mut reader, _) = get_reader(data, Some("fastq"), None)?;
+        let mut columns = Vec::new();
+        assert_eq!(reader.next_columns(&mut columns, 10)?, 2);
+        let (mut reader, _) = get_reader(data, Some("fastq"), None)?;
+        let mut records = Vec::new();
+        while let Some(record) = reader.next_record()? {
+            records.push(
+                record
+                    .into_iter()
+                    .map(Value::into_owned)
+                    .collect::<Vec<_>>(),
+            );
+        }
+        assert_eq!(into_records(columns), records);
+
+        // extra values get their own columns
+        let mut columns = Vec::new();
+        push_record(&mut columns, vec![1.into()]);
+        push_record(&mut columns, vec![2.into(), true.into()]);
+        assert_eq!(
+            columns.pop().map(Column::into_values),
+            Some(vec![Value::Null, true.into()])
+        );
+        let mut row = ColumnRow::new(&mut columns);
+        row.push(3.into());
+        row.finish();
+        assert_eq!(
+            columns.pop().map(Column::into_values),
+            Some(vec![1.into(), 2.into(), 3.into()])
+        );
+        Ok(())
+    }
+}
@@ -1,9 +1,19 @@
 #[cfg(feature = "std")]
 use alloc::boxed::Box;
+#[cfg(feature = "std")]
+use alloc::vec::Vec;
 use core::convert::TryInto;
+#[cfg(feature = "std")]
+use core::mem;
+#[cfg(feature = "std")]
+use std::io::{self, Cursor, Read};
+#[cfg(feature = "std")]
+use std::sync::mpsc::{channel, sync_channel, Receiver, Sender};
+#[cfg(feature = "std")]
+use std::thread::{self, JoinHandle};
 
 #[cfg(all(feature = "compression", feature = "std"))]
-use bzip2::read::BzDecoder;
+use bzip2::read::MultiBzDecoder;
 #[cfg(feature = "std")]
 use flate2::read::MultiGzDecoder;
 #[cfg(all(feature = "compression", feature = "std"))]
@@ -12,6 +22,8 @@ use xz2::read::XzDecoder;
 use zstd::stream::read::Decoder as ZstdDecoder;
 
 use crate::buffer::ReadBuffer;
+#[cfg(feature = "std")]
+use crate::buffer::BUFFER_SIZE;
 use crate::filetype::FileType;
 use crate::EtError;
 
@@ -36,14 +48,15 @@ where
             )
         }
         FileType::Bzip => {
-            let bz_reader = BzDecoder::new(reader.into_box_read());
+            let bz_reader = MultiBzDecoder::new(reader.into_box_read());
             (
                 ReadBuffer::from_reader(Box::new(bz_reader), None)?,
                 Some(file_type),
             )
         }
         FileType::Lzma => {
-            let xz_reader = XzDecoder::new(reader.into_box_read());
+            // like gzip and bzip2, xz streams can be concatenated (e.g. by `cat`)
+            let xz_reader = XzDecoder::new_multi_decoder(reader.into_box_read());
             (
                 ReadBuffer::from_reader(Box::new(xz_reader), None)?,
                 Some(file_type),
@@ -56,6 +69,13 @@ where
                 Some(file_type),
             )
         }
+        FileType::Lzw => {
+            let lzw_reader = LzwDecoder::new(reader.into_box_read());
+            (
+                ReadBuffer::from_reader(Box::new(lzw_reader), None)?,
+                Some(file_type),
+            )
+        }
         _ => (reader, None),
     })
 }
@@ -80,6 +100,13 @@ where
                 Some(file_type),
             )
         }
+        FileType::Lzw => {
+            let lzw_reader = LzwDecoder::new(reader.into_box_read());
+            (
+                ReadBuffer::from_reader(Box::new(lzw_reader), None)?,
+                Some(file_type),
+            )
+        }
         FileType::Bzip | FileType::Lzma | FileType::Zstd => {
             return Err("entab was not compiled with support for compressed files".into());
         }
@@ -100,13 +127,386 @@ where
     let mut reader = data.try_into()?;
     let file_type = reader.sniff_filetype()?;
     Ok(match file_type {
-        FileType::Gzip | FileType::Bzip | FileType::Lzma | FileType::Zstd => {
+        FileType::Gzip | FileType::Bzip | FileType::Lzma | FileType::Zstd | FileType::Lzw => {
             return Err("entab was not compiled with support for any compressed files".into());
         }
         _ => (reader, None),
     })
 }
 
+/// The code that resets the LZW table (in block mode)
+#[cfg(feature = "std")]
+const LZW_CLEAR: u32 = 256;
+
+/// A `Read` that decompresses LZW data in the format written by the Unix `compress` tool (i.e.
+/// `.Z` files).
+///
+/// Codes start at 9 bits and grow as the table fills up to the maximum set in the header; each
+/// time the code size changes (or the table is cleared), `compress` pads out the current group
+/// of 8 codes so that's skipped over here too.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct LzwDecoder<R> {
+    inner: R,
+    input: Vec<u8>,
+    input_pos: usize,
+    /// Bits read from the input that haven't been used yet (the next code is in the low bits)
+    bits: u64,
+    n_bits_buffered: u32,
+    /// How many codes have been read since the code size last changed
+    codes_read: usize,
+    header_read: bool,
+    block_mode: bool,
+    max_bits: u32,
+    n_bits: u32,
+    max_code: u32,
+    free_entry: u32,
+    old_code: Option<u32>,
+    last_char: u8,
+    prefixes: Vec<u32>,
+    suffixes: Vec<u8>,
+    /// Decoded data that hasn't been returned yet (in reverse order)
+    output: Vec<u8>,
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> LzwDecoder<R> {
+    /// Create a new `LzwDecoder` reading compressed data from `inner`
+    pub fn new(inner: R) -> Self {
+        LzwDecoder {
+            inner,
+            input: Vec::new(),
+            input_pos: 0,
+            bits: 0,
+            n_bits_buffered: 0,
+            codes_read: 0,
+            header_read: false,
+            block_mode: false,
+            max_bits: 16,
+            n_bits: 9,
+            max_code: 511,
+            free_entry: 256,
+            old_code: None,
+            last_char: 0,
+            prefixes: Vec::new(),
+            suffixes: Vec::new(),
+            output: Vec::new(),
+        }
+    }
+
+    /// Read the next byte of compressed data
+    fn next_byte(&mut self) -> io::Result<Option<u8>> {
+        if self.input_pos == self.input.len() {
+            self.input.resize(BUFFER_SIZE, 0);
+            let len = loop {
+                match self.inner.read(&mut self.input) {
+                    Ok(len) => break len,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            };
+            self.input.truncate(len);
+            self.input_pos = 0;
+            if len == 0 {
+                return Ok(None);
+            }
+        }
+        self.input_pos += 1;
+        Ok(Some(self.input[self.input_pos - 1]))
+    }
+
+    /// Read the next `n_bits` bits; returns `None` if there aren't that many left
+    fn next_bits(&mut self, n_bits: u32) -> io::Result<Option<u32>> {
+        while self.n_bits_buffered < n_bits {
+            match self.next_byte()? {
+                Some(byte) => {
+                    self.bits |= u64::from(byte) << self.n_bits_buffered;
+                    self.n_bits_buffered += 8;
+                }
+                None => return Ok(None),
+            }
+        }
+        let value = (self.bits & ((1 << n_bits) - 1)) as u32;
+        self.bits >>= n_bits;
+        self.n_bits_buffered -= n_bits;
+        Ok(Some(value))
+    }
+
+    /// Skip the padding at the end of the current group of 8 codes
+    fn skip_to_group_end(&mut self) -> io::Result<()> {
+        let padding = (8 - self.codes_read % 8) % 8;
+        for _ in 0..padding {
+            if self.next_bits(self.n_bits)?.is_none() {
+                break;
+            }
+        }
+        self.codes_read = 0;
+        Ok(())
+    }
+
+    fn read_header(&mut self) -> io::Result<()> {
+        let mut header = [0; 3];
+        for byte in &mut header {
+            *byte = self.next_byte()?.ok_or_else(|| {
+                io::Error::new(io::ErrorKind::UnexpectedEof, "LZW header is truncated")
+            })?;
+        }
+        if header[..2] != [0x1F, 0x9D] {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Data is not LZW-compressed",
+            ));
+        }
+        self.max_bits = u32::from(header[2] & 0x1F);
+        if !(9..=16).contains(&self.max_bits) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("LZW codes can not be {} bits", self.max_bits),
+            ));
+        }
+        self.block_mode = header[2] & 0x80 != 0;
+        self.free_entry = if self.block_mode { 257 } else { 256 };
+        let table_size = 1 << self.max_bits;
+        self.prefixes = vec![0; table_size];
+        self.suffixes = (0..table_size).map(|i| i as u8).collect();
+        self.header_read = true;
+        Ok(())
+    }
+
+    /// Decode codes until there's more output; returns `false` at the end of the data
+    fn decode_next(&mut self) -> io::Result<bool> {
+        let max_max_code = 1 << self.max_bits;
+        loop {
+            if self.free_entry > self.max_code {
+                self.skip_to_group_end()?;
+                self.n_bits += 1;
+                self.max_code = if self.n_bits == self.max_bits {
+                    max_max_code
+                } else {
+                    (1 << self.n_bits) - 1
+                };
+            }
+            let code = match self.next_bits(self.n_bits)? {
+                Some(code) => code,
+                None => return Ok(false),
+            };
+            self.codes_read += 1;
+            let old_code = match self.old_code {
+                Some(old_code) => old_code,
+                None => {
+                    if code >= 256 {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "LZW data starts with an invalid code",
+                        ));
+                    }
+                    self.last_char = code as u8;
+                    self.old_code = Some(code);
+                    self.output.push(self.last_char);
+                    return Ok(true);
+                }
+            };
+            if code == LZW_CLEAR && self.block_mode {
+                // the next code fills the clear code's entry so it's `free_entry - 1` here
+                self.free_entry = 256;
+                self.skip_to_group_end()?;
+                self.n_bits = 9;
+                self.max_code = 511;
+                continue;
+            }
+
+            let mut cur_code = code;
+            if code >= self.free_entry {
+                if code > self.free_entry {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "LZW data has a code that isn't in the table yet",
+                    ));
+                }
+                // the code being defined is the previous string plus its own first character
+                self.output.push(self.last_char);
+                cur_code = old_code;
+            }
+            while cur_code >= 256 {
+                self.output.push(self.suffixes[cur_code as usize]);
+                cur_code = self.prefixes[cur_code as usize];
+            }
+            self.last_char = self.suffixes[cur_code as usize];
+            self.output.push(self.last_char);
+
+            if self.free_entry < max_max_code {
+                self.prefixes[self.free_entry as usize] = old_code;
+                self.suffixes[self.free_entry as usize] = self.last_char;
+                self.free_entry += 1;
+            }
+            self.old_code = Some(code);
+            return Ok(true);
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> Read for LzwDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.header_read {
+            self.read_header()?;
+        }
+        let mut len = 0;
+        while len < buf.len() {
+            match self.output.pop() {
+                Some(byte) => {
+                    buf[len] = byte;
+                    len += 1;
+                }
+                None => {
+                    if !self.decode_next()? {
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(len)
+    }
+}
+
+/// How many bytes of decompressed data the background thread of a `ThreadedRead` reads at a
+/// time
+#[cfg(feature = "std")]
+const THREAD_CHUNK_SIZE: usize = 1 << 16;
+
+/// How many chunks the background thread of a `ThreadedRead` can get ahead of the reader
+#[cfg(feature = "std")]
+const THREAD_CHUNKS_AHEAD: usize = 4;
+
+/// A `Read` that pulls data from another `Read` on a background thread.
+///
+/// The thread reads ahead a bounded number of chunks (so memory use stays bounded) and passes
+/// them back over a channel; when the inner `Read` is a decompressor, this lets inflating the
+/// data and parsing it happen at the same time.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct ThreadedRead {
+    receiver: Receiver<io::Result<Vec<u8>>>,
+    /// Sends used chunks back to the thread so their allocations can be reused
+    recycler: Sender<Vec<u8>>,
+    handle: Option<JoinHandle<()>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+#[cfg(feature = "std")]
+impl ThreadedRead {
+    /// Start reading `reader` on a background thread, keeping up to `chunks_ahead` chunks ready.
+    pub fn new<R>(mut reader: R, chunks_ahead: usize) -> Self
+    where
+        R: Read + Send + 'static,
+    {
+        let (sender, receiver) = sync_channel(chunks_ahead);
+        let (recycler, used_chunks) = channel::<Vec<u8>>();
+        let handle = thread::spawn(move || loop {
+            let mut chunk = used_chunks.try_recv().unwrap_or_default();
+            chunk.resize(THREAD_CHUNK_SIZE, 0);
+            let result = match reader.read(&mut chunk) {
+                // dropping the sender tells the reader there's nothing left
+                Ok(0) => break,
+                Ok(n) => {
+                    chunk.truncate(n);
+                    Ok(chunk)
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => Err(e),
+            };
+            let is_err = result.is_err();
+            // if the reader's been dropped, there's no one left to read to
+            if sender.send(result).is_err() || is_err {
+                break;
+            }
+        });
+        ThreadedRead {
+            receiver,
+            recycler,
+            handle: Some(handle),
+            chunk: Vec::new(),
+            pos: 0,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Read for ThreadedRead {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.chunk.len() {
+            match self.receiver.recv() {
+                Ok(Ok(chunk)) => {
+                    let used = mem::replace(&mut self.chunk, chunk);
+                    // the thread may have already finished so it's fine if this fails
+                    let _ = self.recycler.send(used);
+                    self.pos = 0;
+                }
+                Ok(Err(e)) => return Err(e),
+                Err(_) => {
+                    // make sure the thread finished instead of panicking partway through
+                    if let Some(handle) = self.handle.take() {
+                        if handle.join().is_err() {
+                            return Err(io::Error::other("The decompression thread panicked"));
+                        }
+                    }
+                    return Ok(0);
+                }
+            }
+        }
+        let len = buf.len().min(self.chunk.len() - self.pos);
+        buf[..len].copy_from_slice(&self.chunk[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+/// Like `decompress`, but decompression runs on a background thread (through a `ThreadedRead`)
+/// so it overlaps with parsing; uncompressed data (or any data on single-core machines) is read
+/// directly without a thread.
+///
+/// # Errors
+/// If reading fails or if the stream can't be decompressed, return `EtError`.
+#[cfg(feature = "std")]
+pub fn decompress_threaded<R>(
+    mut reader: R,
+) -> Result<(ReadBuffer<'static>, Option<FileType>), EtError>
+where
+    R: Read + Send + 'static,
+{
+    // read a buffer's worth of the start to tell if the data is compressed (and so the
+    // `ReadBuffer` for uncompressed data starts as full as it would otherwise)
+    let mut magic = Vec::with_capacity(BUFFER_SIZE);
+    let _ = (&mut reader)
+        .take(BUFFER_SIZE as u64)
+        .read_to_end(&mut magic)?;
+    let file_type = FileType::from_magic(&magic);
+    let stream = Cursor::new(magic).chain(reader);
+    let decoder: Box<dyn Read + Send> = match file_type {
+        FileType::Gzip => Box::new(MultiGzDecoder::new(stream)),
+        #[cfg(feature = "compression")]
+        FileType::Bzip => Box::new(MultiBzDecoder::new(stream)),
+        #[cfg(feature = "compression")]
+        FileType::Lzma => Box::new(XzDecoder::new_multi_decoder(stream)),
+        #[cfg(feature = "compression")]
+        FileType::Zstd => Box::new(ZstdDecoder::new(stream)?),
+        FileType::Lzw => Box::new(LzwDecoder::new(stream)),
+        #[cfg(not(feature = "compression"))]
+        FileType::Bzip | FileType::Lzma | FileType::Zstd => {
+            return Err("entab was not compiled with support for compressed files".into());
+        }
+        _ => return Ok((ReadBuffer::from_reader(Box::new(stream), None)?, None)),
+    };
+    // with only one core the thread can't run at the same time as parsing so it'd just add
+    // overhead
+    let decoder: Box<dyn Read + Send> = match thread::available_parallelism() {
+        Ok(n) if n.get() > 1 => Box::new(ThreadedRead::new(decoder, THREAD_CHUNKS_AHEAD)),
+        _ => decoder,
+    };
+    Ok((ReadBuffer::from_reader(decoder, None)?, Some(file_type)))
+}
+
 #[cfg(all(test, feature = "compression", feature = "std"))]
 mod tests {
     use super::*;
@@ -124,6 +524,36 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_decompress_threaded() -> Result<(), EtError> {
+        let f = File::open("tests/data/test.bam")?;
+        let (mut rb, compression) = decompress_threaded(f)?;
+        assert_eq!(compression, Some(FileType::Gzip));
+        let x: &[u8] = rb.next(&mut 1392)?.unwrap();
+        assert_eq!(x.len(), 1392);
+        assert!(rb.next::<&[u8]>(&mut 1).is_err());
+
+        let f = File::open("tests/data/test.csv.zst")?;
+        let (rb, compression) = decompress_threaded(f)?;
+        assert_eq!(compression, Some(FileType::Zstd));
+        assert_eq!(rb.as_ref().len(), 48);
+
+        let (rb, compression) = decompress_threaded(Cursor::new(b">a\nACGT\n".to_vec()))?;
+        assert_eq!(compression, None);
+        assert_eq!(rb.as_ref(), b">a\nACGT\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_threaded_read() -> Result<(), EtError> {
+        // bigger than a chunk so it's passed over in pieces
+        let data: Vec<u8> = (0..200_000).map(|i| (i % 251) as u8).collect();
+        let mut out = Vec::new();
+        let _ = ThreadedRead::new(Cursor::new(data.clone()), 2).read_to_end(&mut out)?;
+        assert_eq!(out, data);
+        Ok(())
+    }
+
     #[test]
     fn test_read_bzip2() -> Result<(), EtError> {
         let f = File::open("tests/data/test.csv.bz2")?;
@@ -134,6 +564,108 @@ mod tests {
         Ok(())
     }
 
+    /// A `Read` that returns at most `chunk` bytes at a time, so decoders have to handle data
+    /// split at every possible point
+    struct ChunkedRead {
+        data: Cursor<Vec<u8>>,
+        chunk: usize,
+    }
+
+    impl Read for ChunkedRead {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(self.chunk);
+            self.data.read(&mut buf[..len])
+        }
+    }
+
+    #[test]
+    fn test_read_bzip2_multistream() -> Result<(), EtError> {
+        // e.g. from `pbzip2` or concatenating bzip2 files
+        let mut data = std::fs::read("tests/data/test.csv.bz2")?;
+        data.extend(data.clone());
+        let (rb, compression) = decompress(&data[..])?;
+        assert_eq!(compression, Some(FileType::Bzip));
+        let mut out = Vec::new();
+        let _ = rb.into_box_read().read_to_end(&mut out)?;
+        assert_eq!(out.len(), 96);
+        assert_eq!(out[..48], out[48..]);
+
+        for chunk in [1, 5] {
+            let stream = ChunkedRead {
+                data: Cursor::new(data.clone()),
+                chunk,
+            };
+            let (rb, _) = decompress_threaded(stream)?;
+            let mut out = Vec::new();
+            let _ = rb.into_box_read().read_to_end(&mut out)?;
+            assert_eq!(out.len(), 96);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_lzw() -> Result<(), EtError> {
+        let f = File::open("tests/data/test.csv.Z")?;
+        let (rb, compression) = decompress(f)?;
+        assert_eq!(compression, Some(FileType::Lzw));
+        assert!(rb.as_ref().starts_with(b"\"Test Title\",Value,Value2\n"));
+        assert_eq!(rb.as_ref().len(), 48);
+
+        // this is compressed with 10-bit codes so the table is cleared a couple times
+        let expected = std::fs::read("tests/data/sequence.fasta")?;
+        let f = File::open("tests/data/sequence.fasta.Z")?;
+        let (rb, compression) = decompress_threaded(f)?;
+        assert_eq!(compression, Some(FileType::Lzw));
+        let mut out = Vec::new();
+        let _ = rb.into_box_read().read_to_end(&mut out)?;
+        assert_eq!(out, expected);
+
+        let f = File::open("tests/data/sequence.fasta.Z")?;
+        let (mut reader, parser) = crate::readers::get_reader(f, None, None)?;
+        assert_eq!(parser, "fasta");
+        assert!(reader.next_record()?.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_lzw_chunk_boundaries() -> Result<(), EtError> {
+        let data = std::fs::read("tests/data/sequence.fasta.Z")?;
+        let expected = std::fs::read("tests/data/sequence.fasta")?;
+        for (chunk, read_size) in [(1, 1), (3, 7), (64, 4096)] {
+            let mut decoder = LzwDecoder::new(ChunkedRead {
+                data: Cursor::new(data.clone()),
+                chunk,
+            });
+            let mut out: Vec<u8> = Vec::new();
+            let mut buf = vec![0; read_size];
+            loop {
+                match decoder.read(&mut buf)? {
+                    0 => break,
+                    n => out.extend(&buf[..n]),
+                }
+            }
+            assert_eq!(out, expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_bad_lzw() {
+        let mut out = Vec::new();
+        // not LZW
+        assert!(LzwDecoder::new(&b"\x1f\x8b\x08"[..])
+            .read_to_end(&mut out)
+            .is_err());
+        // codes can't be more than 16 bits
+        assert!(LzwDecoder::new(&b"\x1f\x9d\x91"[..])
+            .read_to_end(&mut out)
+            .is_err());
+        // the first code has to be a single byte
+        assert!(LzwDecoder::new(&b"\x1f\x9d\x90\xff\xff"[..])
+            .read_to_end(&mut out)
+            .is_err());
+    }
+
     #[test]
     fn test_read_xz() -> Result<(), EtError> {
         let f = File::open("tests/data/test.csv.xz")?;
@@ -144,6 +676,24 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_read_xz_multistream() -> Result<(), EtError> {
+        let mut data = std::fs::read("tests/data/test.csv.xz")?;
+        data.extend(data.clone());
+        let (rb, compression) = decompress(&data[..])?;
+        assert_eq!(compression, Some(FileType::Lzma));
+        let mut out = Vec::new();
+        let _ = rb.into_box_read().read_to_end(&mut out)?;
+        assert_eq!(out.len(), 96);
+        assert_eq!(out[..48], out[48..]);
+
+        let (rb, _) = decompress_threaded(Cursor::new(data))?;
+        let mut out = Vec::new();
+        let _ = rb.into_box_read().read_to_end(&mut out)?;
+        assert_eq!(out.len(), 96);
+        Ok(())
+    }
+
     #[test]
     fn test_read_zstd() -> Result<(), EtError> {
         let f = File::open("tests/data/test.csv.zst")?;
@@ -153,4 +703,20 @@ mod tests {
         assert_eq!(rb.as_ref().len(), 48);
         Ok(())
     }
+
+    #[test]
+    fn test_read_zstd_skippable_frames() -> Result<(), EtError> {
+        // e.g. `pzstd` and the seekable format put metadata in skippable frames (including at
+        // the start of the file) and write multiple frames
+        let frame = std::fs::read("tests/data/test.csv.zst")?;
+        let mut data = b"\x50\x2A\x4D\x18\x04\x00\x00\x00abcd".to_vec();
+        data.extend(&frame);
+        data.extend(&frame);
+        let (rb, compression) = decompress(&data[..])?;
+        assert_eq!(compression, Some(FileType::Zstd));
+        let mut out = Vec::new();
+        let _ = rb.into_box_read().read_to_end(&mut out)?;
+        assert_eq!(out.len(), 96);
+        Ok(())
+    }
 }
@@ -0,0 +1,340 @@
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::{TryFrom, TryInto};
+
+use crate::buffer::ReadBuffer;
+use crate::capabilities::Capabilities;
+use crate::error::EtError;
+use crate::filetype::FileType;
+use crate::parsers::sam::{BamReader, SamReader};
+use crate::readers::RecordReader;
+use crate::record::Value;
+
+/// Alignments with any of these flags (unmapped, secondary, failing QC, or PCR/optical
+/// duplicates) aren't counted; this is the same default as `samtools depth`.
+pub const DEFAULT_SKIP_FLAGS: u16 = 0x4 | 0x100 | 0x200 | 0x400;
+
+/// Where the alignments come from
+#[derive(Debug)]
+enum Alignments<'r> {
+    Bam(BamReader<'r>),
+    Sam(SamReader<'r>),
+}
+
+/// The parts of an alignment needed to add it to the coverage
+#[derive(Debug)]
+struct Alignment {
+    /// The reference the alignment is on, if it's a different one than the last alignment's
+    new_ref: Option<String>,
+    /// The (0-based) position the alignment starts at
+    start: u64,
+    /// The (start, length) of each stretch of the reference covered by the alignment
+    blocks: Vec<(u64, u64)>,
+}
+
+/// The stretches of the reference that an alignment starting at `start` with `cigar` (in the
+/// text format, e.g. `10M2D5M`) covers; matches (`M`, `=`, and `X`) are counted, but deletions
+/// and skipped regions aren't.
+fn cigar_blocks(start: u64, cigar: &[u8]) -> Result<Vec<(u64, u64)>, EtError> {
+    let mut blocks = Vec::new();
+    let mut pos = start;
+    let mut len: u64 = 0;
+    for &c in cigar {
+        if c.is_ascii_digit() {
+            len = len
+                .checked_mul(10)
+                .and_then(|l| l.checked_add(u64::from(c - b'0')))
+                .ok_or("CIGAR operation is too long")?;
+            continue;
+        }
+        match c {
+            b'M' | b'=' | b'X' => {
+                if len > 0 {
+                    blocks.push((pos, len));
+                }
+                pos += len;
+            }
+            b'D' | b'N' => pos += len,
+            b'I' | b'S' | b'H' | b'P' => {}
+            _ => return Err(format!("Unknown CIGAR operation {}", char::from(c)).into()),
+        }
+        len = 0;
+    }
+    if len != 0 {
+        return Err("CIGAR string ends with a number".into());
+    }
+    Ok(blocks)
+}
+
+/// Computes the read depth (coverage) at each position from the alignments in a BAM or SAM
+/// file, like `samtools depth`.
+///
+/// Each record is a `ref_name`, `pos` (0-based, like the `pos` of the alignments themselves),
+/// and `depth`; positions without any coverage are skipped. The alignments have to be sorted by
+/// position (e.g. with `samtools sort`), but only the stretch of the reference the current
+/// alignments overlap is kept in memory so this works on files of any size.
+#[derive(Debug)]
+pub struct DepthReader<'r> {
+    alignments: Alignments<'r>,
+    skip_flags: u16,
+    ref_name: String,
+    /// The references that have already been finished (to catch unsorted files)
+    finished_refs: BTreeSet<String>,
+    /// The position of the first depth in `depths`
+    window_start: u64,
+    depths: VecDeque<u32>,
+    /// The next alignment; positions before it starts can't change anymore
+    next: Option<Alignment>,
+    done: bool,
+}
+
+impl<'r> DepthReader<'r> {
+    /// Create a new `DepthReader` from the data of a BAM or SAM file (which type is detected
+    /// from the data); alignments with any of the `skip_flags` (`DEFAULT_SKIP_FLAGS` if `None`)
+    /// aren't counted.
+    ///
+    /// # Errors
+    /// If the header of the file can't be read, an error is returned.
+    pub fn new<B>(data: B, skip_flags: Option<u16>) -> Result<Self, EtError>
+    where
+        B: TryInto<ReadBuffer<'r>>,
+        EtError: From<<B as TryInto<ReadBuffer<'r>>>::Error>,
+    {
+        let mut rb: ReadBuffer<'r> = data.try_into()?;
+        let alignments = if rb.sniff_filetype()? == FileType::Bam {
+            Alignments::Bam(BamReader::new::<ReadBuffer<'r>>(rb, None)?)
+        } else {
+            Alignments::Sam(SamReader::new::<ReadBuffer<'r>>(rb, None)?)
+        };
+        Ok(DepthReader {
+            alignments,
+            skip_flags: skip_flags.unwrap_or(DEFAULT_SKIP_FLAGS),
+            ref_name: String::new(),
+            finished_refs: BTreeSet::new(),
+            window_start: 0,
+            depths: VecDeque::new(),
+            next: None,
+            done: false,
+        })
+    }
+
+    /// Read the next alignment that counts towards the depth
+    fn read_alignment(&mut self) -> Result<Option<Alignment>, EtError> {
+        loop {
+            let (flag, ref_name, pos, blocks) = match &mut self.alignments {
+                Alignments::Bam(reader) => match reader.next()? {
+                    Some(r) => (r.flag, r.ref_name, r.pos, cigar_blocks_at(r.pos, &r.cigar)?),
+                    None => return Ok(None),
+                },
+                Alignments::Sam(reader) => match reader.next()? {
+                    Some(r) => (r.flag, r.ref_name, r.pos, cigar_blocks_at(r.pos, r.cigar)?),
+                    None => return Ok(None),
+                },
+            };
+            let start = match pos {
+                Some(start) if flag & self.skip_flags == 0 && !ref_name.is_empty() => start,
+                _ => continue,
+            };
+            let new_ref = if ref_name == self.ref_name {
+                None
+            } else {
+                Some(ref_name.to_string())
+            };
+            return Ok(Some(Alignment {
+                new_ref,
+                start,
+                blocks,
+            }));
+        }
+    }
+
+    /// Add the coverage of `alignment` into the depths
+    fn add(&mut self, alignment: Alignment) -> Result<(), EtError> {
+        if let Some(name) = alignment.new_ref {
+            // everything on the last reference has been returned already
+            let last = core::mem::replace(&mut self.ref_name, name);
+            let _ = self.finished_refs.insert(last);
+            if self.finished_refs.contains(&self.ref_name) {
+                return Err(format!(
+                    "Alignments on {} aren't all together; the file must be sorted by position",
+                    self.ref_name
+                )
+                .into());
+            }
+        }
+        if self.depths.is_empty() {
+            self.window_start = alignment.start;
+        } else if alignment.start < self.window_start {
+            return Err(format!(
+                "Alignment at {}:{} is before the one preceding it; the file must be sorted by position",
+                self.ref_name, alignment.start
+            )
+            .into());
+        }
+        for (start, len) in alignment.blocks {
+            let offset = usize::try_from(start - self.window_start)?;
+            let end = offset + usize::try_from(len)?;
+            if end > self.depths.len() {
+                self.depths.resize(end, 0);
+            }
+            for depth in self.depths.range_mut(offset..end) {
+                *depth = depth.saturating_add(1);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `cigar_blocks` for an alignment that may not have a position
+fn cigar_blocks_at(pos: Option<u64>, cigar: &[u8]) -> Result<Vec<(u64, u64)>, EtError> {
+    match pos {
+        Some(pos) => cigar_blocks(pos, cigar),
+        None => Ok(Vec::new()),
+    }
+}
+
+impl<'r> RecordReader for DepthReader<'r> {
+    fn next_record(&mut self) -> Result<Option<Vec<Value<'_>>>, EtError> {
+        loop {
+            // positions before the next alignment on this reference won't change anymore
+            let limit = match &self.next {
+                Some(alignment) if alignment.new_ref.is_none() => alignment.start,
+                _ if self.next.is_none() && !self.done => 0,
+                _ => u64::MAX,
+            };
+            while self.window_start < limit {
+                let depth = match self.depths.pop_front() {
+                    Some(depth) => depth,
+                    None => break,
+                };
+                let pos = self.window_start;
+                self.window_start += 1;
+                if depth > 0 {
+                    return Ok(Some(vec![
+                        self.ref_name.as_str().into(),
+                        pos.into(),
+                        depth.into(),
+                    ]));
+                }
+            }
+
+            if let Some(alignment) = self.next.take() {
+                self.add(alignment)?;
+            } else if self.done {
+                return Ok(None);
+            }
+            self.next = self.read_alignment()?;
+            if self.next.is_none() {
+                self.done = true;
+            }
+        }
+    }
+
+    fn headers(&self) -> Vec<String> {
+        vec![
+            "ref_name".to_string(),
+            "pos".to_string(),
+            "depth".to_string(),
+        ]
+    }
+
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        match &self.alignments {
+            Alignments::Bam(reader) => reader.metadata(),
+            Alignments::Sam(reader) => reader.metadata(),
+        }
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::TYPED_SCHEMA
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAM: &[u8] = b"@SQ\tSN:chr1\tLN:100\n@SQ\tSN:chr2\tLN:100\n\
+r1\t0\tchr1\t1\t60\t4M\t*\t0\t0\tACGT\t*\n\
+r2\t0\tchr1\t3\t60\t2M1D2M\t*\t0\t0\tACGT\t*\n\
+r3\t4\tchr1\t3\t0\t*\t*\t0\t0\tACGT\t*\n\
+r4\t1024\tchr1\t3\t60\t4M\t*\t0\t0\tACGT\t*\n\
+r5\t0\tchr2\t1\t60\t1S2M\t*\t0\t0\tACG\t*\n";
+
+    fn read_all(mut reader: DepthReader<'_>) -> Result<Vec<(String, u64, u64)>, EtError> {
+        let mut depths = Vec::new();
+        while let Some(record) = reader.next_record()? {
+            let (pos, depth) = match (&record[1], &record[2]) {
+                (Value::Integer(pos), Value::Integer(depth)) => (*pos as u64, *depth as u64),
+                _ => panic!("positions and depths should be integers"),
+            };
+            depths.push((record[0].clone().into_string()?, pos, depth));
+        }
+        Ok(depths)
+    }
+
+    #[test]
+    fn test_cigar_blocks() -> Result<(), EtError> {
+        assert_eq!(cigar_blocks(10, b"3S4M2I3M")?, [(10, 4), (14, 3)]);
+        assert_eq!(cigar_blocks(0, b"2M5N1=1X")?, [(0, 2), (7, 1), (8, 1)]);
+        assert!(cigar_blocks(0, b"2Q").is_err());
+        assert!(cigar_blocks(0, b"2M3").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sam_depth() -> Result<(), EtError> {
+        let reader = DepthReader::new(SAM, None)?;
+        assert_eq!(reader.headers(), ["ref_name", "pos", "depth"]);
+        let expected = [
+            ("chr1", 0, 1),
+            ("chr1", 1, 1),
+            ("chr1", 2, 2),
+            ("chr1", 3, 2),
+            ("chr1", 5, 1),
+            ("chr1", 6, 1),
+            ("chr2", 0, 1),
+            ("chr2", 1, 1),
+        ];
+        let depths = read_all(reader)?;
+        assert_eq!(depths.len(), expected.len());
+        for ((name, pos, depth), (e_name, e_pos, e_depth)) in depths.iter().zip(&expected) {
+            assert_eq!((name.as_str(), *pos, *depth), (*e_name, *e_pos, *e_depth));
+        }
+
+        // counting duplicates too
+        let depths = read_all(DepthReader::new(SAM, Some(0x4))?)?;
+        assert_eq!(depths[2], ("chr1".to_string(), 2, 3));
+        Ok(())
+    }
+
+    #[test]
+    fn test_unsorted_depth() -> Result<(), EtError> {
+        let data = b"r1\t0\tchr1\t10\t60\t4M\t*\t0\t0\tACGT\t*\n\
+r2\t0\tchr1\t1\t60\t4M\t*\t0\t0\tACGT\t*\n";
+        assert!(read_all(DepthReader::new(&data[..], None)?).is_err());
+
+        let data = b"r1\t0\tchr1\t1\t60\t4M\t*\t0\t0\tACGT\t*\n\
+r2\t0\tchr2\t1\t60\t4M\t*\t0\t0\tACGT\t*\n\
+r3\t0\tchr1\t5\t60\t4M\t*\t0\t0\tACGT\t*\n";
+        assert!(read_all(DepthReader::new(&data[..], None)?).is_err());
+        Ok(())
+    }
+
+    #[cfg(all(feature = "compression", feature = "std"))]
+    #[test]
+    fn test_bam_depth() -> Result<(), EtError> {
+        use crate::readers::get_reader;
+
+        let f = std::fs::File::open("tests/data/test.bam")?;
+        let (mut reader, _) = get_reader(f, Some("depth"), None)?;
+        assert_eq!(reader.headers(), ["ref_name", "pos", "depth"]);
+        assert!(reader.metadata().contains_key("references"));
+        // none of the reads in the test file are mapped
+        assert!(reader.next_record()?.is_none());
+        Ok(())
+    }
+}
@@ -1,6 +1,6 @@
 use alloc::borrow::Cow;
-#[cfg(feature = "std")]
 use alloc::boxed::Box;
+use alloc::format;
 use alloc::str::Utf8Error;
 use alloc::string::{FromUtf8Error, String, ToString};
 use alloc::vec::Vec;
@@ -10,7 +10,7 @@ use core::num::{ParseFloatError, ParseIntError, TryFromIntError};
 #[cfg(feature = "std")]
 use std::error::Error;
 #[cfg(feature = "std")]
-use std::io::Error as IoError;
+use std::io::{Error as IoError, ErrorKind};
 
 use crate::buffer::ReadBuffer;
 
@@ -32,17 +32,44 @@ pub struct EtErrorContext {
     pub context_pos: usize,
 }
 
+/// The kind of an `EtError`, for errors callers may want to handle differently from others.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum EtErrorKind {
+    /// Any other error
+    #[default]
+    Other,
+    /// The data ended partway through a record (e.g. the file was cut short while copying or
+    /// a stream was interrupted)
+    Truncated {
+        /// How many bytes the parser needed to finish the record, if it knew
+        needed: Option<usize>,
+        /// How many bytes of the record there were before the data ended
+        available: usize,
+    },
+    /// Reading was stopped by a `CancelToken` (either because it was cancelled or because its
+    /// timeout passed)
+    Cancelled,
+    /// The format of the data couldn't be detected from its contents (or there's no parser for
+    /// the format it was detected as), so a parser has to be chosen for it
+    UnknownFormat,
+}
+
 #[derive(Debug)]
 /// The Error struct for entab
 pub struct EtError {
     /// A succinct message describing the error
     pub msg: Cow<'static, str>,
-    /// Extra context, if available
-    pub context: Option<EtErrorContext>,
+    /// What kind of error this is
+    pub kind: EtErrorKind,
+    /// Extra context, if available (boxed to keep `Result`s with errors small)
+    pub context: Option<Box<EtErrorContext>>,
     /// If the error could be recovered from by pulling more data into the buffer.
     pub incomplete: bool,
+    /// For incomplete errors, how many bytes (from the start of the slice passed to the parser)
+    /// need to be in the buffer before the parser can make progress, if known.
+    pub needed: Option<usize>,
     #[cfg(feature = "std")]
-    orig_err: Option<Box<dyn Error>>,
+    orig_err: Option<Box<dyn Error + Send + Sync>>,
 }
 
 impl EtError {
@@ -51,8 +78,10 @@ impl EtError {
     pub fn new(msg: &'static str) -> Self {
         EtError {
             msg: Cow::Borrowed(msg),
+            kind: EtErrorKind::Other,
             context: None,
             incomplete: false,
+            needed: None,
             #[cfg(feature = "std")]
             orig_err: None,
         }
@@ -66,6 +95,33 @@ impl EtError {
         self
     }
 
+    /// Marks the `EtError` as "incomplete" like `incomplete`, but also records that the parser
+    /// needs at least `n_bytes` in the buffer so it can be grown to that size in one step (instead
+    /// of being refilled repeatedly until it's big enough).
+    #[must_use]
+    pub fn incomplete_needing(mut self, n_bytes: usize) -> Self {
+        self.incomplete = true;
+        self.needed = Some(n_bytes);
+        self
+    }
+
+    /// Turns an "incomplete" error into a `Truncated` one for when there's no more data to pull
+    /// into the buffer; `available` is how many bytes the parser had to work with and `byte` and
+    /// `record` are where the data ended.
+    #[must_use]
+    pub fn truncated(mut self, available: usize, byte: u64, record: u64) -> Self {
+        self.msg = Cow::Owned(format!(
+            "File appears truncated at byte {}, record {}: {}",
+            byte, record, self.msg
+        ));
+        self.kind = EtErrorKind::Truncated {
+            needed: self.needed,
+            available,
+        };
+        self.incomplete = false;
+        self
+    }
+
     /// Fill the positional error information from a `ReadBuffer` directly.
     #[must_use]
     pub fn add_context_from_readbuffer(self, buffer: &ReadBuffer) -> Self {
@@ -77,6 +133,28 @@ impl EtError {
         )
     }
 
+    /// The byte in the data the error occured at, if it's known
+    #[must_use]
+    pub fn byte(&self) -> Option<u64> {
+        self.context.as_ref().map(|c| c.byte)
+    }
+
+    /// The (zero-based) index of the record the error occured in, if it's known; errors while
+    /// reading a file's header are in record 0
+    #[must_use]
+    pub fn record(&self) -> Option<u64> {
+        self.context.as_ref().map(|c| c.record)
+    }
+
+    /// Was this caused by a reader that didn't have any data ready yet (see `PushParser`)?
+    #[cfg(feature = "std")]
+    pub(crate) fn is_would_block(&self) -> bool {
+        self.orig_err
+            .as_ref()
+            .and_then(|e| e.downcast_ref::<IoError>())
+            .is_some_and(|e| e.kind() == ErrorKind::WouldBlock)
+    }
+
     /// Fill the positional error information on the error.
     ///
     /// Used to display e.g. where a parsing error in a file occured.
@@ -102,12 +180,12 @@ impl EtError {
             (false, false) => ((buffer[consumed - 16..consumed + 16]).to_vec(), 16),
         };
 
-        self.context = Some(EtErrorContext {
+        self.context = Some(Box::new(EtErrorContext {
             record: record_pos,
             byte: reader_pos + consumed as u64,
             context,
             context_pos,
-        });
+        }));
         self
     }
 }
@@ -154,8 +232,8 @@ impl Error for EtError {
 }
 
 impl From<Infallible> for EtError {
-    fn from(_error: Infallible) -> Self {
-        panic!("Infallible things shouldn't panic!")
+    fn from(error: Infallible) -> Self {
+        match error {}
     }
 }
 
@@ -163,8 +241,10 @@ impl From<&'static str> for EtError {
     fn from(error: &'static str) -> Self {
         EtError {
             msg: Cow::Borrowed(error),
+            kind: EtErrorKind::Other,
             context: None,
             incomplete: false,
+            needed: None,
             #[cfg(feature = "std")]
             orig_err: None,
         }
@@ -175,8 +255,10 @@ impl From<String> for EtError {
     fn from(msg: String) -> Self {
         EtError {
             msg: Cow::Owned(msg),
+            kind: EtErrorKind::Other,
             context: None,
             incomplete: false,
+            needed: None,
             #[cfg(feature = "std")]
             orig_err: None,
         }
@@ -187,8 +269,10 @@ impl From<FromUtf8Error> for EtError {
     fn from(error: FromUtf8Error) -> Self {
         EtError {
             msg: Cow::Owned(error.to_string()),
+            kind: EtErrorKind::Other,
             context: None,
             incomplete: false,
+            needed: None,
             #[cfg(feature = "std")]
             orig_err: Some(Box::new(error)),
         }
@@ -200,8 +284,10 @@ impl From<IoError> for EtError {
     fn from(error: IoError) -> Self {
         EtError {
             msg: Cow::Owned(error.to_string()),
+            kind: EtErrorKind::Other,
             context: None,
             incomplete: false,
+            needed: None,
             #[cfg(feature = "std")]
             orig_err: Some(Box::new(error)),
         }
@@ -212,8 +298,10 @@ impl From<Utf8Error> for EtError {
     fn from(error: Utf8Error) -> Self {
         EtError {
             msg: Cow::Owned(error.to_string()),
+            kind: EtErrorKind::Other,
             context: None,
             incomplete: false,
+            needed: None,
             #[cfg(feature = "std")]
             orig_err: Some(Box::new(error)),
         }
@@ -224,8 +312,10 @@ impl From<ParseFloatError> for EtError {
     fn from(error: ParseFloatError) -> Self {
         EtError {
             msg: Cow::Owned(error.to_string()),
+            kind: EtErrorKind::Other,
             context: None,
             incomplete: false,
+            needed: None,
             #[cfg(feature = "std")]
             orig_err: Some(Box::new(error)),
         }
@@ -236,8 +326,10 @@ impl From<ParseIntError> for EtError {
     fn from(error: ParseIntError) -> Self {
         EtError {
             msg: Cow::Owned(error.to_string()),
+            kind: EtErrorKind::Other,
             context: None,
             incomplete: false,
+            needed: None,
             #[cfg(feature = "std")]
             orig_err: Some(Box::new(error)),
         }
@@ -248,14 +340,30 @@ impl From<TryFromIntError> for EtError {
     fn from(error: TryFromIntError) -> Self {
         EtError {
             msg: Cow::Owned(error.to_string()),
+            kind: EtErrorKind::Other,
             context: None,
             incomplete: false,
+            needed: None,
             #[cfg(feature = "std")]
             orig_err: Some(Box::new(error)),
         }
     }
 }
 
+#[cfg(feature = "arrow")]
+impl From<arrow_schema::ArrowError> for EtError {
+    fn from(error: arrow_schema::ArrowError) -> Self {
+        EtError {
+            msg: Cow::Owned(error.to_string()),
+            kind: EtErrorKind::Other,
+            context: None,
+            incomplete: false,
+            needed: None,
+            orig_err: Some(Box::new(error)),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use alloc::format;
@@ -2,7 +2,10 @@ use alloc::format;
 use alloc::string::String;
 use alloc::vec::Vec;
 
-use crate::error::EtError;
+use memchr::memmem;
+
+use crate::error::{EtError, EtErrorKind};
+use crate::parsers::tsv_inference::looks_delimited;
 
 /// A file format.
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -16,6 +19,11 @@ pub enum FileType {
     Lzma,
     /// Zstd compression container
     Zstd,
+    /// LZW compression container (from the Unix `compress` tool; usually `.Z` files)
+    Lzw,
+    // archives
+    /// Tar archive of other files
+    Tar,
     // bioinformatics
     /// "Binary alignment map" data. Compressed version of SAM.
     Bam,
@@ -31,9 +39,11 @@ pub enum FileType {
     Scf, // http://staden.sourceforge.net/manual/formats_unix_2.html
     /// DNA sequencing chromatogram trace format
     Ztr, // http://staden.sourceforge.net/manual/formats_unix_12.html
+    /// Applied Biosystems capillary electrophoresis (fragment analysis and sequencing) format
+    Abif,
     // chemoinformatics
-    /// Agilent format used for MS-MS trace data
-    AgilentMsMsScan, // bin   0x01, 0x01
+    /// Agilent Masshunter scan index (`MSScan.bin`) for mass spectrometry data
+    AgilentMsMsScan,
     /// Agilent format used for flame ionization data (array-based)
     AgilentChemstationArray,
     /// Agilent format used for UV-visible array data
@@ -64,13 +74,22 @@ pub enum FileType {
     ThermoDxf,
     /// Waters isotope mass spectrometry format
     WatersAutospec,
+    /// Waters MassLynx mass spectrometry data (the `_FUNC*.DAT` files in a `.raw` directory)
+    WatersMassLynx,
     /// Vendor-independent MS file format based on HDF
     NetCdf,
     /// Vendor-independent MS file format based on XML
     MzXml,
+    /// Tab-separated proteomics (and metabolomics) identification results
+    MzTab,
+    /// XML-based peptide identification results (e.g. from the Trans-Proteomic Pipeline)
+    PepXml,
     // geology
     /// "Log ASCII Standard" format for well log information
     Las,
+    // location formats
+    /// GPS Exchange Format tracks, routes, and waypoints
+    Gpx,
     // image formats
     /// DICOM Medical File Format
     Dicom,
@@ -80,7 +99,11 @@ pub enum FileType {
     Jpeg,
     /// Portable Network Graphics image format
     Png,
+    /// Tagged Image File Format (including OME-TIFF microscopy images)
+    Tiff,
     // generic data formats
+    /// Microsoft "Compound File Binary" container (also called OLE2 or structured storage)
+    MsCfb,
     /// Generic scientific data format
     Hdf5,
     /// Apache Avro
@@ -89,82 +112,190 @@ pub enum FileType {
     ApacheParquet,
     /// SQLite database
     Sqlite,
-    /// Tab- or comma-seperated value format
+    /// Tab-, comma-, or otherwise delimited text (the delimiter is detected by the parser)
     DelimitedText,
+    // graph formats
+    /// XML-based graph/network format
+    GraphMl,
+    /// "Graph Modelling Language" graph/network format
+    Gml,
+    /// No data at all (e.g. an empty file)
+    Empty,
     /// Unknown file type
     Unknown(Option<String>),
 }
 
+/// How many bytes from the start of a file `FileType::from_magic` needs to detect its format
+pub const MAGIC_LEN: usize = 512;
+
+/// The formats that can be detected by the bytes they start with, sorted by their first byte
+/// (and with longer prefixes first for the same byte so e.g. SAM's `@HD\t` is checked before
+/// FASTQ's `@`).
+const MAGIC: &[(&[u8], FileType)] = &[
+    (b"\x01\x01\x00\x00", FileType::AgilentMsMsScan),
+    (b"\x01\x32\x00\x00", FileType::AgilentChemstationMs),
+    (b"\x01\xA1F\x00i\x00n\x00", FileType::ThermoRaw),
+    (b"\x02\x02\x00\x00", FileType::AgilentMasshunterDadHeader),
+    (b"\x02\x33\x30\x00", FileType::AgilentChemstationMwd),
+    (b"\x02\x33\x31\x00", FileType::AgilentChemstationDad),
+    (b"\x02\x38\x31\x00", FileType::AgilentChemstationFid),
+    (b"\x03\x02\x00\x00", FileType::AgilentMasshunterDad),
+    (b"\x03\x31\x33\x30", FileType::AgilentChemstationMwd),
+    (b"\x03\x31\x33\x31", FileType::AgilentChemstationUv),
+    (b"\x03\x31\x37\x39", FileType::AgilentChemstationArray),
+    (b"\x04\x03\x02\x01SPAH", FileType::InficonHapsite),
+    (b"\x0F\x8B", FileType::Gzip),
+    (b"\x1F\x8B", FileType::Gzip),
+    (b"\x1F\x9D", FileType::Lzw),
+    (b"\x24\x00", FileType::BrukerBaf),
+    (b"\x28\xB5\x2F\xFD", FileType::Zstd),
+    (b"\x2Escf", FileType::Scf),
+    (b">", FileType::Fasta),
+    (b"@HD\t", FileType::Sam),
+    (b"@SQ\t", FileType::Sam),
+    (b"@", FileType::Fastq),
+    (b"ABIF", FileType::Abif),
+    (b"BAM\x01", FileType::Bam),
+    (b"BZ", FileType::Bzip),
+    (b"CD", FileType::NetCdf),
+    (b"DICM", FileType::Dicom),
+    (b"FCS2.0  ", FileType::Facs),
+    (b"FCS3.0  ", FileType::Facs),
+    (b"FCS3.1  ", FileType::Facs),
+    (b"GIF8", FileType::Gif),
+    (b"II*\x00", FileType::Tiff),
+    (b"MM\x00*", FileType::Tiff),
+    (b"MTD\t", FileType::MzTab),
+    (b"Obj\x01", FileType::ApacheAvro),
+    (b"PAR1", FileType::ApacheParquet),
+    (b"SQLite f", FileType::Sqlite),
+    (b"~VERSION", FileType::Las),
+    (b"~Version", FileType::Las),
+    (b"\x89HDF\r\n\x1A\n", FileType::Hdf5),
+    (b"\x89PNG\r\n\x1A\n", FileType::Png),
+    (b"\xAEZTR\x0D\x0A\x1A\x0A", FileType::Ztr),
+    (b"\xD0\xCF\x11\xE0\xA1\xB1\x1A\xE1", FileType::MsCfb),
+    (b"\xFD\x37", FileType::Lzma),
+    (b"\xFF\xD8\xFF\xDB", FileType::Jpeg),
+    (b"\xFF\xD8\xFF\xE0", FileType::Jpeg),
+    (b"\xFF\xD8\xFF\xE1", FileType::Jpeg),
+    (b"\xFF\xD8\xFF\xEE", FileType::Jpeg),
+    (b"\xFF\xFF\x05\x00", FileType::ThermoDxf),
+    (b"\xFF\xFF\x06\x00", FileType::ThermoDxf),
+];
+
+/// Where the entries in `MAGIC` starting with each byte are (the ones for byte `b` are in
+/// `MAGIC_INDEX[b]..MAGIC_INDEX[b + 1]`) so detection only compares against a few prefixes.
+const MAGIC_INDEX: [usize; 257] = magic_index(MAGIC);
+
+// if `MAGIC` isn't sorted by first byte, some of it won't be in the index
+const _: () = assert!(
+    MAGIC_INDEX[256] == MAGIC.len(),
+    "MAGIC must be sorted by first byte"
+);
+
+/// The length of the longest prefix in `MAGIC`, so a reader can make sure it has at least that
+/// much data buffered before detecting the format (if the data's that long).
+pub const MAGIC_PREFIX_LEN: usize = longest_prefix(MAGIC);
+
+/// Find the length of the longest prefix in `table` (at compile time)
+const fn longest_prefix(table: &[(&[u8], FileType)]) -> usize {
+    let mut longest = 0;
+    let mut ix = 0;
+    while ix < table.len() {
+        if table[ix].0.len() > longest {
+            longest = table[ix].0.len();
+        }
+        ix += 1;
+    }
+    longest
+}
+
+/// Build the index of where each first byte's entries in `table` start (at compile time)
+const fn magic_index(table: &[(&[u8], FileType)]) -> [usize; 257] {
+    let mut index = [0; 257];
+    let mut ix = 0;
+    let mut byte = 0;
+    while byte < 256 {
+        index[byte] = ix;
+        while ix < table.len() && table[ix].0[0] as usize == byte {
+            ix += 1;
+        }
+        byte += 1;
+    }
+    index[256] = ix;
+    index
+}
+
 impl FileType {
+    /// Is this a compression container (that `decompress` unwraps) instead of a format itself?
+    #[must_use]
+    pub fn is_compression(&self) -> bool {
+        matches!(
+            self,
+            FileType::Gzip | FileType::Bzip | FileType::Lzma | FileType::Zstd | FileType::Lzw
+        )
+    }
+
     /// Given a slice from the beginning of the file, try to guess which file
     /// format that file is in.
+    ///
+    /// Only the first `MAGIC_LEN` bytes are looked at so that's all that needs to be read to
+    /// detect a format (anything past them is ignored).
     #[must_use]
     pub fn from_magic(magic: &[u8]) -> FileType {
-        if magic.len() > 8 {
-            match &magic[..8] {
-                b"FCS2.0  " | b"FCS3.0  " | b"FCS3.1  " => return FileType::Facs,
-                b"~VERSION" | b"~Version" => return FileType::Las,
-                b"\x89PNG\r\n\x1A\n" => return FileType::Png,
-                b"\x89HDF\r\n\x1A\n" => return FileType::Hdf5,
-                b"\x04\x03\x02\x01SPAH" => return FileType::InficonHapsite,
-                b"\xAEZTR\x0D\x0A\x1A\x0A" => return FileType::Ztr,
-                b"\x01\xA1F\x00i\x00n\x00" => return FileType::ThermoRaw,
-                b"SQLite f" => return FileType::Sqlite,
-                _ => {}
-            }
+        let magic = &magic[..magic.len().min(MAGIC_LEN)];
+        if magic.is_empty() {
+            return FileType::Empty;
         }
-        if magic.len() > 4 {
-            match &magic[..4] {
-                b"BAM\x01" => return FileType::Bam,
-                b"DICM" => return FileType::Dicom,
-                b"GIF8" => return FileType::Gif,
-                b"@HD\t" | b"@SQ\t" => return FileType::Sam,
-                b"PAR1" => return FileType::ApacheParquet,
-                b"\x2Escf" => return FileType::Scf,
-                b"\x01\x32\x00\x00" => return FileType::AgilentChemstationMs,
-                b"\x02\x02\x00\x00" => return FileType::AgilentMasshunterDadHeader,
-                b"\x02\x33\x30\x00" => return FileType::AgilentChemstationMwd,
-                b"\x02\x33\x31\x00" => return FileType::AgilentChemstationDad,
-                b"\x02\x38\x31\x00" => return FileType::AgilentChemstationFid,
-                b"\x03\x02\x00\x00" => return FileType::AgilentMasshunterDad,
-                b"\x03\x31\x33\x30" => return FileType::AgilentChemstationMwd,
-                b"\x03\x31\x33\x31" => return FileType::AgilentChemstationUv,
-                b"\x03\x31\x37\x39" => return FileType::AgilentChemstationArray,
-                b"\x28\xB5\x2F\xFD" => return FileType::Zstd,
-                b"\x4F\x62\x6A\x01" => return FileType::ApacheAvro,
-                b"\xFF\xD8\xFF\xDB" | b"\xFF\xD8\xFF\xE0" | b"\xFF\xD8\xFF\xE1"
-                | b"\xFF\xD8\xFF\xEE" => return FileType::Jpeg,
-                [0xFF, 0xFF, 0x06 | 0x05, 0x00] => {
-                    if magic.len() >= 78 && &magic[52..64] == b"C\x00I\x00s\x00o\x00G\x00C\x00" {
-                        return FileType::ThermoCf;
-                    }
-                    return FileType::ThermoDxf;
-                }
-                _ => {}
+        // tar headers start with the file name so check these before anything else
+        if magic.len() >= 262 && &magic[257..262] == b"ustar" {
+            return FileType::Tar;
+        }
+        if magic.starts_with(b"<?xml")
+            || magic.starts_with(b"<graphml")
+            || magic.starts_with(b"<gpx")
+            || magic.starts_with(b"<mzXML")
+            || magic.starts_with(b"<msms_pipeline_analysis")
+        {
+            if memmem::find(magic, b"<graphml").is_some() {
+                return FileType::GraphMl;
+            }
+            if memmem::find(magic, b"<gpx").is_some() {
+                return FileType::Gpx;
+            }
+            if memmem::find(magic, b"<mzXML").is_some() {
+                return FileType::MzXml;
+            }
+            if memmem::find(magic, b"<msms_pipeline_analysis").is_some() {
+                return FileType::PepXml;
             }
         }
-        if magic.len() < 2 {
-            return FileType::Unknown(Some(
-                magic
-                    .iter()
-                    .take(8)
-                    .map(|x| format!("{:x}", x))
-                    .collect::<Vec<String>>()
-                    .join(""),
-            ));
+        if magic.starts_with(b"Creator \"")
+            || (magic.starts_with(b"graph") && magic[5..].trim_ascii_start().starts_with(b"["))
+        {
+            return FileType::Gml;
         }
-        match &magic[..2] {
-            [0x0F | 0x1F, 0x8B] => return FileType::Gzip,
-            [0x42, 0x5A] => return FileType::Bzip,
-            [0xFD, 0x37] => return FileType::Lzma,
-            [0x24, 0x00] => return FileType::BrukerBaf,
-            [0x43, 0x44] => return FileType::NetCdf,
-            _ => {}
+        // zstd "skippable" frames (e.g. the metadata `pzstd` starts files with)
+        if let [0x50..=0x5F, 0x2A, 0x4D, 0x18, ..] = magic {
+            return FileType::Zstd;
         }
-        match &magic[..1] {
-            b">" => FileType::Fasta,
-            b"@" => FileType::Fastq,
-            _ => FileType::Unknown(Some(
+        let first = usize::from(magic[0]);
+        let candidates = &MAGIC[MAGIC_INDEX[first]..MAGIC_INDEX[first + 1]];
+        match candidates
+            .iter()
+            .find(|(prefix, _)| magic.starts_with(prefix))
+        {
+            // Thermo's isotope formats share a header and differ in the class name in it
+            Some((_, FileType::ThermoDxf))
+                if magic.len() >= 78 && &magic[52..64] == b"C\x00I\x00s\x00o\x00G\x00C\x00" =>
+            {
+                FileType::ThermoCf
+            }
+            Some((_, file_type)) => file_type.clone(),
+            // text without any other signature is most likely a table (e.g. a CSV)
+            None if looks_delimited(magic) => FileType::DelimitedText,
+            None => FileType::Unknown(Some(
                 magic
                     .iter()
                     .take(8)
@@ -180,6 +311,7 @@ impl FileType {
     #[must_use]
     pub fn from_extension(ext: &str) -> &[Self] {
         match ext {
+            "ab1" | "abi" | "fsa" => &[FileType::Abif],
             "ami" => &[FileType::BrukerMsms],
             "avro" => &[FileType::ApacheAvro],
             "baf" => &[FileType::BrukerBaf],
@@ -199,13 +331,18 @@ impl FileType {
             "faq" | "fastq" | "fq" => &[FileType::Fastq],
             "fcs" | "lmd" => &[FileType::Facs],
             "gif" => &[FileType::Gif],
+            "gml" => &[FileType::Gml],
+            "gpx" => &[FileType::Gpx],
+            "graphml" => &[FileType::GraphMl],
             "gz" | "gzip" => &[FileType::Gzip],
             "hdf" => &[FileType::Hdf5],
             "hps" => &[FileType::InficonHapsite],
             "idx" => &[FileType::WatersAutospec],
             "jpg" | "jpeg" => &[FileType::Jpeg],
             "ms" => &[FileType::AgilentChemstationMs],
+            "mztab" => &[FileType::MzTab],
             "mzxml" => &[FileType::MzXml],
+            "pepxml" => &[FileType::PepXml],
             "png" => &[FileType::Png],
             "raw" => &[FileType::ThermoRaw],
             "sam" => &[FileType::Sam],
@@ -213,12 +350,15 @@ impl FileType {
             "sd" => &[FileType::AgilentMasshunterDadHeader],
             "sp" => &[FileType::AgilentMasshunterDad],
             "sqlite" => &[FileType::Sqlite],
+            "tar" => &[FileType::Tar],
+            "tif" | "tiff" => &[FileType::Tiff],
             "uv" => &[
                 FileType::AgilentChemstationDad,
                 FileType::AgilentChemstationUv,
             ],
             "xz" => &[FileType::Lzma],
-            "zstd" => &[FileType::Zstd],
+            "zst" | "zstd" => &[FileType::Zstd],
+            "z" => &[FileType::Lzw],
             "ztr" => &[FileType::Ztr],
             _ => &[FileType::Unknown(None)],
         }
@@ -230,6 +370,7 @@ impl FileType {
     /// If a file is unsupported, an error will be returned.
     pub fn to_parser_name<'a>(&self, hint: Option<&'a str>) -> Result<&'a str, EtError> {
         Ok(match (self, hint) {
+            (FileType::Abif, None) => "abif",
             (FileType::AgilentChemstationArray, None) => "chemstation_array",
             (FileType::AgilentChemstationDad, None) => "chemstation_dad",
             (FileType::AgilentChemstationFid, None) => "chemstation_fid",
@@ -237,26 +378,46 @@ impl FileType {
             (FileType::AgilentChemstationMwd, None) => "chemstation_mwd",
             (FileType::AgilentChemstationUv, None) => "chemstation_uv",
             (FileType::AgilentMasshunterDad, None) => "masshunter_dad",
+            (FileType::AgilentMsMsScan, None) => "masshunter_ms",
             (FileType::AgilentMasshunterDadHeader, None) => return Err("Reading the \".sd\" file is unsupported. Please open the \".sp\" data file instead".into()),
             (FileType::Bam, None) => "bam",
             (FileType::Fasta, None) => "fasta",
             (FileType::Fastq, None) => "fastq",
             (FileType::Facs, None) => "flow",
+            (FileType::Gml, None) => "gml",
+            (FileType::Gpx, None) => "gpx",
+            (FileType::GraphMl, None) => "graphml",
             (FileType::InficonHapsite, None) => "inficon_hapsite",
+            (FileType::MsCfb, None) => "cfb",
+            (FileType::MzTab, None) => "mztab",
+            (FileType::MzXml, None) => "mzxml",
+            (FileType::PepXml, None) => "pepxml",
             (FileType::Png, None) => "png",
             (FileType::Sam, None) => "sam",
             (FileType::ThermoCf, None) => "thermo_cf",
             (FileType::ThermoDxf, None) => "thermo_dxf",
+            (FileType::Tar, None) => "tar",
             (FileType::ThermoRaw, None) => "thermo_raw",
-            (FileType::DelimitedText, None) => "tsv",
-            (FileType::Unknown(Some(u)), None) => return Err(format!("File starting with #{}# has no parser", u).into()),
-            (FileType::Unknown(None), None) => return Err("Unknown file has no parser".into()),
+            (FileType::Tiff, None) => "tiff",
+            (FileType::DelimitedText, None) => "delimited",
+            (FileType::WatersMassLynx, None) => "waters",
+            // there's nothing to parse so read it as a table without any columns
+            (FileType::Empty, None) => "tsv",
+            (FileType::Unknown(Some(u)), None) => return Err(unknown_format(format!("File starting with #{}# has no parser", u))),
+            (FileType::Unknown(None), None) => return Err(unknown_format("Unknown file has no parser".into())),
             (_, Some(x)) => x,
-            (x, _) => return Err(format!("{:?} doesn't have a parser", x).into())
+            (x, _) => return Err(unknown_format(format!("{:?} doesn't have a parser", x))),
         })
     }
 }
 
+/// An error for data that no parser could be picked for
+fn unknown_format(msg: String) -> EtError {
+    let mut err = EtError::from(msg);
+    err.kind = EtErrorKind::UnknownFormat;
+    err
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -265,37 +426,158 @@ mod tests {
     #[test]
     fn test_parser_names() {
         let filetypes = [
+            (FileType::Abif, "abif"),
             (FileType::AgilentChemstationArray, "chemstation_array"),
             (FileType::AgilentChemstationFid, "chemstation_fid"),
             (FileType::AgilentChemstationMs, "chemstation_ms"),
             (FileType::AgilentChemstationMwd, "chemstation_mwd"),
             (FileType::AgilentChemstationUv, "chemstation_uv"),
             (FileType::AgilentMasshunterDad, "masshunter_dad"),
+            (FileType::AgilentMsMsScan, "masshunter_ms"),
             (FileType::Bam, "bam"),
             (FileType::Fasta, "fasta"),
             (FileType::Fastq, "fastq"),
             (FileType::Facs, "flow"),
+            (FileType::Gml, "gml"),
+            (FileType::Gpx, "gpx"),
+            (FileType::GraphMl, "graphml"),
             (FileType::InficonHapsite, "inficon_hapsite"),
+            (FileType::MsCfb, "cfb"),
+            (FileType::MzTab, "mztab"),
+            (FileType::MzXml, "mzxml"),
+            (FileType::PepXml, "pepxml"),
             (FileType::Png, "png"),
             (FileType::Sam, "sam"),
             (FileType::ThermoCf, "thermo_cf"),
             (FileType::ThermoDxf, "thermo_dxf"),
             (FileType::ThermoRaw, "thermo_raw"),
-            (FileType::DelimitedText, "tsv"),
+            (FileType::Tiff, "tiff"),
+            (FileType::Tar, "tar"),
+            (FileType::DelimitedText, "delimited"),
+            (FileType::WatersMassLynx, "waters"),
+            (FileType::Empty, "tsv"),
         ];
         for (ft, parser) in filetypes {
             assert_eq!(ft.to_parser_name(None).unwrap(), parser);
         }
     }
 
+    #[test]
+    fn test_graph_magic() {
+        let graphml =
+            b"<?xml version=\"1.0\"?>\n<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">";
+        assert_eq!(FileType::from_magic(graphml), FileType::GraphMl);
+        assert_eq!(FileType::from_magic(b"graph [\n  node ["), FileType::Gml);
+        assert_eq!(
+            FileType::from_magic(b"Creator \"x\"\ngraph ["),
+            FileType::Gml
+        );
+        assert_ne!(
+            FileType::from_magic(b"<?xml version=\"1.0\"?><mzXML>"),
+            FileType::GraphMl
+        );
+        assert_eq!(
+            FileType::from_magic(b"<?xml version=\"1.0\"?><mzXML>"),
+            FileType::MzXml
+        );
+        assert_eq!(
+            FileType::from_magic(b"<?xml version=\"1.0\"?>\n<gpx version=\"1.1\">"),
+            FileType::Gpx
+        );
+        assert_eq!(
+            FileType::from_magic(b"<?xml version=\"1.0\"?>\n<msms_pipeline_analysis date=\"\">"),
+            FileType::PepXml
+        );
+        assert_eq!(
+            FileType::from_magic(b"MTD\tmzTab-version\t1.0.0\n"),
+            FileType::MzTab
+        );
+    }
+
+    #[test]
+    fn test_compression_magic() {
+        assert_eq!(
+            FileType::from_magic(b"\xFD7zXZ\x00\x00\x04"),
+            FileType::Lzma
+        );
+        assert_eq!(
+            FileType::from_magic(b"\x28\xB5\x2F\xFD\x24\x30"),
+            FileType::Zstd
+        );
+        assert_eq!(
+            FileType::from_magic(b"\x5E\x2A\x4D\x18\x04\x00\x00\x00"),
+            FileType::Zstd
+        );
+        assert!(FileType::Zstd.is_compression());
+        assert_eq!(FileType::from_extension("zst"), &[FileType::Zstd]);
+        assert_eq!(FileType::from_extension("xz"), &[FileType::Lzma]);
+    }
+
+    #[test]
+    fn test_cfb_files() {
+        let magic = b"\xD0\xCF\x11\xE0\xA1\xB1\x1A\xE1\x00\x00";
+        assert_eq!(FileType::from_magic(magic), FileType::MsCfb);
+        assert_eq!(FileType::MsCfb.to_parser_name(None).unwrap(), "cfb");
+    }
+
+    #[test]
+    fn test_empty_files() {
+        assert_eq!(FileType::from_magic(b""), FileType::Empty);
+        assert_ne!(FileType::from_magic(b"\n"), FileType::Empty);
+    }
+
+    #[test]
+    fn test_magic_table() {
+        for (ix, (prefix, file_type)) in MAGIC.iter().enumerate() {
+            // every prefix is reachable (so nothing before it with the same first byte is a
+            // prefix of it) and detects its format
+            let mut magic = prefix.to_vec();
+            magic.resize(80, 0);
+            let detected = FileType::from_magic(&magic);
+            assert_eq!(&detected, file_type, "{:?} isn't reachable", prefix);
+            if let Some((next, _)) = MAGIC.get(ix + 1) {
+                assert!(prefix[0] <= next[0], "{:?} is out of order", next);
+            }
+        }
+        assert_eq!(FileType::from_magic(b"@HD\tVN:1.6"), FileType::Sam);
+        assert_eq!(FileType::from_magic(b"@read1\nACGT"), FileType::Fastq);
+        assert_eq!(
+            FileType::from_magic(b"Sample;Value\nA1;1,5\n"),
+            FileType::DelimitedText
+        );
+        assert!(matches!(
+            FileType::from_magic(b"Just some text\n"),
+            FileType::Unknown(_)
+        ));
+    }
+
+    #[test]
+    fn test_magic_len() {
+        // nothing past `MAGIC_LEN` is used for detection
+        let mut xml = b"<?xml version=\"1.0\"?>\n".to_vec();
+        xml.resize(MAGIC_LEN, b' ');
+        xml.extend_from_slice(b"<graphml>");
+        assert_eq!(
+            FileType::from_magic(&xml[..MAGIC_LEN + 9]),
+            FileType::from_magic(&xml[..MAGIC_LEN])
+        );
+        assert_ne!(FileType::from_magic(&xml), FileType::GraphMl);
+
+        let mut cf = b"\xFF\xFF\x06\x00".to_vec();
+        cf.resize(52, 0);
+        cf.extend_from_slice(b"C\x00I\x00s\x00o\x00G\x00C\x00");
+        cf.resize(80, 0);
+        assert_eq!(FileType::from_magic(&cf), FileType::ThermoCf);
+        assert_eq!(FileType::from_magic(&cf[..60]), FileType::ThermoDxf);
+    }
+
     #[test]
     fn test_unknown_files() {
         let unknown_type = FileType::from_magic(b"\x00\x00\x00\x00");
         assert_eq!(unknown_type, FileType::Unknown(Some("0000".to_string())));
 
-        assert_eq!(
-            unknown_type.to_parser_name(None).unwrap_err().msg,
-            "File starting with #0000# has no parser"
-        );
+        let err = unknown_type.to_parser_name(None).unwrap_err();
+        assert_eq!(err.msg, "File starting with #0000# has no parser");
+        assert_eq!(err.kind, EtErrorKind::UnknownFormat);
     }
 }
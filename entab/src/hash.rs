@@ -0,0 +1,205 @@
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use xxhash_rust::xxh3::Xxh3;
+
+use crate::capabilities::Capabilities;
+use crate::error::EtError;
+use crate::provenance::{add_step, transform_step};
+use crate::readers::RecordReader;
+use crate::record::Value;
+use crate::summary::SummaryFields;
+
+/// The name of the column `HashReader` adds
+pub const HASH_HEADER: &str = "_hash";
+
+/// Feed a canonical encoding of `value` into `hasher`.
+///
+/// Every value is tagged with its type and variable-length values are prefixed with their
+/// lengths so different records can't encode the same bytes. Floats that are whole numbers are
+/// hashed as integers (so e.g. `2.0` read from one format and `2` read from another match),
+/// `-0.0` is hashed as `0`, and every NaN is hashed the same.
+fn hash_value(hasher: &mut Xxh3, value: &Value) {
+    match value {
+        Value::Null => hasher.update(&[0]),
+        Value::Boolean(b) => hasher.update(&[1, u8::from(*b)]),
+        Value::Datetime(dt) => {
+            let dt = dt.and_utc();
+            hasher.update(&[2]);
+            hasher.update(&dt.timestamp().to_le_bytes());
+            hasher.update(&dt.timestamp_subsec_nanos().to_le_bytes());
+        }
+        Value::Float(f) if f.fract() == 0. && f.abs() < 9.2e18 => {
+            hash_value(hasher, &Value::Integer(*f as i64));
+        }
+        Value::Float(f) => {
+            let bits = if f.is_nan() { f64::NAN } else { *f }.to_bits();
+            hasher.update(&[3]);
+            hasher.update(&bits.to_le_bytes());
+        }
+        Value::Integer(i) => {
+            hasher.update(&[4]);
+            hasher.update(&i.to_le_bytes());
+        }
+        Value::String(s) => {
+            hasher.update(&[5]);
+            hasher.update(&(s.len() as u64).to_le_bytes());
+            hasher.update(s.as_bytes());
+        }
+        Value::List(values) => {
+            hasher.update(&[6]);
+            hasher.update(&(values.len() as u64).to_le_bytes());
+            for v in values {
+                hash_value(hasher, v);
+            }
+        }
+        Value::Record(fields) => {
+            hasher.update(&[7]);
+            hasher.update(&(fields.len() as u64).to_le_bytes());
+            for (key, v) in fields {
+                hasher.update(&(key.len() as u64).to_le_bytes());
+                hasher.update(key.as_bytes());
+                hash_value(hasher, v);
+            }
+        }
+    }
+}
+
+/// Compute a hash of the values in `record` that's stable across runs, platforms, and versions
+/// of entab (so hashes can be stored alongside archived data and compared later).
+#[must_use]
+pub fn hash_record(record: &[Value]) -> u64 {
+    let mut hasher = Xxh3::new();
+    hasher.update(&(record.len() as u64).to_le_bytes());
+    for value in record {
+        hash_value(&mut hasher, value);
+    }
+    hasher.digest()
+}
+
+/// Like `hash_record`, but leaving out the values at the `skip` indices (e.g. to find the
+/// records that only differ in those columns).
+#[must_use]
+pub(crate) fn hash_record_except(record: &[Value], skip: &[usize]) -> u64 {
+    let mut hasher = Xxh3::new();
+    for (ix, value) in record.iter().enumerate() {
+        if !skip.contains(&ix) {
+            hash_value(&mut hasher, value);
+        }
+    }
+    hasher.digest()
+}
+
+/// Wraps a `RecordReader` to add a `_hash` column with the `hash_record` of each record (as a
+/// 16-character hex string).
+#[derive(Debug)]
+pub struct HashReader<'r> {
+    reader: Box<dyn RecordReader + 'r>,
+}
+
+impl<'r> HashReader<'r> {
+    /// Create a new `HashReader` around a `RecordReader`
+    ///
+    /// # Errors
+    /// If the reader already has a `_hash` column, an error is returned.
+    pub fn new(reader: Box<dyn RecordReader + 'r>) -> Result<Self, EtError> {
+        if reader.headers().iter().any(|h| h == HASH_HEADER) {
+            return Err(format!("The file already has a {} column", HASH_HEADER).into());
+        }
+        Ok(HashReader { reader })
+    }
+}
+
+impl<'r> RecordReader for HashReader<'r> {
+    fn next_record(&mut self) -> Result<Option<Vec<Value<'_>>>, EtError> {
+        let mut record = match self.reader.next_record()? {
+            Some(record) => record,
+            None => return Ok(None),
+        };
+        let hash = hash_record(&record);
+        record.push(format!("{:016x}", hash).into());
+        Ok(Some(record))
+    }
+
+    fn skip_records(&mut self, n: usize) -> Result<usize, EtError> {
+        self.reader.skip_records(n)
+    }
+
+    fn bytes_read(&self) -> Option<u64> {
+        self.reader.bytes_read()
+    }
+
+    fn headers(&self) -> Vec<String> {
+        let mut headers = self.reader.headers();
+        headers.push(HASH_HEADER.into());
+        headers
+    }
+
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        let mut metadata = self.reader.metadata();
+        add_step(&mut metadata, transform_step("hash", BTreeMap::new()));
+        metadata
+    }
+
+    fn column_metadata(&self) -> BTreeMap<String, BTreeMap<String, Value<'_>>> {
+        self.reader.column_metadata()
+    }
+
+    fn summary_fields(&self) -> SummaryFields {
+        self.reader.summary_fields()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.reader.size_hint()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.reader.capabilities()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::readers::get_reader;
+
+    #[test]
+    fn test_hash_record() {
+        let record = [Value::from("a"), Value::from(2.0), Value::Null];
+        assert_eq!(
+            hash_record(&record),
+            hash_record(&["a".into(), 2i64.into(), Value::Null])
+        );
+        // the field boundaries are part of the hash
+        assert_ne!(
+            hash_record(&["ab".into(), "c".into()]),
+            hash_record(&["a".into(), "bc".into()])
+        );
+        assert_ne!(hash_record(&[1i64.into()]), hash_record(&["1".into()]));
+        assert_eq!(
+            hash_record(&[f64::NAN.into(), (-0.).into()]),
+            hash_record(&[(-f64::NAN).into(), 0.into()])
+        );
+        // hashes have to stay the same between versions
+        assert_eq!(hash_record(&[]), 0xc77b_3abb_6f87_acd9);
+    }
+
+    #[test]
+    fn test_hash_reader() -> Result<(), EtError> {
+        let mut params = BTreeMap::new();
+        let _ = params.insert("hash".into(), Value::Boolean(true));
+        let (mut reader, _) =
+            get_reader(&b">a\nACGT\n>b\nACGT\n>a\nACGT\n"[..], None, Some(params))?;
+        assert_eq!(reader.headers(), ["id", "sequence", "_hash"]);
+        let first = reader.next_record()?.unwrap()[2].clone().into_owned();
+        let second = reader.next_record()?.unwrap()[2].clone().into_owned();
+        let third = reader.next_record()?.unwrap()[2].clone().into_owned();
+        assert_ne!(first, second);
+        assert_eq!(first, third);
+        assert_eq!(first.into_string()?.len(), 16);
+        Ok(())
+    }
+}
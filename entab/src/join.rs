@@ -0,0 +1,425 @@
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::fmt;
+use core::str::FromStr;
+
+use crate::error::EtError;
+use crate::provenance::{add_step, transform_step};
+use crate::readers::RecordReader;
+use crate::record::Value;
+
+/// Which records a `JoinReader` returns
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum JoinKind {
+    /// Only the records with a key in both readers
+    Inner,
+    /// Every record in the left reader, with nulls for the right reader's fields if its key
+    /// isn't in the right reader
+    Left,
+}
+
+impl FromStr for JoinKind {
+    type Err = EtError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "inner" => Ok(JoinKind::Inner),
+            "left" => Ok(JoinKind::Left),
+            x => Err(format!("Unknown join {}; expected inner or left", x).into()),
+        }
+    }
+}
+
+impl fmt::Display for JoinKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            JoinKind::Inner => "inner",
+            JoinKind::Left => "left",
+        })
+    }
+}
+
+/// The text of a key as it's written out (and so as a sorted file has it)
+fn key_text<'v>(value: &'v Value<'_>) -> Result<Cow<'v, str>, EtError> {
+    Ok(match value {
+        Value::String(s) => Cow::Borrowed(s.as_ref()),
+        Value::Boolean(b) => Cow::Borrowed(if *b { "true" } else { "false" }),
+        Value::Datetime(d) => Cow::Owned(format!("{:?}", d)),
+        Value::Float(f) => Cow::Owned(f.to_string()),
+        Value::Integer(i) => Cow::Owned(i.to_string()),
+        _ => return Err(format!("Can not join on a key of {:?}", value).into()),
+    })
+}
+
+/// Compare two keys by the bytes of their text, like `LC_ALL=C sort` does (so e.g. 10 comes
+/// before 9); nulls come first.
+///
+/// # Errors
+/// If either key is a list or a record, an error is returned.
+fn compare_keys(a: &Value<'_>, b: &Value<'_>) -> Result<Ordering, EtError> {
+    Ok(match (a, b) {
+        (Value::Null, Value::Null) => Ordering::Equal,
+        (Value::Null, _) => Ordering::Less,
+        (_, Value::Null) => Ordering::Greater,
+        (a, b) => key_text(a)?.as_bytes().cmp(key_text(b)?.as_bytes()),
+    })
+}
+
+/// Find the position of the `key` column in `headers`
+fn key_column(headers: &[String], key: &str, side: &str) -> Result<usize, EtError> {
+    headers
+        .iter()
+        .position(|h| h == key)
+        .ok_or_else(|| format!("The {} reader does not have a {} column", side, key).into())
+}
+
+/// Joins the records of two readers that are both sorted by a key column, returning each left
+/// record followed by the fields (other than the key) of every right record with the same key.
+///
+/// Both readers are streamed through once so neither is held in memory, but because of this
+/// they have to be sorted by the bytes of their keys' text (as with `LC_ALL=C sort`, so numbers
+/// aren't sorted by value); an unsorted reader returns an error. Records with a null key are
+/// never matched. Right columns with the same name as a left column have `_right` added to
+/// their names.
+#[derive(Debug)]
+pub struct JoinReader<'r> {
+    left: Box<dyn RecordReader + 'r>,
+    right: Box<dyn RecordReader + 'r>,
+    kind: JoinKind,
+    key: String,
+    left_key: usize,
+    right_key: usize,
+    /// The key of the last left record (to check it's sorted)
+    last_left_key: Option<Value<'static>>,
+    /// The right records that have the key of the current left record
+    group: Vec<Vec<Value<'static>>>,
+    group_key: Option<Value<'static>>,
+    /// The next right record (read ahead to find the end of the group)
+    right_next: Option<Vec<Value<'static>>>,
+    right_done: bool,
+    /// The left record being joined and the index of the next record in `group` to join it with
+    current: Option<Vec<Value<'static>>>,
+    group_ix: usize,
+}
+
+impl<'r> JoinReader<'r> {
+    /// Create a new `JoinReader` joining the records of `left` and `right` that have the same
+    /// value in their `key` columns.
+    ///
+    /// # Errors
+    /// If either reader doesn't have a `key` column, an error is returned.
+    pub fn new(
+        left: Box<dyn RecordReader + 'r>,
+        right: Box<dyn RecordReader + 'r>,
+        key: &str,
+        kind: JoinKind,
+    ) -> Result<Self, EtError> {
+        let left_key = key_column(&left.headers(), key, "left")?;
+        let right_key = key_column(&right.headers(), key, "right")?;
+        Ok(JoinReader {
+            left,
+            right,
+            kind,
+            key: key.to_string(),
+            left_key,
+            right_key,
+            last_left_key: None,
+            group: Vec::new(),
+            group_key: None,
+            right_next: None,
+            right_done: false,
+            current: None,
+            group_ix: 0,
+        })
+    }
+
+    /// Read the next right record into `right_next`, checking the right reader's sorted
+    fn advance_right(&mut self) -> Result<(), EtError> {
+        let next = match self.right.next_record()? {
+            Some(record) => record
+                .into_iter()
+                .map(Value::into_owned)
+                .collect::<Vec<_>>(),
+            None => {
+                self.right_done = true;
+                self.right_next = None;
+                return Ok(());
+            }
+        };
+        if let Some(prev) = &self.right_next {
+            if compare_keys(&prev[self.right_key], &next[self.right_key])? == Ordering::Greater {
+                return Err(format!("The right reader isn't sorted by {}", self.key).into());
+            }
+        }
+        self.right_next = Some(next);
+        Ok(())
+    }
+
+    /// Collect the right records with `key` into `group` (skipping any before it); null keys
+    /// don't match anything
+    fn load_group(&mut self, key: &Value<'static>) -> Result<(), EtError> {
+        self.group.clear();
+        if self.right_next.is_none() && !self.right_done {
+            self.advance_right()?;
+        }
+        while let Some(next) = &self.right_next {
+            match compare_keys(&next[self.right_key], key)? {
+                Ordering::Equal if !matches!(key, Value::Null) => self.group.push(next.clone()),
+                Ordering::Less | Ordering::Equal => {}
+                Ordering::Greater => break,
+            }
+            self.advance_right()?;
+        }
+        self.group_key = Some(key.clone());
+        Ok(())
+    }
+
+    /// `left` followed by the fields of `right` (or nulls if there isn't one), without its key
+    fn joined(
+        &self,
+        left: &[Value<'static>],
+        right: Option<&[Value<'static>]>,
+    ) -> Vec<Value<'static>> {
+        let mut record = left.to_vec();
+        match right {
+            Some(right) => record.extend(
+                right
+                    .iter()
+                    .enumerate()
+                    .filter(|(ix, _)| *ix != self.right_key)
+                    .map(|(_, value)| value.clone()),
+            ),
+            None => {
+                let n_right = self.right.headers().len().saturating_sub(1);
+                record.resize(left.len() + n_right, Value::Null);
+            }
+        }
+        record
+    }
+}
+
+impl<'r> RecordReader for JoinReader<'r> {
+    fn next_record(&mut self) -> Result<Option<Vec<Value<'_>>>, EtError> {
+        loop {
+            if let Some(left) = &self.current {
+                if let Some(right) = self.group.get(self.group_ix) {
+                    self.group_ix += 1;
+                    return Ok(Some(self.joined(left, Some(right))));
+                }
+                if self.group.is_empty() && self.kind == JoinKind::Left && self.group_ix == 0 {
+                    // only return the unmatched record once
+                    self.group_ix = 1;
+                    return Ok(Some(self.joined(left, None)));
+                }
+            }
+            let left: Vec<Value<'static>> = match self.left.next_record()? {
+                Some(record) => record.into_iter().map(Value::into_owned).collect(),
+                None => {
+                    self.current = None;
+                    return Ok(None);
+                }
+            };
+            let key = left[self.left_key].clone();
+            if let Some(last) = &self.last_left_key {
+                if compare_keys(last, &key)? == Ordering::Greater {
+                    return Err(format!("The left reader isn't sorted by {}", self.key).into());
+                }
+            }
+            let same_group = match &self.group_key {
+                Some(group_key) => compare_keys(group_key, &key)? == Ordering::Equal,
+                None => false,
+            };
+            if !same_group {
+                self.load_group(&key)?;
+            }
+            self.last_left_key = Some(key);
+            self.current = Some(left);
+            self.group_ix = 0;
+        }
+    }
+
+    fn headers(&self) -> Vec<String> {
+        let mut headers = self.left.headers();
+        let n_left = headers.len();
+        for (ix, header) in self.right.headers().into_iter().enumerate() {
+            if ix == self.right_key {
+                continue;
+            }
+            if headers[..n_left].contains(&header) {
+                headers.push(format!("{}_right", header));
+            } else {
+                headers.push(header);
+            }
+        }
+        headers
+    }
+
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        let mut metadata = self.left.metadata();
+        let mut params = BTreeMap::new();
+        drop(params.insert("on".to_string(), self.key.clone().into()));
+        drop(params.insert("how".to_string(), self.kind.to_string().into()));
+        add_step(&mut metadata, transform_step("join", params));
+        metadata
+    }
+
+    fn column_metadata(&self) -> BTreeMap<String, BTreeMap<String, Value<'_>>> {
+        let left_headers = self.left.headers();
+        let mut column_metadata = self.left.column_metadata();
+        // renamed the same way as in `headers`
+        for (column, metadata) in self.right.column_metadata() {
+            if column == self.key {
+                continue;
+            }
+            let column = if left_headers.contains(&column) {
+                format!("{}_right", column)
+            } else {
+                column
+            };
+            drop(column_metadata.insert(column, metadata));
+        }
+        column_metadata
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+    use crate::readers::get_reader;
+
+    fn read_all(mut reader: impl RecordReader) -> Result<Vec<Vec<Value<'static>>>, EtError> {
+        let mut records = Vec::new();
+        while let Some(record) = reader.next_record()? {
+            records.push(record.into_iter().map(Value::into_owned).collect());
+        }
+        Ok(records)
+    }
+
+    fn join(
+        left: &'static [u8],
+        right: &'static [u8],
+        kind: JoinKind,
+    ) -> Result<JoinReader<'static>, EtError> {
+        let (left, _) = get_reader(left, Some("tsv"), None)?;
+        let (right, _) = get_reader(right, Some("tsv"), None)?;
+        JoinReader::new(left, right, "id", kind)
+    }
+
+    #[test]
+    fn test_join() -> Result<(), EtError> {
+        let left = b"id\tlength\na\t10\nb\t20\nb\t21\nd\t40\n";
+        let right = b"id\tlength\tmapq\nA\t1\t1\nb\t2\t60\nb\t3\t30\nc\t4\t10\nd\t5\t0\n";
+
+        let reader = join(left, right, JoinKind::Inner)?;
+        assert_eq!(reader.headers(), ["id", "length", "length_right", "mapq"]);
+        let records = read_all(reader)?;
+        let ids: Vec<(&Value, &Value, &Value)> =
+            records.iter().map(|r| (&r[0], &r[1], &r[2])).collect();
+        assert_eq!(
+            ids,
+            [
+                (&"b".into(), &Value::Integer(20), &Value::Integer(2)),
+                (&"b".into(), &Value::Integer(20), &Value::Integer(3)),
+                (&"b".into(), &Value::Integer(21), &Value::Integer(2)),
+                (&"b".into(), &Value::Integer(21), &Value::Integer(3)),
+                (&"d".into(), &Value::Integer(40), &Value::Integer(5)),
+            ]
+        );
+
+        let records = read_all(join(left, right, JoinKind::Left)?)?;
+        assert_eq!(records.len(), 6);
+        assert_eq!(
+            records[0],
+            ["a".into(), Value::Integer(10), Value::Null, Value::Null]
+        );
+
+        // unsorted inputs are caught
+        assert!(read_all(join(b"id\nb\na\n", right, JoinKind::Inner)?).is_err());
+        assert!(read_all(join(left, b"id\nc\nb\nd\n", JoinKind::Inner)?).is_err());
+        assert!(join(left, b"name\na\n", JoinKind::Inner).is_err());
+        assert!("outer".parse::<JoinKind>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_join_sorted_as_text() -> Result<(), EtError> {
+        // numbers are in `LC_ALL=C sort` order, not by value
+        let records = read_all(join(
+            b"id\tx\n10\ta\n9\tb\n",
+            b"id\ty\n10\t1\n2\t2\n9\t3\n",
+            JoinKind::Inner,
+        )?)?;
+        assert_eq!(
+            records,
+            [
+                [Value::Integer(10), "a".into(), Value::Integer(1)],
+                [Value::Integer(9), "b".into(), Value::Integer(3)],
+            ]
+        );
+        Ok(())
+    }
+
+    /// A reader with an `id` key and a `value` column that has column metadata
+    #[derive(Debug)]
+    struct KeyReader(Vec<Vec<Value<'static>>>);
+
+    impl RecordReader for KeyReader {
+        fn next_record(&mut self) -> Result<Option<Vec<Value<'_>>>, EtError> {
+            if self.0.is_empty() {
+                return Ok(None);
+            }
+            Ok(Some(self.0.remove(0)))
+        }
+
+        fn headers(&self) -> Vec<String> {
+            vec!["id".into(), "value".into()]
+        }
+
+        fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+            BTreeMap::new()
+        }
+
+        fn column_metadata(&self) -> BTreeMap<String, BTreeMap<String, Value<'_>>> {
+            let mut units = BTreeMap::new();
+            drop(units.insert("units".to_string(), "ms".into()));
+            let mut column_metadata = BTreeMap::new();
+            drop(column_metadata.insert("value".to_string(), units));
+            column_metadata
+        }
+    }
+
+    #[test]
+    fn test_join_nulls() -> Result<(), EtError> {
+        let left = KeyReader(vec![
+            vec![Value::Null, 1.into()],
+            vec!["a".into(), 2.into()],
+        ]);
+        let right = KeyReader(vec![
+            vec![Value::Null, 3.into()],
+            vec!["a".into(), 4.into()],
+        ]);
+        let reader = JoinReader::new(Box::new(left), Box::new(right), "id", JoinKind::Left)?;
+        assert_eq!(reader.headers(), ["id", "value", "value_right"]);
+        let column_metadata = reader.column_metadata();
+        assert_eq!(
+            column_metadata.keys().collect::<Vec<_>>(),
+            ["value", "value_right"]
+        );
+        // the null keys don't match each other
+        assert_eq!(
+            read_all(reader)?,
+            [
+                [Value::Null, 1.into(), Value::Null],
+                ["a".into(), 2.into(), 4.into()],
+            ]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,380 @@
+use alloc::borrow::Cow;
+use alloc::collections::{btree_map, BTreeMap, VecDeque};
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use core::str::from_utf8;
+
+use crate::buffer::ReadBuffer;
+use crate::capabilities::Capabilities;
+use crate::error::EtError;
+use crate::filetype::FileType;
+use crate::parsers::fasta::FastaReader;
+use crate::parsers::fastq::FastqReader;
+use crate::readers::RecordReader;
+use crate::record::Value;
+
+/// Parameters for which kmers a `KmerReader` returns
+#[derive(Clone, Copy, Debug)]
+pub struct KmerParams {
+    /// The length of each kmer
+    pub kmer_size: usize,
+    /// Return the lesser (alphabetically) of each kmer and its reverse complement so a kmer
+    /// is the same whichever strand it was read from
+    pub canonical: bool,
+    /// Only return the minimizer (the least kmer, alphabetically) of every window of this
+    /// many consecutive kmers
+    pub minimizer_window: Option<usize>,
+    /// Return how many times each kmer was seen in the file instead of every position
+    pub counts: bool,
+}
+
+impl Default for KmerParams {
+    fn default() -> Self {
+        KmerParams {
+            kmer_size: 21,
+            canonical: false,
+            minimizer_window: None,
+            counts: false,
+        }
+    }
+}
+
+impl KmerParams {
+    /// Set the length of each kmer
+    #[must_use]
+    pub fn kmer_size(mut self, kmer_size: usize) -> Self {
+        self.kmer_size = kmer_size;
+        self
+    }
+
+    /// Normalize each kmer to the lesser of it and its reverse complement
+    #[must_use]
+    pub fn canonical(mut self, canonical: bool) -> Self {
+        self.canonical = canonical;
+        self
+    }
+
+    /// Only return the minimizer of each window of `window` consecutive kmers
+    #[must_use]
+    pub fn minimizer_window(mut self, window: Option<usize>) -> Self {
+        self.minimizer_window = window;
+        self
+    }
+
+    /// Count the kmers instead of returning each one
+    #[must_use]
+    pub fn counts(mut self, counts: bool) -> Self {
+        self.counts = counts;
+        self
+    }
+}
+
+/// The reverse complement of a (valid, uppercase) kmer
+fn reverse_complement(kmer: &[u8]) -> Vec<u8> {
+    kmer.iter()
+        .rev()
+        .map(|b| match b {
+            b'A' => b'T',
+            b'C' => b'G',
+            b'G' => b'C',
+            _ => b'A',
+        })
+        .collect()
+}
+
+/// Where the sequences come from
+#[derive(Debug)]
+enum Sequences<'r> {
+    Fasta(FastaReader<'r>),
+    Fastq(FastqReader<'r>),
+}
+
+/// Splits the sequences in a FASTA or FASTQ file into kmers.
+///
+/// Each record is the `id` of a sequence, the (0-based) `pos` in it a kmer starts at, and the
+/// `kmer` itself; kmers are uppercased and any that include bases other than A, C, G, or T are
+/// skipped. `KmerParams` can normalize kmers to their canonical form, select only minimizers
+/// (minimizer windows don't span skipped kmers), or count the kmers instead, in which case each
+/// record is a `kmer` and its `count` (sorted by kmer) once the whole file has been read.
+#[derive(Debug)]
+pub struct KmerReader<'r> {
+    sequences: Sequences<'r>,
+    params: KmerParams,
+    id: String,
+    sequence: Vec<u8>,
+    /// The start of the next kmer to look at in `sequence`
+    pos: usize,
+    /// The kmers in the current window that could still be the minimizer of it or a later
+    /// window (in order, so each is no less than the ones before it)
+    window: VecDeque<(usize, Vec<u8>)>,
+    /// How many valid kmers in a row there have been (windows can't start until there are
+    /// enough)
+    run_len: usize,
+    /// The position of the last minimizer returned
+    last_minimizer: Option<usize>,
+    kmer: Vec<u8>,
+    /// The kmers and their counts left to return (once they've been counted)
+    counts: Option<btree_map::IntoIter<Vec<u8>, u64>>,
+}
+
+impl<'r> KmerReader<'r> {
+    /// Create a new `KmerReader` from the data of a FASTA or FASTQ file (which type is
+    /// detected from the data).
+    ///
+    /// # Errors
+    /// If the params are invalid or the file can't be read, an error is returned.
+    pub fn new<B>(data: B, params: Option<KmerParams>) -> Result<Self, EtError>
+    where
+        B: TryInto<ReadBuffer<'r>>,
+        EtError: From<<B as TryInto<ReadBuffer<'r>>>::Error>,
+    {
+        let params = params.unwrap_or_default();
+        if params.kmer_size == 0 {
+            return Err("kmer_size must be at least 1".into());
+        }
+        if params.minimizer_window == Some(0) {
+            return Err("minimizer_window must be at least 1".into());
+        }
+        let mut rb: ReadBuffer<'r> = data.try_into()?;
+        let sequences = if rb.sniff_filetype()? == FileType::Fastq {
+            Sequences::Fastq(FastqReader::new::<ReadBuffer<'r>>(rb, None)?)
+        } else {
+            Sequences::Fasta(FastaReader::new::<ReadBuffer<'r>>(rb, None)?)
+        };
+        Ok(KmerReader {
+            sequences,
+            params,
+            id: String::new(),
+            sequence: Vec::new(),
+            pos: 0,
+            window: VecDeque::new(),
+            run_len: 0,
+            last_minimizer: None,
+            kmer: Vec::new(),
+            counts: None,
+        })
+    }
+
+    /// Start on the next sequence in the file, returning `false` if there are no more
+    fn next_sequence(&mut self) -> Result<bool, EtError> {
+        let (id, sequence) = match &mut self.sequences {
+            Sequences::Fasta(reader) => match reader.next()? {
+                Some(record) => (record.id, record.sequence.into_owned()),
+                None => return Ok(false),
+            },
+            Sequences::Fastq(reader) => match reader.next()? {
+                Some(record) => (record.id, record.sequence.to_vec()),
+                None => return Ok(false),
+            },
+        };
+        self.id = id.to_string();
+        self.sequence = sequence;
+        self.sequence.make_ascii_uppercase();
+        self.pos = 0;
+        self.window.clear();
+        self.run_len = 0;
+        self.last_minimizer = None;
+        Ok(true)
+    }
+
+    /// Find the next kmer to return in the current sequence, returning its position and putting
+    /// it into `self.kmer`
+    fn next_kmer(&mut self) -> Option<usize> {
+        let k = self.params.kmer_size;
+        while self.pos + k <= self.sequence.len() {
+            let start = self.pos;
+            self.pos += 1;
+            let kmer = &self.sequence[start..start + k];
+            if !kmer.iter().all(|b| matches!(b, b'A' | b'C' | b'G' | b'T')) {
+                self.window.clear();
+                self.run_len = 0;
+                continue;
+            }
+            let mut kmer = kmer.to_vec();
+            if self.params.canonical {
+                let rc = reverse_complement(&kmer);
+                if rc < kmer {
+                    kmer = rc;
+                }
+            }
+            let window = match self.params.minimizer_window {
+                Some(window) => window,
+                None => {
+                    self.kmer = kmer;
+                    return Some(start);
+                }
+            };
+            // kmers that are greater than this one can't be the minimizer of any later windows
+            while self.window.back().is_some_and(|(_, last)| *last > kmer) {
+                let _ = self.window.pop_back();
+            }
+            self.window.push_back((start, kmer));
+            while self
+                .window
+                .front()
+                .is_some_and(|(pos, _)| pos + window <= start)
+            {
+                let _ = self.window.pop_front();
+            }
+            self.run_len += 1;
+            if self.run_len < window {
+                continue;
+            }
+            // consecutive windows often share a minimizer, but it's only returned once
+            if let Some((pos, minimizer)) = self.window.front() {
+                if self.last_minimizer != Some(*pos) {
+                    self.last_minimizer = Some(*pos);
+                    self.kmer.clone_from(minimizer);
+                    return Some(*pos);
+                }
+            }
+        }
+        None
+    }
+
+    /// Read through the whole file, counting the kmers in it
+    fn count_kmers(&mut self) -> Result<BTreeMap<Vec<u8>, u64>, EtError> {
+        let mut counts = BTreeMap::new();
+        while self.next_sequence()? {
+            while self.next_kmer().is_some() {
+                *counts.entry(self.kmer.clone()).or_insert(0) += 1;
+            }
+        }
+        Ok(counts)
+    }
+}
+
+impl<'r> RecordReader for KmerReader<'r> {
+    fn next_record(&mut self) -> Result<Option<Vec<Value<'_>>>, EtError> {
+        if self.params.counts {
+            if self.counts.is_none() {
+                self.counts = Some(self.count_kmers()?.into_iter());
+            }
+            return match self.counts.as_mut().and_then(Iterator::next) {
+                Some((kmer, count)) => {
+                    self.kmer = kmer;
+                    Ok(Some(vec![from_utf8(&self.kmer)?.into(), count.into()]))
+                }
+                None => Ok(None),
+            };
+        }
+        loop {
+            if let Some(pos) = self.next_kmer() {
+                return Ok(Some(vec![
+                    self.id.as_str().into(),
+                    (pos as u64).into(),
+                    Value::String(Cow::Borrowed(from_utf8(&self.kmer)?)),
+                ]));
+            }
+            if !self.next_sequence()? {
+                return Ok(None);
+            }
+        }
+    }
+
+    fn headers(&self) -> Vec<String> {
+        if self.params.counts {
+            vec!["kmer".to_string(), "count".to_string()]
+        } else {
+            vec!["id".to_string(), "pos".to_string(), "kmer".to_string()]
+        }
+    }
+
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        match &self.sequences {
+            Sequences::Fasta(reader) => reader.metadata(),
+            Sequences::Fastq(reader) => reader.metadata(),
+        }
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::TYPED_SCHEMA
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_kmers(data: &[u8], params: KmerParams) -> Result<Vec<(u64, String)>, EtError> {
+        let mut reader = KmerReader::new(data, Some(params))?;
+        let mut kmers = Vec::new();
+        while let Some(record) = reader.next_record()? {
+            let pos = match record[1] {
+                Value::Integer(pos) => pos as u64,
+                _ => panic!("positions should be integers"),
+            };
+            kmers.push((pos, record[2].clone().into_string()?));
+        }
+        Ok(kmers)
+    }
+
+    #[test]
+    fn test_kmers() -> Result<(), EtError> {
+        let params = KmerParams::default().kmer_size(3);
+        let mut reader = KmerReader::new(&b">a\nACgTT\n>b\nGG\n>c\nTTNGAA\n"[..], Some(params))?;
+        assert_eq!(reader.headers(), ["id", "pos", "kmer"]);
+        let record = reader.next_record()?.unwrap();
+        assert_eq!(record, ["a".into(), Value::Integer(0), "ACG".into()]);
+
+        let kmers = read_kmers(b">a\nACgTT\n>b\nGG\n>c\nTTNGAA\n", params)?;
+        let expected = [(0, "ACG"), (1, "CGT"), (2, "GTT"), (3, "GAA")];
+        assert_eq!(kmers.len(), expected.len());
+        for ((pos, kmer), (e_pos, e_kmer)) in kmers.iter().zip(&expected) {
+            assert_eq!((*pos, kmer.as_str()), (*e_pos, *e_kmer));
+        }
+
+        // FASTQ files work too
+        let kmers = read_kmers(b"@a\nTTTA\n+\n!!!!\n", params.canonical(true))?;
+        assert_eq!(kmers[0], (0, "AAA".to_string()));
+        assert_eq!(kmers[1], (1, "TAA".to_string()));
+
+        assert!(KmerReader::new(&b">a\nA\n"[..], Some(params.kmer_size(0))).is_err());
+        assert!(KmerReader::new(&b">a\nA\n"[..], Some(params.minimizer_window(Some(0)))).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_minimizers() -> Result<(), EtError> {
+        let params = KmerParams::default().kmer_size(2).minimizer_window(Some(3));
+        // the kmers are TG, GC, CA, AT, TT, TG, GT, and TC
+        let kmers = read_kmers(b">a\nTGCATTGTC\n", params)?;
+        let expected = [(2, "CA"), (3, "AT"), (6, "GT")];
+        assert_eq!(kmers.len(), expected.len());
+        for ((pos, kmer), (e_pos, e_kmer)) in kmers.iter().zip(&expected) {
+            assert_eq!((*pos, kmer.as_str()), (*e_pos, *e_kmer));
+        }
+
+        // windows don't span bases that aren't ACGT and the first position wins ties
+        let kmers = read_kmers(b">a\nCCCNAAAA\n", params)?;
+        assert_eq!(kmers, [(4, "AA".to_string())]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_kmer_counts() -> Result<(), EtError> {
+        let params = KmerParams::default()
+            .kmer_size(2)
+            .canonical(true)
+            .counts(true);
+        let mut reader = KmerReader::new(&b">a\nAACTT\n>b\nGTN\n"[..], Some(params))?;
+        assert_eq!(reader.headers(), ["kmer", "count"]);
+        // AA, AC, CT (AG), TT (AA), and GT (AC)
+        assert_eq!(
+            reader.next_record()?.unwrap(),
+            ["AA".into(), Value::Integer(2)]
+        );
+        assert_eq!(
+            reader.next_record()?.unwrap(),
+            ["AC".into(), Value::Integer(2)]
+        );
+        assert_eq!(
+            reader.next_record()?.unwrap(),
+            ["AG".into(), Value::Integer(1)]
+        );
+        assert!(reader.next_record()?.is_none());
+        Ok(())
+    }
+}
@@ -11,6 +11,18 @@
     unused_qualifications,
     unused_results
 )]
+// malformed files should return errors instead of aborting e.g. the WASM or FFI host
+#![cfg_attr(
+    not(test),
+    deny(
+        clippy::expect_used,
+        clippy::panic,
+        clippy::todo,
+        clippy::unimplemented,
+        clippy::unreachable,
+        clippy::unwrap_used
+    )
+)]
 //! entab is a library to parse different "record-formatted" file formats
 //! into tabular form.
 //!
@@ -55,22 +67,76 @@
 //! # use entab::EtError;
 //! # Ok::<(), EtError>(())
 //! ```
+//!
+//! # Panics
+//! Parsers shouldn't panic on any input, however malformed: bad or truncated data returns an
+//! `EtError` instead so e.g. the WebAssembly and Python/R bindings can't abort the program
+//! they're running in (the library code is linted against `panic!`, `unreachable!`, `unwrap`,
+//! and the like, and the minimized inputs in `tests/data/crashers` are checked against every
+//! parser). A panic on any input is a bug.
 
 extern crate alloc;
 
+/// Downsampling of trace data
+pub mod aggregate;
+/// Retention-time alignment of chromatograms
+pub mod align;
+/// Reading files bundled into archives
+#[cfg(feature = "std")]
+pub mod archive;
+/// Block-by-block reading of BGZF files (e.g. BAMs) for seeking with an index
+pub mod bgzf;
+/// Byte order marks at the start of text files
+pub mod bom;
 /// The buffer interface that underlies the file readers
 pub mod buffer;
+/// The version, features, and parsers of this build
+pub mod build_info;
+/// Stopping long-running parses from another thread
+pub mod cancel;
+/// Flags for what a reader can do beyond reading records in order
+pub mod capabilities;
+/// Splitting files into chunks that can be parsed independently
+#[cfg(feature = "std")]
+pub mod chunks;
+/// Reading records into a typed vector for each column
+pub mod columns;
 /// Generic file decompression
 pub mod compression;
+/// Per-position read depth of alignments
+pub mod depth;
 /// Miscellanous utility functions and error handling
 pub mod error;
 /// File format inference
 pub mod filetype;
+/// Stable hashes of records for deduplication and diffing
+pub mod hash;
+/// Streaming joins of two readers sorted by a key column
+pub mod join;
+/// Kmers, minimizers, and kmer counts of sequences
+pub mod kmers;
+/// Checks for suspicious values in records
+pub mod lint;
 /// Lightweight parsers to read records out of buffers
 pub mod parsers;
+/// Records of how a reader's records were produced
+pub mod provenance;
+/// Parsing data that's pushed in as it arrives
+#[cfg(feature = "std")]
+pub mod push;
 /// Parsers for specific file formats
 pub mod readers;
 /// Record and abstract record reading
 pub mod record;
+/// Statistics about the records read during a conversion
+pub mod report;
+/// Extracting the raw segments embedded in files (e.g. an FCS file's ANALYSIS segment)
+#[cfg(feature = "std")]
+pub mod segments;
+/// Single-row overviews of files
+pub mod summary;
+/// Writing records out into other formats
+#[cfg(feature = "std")]
+pub mod writers;
 
 pub use error::EtError;
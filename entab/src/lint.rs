@@ -0,0 +1,272 @@
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::readers::RecordReader;
+use crate::record::Value;
+
+/// A test for one kind of suspicious value (e.g. a negative time) that's run against every record.
+///
+/// Checks are created for a specific reader (so they can find the columns they look at from its
+/// headers) and can keep state between records (e.g. to compare a record to the one before it).
+pub trait Check: fmt::Debug {
+    /// A short name for the kind of problem found (e.g. `negative_time`) that warnings are
+    /// counted under
+    fn name(&self) -> &'static str;
+
+    /// Look at the next record and return a description of what's wrong with it, if anything
+    fn check(&mut self, record: &[Value]) -> Option<String>;
+}
+
+/// The numeric value of a field (if it's a number)
+fn as_number(value: Option<&Value>) -> Option<f64> {
+    match value {
+        Some(Value::Float(f)) => Some(*f),
+        Some(Value::Integer(i)) => Some(*i as f64),
+        _ => None,
+    }
+}
+
+/// Flags times before the start of the run
+#[derive(Clone, Copy, Debug)]
+pub struct NegativeTime {
+    column: usize,
+}
+
+impl Check for NegativeTime {
+    fn name(&self) -> &'static str {
+        "negative_time"
+    }
+
+    fn check(&mut self, record: &[Value]) -> Option<String> {
+        let time = as_number(record.get(self.column))?;
+        (time < 0.).then(|| format!("time {} is negative", time))
+    }
+}
+
+/// Flags times that are earlier than the time of the record before them
+#[derive(Clone, Copy, Debug)]
+pub struct TimeOrder {
+    column: usize,
+    last: Option<f64>,
+}
+
+impl Check for TimeOrder {
+    fn name(&self) -> &'static str {
+        "non_monotonic_time"
+    }
+
+    fn check(&mut self, record: &[Value]) -> Option<String> {
+        let time = as_number(record.get(self.column))?;
+        let last = self.last.replace(time)?;
+        (time < last).then(|| format!("time {} is before the previous time {}", time, last))
+    }
+}
+
+/// Flags quality strings that aren't the same length as their sequence
+#[derive(Clone, Copy, Debug)]
+pub struct QualityLength {
+    sequence: usize,
+    quality: usize,
+}
+
+impl Check for QualityLength {
+    fn name(&self) -> &'static str {
+        "quality_length"
+    }
+
+    fn check(&mut self, record: &[Value]) -> Option<String> {
+        let (sequence, quality) = match (record.get(self.sequence), record.get(self.quality)) {
+            (Some(Value::String(s)), Some(Value::String(q))) => (s.len(), q.len()),
+            _ => return None,
+        };
+        // records without quality scores (e.g. a `*` in a SAM file) are fine
+        (quality != 0 && quality != sequence).then(|| {
+            format!(
+                "quality has {} scores for a sequence of length {}",
+                quality, sequence
+            )
+        })
+    }
+}
+
+/// Flags integer values at the top of their column's range (e.g. a saturated FCS detector);
+/// float values are skipped because their range isn't a hard limit on what can be stored.
+#[derive(Clone, Debug)]
+pub struct RangeCeiling {
+    /// The index, name, and largest possible value of each column with a range
+    columns: Vec<(usize, String, f64)>,
+}
+
+impl Check for RangeCeiling {
+    fn name(&self) -> &'static str {
+        "range_ceiling"
+    }
+
+    fn check(&mut self, record: &[Value]) -> Option<String> {
+        let saturated: Vec<&str> = self
+            .columns
+            .iter()
+            .filter(|(ix, _, max)| match record.get(*ix) {
+                Some(Value::Integer(i)) => *i as f64 >= *max,
+                _ => false,
+            })
+            .map(|(_, name, _)| name.as_str())
+            .collect();
+        (!saturated.is_empty()).then(|| format!("{} at the top of the range", saturated.join(", ")))
+    }
+}
+
+/// The checks that apply to the records `reader` returns, based on its headers and column
+/// metadata.
+#[must_use]
+pub fn default_checks(reader: &dyn RecordReader) -> Vec<Box<dyn Check>> {
+    let headers = reader.headers();
+    let position = |name: &str| headers.iter().position(|h| h == name);
+    let mut checks: Vec<Box<dyn Check>> = Vec::new();
+    if let Some(column) = position(reader.summary_fields().time) {
+        checks.push(Box::new(NegativeTime { column }));
+        checks.push(Box::new(TimeOrder { column, last: None }));
+    }
+    if let (Some(sequence), Some(quality)) = (position("sequence"), position("quality")) {
+        checks.push(Box::new(QualityLength { sequence, quality }));
+    }
+    let columns: Vec<(usize, String, f64)> = reader
+        .column_metadata()
+        .into_iter()
+        .filter_map(|(name, metadata)| {
+            // integer values go from 0 to one less than the range
+            let max = as_number(metadata.get("range"))? - 1.;
+            Some((position(&name)?, name, max))
+        })
+        .collect();
+    if !columns.is_empty() {
+        checks.push(Box::new(RangeCeiling { columns }));
+    }
+    checks
+}
+
+/// A problem found with a record
+#[derive(Clone, Debug, PartialEq)]
+pub struct Warning {
+    /// The name of the check that found it
+    pub check: &'static str,
+    /// What's wrong with the record
+    pub message: String,
+}
+
+/// Runs a set of `Check`s over records and counts how many times each one found a problem.
+#[derive(Debug, Default)]
+pub struct Linter {
+    checks: Vec<Box<dyn Check>>,
+    counts: BTreeMap<&'static str, usize>,
+}
+
+impl Linter {
+    /// Create a linter with the `default_checks` for `reader`
+    #[must_use]
+    pub fn for_reader(reader: &dyn RecordReader) -> Self {
+        Linter::new(default_checks(reader))
+    }
+
+    /// Create a linter that runs `checks`
+    #[must_use]
+    pub fn new(checks: Vec<Box<dyn Check>>) -> Self {
+        Linter {
+            checks,
+            counts: BTreeMap::new(),
+        }
+    }
+
+    /// Add another check (e.g. one specific to a single instrument)
+    pub fn add_check(&mut self, check: Box<dyn Check>) {
+        self.checks.push(check);
+    }
+
+    /// Run every check against the next record and return the problems found
+    pub fn check(&mut self, record: &[Value]) -> Vec<Warning> {
+        let mut warnings = Vec::new();
+        for check in &mut self.checks {
+            if let Some(message) = check.check(record) {
+                *self.counts.entry(check.name()).or_default() += 1;
+                warnings.push(Warning {
+                    check: check.name(),
+                    message,
+                });
+            }
+        }
+        warnings
+    }
+
+    /// The number of records each check has found a problem with so far
+    #[must_use]
+    pub fn counts(&self) -> &BTreeMap<&'static str, usize> {
+        &self.counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+    use crate::parsers::tsv::TsvReader;
+
+    #[test]
+    fn test_time_checks() -> Result<(), crate::EtError> {
+        let data = b"time\tintensity\n-0.5\t1\n1\t2\n0.5\t3\n";
+        let mut reader = TsvReader::new(&data[..], None)?;
+        let mut linter = Linter::for_reader(&reader);
+        let mut warnings = Vec::new();
+        while let Some(record) = reader.next_record()? {
+            warnings.extend(linter.check(&record));
+        }
+        assert_eq!(
+            warnings,
+            vec![
+                Warning {
+                    check: "negative_time",
+                    message: "time -0.5 is negative".into(),
+                },
+                Warning {
+                    check: "non_monotonic_time",
+                    message: "time 0.5 is before the previous time 1".into(),
+                },
+            ]
+        );
+        assert_eq!(linter.counts()["negative_time"], 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_quality_length() -> Result<(), crate::EtError> {
+        let data = b"id\tsequence\tquality\na\tACGT\tIIII\nb\tACGT\tII\n";
+        let mut reader = TsvReader::new(&data[..], None)?;
+        let mut linter = Linter::for_reader(&reader);
+        assert!(linter.check(&reader.next_record()?.unwrap()).is_empty());
+        let warnings = linter.check(&reader.next_record()?.unwrap());
+        assert_eq!(warnings[0].check, "quality_length");
+        Ok(())
+    }
+
+    #[test]
+    fn test_range_ceiling() {
+        let mut check = RangeCeiling {
+            columns: vec![(1, "FL1-A".into(), 1023.)],
+        };
+        assert!(check
+            .check(&[Value::Float(1.), Value::Integer(1000)])
+            .is_none());
+        assert_eq!(
+            check.check(&[Value::Float(1.), Value::Integer(1023)]),
+            Some("FL1-A at the top of the range".into())
+        );
+        // float channels can go past their range
+        assert!(check
+            .check(&[Value::Float(1.), Value::Float(4096.)])
+            .is_none());
+    }
+}
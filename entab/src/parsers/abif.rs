@@ -0,0 +1,411 @@
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+use chrono::NaiveDate;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::capabilities::Capabilities;
+use crate::parsers::text::TextDecoding;
+use crate::parsers::{extract, extract_with, Endian, FromSlice};
+use crate::record::{StateMetadata, Value};
+use crate::summary::SummaryFields;
+use crate::EtError;
+use crate::{impl_reader, impl_record};
+
+/// The size of an entry in the directory
+const ENTRY_SIZE: usize = 28;
+
+/// The `DATA` tag numbers of the raw traces for dyes 1 through 8 (the fifth and later dyes were
+/// added after the original four so they're numbered differently)
+const DYE_DATA_NUMBERS: [i32; 8] = [1, 2, 3, 4, 105, 106, 107, 108];
+
+/// A single tagged item in an ABIF file
+#[derive(Clone, Copy, Debug)]
+struct AbifEntry<'b> {
+    name: &'b [u8],
+    number: i32,
+    element_type: i16,
+    data: &'b [u8],
+}
+
+impl<'b> AbifEntry<'b> {
+    /// Read text from a pString, cString, or char array entry
+    fn text(&self, decoding: TextDecoding) -> Result<String, EtError> {
+        let raw = match self.element_type {
+            // pString
+            18 => self
+                .data
+                .get(1..1 + usize::from(*self.data.first().unwrap_or(&0)))
+                .ok_or("ABIF string is longer than its entry")?,
+            _ => self.data,
+        };
+        let raw = raw.split(|c| *c == 0).next().unwrap_or(raw);
+        Ok(decoding.decode(raw)?.trim().to_string())
+    }
+
+    /// Read an array of big-endian shorts
+    fn shorts(&self) -> Vec<i16> {
+        self.data
+            .chunks_exact(2)
+            .map(|c| i16::from_be_bytes([c[0], c[1]]))
+            .collect()
+    }
+}
+
+/// Read the directory out of an ABIF file along with the position of the end of the last
+/// piece of data it points to (an "incomplete" error is returned if that's not all in `buf`).
+fn read_directory(buf: &[u8]) -> Result<(Vec<AbifEntry<'_>>, usize), EtError> {
+    if buf.len() < 4 {
+        return Err(EtError::from("ABIF header is incomplete").incomplete());
+    }
+    if &buf[..4] != b"ABIF" {
+        return Err("Not an ABIF file".into());
+    }
+    // the root entry (starting at byte 6) points to the directory
+    let n_entries = usize::try_from(extract_with::<i32>(buf, &mut 18, &Endian::Big)?)?;
+    let dir_start = usize::try_from(extract_with::<i32>(buf, &mut 26, &Endian::Big)?)?;
+    let mut end = dir_start + n_entries * ENTRY_SIZE;
+    // a corrupt count shouldn't allocate more entries than could fit in the buffer
+    let mut entries = Vec::with_capacity(n_entries.min(buf.len() / ENTRY_SIZE));
+    let mut entry_size = ENTRY_SIZE;
+    for ix in 0..n_entries {
+        let entry = extract::<&[u8]>(buf, &mut (dir_start + ix * ENTRY_SIZE), &mut entry_size)?;
+        let mut data_size = usize::try_from(extract_with::<i32>(entry, &mut 16, &Endian::Big)?)?;
+        // data of four bytes or less is stored in the entry itself
+        let data = if data_size <= 4 {
+            &entry[20..20 + data_size]
+        } else {
+            let mut data_start =
+                usize::try_from(extract_with::<i32>(entry, &mut 20, &Endian::Big)?)?;
+            end = end.max(data_start + data_size);
+            extract::<&[u8]>(buf, &mut data_start, &mut data_size)?
+        };
+        entries.push(AbifEntry {
+            name: &entry[..4],
+            number: extract_with::<i32>(entry, &mut 4, &Endian::Big)?,
+            element_type: extract_with::<i16>(entry, &mut 8, &Endian::Big)?,
+            data,
+        });
+    }
+    Ok((entries, end))
+}
+
+/// The trace for a single dye
+#[derive(Clone, Debug, Default)]
+struct AbifChannel {
+    dye: String,
+    data: Vec<i16>,
+}
+
+/// The current state of the ABIF reader
+#[derive(Clone, Debug, Default)]
+pub struct AbifState {
+    channels: Vec<AbifChannel>,
+    n_scans: usize,
+    cur_scan: usize,
+    cur_channel: usize,
+    metadata: BTreeMap<String, Value<'static>>,
+}
+
+impl StateMetadata for AbifState {
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        self.metadata.clone()
+    }
+
+    fn summary_fields(&self) -> SummaryFields {
+        SummaryFields {
+            time: "scan",
+            ..SummaryFields::default()
+        }
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::HAS_METADATA | Capabilities::TYPED_SCHEMA
+    }
+
+    fn header(&self) -> Vec<&str> {
+        vec!["scan", "dye", "intensity"]
+    }
+}
+
+impl<'b: 's, 's> FromSlice<'b, 's> for AbifState {
+    type State = TextDecoding;
+
+    fn parse(
+        buf: &[u8],
+        eof: bool,
+        consumed: &mut usize,
+        _state: &mut Self::State,
+    ) -> Result<bool, EtError> {
+        match read_directory(buf) {
+            Ok((_, end)) => {
+                *consumed += end;
+                Ok(true)
+            }
+            Err(e) if e.incomplete && eof => Err("ABIF file is truncated".into()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn get(&mut self, buf: &'b [u8], decoding: &'s Self::State) -> Result<(), EtError> {
+        let (entries, _) = read_directory(buf)?;
+        let find = |name: &[u8], number: i32| {
+            entries
+                .iter()
+                .find(|e| e.name == name && e.number == number)
+        };
+
+        let mut metadata = BTreeMap::new();
+        for (tag, key) in [
+            (b"SMPL", "sample"),
+            (b"User", "operator"),
+            (b"TUBE", "well"),
+            (b"CTID", "plate"),
+            (b"RunN", "run_name"),
+            (b"MCHN", "instrument"),
+            (b"MODL", "model"),
+            (b"DySN", "dye_set"),
+            (b"StdF", "size_standard"),
+        ] {
+            if let Some(entry) = find(tag, 1) {
+                let text = entry.text(*decoding)?;
+                if !text.is_empty() {
+                    drop(metadata.insert(key.to_string(), text.into()));
+                }
+            }
+        }
+        if let (Some(date), Some(time)) = (find(b"RUND", 1), find(b"RUNT", 1)) {
+            let (d, t) = (date.data, time.data);
+            if d.len() >= 4 && t.len() >= 3 {
+                let datetime = NaiveDate::from_ymd_opt(
+                    i32::from(i16::from_be_bytes([d[0], d[1]])),
+                    u32::from(d[2]),
+                    u32::from(d[3]),
+                )
+                .and_then(|d| d.and_hms_opt(u32::from(t[0]), u32::from(t[1]), u32::from(t[2])));
+                if let Some(datetime) = datetime {
+                    drop(metadata.insert("run_date".to_string(), datetime.into()));
+                }
+            }
+        }
+
+        let n_dyes = match find(b"Dye#", 1).and_then(|e| e.shorts().first().copied()) {
+            Some(n) => usize::try_from(n)?,
+            None => DYE_DATA_NUMBERS.len(),
+        };
+        let mut channels = Vec::new();
+        let mut wavelengths = Vec::new();
+        for (ix, number) in DYE_DATA_NUMBERS.iter().take(n_dyes).enumerate() {
+            let dye_number = i32::try_from(ix + 1)?;
+            let data = match find(b"DATA", *number) {
+                Some(entry) => entry.shorts(),
+                None => continue,
+            };
+            let dye = match find(b"DyeN", dye_number) {
+                Some(entry) => entry.text(*decoding)?,
+                None => format!("dye{}", dye_number),
+            };
+            if let Some(w) = find(b"DyeW", dye_number).and_then(|e| e.shorts().first().copied()) {
+                wavelengths.push(Value::from(i32::from(w)));
+            }
+            channels.push(AbifChannel { dye, data });
+        }
+        if channels.is_empty() {
+            return Err("ABIF file has no dye traces".into());
+        }
+        let dyes: Vec<Value> = channels.iter().map(|c| c.dye.clone().into()).collect();
+        if metadata.contains_key("size_standard") {
+            // by convention the size standard is run in the last dye (e.g. LIZ or ROX)
+            drop(metadata.insert(
+                "size_standard_dye".to_string(),
+                dyes[dyes.len() - 1].clone(),
+            ));
+        }
+        drop(metadata.insert("dyes".to_string(), dyes.into()));
+        if wavelengths.len() == channels.len() {
+            drop(metadata.insert("dye_wavelengths".to_string(), wavelengths.into()));
+        }
+
+        self.n_scans = channels.iter().map(|c| c.data.len()).min().unwrap_or(0);
+        self.channels = channels;
+        self.cur_scan = 0;
+        self.cur_channel = 0;
+        self.metadata = metadata;
+        Ok(())
+    }
+}
+
+/// A single reading of one dye's trace from an ABIF file
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AbifRecord<'r> {
+    /// The scan (i.e. the time point) the reading was made at
+    pub scan: u32,
+    /// The name of the dye (e.g. `6-FAM`)
+    pub dye: &'r str,
+    /// The raw fluorescence intensity
+    pub intensity: f64,
+}
+
+impl_record!(AbifRecord<'r>: scan, dye, intensity);
+
+impl<'b: 's, 's> FromSlice<'b, 's> for AbifRecord<'s> {
+    type State = AbifState;
+
+    fn parse(
+        _buf: &[u8],
+        _eof: bool,
+        _consumed: &mut usize,
+        state: &mut Self::State,
+    ) -> Result<bool, EtError> {
+        // all of the data was read into the state so records don't use the buffer
+        if state.cur_channel >= state.channels.len() {
+            state.cur_channel = 0;
+            state.cur_scan += 1;
+        }
+        if state.cur_scan >= state.n_scans {
+            return Ok(false);
+        }
+        state.cur_channel += 1;
+        Ok(true)
+    }
+
+    fn get(&mut self, _buf: &'b [u8], state: &'s Self::State) -> Result<(), EtError> {
+        let channel = &state.channels[state.cur_channel - 1];
+        self.scan = u32::try_from(state.cur_scan)?;
+        self.dye = &channel.dye;
+        self.intensity = f64::from(channel.data[state.cur_scan]);
+        Ok(())
+    }
+}
+
+impl_reader!(
+    /// A reader for Applied Biosystems ABIF files from capillary electrophoresis instruments
+    /// (`.fsa` fragment analysis and `.ab1` sequencing files).
+    ///
+    /// Each record is the raw intensity of one dye at one scan (the dyes for each scan are
+    /// returned together); the size standard used and the dyes' names and wavelengths are in
+    /// the metadata.
+    AbifReader,
+    AbifRecord,
+    AbifRecord<'r>,
+    AbifState,
+    TextDecoding
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::readers::RecordReader;
+
+    /// Build an ABIF file out of (tag, number, element type, data) entries
+    fn abif(entries: &[(&[u8; 4], i32, i16, Vec<u8>)]) -> Vec<u8> {
+        let mut data = b"ABIF\x00\x65".to_vec();
+        data.resize(128, 0);
+        let mut directory = Vec::new();
+        for (name, number, element_type, value) in entries {
+            directory.extend(&name[..]);
+            directory.extend(&number.to_be_bytes());
+            directory.extend(&element_type.to_be_bytes());
+            directory.extend(&1i16.to_be_bytes());
+            directory.extend(&(value.len() as i32).to_be_bytes());
+            directory.extend(&(value.len() as i32).to_be_bytes());
+            if value.len() <= 4 {
+                let mut inline = value.clone();
+                inline.resize(4, 0);
+                directory.extend(inline);
+            } else {
+                directory.extend(&(data.len() as i32).to_be_bytes());
+                data.extend(value);
+            }
+            directory.extend(&[0; 4]);
+        }
+        let dir_start = data.len() as i32;
+        data[6..10].copy_from_slice(b"tdir");
+        data[18..22].copy_from_slice(&(entries.len() as i32).to_be_bytes());
+        data[26..30].copy_from_slice(&dir_start.to_be_bytes());
+        data.extend(directory);
+        data
+    }
+
+    fn shorts(values: &[i16]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_be_bytes()).collect()
+    }
+
+    fn pstring(text: &str) -> Vec<u8> {
+        let mut data = vec![text.len() as u8];
+        data.extend(text.as_bytes());
+        data
+    }
+
+    #[test]
+    fn test_fsa_reader() -> Result<(), EtError> {
+        let data = abif(&[
+            (b"Dye#", 1, 4, shorts(&[2])),
+            (b"DyeN", 1, 18, pstring("6-FAM")),
+            (b"DyeN", 2, 18, pstring("GS500LIZ")),
+            (b"DyeW", 1, 4, shorts(&[522])),
+            (b"DyeW", 2, 4, shorts(&[655])),
+            (b"DATA", 1, 4, shorts(&[10, 20, 30])),
+            (b"DATA", 2, 4, shorts(&[-1, 500, 7])),
+            (b"SMPL", 1, 18, pstring("sample A1")),
+            (b"StdF", 1, 18, pstring("GS500LIZ.xml")),
+            (b"RUND", 1, 10, vec![0x07, 0xE4, 3, 4]),
+            (b"RUNT", 1, 11, vec![13, 14, 15, 0]),
+        ]);
+        let mut reader = AbifReader::new(&data[..], None)?;
+        let metadata = reader.metadata();
+        assert_eq!(metadata["sample"], "sample A1".into());
+        assert_eq!(metadata["size_standard"], "GS500LIZ.xml".into());
+        assert_eq!(metadata["size_standard_dye"], "GS500LIZ".into());
+        assert_eq!(
+            metadata["dye_wavelengths"],
+            Value::List(vec![522.into(), 655.into()])
+        );
+        assert_eq!(
+            metadata["run_date"],
+            NaiveDate::from_ymd_opt(2020, 3, 4)
+                .unwrap()
+                .and_hms_opt(13, 14, 15)
+                .unwrap()
+                .into()
+        );
+
+        let record = reader.next()?.unwrap();
+        assert_eq!(
+            record,
+            AbifRecord {
+                scan: 0,
+                dye: "6-FAM",
+                intensity: 10.
+            }
+        );
+        let record = reader.next()?.unwrap();
+        assert_eq!((record.scan, record.dye), (0, "GS500LIZ"));
+        assert_eq!(record.intensity, -1.);
+        let mut n_records = 2;
+        while let Some(record) = reader.next()? {
+            n_records += 1;
+            if n_records == 6 {
+                assert_eq!((record.scan, record.intensity), (2, 7.));
+            }
+        }
+        assert_eq!(n_records, 6);
+        Ok(())
+    }
+
+    #[test]
+    fn test_bad_abif() {
+        let data = abif(&[(b"SMPL", 1, 18, pstring("no traces"))]);
+        assert!(AbifReader::new(&data[..], None).is_err());
+
+        let data = abif(&[(b"DATA", 1, 4, shorts(&[1, 2, 3, 4]))]);
+        assert!(AbifReader::new(&data[..data.len() - 10], None).is_err());
+        assert!(AbifReader::new(&b"ABIX"[..], None).is_err());
+    }
+}
@@ -1,19 +1,28 @@
 use alloc::collections::BTreeMap;
 use alloc::str;
-use alloc::string::String;
+use alloc::string::{String, ToString};
 use alloc::vec;
 use alloc::vec::Vec;
 use core::marker::Copy;
 
-use crate::parsers::agilent::metadata::ChemstationMetadata;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::capabilities::Capabilities;
+use crate::parsers::agilent::metadata::{ChemstationMetadata, ChemstationParams};
 use crate::parsers::agilent::read_agilent_header;
-use crate::parsers::{extract, Endian, FromSlice};
-use crate::record::{StateMetadata, Value};
+use crate::parsers::{extract, extract_with, sub_or_err, Endian, FromSlice};
+use crate::record::{PushFields, StateMetadata, Value};
 use crate::EtError;
 use crate::{impl_reader, impl_record};
 
 const CHEMSTATION_TIME_STEP: f64 = 0.2;
 
+/// Chemstation MS files store times in milliseconds (and they're reported in minutes)
+const MS_TIME_DIVISOR: f64 = 60000.;
+/// Chemstation MS files store m/z's in twentieths
+const MS_MZ_DIVISOR: f64 = 20.;
+
 #[derive(Clone, Debug, Default)]
 /// Internal state for the `ChemstationFidRecord` parser
 pub struct ChemstationFidState {
@@ -29,13 +38,25 @@ impl StateMetadata for ChemstationFidState {
         (&self.metadata).into()
     }
 
+    fn column_metadata(&self) -> BTreeMap<String, BTreeMap<String, Value<'_>>> {
+        self.metadata.column_metadata()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::HAS_METADATA | Capabilities::TYPED_SCHEMA
+    }
+
     fn header(&self) -> Vec<&str> {
-        vec!["time", "intensity"]
+        let mut header = vec!["time", "intensity"];
+        if self.metadata.raw_values {
+            header.push("corrected_intensity");
+        }
+        header
     }
 }
 
 impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationFidState {
-    type State = ();
+    type State = ChemstationParams;
 
     fn parse(
         rb: &[u8],
@@ -47,8 +68,8 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationFidState {
         Ok(true)
     }
 
-    fn get(&mut self, rb: &'b [u8], _state: &'s Self::State) -> Result<(), EtError> {
-        let metadata = ChemstationMetadata::from_header(rb)?;
+    fn get(&mut self, rb: &'b [u8], params: &'s Self::State) -> Result<(), EtError> {
+        let metadata = ChemstationMetadata::from_header(rb, *params)?;
         // offset the current time back one step so it'll be right after the first time that parse
         self.cur_time = metadata.start_time - CHEMSTATION_TIME_STEP;
         self.cur_intensity = 0.;
@@ -60,15 +81,32 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationFidState {
 }
 
 #[derive(Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 /// A point in a FID trace
 pub struct ChemstationFidRecord {
     /// The time recorded at
     pub time: f64,
     /// The intensity recorded
     pub intensity: f64,
+    /// The intensity after the file's corrections (only if the `raw_values` param was set, in
+    /// which case `intensity` is the uncorrected value)
+    pub corrected_intensity: Option<f64>,
 }
 
-impl_record!(ChemstationFidRecord: time, intensity);
+impl<'r> From<ChemstationFidRecord> for Vec<Value<'r>> {
+    fn from(record: ChemstationFidRecord) -> Self {
+        let mut values = Vec::with_capacity(3);
+        record.push_fields(&mut values);
+        values
+    }
+}
+
+impl<'r> PushFields<'r> for ChemstationFidRecord {
+    fn push_fields<E: Extend<Value<'r>>>(self, fields: &mut E) {
+        fields.extend([self.time.into(), self.intensity.into()]);
+        fields.extend(self.corrected_intensity.map(Value::from));
+    }
+}
 
 impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationFidRecord {
     type State = ChemstationFidState;
@@ -88,10 +126,10 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationFidRecord {
             return Err(EtError::from("Incomplete FID file").incomplete());
         }
 
-        let intensity: i16 = extract(buffer, con, &mut Endian::Big)?;
+        let intensity: i16 = extract_with(buffer, con, &Endian::Big)?;
         if intensity == 32767 {
-            let high_value: i32 = extract(buffer, con, &mut Endian::Big)?;
-            let low_value: u16 = extract(buffer, con, &mut Endian::Big)?;
+            let high_value: i32 = extract_with(buffer, con, &Endian::Big)?;
+            let low_value: u16 = extract_with(buffer, con, &Endian::Big)?;
             state.cur_delta = 0.;
             state.cur_intensity = f64::from(high_value) * 65534. + f64::from(low_value);
         } else {
@@ -106,8 +144,8 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationFidRecord {
 
     fn get(&mut self, _buf: &'b [u8], state: &'s Self::State) -> Result<(), EtError> {
         self.time = state.cur_time;
-        self.intensity =
-            state.cur_intensity * state.metadata.mult_correction + state.metadata.offset_correction;
+        (self.intensity, self.corrected_intensity) =
+            state.metadata.intensities(state.cur_intensity);
         Ok(())
     }
 }
@@ -117,9 +155,10 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationFidRecord {
 pub struct ChemstationMsState {
     n_scans_left: usize,
     n_mzs_left: usize,
-    cur_time: f64,
-    cur_mz: f64,
-    cur_intensity: f64,
+    cur_time: u32,
+    cur_mz: u16,
+    cur_intensity: u32,
+    integer_values: bool,
     metadata: ChemstationMetadata,
 }
 
@@ -128,13 +167,37 @@ impl StateMetadata for ChemstationMsState {
         (&self.metadata).into()
     }
 
+    fn column_metadata(&self) -> BTreeMap<String, BTreeMap<String, Value<'_>>> {
+        let mut column_metadata = BTreeMap::new();
+        for (column, units, divisor) in [
+            ("time", "min", MS_TIME_DIVISOR),
+            ("mz", "m/z", MS_MZ_DIVISOR),
+        ] {
+            let scale = 1. / divisor;
+            let mut metadata = BTreeMap::new();
+            drop(metadata.insert("units".to_string(), units.into()));
+            // the smallest difference the file can store between two values
+            drop(metadata.insert("precision".to_string(), scale.into()));
+            if self.integer_values {
+                drop(metadata.insert("scale".to_string(), scale.into()));
+                drop(metadata.insert("offset".to_string(), 0.0.into()));
+            }
+            drop(column_metadata.insert(column.to_string(), metadata));
+        }
+        column_metadata
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::HAS_METADATA | Capabilities::TYPED_SCHEMA
+    }
+
     fn header(&self) -> Vec<&str> {
         vec!["time", "mz", "intensity"]
     }
 }
 
 impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationMsState {
-    type State = ();
+    type State = ChemstationParams;
 
     fn parse(
         buffer: &[u8],
@@ -146,17 +209,19 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationMsState {
         Ok(true)
     }
 
-    fn get(&mut self, buffer: &'b [u8], _state: &'s Self::State) -> Result<(), EtError> {
-        let metadata = ChemstationMetadata::from_header(buffer)?;
-        let n_scans = u32::extract(&buffer[278..], &Endian::Big)? as usize;
+    fn get(&mut self, buffer: &'b [u8], params: &'s Self::State) -> Result<(), EtError> {
+        let metadata = ChemstationMetadata::from_header(buffer, *params)?;
+        let n_scans = extract_with::<u32>(buffer, &mut 278, &Endian::Big)? as usize;
 
         self.n_scans_left = n_scans;
+        self.integer_values = params.integer_values;
         self.metadata = metadata;
         Ok(())
     }
 }
 
 #[derive(Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 /// A single time/mz record from a Chemstation MS file
 pub struct ChemstationMsRecord {
     /// The time recorded at
@@ -187,12 +252,10 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationMsRecord {
         let mut n_mzs_left = state.n_mzs_left;
         while n_mzs_left == 0 {
             // handle the record header
-            let raw_n_mzs_left: u16 = extract(rb, con, &mut Endian::Big)?;
-            if raw_n_mzs_left < 14 {
-                return Err("Invalid Chemstation MS record header".into());
-            }
-            n_mzs_left = usize::from((raw_n_mzs_left - 14) / 2);
-            state.cur_time = f64::from(extract::<u32>(rb, con, &mut Endian::Big)?) / 60000.;
+            let raw_n_mzs_left: u16 = extract_with(rb, con, &Endian::Big)?;
+            n_mzs_left =
+                usize::from(sub_or_err(raw_n_mzs_left, 14, "Chemstation MS record header")? / 2);
+            state.cur_time = extract_with::<u32>(rb, con, &Endian::Big)?;
             // eight more bytes of unknown information and then last 4 bytes
             // is a u16/u16 pair for the highest peak?
             let _ = extract::<&[u8]>(rb, con, &mut 12)?;
@@ -207,10 +270,10 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationMsRecord {
         }
 
         // just read the mz/intensity
-        state.cur_mz = f64::from(extract::<u16>(rb, con, &mut Endian::Big)?) / 20.;
-        let raw_intensity: u16 = extract(rb, con, &mut Endian::Big)?;
-        state.cur_intensity =
-            f64::from(raw_intensity & 16383) * 8f64.powi(i32::from(raw_intensity) >> 14);
+        state.cur_mz = extract_with::<u16>(rb, con, &Endian::Big)?;
+        // the intensity is a 14-bit mantissa and a 2-bit exponent (in powers of 8)
+        let raw_intensity: u16 = extract_with(rb, con, &Endian::Big)?;
+        state.cur_intensity = u32::from(raw_intensity & 16383) << (3 * (raw_intensity >> 14));
         if n_mzs_left == 1 {
             state.n_scans_left -= 1;
             // eat the footer
@@ -223,6 +286,41 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationMsRecord {
         Ok(true)
     }
 
+    fn get(&mut self, _buf: &'b [u8], state: &'s Self::State) -> Result<(), EtError> {
+        self.time = f64::from(state.cur_time) / MS_TIME_DIVISOR;
+        self.mz = f64::from(state.cur_mz) / MS_MZ_DIVISOR;
+        self.intensity = f64::from(state.cur_intensity);
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// A single time/mz record from a Chemstation MS file with the values as they're stored
+/// (see the `scale` in the column metadata)
+pub struct ChemstationMsIntegerRecord {
+    /// The time recorded at (in milliseconds)
+    pub time: u32,
+    /// The m/z recorded at (in twentieths)
+    pub mz: u16,
+    /// The intensity recorded
+    pub intensity: u32,
+}
+
+impl_record!(ChemstationMsIntegerRecord: time, mz, intensity);
+
+impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationMsIntegerRecord {
+    type State = ChemstationMsState;
+
+    fn parse(
+        rb: &[u8],
+        eof: bool,
+        consumed: &mut usize,
+        state: &mut Self::State,
+    ) -> Result<bool, EtError> {
+        ChemstationMsRecord::parse(rb, eof, consumed, state)
+    }
+
     fn get(&mut self, _buf: &'b [u8], state: &'s Self::State) -> Result<(), EtError> {
         self.time = state.cur_time;
         self.mz = state.cur_mz;
@@ -246,13 +344,25 @@ impl StateMetadata for ChemstationMwdState {
         (&self.metadata).into()
     }
 
+    fn column_metadata(&self) -> BTreeMap<String, BTreeMap<String, Value<'_>>> {
+        self.metadata.column_metadata()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::HAS_METADATA | Capabilities::TYPED_SCHEMA
+    }
+
     fn header(&self) -> Vec<&str> {
-        vec!["time", "signal", "intensity"]
+        let mut header = vec!["time", "signal", "intensity"];
+        if self.metadata.raw_values {
+            header.push("corrected_intensity");
+        }
+        header
     }
 }
 
 impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationMwdState {
-    type State = ();
+    type State = ChemstationParams;
 
     fn parse(
         rb: &[u8],
@@ -264,8 +374,8 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationMwdState {
         Ok(true)
     }
 
-    fn get(&mut self, buf: &'b [u8], _state: &'s Self::State) -> Result<(), EtError> {
-        let metadata = ChemstationMetadata::from_header(buf)?;
+    fn get(&mut self, buf: &'b [u8], params: &'s Self::State) -> Result<(), EtError> {
+        let metadata = ChemstationMetadata::from_header(buf, *params)?;
 
         self.n_wvs_left = 0;
         // offset the current time back one step so it'll be right after the first time that parse
@@ -278,6 +388,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationMwdState {
 }
 
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 /// A single point from an e.g. moving wavelength detector trace
 pub struct ChemstationMwdRecord<'r> {
     /// The name of the signal that's being tracked
@@ -286,12 +397,23 @@ pub struct ChemstationMwdRecord<'r> {
     pub time: f64,
     /// The intensity recorded
     pub intensity: f64,
+    /// The intensity after the file's corrections (only if the `raw_values` param was set, in
+    /// which case `intensity` is the uncorrected value)
+    pub corrected_intensity: Option<f64>,
 }
 
 impl<'r> From<ChemstationMwdRecord<'r>> for Vec<Value<'r>> {
     fn from(record: ChemstationMwdRecord<'r>) -> Self {
+        let mut values = Vec::with_capacity(4);
+        record.push_fields(&mut values);
+        values
+    }
+}
+
+impl<'r> PushFields<'r> for ChemstationMwdRecord<'r> {
+    fn push_fields<E: Extend<Value<'r>>>(self, fields: &mut E) {
         // signal name is something like "MWD A, Sig=210,5 Ref=360,100"
-        let signal = record
+        let signal = self
             .signal_name
             .split_once("Sig=")
             .map(|x| x.1)
@@ -300,7 +422,8 @@ impl<'r> From<ChemstationMwdRecord<'r>> for Vec<Value<'r>> {
                     .and_then(|sig_name| sig_name.parse::<f64>().ok())
             })
             .unwrap_or(0.);
-        vec![record.time.into(), signal.into(), record.intensity.into()]
+        fields.extend([self.time.into(), signal.into(), self.intensity.into()]);
+        fields.extend(self.corrected_intensity.map(Value::from));
     }
 }
 
@@ -320,16 +443,17 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationMwdRecord<'s> {
         let mut n_wvs_left = state.n_wvs_left;
         if n_wvs_left == 0 {
             // mask out the top nibble because it's always 0b0001 (i hope?)
-            n_wvs_left = usize::from(extract::<u16>(rb, con, &mut Endian::Big)?) & 0b1111_1111_1111;
+            n_wvs_left =
+                usize::from(extract_with::<u16>(rb, con, &Endian::Big)?) & 0b1111_1111_1111;
             if n_wvs_left == 0 {
                 // TODO: consume the rest of the file so this can't accidentally repeat?
                 return Ok(false);
             }
         }
 
-        let intensity: i16 = extract(rb, con, &mut Endian::Big)?;
+        let intensity: i16 = extract_with(rb, con, &Endian::Big)?;
         if intensity == -32768 {
-            state.cur_intensity = f64::from(extract::<i32>(rb, con, &mut Endian::Big)?);
+            state.cur_intensity = f64::from(extract_with::<i32>(rb, con, &Endian::Big)?);
         } else {
             state.cur_intensity += f64::from(intensity);
         }
@@ -343,8 +467,8 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationMwdRecord<'s> {
     fn get(&mut self, _rb: &'b [u8], state: &'s Self::State) -> Result<(), EtError> {
         self.signal_name = &state.metadata.signal_name;
         self.time = state.cur_time;
-        self.intensity =
-            state.cur_intensity * state.metadata.mult_correction + state.metadata.offset_correction;
+        (self.intensity, self.corrected_intensity) =
+            state.metadata.intensities(state.cur_intensity);
         Ok(())
     }
 }
@@ -366,13 +490,30 @@ impl StateMetadata for ChemstationDadState {
         (&self.metadata).into()
     }
 
+    fn column_metadata(&self) -> BTreeMap<String, BTreeMap<String, Value<'_>>> {
+        let mut column_metadata = self.metadata.column_metadata();
+        // DAD intensities are always stored in units of 1/2000
+        if let Some(intensity) = column_metadata.get_mut("intensity") {
+            drop(intensity.insert(
+                "scale".to_string(),
+                (self.metadata.unit_scale / 2000.).into(),
+            ));
+            drop(intensity.insert("offset".to_string(), 0.0.into()));
+        }
+        column_metadata
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::HAS_METADATA | Capabilities::TYPED_SCHEMA
+    }
+
     fn header(&self) -> Vec<&str> {
         vec!["time", "wavelength", "intensity"]
     }
 }
 
 impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationDadState {
-    type State = ();
+    type State = ChemstationParams;
 
     fn parse(
         rb: &[u8],
@@ -384,9 +525,10 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationDadState {
         Ok(true)
     }
 
-    fn get(&mut self, buf: &'b [u8], _state: &'s Self::State) -> Result<(), EtError> {
-        let metadata = ChemstationMetadata::from_header(buf)?;
-        let n_scans = u32::extract(&buf[278..], &Endian::Big)? as usize;
+    fn get(&mut self, buf: &'b [u8], params: &'s Self::State) -> Result<(), EtError> {
+        // DAD intensities don't have corrections applied so there are no raw values to return
+        let metadata = ChemstationMetadata::from_header(buf, params.raw_values(false))?;
+        let n_scans = extract_with::<u32>(buf, &mut 278, &Endian::Big)? as usize;
 
         self.n_scans_left = n_scans;
         self.metadata = metadata;
@@ -395,6 +537,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationDadState {
 }
 
 #[derive(Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 /// A single point from an e.g. moving wavelength detector trace
 pub struct ChemstationDadRecord {
     /// The time recorded at
@@ -423,7 +566,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationDadRecord {
         let mut n_scans_left = state.n_scans_left;
         let mut n_bytes_left = state.n_bytes_left;
         if n_bytes_left == 0 {
-            let scan_type = extract::<u16>(rb, con, &mut Endian::Little)?;
+            let scan_type = extract_with::<u16>(rb, con, &Endian::Little)?;
             if scan_type != 67 {
                 // i'm not sure we ever hit this (tracking the n_scans_left should prevent it), but
                 // sometimes there's a different type of scan (68) at the end which starts a stream
@@ -432,11 +575,11 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationDadRecord {
                 return Ok(false);
             }
             n_bytes_left =
-                usize::from(extract::<u16>(rb, con, &mut Endian::Little)?.saturating_sub(22));
-            state.cur_time = f64::from(extract::<u32>(rb, con, &mut Endian::Little)?);
-            state.cur_wv = f64::from(extract::<u16>(rb, con, &mut Endian::Little)?);
-            let _ = extract::<u16>(rb, con, &mut Endian::Little)?; // the end wavelength
-            state.wv_step = f64::from(extract::<u16>(rb, con, &mut Endian::Little)?);
+                usize::from(extract_with::<u16>(rb, con, &Endian::Little)?.saturating_sub(22));
+            state.cur_time = f64::from(extract_with::<u32>(rb, con, &Endian::Little)?);
+            state.cur_wv = f64::from(extract_with::<u16>(rb, con, &Endian::Little)?);
+            let _ = extract_with::<u16>(rb, con, &Endian::Little)?; // the end wavelength
+            state.wv_step = f64::from(extract_with::<u16>(rb, con, &Endian::Little)?);
             let _ = extract::<&[u8]>(rb, con, &mut 8)?;
             state.cur_intensity = 0.;
             if n_bytes_left == 0 {
@@ -448,9 +591,9 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationDadRecord {
             state.cur_wv += state.wv_step;
         }
 
-        let intensity: i16 = extract(rb, con, &mut Endian::Little)?;
+        let intensity: i16 = extract_with(rb, con, &Endian::Little)?;
         if intensity == -32768 {
-            state.cur_intensity = f64::from(extract::<i32>(rb, con, &mut Endian::Little)?);
+            state.cur_intensity = f64::from(extract_with::<i32>(rb, con, &Endian::Little)?);
             state.n_bytes_left = n_bytes_left.saturating_sub(6);
         } else {
             state.cur_intensity += f64::from(intensity);
@@ -465,7 +608,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationDadRecord {
     fn get(&mut self, _rb: &'b [u8], state: &'s Self::State) -> Result<(), EtError> {
         self.wavelength = state.cur_wv / 20.;
         self.time = state.cur_time / 60_000.;
-        self.intensity = state.cur_intensity / 2000.;
+        self.intensity = state.cur_intensity / 2000. * state.metadata.unit_scale;
         Ok(())
     }
 }
@@ -475,34 +618,45 @@ impl_reader!(
     ChemstationDadRecord,
     ChemstationDadRecord,
     ChemstationDadState,
-    ()
+    ChemstationParams
 );
 impl_reader!(
     ChemstationFidReader,
     ChemstationFidRecord,
     ChemstationFidRecord,
     ChemstationFidState,
-    ()
+    ChemstationParams
 );
 impl_reader!(
     ChemstationMsReader,
     ChemstationMsRecord,
     ChemstationMsRecord,
     ChemstationMsState,
-    ()
+    ChemstationParams
+);
+impl_reader!(
+    /// A reader for Chemstation MS files that reports the times and m/z's as the integers
+    /// they're stored as (the `integer_values` param)
+    ChemstationMsIntegerReader,
+    ChemstationMsIntegerRecord,
+    ChemstationMsIntegerRecord,
+    ChemstationMsState,
+    ChemstationParams
 );
 impl_reader!(
     ChemstationMwdReader,
     ChemstationMwdRecord,
     ChemstationMwdRecord<'r>,
     ChemstationMwdState,
-    ()
+    ChemstationParams
 );
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::parsers::text::TextDecoding;
     use crate::readers::RecordReader;
+    use chrono::NaiveDate;
 
     #[test]
     fn test_chemstation_reader_fid() -> Result<(), EtError> {
@@ -510,7 +664,9 @@ mod tests {
         let mut reader = ChemstationFidReader::new(data, None)?;
         let _ = reader.metadata();
         assert_eq!(reader.headers(), ["time", "intensity"]);
-        let ChemstationFidRecord { time, intensity } = reader.next()?.unwrap();
+        let ChemstationFidRecord {
+            time, intensity, ..
+        } = reader.next()?.unwrap();
         // TODO: try to confirm this time is correct
         assert!((time - 20184.8775).abs() < 0.0001);
         assert!((intensity - 17.500).abs() < 0.001);
@@ -524,6 +680,40 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_chemstation_latin1_metadata() -> Result<(), EtError> {
+        let mut data = include_bytes!("../../../tests/data/test_fid.ch").to_vec();
+        // replace the "n" in the sample name ("5970 mix 10nG") with a Latin-1 "ñ"
+        data[36] = 0xF1;
+        let reader = ChemstationFidReader::new(&data[..], None)?;
+        assert_eq!(reader.metadata()["sample"], "5970 mix 10ñG".into());
+        let params = ChemstationParams::default().text_decoding(TextDecoding::Strict);
+        let reader = ChemstationFidReader::new(&data[..], Some(params));
+        assert!(reader.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_chemstation_default_date() -> Result<(), EtError> {
+        let mut data = include_bytes!("../../../tests/data/test_fid.ch").to_vec();
+        // make the run date unparseable
+        data[180] = b'?';
+        let reader = ChemstationFidReader::new(&data[..], None)?;
+        assert_eq!(reader.metadata()["run_date"], Value::Null);
+        assert!(reader.metadata().contains_key("warnings"));
+
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let params = ChemstationParams::default().default_date(date);
+        let reader = ChemstationFidReader::new(&data[..], Some(params))?;
+        assert_eq!(
+            reader.metadata()["run_date"],
+            date.and_hms_opt(0, 0, 0).unwrap().into()
+        );
+        let reader = ChemstationFidReader::new(&data[..], Some(params.strict_dates(true)))?;
+        assert_eq!(reader.metadata()["run_date"], Value::Null);
+        Ok(())
+    }
+
     #[test]
     fn test_chemstation_reader_ms() -> Result<(), EtError> {
         let data: &[u8] = include_bytes!("../../../tests/data/carotenoid_extract.d/MSD1.MS");
@@ -556,6 +746,27 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_chemstation_ms_integer_values() -> Result<(), EtError> {
+        let data: &[u8] = include_bytes!("../../../tests/data/carotenoid_extract.d/MSD1.MS");
+        let reader = ChemstationMsReader::new(data, None)?;
+        let column_metadata = reader.column_metadata();
+        assert_eq!(column_metadata["mz"]["precision"], 0.05.into());
+        assert!(!column_metadata["mz"].contains_key("scale"));
+
+        let params = ChemstationParams::default().integer_values(true);
+        let mut reader = ChemstationMsIntegerReader::new(data, Some(params))?;
+        assert_eq!(reader.headers(), ["time", "mz", "intensity"]);
+        let column_metadata = reader.column_metadata();
+        assert_eq!(column_metadata["mz"]["scale"], 0.05.into());
+        assert_eq!(column_metadata["time"]["units"], "min".into());
+        let record = reader.next()?.unwrap();
+        assert_eq!(record.time, 4750);
+        assert_eq!(record.mz, 18314);
+        assert_eq!(record.intensity, 112);
+        Ok(())
+    }
+
     #[test]
     fn test_chemstation_reader_mwd() -> Result<(), EtError> {
         let data: &[u8] = include_bytes!("../../../tests/data/chemstation_mwd.d/mwd1A.ch");
@@ -566,6 +777,7 @@ mod tests {
             time,
             signal_name,
             intensity,
+            ..
         } = reader.next()?.unwrap();
         assert!((time - -0.039667).abs() < 0.000001);
         assert_eq!(signal_name, "MWD A, Sig=210,5 Ref=360,100");
@@ -576,6 +788,56 @@ mod tests {
             n_mzs += 1;
         }
         assert_eq!(n_mzs, 1801);
+        assert_eq!(reader.metadata()["y_units"], "mAU".into());
+        Ok(())
+    }
+
+    #[test]
+    fn test_chemstation_units() -> Result<(), EtError> {
+        let data: &[u8] = include_bytes!("../../../tests/data/test_fid.ch");
+        let reader = ChemstationFidReader::new(data, None)?;
+        let column_metadata = reader.column_metadata();
+        assert_eq!(column_metadata["intensity"]["units"], "pA".into());
+        assert!(column_metadata["intensity"].contains_key("scale"));
+
+        // the same trace, but in nA
+        let mut data = data.to_vec();
+        data[580..583].copy_from_slice(b"\x02nA");
+        let params = ChemstationParams::default().canonical_units(true);
+        let mut reader = ChemstationFidReader::new(&data[..], Some(params))?;
+        let ChemstationFidRecord { intensity, .. } = reader.next()?.unwrap();
+        assert!((intensity - 17500.).abs() < 1.);
+        assert_eq!(reader.metadata()["y_units"], "pA".into());
+        let column_metadata = reader.column_metadata();
+        assert_eq!(column_metadata["intensity"]["converted_from"], "nA".into());
+        Ok(())
+    }
+
+    #[test]
+    fn test_chemstation_raw_values() -> Result<(), EtError> {
+        let data: &[u8] = include_bytes!("../../../tests/data/chemstation_mwd.d/mwd1A.ch");
+        let params = ChemstationParams::default().raw_values(true);
+        let mut reader = ChemstationMwdReader::new(data, Some(params))?;
+        assert_eq!(
+            reader.headers(),
+            ["time", "signal", "intensity", "corrected_intensity"]
+        );
+        let column_metadata = reader.column_metadata();
+        assert_eq!(column_metadata["intensity"]["scale"], 1.0.into());
+        assert_eq!(
+            column_metadata["corrected_intensity"]["units"],
+            "mAU".into()
+        );
+        let ChemstationMwdRecord {
+            intensity,
+            corrected_intensity,
+            ..
+        } = reader.next()?.unwrap();
+        assert!((corrected_intensity.unwrap() - -36.34977).abs() < 0.00001);
+        let metadata = ChemstationMetadata::from_header(data, params)?;
+        assert!((metadata.correct(intensity) - -36.34977).abs() < 0.00001);
+        assert!((intensity - -36.34977).abs() > 1.);
+        assert_eq!(RecordReader::next_record(&mut reader)?.unwrap().len(), 4);
         Ok(())
     }
 
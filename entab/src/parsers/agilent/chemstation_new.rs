@@ -5,12 +5,16 @@ use alloc::vec;
 use alloc::vec::Vec;
 use core::marker::Copy;
 
-use crate::parsers::agilent::metadata::ChemstationMetadata;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::capabilities::Capabilities;
+use crate::impl_reader;
+use crate::parsers::agilent::metadata::{ChemstationMetadata, ChemstationParams};
 use crate::parsers::agilent::read_agilent_header;
-use crate::parsers::{extract, Endian, FromSlice};
-use crate::record::{StateMetadata, Value};
+use crate::parsers::{extract, extract_with, sub_or_err, Endian, FromSlice};
+use crate::record::{PushFields, StateMetadata, Value};
 use crate::EtError;
-use crate::{impl_reader, impl_record};
 
 #[derive(Clone, Debug, Default)]
 /// Internal state for the `ChemstationUvRecord` parser
@@ -29,13 +33,25 @@ impl StateMetadata for ChemstationUvState {
         (&self.metadata).into()
     }
 
+    fn column_metadata(&self) -> BTreeMap<String, BTreeMap<String, Value<'_>>> {
+        self.metadata.column_metadata()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::HAS_METADATA | Capabilities::TYPED_SCHEMA
+    }
+
     fn header(&self) -> Vec<&str> {
-        vec!["time", "wavelength", "intensity"]
+        let mut header = vec!["time", "wavelength", "intensity"];
+        if self.metadata.raw_values {
+            header.push("corrected_intensity");
+        }
+        header
     }
 }
 
 impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationUvState {
-    type State = ();
+    type State = ChemstationParams;
 
     fn parse(
         rb: &[u8],
@@ -47,10 +63,10 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationUvState {
         Ok(true)
     }
 
-    fn get(&mut self, rb: &'b [u8], _state: &'s Self::State) -> Result<(), EtError> {
-        let n_scans = u32::extract(&rb[278..], &Endian::Big)? as usize;
+    fn get(&mut self, rb: &'b [u8], params: &'s Self::State) -> Result<(), EtError> {
+        let n_scans = extract_with::<u32>(rb, &mut 278, &Endian::Big)? as usize;
 
-        self.metadata = ChemstationMetadata::from_header(rb)?;
+        self.metadata = ChemstationMetadata::from_header(rb, *params)?;
         self.n_scans_left = n_scans;
         self.n_wvs_left = 0;
         self.cur_time = 0.;
@@ -62,6 +78,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationUvState {
 }
 
 #[derive(Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 /// A record from a Chemstation UV file
 pub struct ChemstationUvRecord {
     /// The time recorded at
@@ -70,9 +87,29 @@ pub struct ChemstationUvRecord {
     pub wavelength: f64,
     /// The intensity record
     pub intensity: f64,
+    /// The intensity after the file's corrections (only if the `raw_values` param was set, in
+    /// which case `intensity` is the uncorrected value)
+    pub corrected_intensity: Option<f64>,
 }
 
-impl_record!(ChemstationUvRecord: time, wavelength, intensity);
+impl<'r> From<ChemstationUvRecord> for Vec<Value<'r>> {
+    fn from(record: ChemstationUvRecord) -> Self {
+        let mut values = Vec::with_capacity(4);
+        record.push_fields(&mut values);
+        values
+    }
+}
+
+impl<'r> PushFields<'r> for ChemstationUvRecord {
+    fn push_fields<E: Extend<Value<'r>>>(self, fields: &mut E) {
+        fields.extend([
+            self.time.into(),
+            self.wavelength.into(),
+            self.intensity.into(),
+        ]);
+        fields.extend(self.corrected_intensity.map(Value::from));
+    }
+}
 
 impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationUvRecord {
     type State = ChemstationUvState;
@@ -93,27 +130,25 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationUvRecord {
         //
         if n_wvs_left == 0 {
             let _ = extract::<&[u8]>(rb, con, &mut 4)?; // 67, 624/224
-            state.cur_time = f64::from(extract::<u32>(rb, con, &mut Endian::Little)?) / 60000.;
-            let wv_start: u16 = extract(rb, con, &mut Endian::Little)?;
-            let wv_end: u16 = extract(rb, con, &mut Endian::Little)?;
-            if wv_start > wv_end {
-                return Err("Wavelength range has invalid bounds".into());
-            }
-            let wv_step: u16 = extract(rb, con, &mut Endian::Little)?;
+            state.cur_time = f64::from(extract_with::<u32>(rb, con, &Endian::Little)?) / 60000.;
+            let wv_start: u16 = extract_with(rb, con, &Endian::Little)?;
+            let wv_end: u16 = extract_with(rb, con, &Endian::Little)?;
+            let wv_range = sub_or_err(wv_end, wv_start, "wavelength range")?;
+            let wv_step: u16 = extract_with(rb, con, &Endian::Little)?;
             if wv_step == 0 {
                 return Err("Invalid wavelength step".into());
             }
 
-            n_wvs_left = usize::from((wv_end - wv_start) / wv_step) + 1;
+            n_wvs_left = usize::from(wv_range / wv_step) + 1;
             state.wv_step = f64::from(wv_step) / 20.;
             state.cur_wv = f64::from(wv_start) / 20. - state.wv_step;
             state.cur_intensity = 0.;
             let _ = extract::<&[u8]>(rb, con, &mut 8)?; // 80/53, 4, 400, 0
         };
 
-        let delta = extract::<i16>(rb, con, &mut Endian::Little)?;
+        let delta = extract_with::<i16>(rb, con, &Endian::Little)?;
         if delta == -32768 {
-            state.cur_intensity = f64::from(extract::<i32>(rb, con, &mut Endian::Little)?);
+            state.cur_intensity = f64::from(extract_with::<i32>(rb, con, &Endian::Little)?);
         } else {
             state.cur_intensity += f64::from(delta);
         }
@@ -130,7 +165,8 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationUvRecord {
     fn get(&mut self, _rb: &'b [u8], state: &'s Self::State) -> Result<(), EtError> {
         self.time = state.cur_time;
         self.wavelength = state.cur_wv;
-        self.intensity = state.cur_intensity * state.metadata.mult_correction;
+        (self.intensity, self.corrected_intensity) =
+            state.metadata.intensities(state.cur_intensity);
         Ok(())
     }
 }
@@ -140,7 +176,7 @@ impl_reader!(
     ChemstationUvRecord,
     ChemstationUvRecord,
     ChemstationUvState,
-    ()
+    ChemstationParams
 );
 
 #[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
@@ -153,6 +189,16 @@ pub enum ChemstationArrayRecordType {
     Float64Array,
 }
 
+impl ChemstationArrayRecordType {
+    /// The number of bytes each value takes up
+    fn size(self) -> usize {
+        match self {
+            ChemstationArrayRecordType::Float32Array => 4,
+            ChemstationArrayRecordType::Float64Array => 8,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Default)]
 /// Internal state for the `ChemstationArrayRecord` parser
 pub struct ChemstationArrayState {
@@ -168,13 +214,34 @@ impl StateMetadata for ChemstationArrayState {
         (&self.metadata).into()
     }
 
+    fn column_metadata(&self) -> BTreeMap<String, BTreeMap<String, Value<'_>>> {
+        self.metadata.column_metadata()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::SEEKABLE | Capabilities::HAS_METADATA | Capabilities::TYPED_SCHEMA
+    }
+
+    fn skip_records(&mut self, n: usize) -> (usize, u64) {
+        let n_skip = n.min(self.n_scans_left);
+        self.n_scans_left -= n_skip;
+        #[allow(clippy::cast_precision_loss)]
+        let skipped_time = n_skip as f64 * self.time_step;
+        self.cur_time += skipped_time;
+        (n_skip, n_skip as u64 * self.record_type.size() as u64)
+    }
+
     fn header(&self) -> Vec<&str> {
-        vec!["time", "intensity"]
+        let mut header = vec!["time", "intensity"];
+        if self.metadata.raw_values {
+            header.push("corrected_intensity");
+        }
+        header
     }
 }
 
 impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationArrayState {
-    type State = ();
+    type State = ChemstationParams;
 
     fn parse(
         rb: &[u8],
@@ -186,25 +253,25 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationArrayState {
         Ok(true)
     }
 
-    fn get(&mut self, rb: &'b [u8], _state: &'s Self::State) -> Result<(), EtError> {
-        self.metadata = ChemstationMetadata::from_header(rb)?;
+    fn get(&mut self, rb: &'b [u8], params: &'s Self::State) -> Result<(), EtError> {
+        self.metadata = ChemstationMetadata::from_header(rb, *params)?;
 
-        let record_type = if &rb[348..352] == b"G\x00C\x00"
-            || &rb[3090..3104] == b"M\x00u\x00s\x00t\x00a\x00n\x00g\x00"
+        let record_type = if rb.get(348..352) == Some(b"G\x00C\x00")
+            || rb.get(3090..3104) == Some(b"M\x00u\x00s\x00t\x00a\x00n\x00g\x00")
         {
             ChemstationArrayRecordType::Float64Array
         } else {
             ChemstationArrayRecordType::Float32Array
         };
 
-        let tstep_num = u16::extract(&rb[4122..], &Endian::Big)? as f64;
-        let tstep_denom = u16::extract(&rb[4124..], &Endian::Big)? as f64;
+        let tstep_num = extract_with::<u16>(rb, &mut 4122, &Endian::Big)? as f64;
+        let tstep_denom = extract_with::<u16>(rb, &mut 4124, &Endian::Big)? as f64;
         let tstep = (tstep_num / tstep_denom) / 60.;
 
         // The file from issue #42 has 12000 scans, but the field at 278 only says 197?
         // The other file I have is correct so maybe that's corrupt, but we're using
         // the time step to figure this out for now.
-        // let n_scans = u32::extract(&rb[278..], &Endian::Big)? as usize;
+        // let n_scans = extract_with::<u32>(rb, &mut 278, &Endian::Big)? as usize;
         let n_scans = 1 + ((self.metadata.end_time - self.metadata.start_time) / tstep) as usize;
 
         self.n_scans_left = n_scans;
@@ -216,21 +283,38 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationArrayState {
 }
 
 #[derive(Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 /// A record from a Chemstation UV file
 pub struct ChemstationArrayRecord {
     /// The time recorded at
     pub time: f64,
     /// The intensity recorded
     pub intensity: f64,
+    /// The intensity after the file's corrections (only if the `raw_values` param was set, in
+    /// which case `intensity` is the uncorrected value)
+    pub corrected_intensity: Option<f64>,
 }
 
-impl_record!(ChemstationArrayRecord: time, intensity);
+impl<'r> From<ChemstationArrayRecord> for Vec<Value<'r>> {
+    fn from(record: ChemstationArrayRecord) -> Self {
+        let mut values = Vec::with_capacity(3);
+        record.push_fields(&mut values);
+        values
+    }
+}
+
+impl<'r> PushFields<'r> for ChemstationArrayRecord {
+    fn push_fields<E: Extend<Value<'r>>>(self, fields: &mut E) {
+        fields.extend([self.time.into(), self.intensity.into()]);
+        fields.extend(self.corrected_intensity.map(Value::from));
+    }
+}
 
 impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationArrayRecord {
     type State = ChemstationArrayState;
 
     fn parse(
-        _rb: &[u8],
+        rb: &[u8],
         _eof: bool,
         consumed: &mut usize,
         state: &mut Self::State,
@@ -238,10 +322,13 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationArrayRecord {
         if state.n_scans_left == 0 {
             return Ok(false);
         }
-        *consumed += match state.record_type {
-            ChemstationArrayRecordType::Float32Array => 4,
-            ChemstationArrayRecordType::Float64Array => 8,
-        };
+        let record_size = state.record_type.size();
+        if rb.len() < record_size {
+            return Err(
+                EtError::from("Incomplete Chemstation record").incomplete_needing(record_size)
+            );
+        }
+        *consumed += record_size;
         state.n_scans_left -= 1;
         state.cur_time += state.time_step;
         Ok(true)
@@ -251,15 +338,15 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationArrayRecord {
         let con = &mut 0;
         let intensity = match state.record_type {
             ChemstationArrayRecordType::Float32Array => {
-                extract::<f32>(rb, con, &mut Endian::Little)? as f64
+                extract_with::<f32>(rb, con, &Endian::Little)? as f64
             }
             ChemstationArrayRecordType::Float64Array => {
-                extract::<f64>(rb, con, &mut Endian::Little)?
+                extract_with::<f64>(rb, con, &Endian::Little)?
             }
         };
 
         self.time = state.cur_time;
-        self.intensity = intensity * state.metadata.mult_correction;
+        (self.intensity, self.corrected_intensity) = state.metadata.intensities(intensity);
         Ok(())
     }
 }
@@ -269,12 +356,14 @@ impl_reader!(
     ChemstationArrayRecord,
     ChemstationArrayRecord,
     ChemstationArrayState,
-    ()
+    ChemstationParams
 );
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(feature = "std")]
+    use crate::buffer::ReadBuffer;
     use crate::readers::RecordReader;
 
     #[test]
@@ -288,6 +377,7 @@ mod tests {
             time,
             wavelength,
             intensity,
+            ..
         } = reader.next()?.unwrap();
         assert!((time - 0.001333).abs() < 0.000001);
         assert!((wavelength - 200.).abs() < 0.000001);
@@ -297,6 +387,7 @@ mod tests {
             time,
             wavelength,
             intensity,
+            ..
         } = reader.next()?.unwrap();
         assert!((time - 0.001333).abs() < 0.000001);
         assert!((wavelength - 202.).abs() < 0.000001);
@@ -317,7 +408,9 @@ mod tests {
         let _ = reader.metadata();
         assert_eq!(reader.headers(), ["time", "intensity"]);
 
-        let ChemstationArrayRecord { time, intensity } = reader.next()?.unwrap();
+        let ChemstationArrayRecord {
+            time, intensity, ..
+        } = reader.next()?.unwrap();
         assert!((time - 0.00166095).abs() < 0.000001);
         assert_eq!(intensity, 7.7457031249999995);
 
@@ -328,4 +421,32 @@ mod tests {
         assert_eq!(n_mzs, 12000);
         Ok(())
     }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_array_chemstation_skip_records() -> Result<(), EtError> {
+        let data: &[u8] = include_bytes!("../../../tests/data/test_179_fid.ch");
+        let mut reader = ChemstationArrayReader::new(data, None)?;
+        for _ in 0..5000 {
+            let _ = reader.next()?;
+        }
+        let ChemstationArrayRecord {
+            time, intensity, ..
+        } = reader.next()?.unwrap();
+
+        // records past the end of the buffer are skipped without being read into it
+        let rb = ReadBuffer::from_reader(Box::new(std::io::Cursor::new(data)), Some(4096))?;
+        let mut reader = ChemstationArrayReader::new(rb, None)?;
+        assert!(reader.capabilities().contains(Capabilities::SEEKABLE));
+        assert_eq!(reader.skip_records(5000)?, 5000);
+        let record = reader.next()?.unwrap();
+        assert!((record.time - time).abs() < 1e-9);
+        assert_eq!(record.intensity, intensity);
+        assert_eq!(reader.skip_records(20000)?, 12000 - 5001);
+        assert!(reader.next()?.is_none());
+
+        let mut reader = ChemstationArrayReader::new(&data[..data.len() - 100], None)?;
+        assert!(reader.skip_records(12000).is_err());
+        Ok(())
+    }
 }
@@ -3,8 +3,10 @@ use core::marker::Copy;
 
 use encoding::all::ISO_8859_1;
 use encoding::{DecoderTrap, Encoding};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
-use crate::parsers::{extract, Endian, FromSlice};
+use crate::parsers::{extract, extract_with, Endian, FromSlice};
 use crate::record::StateMetadata;
 use crate::EtError;
 use crate::{impl_reader, impl_record};
@@ -43,19 +45,19 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationRegState {
         if header[25] != b'A' {
             return Err(EtError::from("Version of REG file is too new"));
         }
-        let n_sections = u16::extract(&header[38..], &Endian::Little)?;
+        let n_sections = extract_with::<u16>(header, &mut 38, &Endian::Little)?;
 
         // TODO: parse multiple sections
 
-        let n_records = extract::<u32>(buf, con, &mut Endian::Little)? as usize;
+        let n_records = extract_with::<u32>(buf, con, &Endian::Little)? as usize;
 
         let mut records = Vec::with_capacity(n_records);
         for _ in 0..n_records {
-            let _ = extract::<u16>(buf, con, &mut Endian::Little)?;
-            let record_type = extract::<u16>(buf, con, &mut Endian::Little)?;
-            let record_len = extract::<u32>(buf, con, &mut Endian::Little)? as usize;
-            let _ = extract::<u32>(buf, con, &mut Endian::Little)?;
-            let record_id = extract::<u32>(buf, con, &mut Endian::Little)?;
+            let _ = extract_with::<u16>(buf, con, &Endian::Little)?;
+            let record_type = extract_with::<u16>(buf, con, &Endian::Little)?;
+            let record_len = extract_with::<u32>(buf, con, &Endian::Little)? as usize;
+            let _ = extract_with::<u32>(buf, con, &Endian::Little)?;
+            let record_id = extract_with::<u32>(buf, con, &Endian::Little)?;
             records.push((record_type, record_len, record_id))
         }
 
@@ -77,7 +79,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationRegState {
                 // key-value?
                 1537 => {
                     // the matching data is in a 32770 record so we only get the name
-                    let record_id = u32::extract(&record_data[35..], &Endian::Little)?;
+                    let record_id = extract_with::<u32>(record_data, &mut 35, &Endian::Little)?;
                     let _ = names.insert(record_id, decode_iso_8859(record_data[14..30].split(|c| *c == 0).next().unwrap_or(&record_data[14..30]))?);
                 },
                 // part of a linked list
@@ -86,21 +88,21 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationRegState {
                         return Err(EtError::from("Data type 1538 was an unexpected size"));
                     }
                     let _ = names.insert(record_id, decode_iso_8859(&record_data[14..35])?);
-                    let _ = metadata.insert(record_id, u32::extract(&record_data[35..], &Endian::Little)?.into());
+                    let _ = metadata.insert(record_id, extract_with::<u32>(record_data, &mut 35, &Endian::Little)?.into());
                 },
                 // another part of a linked list with a table reference
                 1539 => {
                     if record_data.len() != 39 {
                         return Err(EtError::from("Data type 1539 was an unexpected size"));
                     }
-                    let id = u32::extract(&record_data[35..], &Endian::Little)?;
+                    let id = extract_with::<u32>(record_data, &mut 35, &Endian::Little)?;
                     let _ = names.insert(id, decode_iso_8859(&record_data[14..35])?);
                     // no data?
                 },
                 // table of values
                 1793 => {
-					let n_rows = u16::extract(&record_data[4..], &Endian::Little)?;
-					let n_columns = u16::extract(&record_data[16..], &Endian::Little)?;
+					let n_rows = extract_with::<u16>(record_data, &mut 4, &Endian::Little)?;
+					let n_columns = extract_with::<u16>(record_data, &mut 16, &Endian::Little)?;
 					if n_columns == 0 {
 						continue;
 					}
@@ -121,7 +123,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationRegState {
                     let n_points = record_data.len() / 4 - 1;
                     let mut data: Vec<Value> = Vec::with_capacity(n_points);
                     for ix in 0..n_points {
-                        data.push(u32::extract(&record_data[4 * ix + 4..], &Endian::Little)?.into());
+                        data.push(extract_with::<u32>(record_data, &mut (4 * ix + 4), &Endian::Little)?.into());
                     }
                     let _ = metadata.insert(record_id, data.into());
                 },
@@ -143,6 +145,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationRegState {
 
 /// Record
 #[derive(Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ChemstationRegRecord {
     point: f64
 }
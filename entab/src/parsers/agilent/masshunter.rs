@@ -1,11 +1,23 @@
 use alloc::collections::BTreeMap;
-use alloc::string::ToString;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
 use std::fs::File;
-use std::path::Path;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::bom::strip_bom;
 use crate::buffer::ReadBuffer;
+use crate::capabilities::Capabilities;
 use crate::impl_record;
-use crate::parsers::{Endian, FromSlice};
+use crate::parsers::datetime::DateParser;
+use crate::parsers::xml::{next_tag, XmlReader, XmlRecord, XmlTagType};
+use crate::parsers::{extract_with, sub_or_err, Endian, FromSlice};
 use crate::readers::{init_state, RecordReader};
 use crate::record::{StateMetadata, Value};
 use crate::EtError;
@@ -22,6 +34,10 @@ pub struct MasshunterDadState {
 }
 
 impl StateMetadata for MasshunterDadState {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::MULTI_STREAM | Capabilities::TYPED_SCHEMA
+    }
+
     fn header(&self) -> Vec<&str> {
         vec!["time", "wavelength", "intensity"]
     }
@@ -44,7 +60,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for MasshunterDadState {
     }
 
     fn get(&mut self, buf: &'b [u8], _state: &'s Self::State) -> Result<(), EtError> {
-        self.n_scans = u64::extract(&buf[80..88], &Endian::Little)?;
+        self.n_scans = extract_with::<u64>(buf, &mut 80, &Endian::Little)?;
         self.n_pts = 1;
         self.skip_data_bytes = 68;
         Ok(())
@@ -52,6 +68,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for MasshunterDadState {
 }
 
 #[derive(Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 /// A dummy record so the header will update the current state
 pub struct MasshunterDadHeaderRecord {}
 
@@ -96,10 +113,10 @@ impl<'b: 's, 's> FromSlice<'b, 's> for MasshunterDadHeaderRecord {
             return Err(EtError::from("Record header is too short").incomplete());
         }
         state.skip_data_bytes += 16;
-        state.cur_time = f64::extract(&buf[4..12], &Endian::Little)?;
-        state.wavelength_step = f64::extract(&buf[20..28], &Endian::Little)?;
-        state.n_pts = u32::extract(&buf[44..48], &Endian::Little)?;
-        state.cur_wavelength = f64::extract(&buf[48..56], &Endian::Little)?;
+        state.cur_time = extract_with::<f64>(buf, &mut 4, &Endian::Little)?;
+        state.wavelength_step = extract_with::<f64>(buf, &mut 20, &Endian::Little)?;
+        state.n_pts = extract_with::<u32>(buf, &mut 44, &Endian::Little)?;
+        state.cur_wavelength = extract_with::<f64>(buf, &mut 48, &Endian::Little)?;
         state.n_scans -= 1;
         *consumed += 80;
         Ok(true)
@@ -111,6 +128,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for MasshunterDadHeaderRecord {
 }
 
 #[derive(Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 /// The intensity at a single wavelength in a DAD run
 pub struct MasshunterDadRecord {
     /// The time recorded at
@@ -145,7 +163,8 @@ impl<'b: 's, 's> FromSlice<'b, 's> for MasshunterDadRecord {
     fn get(&mut self, buf: &'b [u8], state: &'s Self::State) -> Result<(), EtError> {
         self.time = state.cur_time;
         self.wavelength = state.cur_wavelength;
-        self.intensity = f64::extract(&buf[buf.len() - 8..], &Endian::Little)?;
+        let pos = &mut sub_or_err(buf.len(), 8, "DAD data point")?;
+        self.intensity = extract_with::<f64>(buf, pos, &Endian::Little)?;
         Ok(())
     }
 }
@@ -220,6 +239,511 @@ impl<'r> RecordReader for MasshunterDadReader<'r> {
     fn metadata(&self) -> BTreeMap<String, Value> {
         self.state.metadata()
     }
+
+    /// What this Reader can do.
+    fn capabilities(&self) -> Capabilities {
+        self.state.capabilities()
+    }
+}
+
+/// The fields in each scan record of `MSScan.bin` and their types, as documented in the
+/// `MSScan.xsd` that MassHunter writes alongside it (with the nested `DataDependentScanParamType`
+/// and `SpectrumParamsType` fields flattened out); used if a run doesn't have its own copy.
+const DEFAULT_SCAN_FIELDS: &[(&str, &str)] = &[
+    ("ScanID", "xs:int"),
+    ("ScanMethodID", "xs:int"),
+    ("TimeSegmentID", "xs:int"),
+    ("ScanTime", "xs:double"),
+    ("MSLevel", "xs:short"),
+    ("ScanType", "xs:int"),
+    ("TIC", "xs:double"),
+    ("BasePeakMZ", "xs:double"),
+    ("BasePeakValue", "xs:double"),
+    ("CycleNumber", "xs:int"),
+    ("Status", "xs:int"),
+    ("IonMode", "xs:int"),
+    ("IonPolarity", "xs:short"),
+    ("CompensationField", "xs:float"),
+    ("DispersionField", "xs:float"),
+    ("Fragmentor", "xs:float"),
+    ("CollisionEnergy", "xs:float"),
+    ("MzOfInterest", "xs:double"),
+    ("SamplingPeriod", "xs:double"),
+    ("DwellTime", "xs:int"),
+    ("MeasuredMassRangeMin", "xs:double"),
+    ("MeasuredMassRangeMax", "xs:double"),
+    ("Threshold", "xs:double"),
+    ("IsFragmentorDynamic", "xs:short"),
+    ("IsCollisionEnergyDynamic", "xs:short"),
+    ("DDScanID", "xs:int"),
+    ("DDScanID2", "xs:int"),
+    ("SpectrumFormatID", "xs:short"),
+    ("SpectrumOffset", "xs:long"),
+    ("ByteCount", "xs:int"),
+    ("PointCount", "xs:int"),
+    ("MinX", "xs:double"),
+    ("MaxX", "xs:double"),
+    ("MinY", "xs:double"),
+    ("MaxY", "xs:double"),
+];
+
+/// The `SpectrumFormatID` of profile spectra in `MSProfile.bin`
+const PROFILE_FORMAT: i16 = 2;
+
+/// The `SpectrumFormatID` of centroided (peak-detected) spectra in `MSPeak.bin`
+const PEAK_FORMAT: i16 = 3;
+
+/// Where the fields needed to find each spectrum are in the scan records of `MSScan.bin`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MasshunterScanLayout {
+    record_len: usize,
+    time: usize,
+    format_id: usize,
+    offset: usize,
+    byte_count: usize,
+    point_count: usize,
+}
+
+impl MasshunterScanLayout {
+    /// Work out the layout from the name and XML schema type of each field in a scan record
+    fn from_fields<'a, I>(fields: I) -> Result<Self, EtError>
+    where
+        I: IntoIterator<Item = (&'a str, &'a str)>,
+    {
+        let mut pos = 0;
+        let mut found = [None; 5];
+        for (name, field_type) in fields {
+            let ix = match name {
+                "ScanTime" => Some(0),
+                "SpectrumFormatID" => Some(1),
+                "SpectrumOffset" => Some(2),
+                "ByteCount" => Some(3),
+                "PointCount" => Some(4),
+                _ => None,
+            };
+            if let Some(ix) = ix {
+                found[ix] = Some(pos);
+            }
+            pos += match field_type.trim_start_matches("xs:") {
+                "short" => 2,
+                "int" | "float" => 4,
+                "long" | "double" => 8,
+                t => return Err(format!("MSScan field {} has unknown type {}", name, t).into()),
+            };
+        }
+        if let [Some(time), Some(format_id), Some(offset), Some(byte_count), Some(point_count)] =
+            found
+        {
+            Ok(MasshunterScanLayout {
+                record_len: pos,
+                time,
+                format_id,
+                offset,
+                byte_count,
+                point_count,
+            })
+        } else {
+            Err("MSScan schema is missing the time or spectrum location fields".into())
+        }
+    }
+
+    /// Work out the layout from the `ScanRecordType` in an `MSScan.xsd` file
+    fn from_xsd(xsd: &[u8]) -> Result<Self, EtError> {
+        // the name and type of the fields in each complex type
+        let mut types: BTreeMap<String, Vec<(String, String)>> = BTreeMap::new();
+        let mut cur_type: Option<String> = None;
+        let consumed = &mut 0;
+        while let Some(tag) = next_tag(xsd, true, consumed)? {
+            match (tag.id, tag.tag_type) {
+                ("xs:complexType", XmlTagType::Open) => {
+                    cur_type = tag.attribute("name").map(ToString::to_string);
+                }
+                ("xs:complexType", XmlTagType::Close) => cur_type = None,
+                ("xs:element", XmlTagType::Open | XmlTagType::SelfClose) => {
+                    if let (Some(cur_type), Some(name), Some(field_type)) =
+                        (&cur_type, tag.attribute("name"), tag.attribute("type"))
+                    {
+                        types
+                            .entry(cur_type.clone())
+                            .or_default()
+                            .push((name.to_string(), field_type.to_string()));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // fields that are themselves complex types are stored inline
+        let mut fields = Vec::new();
+        let mut stack = vec![types
+            .get("ScanRecordType")
+            .ok_or("MSScan schema has no ScanRecordType")?
+            .iter()];
+        while let Some(iter) = stack.last_mut() {
+            let (name, field_type) = match iter.next() {
+                Some(field) => field,
+                None => {
+                    let _ = stack.pop();
+                    continue;
+                }
+            };
+            match types.get(field_type) {
+                Some(_) if stack.len() > 8 => {
+                    return Err("MSScan schema types are nested too deeply".into());
+                }
+                Some(subfields) => stack.push(subfields.iter()),
+                None => fields.push((name.as_str(), field_type.as_str())),
+            }
+        }
+        MasshunterScanLayout::from_fields(fields)
+    }
+}
+
+/// Read the run information out of a MassHunter `Contents.xml` file
+fn read_contents(path: &Path) -> Result<BTreeMap<String, Value<'static>>, EtError> {
+    let mut metadata = BTreeMap::new();
+    let file = match File::open(path) {
+        Ok(file) => file,
+        // older runs may not have one
+        Err(_) => return Ok(metadata),
+    };
+    let mut reader = XmlReader::new(strip_bom(ReadBuffer::try_from(file)?)?, None)?;
+    while let Some(XmlRecord { tags, text }) = reader.next()? {
+        if tags.len() != 2 || tags[0] != "Contents" || text.trim().is_empty() {
+            continue;
+        }
+        let (key, value) = match tags[1].as_str() {
+            "AcquiredTime" => ("run_date", DateParser::default().parse_utc(&text)?.into()),
+            "InstrumentName" => ("instrument", text.trim().to_string().into()),
+            "AcqSoftwareVersion" => ("software_version", text.trim().to_string().into()),
+            _ => continue,
+        };
+        drop(metadata.insert(key.to_string(), value));
+    }
+    Ok(metadata)
+}
+
+/// Store the current state of the `MasshunterMsReader`
+#[derive(Clone, Debug, Default)]
+pub struct MasshunterMsState {
+    layout: MasshunterScanLayout,
+    metadata: BTreeMap<String, Value<'static>>,
+}
+
+impl StateMetadata for MasshunterMsState {
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        self.metadata.clone()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::MULTI_STREAM | Capabilities::TYPED_SCHEMA | Capabilities::HAS_METADATA
+    }
+
+    fn header(&self) -> Vec<&str> {
+        vec!["time", "mz", "intensity"]
+    }
+}
+
+impl<'b: 's, 's> FromSlice<'b, 's> for MasshunterMsState {
+    type State = ();
+
+    /// `MSScan.bin` starts with a header with the position of the first scan record at byte 88
+    fn parse(
+        buf: &[u8],
+        _eof: bool,
+        consumed: &mut usize,
+        _state: &mut Self::State,
+    ) -> Result<bool, EtError> {
+        if buf.len() < 92 {
+            return Err(EtError::from("MSScan header is too short").incomplete_needing(92));
+        }
+        let start = extract_with::<u32>(buf, &mut 88, &Endian::Little)? as usize;
+        if start < 92 {
+            return Err(format!("Invalid MSScan record start of {}", start).into());
+        } else if buf.len() < start {
+            return Err(EtError::from("MSScan header is incomplete").incomplete_needing(start));
+        }
+        *consumed += start;
+        Ok(true)
+    }
+}
+
+/// Where a scan's spectrum is, from its record in `MSScan.bin`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MasshunterScan {
+    /// The time the scan was taken at
+    pub time: f64,
+    /// The format of the spectrum (`2` for profile spectra in `MSProfile.bin` and `3` for
+    /// centroided spectra in `MSPeak.bin`)
+    pub format_id: i16,
+    /// The position of the spectrum in its data file
+    pub offset: u64,
+    /// How many bytes the spectrum takes up
+    pub byte_count: u32,
+    /// How many points are in the spectrum
+    pub point_count: u32,
+}
+
+impl<'b: 's, 's> FromSlice<'b, 's> for MasshunterScan {
+    type State = MasshunterScanLayout;
+
+    fn parse(
+        buf: &[u8],
+        eof: bool,
+        consumed: &mut usize,
+        layout: &mut Self::State,
+    ) -> Result<bool, EtError> {
+        if buf.is_empty() && eof {
+            return Ok(false);
+        } else if buf.len() < layout.record_len {
+            return Err(
+                EtError::from("MSScan record is incomplete").incomplete_needing(layout.record_len)
+            );
+        }
+        *consumed += layout.record_len;
+        Ok(true)
+    }
+
+    fn get(&mut self, buf: &'b [u8], layout: &'s Self::State) -> Result<(), EtError> {
+        let le = &Endian::Little;
+        let MasshunterScanLayout {
+            mut time,
+            mut format_id,
+            mut offset,
+            mut byte_count,
+            mut point_count,
+            ..
+        } = *layout;
+        self.time = extract_with::<f64>(buf, &mut time, le)?;
+        self.format_id = extract_with::<i16>(buf, &mut format_id, le)?;
+        self.offset = extract_with::<u64>(buf, &mut offset, le)?;
+        self.byte_count = extract_with::<u32>(buf, &mut byte_count, le)?;
+        self.point_count = extract_with::<u32>(buf, &mut point_count, le)?;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// The intensity at a single m/z in a MassHunter MS run
+pub struct MasshunterMsRecord {
+    /// The time the scan was taken at
+    pub time: f64,
+    /// The mass-to-charge ratio
+    pub mz: f64,
+    /// The intensity recorded
+    pub intensity: f64,
+}
+
+impl_record!(MasshunterMsRecord: time, mz, intensity);
+
+/// One of the files in a MassHunter run that the spectra are stored in
+#[derive(Debug)]
+struct SpectrumFile {
+    name: &'static str,
+    /// `None` if the run doesn't have this file
+    file: Option<(File, u64)>,
+}
+
+impl SpectrumFile {
+    fn open(dir: &Path, name: &'static str) -> Result<Self, EtError> {
+        let file = match File::open(dir.join(name)) {
+            Ok(file) => {
+                let len = file.metadata()?.len();
+                Some((file, len))
+            }
+            Err(_) => None,
+        };
+        Ok(SpectrumFile { name, file })
+    }
+
+    /// Read the `buf.len()` bytes of the spectrum at `offset` into `buf`
+    fn read_at(&mut self, offset: u64, buf: &mut [u8], time: f64) -> Result<(), EtError> {
+        let name = self.name;
+        let (file, len) = self
+            .file
+            .as_mut()
+            .ok_or_else(|| EtError::from(format!("{} could not be opened", name)))?;
+        if offset.saturating_add(buf.len() as u64) > *len {
+            return Err(format!("Spectrum at {} min is past the end of {}", time, name).into());
+        }
+        let _ = file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(buf)?;
+        Ok(())
+    }
+}
+
+/// How the points of the current spectrum are stored
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SpectrumPoints {
+    /// A starting m/z and the step between points (as two f64s) and then the intensity at each
+    /// point (as f32s)
+    Profile { mz_start: f64, mz_step: f64 },
+    /// The m/z of every peak (as f64s) and then the intensity of each (as f32s or f64s)
+    Peaks { intensity_width: usize },
+}
+
+/// Read the mass spectra from a MassHunter `.d` run (`AcqData/MSScan.bin` and the
+/// `MSProfile.bin` and `MSPeak.bin` next to it), along with the run information in
+/// `Contents.xml`.
+///
+/// Profile spectra are read from `MSProfile.bin` and centroided spectra from `MSPeak.bin`;
+/// compressed spectra return an error.
+#[derive(Debug)]
+pub struct MasshunterMsReader<'r> {
+    rb: ReadBuffer<'r>,
+    state: MasshunterMsState,
+    profile: SpectrumFile,
+    peak: SpectrumFile,
+    time: f64,
+    /// The current spectrum, laid out as described by `points`
+    spectrum: Vec<u8>,
+    points: SpectrumPoints,
+    n_points: usize,
+    point_ix: usize,
+}
+
+impl<'r> MasshunterMsReader<'r> {
+    /// Create a new `MasshunterMsReader` from the data in `MSScan.bin`; `params` must be the
+    /// path to it so the other files in the run can be found.
+    ///
+    /// # Errors
+    /// If the other files in the run don't exist or can't be read, an error will be returned.
+    pub fn new<B>(data: B, params: Option<String>) -> Result<Self, EtError>
+    where
+        B: ::core::convert::TryInto<ReadBuffer<'r>>,
+        EtError: From<<B as ::core::convert::TryInto<ReadBuffer<'r>>>::Error>,
+    {
+        let filename = params.ok_or_else(|| {
+            EtError::new("Parser requires a filename; streams can not be parsed.")
+        })?;
+        let dir = Path::new(&filename)
+            .parent()
+            .map_or_else(PathBuf::new, Path::to_path_buf);
+        let profile = SpectrumFile::open(&dir, "MSProfile.bin")?;
+        let peak = SpectrumFile::open(&dir, "MSPeak.bin")?;
+        if profile.file.is_none() && peak.file.is_none() {
+            return Err("MSProfile.bin or MSPeak.bin could not be opened".into());
+        }
+
+        let (rb, mut state) = init_state::<MasshunterMsState, _, _>(data, None)?;
+        state.layout = match std::fs::read(dir.join("MSScan.xsd")) {
+            Ok(xsd) => MasshunterScanLayout::from_xsd(&xsd)?,
+            Err(_) => MasshunterScanLayout::from_fields(DEFAULT_SCAN_FIELDS.iter().copied())?,
+        };
+        state.metadata = read_contents(&dir.join("Contents.xml"))?;
+        Ok(MasshunterMsReader {
+            rb,
+            state,
+            profile,
+            peak,
+            time: 0.,
+            spectrum: Vec::new(),
+            points: SpectrumPoints::Peaks { intensity_width: 4 },
+            n_points: 0,
+            point_ix: 0,
+        })
+    }
+
+    /// Read the spectrum for `scan` out of `MSProfile.bin` or `MSPeak.bin`
+    fn read_spectrum(&mut self, scan: MasshunterScan) -> Result<(), EtError> {
+        let byte_count = u64::from(scan.byte_count);
+        let point_count = u64::from(scan.point_count);
+        let (file, intensity_width) = match scan.format_id {
+            PROFILE_FORMAT if byte_count == 16 + 4 * point_count => (&mut self.profile, None),
+            PEAK_FORMAT if byte_count == 12 * point_count => (&mut self.peak, Some(4)),
+            PEAK_FORMAT if byte_count == 16 * point_count => (&mut self.peak, Some(8)),
+            PROFILE_FORMAT | PEAK_FORMAT => {
+                return Err("Compressed MassHunter spectra are not supported".into());
+            }
+            f => {
+                return Err(format!(
+                    "MassHunter spectrum format {} is not supported (only profile and peak spectra are)",
+                    f
+                )
+                .into());
+            }
+        };
+        self.spectrum.resize(byte_count as usize, 0);
+        file.read_at(scan.offset, &mut self.spectrum, scan.time)?;
+        self.points = match intensity_width {
+            Some(intensity_width) => SpectrumPoints::Peaks { intensity_width },
+            None => SpectrumPoints::Profile {
+                mz_start: extract_with::<f64>(&self.spectrum, &mut 0, &Endian::Little)?,
+                mz_step: extract_with::<f64>(&self.spectrum, &mut 8, &Endian::Little)?,
+            },
+        };
+        self.time = scan.time;
+        self.n_points = scan.point_count as usize;
+        self.point_ix = 0;
+        Ok(())
+    }
+
+    /// Return the next record
+    ///
+    /// # Errors
+    /// If the next record can't be read, returns an error.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<MasshunterMsRecord>, EtError> {
+        while self.point_ix >= self.n_points {
+            let scan = match self.rb.next::<MasshunterScan>(&mut self.state.layout)? {
+                Some(scan) => scan,
+                None => return Ok(None),
+            };
+            self.read_spectrum(scan)?;
+        }
+        let le = &Endian::Little;
+        let (mz, intensity) = match self.points {
+            SpectrumPoints::Profile { mz_start, mz_step } => {
+                #[allow(clippy::cast_precision_loss)]
+                let mz = mz_start + mz_step * self.point_ix as f64;
+                let pos = &mut (16 + 4 * self.point_ix);
+                (mz, f64::from(extract_with::<f32>(&self.spectrum, pos, le)?))
+            }
+            SpectrumPoints::Peaks { intensity_width } => {
+                let mz = extract_with::<f64>(&self.spectrum, &mut (8 * self.point_ix), le)?;
+                let pos = &mut (8 * self.n_points + intensity_width * self.point_ix);
+                let intensity = if intensity_width == 4 {
+                    f64::from(extract_with::<f32>(&self.spectrum, pos, le)?)
+                } else {
+                    extract_with::<f64>(&self.spectrum, pos, le)?
+                };
+                (mz, intensity)
+            }
+        };
+        self.point_ix += 1;
+        Ok(Some(MasshunterMsRecord {
+            time: self.time,
+            mz,
+            intensity,
+        }))
+    }
+}
+
+impl<'r> RecordReader for MasshunterMsReader<'r> {
+    /// The next record, expressed as a `Vec` of `Value`s.
+    fn next_record(&mut self) -> Result<Option<::alloc::vec::Vec<Value<'_>>>, EtError> {
+        Ok(self.next()?.map(Into::into))
+    }
+
+    /// The headers for this Reader.
+    fn headers(&self) -> ::alloc::vec::Vec<::alloc::string::String> {
+        self.state
+            .header()
+            .iter()
+            .map(|s| (*s).to_string())
+            .collect()
+    }
+
+    /// The metadata for this Reader.
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        self.state.metadata()
+    }
+
+    /// What this Reader can do.
+    fn capabilities(&self) -> Capabilities {
+        self.state.capabilities()
+    }
 }
 
 #[cfg(test)]
@@ -253,4 +777,105 @@ mod tests {
         assert_eq!(n_mzs, 240 * 276);
         Ok(())
     }
+
+    #[test]
+    fn test_ms_file() -> Result<(), EtError> {
+        let mut filename = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        filename.push("tests/data/masshunter_example/AcqData/MSScan.bin");
+
+        let data: &[u8] =
+            include_bytes!("../../../tests/data/masshunter_example/AcqData/MSScan.bin");
+        let mut reader =
+            MasshunterMsReader::new(data, Some(filename.to_str().unwrap().to_string()))?;
+        assert_eq!(reader.headers(), ["time", "mz", "intensity"]);
+        let metadata = reader.metadata();
+        assert_eq!(metadata["instrument"], "Instrument 1".into());
+        assert_eq!(
+            metadata["run_date"],
+            DateParser::default()
+                .parse_utc("2013-11-25T03:37:13Z")?
+                .into()
+        );
+        let MasshunterMsRecord {
+            time,
+            mz,
+            intensity,
+        } = reader.next()?.unwrap();
+        assert!((time - 0.00011667).abs() < 0.000001);
+        assert!((mz - 100.).abs() < 0.00001);
+        assert!((intensity - 41.72).abs() < 0.00001);
+
+        let mut n_mzs = 1;
+        let mut last = None;
+        while let Some(record) = reader.next()? {
+            n_mzs += 1;
+            last = Some(record);
+        }
+        assert_eq!(n_mzs, 49 * 5121);
+        let last = last.unwrap();
+        assert!((last.time - 0.4021).abs() < 0.000001);
+        assert!((last.mz - 612.).abs() < 0.00001);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ms_peaks() -> Result<(), EtError> {
+        // a run with a scan of two centroided peaks and then a profile scan
+        let dir = tempfile::tempdir()?;
+        let layout = MasshunterScanLayout::from_fields(DEFAULT_SCAN_FIELDS.iter().copied())?;
+        let mut scans = vec![0; 92];
+        scans[88..92].copy_from_slice(&92u32.to_le_bytes());
+        for (time, format_id, byte_count, point_count) in [(1.5, 3, 24, 2), (2., 2, 20, 1)] {
+            let mut record = vec![0; layout.record_len];
+            record[layout.time..layout.time + 8].copy_from_slice(&f64::to_le_bytes(time));
+            record[layout.format_id..layout.format_id + 2]
+                .copy_from_slice(&i16::to_le_bytes(format_id));
+            record[layout.byte_count..layout.byte_count + 4]
+                .copy_from_slice(&u32::to_le_bytes(byte_count));
+            record[layout.point_count..layout.point_count + 4]
+                .copy_from_slice(&u32::to_le_bytes(point_count));
+            scans.extend_from_slice(&record);
+        }
+        let filename = dir.path().join("MSScan.bin");
+        std::fs::write(&filename, &scans)?;
+        let mut peaks = Vec::new();
+        peaks.extend_from_slice(&100.5f64.to_le_bytes());
+        peaks.extend_from_slice(&200.25f64.to_le_bytes());
+        peaks.extend_from_slice(&10f32.to_le_bytes());
+        peaks.extend_from_slice(&20f32.to_le_bytes());
+        std::fs::write(dir.path().join("MSPeak.bin"), &peaks)?;
+
+        let filename = filename.to_str().unwrap().to_string();
+        let mut reader = MasshunterMsReader::new(&scans[..], Some(filename))?;
+        let record = reader.next()?.unwrap();
+        assert_eq!(
+            (record.time, record.mz, record.intensity),
+            (1.5, 100.5, 10.)
+        );
+        let record = reader.next()?.unwrap();
+        assert_eq!(
+            (record.time, record.mz, record.intensity),
+            (1.5, 200.25, 20.)
+        );
+        // there's no MSProfile.bin for the next scan to be read from
+        let err = reader.next().unwrap_err();
+        assert_eq!(err.msg, "MSProfile.bin could not be opened");
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_layout() -> Result<(), EtError> {
+        // the layout from the schema should match the built-in one
+        let xsd: &[u8] =
+            include_bytes!("../../../tests/data/masshunter_example/AcqData/MSScan.xsd");
+        let layout = MasshunterScanLayout::from_xsd(xsd)?;
+        assert_eq!(
+            layout,
+            MasshunterScanLayout::from_fields(DEFAULT_SCAN_FIELDS.iter().copied())?
+        );
+        assert_eq!(layout.record_len, 186);
+        assert_eq!(layout.offset, 138);
+        assert!(MasshunterScanLayout::from_fields([("ScanTime", "xs:double")]).is_err());
+        Ok(())
+    }
 }
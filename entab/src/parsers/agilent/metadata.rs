@@ -1,14 +1,141 @@
 use alloc::collections::BTreeMap;
-use alloc::{format, str};
+use alloc::format;
 use alloc::string::{String, ToString};
-use core::char::{decode_utf16, REPLACEMENT_CHARACTER};
+use alloc::vec::Vec;
 
-use chrono::NaiveDateTime;
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
 
-use crate::parsers::{Endian, FromSlice};
+use crate::parsers::datetime::{parse_or_warn, DateParser, CHEMSTATION_FORMATS};
+use crate::parsers::text::TextDecoding;
+use crate::parsers::{extract_with, Endian};
 use crate::record::Value;
 use crate::EtError;
 
+/// Units that Chemstation files report intensities in (compared case-insensitively), the
+/// standard unit for that kind of detector, and the factor that converts into it
+const UNITS: &[(&str, &str, f64)] = &[
+    ("AU", "mAU", 1000.),
+    ("mAU", "mAU", 1.),
+    ("µAU", "mAU", 0.001),
+    ("µA", "pA", 1e6),
+    ("nA", "pA", 1000.),
+    ("pA", "pA", 1.),
+    ("fA", "pA", 0.001),
+    ("V", "mV", 1000.),
+    ("mV", "mV", 1.),
+    ("µV", "mV", 0.001),
+    ("RIU", "nRIU", 1e9),
+    ("µRIU", "nRIU", 1000.),
+    ("nRIU", "nRIU", 1.),
+    ("counts", "counts", 1.),
+];
+
+/// Clean up a units string from a file header: control characters and surrounding whitespace
+/// are removed, the Greek mu (and a `u` in front of a known unit, e.g. `uV`) is written as the
+/// micro sign, and known units are given their usual capitalization (e.g. `MAU` becomes `mAU`).
+#[must_use]
+pub fn sanitize_units(raw: &str) -> String {
+    let cleaned: String = raw
+        .chars()
+        .filter(|c| !c.is_control())
+        .map(|c| if c == 'μ' { 'µ' } else { c })
+        .collect();
+    let cleaned = cleaned.trim();
+    let known = |units: &str| {
+        UNITS
+            .iter()
+            .find(|(unit, _, _)| unit.eq_ignore_ascii_case(units))
+            .map(|(unit, _, _)| (*unit).to_string())
+    };
+    if let Some(unit) = known(cleaned) {
+        return unit;
+    }
+    // only a `u` that's a prefix on a unit symbol is a micro (so e.g. `units` is left alone)
+    cleaned
+        .strip_prefix('u')
+        .and_then(|rest| known(&format!("µ{}", rest)))
+        .unwrap_or_else(|| cleaned.to_string())
+}
+
+/// The standard unit for intensities in `units` (mAU for absorbance, pA for current, mV for
+/// voltage, and nRIU for refractive index) and the factor that converts into it, if the units
+/// are known
+#[must_use]
+pub fn canonical_units(units: &str) -> Option<(&'static str, f64)> {
+    UNITS
+        .iter()
+        .find(|(unit, _, _)| *unit == units)
+        .map(|(_, canonical, factor)| (*canonical, *factor))
+}
+
+/// Parameters for reading Chemstation files
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ChemstationParams {
+    /// How text fields that aren't validly encoded are handled
+    pub text_decoding: TextDecoding,
+    /// Convert intensities into the standard unit for their detector (see `canonical_units`) so
+    /// traces from different detectors or instruments can be compared
+    pub canonical_units: bool,
+    /// Report the detector's raw values as the intensity (with the values after the file's
+    /// offset and scaling corrections in a separate `corrected_intensity` column)
+    pub raw_values: bool,
+    /// Report values that are stored as scaled integers (e.g. the m/z's in Chemstation MS
+    /// files) as those integers instead of converting them into floats; the `scale` and
+    /// `offset` in the column metadata convert them back. Only the MS parser has values like
+    /// this so the other Chemstation parsers ignore it.
+    pub integer_values: bool,
+    /// Never fall back to `default_date` for run dates that are missing or can't be parsed
+    /// (they're reported as null)
+    pub strict_dates: bool,
+    /// The run date to report for files without a parseable one (if not set, it's null)
+    pub default_date: Option<NaiveDate>,
+}
+
+impl ChemstationParams {
+    /// Set how text fields that aren't validly encoded are handled
+    #[must_use]
+    pub fn text_decoding(mut self, text_decoding: TextDecoding) -> Self {
+        self.text_decoding = text_decoding;
+        self
+    }
+
+    /// Set whether intensities are converted into the standard unit for their detector
+    #[must_use]
+    pub fn canonical_units(mut self, canonical_units: bool) -> Self {
+        self.canonical_units = canonical_units;
+        self
+    }
+
+    /// Set whether intensities are the detector's raw values instead of the corrected ones
+    #[must_use]
+    pub fn raw_values(mut self, raw_values: bool) -> Self {
+        self.raw_values = raw_values;
+        self
+    }
+
+    /// Set whether values stored as scaled integers are reported as those integers (only
+    /// used by the MS parser)
+    #[must_use]
+    pub fn integer_values(mut self, integer_values: bool) -> Self {
+        self.integer_values = integer_values;
+        self
+    }
+
+    /// Report missing or unparseable run dates as null even if there's a `default_date`
+    #[must_use]
+    pub fn strict_dates(mut self, strict_dates: bool) -> Self {
+        self.strict_dates = strict_dates;
+        self
+    }
+
+    /// Use this date for files without a parseable run date
+    #[must_use]
+    pub fn default_date(mut self, default_date: NaiveDate) -> Self {
+        self.default_date = Some(default_date);
+        self
+    }
+}
+
 #[derive(Clone, Debug, Default)]
 /// Metadata consistly found in Chemstation file formats
 pub struct ChemstationMetadata {
@@ -40,20 +167,32 @@ pub struct ChemstationMetadata {
     pub instrument: String,
     /// The method the instrument ran
     pub method: String,
-    /// The units of the y scale.
+    /// The units of the y scale (after any conversion into a standard unit).
     pub y_units: String,
+    /// The units of the y scale as they're written in the file, if intensities were converted
+    /// into other units
+    pub converted_from: Option<String>,
+    /// What intensities are multiplied by to convert them into `y_units` (1 unless they were
+    /// converted into a standard unit)
+    pub unit_scale: f64,
+    /// Whether intensities are reported uncorrected (with the corrected values alongside them)
+    pub raw_values: bool,
+    /// Problems found while reading the metadata (e.g. dates in unknown formats)
+    pub warnings: Vec<String>,
 }
 
 impl ChemstationMetadata {
-    /// Parse the header to extract the metadata
-    pub fn from_header(header: &[u8]) -> Result<Self, EtError> {
+    /// Parse the header to extract the metadata (decoding any text fields and converting units
+    /// as set in `params`)
+    pub fn from_header(header: &[u8], params: ChemstationParams) -> Result<Self, EtError> {
+        let decoding = params.text_decoding;
         if header.len() < 256 {
             return Err(EtError::from(
                 "All Chemstation header needs to be at least 256 bytes long",
             )
             .incomplete());
         }
-        let version = u32::extract(&header[248..], &Endian::Big)?;
+        let version = extract_with::<u32>(header, &mut 248, &Endian::Big)?;
 
         let required_length = match version {
             2 | 31 | 102 => 512,
@@ -82,99 +221,99 @@ impl ChemstationMetadata {
         // There's another data section at 4100 that
         // has duplicates of some of these values?
 
-        let sequence = u16::extract(&header[252..], &Endian::Big)?;
-        let vial = u16::extract(&header[254..], &Endian::Big)?;
-        let replicate = u16::extract(&header[256..], &Endian::Big)?;
+        let sequence = extract_with::<u16>(header, &mut 252, &Endian::Big)?;
+        let vial = extract_with::<u16>(header, &mut 254, &Endian::Big)?;
+        let replicate = extract_with::<u16>(header, &mut 256, &Endian::Big)?;
 
         let sample = match version {
-            0..=102 => get_pascal(&header[24..24 + 60], "sample")?,
-            _ => get_utf16_pascal(&header[858..]),
+            0..=102 => get_pascal(&header[24..24 + 60], "sample", decoding)?,
+            _ => get_utf16_pascal(&header[858..], decoding)?,
         };
         let description = match version {
-            0..=102 => get_pascal(&header[86..86 + 60], "description")?,
+            0..=102 => get_pascal(&header[86..86 + 60], "description", decoding)?,
             _ => "".to_string(),
         };
         let operator = match version {
-            0..=102 => get_pascal(&header[148..148 + 28], "operator")?,
-            _ => get_utf16_pascal(&header[1880..]),
+            0..=102 => get_pascal(&header[148..148 + 28], "operator", decoding)?,
+            _ => get_utf16_pascal(&header[1880..], decoding)?,
         };
         let instrument = match version {
-            0..=102 => get_pascal(&header[208..228], "instrument")?,
-            _ => get_utf16_pascal(&header[2492..]),
+            0..=102 => get_pascal(&header[208..228], "instrument", decoding)?,
+            _ => get_utf16_pascal(&header[2492..], decoding)?,
         };
         let method = match version {
-            0..=102 => get_pascal(&header[228..], "method")?,
-            _ => get_utf16_pascal(&header[2574..]),
+            0..=102 => get_pascal(&header[228..], "method", decoding)?,
+            _ => get_utf16_pascal(&header[2574..], decoding)?,
         };
 
         let signal_name = match version {
-            30 | 81 => get_pascal(&header[596..596 + 40], "signal_name")?,
-            130 | 179 => get_utf16_pascal(&header[4213..]),
+            30 | 81 => get_pascal(&header[596..596 + 40], "signal_name", decoding)?,
+            130 | 179 => get_utf16_pascal(&header[4213..], decoding)?,
             _ => "".to_string(),
         };
 
         let offset_correction = match version {
-            30 | 81 => f64::extract(&header[636..], &Endian::Big)?,
+            30 | 81 => extract_with::<f64>(header, &mut 636, &Endian::Big)?,
             _ => 0.,
         };
         let mult_correction = match version {
-            30 | 81 => f64::extract(&header[644..], &Endian::Big)?,
-            131 => f64::extract(&header[3085..3093], &Endian::Big)?,
-            130 | 179 => f64::extract(&header[4732..4770], &Endian::Big)?,
+            30 | 81 => extract_with::<f64>(header, &mut 644, &Endian::Big)?,
+            131 => extract_with::<f64>(header, &mut 3085, &Endian::Big)?,
+            130 | 179 => extract_with::<f64>(header, &mut 4732, &Endian::Big)?,
             _ => 1.,
         };
         let start_time = match version {
             2 | 30 | 81 | 102 | 130 | 131 => {
-                i32::extract(&header[282..], &Endian::Big)? as f64 / 60000.
+                extract_with::<i32>(header, &mut 282, &Endian::Big)? as f64 / 60000.
             }
-            179 => f32::extract(&header[282..], &Endian::Big)? as f64 / 60000.,
+            179 => extract_with::<f32>(header, &mut 282, &Endian::Big)? as f64 / 60000.,
             _ => 0.,
         };
         let end_time = match version {
             2 | 30 | 81 | 102 | 130 | 131 => {
-                i32::extract(&header[286..], &Endian::Big)? as f64 / 60000.
+                extract_with::<i32>(header, &mut 286, &Endian::Big)? as f64 / 60000.
             }
-            179 => f32::extract(&header[286..], &Endian::Big)? as f64 / 60000.,
+            179 => extract_with::<f32>(header, &mut 286, &Endian::Big)? as f64 / 60000.,
             _ => 0.,
         };
-        let y_units = match version {
-            81 => get_pascal(&header[244..244 + 64], "y_units")?,
-            131 => get_utf16_pascal(&header[3093..]),
-            130 | 179 => get_utf16_pascal(&header[4172..]),
+        let mut y_units = match version {
+            30 | 81 => get_pascal(&header[580..596], "y_units", decoding)?,
+            131 => get_utf16_pascal(&header[3093..], decoding)?,
+            130 | 179 => get_utf16_pascal(&header[4172..], decoding)?,
             _ => "".to_string(),
         };
+        y_units = sanitize_units(&y_units);
+        let mut converted_from = None;
+        let mut unit_scale = 1.;
+        if params.canonical_units {
+            if let Some((canonical, factor)) = canonical_units(&y_units) {
+                if canonical != y_units {
+                    converted_from = Some(y_units);
+                    y_units = canonical.to_string();
+                    unit_scale = factor;
+                }
+            }
+        }
 
         // We need to detect the date format before we can convert into a
         // NaiveDateTime; not sure the format even maps to the file type
         // (it may be computer-dependent?)
         let raw_run_date = match version {
-            0..=102 => get_pascal(&header[178..178 + 60], "run_date")?,
-            130 | 131 | 179 => get_utf16_pascal(&header[2391..]),
+            0..=102 => get_pascal(&header[178..178 + 60], "run_date", decoding)?,
+            130 | 131 | 179 => get_utf16_pascal(&header[2391..], decoding)?,
             _ => "".to_string(),
         };
-        let run_date = if let Ok(d) =
-            NaiveDateTime::parse_from_str(raw_run_date.as_ref(), "%d-%b-%y, %H:%M:%S")
-        {
-            // format in MWD
-            Some(d)
-        } else if let Ok(d) =
-            NaiveDateTime::parse_from_str(raw_run_date.as_ref(), "%d %b %y %l:%M %P")
-        {
-            // format in MS
-            Some(d)
-        } else if let Ok(d) =
-            NaiveDateTime::parse_from_str(raw_run_date.as_ref(), "%d %b %y %l:%M %P %z")
-        {
-            // format in MS with timezone
-            Some(d)
-        } else if let Ok(d) =
-            NaiveDateTime::parse_from_str(raw_run_date.as_ref(), "%m/%d/%y %I:%M:%S %p")
-        {
-            // format in FID
-            Some(d)
-        } else {
-            None
-        };
+        let mut warnings = Vec::new();
+        let run_date = parse_or_warn(
+            &DateParser::new(CHEMSTATION_FORMATS),
+            "run_date",
+            &raw_run_date,
+            &mut warnings,
+        )
+        .or_else(|| match params.default_date {
+            Some(date) if !params.strict_dates => Some(date.and_time(NaiveTime::MIN)),
+            _ => None,
+        });
 
         Ok(Self {
             start_time,
@@ -192,8 +331,69 @@ impl ChemstationMetadata {
             instrument,
             method,
             y_units,
+            converted_from,
+            unit_scale,
+            raw_values: params.raw_values,
+            warnings,
         })
     }
+
+    /// Metadata for the intensity column: its units and how the detector's raw values were
+    /// scaled into them
+    #[must_use]
+    pub fn intensity_metadata(&self) -> BTreeMap<String, Value<'static>> {
+        let mut map = BTreeMap::new();
+        if !self.y_units.is_empty() {
+            drop(map.insert("units".to_string(), self.y_units.clone().into()));
+        }
+        if let Some(units) = &self.converted_from {
+            drop(map.insert("converted_from".to_string(), units.clone().into()));
+        }
+        drop(map.insert(
+            "scale".to_string(),
+            (self.mult_correction * self.unit_scale).into(),
+        ));
+        drop(map.insert(
+            "offset".to_string(),
+            (self.offset_correction * self.unit_scale).into(),
+        ));
+        map
+    }
+
+    /// Apply the file's offset and scaling corrections (and any unit conversion) to a raw
+    /// detector value
+    #[must_use]
+    pub fn correct(&self, raw: f64) -> f64 {
+        (raw * self.mult_correction + self.offset_correction) * self.unit_scale
+    }
+
+    /// The `intensity` and `corrected_intensity` for a raw detector value: the corrected value
+    /// and nothing normally, or the raw value and the corrected one if `raw_values` was set
+    #[must_use]
+    pub fn intensities(&self, raw: f64) -> (f64, Option<f64>) {
+        if self.raw_values {
+            (raw, Some(self.correct(raw)))
+        } else {
+            (self.correct(raw), None)
+        }
+    }
+
+    /// The column metadata for a reader whose only intensity column is called `intensity`
+    /// (plus `corrected_intensity` if `raw_values` was set)
+    #[must_use]
+    pub fn column_metadata(&self) -> BTreeMap<String, BTreeMap<String, Value<'static>>> {
+        let mut map = BTreeMap::new();
+        if self.raw_values {
+            let mut raw = BTreeMap::new();
+            drop(raw.insert("scale".to_string(), 1.0.into()));
+            drop(raw.insert("offset".to_string(), 0.0.into()));
+            drop(map.insert("intensity".to_string(), raw));
+            drop(map.insert("corrected_intensity".to_string(), self.intensity_metadata()));
+        } else {
+            drop(map.insert("intensity".to_string(), self.intensity_metadata()));
+        }
+        map
+    }
 }
 
 impl<'r> From<&ChemstationMetadata> for BTreeMap<String, Value<'r>> {
@@ -226,23 +426,49 @@ impl<'r> From<&ChemstationMetadata> for BTreeMap<String, Value<'r>> {
         drop(map.insert("instrument".to_string(), metadata.instrument.clone().into()));
         drop(map.insert("method".to_string(), metadata.method.clone().into()));
         drop(map.insert("y_units".to_string(), metadata.y_units.clone().into()));
+        if !metadata.warnings.is_empty() {
+            drop(map.insert("warnings".to_string(), metadata.warnings.clone().into()));
+        }
         map
     }
 }
 
-fn get_utf16_pascal(data: &[u8]) -> String {
-    let iter = (1..=2 * usize::from(data[0]))
-        .step_by(2)
-        .map(|i| u16::from_le_bytes([data[i], data[i + 1]]));
-    decode_utf16(iter)
-        .map(|r| r.unwrap_or(REPLACEMENT_CHARACTER))
-        .collect::<String>()
+fn get_utf16_pascal(data: &[u8], decoding: TextDecoding) -> Result<String, EtError> {
+    let string_len = 2 * usize::from(data[0]);
+    let text = data
+        .get(1..1 + string_len)
+        .ok_or_else(|| EtError::from("Invalid text length").incomplete())?;
+    decoding.decode_utf16le(text)
 }
 
-fn get_pascal(data: &[u8], field_name: &'static str) -> Result<String, EtError> {
+fn get_pascal(
+    data: &[u8],
+    field_name: &'static str,
+    decoding: TextDecoding,
+) -> Result<String, EtError> {
     let string_len = usize::from(data[0]);
-    if string_len > data.len() {
+    if string_len >= data.len() {
         return Err(EtError::from(format!("Invalid {} length", field_name)).incomplete());
     }
-    Ok(str::from_utf8(&data[1..1 + string_len])?.trim().to_string())
+    Ok(decoding
+        .decode(&data[1..1 + string_len])?
+        .trim()
+        .to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_units() {
+        assert_eq!(sanitize_units(" MAU\0"), "mAU");
+        assert_eq!(sanitize_units("uV"), "µV");
+        assert_eq!(sanitize_units("μRIU"), "µRIU");
+        assert_eq!(sanitize_units("LU"), "LU");
+        assert_eq!(sanitize_units("units"), "units");
+        assert_eq!(sanitize_units("u"), "u");
+        assert_eq!(canonical_units("µV"), Some(("mV", 0.001)));
+        assert_eq!(canonical_units("LU"), None);
+    }
 }
@@ -10,32 +10,106 @@ pub mod chemstation_new;
 pub mod masshunter;
 /// Read the common metadata format at the top of Chemstation files
 pub mod metadata;
+/// Reader that merges all of the signals in a Chemstation `.D` run directory
+#[cfg(feature = "std")]
+pub mod run;
+
+use alloc::format;
 
 use crate::error::EtError;
-use crate::parsers::common::Skip;
-use crate::parsers::{extract, Endian, FromSlice};
+use crate::parsers::{extract_with, Endian};
+
+/// The largest header `read_agilent_header` will accept
+const MAX_HEADER_SIZE: usize = 1 << 20;
 
 /// Read the header chunk for an Agilent file
 pub(crate) fn read_agilent_header(rb: &[u8], ms_format: bool) -> Result<usize, EtError> {
     if rb.len() < 268 {
-        return Err(EtError::from("Agilent header too short").incomplete());
+        return Err(EtError::from("Agilent header too short").incomplete_needing(268));
     }
 
     // figure out how big the header should be and then get it
-    let raw_header_size = u32::extract(&rb[264..268], &Endian::Big)? as usize;
+    let raw_header_size = extract_with::<u32>(rb, &mut 264, &Endian::Big)? as usize;
     if raw_header_size == 0 {
         return Err("Invalid header length of 0".into());
     }
     let mut header_size = 2 * (raw_header_size - 1);
     if !ms_format {
-        header_size *= 256;
+        header_size = header_size.saturating_mul(256);
     }
     if header_size < 512 {
         return Err("Header length too short".into());
-    } else if header_size > 20_000 {
-        return Err("Header length too long".into());
+    } else if header_size > MAX_HEADER_SIZE {
+        return Err(format!("Header length of {} is too long", header_size).into());
+    }
+    if rb.len() < header_size {
+        // ask for the whole header at once so the buffer only has to grow once
+        return Err(EtError::from("Agilent header incomplete").incomplete_needing(header_size));
+    }
+    Ok(header_size)
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use super::*;
+    use crate::buffer::ReadBuffer;
+    use crate::parsers::agilent::chemstation::ChemstationFidReader;
+    use crate::parsers::agilent::chemstation_new::ChemstationArrayReader;
+    use crate::readers::RecordReader;
+
+    /// A reader that only returns a few bytes from each `read` call (like some decompressors)
+    struct Trickle<R>(R);
+
+    impl<R: Read> Read for Trickle<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = buf.len().min(37);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    fn trickle_buffer(data: &'static [u8]) -> Result<ReadBuffer<'static>, EtError> {
+        ReadBuffer::from_reader(Box::new(Trickle(Cursor::new(data))), Some(64))
+    }
+
+    fn count_records(mut reader: impl RecordReader) -> Result<usize, EtError> {
+        let mut n_records = 0;
+        while reader.next_record()?.is_some() {
+            n_records += 1;
+        }
+        Ok(n_records)
+    }
+
+    #[test]
+    fn test_read_agilent_header() {
+        let data: &[u8] = include_bytes!("../../../tests/data/test_fid.ch");
+        let e = read_agilent_header(&data[..100], false).unwrap_err();
+        assert!(e.incomplete);
+        assert_eq!(e.needed, Some(268));
+        let e = read_agilent_header(&data[..300], false).unwrap_err();
+        assert!(e.incomplete);
+        assert_eq!(e.needed, Some(1024));
+        assert_eq!(read_agilent_header(data, false).ok(), Some(1024));
+
+        let mut data = data[..1024].to_vec();
+        data[264..268].copy_from_slice(&[0xFF; 4]);
+        assert!(!read_agilent_header(&data, false).unwrap_err().incomplete);
+    }
+
+    #[test]
+    fn test_headers_spanning_refills() -> Result<(), EtError> {
+        let data: &[u8] = include_bytes!("../../../tests/data/test_fid.ch");
+        let n_records = count_records(ChemstationFidReader::new(data, None)?)?;
+        let reader = ChemstationFidReader::new(trickle_buffer(data)?, None)?;
+        assert_eq!(reader.metadata()["sample"], "5970 mix 10nG".into());
+        assert_eq!(count_records(reader)?, n_records);
+
+        // this header is ~6kb so it needs a lot of small reads to fill
+        let data: &[u8] = include_bytes!("../../../tests/data/test_179_fid.ch");
+        let n_records = count_records(ChemstationArrayReader::new(data, None)?)?;
+        let reader = ChemstationArrayReader::new(trickle_buffer(data)?, None)?;
+        assert_eq!(count_records(reader)?, n_records);
+        Ok(())
     }
-    let con = &mut 0;
-    let _ = extract::<Skip>(rb, con, &mut header_size)?;
-    Ok(*con)
 }
@@ -0,0 +1,318 @@
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use std::fs::{read_dir, File};
+use std::path::{Path, PathBuf};
+
+use crate::capabilities::Capabilities;
+use crate::error::EtError;
+use crate::readers::{get_reader, RecordReader};
+use crate::record::Value;
+
+/// The extensions of the signal files in a Chemstation run (DAD/MWD/FID traces are `.ch` or `.uv`
+/// files and mass spectra are `.ms` files)
+const SIGNAL_EXTENSIONS: [&str; 3] = ["ch", "uv", "ms"];
+
+/// Find the signal files of the Chemstation run that `path` is part of: the files in `path` if
+/// it's a `.D` directory or the files next to it if it's one of the files inside one. The paths
+/// are sorted by name so the signals are always read in the same order.
+///
+/// # Errors
+/// If the directory can't be read or has no signal files in it, an error is returned.
+pub fn run_signal_paths(path: &Path) -> Result<Vec<PathBuf>, EtError> {
+    let dir = if path.is_dir() {
+        path
+    } else {
+        path.parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."))
+    };
+    let mut paths = Vec::new();
+    for entry in read_dir(dir)? {
+        let entry_path = entry?.path();
+        let is_signal = entry_path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| SIGNAL_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()));
+        if is_signal && entry_path.is_file() {
+            paths.push(entry_path);
+        }
+    }
+    if paths.is_empty() {
+        return Err(format!(
+            "{} is not a Chemstation run (it has no .ch, .uv, or .ms files)",
+            dir.display()
+        )
+        .into());
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Note which signal of the run an error came from
+fn in_signal(mut err: EtError, signal: &str) -> EtError {
+    err.msg = format!("{} (in {})", err.msg, signal).into();
+    err
+}
+
+/// One of the signal files being read by an `AgilentRunReader`
+struct RunSignal {
+    /// The name of the file without its extension (e.g. `MSD1` or `mwd1A`)
+    name: String,
+    reader: Box<dyn RecordReader>,
+    /// Where each of this signal's columns goes in the merged record
+    columns: Vec<usize>,
+}
+
+/// Reads all of the signals (MS, DAD, MWD, and FID files) in a Chemstation `.D` run directory as
+/// one stream of records with a `signal` column naming the file each record came from.
+///
+/// The columns are the union of the columns of all of the signals (e.g. `mz` for the MS records
+/// and `wavelength` for the DAD records) with nulls for the columns a signal doesn't have. MWD
+/// traces call their wavelength `signal`, so it's moved into the `wavelength` column here.
+///
+/// Metadata that's the same for every signal (e.g. the `sample` and `method`) is kept as-is,
+/// while values that differ between the signals are prefixed with the signal they're from (e.g.
+/// `mwd1A.signal_name`); the `signals` key lists the names of all of the signals.
+pub struct AgilentRunReader {
+    signals: Vec<RunSignal>,
+    headers: Vec<String>,
+    metadata: BTreeMap<String, Value<'static>>,
+    /// The index of the signal currently being read
+    cur_signal: usize,
+}
+
+impl AgilentRunReader {
+    /// Create a new `AgilentRunReader` for the run directory at `path` (or the run that the file
+    /// at `path` is in); `params` are passed to the reader of every signal.
+    ///
+    /// # Errors
+    /// If the run has no signal files or one of them can't be read, an error is returned.
+    pub fn new(path: &Path, params: BTreeMap<String, Value<'_>>) -> Result<Self, EtError> {
+        let mut signals = Vec::new();
+        let mut headers = vec!["signal".to_string()];
+        for signal_path in run_signal_paths(path)? {
+            let name = signal_path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let mut signal_params = params.clone();
+            let _ = signal_params.insert(
+                "filename".to_string(),
+                signal_path.to_string_lossy().into_owned().into(),
+            );
+            let (reader, _) = get_reader(File::open(&signal_path)?, None, Some(signal_params))
+                .map_err(|e| in_signal(e, &name))?;
+            let columns = reader
+                .headers()
+                .into_iter()
+                .map(|header| {
+                    let header = if header == "signal" {
+                        "wavelength".to_string()
+                    } else {
+                        header
+                    };
+                    match headers.iter().position(|h| *h == header) {
+                        Some(ix) => ix,
+                        None => {
+                            headers.push(header);
+                            headers.len() - 1
+                        }
+                    }
+                })
+                .collect();
+            signals.push(RunSignal {
+                name,
+                reader,
+                columns,
+            });
+        }
+        let metadata = merge_metadata(&signals);
+        Ok(AgilentRunReader {
+            signals,
+            headers,
+            metadata,
+            cur_signal: 0,
+        })
+    }
+}
+
+/// Combine the metadata of each of the signals in a run
+fn merge_metadata(signals: &[RunSignal]) -> BTreeMap<String, Value<'static>> {
+    let all_metadata: Vec<BTreeMap<String, Value<'_>>> =
+        signals.iter().map(|s| s.reader.metadata()).collect();
+    let mut metadata = BTreeMap::new();
+    for (signal, signal_metadata) in signals.iter().zip(&all_metadata) {
+        for (key, value) in signal_metadata {
+            let is_shared = all_metadata.iter().all(|m| m.get(key) == Some(value));
+            let key = if is_shared {
+                key.clone()
+            } else {
+                format!("{}.{}", signal.name, key)
+            };
+            let _ = metadata.insert(key, value.clone().into_owned());
+        }
+    }
+    let names = signals.iter().map(|s| s.name.clone().into()).collect();
+    let _ = metadata.insert("signals".to_string(), Value::List(names));
+    metadata
+}
+
+impl core::fmt::Debug for AgilentRunReader {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("AgilentRunReader")
+            .field("headers", &self.headers)
+            .field("cur_signal", &self.cur_signal)
+            .finish()
+    }
+}
+
+impl RecordReader for AgilentRunReader {
+    fn next_record(&mut self) -> Result<Option<Vec<Value<'_>>>, EtError> {
+        let n_columns = self.headers.len();
+        loop {
+            let signal = match self.signals.get_mut(self.cur_signal) {
+                Some(signal) => signal,
+                None => return Ok(None),
+            };
+            let RunSignal { name, reader, .. } = signal;
+            let record: Vec<Value<'static>> =
+                match reader.next_record().map_err(|e| in_signal(e, name))? {
+                    Some(record) => record.into_iter().map(Value::into_owned).collect(),
+                    None => {
+                        self.cur_signal += 1;
+                        continue;
+                    }
+                };
+            let mut values = vec![Value::Null; n_columns];
+            values[0] = Value::String(Cow::Borrowed(&self.signals[self.cur_signal].name));
+            for (ix, value) in self.signals[self.cur_signal].columns.iter().zip(record) {
+                values[*ix] = value;
+            }
+            return Ok(Some(values));
+        }
+    }
+
+    fn headers(&self) -> Vec<String> {
+        self.headers.clone()
+    }
+
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        self.metadata.clone()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.signals
+            .get(self.cur_signal..)
+            .unwrap_or_default()
+            .iter()
+            .map(|s| s.reader.size_hint())
+            .fold((0, Some(0)), |(low, high), (s_low, s_high)| {
+                (
+                    low.saturating_add(s_low),
+                    high.zip(s_high).and_then(|(h, s)| h.checked_add(s)),
+                )
+            })
+    }
+
+    /// The reader has metadata and reads from several files; it only knows how many records
+    /// are left (or their types) if all of the signals do
+    fn capabilities(&self) -> Capabilities {
+        let shared = self.signals.iter().fold(
+            Capabilities::SIZED | Capabilities::TYPED_SCHEMA,
+            |caps, s| caps & s.reader.capabilities(),
+        );
+        shared | Capabilities::HAS_METADATA | Capabilities::MULTI_STREAM
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_signal_paths() -> Result<(), EtError> {
+        let paths = run_signal_paths(Path::new("tests/data/carotenoid_extract.d"))?;
+        assert_eq!(paths.len(), 2);
+        assert!(paths[0].ends_with("MSD1.MS"));
+        assert!(paths[1].ends_with("dad1.uv"));
+
+        // any file in the run finds the same signals
+        let paths = run_signal_paths(Path::new("tests/data/chemstation_mwd.d/RUN.LOG"))?;
+        assert_eq!(paths.len(), 5);
+
+        assert!(run_signal_paths(Path::new("tests/data/masshunter_example")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_agilent_run_reader() -> Result<(), EtError> {
+        let mut reader =
+            AgilentRunReader::new(Path::new("tests/data/chemstation_mwd.d"), BTreeMap::new())?;
+        assert_eq!(
+            reader.headers(),
+            ["signal", "time", "wavelength", "intensity"]
+        );
+        let metadata = reader.metadata();
+        assert_eq!(metadata["sample"], "Cytochrome C".into());
+        assert_eq!(
+            metadata["mwd1B.signal_name"],
+            "MWD B, Sig=230,5 Ref=360,100".into()
+        );
+        assert!(!metadata.contains_key("signal_name"));
+        assert_eq!(
+            metadata["signals"],
+            Value::List(vec![
+                "mwd1A".into(),
+                "mwd1B".into(),
+                "mwd1C".into(),
+                "mwd1D".into(),
+                "mwd1E".into()
+            ])
+        );
+        let record = reader.next_record()?.unwrap();
+        assert_eq!(record[0], "mwd1A".into());
+        assert_eq!(record[2], Value::Float(210.));
+
+        let mut n_records = 1;
+        let mut last_signal = String::new();
+        while let Some(record) = reader.next_record()? {
+            if let Value::String(s) = &record[0] {
+                last_signal = s.to_string();
+            }
+            n_records += 1;
+        }
+        assert_eq!(last_signal, "mwd1E");
+        assert_eq!(n_records, 5 * 1801);
+        Ok(())
+    }
+
+    #[test]
+    fn test_agilent_run_reader_mixed() -> Result<(), EtError> {
+        let mut reader = AgilentRunReader::new(
+            Path::new("tests/data/carotenoid_extract.d/dad1.uv"),
+            BTreeMap::new(),
+        )?;
+        assert_eq!(
+            reader.headers(),
+            ["signal", "time", "mz", "intensity", "wavelength"]
+        );
+        assert_eq!(reader.metadata()["method"], "RJBBARUA.M".into());
+        assert_eq!(reader.metadata()["MSD1.y_units"], "".into());
+        let record = reader.next_record()?.unwrap();
+        assert_eq!(record[0], "MSD1".into());
+        assert_eq!(record[4], Value::Null);
+        while let Some(record) = reader.next_record()? {
+            if record[0] == "dad1".into() {
+                assert_eq!(record[2], Value::Null);
+                assert_eq!(record[4], Value::Float(200.));
+                break;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,207 @@
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+use crate::bgzf::VirtualOffset;
+use crate::parsers::{extract, extract_with, Endian};
+use crate::EtError;
+
+/// The bin that holds the number of mapped and unmapped reads on a reference instead of chunks
+const PSEUDO_BIN: u32 = 37450;
+
+/// How many bases each entry in the linear index covers (as a power of 2)
+const LINEAR_SHIFT: u32 = 14;
+
+/// A stretch of a BGZF file (from `start` up to but not including `end`) with alignments in it
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct BaiChunk {
+    /// The virtual offset of the first alignment in the chunk
+    pub start: VirtualOffset,
+    /// The virtual offset just past the last alignment in the chunk
+    pub end: VirtualOffset,
+}
+
+/// The index for the alignments on one reference sequence
+#[derive(Clone, Debug, Default)]
+struct BaiReference {
+    bins: BTreeMap<u32, Vec<BaiChunk>>,
+    /// The smallest virtual offset of an alignment overlapping each 16 kb window
+    intervals: Vec<VirtualOffset>,
+}
+
+/// The bins that alignments overlapping `start..end` (0-based, end-exclusive) could be in
+fn region_to_bins(start: u64, end: u64) -> Vec<u32> {
+    let mut bins = Vec::new();
+    let end = end.max(start + 1) - 1;
+    // each level of the binning scheme splits the bins of the one above it into 8
+    for (first_bin, shift) in [(0, 29), (1, 26), (9, 23), (73, 20), (585, 17), (4681, 14)] {
+        let n_bins = 1 << (29 - shift);
+        let first = first_bin + (start >> shift).min(n_bins - 1);
+        let last = first_bin + (end >> shift).min(n_bins - 1);
+        bins.extend((first..=last).filter_map(|bin| u32::try_from(bin).ok()));
+    }
+    bins
+}
+
+/// A BAI index of a coordinate-sorted BAM file, for finding the parts of the file with the
+/// alignments in a region (see `BamReader::seek_region`).
+#[derive(Clone, Debug, Default)]
+pub struct BaiIndex {
+    references: Vec<BaiReference>,
+    /// The number of alignments without coordinates (at the end of the file), if the index has
+    /// it
+    pub n_unplaced: Option<u64>,
+}
+
+impl BaiIndex {
+    /// Read an index out of the contents of a `.bai` file.
+    ///
+    /// # Errors
+    /// If the data isn't a BAI index or is truncated, an error is returned.
+    pub fn from_slice(data: &[u8]) -> Result<Self, EtError> {
+        let con = &mut 0;
+        if extract::<&[u8]>(data, con, &mut 4).ok() != Some(&b"BAI\x01"[..]) {
+            return Err("Not a valid BAI index".into());
+        }
+        let n_references = extract_with::<u32>(data, con, &Endian::Little)?;
+        let mut references = Vec::new();
+        for _ in 0..n_references {
+            let mut reference = BaiReference::default();
+            let n_bins = extract_with::<u32>(data, con, &Endian::Little)?;
+            for _ in 0..n_bins {
+                let bin = extract_with::<u32>(data, con, &Endian::Little)?;
+                let n_chunks = extract_with::<u32>(data, con, &Endian::Little)?;
+                let mut chunks = Vec::new();
+                for _ in 0..n_chunks {
+                    let start = VirtualOffset(extract_with(data, con, &Endian::Little)?);
+                    let end = VirtualOffset(extract_with(data, con, &Endian::Little)?);
+                    chunks.push(BaiChunk { start, end });
+                }
+                if bin != PSEUDO_BIN {
+                    drop(reference.bins.insert(bin, chunks));
+                }
+            }
+            let n_intervals = extract_with::<u32>(data, con, &Endian::Little)?;
+            for _ in 0..n_intervals {
+                let offset = extract_with::<u64>(data, con, &Endian::Little)?;
+                reference.intervals.push(VirtualOffset(offset));
+            }
+            references.push(reference);
+        }
+        let n_unplaced = if data.len() >= *con + 8 {
+            Some(extract_with::<u64>(data, con, &Endian::Little)?)
+        } else {
+            None
+        };
+        Ok(BaiIndex {
+            references,
+            n_unplaced,
+        })
+    }
+
+    /// The number of reference sequences in the index
+    #[must_use]
+    pub fn n_references(&self) -> usize {
+        self.references.len()
+    }
+
+    /// The chunks of the file that alignments on the `ref_id`th reference overlapping
+    /// `start..end` (0-based, end-exclusive) could be in, in the order they're in the file and
+    /// with overlapping chunks merged.
+    ///
+    /// # Errors
+    /// If there's no reference `ref_id` in the index, an error is returned.
+    pub fn chunks(&self, ref_id: usize, start: u64, end: u64) -> Result<Vec<BaiChunk>, EtError> {
+        let reference = self.references.get(ref_id).ok_or_else(|| {
+            format!(
+                "BAI index has {} references; there is no reference {}",
+                self.references.len(),
+                ref_id
+            )
+        })?;
+        // alignments that start before this can't overlap the region
+        let min_offset = usize::try_from(start >> LINEAR_SHIFT)
+            .ok()
+            .and_then(|ix| reference.intervals.get(ix))
+            .copied()
+            .unwrap_or_default();
+        let mut chunks: Vec<BaiChunk> = region_to_bins(start, end)
+            .iter()
+            .filter_map(|bin| reference.bins.get(bin))
+            .flatten()
+            .filter(|chunk| chunk.end > min_offset)
+            .copied()
+            .collect();
+        chunks.sort_unstable_by_key(|chunk| chunk.start);
+
+        let mut merged: Vec<BaiChunk> = Vec::with_capacity(chunks.len());
+        for chunk in chunks {
+            match merged.last_mut() {
+                Some(last) if chunk.start <= last.end => last.end = last.end.max(chunk.end),
+                _ => merged.push(chunk),
+            }
+        }
+        Ok(merged)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_region_to_bins() {
+        assert_eq!(region_to_bins(0, 1), [0, 1, 9, 73, 585, 4681]);
+        assert_eq!(
+            region_to_bins(16_000, 17_000),
+            [0, 1, 9, 73, 585, 4681, 4682]
+        );
+    }
+
+    #[test]
+    fn test_bai_index() -> Result<(), EtError> {
+        let mut data = b"BAI\x01\x01\x00\x00\x00".to_vec();
+        // three bins (one being the pseudo-bin with read counts)
+        data.extend([3, 0, 0, 0]);
+        for (bin, chunks) in [
+            (4681_u32, vec![(100_u64, 200_u64)]),
+            (PSEUDO_BIN, vec![(100, 400), (3, 0)]),
+            (4682, vec![(150, 300), (500, 600)]),
+        ] {
+            data.extend(bin.to_le_bytes());
+            data.extend((chunks.len() as u32).to_le_bytes());
+            for (start, end) in chunks {
+                data.extend((start << 16).to_le_bytes());
+                data.extend((end << 16).to_le_bytes());
+            }
+        }
+        // two intervals in the linear index
+        data.extend([2, 0, 0, 0]);
+        data.extend((100_u64 << 16).to_le_bytes());
+        data.extend((450_u64 << 16).to_le_bytes());
+        data.extend(7_u64.to_le_bytes());
+
+        let index = BaiIndex::from_slice(&data)?;
+        assert_eq!(index.n_references(), 1);
+        assert_eq!(index.n_unplaced, Some(7));
+        let chunk = |start: u64, end: u64| BaiChunk {
+            start: VirtualOffset::new(start, 0),
+            end: VirtualOffset::new(end, 0),
+        };
+        assert_eq!(index.chunks(0, 0, 100)?, [chunk(100, 200)]);
+        // the overlapping chunks are merged
+        assert_eq!(
+            index.chunks(0, 16_000, 17_000)?,
+            [chunk(100, 300), chunk(500, 600)]
+        );
+        // the linear index rules out the chunks before the second window
+        assert_eq!(index.chunks(0, 17_000, 18_000)?, [chunk(500, 600)]);
+        assert!(index.chunks(1, 0, 100).is_err());
+
+        assert!(BaiIndex::from_slice(b"BAM\x01").is_err());
+        assert!(BaiIndex::from_slice(&data[..20]).is_err());
+        Ok(())
+    }
+}
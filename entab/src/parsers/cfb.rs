@@ -0,0 +1,666 @@
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::{TryFrom, TryInto};
+
+use chrono::{NaiveDateTime, TimeZone, Utc};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::capabilities::Capabilities;
+use crate::parsers::text::TextDecoding;
+use crate::parsers::{extract_with, Endian, FromSlice};
+use crate::record::{StateMetadata, Value};
+use crate::EtError;
+use crate::{impl_reader, impl_record};
+
+/// The first 8 bytes of every compound file
+const CFB_MAGIC: &[u8] = b"\xD0\xCF\x11\xE0\xA1\xB1\x1A\xE1";
+/// Sector numbers above this are markers (e.g. for the end of a chain) and not real sectors
+const MAX_REG_SECT: u32 = 0xFFFF_FFFA;
+/// Marks the end of a chain of sectors in the FAT
+const END_OF_CHAIN: u32 = 0xFFFF_FFFE;
+/// Marks a missing sibling or child in the directory
+const NO_STREAM: u32 = 0xFFFF_FFFF;
+/// The size of each entry in the directory
+const DIR_ENTRY_SIZE: usize = 128;
+/// How deeply storages can be nested (to keep malformed files from overflowing the stack)
+const MAX_DEPTH: usize = 256;
+
+/// Convert a "Windows" timestamp into a regular `DateTime`.
+///
+/// Windows time is the number of "100 nanosecond" chunks since January 1, 1601 so to convert to
+/// unix time we first need to convert into nanoseconds and then subtract the number of nanoseconds
+/// from then to Jan 1, 1970.
+///
+/// # Errors
+/// If the time is too far in the future to represent, an error is returned.
+pub fn from_windows_time(time: u64) -> Result<NaiveDateTime, EtError> {
+    let unix_time = time
+        .saturating_mul(100)
+        .saturating_sub(11_644_473_600_000_000_000);
+    Ok(Utc.timestamp_nanos(unix_time.try_into()?).naive_utc())
+}
+
+/// What kind of thing an entry in a compound file's directory is
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CfbEntryType {
+    /// A "folder" holding other storages and streams
+    Storage,
+    /// A "file" with data in it
+    Stream,
+}
+
+impl CfbEntryType {
+    /// The name of the type (as in the `type` column of the `cfb` reader)
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CfbEntryType::Storage => "storage",
+            CfbEntryType::Stream => "stream",
+        }
+    }
+}
+
+/// A storage or stream in a compound file
+#[derive(Clone, Debug, PartialEq)]
+pub struct CfbEntry {
+    /// The full path of the entry with the storages it's in separated by `/` (e.g.
+    /// `Sample1/Index`)
+    pub path: String,
+    /// If it's a storage or a stream
+    pub entry_type: CfbEntryType,
+    /// The size of the data in the stream (0 for storages)
+    pub size: u64,
+    /// When the storage was created (streams don't have this)
+    pub created: Option<NaiveDateTime>,
+    /// When the storage was last modified (streams don't have this)
+    pub modified: Option<NaiveDateTime>,
+    /// The first sector of the stream's data
+    start_sector: u32,
+}
+
+/// An entry as stored in the directory (before the tree is resolved into paths)
+#[derive(Clone, Debug)]
+struct RawEntry {
+    name: String,
+    object_type: u8,
+    left: u32,
+    right: u32,
+    child: u32,
+    created: Option<NaiveDateTime>,
+    modified: Option<NaiveDateTime>,
+    start_sector: u32,
+    size: u64,
+}
+
+impl RawEntry {
+    fn parse(buf: &[u8], sector_size: usize) -> Result<Self, EtError> {
+        let name_len = usize::from(extract_with::<u16>(buf, &mut 64, &Endian::Little)?);
+        // the name length includes the null terminator
+        let name =
+            TextDecoding::Lossy.decode_utf16le(&buf[..name_len.min(64).saturating_sub(2)])?;
+        let time = |mut at: usize| -> Result<Option<NaiveDateTime>, EtError> {
+            let time = extract_with::<u64>(buf, &mut at, &Endian::Little)?;
+            Ok(if time == 0 {
+                None
+            } else {
+                from_windows_time(time).ok()
+            })
+        };
+        let mut size = extract_with::<u64>(buf, &mut 120, &Endian::Little)?;
+        if sector_size == 512 {
+            // version 3 files may have junk in the high bits
+            size &= 0xFFFF_FFFF;
+        }
+        Ok(RawEntry {
+            name,
+            object_type: buf[66],
+            left: extract_with::<u32>(buf, &mut 68, &Endian::Little)?,
+            right: extract_with::<u32>(buf, &mut 72, &Endian::Little)?,
+            child: extract_with::<u32>(buf, &mut 76, &Endian::Little)?,
+            created: time(100)?,
+            modified: time(108)?,
+            start_sector: extract_with::<u32>(buf, &mut 116, &Endian::Little)?,
+            size,
+        })
+    }
+}
+
+/// A Microsoft "Compound File Binary" container (also known as OLE2 or structured storage),
+/// which stores a tree of storages and streams inside a single file like a small file system.
+///
+/// Several vendors (e.g. Sciex for `.wiff` files) keep their data in these so vendor parsers can
+/// use this to get at the streams they need.
+///
+/// See Microsoft's documentation for more info:
+/// <https://docs.microsoft.com/en-us/openspecs/windows_protocols/ms-cfb/05060311-bfce-4b12-874d-71fd4ce63aea>
+#[derive(Clone, Debug)]
+pub struct CompoundFile<'b> {
+    data: &'b [u8],
+    sector_size: usize,
+    mini_sector_size: usize,
+    mini_cutoff: u64,
+    fat: Vec<u32>,
+    mini_fat: Vec<u32>,
+    mini_stream: Vec<u8>,
+    entries: Vec<CfbEntry>,
+}
+
+impl<'b> CompoundFile<'b> {
+    /// Read the structure of the compound file in `data` (which has to be the entire file).
+    ///
+    /// # Errors
+    /// If `data` isn't a compound file or it's truncated or malformed, an error is returned.
+    pub fn new(data: &'b [u8]) -> Result<Self, EtError> {
+        if data.len() < 512 {
+            return Err("Compound file is too short to have a header".into());
+        }
+        if &data[..8] != CFB_MAGIC {
+            return Err("Not a compound file".into());
+        }
+        let sector_size = match extract_with::<u16>(data, &mut 30, &Endian::Little)? {
+            9 => 512,
+            12 => 4096,
+            x => return Err(format!("Invalid compound file sector shift {}", x).into()),
+        };
+        let mini_sector_size = match extract_with::<u16>(data, &mut 32, &Endian::Little)? {
+            6 => 64,
+            x => return Err(format!("Invalid compound file mini sector shift {}", x).into()),
+        };
+        let n_fat_sectors = usize::try_from(extract_with::<u32>(data, &mut 44, &Endian::Little)?)?;
+        let first_dir_sector = extract_with::<u32>(data, &mut 48, &Endian::Little)?;
+        let mini_cutoff = u64::from(extract_with::<u32>(data, &mut 56, &Endian::Little)?);
+        let first_mini_fat_sector = extract_with::<u32>(data, &mut 60, &Endian::Little)?;
+        let first_difat_sector = extract_with::<u32>(data, &mut 68, &Endian::Little)?;
+        let n_difat_sectors = extract_with::<u32>(data, &mut 72, &Endian::Little)?;
+
+        let mut file = CompoundFile {
+            data,
+            sector_size,
+            mini_sector_size,
+            mini_cutoff,
+            fat: Vec::new(),
+            mini_fat: Vec::new(),
+            mini_stream: Vec::new(),
+            entries: Vec::new(),
+        };
+
+        // the first 109 FAT sector locations are in the header and the rest are in a chain of
+        // DIFAT sectors (where the last location in each points to the next DIFAT sector)
+        let mut fat_sectors = read_u32s(&data[76..512]);
+        let mut difat_sector = first_difat_sector;
+        for _ in 0..n_difat_sectors {
+            if difat_sector > MAX_REG_SECT {
+                break;
+            }
+            let mut locations = read_u32s(file.sector(difat_sector)?);
+            difat_sector = locations.pop().unwrap_or(END_OF_CHAIN);
+            fat_sectors.extend(locations);
+        }
+        fat_sectors.truncate(n_fat_sectors);
+        let mut fat = Vec::with_capacity(fat_sectors.len() * sector_size / 4);
+        for sector in fat_sectors {
+            fat.extend(read_u32s(file.sector(sector)?));
+        }
+        file.fat = fat;
+
+        if first_mini_fat_sector <= MAX_REG_SECT {
+            file.mini_fat = read_u32s(&file.read_chain(first_mini_fat_sector, None)?);
+        }
+
+        let directory = file.read_chain(first_dir_sector, None)?;
+        let raw_entries = directory
+            .chunks_exact(DIR_ENTRY_SIZE)
+            .map(|entry| RawEntry::parse(entry, sector_size))
+            .collect::<Result<Vec<_>, _>>()?;
+        let root = match raw_entries.first() {
+            Some(root) if root.object_type == 5 => root,
+            _ => return Err("Compound file is missing its root entry".into()),
+        };
+        // the data for all of the small streams are packed together in the root's stream
+        if root.start_sector <= MAX_REG_SECT {
+            file.mini_stream = file.read_chain(root.start_sector, Some(root.size))?;
+        }
+
+        let mut visited = vec![false; raw_entries.len()];
+        visited[0] = true;
+        let mut entries = Vec::new();
+        add_entries(&raw_entries, root.child, "", 0, &mut visited, &mut entries)?;
+        file.entries = entries;
+        Ok(file)
+    }
+
+    /// Every storage and stream in the file (not including the root storage), in depth-first
+    /// order with each storage's contents right after it.
+    #[must_use]
+    pub fn entries(&self) -> &[CfbEntry] {
+        &self.entries
+    }
+
+    /// Find the storage or stream at `path` (like the Windows API, names are matched
+    /// case-insensitively).
+    #[must_use]
+    pub fn find(&self, path: &str) -> Option<&CfbEntry> {
+        let path = path.trim_start_matches('/');
+        self.entries
+            .iter()
+            .find(|e| e.path.to_lowercase() == path.to_lowercase())
+    }
+
+    /// Read all of the data in the stream at `path`.
+    ///
+    /// # Errors
+    /// If there's no stream at `path` or its data is truncated or malformed, an error is
+    /// returned.
+    pub fn read_stream(&self, path: &str) -> Result<Vec<u8>, EtError> {
+        match self.find(path) {
+            Some(entry) => self.stream_data(entry),
+            None => Err(format!("Compound file has no stream {}", path).into()),
+        }
+    }
+
+    /// Read all of the data in the stream for `entry` (from `entries`).
+    ///
+    /// # Errors
+    /// If `entry` is a storage or its data is truncated or malformed, an error is returned.
+    pub fn stream_data(&self, entry: &CfbEntry) -> Result<Vec<u8>, EtError> {
+        if entry.entry_type != CfbEntryType::Stream {
+            return Err(format!("{} is a storage, not a stream", entry.path).into());
+        }
+        if entry.size == 0 {
+            return Ok(Vec::new());
+        }
+        if entry.size >= self.mini_cutoff {
+            return self.read_chain(entry.start_sector, Some(entry.size));
+        }
+
+        // small streams are stored in "mini sectors" in the mini stream instead
+        let size = usize::try_from(entry.size)?;
+        let mut data = Vec::with_capacity(size.min(self.mini_stream.len()));
+        let mut sector = entry.start_sector;
+        while data.len() < size {
+            let start = usize::try_from(sector)? * self.mini_sector_size;
+            let chunk = self
+                .mini_stream
+                .get(start..start + self.mini_sector_size)
+                .ok_or_else(|| {
+                    format!("Stream {} is past the end of the mini stream", entry.path)
+                })?;
+            data.extend_from_slice(chunk);
+            sector = next_sector(&self.mini_fat, sector)?;
+            if data.len() > self.mini_stream.len() {
+                return Err(format!("Stream {} has a loop in its mini sectors", entry.path).into());
+            }
+        }
+        data.truncate(size);
+        Ok(data)
+    }
+
+    /// The data in sector number `sector`
+    fn sector(&self, sector: u32) -> Result<&'b [u8], EtError> {
+        let start = (usize::try_from(sector)? + 1)
+            .checked_mul(self.sector_size)
+            .ok_or("Invalid compound file sector")?;
+        if start >= self.data.len() {
+            return Err(format!(
+                "Compound file sector {} is past the end of the file",
+                sector
+            )
+            .into());
+        }
+        Ok(&self.data[start..self.data.len().min(start + self.sector_size)])
+    }
+
+    /// Read the chain of sectors starting at `start` (up to `size` bytes, if given)
+    fn read_chain(&self, start: u32, size: Option<u64>) -> Result<Vec<u8>, EtError> {
+        let max_len = match size {
+            Some(size) => usize::try_from(size)?,
+            None => self.data.len(),
+        };
+        let mut data = Vec::new();
+        let mut sector = start;
+        while sector != END_OF_CHAIN && data.len() < max_len {
+            data.extend_from_slice(self.sector(sector)?);
+            // every sector is in the file so a longer chain has to be looping
+            if data.len() > self.data.len() {
+                return Err("Compound file has a loop in its sectors".into());
+            }
+            sector = next_sector(&self.fat, sector)?;
+        }
+        if let Some(size) = size {
+            if data.len() < max_len {
+                return Err(format!(
+                    "Compound file stream should be {} bytes long, but only {} were found",
+                    size,
+                    data.len()
+                )
+                .into());
+            }
+            data.truncate(max_len);
+        }
+        Ok(data)
+    }
+}
+
+/// Read a slice as little-endian u32s
+fn read_u32s(buf: &[u8]) -> Vec<u32> {
+    buf.chunks_exact(4)
+        .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+        .collect()
+}
+
+/// Look up the sector after `sector` in a FAT (or mini FAT)
+fn next_sector(fat: &[u32], sector: u32) -> Result<u32, EtError> {
+    match fat.get(usize::try_from(sector)?) {
+        Some(&next) if next <= MAX_REG_SECT || next == END_OF_CHAIN => Ok(next),
+        Some(_) => Err(format!("Compound file sector {} isn't part of a stream", sector).into()),
+        None => Err(format!("Compound file sector {} is past the end of the FAT", sector).into()),
+    }
+}
+
+/// Add the entries in the tree of siblings starting at `id` (and the contents of any storages
+/// in it) to `entries`, in order.
+fn add_entries(
+    raw_entries: &[RawEntry],
+    id: u32,
+    prefix: &str,
+    depth: usize,
+    visited: &mut [bool],
+    entries: &mut Vec<CfbEntry>,
+) -> Result<(), EtError> {
+    if depth > MAX_DEPTH {
+        return Err("Compound file storages are nested too deeply".into());
+    }
+    // the siblings are stored as a binary tree so walk it in order
+    let mut stack = Vec::new();
+    let mut cur = id;
+    loop {
+        while cur != NO_STREAM {
+            let ix = usize::try_from(cur)?;
+            match visited.get(ix) {
+                Some(false) => visited[ix] = true,
+                Some(true) => return Err("Compound file directory has a loop".into()),
+                None => return Err(format!("Compound file has no directory entry {}", cur).into()),
+            }
+            stack.push(ix);
+            cur = raw_entries[ix].left;
+        }
+        let raw = match stack.pop() {
+            Some(ix) => &raw_entries[ix],
+            None => return Ok(()),
+        };
+        cur = raw.right;
+
+        let entry_type = match raw.object_type {
+            1 => CfbEntryType::Storage,
+            2 => CfbEntryType::Stream,
+            // unallocated entries shouldn't be in the tree, but skip them if they are
+            _ => continue,
+        };
+        let path = format!("{}{}", prefix, raw.name);
+        entries.push(CfbEntry {
+            path: path.clone(),
+            entry_type,
+            size: if entry_type == CfbEntryType::Stream {
+                raw.size
+            } else {
+                0
+            },
+            created: raw.created,
+            modified: raw.modified,
+            start_sector: raw.start_sector,
+        });
+        if entry_type == CfbEntryType::Storage {
+            let prefix = path + "/";
+            add_entries(raw_entries, raw.child, &prefix, depth + 1, visited, entries)?;
+        }
+    }
+}
+
+/// The current state of the `cfb` reader
+#[derive(Clone, Debug, Default)]
+pub struct CfbState {
+    entries: Vec<CfbEntry>,
+    cur_entry: usize,
+    metadata: BTreeMap<String, Value<'static>>,
+}
+
+impl StateMetadata for CfbState {
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        self.metadata.clone()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n_left = self.entries.len() - self.cur_entry;
+        (n_left, Some(n_left))
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::SIZED | Capabilities::HAS_METADATA | Capabilities::TYPED_SCHEMA
+    }
+
+    fn header(&self) -> Vec<&str> {
+        vec!["path", "type", "size", "created", "modified"]
+    }
+}
+
+impl<'b: 's, 's> FromSlice<'b, 's> for CfbState {
+    type State = ();
+
+    fn parse(
+        buf: &[u8],
+        eof: bool,
+        consumed: &mut usize,
+        _state: &mut Self::State,
+    ) -> Result<bool, EtError> {
+        if buf.len() >= CFB_MAGIC.len() && &buf[..CFB_MAGIC.len()] != CFB_MAGIC {
+            return Err("Not a compound file".into());
+        }
+        // the sectors can be anywhere in the file so we need all of it
+        if !eof {
+            return Err(EtError::from("Compound files are read all at once").incomplete());
+        }
+        *consumed += buf.len();
+        Ok(true)
+    }
+
+    fn get(&mut self, buf: &'b [u8], _state: &'s Self::State) -> Result<(), EtError> {
+        let file = CompoundFile::new(buf)?;
+        let mut metadata = BTreeMap::new();
+        let major_version = extract_with::<u16>(buf, &mut 26, &Endian::Little)?;
+        drop(metadata.insert("version".to_string(), major_version.into()));
+        drop(metadata.insert("sector_size".to_string(), (file.sector_size as u64).into()));
+        self.entries = file.entries;
+        self.cur_entry = 0;
+        self.metadata = metadata;
+        Ok(())
+    }
+}
+
+/// A storage or stream in a compound file
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CfbRecord<'r> {
+    /// The full path of the storage or stream
+    pub path: &'r str,
+    /// `storage` or `stream`
+    pub entry_type: &'r str,
+    /// The number of bytes in the stream
+    pub size: u64,
+    /// When the storage was created
+    pub created: Option<NaiveDateTime>,
+    /// When the storage was last modified
+    pub modified: Option<NaiveDateTime>,
+}
+
+impl_record!(CfbRecord<'r>: path, entry_type, size, created, modified);
+
+impl<'b: 's, 's> FromSlice<'b, 's> for CfbRecord<'s> {
+    type State = CfbState;
+
+    fn parse(
+        _buf: &[u8],
+        _eof: bool,
+        _consumed: &mut usize,
+        state: &mut Self::State,
+    ) -> Result<bool, EtError> {
+        // the directory was read into the state so records don't use the buffer
+        if state.cur_entry >= state.entries.len() {
+            return Ok(false);
+        }
+        state.cur_entry += 1;
+        Ok(true)
+    }
+
+    fn get(&mut self, _buf: &'b [u8], state: &'s Self::State) -> Result<(), EtError> {
+        let entry = &state.entries[state.cur_entry - 1];
+        self.path = &entry.path;
+        self.entry_type = entry.entry_type.as_str();
+        self.size = entry.size;
+        self.created = entry.created;
+        self.modified = entry.modified;
+        Ok(())
+    }
+}
+
+impl_reader!(
+    /// A reader that lists the storages and streams in a Microsoft compound file (e.g. for
+    /// seeing what's in a vendor file before writing a parser for it).
+    CfbReader,
+    CfbRecord,
+    CfbRecord<'r>,
+    CfbState,
+    ()
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::readers::get_reader;
+
+    const FREE: u32 = 0xFFFF_FFFF;
+
+    fn dir_entry(
+        name: &str,
+        object_type: u8,
+        siblings: (u32, u32, u32),
+        start: u32,
+        size: u64,
+    ) -> Vec<u8> {
+        let mut entry = vec![0; DIR_ENTRY_SIZE];
+        let name: Vec<u8> = name.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        entry[..name.len()].copy_from_slice(&name);
+        entry[64..66].copy_from_slice(&(name.len() as u16 + 2).to_le_bytes());
+        entry[66] = object_type;
+        entry[68..72].copy_from_slice(&siblings.0.to_le_bytes());
+        entry[72..76].copy_from_slice(&siblings.1.to_le_bytes());
+        entry[76..80].copy_from_slice(&siblings.2.to_le_bytes());
+        entry[116..120].copy_from_slice(&start.to_le_bytes());
+        entry[120..128].copy_from_slice(&size.to_le_bytes());
+        entry
+    }
+
+    fn sector(u32s: &[u32]) -> Vec<u8> {
+        let mut data: Vec<u8> = u32s.iter().flat_map(|i| i.to_le_bytes()).collect();
+        data.resize(512, 0xFF);
+        data
+    }
+
+    /// Build a small version 3 compound file with a `Big` stream (in regular sectors) and a
+    /// `Sample1` storage with an `Index` stream (in the mini stream) in it.
+    fn test_file() -> Vec<u8> {
+        let mut header = vec![0; 76];
+        header[..8].copy_from_slice(CFB_MAGIC);
+        header[24..26].copy_from_slice(&0x3Eu16.to_le_bytes());
+        header[26..28].copy_from_slice(&3u16.to_le_bytes());
+        header[28..30].copy_from_slice(&0xFFFEu16.to_le_bytes());
+        header[30..32].copy_from_slice(&9u16.to_le_bytes());
+        header[32..34].copy_from_slice(&6u16.to_le_bytes());
+        header[44..48].copy_from_slice(&1u32.to_le_bytes()); // FAT sectors
+        header[48..52].copy_from_slice(&1u32.to_le_bytes()); // first directory sector
+        header[56..60].copy_from_slice(&4096u32.to_le_bytes());
+        header[60..64].copy_from_slice(&2u32.to_le_bytes()); // first mini FAT sector
+        header[64..68].copy_from_slice(&1u32.to_le_bytes());
+        header[68..72].copy_from_slice(&END_OF_CHAIN.to_le_bytes());
+        let mut data = header;
+        data.extend(sector(&[0])); // the FAT is in sector 0
+        data.truncate(512);
+        data.resize(512, 0xFF);
+
+        // sector 0: FAT
+        let mut fat = vec![0xFFFF_FFFD, END_OF_CHAIN, END_OF_CHAIN, END_OF_CHAIN];
+        fat.extend(5..14);
+        fat.push(END_OF_CHAIN);
+        data.extend(sector(&fat));
+        // sector 1: directory
+        data.extend(dir_entry("Root Entry", 5, (NO_STREAM, NO_STREAM, 1), 3, 64));
+        data.extend(dir_entry("Sample1", 1, (3, NO_STREAM, 2), 0, 0));
+        data.extend(dir_entry(
+            "Index",
+            2,
+            (NO_STREAM, NO_STREAM, NO_STREAM),
+            0,
+            10,
+        ));
+        data.extend(dir_entry(
+            "Big",
+            2,
+            (NO_STREAM, NO_STREAM, NO_STREAM),
+            4,
+            5000,
+        ));
+        // sector 2: mini FAT
+        data.extend(sector(&[END_OF_CHAIN, FREE]));
+        // sector 3: mini stream
+        let mut mini = b"0123456789".to_vec();
+        mini.resize(512, 0);
+        data.extend(mini);
+        // sectors 4 to 13: the big stream
+        data.extend((0..5120).map(|i| (i % 251) as u8));
+        data
+    }
+
+    #[test]
+    fn test_compound_file() -> Result<(), EtError> {
+        let data = test_file();
+        let file = CompoundFile::new(&data)?;
+        let paths: Vec<&str> = file.entries().iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["Big", "Sample1", "Sample1/Index"]);
+        assert_eq!(file.read_stream("Sample1/Index")?, b"0123456789");
+        assert_eq!(file.read_stream("/sample1/INDEX")?, b"0123456789");
+        let big = file.read_stream("Big")?;
+        assert_eq!(big.len(), 5000);
+        assert_eq!(big[4999], (4999 % 251) as u8);
+        assert!(file.read_stream("Sample1").is_err());
+        assert!(file.read_stream("Missing").is_err());
+
+        // the big stream runs past the end of the file
+        let file = CompoundFile::new(&data[..4000])?;
+        assert!(file.read_stream("Big").is_err());
+        assert!(CompoundFile::new(&data[..300]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cfb_reader() -> Result<(), EtError> {
+        let data = test_file();
+        let (mut reader, parser) = get_reader(&data[..], None, None)?;
+        assert_eq!(parser, "cfb");
+        assert_eq!(
+            reader.headers(),
+            ["path", "type", "size", "created", "modified"]
+        );
+        assert_eq!(reader.metadata()["version"], 3.into());
+        assert_eq!(reader.size_hint(), (3, Some(3)));
+        let record = reader.next_record()?.unwrap();
+        assert_eq!(record[..3], ["Big".into(), "stream".into(), 5000.into()]);
+        let record = reader.next_record()?.unwrap();
+        assert_eq!(record[..3], ["Sample1".into(), "storage".into(), 0.into()]);
+        assert!(reader.next_record()?.is_some());
+        assert!(reader.next_record()?.is_none());
+        Ok(())
+    }
+}
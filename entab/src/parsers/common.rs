@@ -1,6 +1,6 @@
 use alloc::format;
-use core::convert::TryInto;
-use core::marker::Copy;
+use core::convert::{TryFrom, TryInto};
+use core::marker::{Copy, PhantomData};
 
 use memchr::{memchr, memchr_iter};
 
@@ -158,6 +158,169 @@ impl<'b: 's, 's> FromSlice<'b, 's> for NewLine<'b> {
     }
 }
 
+/// Remove a trailing `\n` or `\r\n` from a line
+#[inline]
+pub(crate) fn strip_line_ending(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+/// Used to read a single line out of the buffer like `NewLine`, but with a maximum length (the
+/// `state`, not counting the line ending).
+///
+/// If no line ending is found within the maximum length, an error is returned instead of
+/// pulling more and more data into the buffer looking for one (e.g. when a binary file is being
+/// read as text).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct BoundedLine<'b>(pub(crate) &'b [u8]);
+
+impl<'b: 's, 's> FromSlice<'b, 's> for BoundedLine<'b> {
+    type State = usize;
+
+    #[inline]
+    fn parse(
+        buf: &[u8],
+        eof: bool,
+        consumed: &mut usize,
+        max_len: &mut Self::State,
+    ) -> Result<bool, EtError> {
+        if buf.is_empty() {
+            if eof {
+                return Ok(false);
+            }
+            return Err(EtError::new("Could not extract a new line").incomplete());
+        }
+        // leave room for a `\r\n` after the longest allowed line
+        let search_len = buf.len().min(max_len.saturating_add(2));
+        let line_len = match memchr(b'\n', &buf[..search_len]) {
+            Some(e) => e + 1,
+            None if search_len < buf.len() || buf.len() > max_len.saturating_add(1) => {
+                return Err(format!("Line is longer than the maximum of {} bytes", max_len).into())
+            }
+            None if eof => buf.len(),
+            None => return Err(EtError::new("Could not extract a new line").incomplete()),
+        };
+        if strip_line_ending(&buf[..line_len]).len() > *max_len {
+            return Err(format!("Line is longer than the maximum of {} bytes", max_len).into());
+        }
+        *consumed += line_len;
+        Ok(true)
+    }
+
+    #[inline]
+    fn get(&mut self, buf: &'b [u8], _max_len: &Self::State) -> Result<(), EtError> {
+        self.0 = strip_line_ending(buf);
+        Ok(())
+    }
+}
+
+/// Used to read the bytes up to the next delimiter (the `state`); the delimiter is consumed,
+/// but not included in the token. At the end of the data, everything remaining is returned.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct DelimitedToken<'b>(pub(crate) &'b [u8]);
+
+impl<'b: 's, 's> FromSlice<'b, 's> for DelimitedToken<'b> {
+    type State = u8;
+
+    #[inline]
+    fn parse(
+        buf: &[u8],
+        eof: bool,
+        consumed: &mut usize,
+        delim: &mut Self::State,
+    ) -> Result<bool, EtError> {
+        if let Some(e) = memchr(*delim, buf) {
+            *consumed += e + 1;
+            return Ok(true);
+        }
+        if !eof {
+            return Err(EtError::new("Could not find the end of the token").incomplete());
+        }
+        if buf.is_empty() {
+            return Ok(false);
+        }
+        *consumed += buf.len();
+        Ok(true)
+    }
+
+    #[inline]
+    fn get(&mut self, buf: &'b [u8], delim: &Self::State) -> Result<(), EtError> {
+        self.0 = buf.strip_suffix(&[*delim]).unwrap_or(buf);
+        Ok(())
+    }
+}
+
+/// Integers that can be the length of a `LengthPrefixed` value
+pub(crate) trait LengthPrefix {
+    /// The number of bytes the length takes up
+    const SIZE: usize;
+
+    /// Read the length out of the first `SIZE` bytes of `buf`
+    fn read_len(buf: &[u8], endian: Endian) -> Result<usize, EtError>;
+}
+
+macro_rules! impl_length_prefix {
+    ($type:ty) => {
+        impl LengthPrefix for $type {
+            const SIZE: usize = core::mem::size_of::<$type>();
+
+            #[inline]
+            fn read_len(buf: &[u8], endian: Endian) -> Result<usize, EtError> {
+                let bytes = buf[..Self::SIZE].try_into().unwrap();
+                let len = match endian {
+                    Endian::Big => <$type>::from_be_bytes(bytes),
+                    Endian::Little => <$type>::from_le_bytes(bytes),
+                };
+                Ok(usize::try_from(len)?)
+            }
+        }
+    };
+}
+
+impl_length_prefix!(u8);
+impl_length_prefix!(u16);
+impl_length_prefix!(u32);
+impl_length_prefix!(u64);
+
+/// Used to read a length (of type `T` and with the endianness in the `state`) followed by that
+/// many bytes; only the bytes after the length are returned.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct LengthPrefixed<'b, T>(pub(crate) &'b [u8], pub(crate) PhantomData<T>);
+
+impl<'b: 's, 's, T> FromSlice<'b, 's> for LengthPrefixed<'b, T>
+where
+    T: LengthPrefix + Default,
+{
+    type State = Endian;
+
+    #[inline]
+    fn parse(
+        buf: &[u8],
+        _eof: bool,
+        consumed: &mut usize,
+        endian: &mut Self::State,
+    ) -> Result<bool, EtError> {
+        if buf.len() < T::SIZE {
+            return Err(EtError::new("Could not read the length").incomplete());
+        }
+        let len = T::read_len(buf, *endian)?
+            .checked_add(T::SIZE)
+            .ok_or("Length is too large")?;
+        if buf.len() < len {
+            let err: EtError = format!("Could not extract a slice of size {}", len).into();
+            return Err(err.incomplete());
+        }
+        *consumed += len;
+        Ok(true)
+    }
+
+    #[inline]
+    fn get(&mut self, buf: &'b [u8], _endian: &Self::State) -> Result<(), EtError> {
+        self.0 = &buf[T::SIZE..];
+        Ok(())
+    }
+}
+
 /// Used to read from a buffer until the given `state` slice is found and then discard everything before
 /// that `state` slice. Note that this never returns a consumed length of more than 0 because it
 /// silently updates the state as it consumes so it doesn't have to re-search the buffer if the
@@ -263,3 +426,64 @@ impl<'b: 's, 's> FromSlice<'b, 's> for EndOfFile {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+    use crate::parsers::{extract_opt, extract_with};
+
+    #[test]
+    fn test_bounded_line() -> Result<(), EtError> {
+        let buf = b"short\r\nlonger line\nend";
+        let con = &mut 0;
+        let line = extract_opt::<BoundedLine>(buf, true, con, &mut 11)?;
+        assert_eq!(line, Some(BoundedLine(b"short")));
+        let line = extract_opt::<BoundedLine>(buf, true, con, &mut 11)?;
+        assert_eq!(line, Some(BoundedLine(b"longer line")));
+        let line = extract_opt::<BoundedLine>(buf, true, con, &mut 11)?;
+        assert_eq!(line, Some(BoundedLine(b"end")));
+        assert_eq!(extract_opt::<BoundedLine>(buf, true, con, &mut 11)?, None);
+
+        // too long, with and without a line ending in sight
+        assert!(extract_opt::<BoundedLine>(b"longer line\n", true, &mut 0, &mut 10).is_err());
+        let err = extract_opt::<BoundedLine>(b"longer line", false, &mut 0, &mut 5).unwrap_err();
+        assert!(!err.incomplete);
+        // not enough data to know yet
+        let err = extract_opt::<BoundedLine>(b"line", false, &mut 0, &mut 5).unwrap_err();
+        assert!(err.incomplete);
+        Ok(())
+    }
+
+    #[test]
+    fn test_delimited_token() -> Result<(), EtError> {
+        let buf = b"a\t\tbc";
+        let con = &mut 0;
+        let mut tokens = Vec::new();
+        while let Some(DelimitedToken(token)) = extract_opt(buf, true, con, &mut b'\t')? {
+            tokens.push(token);
+        }
+        assert_eq!(tokens, [&b"a"[..], b"", b"bc"]);
+        assert!(extract_opt::<DelimitedToken>(b"abc", false, &mut 0, &mut b'\t').is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_length_prefixed() -> Result<(), EtError> {
+        let buf = b"\x00\x03abcd";
+        let con = &mut 0;
+        let LengthPrefixed(value, _) = extract_with::<LengthPrefixed<u16>>(buf, con, &Endian::Big)?;
+        assert_eq!(value, b"abc");
+        assert_eq!(*con, 5);
+
+        let err =
+            extract_with::<LengthPrefixed<u32>>(b"\x08\x00\x00\x00abc", &mut 0, &Endian::Little)
+                .unwrap_err();
+        assert!(err.incomplete);
+        // a length that would overflow shouldn't panic
+        let buf = [0xFF; 10];
+        assert!(extract_with::<LengthPrefixed<u64>>(&buf, &mut 0, &Endian::Little).is_err());
+        Ok(())
+    }
+}
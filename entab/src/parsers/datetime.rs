@@ -0,0 +1,333 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+
+use crate::EtError;
+
+/// Formats that Agilent Chemstation has been seen to write run dates in
+pub const CHEMSTATION_FORMATS: &[&str] = &[
+    // MWD
+    "%d-%b-%y, %H:%M:%S",
+    // MS
+    "%d %b %y %l:%M %P",
+    // MS with a timezone
+    "%d %b %y %l:%M %P %z",
+    // FID
+    "%m/%d/%y %I:%M:%S %p",
+];
+
+/// Formats that FCS files have been seen to write `$DATE` in
+pub const FCS_FORMATS: &[&str] = &[
+    "%d-%b-%y", // FCS2.0 only had a two-digit year, e.g. 01-JAN-20
+    "%d-%b-%Y", // FCS3.0 and 3.1 are supposed to be e.g. 01-JAN-2020
+    "%Y-%b-%d", // non-standard FCS3.0?
+    "%d-%m-%Y", // one weird Partec FCS2.0 file had this
+];
+
+/// Other common formats (mostly ISO 8601-like)
+pub const COMMON_FORMATS: &[&str] = &[
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%d",
+    "%Y/%m/%d %H:%M:%S",
+    "%Y/%m/%d",
+];
+
+/// Month names (lowercased, with abbreviations) that instrument software running in German,
+/// French, or Spanish locales write into dates
+pub const LOCALE_MONTHS: &[(&str, u32)] = &[
+    // German
+    ("januar", 1),
+    ("jän", 1),
+    ("februar", 2),
+    ("märz", 3),
+    ("mär", 3),
+    ("mrz", 3),
+    ("mai", 5),
+    ("juni", 6),
+    ("juli", 7),
+    ("okt", 10),
+    ("oktober", 10),
+    ("dez", 12),
+    ("dezember", 12),
+    // French
+    ("janv", 1),
+    ("janvier", 1),
+    ("févr", 2),
+    ("fév", 2),
+    ("février", 2),
+    ("mars", 3),
+    ("avr", 4),
+    ("avril", 4),
+    ("juin", 6),
+    ("juil", 7),
+    ("juillet", 7),
+    ("août", 8),
+    ("aoû", 8),
+    ("sept", 9),
+    ("septembre", 9),
+    ("octobre", 10),
+    ("novembre", 11),
+    ("déc", 12),
+    ("décembre", 12),
+    // Spanish
+    ("ene", 1),
+    ("enero", 1),
+    ("febrero", 2),
+    ("marzo", 3),
+    ("abr", 4),
+    ("abril", 4),
+    ("mayo", 5),
+    ("junio", 6),
+    ("julio", 7),
+    ("ago", 8),
+    ("agosto", 8),
+    ("septiembre", 9),
+    ("setiembre", 9),
+    ("octubre", 10),
+    ("noviembre", 11),
+    ("dic", 12),
+    ("diciembre", 12),
+];
+
+const ENGLISH_MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Parses the many different ways instruments write dates by trying a list of `chrono` format
+/// strings in order (after translating any non-English month names).
+#[derive(Clone, Debug)]
+pub struct DateParser {
+    formats: Vec<String>,
+    month_names: Vec<(String, u32)>,
+}
+
+impl Default for DateParser {
+    fn default() -> Self {
+        let formats = COMMON_FORMATS
+            .iter()
+            .chain(CHEMSTATION_FORMATS)
+            .chain(FCS_FORMATS);
+        DateParser::new(formats)
+    }
+}
+
+impl DateParser {
+    /// Create a parser that tries each of the `formats` in order (and understands the month
+    /// names in `LOCALE_MONTHS`)
+    pub fn new<I, S>(formats: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        DateParser {
+            formats: formats
+                .into_iter()
+                .map(|f| f.as_ref().to_string())
+                .collect(),
+            month_names: LOCALE_MONTHS
+                .iter()
+                .map(|(name, month)| ((*name).to_string(), *month))
+                .collect(),
+        }
+    }
+
+    /// Add another format to try (after all the existing ones)
+    #[must_use]
+    pub fn format(mut self, format: &str) -> Self {
+        self.formats.push(format.to_string());
+        self
+    }
+
+    /// Replace the non-English month names that are translated before parsing
+    #[must_use]
+    pub fn month_names(mut self, names: &[(&str, u32)]) -> Self {
+        self.month_names = names
+            .iter()
+            .map(|(name, month)| (name.to_lowercase(), *month))
+            .collect();
+        self
+    }
+
+    /// Replace any known non-English month names with English abbreviations
+    fn translate(&self, text: &str) -> String {
+        let mut translated = String::with_capacity(text.len());
+        let mut word = String::new();
+        // returns if the word was a month name
+        let flush = |word: &mut String, translated: &mut String| {
+            let lower = word.to_lowercase();
+            let month = self
+                .month_names
+                .iter()
+                .find(|(name, _)| *name == lower)
+                .and_then(|(_, month)| ENGLISH_MONTHS.get((*month as usize).wrapping_sub(1)));
+            translated.push_str(month.unwrap_or(&word.as_str()));
+            word.clear();
+            month.is_some()
+        };
+        for c in text.chars() {
+            if c.is_alphabetic() {
+                word.push(c);
+                continue;
+            }
+            // abbreviated months are sometimes followed by a period (e.g. "janv.")
+            if flush(&mut word, &mut translated) && c == '.' {
+                continue;
+            }
+            translated.push(c);
+        }
+        let _ = flush(&mut word, &mut translated);
+        translated
+    }
+
+    /// Parse `text` into a date and time; formats without a time are assumed to be at midnight.
+    ///
+    /// # Errors
+    /// If none of the formats match, an error is returned.
+    pub fn parse(&self, text: &str) -> Result<NaiveDateTime, EtError> {
+        let translated = self.translate(text.trim());
+        for format in &self.formats {
+            if let Ok(datetime) = NaiveDateTime::parse_from_str(&translated, format) {
+                return Ok(datetime);
+            }
+            if let Ok(date) = NaiveDate::parse_from_str(&translated, format) {
+                if let Some(datetime) = date.and_hms_opt(0, 0, 0) {
+                    return Ok(datetime);
+                }
+            }
+        }
+        Err(format!("Could not parse date {:?}", text.trim()).into())
+    }
+
+    /// Parse a timestamp that may have a UTC offset (e.g. `2020-01-02T03:04:05Z`, like in GPX
+    /// files and many data loggers) into UTC; timestamps without one are parsed with the formats.
+    ///
+    /// # Errors
+    /// If none of the formats match, an error is returned.
+    pub fn parse_utc(&self, text: &str) -> Result<NaiveDateTime, EtError> {
+        match DateTime::parse_from_rfc3339(text.trim()) {
+            Ok(datetime) => Ok(datetime.naive_utc()),
+            Err(_) => self.parse(text),
+        }
+    }
+
+    /// Parse `text` into a date (ignoring any time)
+    ///
+    /// # Errors
+    /// If none of the formats match, an error is returned.
+    pub fn parse_date(&self, text: &str) -> Result<NaiveDate, EtError> {
+        self.parse(text).map(|d| d.date())
+    }
+}
+
+/// Parse `text` with `parser`; if that's not possible, a warning is added to `warnings` instead
+/// (so it can be reported in the metadata) unless `text` is empty.
+pub(crate) fn parse_or_warn(
+    parser: &DateParser,
+    key: &str,
+    text: &str,
+    warnings: &mut Vec<String>,
+) -> Option<NaiveDateTime> {
+    match parser.parse(text) {
+        Ok(datetime) => Some(datetime),
+        Err(_) if text.trim().is_empty() => None,
+        Err(e) => {
+            warnings.push(format!("{}: {}", key, e.msg));
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn datetime(y: i32, m: u32, d: u32, h: u32, min: u32, s: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, m, d)
+            .unwrap()
+            .and_hms_opt(h, min, s)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_chemstation_dates() -> Result<(), EtError> {
+        let parser = DateParser::new(CHEMSTATION_FORMATS);
+        assert_eq!(
+            parser.parse("16-Jun-11, 16:33:09")?,
+            datetime(2011, 6, 16, 16, 33, 9)
+        );
+        assert_eq!(
+            parser.parse("10/27/10 5:14:59 PM")?,
+            datetime(2010, 10, 27, 17, 14, 59)
+        );
+        assert!(parser.parse("2011-06-16").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_date_only_formats() -> Result<(), EtError> {
+        let parser = DateParser::default();
+        assert_eq!(parser.parse("2020-03-04")?, datetime(2020, 3, 4, 0, 0, 0));
+        assert_eq!(
+            parser.parse_date("26-OCT-2012")?,
+            NaiveDate::from_ymd_opt(2012, 10, 26).unwrap()
+        );
+        let parser = DateParser::new(["%d %Y"]).format("%Y.%m.%d");
+        assert_eq!(parser.parse("2020.01.02")?, datetime(2020, 1, 2, 0, 0, 0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_utc_timestamps() -> Result<(), EtError> {
+        let parser = DateParser::default();
+        assert_eq!(
+            parser.parse_utc("2021-05-01T10:00:00Z")?,
+            datetime(2021, 5, 1, 10, 0, 0)
+        );
+        assert_eq!(
+            parser.parse_utc("2021-05-01T12:00:00+02:00")?,
+            datetime(2021, 5, 1, 10, 0, 0)
+        );
+        assert_eq!(
+            parser.parse_utc("2021-05-01 10:00:00")?,
+            datetime(2021, 5, 1, 10, 0, 0)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_locale_months() -> Result<(), EtError> {
+        let parser = DateParser::default();
+        assert_eq!(
+            parser.parse("12-Mär-19, 08:00:00")?,
+            datetime(2019, 3, 12, 8, 0, 0)
+        );
+        assert_eq!(parser.parse("03-DEZ-2018")?, datetime(2018, 12, 3, 0, 0, 0));
+        assert_eq!(
+            parser.parse("01-févr.-2020")?,
+            datetime(2020, 2, 1, 0, 0, 0)
+        );
+        assert_eq!(parser.parse("15-ago-2021")?, datetime(2021, 8, 15, 0, 0, 0));
+
+        let parser = DateParser::default().month_names(&[("Lutego", 2)]);
+        assert_eq!(
+            parser.parse("01-lutego-2020")?,
+            datetime(2020, 2, 1, 0, 0, 0)
+        );
+        assert!(parser.parse("01-févr-2020").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_or_warn() {
+        let parser = DateParser::default();
+        let mut warnings = Vec::new();
+        assert!(parse_or_warn(&parser, "run_date", "", &mut warnings).is_none());
+        assert!(warnings.is_empty());
+        assert!(parse_or_warn(&parser, "run_date", "yesterday", &mut warnings).is_none());
+        assert_eq!(warnings, ["run_date: Could not parse date \"yesterday\""]);
+    }
+}
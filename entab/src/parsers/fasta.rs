@@ -2,20 +2,29 @@ use alloc::vec;
 use alloc::vec::Vec;
 
 use memchr::{memchr, memchr_iter};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
-use crate::parsers::FromSlice;
-use crate::record::StateMetadata;
+use crate::capabilities::Capabilities;
+use crate::parsers::common::{strip_line_ending, BoundedLine};
+use crate::parsers::{extract_opt, FromSlice};
+use crate::record::{PushFields, StateMetadata, Value};
 use crate::EtError;
 use crate::{impl_reader, impl_record};
 
 use alloc::borrow::Cow;
 
+/// The longest ID/header line a record can have
+const MAX_HEADER_LEN: usize = 1 << 20;
+
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 /// A single sequence from a FASTA file
 pub struct FastaRecord<'r> {
     /// The ID/header line
     pub id: &'r str,
     /// The sequence itself
+    #[cfg_attr(feature = "serde", serde(borrow, with = "crate::record::text_bytes"))]
     pub sequence: Cow<'r, [u8]>,
 }
 
@@ -29,6 +38,10 @@ pub struct FastaState {
 }
 
 impl StateMetadata for FastaState {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::TYPED_SCHEMA
+    }
+
     fn header(&self) -> Vec<&str> {
         vec!["id", "sequence"]
     }
@@ -56,18 +69,17 @@ impl<'b: 's, 's> FromSlice<'b, 's> for FastaRecord<'b> {
         if rb[0] != b'>' {
             return Err("Valid FASTA records start with '>'".into());
         }
-        let seq_start = if let Some(p) = memchr(b'\n', rb) {
-            if p > 0 && rb[p - 1] == b'\r' {
-                // strip out the \r too if this is a \r\n ending
-                parser_state.header_end = p - 1;
-                p + 1
-            } else {
-                parser_state.header_end = p;
-                p + 1
+        let seq_start = &mut 0;
+        let mut max_len = MAX_HEADER_LEN;
+        match extract_opt::<BoundedLine>(rb, eof, seq_start, &mut max_len)? {
+            // every header has to be followed by a new line (even if there's no sequence)
+            Some(BoundedLine(header)) if rb[..*seq_start].ends_with(b"\n") => {
+                parser_state.header_end = header.len();
             }
-        } else {
-            return Err(EtError::new("Incomplete header").incomplete());
-        };
+            // the data ended partway through the header
+            _ => return Err(EtError::from("Incomplete header").incomplete()),
+        }
+        let seq_start = *seq_start;
 
         if let Some(p) = memchr(b'>', &rb[seq_start..]) {
             if p == 0 || rb.get(seq_start + p - 1) != Some(&b'\n') {
@@ -80,7 +92,9 @@ impl<'b: 's, 's> FromSlice<'b, 's> for FastaRecord<'b> {
             }
             *consumed += seq_start + p;
         } else if eof {
-            parser_state.seq = (seq_start, rb.len());
+            // leave off the final line ending so every sequence ends the same way
+            let seq_end = seq_start + strip_line_ending(&rb[seq_start..]).len();
+            parser_state.seq = (seq_start, seq_end);
             // at eof; just return the end
             *consumed += rb.len();
         } else {
@@ -115,11 +129,101 @@ impl<'b: 's, 's> FromSlice<'b, 's> for FastaRecord<'b> {
 
 impl_reader!(FastaReader, FastaRecord, FastaRecord<'r>, FastaState, ());
 
+/// A single sequence from a FASTA file along with how it was split over lines
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WrappedFastaRecord<'r> {
+    /// The ID/header line
+    pub id: &'r str,
+    /// The sequence itself
+    #[cfg_attr(feature = "serde", serde(borrow, with = "crate::record::text_bytes"))]
+    pub sequence: Cow<'r, [u8]>,
+    /// The length of each line the sequence was split over so the record can be written back
+    /// out the same way. Line endings (`\n` or `\r\n`) aren't counted and whether the last line
+    /// had one isn't recorded.
+    pub line_lengths: Vec<usize>,
+}
+
+impl<'r> From<WrappedFastaRecord<'r>> for Vec<Value<'r>> {
+    fn from(record: WrappedFastaRecord<'r>) -> Self {
+        let mut values = Vec::with_capacity(3);
+        record.push_fields(&mut values);
+        values
+    }
+}
+
+impl<'r> PushFields<'r> for WrappedFastaRecord<'r> {
+    fn push_fields<E: Extend<Value<'r>>>(self, fields: &mut E) {
+        let line_lengths = self.line_lengths.into_iter().map(|l| (l as u64).into());
+        fields.extend([
+            self.id.into(),
+            self.sequence.into(),
+            Value::List(line_lengths.collect()),
+        ]);
+    }
+}
+
+/// The current state of FASTA parsing when the line lengths are kept
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WrappedFastaState(FastaState);
+
+impl StateMetadata for WrappedFastaState {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::TYPED_SCHEMA
+    }
+
+    fn header(&self) -> Vec<&str> {
+        vec!["id", "sequence", "line_lengths"]
+    }
+}
+
+impl<'b: 's, 's> FromSlice<'b, 's> for WrappedFastaState {
+    type State = ();
+}
+
+impl<'b: 's, 's> FromSlice<'b, 's> for WrappedFastaRecord<'b> {
+    type State = WrappedFastaState;
+
+    fn parse(
+        rb: &[u8],
+        eof: bool,
+        consumed: &mut usize,
+        parser_state: &mut Self::State,
+    ) -> Result<bool, EtError> {
+        FastaRecord::parse(rb, eof, consumed, &mut parser_state.0)
+    }
+
+    fn get(&mut self, rb: &'b [u8], state: &Self::State) -> Result<(), EtError> {
+        let mut record = FastaRecord::default();
+        record.get(rb, &state.0)?;
+        self.id = record.id;
+        self.sequence = record.sequence;
+        let raw_sequence = &rb[state.0.seq.0..state.0.seq.1];
+        self.line_lengths.clear();
+        self.line_lengths.extend(
+            raw_sequence
+                .split(|b| *b == b'\n')
+                .map(|l| strip_line_ending(l).len()),
+        );
+        Ok(())
+    }
+}
+
+impl_reader!(
+    WrappedFastaReader,
+    WrappedFastaRecord,
+    WrappedFastaRecord<'r>,
+    WrappedFastaState,
+    ()
+);
+
 #[cfg(test)]
 mod tests {
     use alloc::borrow::Cow;
 
     use super::*;
+    use crate::error::EtErrorKind;
+    use crate::readers::RecordReader;
 
     #[test]
     fn test_fasta_reading() -> Result<(), EtError> {
@@ -192,6 +296,36 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_fasta_line_lengths() -> Result<(), EtError> {
+        const TEST_FASTA: &[u8] = b">id\nACGTA\nCGTAC\nGG\n>id2\r\nTGCA\r\n\r\nT";
+        let mut pt = WrappedFastaReader::new(TEST_FASTA, None)?;
+        assert_eq!(pt.headers(), ["id", "sequence", "line_lengths"]);
+
+        let record = pt.next()?.expect("first record present");
+        assert_eq!(
+            record.sequence,
+            Cow::Owned::<[u8]>(b"ACGTACGTACGG".to_vec())
+        );
+        assert_eq!(record.line_lengths, [5, 5, 2]);
+
+        // `\r\n`s aren't counted and the last line doesn't need one
+        let record = pt.next()?.expect("second record present");
+        assert_eq!(record.sequence, Cow::Owned::<[u8]>(b"TGCAT".to_vec()));
+        assert_eq!(record.line_lengths, [4, 0, 1]);
+        assert!(pt.next()?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_fasta_truncated() -> Result<(), EtError> {
+        let mut pt = FastaReader::new(&b">id\nACGT\n>id2"[..], None)?;
+        assert!(pt.next()?.is_some());
+        let err = pt.next().unwrap_err();
+        assert!(matches!(err.kind, EtErrorKind::Truncated { .. }));
+        Ok(())
+    }
+
     #[test]
     fn test_fasta_empty_fields() -> Result<(), EtError> {
         const TEST_FASTA: &[u8] = b">hd\n\n>\n\n";
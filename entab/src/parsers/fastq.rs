@@ -1,42 +1,222 @@
+use alloc::format;
+use alloc::string::{String, ToString};
 use alloc::vec;
 use alloc::vec::Vec;
-use memchr::memchr;
+use core::str::FromStr;
 
-use crate::parsers::FromSlice;
-use crate::record::StateMetadata;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::capabilities::Capabilities;
+use crate::impl_reader;
+use crate::parsers::common::BoundedLine;
+use crate::parsers::{extract_opt, FromSlice};
+use crate::record::{PushFields, StateMetadata, Value};
 use crate::EtError;
-use crate::{impl_reader, impl_record};
+
+/// The longest ID/header line a record can have
+const MAX_HEADER_LEN: usize = 1 << 20;
+/// The longest sequence (or quality score line) a record can have
+const MAX_SEQUENCE_LEN: usize = 1 << 28;
+
+/// Which part of a FASTQ record a tag is extracted from
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FastqTagSource {
+    /// The ID/header line
+    Header,
+    /// The sequence
+    Sequence,
+    /// The quality scores
+    Quality,
+}
+
+/// How a tag is selected out of its source
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FastqTagSelector {
+    /// The bytes from the start position up to the end position (or to the end if `None`)
+    Range(usize, Option<usize>),
+    /// Split on the delimiter and take the field at the index (negative indices count back from
+    /// the last field)
+    Field(u8, isize),
+}
+
+/// A subsequence (e.g. a UMI or cell barcode) to pull out of each record as an extra column.
+///
+/// These can be parsed from strings like `umi=read1[0:12]` (the first twelve bases of the
+/// sequence), `bc=quality[12:]`, or `umi=header/:/-1` (the last `:`-separated field of the header).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FastqTag {
+    /// The name of the column
+    pub name: String,
+    /// Which part of the record this comes from
+    pub source: FastqTagSource,
+    /// Where in the source this is
+    pub selector: FastqTagSelector,
+}
+
+impl FastqTag {
+    /// Pull this tag out of a record; returns `None` if the record is too short or doesn't have
+    /// enough fields.
+    fn extract<'r>(&self, id: &'r str, sequence: &'r [u8], quality: &'r [u8]) -> Option<&'r [u8]> {
+        let source = match self.source {
+            FastqTagSource::Header => id.as_bytes(),
+            FastqTagSource::Sequence => sequence,
+            FastqTagSource::Quality => quality,
+        };
+        match self.selector {
+            FastqTagSelector::Range(start, Some(end)) => source.get(start..end),
+            FastqTagSelector::Range(start, None) => source.get(start..),
+            FastqTagSelector::Field(delim, index) => {
+                let mut fields = source.split(move |c| *c == delim);
+                if index >= 0 {
+                    fields.nth(index as usize)
+                } else {
+                    fields.rev().nth(index.unsigned_abs() - 1)
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for FastqTag {
+    type Err = EtError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, spec) = s
+            .split_once('=')
+            .ok_or_else(|| format!("FASTQ tag {} must be in the form name=source[start:end]", s))?;
+        if name.is_empty() || ["id", "sequence", "quality"].contains(&name) {
+            return Err(format!("FASTQ tag name {:?} is invalid", name).into());
+        }
+        let selector_start = spec.find(['[', '/']).unwrap_or(spec.len());
+        let source = match &spec[..selector_start] {
+            "header" | "id" => FastqTagSource::Header,
+            "read" | "read1" | "seq" | "sequence" => FastqTagSource::Sequence,
+            "qual" | "quality" => FastqTagSource::Quality,
+            x => return Err(format!("Unknown FASTQ tag source {}", x).into()),
+        };
+        let selector_str = &spec[selector_start..];
+        let selector = if let Some(range) = selector_str
+            .strip_prefix('[')
+            .and_then(|r| r.strip_suffix(']'))
+        {
+            let (start, end) = range
+                .split_once(':')
+                .ok_or_else(|| format!("FASTQ tag range {} must contain a ':'", range))?;
+            let start = if start.is_empty() { 0 } else { start.parse()? };
+            let end = if end.is_empty() {
+                None
+            } else {
+                Some(end.parse()?)
+            };
+            if end.is_some_and(|e| e < start) {
+                return Err(format!("FASTQ tag range {} ends before it starts", range).into());
+            }
+            FastqTagSelector::Range(start, end)
+        } else if let Some(field) = selector_str.strip_prefix('/') {
+            let (delim, index) = field.split_once('/').ok_or_else(|| {
+                format!("FASTQ tag field {} must be in the form /delim/index", field)
+            })?;
+            if delim.len() != 1 {
+                return Err(format!("FASTQ tag delimiter {:?} must be one byte", delim).into());
+            }
+            let index: isize = index.parse()?;
+            if index == isize::MIN {
+                return Err(format!("FASTQ tag field index {} is out of range", index).into());
+            }
+            FastqTagSelector::Field(delim.as_bytes()[0], index)
+        } else {
+            return Err(format!("FASTQ tag {} has no [start:end] or /delim/index", s).into());
+        };
+        Ok(FastqTag {
+            name: name.to_string(),
+            source,
+            selector,
+        })
+    }
+}
+
+/// Parameters for parsing FASTQs
+#[derive(Clone, Debug, Default)]
+pub struct FastqParams {
+    /// Extra columns to extract out of each record
+    pub tags: Vec<FastqTag>,
+}
+
+impl FastqParams {
+    /// Add a tag to extract as an extra column
+    #[must_use]
+    pub fn tag(mut self, tag: FastqTag) -> Self {
+        self.tags.push(tag);
+        self
+    }
+}
 
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 /// A single sequence with quality data from a FASTQ file
 pub struct FastqRecord<'r> {
     /// The ID/header line
     pub id: &'r str,
-    /// The sequence itself
+    /// The sequence itself (if the record was wrapped over several lines, the line breaks are
+    /// left in)
+    #[cfg_attr(feature = "serde", serde(borrow, with = "crate::record::text_bytes"))]
     pub sequence: &'r [u8],
-    /// The matching quality scores for bases in the sequence
+    /// The matching quality scores for bases in the sequence (wrapped the same way)
+    #[cfg_attr(feature = "serde", serde(borrow, with = "crate::record::text_bytes"))]
     pub quality: &'r [u8],
+    /// Any tags extracted from the record (in the order of `FastqParams.tags`)
+    #[cfg_attr(
+        feature = "serde",
+        serde(borrow, with = "crate::record::text_bytes::list")
+    )]
+    pub tags: Vec<Option<&'r [u8]>>,
+}
+
+impl<'r> From<FastqRecord<'r>> for Vec<Value<'r>> {
+    fn from(record: FastqRecord<'r>) -> Self {
+        let mut values = Vec::with_capacity(3 + record.tags.len());
+        record.push_fields(&mut values);
+        values
+    }
 }
 
-impl_record!(FastqRecord<'r>: id, sequence, quality);
+impl<'r> PushFields<'r> for FastqRecord<'r> {
+    fn push_fields<E: Extend<Value<'r>>>(self, fields: &mut E) {
+        fields.extend([self.id.into(), self.sequence.into(), self.quality.into()]);
+        fields.extend(self.tags.into_iter().map(Value::from));
+    }
+}
 
 /// The current state of FASTQ parsing; note that we use tuples of usize because Range doesn't
 /// support copying and tuples with an inclusive and exclusive bound are actually fairly slow.
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct FastqState {
     header_end: usize,
     seq: (usize, usize),
     qual: (usize, usize),
+    tags: Vec<FastqTag>,
 }
 
 impl StateMetadata for FastqState {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::TYPED_SCHEMA
+    }
+
     fn header(&self) -> Vec<&str> {
-        vec!["id", "sequence", "quality"]
+        let mut headers = vec!["id", "sequence", "quality"];
+        headers.extend(self.tags.iter().map(|t| t.name.as_str()));
+        headers
     }
 }
 
 impl<'b: 's, 's> FromSlice<'b, 's> for FastqState {
-    type State = ();
+    type State = FastqParams;
+
+    fn get(&mut self, _buf: &'b [u8], params: &'s Self::State) -> Result<(), EtError> {
+        self.tags = params.tags.clone();
+        Ok(())
+    }
 }
 
 impl<'b: 's, 's> FromSlice<'b, 's> for FastqRecord<'s> {
@@ -57,55 +237,57 @@ impl<'b: 's, 's> FromSlice<'b, 's> for FastqRecord<'s> {
         if buf[0] != b'@' {
             return Err("Valid FASTQ records start with '@'".into());
         }
-        // figure out where the first id/header line ends
-        let seq_start = if let Some(p) = memchr(b'\n', buf) {
-            if p > 0 && buf[p - 1] == b'\r' {
-                // strip out the \r too if this is a \r\n ending
-                state.header_end = p - 1;
-            } else {
-                state.header_end = p;
+        let con = &mut 0;
+        let mut next_line = |part: &str, mut max_len: usize| {
+            let start = *con;
+            match extract_opt::<BoundedLine>(buf, eof, con, &mut max_len)? {
+                Some(BoundedLine(line)) => Ok((start, line)),
+                // only happens at the end of the data so this becomes a "truncated" error
+                None => {
+                    Err(EtError::from(format!("Record ended prematurely in {}", part)).incomplete())
+                }
             }
-            p + 1
-        } else {
-            return Err(EtError::new("Record ended prematurely in header").incomplete());
         };
-        // figure out where the sequence data is
-        let id2_start = if let Some(p) = memchr(b'+', &buf[seq_start..]) {
-            if p == 0 || buf[seq_start + p - 1] != b'\n' {
+        let (_, header) = next_line("header", MAX_HEADER_LEN)?;
+        // the sequence can be wrapped over several lines so read until the second header
+        let mut seq = None;
+        let mut seq_len = 0;
+        loop {
+            let (start, line) = next_line("sequence", MAX_SEQUENCE_LEN)?;
+            if line.first() == Some(&b'+') {
+                break;
+            }
+            if line.contains(&b'+') {
                 return Err("Unexpected + found in sequence".into());
             }
-            // the + is technically part of the next header so we're
-            // already one short before we even check the \r
-            if seq_start + p > 2 && buf[seq_start + p - 2] == b'\r' {
-                // strip out the \r too if this is a \r\n ending
-                state.seq = (seq_start, seq_start + p - 2);
-            } else {
-                state.seq = (seq_start, seq_start + p - 1);
+            seq_len += line.len();
+            if seq_len > MAX_SEQUENCE_LEN {
+                return Err("Sequence is longer than the maximum length".into());
+            }
+            let seq_start = seq.map_or(start, |(seq_start, _)| seq_start);
+            seq = Some((seq_start, start + line.len()));
+        }
+        // and the quality scores are wrapped the same way; we can't look for the next header
+        // because quality lines can start with an `@` so read lines until they're long enough
+        let mut qual = None;
+        let mut qual_len = 0;
+        loop {
+            let (start, line) = next_line("quality", MAX_SEQUENCE_LEN)?;
+            qual_len += line.len();
+            let qual_start = qual.map_or(start, |(qual_start, _)| qual_start);
+            qual = Some((qual_start, start + line.len()));
+            if qual_len >= seq_len {
+                break;
             }
-            seq_start + p
-        } else {
-            return Err(EtError::new("Record ended prematurely in sequence").incomplete());
-        };
-        // skip over the second id/header line
-        let qual_start = if let Some(p) = memchr(b'\n', &buf[id2_start..]) {
-            id2_start + p + 1
-        } else {
-            return Err(EtError::new("Record ended prematurely in second header").incomplete());
-        };
-        // and get the quality scores location
-        let qual_end = qual_start + (state.seq.1 - state.seq.0);
-        let mut rec_end = qual_end + (id2_start - state.seq.1);
-        // sometimes the terminal one or two newlines might be missing
-        // so we deduct here to avoid a error overconsuming
-        if rec_end > buf.len() && eof {
-            rec_end -= id2_start - state.seq.1;
         }
-        if rec_end > buf.len() {
-            return Err(EtError::new("Record ended prematurely in quality").incomplete());
+        if qual_len != seq_len {
+            return Err("Quality scores are a different length than the sequence".into());
         }
-        state.qual = (qual_start, qual_end);
+        state.header_end = header.len();
+        state.seq = seq.unwrap_or_default();
+        state.qual = qual.unwrap_or_default();
 
-        *consumed += rec_end;
+        *consumed += *con;
         Ok(true)
     }
 
@@ -113,15 +295,27 @@ impl<'b: 's, 's> FromSlice<'b, 's> for FastqRecord<'s> {
         self.id = alloc::str::from_utf8(&buf[1..state.header_end])?;
         self.sequence = &buf[state.seq.0..state.seq.1];
         self.quality = &buf[state.qual.0..state.qual.1];
+        self.tags.clear();
+        for tag in &state.tags {
+            self.tags
+                .push(tag.extract(self.id, self.sequence, self.quality));
+        }
         Ok(())
     }
 }
 
-impl_reader!(FastqReader, FastqRecord, FastqRecord<'r>, FastqState, ());
+impl_reader!(
+    FastqReader,
+    FastqRecord,
+    FastqRecord<'r>,
+    FastqState,
+    FastqParams
+);
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::error::EtErrorKind;
 
     #[test]
     fn test_fastq_reading() -> Result<(), EtError> {
@@ -132,6 +326,7 @@ mod tests {
             id,
             sequence,
             quality,
+            ..
         }) = pt.next()?
         {
             assert_eq!(id, "id");
@@ -145,6 +340,7 @@ mod tests {
             id,
             sequence,
             quality,
+            ..
         }) = pt.next()?
         {
             assert_eq!(id, "id2");
@@ -167,6 +363,7 @@ mod tests {
             id,
             sequence,
             quality,
+            ..
         }) = pt.next()?
         {
             assert_eq!(id, "id");
@@ -180,6 +377,7 @@ mod tests {
             id,
             sequence,
             quality,
+            ..
         }) = pt.next()?
         {
             assert_eq!(id, "id2");
@@ -193,6 +391,26 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_fastq_multiline() -> Result<(), EtError> {
+        // the second quality line starts with an `@` to make sure it's not taken as a header
+        const TEST_FASTQ: &[u8] = b"@id\nACGT\nAC\n+\n!!!!\n@!\n@id2\nTGCA\n+\n!!!!";
+        let mut pt = FastqReader::new(TEST_FASTQ, None)?;
+        let record = pt.next()?.unwrap();
+        assert_eq!(record.id, "id");
+        assert_eq!(record.sequence, &b"ACGT\nAC"[..]);
+        assert_eq!(record.quality, &b"!!!!\n@!"[..]);
+        let record = pt.next()?.unwrap();
+        assert_eq!(record.id, "id2");
+        assert_eq!(record.sequence, &b"TGCA"[..]);
+        assert!(pt.next()?.is_none());
+
+        // the quality scores still have to be as long as the sequence
+        let mut pt = FastqReader::new(&b"@id\nAC\nGT\n+\n!!!\n"[..], None)?;
+        assert!(pt.next().is_err());
+        Ok(())
+    }
+
     #[test]
     fn test_fastq_pathological_sequences() -> Result<(), EtError> {
         const TEST_FASTQ_1: &[u8] = b"@DF\n+\n+\n!";
@@ -201,11 +419,66 @@ mod tests {
 
         const TEST_FASTQ_2: &[u8] = b"@\n";
         let mut pt = FastqReader::new(TEST_FASTQ_2, None)?;
-        assert!(pt.next().is_err());
+        let err = pt.next().unwrap_err();
+        assert!(matches!(err.kind, EtErrorKind::Truncated { .. }));
+        assert!(err.msg.starts_with("File appears truncated at byte 2,"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fastq_tags() -> Result<(), EtError> {
+        use crate::readers::RecordReader;
 
+        const TEST_FASTQ: &[u8] =
+            b"@r1 1:N:0:ACGT\nAACCGGTT\n+\nABCDEFGH\n@r2 1:N:0:TTGG\nAC\n+\nAB\n";
+        let params = FastqParams::default()
+            .tag("umi=read1[0:4]".parse()?)
+            .tag("umi_qual=quality[0:4]".parse()?)
+            .tag("bc=header/:/-1".parse()?)
+            .tag("rest=seq[4:]".parse()?);
+        let mut pt = FastqReader::new(TEST_FASTQ, Some(params))?;
+        assert_eq!(
+            pt.headers(),
+            ["id", "sequence", "quality", "umi", "umi_qual", "bc", "rest"]
+        );
+
+        let rec = pt.next()?.unwrap();
+        assert_eq!(
+            rec.tags,
+            [
+                Some(&b"AACC"[..]),
+                Some(&b"ABCD"[..]),
+                Some(&b"ACGT"[..]),
+                Some(&b"GGTT"[..])
+            ]
+        );
+        // the second read is too short for the UMI
+        let rec = pt.next_record()?.unwrap();
+        assert_eq!(rec[3], Value::Null);
+        assert_eq!(rec[5], "TTGG".into());
+        assert_eq!(rec[6], Value::Null);
+        assert!(pt.next()?.is_none());
         Ok(())
     }
 
+    #[test]
+    fn test_fastq_bad_tags() {
+        assert!("umi".parse::<FastqTag>().is_err());
+        assert!("umi=read1".parse::<FastqTag>().is_err());
+        assert!("umi=read2[0:12]".parse::<FastqTag>().is_err());
+        assert!("umi=read1[12:0]".parse::<FastqTag>().is_err());
+        assert!("umi=header/::/1".parse::<FastqTag>().is_err());
+        assert!("id=read1[0:12]".parse::<FastqTag>().is_err());
+        assert!("umi=header/:/-9223372036854775808"
+            .parse::<FastqTag>()
+            .is_err());
+        assert_eq!(
+            "umi=header/ /1".parse::<FastqTag>().unwrap().selector,
+            FastqTagSelector::Field(b' ', 1)
+        );
+    }
+
     #[test]
     fn test_fastq_from_file() -> Result<(), EtError> {
         let data: &[u8] = include_bytes!("../../tests/data/test.fastq");
@@ -213,4 +486,26 @@ mod tests {
         while reader.next()?.is_some() {}
         Ok(())
     }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_fastq_serde() -> Result<(), EtError> {
+        let mut reader = FastqReader::new(&b"@id tag=1\nACGT\n+\n!!!#\n"[..], None)?;
+        let record = reader.next()?.unwrap();
+        let json = serde_json::to_string(&record).unwrap();
+        assert_eq!(
+            json,
+            r#"{"id":"id tag=1","sequence":"ACGT","quality":"!!!#","tags":[]}"#
+        );
+        let parsed: FastqRecord = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.id, "id tag=1");
+        assert_eq!(parsed.sequence, b"ACGT");
+        assert_eq!(parsed.quality, b"!!!#");
+
+        // and through a binary format
+        let packed = rmp_serde::to_vec(&record).unwrap();
+        let unpacked: FastqRecord = rmp_serde::from_slice(&packed).unwrap();
+        assert_eq!(unpacked.sequence, b"ACGT");
+        Ok(())
+    }
 }
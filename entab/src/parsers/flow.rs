@@ -1,17 +1,28 @@
-use alloc::borrow::ToOwned;
 use alloc::collections::BTreeMap;
 use alloc::string::{String, ToString};
+use alloc::vec;
 use alloc::vec::Vec;
 use alloc::{format, str};
+use core::convert::TryFrom;
 use core::default::Default;
+use core::mem::size_of;
+use core::str::FromStr;
 
-use chrono::{NaiveDate, NaiveTime};
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
-use crate::impl_reader;
+use crate::capabilities::Capabilities;
+use crate::columns::Column;
 use crate::parsers::common::Skip;
-use crate::parsers::{extract, Endian, FromSlice};
-use crate::record::{StateMetadata, Value};
+use crate::parsers::datetime::{parse_or_warn, DateParser, FCS_FORMATS};
+#[cfg(feature = "std")]
+use crate::parsers::slice_or_incomplete;
+use crate::parsers::{extract, extract_with, sub_or_err, Endian, FromSlice};
+use crate::record::{PushFields, StateMetadata, Value};
+use crate::summary::SummaryFields;
 use crate::EtError;
+use crate::{impl_reader, impl_record};
 
 #[derive(Clone, Debug, Default)]
 struct FcsColumn {
@@ -20,6 +31,21 @@ struct FcsColumn {
     range: u64,
     short_name: String,
     long_name: String,
+    /// Detector voltage ($PnV)
+    voltage: Option<f64>,
+    /// Excitation wavelengths in nm of the laser(s) used ($PnL)
+    excitation_wavelengths: Vec<i64>,
+}
+
+/// What's needed to turn the Time param of each event into an absolute time
+#[derive(Clone, Copy, Debug)]
+struct FcsClock {
+    /// When acquisition began ($DATE and $BTIM)
+    start: NaiveDateTime,
+    /// The index of the Time param
+    time_ix: usize,
+    /// How many seconds each unit of the Time param is ($TIMESTEP)
+    time_step: f64,
 }
 
 /// State of an `FcsReader`.
@@ -31,9 +57,148 @@ pub struct FcsState {
     endian: Endian,
     data_type: char,
     next_data: Option<usize>,
+    n_events: usize,
     n_events_left: usize,
     bytes_data_left: usize,
     metadata: BTreeMap<String, Value<'static>>,
+    first_event: usize,
+    last_event: Option<usize>,
+    sample_n: Option<usize>,
+    /// How many of the selected events in the current data set have been read
+    n_selected_read: usize,
+    /// If every param is an integer of the same (whole byte) width, that width in bytes; events
+    /// like this are decoded in one pass instead of one value at a time
+    uniform_int_width: Option<usize>,
+    /// If an `abs_time` column is being added to each event, how to calculate it
+    clock: Option<FcsClock>,
+    /// The index of the Time param (if there is one)
+    time_ix: Option<usize>,
+    /// How many seconds each unit of the Time param is ($TIMESTEP)
+    time_step: Option<f64>,
+    strict_dates: bool,
+    default_date: Option<NaiveDate>,
+}
+
+impl FcsState {
+    /// The events in the current data set that `first_event`, `last_event`, and `sample_n`
+    /// select: the index of the first event in the range, the number of events in the range,
+    /// and how many of them are sampled.
+    fn selection(&self) -> (usize, usize, usize) {
+        let last = match (self.n_events, self.last_event) {
+            (0, _) => return (0, 0, 0),
+            (n, Some(last)) => last.min(n - 1),
+            (n, None) => n - 1,
+        };
+        if self.first_event > last {
+            return (0, 0, 0);
+        }
+        let n_range = last - self.first_event + 1;
+        let n_sampled = self.sample_n.map_or(n_range, |n| n.min(n_range));
+        (self.first_event, n_range, n_sampled)
+    }
+
+    /// The number of events before the next selected event (or all of the events left if there
+    /// are no more selected events in this data set)
+    fn n_unselected(&self) -> usize {
+        let (first, n_range, n_sampled) = self.selection();
+        if self.n_selected_read >= n_sampled {
+            return self.n_events_left;
+        }
+        // spread the sampled events evenly through the range (in u64 so this can't overflow on
+        // 32-bit platforms)
+        let offset = self.n_selected_read as u64 * n_range as u64 / n_sampled as u64;
+        let next_ix = first + offset as usize;
+        next_ix - (self.n_events - self.n_events_left)
+    }
+
+    /// The parameters that select the same events out of the next data set
+    fn next_params(&self) -> FcsParams {
+        FcsParams {
+            first_event: self.first_event,
+            last_event: self.last_event,
+            sample_n: self.sample_n,
+            abs_time: self.clock.is_some(),
+            strict_dates: self.strict_dates,
+            default_date: self.default_date,
+            ..FcsParams::default()
+        }
+    }
+}
+
+/// Parameters for reading FCS files; these select which events of each data set are read so
+/// e.g. a quick preview of a large file doesn't need to read every event.
+#[derive(Clone, Debug, Default)]
+pub struct FcsParams {
+    /// The index of the first event to read (counting from 0)
+    pub first_event: usize,
+    /// The index of the last event to read (inclusive); if `None`, events are read to the end
+    pub last_event: Option<usize>,
+    /// Only read this many events, spaced evenly through the events that would be read
+    pub sample_n: Option<usize>,
+    /// Add an `abs_time` column with the clock time each event was acquired at (from the Time
+    /// param, `$TIMESTEP`, and the `$DATE` and `$BTIM` acquisition start)
+    pub abs_time: bool,
+    /// Report the `date` metadata as null if `$DATE` is missing or can't be parsed (or `$BTIM`
+    /// is present but can't be parsed) instead of falling back to `default_date`
+    pub strict_dates: bool,
+    /// The date to report for files without a parseable `$DATE` (2000-01-01 if not set)
+    pub default_date: Option<NaiveDate>,
+    /// For the `events_gaps` stream, the shortest time in seconds between two events that's
+    /// reported as a gap (1 second if not set)
+    pub gap_threshold: Option<f64>,
+    /// The keywords in the TEXT segment (filled in while parsing)
+    text: BTreeMap<String, String>,
+}
+
+impl FcsParams {
+    /// Start reading at the event with this index
+    #[must_use]
+    pub fn first_event(mut self, first_event: usize) -> Self {
+        self.first_event = first_event;
+        self
+    }
+
+    /// Stop reading after the event with this index
+    #[must_use]
+    pub fn last_event(mut self, last_event: usize) -> Self {
+        self.last_event = Some(last_event);
+        self
+    }
+
+    /// Read only this many events, spread evenly through the file
+    #[must_use]
+    pub fn sample_n(mut self, sample_n: usize) -> Self {
+        self.sample_n = Some(sample_n);
+        self
+    }
+
+    /// Add an `abs_time` column with the clock time of each event
+    #[must_use]
+    pub fn abs_time(mut self, abs_time: bool) -> Self {
+        self.abs_time = abs_time;
+        self
+    }
+
+    /// Report missing or unparseable dates as null instead of using a default date
+    #[must_use]
+    pub fn strict_dates(mut self, strict_dates: bool) -> Self {
+        self.strict_dates = strict_dates;
+        self
+    }
+
+    /// Use this date for files without a parseable `$DATE` (instead of 2000-01-01)
+    #[must_use]
+    pub fn default_date(mut self, default_date: NaiveDate) -> Self {
+        self.default_date = Some(default_date);
+        self
+    }
+
+    /// Report pauses of at least this many seconds between events in the `events_gaps` stream
+    #[must_use]
+    pub fn gap_threshold(mut self, gap_threshold: f64) -> Self {
+        self.gap_threshold = Some(gap_threshold);
+        self
+    }
 }
 
 impl StateMetadata for FcsState {
@@ -41,25 +206,141 @@ impl StateMetadata for FcsState {
         self.metadata.clone()
     }
 
+    fn column_metadata(&self) -> BTreeMap<String, BTreeMap<String, Value<'_>>> {
+        let mut column_metadata = BTreeMap::new();
+        for param in &self.params {
+            let mut metadata = BTreeMap::new();
+            if !param.long_name.is_empty() {
+                drop(metadata.insert("long_name".into(), param.long_name.as_str().into()));
+            }
+            if let Some(voltage) = param.voltage {
+                drop(metadata.insert("voltage".into(), voltage.into()));
+            }
+            // the time "detector" has a range too, but it's not a ceiling on anything measured
+            if param.range > 0 && !param.short_name.eq_ignore_ascii_case("time") {
+                drop(metadata.insert("range".into(), param.range.into()));
+            }
+            if !param.excitation_wavelengths.is_empty() {
+                let wavelengths = param
+                    .excitation_wavelengths
+                    .iter()
+                    .map(|w| Value::Integer(*w))
+                    .collect::<Vec<_>>();
+                drop(metadata.insert("excitation_wavelengths".into(), wavelengths.into()));
+            }
+            if !metadata.is_empty() {
+                drop(column_metadata.insert(param.short_name.clone(), metadata));
+            }
+        }
+        column_metadata
+    }
+
+    fn summary_fields(&self) -> SummaryFields {
+        SummaryFields {
+            time: "Time",
+            sample: "specimen_source",
+            ..SummaryFields::default()
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (_, _, n_sampled) = self.selection();
+        let n_left = n_sampled.saturating_sub(self.n_selected_read);
+        // there may be more events in the following data sets
+        (n_left, self.next_data.map_or(Some(n_left), |_| None))
+    }
+
     /// The fields in the associated struct
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::SEEKABLE
+            | Capabilities::SIZED
+            | Capabilities::HAS_METADATA
+            | Capabilities::TYPED_SCHEMA
+    }
+
+    fn skip_records(&mut self, n: usize) -> (usize, u64) {
+        // marking events as read is enough; the events before the next one that's selected are
+        // skipped over in the data when it's parsed
+        let (_, _, n_sampled) = self.selection();
+        let n_skip = n.min(n_sampled.saturating_sub(self.n_selected_read));
+        self.n_selected_read += n_skip;
+        (n_skip, 0)
+    }
+
+    fn read_columns(
+        &mut self,
+        buf: &[u8],
+        columns: &mut Vec<Column>,
+        n: usize,
+    ) -> Result<(usize, usize), EtError> {
+        // rows of integers in the platform's byte order can be cast straight into the columns;
+        // anything else (or events that are sampled or get an abs_time) is read one at a time
+        let native = matches!(
+            (self.endian, cfg!(target_endian = "little")),
+            (Endian::Little, true) | (Endian::Big, false)
+        );
+        let width = match self.uniform_int_width {
+            Some(width) if width == 1 || native => width,
+            _ => return Ok((0, 0)),
+        };
+        let (_, n_range, n_sampled) = self.selection();
+        if n_sampled != n_range
+            || self.n_unselected() != 0
+            || self.clock.is_some()
+            || self.params.is_empty()
+            || columns.len() != self.params.len()
+            // values above these ranges are an error that `FcsRecord` should report
+            || self
+                .params
+                .iter()
+                .any(|p| p.range > 0 && !p.range.is_power_of_two())
+        {
+            return Ok((0, 0));
+        }
+        let size = width * self.params.len();
+        let n_rows = n
+            .min(n_sampled.saturating_sub(self.n_selected_read))
+            .min(self.n_events_left)
+            .min(buf.len() / size);
+        if n_rows == 0 {
+            return Ok((0, 0));
+        }
+        let rows = &buf[..n_rows * size];
+        match width {
+            1 => cast_int_rows::<u8>(rows, &self.params, columns),
+            2 => cast_int_rows::<u16>(rows, &self.params, columns),
+            4 => cast_int_rows::<u32>(rows, &self.params, columns),
+            8 => cast_int_rows::<u64>(rows, &self.params, columns),
+            x => return Err(format!("Unknown param size {}", x * 8).into()),
+        }
+        self.n_events_left -= n_rows;
+        self.n_selected_read += n_rows;
+        self.bytes_data_left = self.bytes_data_left.saturating_sub(n_rows * size);
+        Ok((n_rows, n_rows * size))
+    }
+
     fn header(&self) -> Vec<&str> {
         let mut headers = Vec::new();
         for param in &self.params {
             headers.push(param.short_name.as_ref());
         }
+        if self.clock.is_some() {
+            headers.push("abs_time");
+        }
         headers
     }
 }
 
 impl<'b: 's, 's> FromSlice<'b, 's> for FcsState {
-    type State = BTreeMap<String, String>;
+    type State = FcsParams;
 
     fn parse(
         buf: &[u8],
         _eof: bool,
         consumed: &mut usize,
-        map: &mut Self::State,
+        params: &mut Self::State,
     ) -> Result<bool, EtError> {
+        let map = &mut params.text;
         let con = &mut 0;
 
         let magic = extract::<&[u8]>(buf, con, &mut 10)?;
@@ -96,7 +377,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for FcsState {
             extract::<&str>(buf, con, &mut 8)?.trim().to_string(),
         ));
         let _ = extract::<Skip>(buf, con, &mut (text_start - 58))?;
-        let delim: u8 = extract(buf, con, &mut Endian::Little)?;
+        let delim: u8 = extract_with(buf, con, &Endian::Little)?;
         // The spec says repeated delimiters should be parsed as an escaped delimiter, but I've
         // never seen that so we parse them as empty values (which I have seen in Applied
         // Biosystems files) which allows us to simplify the parsing logic a lot.
@@ -132,17 +413,21 @@ impl<'b: 's, 's> FromSlice<'b, 's> for FcsState {
     }
 
     #[allow(clippy::too_many_lines)]
-    fn get(&mut self, _buf: &'b [u8], map: &'s Self::State) -> Result<(), EtError> {
+    fn get(&mut self, _buf: &'b [u8], fcs_params: &'s Self::State) -> Result<(), EtError> {
+        let map = &fcs_params.text;
         let mut params = Vec::new();
         let mut endian = Endian::Little;
         let mut data_type = 'F';
         let mut next_data = None;
         let mut n_events_left = 0;
         let mut metadata = BTreeMap::new();
+        let mut warnings = Vec::new();
+        let date_parser = DateParser::new(FCS_FORMATS);
 
-        let mut date = NaiveDate::from_yo_opt(2000, 1).ok_or(EtError::new("Bad date"))?;
-        let mut time =
-            NaiveTime::from_num_seconds_from_midnight_opt(0, 0).ok_or(EtError::new("Bad time"))?;
+        let mut date = None;
+        let mut time = None;
+        let mut bad_time = false;
+        let mut time_step = None;
         for (key, value) in map.iter() {
             match (key.as_ref(), value.as_ref()) {
                 ("$NEXTDATA", v) => {
@@ -163,39 +448,19 @@ impl<'b: 's, 's> FromSlice<'b, 's> for FcsState {
                 }
                 ("$MODE", v) => return Err(format!("Unknown FCS $MODE {}", v).into()),
                 ("$TOT", v) => n_events_left = v.trim().parse()?,
+                ("$TIMESTEP", v) => time_step = v.trim().parse::<f64>().ok(),
                 ("$BTIM", v) => {
-                    // TODO: sometimes there's a fractional (/60) part after the last colon
-                    // that we should include in the time too
-                    let hms = v
-                        .trim()
-                        .split(':')
-                        .take(3)
-                        .map(ToOwned::to_owned)
-                        .collect::<Vec<String>>()
-                        .join(":");
-                    if let Ok(t) = NaiveTime::parse_from_str(&hms, "%H:%M:%S") {
-                        time = t;
+                    time = parse_btim(v);
+                    if time.is_none() && !v.trim().is_empty() {
+                        warnings.push(format!("$BTIM: Could not parse time {:?}", v.trim()));
+                        bad_time = true;
                     }
                 }
                 ("$CELLS", v) => {
                     drop(metadata.insert("specimen".into(), v.to_string().into()));
                 }
                 ("$DATE", v) => {
-                    // "DD-MM-YYYY"
-                    // "YYYY-mmm-DD"
-                    if let Ok(d) = NaiveDate::parse_from_str(v.trim(), "%d-%b-%y") {
-                        // FCS2.0 only had a two-digit year, e.g. 01-JAN-20).
-                        date = d;
-                    } else if let Ok(d) = NaiveDate::parse_from_str(v.trim(), "%d-%b-%Y") {
-                        // FCS3.0 and 3.1 are supposed to be e.g. 01-JAN-2020.
-                        date = d;
-                    } else if let Ok(d) = NaiveDate::parse_from_str(v.trim(), "%Y-%b-%d") {
-                        // non-standard FCS3.0?
-                        date = d;
-                    } else if let Ok(d) = NaiveDate::parse_from_str(v.trim(), "%d-%m-%Y") {
-                        // one weird Partec FCS2.0 file had this
-                        date = d;
-                    }
+                    date = parse_or_warn(&date_parser, "$DATE", v, &mut warnings).map(|d| d.date());
                 }
                 ("$INST", v) => {
                     drop(metadata.insert("instrument".into(), v.to_string().into()));
@@ -214,14 +479,36 @@ impl<'b: 's, 's> FromSlice<'b, 's> for FcsState {
                 }
                 ("$PAR", v) => {
                     let n_params = v.trim().parse()?;
+                    // every param needs its own keywords, so a corrupt count can't make us
+                    // allocate more params than there are keywords
+                    if n_params > map.len() {
+                        return Err(format!(
+                            "Declared number of params ({}) is more than the number of keywords",
+                            n_params
+                        )
+                        .into());
+                    }
                     if n_params < params.len() {
                         return Err(format!("Declared number of params ({}) is less than the observed number of params ({})", n_params, params.len()).into());
                     }
                     params.resize_with(n_params, FcsColumn::default);
                 }
-                (k, v) if k.starts_with("$P") && k.ends_with(&['B', 'N', 'R', 'S'][..]) => {
-                    let mut i: usize = k[2..k.len() - 1].parse()?;
-                    i -= 1; // params are numbered from 1
+                (k, v)
+                    if k.starts_with("$P") && k.ends_with(&['B', 'L', 'N', 'R', 'S', 'V'][..]) =>
+                {
+                    let i: usize = match k[2..k.len() - 1].parse() {
+                        Ok(i) => i,
+                        // other keywords (e.g. $PLATEWELL) can look like these too
+                        Err(_) if k.ends_with(&['L', 'V'][..]) => continue,
+                        Err(e) => return Err(e.into()),
+                    };
+                    // params are numbered from 1
+                    let i = i
+                        .checked_sub(1)
+                        .ok_or_else(|| EtError::from("FCS params are numbered from 1"))?;
+                    if i >= map.len() {
+                        return Err(format!("FCS param number {} is out of range", i + 1).into());
+                    }
                     if i >= params.len() {
                         params.resize_with(i + 1, FcsColumn::default);
                     }
@@ -247,12 +534,30 @@ impl<'b: 's, 's> FromSlice<'b, 's> for FcsState {
                         }
                     } else if k.ends_with('S') {
                         params[i].long_name = v.to_string();
+                    } else if k.ends_with('V') {
+                        params[i].voltage = v.trim().parse().ok();
+                    } else if k.ends_with('L') {
+                        // FCS3.1 allows multiple comma-separated wavelengths
+                        params[i].excitation_wavelengths =
+                            v.split(',').filter_map(|w| w.trim().parse().ok()).collect();
                     }
                 }
                 _ => {}
             }
         }
-        drop(metadata.insert("date".into(), date.and_time(time).into()));
+        let start = match date {
+            _ if fcs_params.strict_dates && bad_time => None,
+            Some(date) => Some(date.and_time(time.unwrap_or(NaiveTime::MIN))),
+            None if fcs_params.strict_dates => None,
+            None => fcs_params
+                .default_date
+                .or_else(|| NaiveDate::from_yo_opt(2000, 1))
+                .map(|d| d.and_time(time.unwrap_or(NaiveTime::MIN))),
+        };
+        drop(metadata.insert("date".into(), start.into()));
+        if !warnings.is_empty() {
+            drop(metadata.insert("warnings".into(), warnings.into()));
+        }
 
         // make the next_data offset relative
         if let Some(n) = next_data {
@@ -279,13 +584,307 @@ impl<'b: 's, 's> FromSlice<'b, 's> for FcsState {
             }
         }
 
+        self.uniform_int_width = match (data_type, params.first()) {
+            ('I', Some(first))
+                if matches!(first.size, 8 | 16 | 32 | 64)
+                    && params.iter().all(|p| p.size == first.size) =>
+            {
+                Some(usize::from(first.size / 8))
+            }
+            _ => None,
+        };
+        self.time_ix = params
+            .iter()
+            .position(|p| p.short_name.eq_ignore_ascii_case("time"));
+        self.time_step = time_step;
+        self.clock = if fcs_params.abs_time {
+            let time_ix = self.time_ix.ok_or("FCS abs_time needs a Time param")?;
+            let start = match (date, time) {
+                (Some(date), Some(time)) => date.and_time(time),
+                _ => return Err("FCS abs_time needs the $DATE and $BTIM keywords".into()),
+            };
+            Some(FcsClock {
+                start,
+                time_ix,
+                time_step: time_step.ok_or("FCS abs_time needs the $TIMESTEP keyword")?,
+            })
+        } else {
+            None
+        };
         self.params = params;
         self.endian = endian;
         self.data_type = data_type;
         self.next_data = next_data;
+        self.n_events = n_events_left;
         self.n_events_left = n_events_left;
-        self.bytes_data_left = data_end - data_start + 1;
+        self.bytes_data_left = sub_or_err(data_end, data_start, "FCS data segment")? + 1;
         self.metadata = metadata;
+        self.first_event = fcs_params.first_event;
+        self.last_event = fcs_params.last_event;
+        self.sample_n = fcs_params.sample_n;
+        self.n_selected_read = 0;
+        self.strict_dates = fcs_params.strict_dates;
+        self.default_date = fcs_params.default_date;
+        Ok(())
+    }
+}
+
+/// The segments of an FCS file that `fcs_segment` can extract
+pub const FCS_SEGMENTS: &[&str] = &["header", "text", "data", "analysis"];
+
+/// The raw bytes of one of the segments (see `FCS_SEGMENTS`) of the first data set in an FCS
+/// file, e.g. to archive the ANALYSIS segment that vendors store their own results in.
+///
+/// # Errors
+/// If the file isn't a valid FCS file, the segment isn't known, or the file doesn't have it
+/// (e.g. there's no ANALYSIS segment), an error is returned.
+pub fn fcs_segment<'a>(data: &'a [u8], segment: &str) -> Result<&'a [u8], EtError> {
+    let mut params = FcsParams::default();
+    let _ = FcsState::parse(data, true, &mut 0, &mut params)?;
+    // the HEADER has the offsets of each segment in 8-byte fields (which are blank or zero if
+    // they don't fit, so the TEXT keywords take precedence for the DATA and ANALYSIS segments)
+    let header_offset = |mut pos: usize| -> Result<usize, EtError> {
+        let value = extract::<&str>(data, &mut pos, &mut 8)?.trim();
+        Ok(if value.is_empty() { 0 } else { value.parse()? })
+    };
+    let offsets = |begin: &str, end: &str, pos: usize| -> Result<(usize, usize), EtError> {
+        let keyword = |key: &str| -> Result<usize, EtError> {
+            Ok(params.text.get(key).map_or("0", |v| v.trim()).parse()?)
+        };
+        match (keyword(begin)?, keyword(end)?) {
+            (0, 0) => Ok((header_offset(pos)?, header_offset(pos + 8)?)),
+            offsets => Ok(offsets),
+        }
+    };
+    let (start, end) = match segment {
+        "header" => return Ok(&data[..58]),
+        "text" => (header_offset(10)?, header_offset(18)?),
+        "data" => offsets("$BEGINDATA", "$ENDDATA", 26)?,
+        "analysis" => offsets("$BEGINANALYSIS", "$ENDANALYSIS", 42)?,
+        _ => {
+            return Err(format!(
+                "Unknown FCS segment {:?} (try one of {})",
+                segment,
+                FCS_SEGMENTS.join(", ")
+            )
+            .into())
+        }
+    };
+    if start == 0 && end == 0 {
+        return Err(format!("FCS file has no {} segment", segment).into());
+    }
+    // segments end at (and include) their end offset
+    data.get(start..end.saturating_add(1))
+        .ok_or_else(|| format!("FCS {} segment is past the end of the file", segment).into())
+}
+
+/// Parse a `$BTIM` time; FCS 3.1 files have fractional seconds (`hh:mm:ss.cc`) while earlier
+/// versions can have a number of 1/60ths of a second after another colon (`hh:mm:ss:tt`).
+fn parse_btim(value: &str) -> Option<NaiveTime> {
+    let mut parts = value.trim().splitn(4, ':');
+    let hms = [parts.next()?, parts.next()?, parts.next()?].join(":");
+    let time = NaiveTime::parse_from_str(&hms, "%H:%M:%S%.f").ok()?;
+    Some(match parts.next() {
+        Some(ticks) => {
+            let ticks: i64 = ticks.trim().parse().ok()?;
+            time + Duration::microseconds(ticks * 1_000_000 / 60)
+        }
+        None => time,
+    })
+}
+
+/// The number of bytes each event takes up in the data segment
+fn event_size(state: &FcsState) -> Result<usize, EtError> {
+    let mut size = 0;
+    for param in &state.params {
+        size += match state.data_type {
+            'A' if !param.delimited => param.size as usize,
+            'A' if param.delimited => {
+                return Err("Delimited-ASCII number datatypes are not yet supported".into());
+            }
+            'D' => 8,
+            'F' => 4,
+            'I' => {
+                if param.size % 8 != 0 {
+                    return Err(format!("Unknown param size {}", param.size).into());
+                }
+                param.size as usize / 8
+            }
+            x => return Err(format!("Unknown FCS data type {}", x).into()),
+        };
+    }
+    Ok(size)
+}
+
+/// Mask an integer `value` to the param's `range` if it's above it (some instruments store
+/// flags in the unused high bits)
+#[inline]
+fn mask_value(value: u64, range: u64) -> Result<u64, EtError> {
+    if value > range && range > 0 {
+        if range.count_ones() != 1 {
+            return Err("Only ranges of power 2 can mask values".into());
+        }
+        Ok(value & (range - 1))
+    } else {
+        Ok(value)
+    }
+}
+
+/// Decode an event where every param is an integer `width` bytes wide.
+///
+/// This is the common layout for integer files so it's worth skipping the per-value dispatch
+/// in `FcsRecord::get` and converting the whole row in one pass instead.
+fn read_int_event(
+    buf: &[u8],
+    width: usize,
+    state: &FcsState,
+    values: &mut [Value],
+) -> Result<(), EtError> {
+    let mut fill = |decode: fn(&[u8]) -> u64| -> Result<(), EtError> {
+        let chunks = buf.chunks_exact(width);
+        for ((chunk, param), value) in chunks.zip(&state.params).zip(values.iter_mut()) {
+            *value = mask_value(decode(chunk), param.range)?.into();
+        }
+        Ok(())
+    };
+    match (width, state.endian) {
+        (1, _) => fill(|c| c[0].into()),
+        (2, Endian::Big) => fill(|c| u16::from_be_bytes([c[0], c[1]]).into()),
+        (2, Endian::Little) => fill(|c| u16::from_le_bytes([c[0], c[1]]).into()),
+        (4, Endian::Big) => fill(|c| u32::from_be_bytes([c[0], c[1], c[2], c[3]]).into()),
+        (4, Endian::Little) => fill(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]).into()),
+        (8, Endian::Big) => {
+            fill(|c| u64::from_be_bytes([c[0], c[1], c[2], c[3], c[4], c[5], c[6], c[7]]))
+        }
+        (8, Endian::Little) => {
+            fill(|c| u64::from_le_bytes([c[0], c[1], c[2], c[3], c[4], c[5], c[6], c[7]]))
+        }
+        (x, _) => Err(format!("Unknown param size {}", x * 8).into()),
+    }
+}
+
+/// Cast `rows` of integers (in the platform's byte order) that are all `T` wide into `columns`,
+/// masking each value to its param's range like `mask_value` does.
+fn cast_int_rows<T: bytemuck::Pod + Into<u64>>(
+    rows: &[u8],
+    params: &[FcsColumn],
+    columns: &mut [Column],
+) {
+    // the buffer isn't necessarily aligned for `T` so it's copied if it can't be cast in place
+    let copied: Vec<T>;
+    let values: &[T] = match bytemuck::try_cast_slice(rows) {
+        Ok(values) => values,
+        Err(_) => {
+            let mut aligned = vec![T::zeroed(); rows.len() / size_of::<T>()];
+            bytemuck::cast_slice_mut(&mut aligned).copy_from_slice(rows);
+            copied = aligned;
+            &copied
+        }
+    };
+    for (ix, (param, column)) in params.iter().zip(columns).enumerate() {
+        let range = param.range;
+        column.extend_integers(values[ix..].iter().step_by(params.len()).map(|value| {
+            let value: u64 = (*value).into();
+            let value = if value > range && range > 0 {
+                value & (range - 1)
+            } else {
+                value
+            };
+            i64::try_from(value).unwrap_or(i64::MAX)
+        }));
+    }
+}
+
+/// Where the events of the first data set of a FCS file are, so they can be split into chunks
+/// that are each readable on their own (by patching the event count into a copy of the header).
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, Default)]
+pub(crate) struct FcsLayout {
+    /// The offset of the first event
+    pub(crate) data_start: usize,
+    /// The offset just past the last event
+    pub(crate) data_end: usize,
+    /// The number of bytes in each event
+    pub(crate) event_size: usize,
+    /// Where the values of `$TOT`, `$ENDDATA`, and `$NEXTDATA` are in the TEXT segment
+    text_values: Vec<(String, usize, usize)>,
+}
+
+#[cfg(feature = "std")]
+impl FcsLayout {
+    /// Read the layout out of the start of a file (which must extend at least up to the data).
+    pub(crate) fn from_header(buf: &[u8]) -> Result<Self, EtError> {
+        let mut params = FcsParams::default();
+        let mut data_start = 0;
+        if !FcsState::parse(buf, false, &mut data_start, &mut params)? {
+            return Err("Could not read the FCS header".into());
+        }
+        let mut state = FcsState::default();
+        state.get(&buf[..data_start], &params)?;
+        let event_size = event_size(&state)?;
+        if event_size == 0 {
+            return Err("FCS file has no params".into());
+        }
+        let n_events = state.n_events_left;
+        let data_len = n_events
+            .checked_mul(event_size)
+            .ok_or("FCS $TOT is too large")?;
+        if data_len > state.bytes_data_left {
+            return Err("FCS data segment is shorter than $TOT events".into());
+        }
+
+        // find the values we'll need to change in a chunk's copy of the TEXT segment
+        let text_start: usize = str::from_utf8(&buf[10..18])?.trim().parse()?;
+        let text_end: usize = str::from_utf8(&buf[18..26])?.trim().parse()?;
+        let delim = *slice_or_incomplete(buf, text_start)?;
+        let mut text_values = Vec::new();
+        let mut key: Option<String> = None;
+        let mut pos = text_start + 1;
+        for item in slice_or_incomplete(buf, text_start + 1..text_end)?.split(|b| b == &delim) {
+            if let Some(k) = key.take() {
+                if ["$TOT", "$ENDDATA", "$NEXTDATA"].contains(&k.as_str()) {
+                    text_values.push((k, pos, pos + item.len()));
+                }
+            } else {
+                key = Some(String::from_utf8_lossy(item).to_ascii_uppercase());
+            }
+            pos += item.len() + 1;
+        }
+
+        Ok(FcsLayout {
+            data_start,
+            data_end: data_start + data_len,
+            event_size,
+            text_values,
+        })
+    }
+
+    /// Change a copy of the file up to the start of the data (`header`) so it describes a
+    /// chunk of `n_events` events.
+    pub(crate) fn patch_header(&self, header: &mut [u8], n_events: usize) -> Result<(), EtError> {
+        let data_end = self.data_start + n_events * self.event_size - 1;
+        let mut replace = |start: usize, end: usize, value: usize| -> Result<(), EtError> {
+            let field = &mut header[start..end];
+            // fields that are 0 are left alone (e.g. $ENDDATA if it's in the HEADER instead)
+            if str::from_utf8(field)?.trim().trim_end_matches('\0') == "0" {
+                return Ok(());
+            }
+            let value = format!("{:>width$}", value, width = field.len());
+            if value.len() > field.len() {
+                return Err("FCS chunk offsets don't fit in the header".into());
+            }
+            field.copy_from_slice(value.as_bytes());
+            Ok(())
+        };
+        replace(34, 42, data_end)?;
+        for (key, start, end) in &self.text_values {
+            match key.as_str() {
+                "$TOT" => replace(*start, *end, n_events)?,
+                "$ENDDATA" => replace(*start, *end, data_end)?,
+                // only the first data set is split up
+                _ => replace(*start, *end, 0)?,
+            }
+        }
         Ok(())
     }
 }
@@ -298,8 +897,10 @@ impl<'b: 's, 's> FromSlice<'b, 's> for FcsState {
 /// For a more detailed specification of the FCS format, see:
 /// <https://www.bioconductor.org/packages/release/bioc/vignettes/flowCore/inst/doc/fcs3.html>
 #[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct FcsRecord<'r> {
     /// A list of the values for the current FCS scan. See the associated state for their names.
+    #[cfg_attr(feature = "serde", serde(borrow))]
     pub values: Vec<Value<'r>>,
 }
 
@@ -307,112 +908,340 @@ impl<'b: 's, 's> FromSlice<'b, 's> for FcsRecord<'s> {
     type State = FcsState;
 
     fn parse(
-        buf: &[u8],
+        mut buf: &[u8],
         eof: bool,
         consumed: &mut usize,
         state: &mut Self::State,
     ) -> Result<bool, EtError> {
-        let con = &mut 0;
-        if state.n_events_left == 0 {
-            if let Some(next_data) = state.next_data {
-                let _ = extract::<Skip>(buf, con, &mut (next_data + state.bytes_data_left - 1))?;
-                let mut headers = BTreeMap::new();
+        // anything skipped over is added to `consumed` right away (and the state updated) so
+        // skipped events don't have to be held in the buffer and a refill can't re-read them
+        loop {
+            if state.n_events_left == 0 {
+                let next_data = match state.next_data {
+                    Some(next_data) => next_data,
+                    None => return Ok(false),
+                };
+                let con = &mut 0;
+                let _ = extract::<Skip>(
+                    buf,
+                    con,
+                    &mut next_data
+                        .saturating_add(state.bytes_data_left)
+                        .saturating_sub(1),
+                )?;
+                let mut params = state.next_params();
                 let start = *con;
-                if !FcsState::parse(&buf[*con..], eof, con, &mut headers)? {
+                if !FcsState::parse(&buf[*con..], eof, con, &mut params)? {
                     return Ok(false);
                 }
-                FcsState::get(state, &buf[start..*con], &headers)?;
-            } else {
-                return Ok(false);
+                FcsState::get(state, &buf[start..*con], &params)?;
+                *consumed += *con;
+                buf = &buf[*con..];
+                continue;
             }
-        }
 
-        for param in &state.params {
-            *con += match state.data_type {
-                'A' if !param.delimited => param.size as usize,
-                'A' if param.delimited => {
-                    return Err("Delimited-ASCII number datatypes are not yet supported".into());
-                }
-                'D' => 8,
-                'F' => 4,
-                'I' => {
-                    if param.size % 8 != 0 {
-                        return Err(format!("Unknown param size {}", param.size).into());
-                    }
-                    param.size as usize / 8
-                }
-                _ => panic!("Data type is in an unknown state"),
-            };
+            let n_unselected = state.n_unselected();
+            if n_unselected == 0 {
+                break;
+            }
+            let size = event_size(state)?;
+            let n_skip = n_unselected.min(buf.len() / size.max(1));
+            if n_skip == 0 {
+                return Err(EtError::from("Record was incomplete").incomplete());
+            }
+            state.n_events_left -= n_skip;
+            state.bytes_data_left = state.bytes_data_left.saturating_sub(n_skip * size);
+            *consumed += n_skip * size;
+            buf = &buf[n_skip * size..];
         }
-        if *con > buf.len() {
+
+        let size = event_size(state)?;
+        if size > buf.len() {
             return Err(EtError::from("Record was incomplete").incomplete());
         }
         state.n_events_left -= 1;
-        state.bytes_data_left = state.bytes_data_left.saturating_sub(*con);
-        *consumed += *con;
+        state.n_selected_read += 1;
+        state.bytes_data_left = state.bytes_data_left.saturating_sub(size);
+        *consumed += size;
         Ok(true)
     }
 
     fn get(&mut self, buf: &'b [u8], state: &'s Self::State) -> Result<(), EtError> {
-        if self.values.len() != state.params.len() {
-            self.values.resize(state.params.len(), Value::Null);
+        let n_values = state.params.len() + usize::from(state.clock.is_some());
+        if self.values.len() != n_values {
+            self.values.resize(n_values, Value::Null);
         }
-        // TODO: need to handle incompletes here
-        let con = &mut 0;
-        for (ix, param) in state.params.iter().enumerate() {
-            self.values[ix] = match state.data_type {
-                'A' if !param.delimited => {
-                    let n = extract::<&[u8]>(buf, con, &mut (param.size as usize))?;
-                    str::from_utf8(n)?.trim().parse::<f64>()?.into()
-                }
-                'A' if param.delimited => {
-                    return Err("Delimited-ASCII number datatypes are not yet supported".into());
-                }
-                'D' => extract::<f64>(buf, con, &mut state.endian.clone())?.into(),
-                'F' => extract::<f32>(buf, con, &mut state.endian.clone())?.into(),
-                'I' => {
-                    let value: u64 = match param.size {
-                        8 => extract::<u8>(buf, con, &mut state.endian.clone())?.into(),
-                        16 => extract::<u16>(buf, con, &mut state.endian.clone())?.into(),
-                        24 => {
-                            let top =
-                                u32::from(extract::<u8>(buf, con, &mut state.endian.clone())?);
-                            let bottom =
-                                u32::from(extract::<u16>(buf, con, &mut state.endian.clone())?);
-                            ((top << 16) + bottom).into()
-                        }
-                        32 => extract::<u32>(buf, con, &mut state.endian.clone())?.into(),
-                        64 => extract::<u64>(buf, con, &mut state.endian.clone())?,
-                        x => return Err(format!("Unknown param size {}", x).into()),
-                    };
-                    if value > param.range && param.range > 0 {
-                        if param.range.count_ones() != 1 {
-                            return Err("Only ranges of power 2 can mask values".into());
-                        }
-                        let range_mask = param.range - 1;
-                        (value & range_mask).into()
-                    } else {
-                        value.into()
-                    }
-                }
-                _ => panic!("Data type is in an unknown state"),
+        // the event is at the end of `buf` (after any data that was skipped over to reach it)
+        let buf = &buf[buf.len().saturating_sub(event_size(state)?)..];
+        if let Some(width) = state.uniform_int_width {
+            read_int_event(buf, width, state, &mut self.values)?;
+        } else {
+            read_event(buf, state, &mut self.values)?;
+        }
+        if let Some(clock) = state.clock {
+            let time = match self.values[clock.time_ix] {
+                Value::Float(f) => f,
+                #[allow(clippy::cast_precision_loss)]
+                Value::Integer(i) => i as f64,
+                _ => return Err("FCS Time param is not a number".into()),
             };
+            #[allow(clippy::cast_possible_truncation)]
+            let offset = Duration::microseconds((time * clock.time_step * 1e6).round() as i64);
+            let abs_time = clock
+                .start
+                .checked_add_signed(offset)
+                .ok_or("FCS Time param is out of range for a date")?;
+            self.values[n_values - 1] = abs_time.into();
         }
         Ok(())
     }
 }
 
+/// Decode an event value by value (for events that `read_int_event` can't handle).
+fn read_event<'r>(buf: &[u8], state: &FcsState, values: &mut [Value<'r>]) -> Result<(), EtError> {
+    let con = &mut 0;
+    for (ix, param) in state.params.iter().enumerate() {
+        values[ix] = match state.data_type {
+            'A' if !param.delimited => {
+                let n = extract::<&[u8]>(buf, con, &mut (param.size as usize))?;
+                str::from_utf8(n)?.trim().parse::<f64>()?.into()
+            }
+            'A' if param.delimited => {
+                return Err("Delimited-ASCII number datatypes are not yet supported".into());
+            }
+            'D' => extract::<f64>(buf, con, &mut state.endian.clone())?.into(),
+            'F' => extract::<f32>(buf, con, &mut state.endian.clone())?.into(),
+            'I' => {
+                let value: u64 = match param.size {
+                    8 => extract::<u8>(buf, con, &mut state.endian.clone())?.into(),
+                    16 => extract::<u16>(buf, con, &mut state.endian.clone())?.into(),
+                    24 => {
+                        let top = u32::from(extract::<u8>(buf, con, &mut state.endian.clone())?);
+                        let bottom =
+                            u32::from(extract::<u16>(buf, con, &mut state.endian.clone())?);
+                        ((top << 16) + bottom).into()
+                    }
+                    32 => extract::<u32>(buf, con, &mut state.endian.clone())?.into(),
+                    64 => extract::<u64>(buf, con, &mut state.endian.clone())?,
+                    x => return Err(format!("Unknown param size {}", x).into()),
+                };
+                mask_value(value, param.range)?.into()
+            }
+            x => return Err(format!("Unknown FCS data type {}", x).into()),
+        };
+    }
+    Ok(())
+}
+
 impl<'r> From<FcsRecord<'r>> for Vec<Value<'r>> {
     fn from(record: FcsRecord<'r>) -> Self {
         record.values
     }
 }
 
-impl_reader!(FcsReader, FcsRecord, FcsRecord<'r>, FcsState, BTreeMap<String, String>);
+impl<'r> PushFields<'r> for FcsRecord<'r> {
+    fn push_fields<E: Extend<Value<'r>>>(self, fields: &mut E) {
+        fields.extend(self.values);
+    }
+}
+
+impl_reader!(FcsReader, FcsRecord, FcsRecord<'r>, FcsState, FcsParams);
+
+/// Which kind of record a FCS reader returns
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum FcsStream {
+    /// Return one record per event
+    #[default]
+    Events,
+    /// Return one record per discontinuity in the Time param (e.g. acquisition was paused or
+    /// the instrument clogged)
+    EventsGaps,
+}
+
+impl FromStr for FcsStream {
+    type Err = EtError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "event" | "events" => Ok(FcsStream::Events),
+            "gaps" | "events_gaps" => Ok(FcsStream::EventsGaps),
+            x => Err(format!(
+                "FCS stream must be \"events\" or \"events_gaps\", not {}",
+                x
+            )
+            .into()),
+        }
+    }
+}
+
+/// The internal state of the `FcsGapReader`.
+#[derive(Clone, Debug, Default)]
+pub struct FcsGapState {
+    fcs: FcsState,
+    /// The shortest pause (in seconds) that's reported
+    threshold: f64,
+    /// The Time of the last event read in the current data set
+    last_time: Option<f64>,
+    /// The values of the event being checked (only the Time is used)
+    values: Vec<Value<'static>>,
+    /// The index of the event after the current gap and the Times on either side of it
+    gap: (usize, f64, f64),
+}
+
+impl StateMetadata for FcsGapState {
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        let mut metadata = self.fcs.metadata();
+        drop(metadata.insert("gap_threshold".into(), self.threshold.into()));
+        metadata
+    }
+
+    fn column_metadata(&self) -> BTreeMap<String, BTreeMap<String, Value<'_>>> {
+        let mut column_metadata = BTreeMap::new();
+        for column in ["start", "end", "duration"] {
+            let mut metadata = BTreeMap::new();
+            drop(metadata.insert("units".into(), "s".into()));
+            drop(column_metadata.insert(column.into(), metadata));
+        }
+        column_metadata
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::HAS_METADATA | Capabilities::TYPED_SCHEMA
+    }
+
+    fn header(&self) -> Vec<&str> {
+        vec!["event", "start", "end", "duration"]
+    }
+}
+
+impl<'b: 's, 's> FromSlice<'b, 's> for FcsGapState {
+    type State = FcsParams;
+
+    fn parse(
+        buf: &[u8],
+        eof: bool,
+        consumed: &mut usize,
+        params: &mut Self::State,
+    ) -> Result<bool, EtError> {
+        FcsState::parse(buf, eof, consumed, params)
+    }
+
+    fn get(&mut self, buf: &'b [u8], params: &'s Self::State) -> Result<(), EtError> {
+        self.fcs.get(buf, params)?;
+        // every event has to be checked to find the gaps between them
+        self.fcs.first_event = 0;
+        self.fcs.last_event = None;
+        self.fcs.sample_n = None;
+        if self.fcs.time_ix.is_none() {
+            return Err("FCS gap detection needs a Time param".into());
+        }
+        if self.fcs.time_step.is_none() {
+            return Err("FCS gap detection needs the $TIMESTEP keyword".into());
+        }
+        self.threshold = params.gap_threshold.unwrap_or(1.);
+        self.last_time = None;
+        Ok(())
+    }
+}
+
+/// A discontinuity in the Time param of a FCS file.
+///
+/// These are found by comparing each event's Time to the one before it, so a `duration` at
+/// least as long as the `gap_threshold` is a pause in acquisition (or a clog) and a negative
+/// `duration` is the Time jumping backwards (e.g. if the instrument's counter rolled over).
+#[derive(Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FcsGapRecord {
+    /// The index of the first event after the gap (counting from 0 in each data set)
+    pub event: u64,
+    /// The Time of the last event before the gap, in seconds
+    pub start: f64,
+    /// The Time of the first event after the gap, in seconds
+    pub end: f64,
+    /// How long the gap was, in seconds
+    pub duration: f64,
+}
+
+impl_record!(FcsGapRecord: event, start, end, duration);
+
+impl<'b: 's, 's> FromSlice<'b, 's> for FcsGapRecord {
+    type State = FcsGapState;
+
+    fn parse(
+        buf: &[u8],
+        eof: bool,
+        consumed: &mut usize,
+        state: &mut Self::State,
+    ) -> Result<bool, EtError> {
+        // each event is consumed as it's checked so only the events since the last refill of
+        // the buffer have to be held
+        let start = *consumed;
+        loop {
+            if state.fcs.n_events_left == 0 {
+                // the Time starts over in each data set
+                state.last_time = None;
+            }
+            if !FcsRecord::parse(&buf[*consumed - start..], eof, consumed, &mut state.fcs)? {
+                return Ok(false);
+            }
+            let fcs = &state.fcs;
+            let (time_ix, time_step) = match (fcs.time_ix, fcs.time_step) {
+                (Some(time_ix), Some(time_step)) => (time_ix, time_step),
+                _ => return Err("FCS gap detection needs a Time param and $TIMESTEP".into()),
+            };
+            if state.values.len() != fcs.params.len() {
+                state.values.resize(fcs.params.len(), Value::Null);
+            }
+            let end = *consumed - start;
+            let event = &buf[end.saturating_sub(event_size(fcs)?)..end];
+            if let Some(width) = fcs.uniform_int_width {
+                read_int_event(event, width, fcs, &mut state.values)?;
+            } else {
+                read_event(event, fcs, &mut state.values)?;
+            }
+            let time = match state.values[time_ix] {
+                Value::Float(f) => f * time_step,
+                #[allow(clippy::cast_precision_loss)]
+                Value::Integer(i) => i as f64 * time_step,
+                _ => return Err("FCS Time param is not a number".into()),
+            };
+            let last_time = state.last_time.replace(time);
+            if let Some(last_time) = last_time {
+                if time < last_time || time - last_time >= state.threshold {
+                    let event_ix = fcs.n_events - fcs.n_events_left - 1;
+                    state.gap = (event_ix, last_time, time);
+                    return Ok(true);
+                }
+            }
+        }
+    }
+
+    fn get(&mut self, _buf: &'b [u8], state: &'s Self::State) -> Result<(), EtError> {
+        let (event, start, end) = state.gap;
+        self.event = event as u64;
+        self.start = start;
+        self.end = end;
+        self.duration = end - start;
+        Ok(())
+    }
+}
+
+impl_reader!(
+    /// A reader for the pauses and other discontinuities in the Time param of a FCS file
+    FcsGapReader,
+    FcsGapRecord,
+    FcsGapRecord,
+    FcsGapState,
+    FcsParams
+);
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(feature = "std")]
+    use crate::buffer::ReadBuffer;
+    #[cfg(feature = "std")]
+    use crate::columns::{into_records, ColumnValues};
     use crate::readers::RecordReader;
 
     #[test]
@@ -448,6 +1277,238 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_fcs_int_events() -> Result<(), EtError> {
+        let column = |size, range| FcsColumn {
+            size,
+            range,
+            ..Default::default()
+        };
+        let event = b"\x01\x02\x03\x04\x85\x06\x07\x08";
+        for (size, endian, expected) in [
+            (8, Endian::Little, [1, 2, 3, 4, 5, 6, 7, 8]),
+            (
+                16,
+                Endian::Big,
+                [0x0102, 0x0304, 0x0506, 0x0708, 0, 0, 0, 0],
+            ),
+            (
+                16,
+                Endian::Little,
+                [0x0201, 0x0403, 0x0685, 0x0807, 0, 0, 0, 0],
+            ),
+        ] {
+            let n_params = 64 / size;
+            let state = FcsState {
+                params: (0..n_params)
+                    .map(|_| column(size, 1 << (size - 1)))
+                    .collect(),
+                endian,
+                data_type: 'I',
+                ..Default::default()
+            };
+            let fast_state = FcsState {
+                uniform_int_width: Some(usize::from(size / 8)),
+                ..state.clone()
+            };
+            let mut slow = FcsRecord::default();
+            slow.get(&event[..], &state)?;
+            let mut fast = FcsRecord::default();
+            fast.get(&event[..], &fast_state)?;
+            assert_eq!(slow.values, fast.values);
+            let expected: Vec<Value> = expected[..usize::from(n_params)]
+                .iter()
+                .map(|v| Value::from(*v as u64))
+                .collect();
+            assert_eq!(fast.values, expected);
+        }
+
+        // a state that didn't come from a valid header is an error instead of a panic
+        let state = FcsState {
+            params: vec![column(8, 0)],
+            data_type: 'X',
+            ..Default::default()
+        };
+        assert!(FcsRecord::default().get(&event[..], &state).is_err());
+        Ok(())
+    }
+
+    /// A FCS file of events with three 16-bit integer params
+    #[cfg(feature = "std")]
+    fn int_fcs(byte_order: &str, events: &[[u16; 3]]) -> Vec<u8> {
+        let data_len = events.len() * 6;
+        let text = |start: usize| {
+            format!(
+                "/$BEGINDATA/{:08}/$ENDDATA/{:08}/$PAR/3/$TOT/{}/$DATATYPE/I/$BYTEORD/{}/$MODE/L/\
+                 $P1B/16/$P1R/1024/$P1N/A/$P2B/16/$P2R/65536/$P2N/B/$P3B/16/$P3R/16/$P3N/C/",
+                start,
+                start + data_len - 1,
+                events.len(),
+                byte_order,
+            )
+        };
+        let text_len = text(0).len();
+        // the data offsets are only in the TEXT segment (they're 0 in the header)
+        let mut file = format!("FCS3.0    {:>8}{:>8}", 58, 57 + text_len).into_bytes();
+        file.extend(format!("{:>8}", 0).repeat(4).as_bytes());
+        file.extend(text(58 + text_len).as_bytes());
+        for value in events.iter().flatten() {
+            if byte_order == "1,2" {
+                file.extend(value.to_le_bytes());
+            } else {
+                file.extend(value.to_be_bytes());
+            }
+        }
+        file
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_fcs_int_columns() -> Result<(), EtError> {
+        let events: Vec<[u16; 3]> = (0..1000u16).map(|i| [i, i * 60, i % 40]).collect();
+        let expected: Vec<Vec<Value>> = events
+            .iter()
+            // values above the range of 16 have their high bits masked off
+            .map(|e| {
+                vec![
+                    e[0].into(),
+                    e[1].into(),
+                    if e[2] > 16 { e[2] % 16 } else { e[2] }.into(),
+                ]
+            })
+            .collect();
+        for (byte_order, buffer_size, params) in [
+            ("1,2", None, FcsParams::default()),
+            // refills land partway through events and the rows aren't aligned in the buffer
+            ("1,2", Some(101), FcsParams::default()),
+            (
+                "1,2",
+                Some(101),
+                FcsParams::default().first_event(7).last_event(900),
+            ),
+            // events that are sampled or in the other byte order are read one by one
+            ("1,2", None, FcsParams::default().sample_n(10)),
+            ("2, 1", Some(101), FcsParams::default()),
+        ] {
+            let file = int_fcs(byte_order, &events);
+            let rb = ReadBuffer::from_reader(Box::new(std::io::Cursor::new(file)), buffer_size)?;
+            let mut reader = FcsReader::new(rb, Some(params.clone()))?;
+            let mut columns = Vec::new();
+            while reader.next_columns(&mut columns, 64)? > 0 {}
+            let (first, n_range, n_sampled) = reader.state.selection();
+            let selected: Vec<_> = (0..n_sampled)
+                .map(|ix| &expected[first + ix * n_range / n_sampled])
+                .cloned()
+                .collect();
+            assert!(matches!(columns[1].values(), ColumnValues::Integer(_)));
+            assert_eq!(into_records(columns), selected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_fcs_event_selection() -> Result<(), EtError> {
+        let buf: &[u8] =
+            include_bytes!("../../tests/data/HTS_BD_LSR_II_Mixed_Specimen_001_D6_D06.fcs");
+        let mut all_events = Vec::new();
+        let mut reader = FcsReader::new(buf, None)?;
+        while let Some(record) = reader.next()? {
+            all_events.push(
+                record
+                    .values
+                    .into_iter()
+                    .map(Value::into_owned)
+                    .collect::<Vec<_>>(),
+            );
+        }
+
+        let params = FcsParams::default().first_event(10).last_event(19);
+        let mut reader = FcsReader::new(buf, Some(params))?;
+        assert_eq!(reader.size_hint(), (10, Some(10)));
+        for values in &all_events[10..20] {
+            assert_eq!(&reader.next()?.unwrap().values, values);
+        }
+        assert!(reader.next()?.is_none());
+
+        let params = FcsParams::default().first_event(14900).sample_n(5);
+        let mut reader = FcsReader::new(buf, Some(params))?;
+        // the 45 events left are sampled every 9 events
+        for ix in [14900, 14909, 14918, 14927, 14936] {
+            assert_eq!(reader.next()?.unwrap().values, all_events[ix]);
+        }
+        assert!(reader.next()?.is_none());
+        assert_eq!(reader.size_hint(), (0, Some(0)));
+
+        // past the end of the file
+        let params = FcsParams::default().first_event(20000);
+        let mut reader = FcsReader::new(buf, Some(params))?;
+        assert!(reader.next()?.is_none());
+        Ok(())
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_fcs_sampling_from_stream() -> Result<(), EtError> {
+        let buf: &[u8] =
+            include_bytes!("../../tests/data/HTS_BD_LSR_II_Mixed_Specimen_001_D6_D06.fcs");
+        let params = FcsParams::default().sample_n(3);
+        let mut expected = Vec::new();
+        let mut reader = FcsReader::new(buf, Some(params.clone()))?;
+        while let Some(record) = reader.next()? {
+            expected.push(
+                record
+                    .values
+                    .into_iter()
+                    .map(Value::into_owned)
+                    .collect::<Vec<_>>(),
+            );
+        }
+        assert_eq!(expected.len(), 3);
+
+        // events are skipped a buffer at a time when streaming
+        let rb = ReadBuffer::from_reader(Box::new(std::io::Cursor::new(buf)), Some(4096))?;
+        let mut reader = FcsReader::new(rb, Some(params))?;
+        for values in expected {
+            assert_eq!(reader.next()?.unwrap().values, values);
+        }
+        assert!(reader.next()?.is_none());
+        Ok(())
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_fcs_skip_records() -> Result<(), EtError> {
+        let buf: &[u8] =
+            include_bytes!("../../tests/data/HTS_BD_LSR_II_Mixed_Specimen_001_D6_D06.fcs");
+        let mut all_events = Vec::new();
+        let mut reader = FcsReader::new(buf, None)?;
+        while let Some(record) = reader.next()? {
+            all_events.push(
+                record
+                    .values
+                    .into_iter()
+                    .map(Value::into_owned)
+                    .collect::<Vec<_>>(),
+            );
+        }
+
+        let rb = ReadBuffer::from_reader(Box::new(std::io::Cursor::new(buf)), Some(4096))?;
+        let mut reader = FcsReader::new(rb, None)?;
+        assert!(reader.capabilities().contains(Capabilities::SEEKABLE));
+        assert_eq!(reader.skip_records(10000)?, 10000);
+        assert_eq!(reader.size_hint().0, all_events.len() - 10000);
+        assert_eq!(reader.next()?.unwrap().values, all_events[10000]);
+        assert_eq!(reader.skip_records(1_000_000)?, all_events.len() - 10001);
+        assert!(reader.next()?.is_none());
+
+        // skipping counts the sampled events
+        let params = FcsParams::default().first_event(14900).sample_n(5);
+        let mut reader = FcsReader::new(buf, Some(params))?;
+        assert_eq!(reader.skip_records(3)?, 3);
+        assert_eq!(reader.next()?.unwrap().values, all_events[14927]);
+        Ok(())
+    }
+
     #[test]
     fn test_fcs_reader_metadata() -> Result<(), EtError> {
         let buf: &[u8] =
@@ -463,6 +1524,232 @@ mod tests {
                 .unwrap()
                 .into()
         );
+
+        let column_metadata = reader.column_metadata();
+        assert_eq!(column_metadata["FSC-A"]["voltage"], 611.0.into());
+        assert_eq!(column_metadata["AmCyan-A"]["voltage"], 550.0.into());
+        assert!(!column_metadata.contains_key("Time"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_fcs_abs_time() -> Result<(), EtError> {
+        let buf: &[u8] =
+            include_bytes!("../../tests/data/HTS_BD_LSR_II_Mixed_Specimen_001_D6_D06.fcs");
+        let params = FcsParams::default().abs_time(true);
+        let mut reader = FcsReader::new(buf, Some(params))?;
+        assert_eq!(
+            reader.headers().last().map(String::as_str),
+            Some("abs_time")
+        );
+        let start = NaiveDate::from_ymd_opt(2012, 10, 26)
+            .unwrap()
+            .and_hms_opt(18, 8, 10)
+            .unwrap();
+        let mut last = start;
+        while let Some(record) = reader.next()? {
+            assert_eq!(record.values.len(), 12);
+            // the Time param counts in $TIMESTEP (0.01 second) units from $BTIM
+            let time = match record.values[10] {
+                Value::Float(t) => t,
+                _ => panic!("Time should be a float"),
+            };
+            let abs_time = match record.values[11] {
+                Value::Datetime(d) => d,
+                _ => panic!("abs_time should be a datetime"),
+            };
+            assert_eq!(
+                abs_time,
+                start + Duration::microseconds((time * 10_000.).round() as i64)
+            );
+            assert!(abs_time >= last);
+            last = abs_time;
+        }
+        // $ETIM is 18:08:21
+        assert_eq!(last.time().format("%H:%M:%S").to_string(), "18:08:20");
+
+        // a huge $TIMESTEP puts the times past any date chrono can hold
+        let mut data = buf.to_vec();
+        let pos = data
+            .windows(14)
+            .position(|w| w == b"$TIMESTEP\x0c0.01")
+            .unwrap();
+        data[pos + 10..pos + 14].copy_from_slice(b"9e99");
+        let params = FcsParams::default().abs_time(true);
+        let mut reader = FcsReader::new(&data[..], Some(params))?;
+        let err = loop {
+            match reader.next() {
+                Ok(Some(_)) => {}
+                Ok(None) => panic!("Times should have overflowed"),
+                Err(e) => break e,
+            }
+        };
+        assert!(err.msg.contains("out of range for a date"));
+        Ok(())
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_fcs_gaps() -> Result<(), EtError> {
+        let buf: &[u8] =
+            include_bytes!("../../tests/data/HTS_BD_LSR_II_Mixed_Specimen_001_D6_D06.fcs");
+        // find the gaps between the Times of the events ourselves ($TIMESTEP is 0.01)
+        let mut expected = Vec::new();
+        let mut last_time = None;
+        let mut reader = FcsReader::new(buf, None)?;
+        let mut ix = 0;
+        while let Some(record) = reader.next()? {
+            let time = match record.values[10] {
+                Value::Float(t) => t * 0.01,
+                _ => panic!("Time should be a float"),
+            };
+            if let Some(last) = last_time {
+                if time - last >= 0.007 {
+                    expected.push((ix, last, time));
+                }
+            }
+            last_time = Some(time);
+            ix += 1;
+        }
+        assert!(!expected.is_empty());
+
+        let params = FcsParams::default().gap_threshold(0.007).sample_n(2);
+        let mut reader = FcsGapReader::new(buf, Some(params.clone()))?;
+        assert_eq!(reader.headers(), ["event", "start", "end", "duration"]);
+        assert_eq!(reader.metadata()["gap_threshold"], Value::Float(0.007));
+        let mut gaps = Vec::new();
+        while let Some(gap) = reader.next()? {
+            assert_eq!(gap.duration, gap.end - gap.start);
+            gaps.push((gap.event, gap.start, gap.end));
+        }
+        assert_eq!(gaps, expected);
+
+        // events are checked as they're consumed so this works when streaming too
+        let rb = ReadBuffer::from_reader(Box::new(std::io::Cursor::new(buf)), Some(4096))?;
+        let mut reader = FcsGapReader::new(rb, Some(params))?;
+        for (event, start, end) in expected {
+            let gap = reader.next()?.unwrap();
+            assert_eq!((gap.event, gap.start, gap.end), (event, start, end));
+        }
+        assert!(reader.next()?.is_none());
+
+        // with the default threshold of a second, there are no pauses in this file
+        let mut reader = FcsGapReader::new(buf, None)?;
+        assert!(reader.next()?.is_none());
+
+        assert_eq!("events_gaps".parse::<FcsStream>()?, FcsStream::EventsGaps);
+        assert!("pauses".parse::<FcsStream>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_btim() {
+        let hms = |h, m, s, us| NaiveTime::from_hms_micro_opt(h, m, s, us);
+        assert_eq!(parse_btim("18:08:10"), hms(18, 8, 10, 0));
+        assert_eq!(parse_btim(" 18:08:10.25"), hms(18, 8, 10, 250_000));
+        assert_eq!(parse_btim("18:08:10:30"), hms(18, 8, 10, 500_000));
+        assert_eq!(parse_btim("18:08"), None);
+    }
+
+    #[test]
+    fn test_fcs_column_metadata() -> Result<(), EtError> {
+        let mut map = BTreeMap::new();
+        for (key, value) in [
+            ("$BEGINDATA", "0"),
+            ("$ENDDATA", "0"),
+            ("$DATATYPE", "I"),
+            ("$PAR", "2"),
+            ("$TOT", "0"),
+            ("$P1B", "16"),
+            ("$P1N", "FL1-A"),
+            ("$P1S", "CD3 FITC"),
+            ("$P1V", "450"),
+            ("$P1L", "488"),
+            ("$P1R", "1024"),
+            ("$P2B", "16"),
+            ("$P2N", "FL2-A"),
+            ("$P2L", "405,561"),
+            ("$PLATEWELL", "D6"),
+        ] {
+            drop(map.insert(key.to_string(), value.to_string()));
+        }
+        let mut state = FcsState::default();
+        state.get(
+            b"",
+            &FcsParams {
+                text: map,
+                ..FcsParams::default()
+            },
+        )?;
+        let column_metadata = state.column_metadata();
+        assert_eq!(column_metadata["FL1-A"]["long_name"], "CD3 FITC".into());
+        assert_eq!(column_metadata["FL1-A"]["voltage"], 450.0.into());
+        assert_eq!(column_metadata["FL1-A"]["range"], 1024u64.into());
+        assert_eq!(
+            column_metadata["FL1-A"]["excitation_wavelengths"],
+            vec![Value::Integer(488)].into()
+        );
+        assert_eq!(
+            column_metadata["FL2-A"]["excitation_wavelengths"],
+            vec![Value::Integer(405), Value::Integer(561)].into()
+        );
+        assert!(!column_metadata["FL2-A"].contains_key("voltage"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_fcs_dates() -> Result<(), EtError> {
+        let metadata = |date: &str, params: FcsParams| -> Result<_, EtError> {
+            let mut map = BTreeMap::new();
+            for (key, value) in [
+                ("$BEGINDATA", "0"),
+                ("$ENDDATA", "0"),
+                ("$TOT", "0"),
+                ("$BTIM", "18:08:10"),
+                ("$DATE", date),
+            ] {
+                drop(map.insert(key.to_string(), value.to_string()));
+            }
+            let mut state = FcsState::default();
+            state.get(
+                b"",
+                &FcsParams {
+                    text: map,
+                    ..params
+                },
+            )?;
+            Ok(state
+                .metadata()
+                .into_iter()
+                .map(|(k, v)| (k, v.into_owned()))
+                .collect::<BTreeMap<_, _>>())
+        };
+        let datetime = |y, m, d| {
+            NaiveDate::from_ymd_opt(y, m, d)
+                .unwrap()
+                .and_hms_opt(18, 8, 10)
+                .unwrap()
+        };
+
+        let md = metadata("26-OCT-2012", FcsParams::default().strict_dates(true))?;
+        assert_eq!(md["date"], datetime(2012, 10, 26).into());
+        assert!(!md.contains_key("warnings"));
+
+        // unparseable dates fall back to a default date (with a warning)
+        let md = metadata("someday", FcsParams::default())?;
+        assert_eq!(md["date"], datetime(2000, 1, 1).into());
+        assert_eq!(
+            md["warnings"],
+            vec![Value::from("$DATE: Could not parse date \"someday\"")].into()
+        );
+        let default = NaiveDate::from_ymd_opt(1999, 12, 31).unwrap();
+        let md = metadata("someday", FcsParams::default().default_date(default))?;
+        assert_eq!(md["date"], datetime(1999, 12, 31).into());
+
+        // or are null in strict mode
+        let md = metadata("someday", FcsParams::default().strict_dates(true))?;
+        assert_eq!(md["date"], Value::Null);
+        assert!(md.contains_key("warnings"));
         Ok(())
     }
 
@@ -0,0 +1,367 @@
+use alloc::borrow::Cow;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use chrono::NaiveDateTime;
+use memchr::memchr;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::capabilities::Capabilities;
+use crate::parsers::datetime::DateParser;
+use crate::parsers::xml::{next_tag, skip_element, XmlTag, XmlTagType, XmlText};
+use crate::parsers::{extract_with, FromSlice};
+use crate::record::{StateMetadata, Value};
+use crate::EtError;
+use crate::{impl_reader, impl_record};
+
+/// The current state of the GPX reader
+#[derive(Clone, Debug, Default)]
+pub struct GpxState {
+    metadata: BTreeMap<String, Value<'static>>,
+    /// The kind of point the current record is (`waypoint`, `route`, or `track`)
+    point_type: &'static str,
+    /// The name of the route or track the current point is in
+    track: Option<String>,
+    /// Which segment of the track the current point is in
+    segment: Option<u64>,
+    /// Set between the start of a route or track and its first point (where its name is)
+    in_track_header: bool,
+    /// The length of the element for the current record (the end of the buffer passed to `get`)
+    record_len: usize,
+    dates: DateParser,
+}
+
+impl StateMetadata for GpxState {
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        self.metadata.clone()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::HAS_METADATA | Capabilities::TYPED_SCHEMA
+    }
+
+    fn header(&self) -> Vec<&str> {
+        vec![
+            "time",
+            "latitude",
+            "longitude",
+            "elevation",
+            "name",
+            "point_type",
+            "track",
+            "segment",
+        ]
+    }
+}
+
+impl<'b: 's, 's> FromSlice<'b, 's> for GpxState {
+    type State = ();
+
+    fn parse(
+        buffer: &[u8],
+        eof: bool,
+        consumed: &mut usize,
+        _state: &mut Self::State,
+    ) -> Result<bool, EtError> {
+        let con = &mut 0;
+        loop {
+            match next_tag(buffer, eof, con)? {
+                Some(tag) if tag.id == "gpx" && tag.tag_type == XmlTagType::Open => break,
+                Some(_) => {}
+                None => return Err("No <gpx> element found".into()),
+            }
+        }
+        // include the <metadata> element (if there is one) so it can be read in `get`
+        let peek = &mut con.clone();
+        if let Some(tag) = next_tag(buffer, eof, peek)? {
+            if tag.id == "metadata" && tag.tag_type == XmlTagType::Open {
+                skip_element(buffer, eof, peek, "metadata")?;
+                *con = *peek;
+            }
+        }
+        *consumed += *con;
+        Ok(true)
+    }
+
+    fn get(&mut self, buffer: &'b [u8], _state: &'s Self::State) -> Result<(), EtError> {
+        // the names of the elements the current text is inside
+        let mut path: Vec<&str> = Vec::new();
+        let con = &mut 0;
+        while *con < buffer.len() {
+            if buffer[*con] != b'<' {
+                let XmlText(text) = extract_with(buffer, con, &())?;
+                let key = match path[..] {
+                    [.., "gpx", "metadata", "name"] => "name",
+                    [.., "gpx", "metadata", "desc"] => "description",
+                    [.., "gpx", "metadata", "keywords"] => "keywords",
+                    [.., "gpx", "metadata", "time"] => {
+                        let date = self.dates.parse_utc(&text)?;
+                        drop(self.metadata.insert("date".to_string(), date.into()));
+                        continue;
+                    }
+                    _ => continue,
+                };
+                drop(
+                    self.metadata
+                        .insert(key.to_string(), text.trim().to_string().into()),
+                );
+                continue;
+            }
+            let tag: XmlTag = extract_with(buffer, con, &())?;
+            match tag.tag_type {
+                XmlTagType::Open => {
+                    if tag.id == "gpx" {
+                        for (attr, key) in [("creator", "creator"), ("version", "gpx_version")] {
+                            if let Some(value) = tag.attribute(attr) {
+                                drop(
+                                    self.metadata
+                                        .insert(key.to_string(), value.to_string().into()),
+                                );
+                            }
+                        }
+                    }
+                    path.push(tag.id);
+                }
+                XmlTagType::Close => drop(path.pop()),
+                XmlTagType::SelfClose | XmlTagType::Declaration => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A single point (waypoint, or a point on a route or track) from a GPX file
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct GpxRecord<'r> {
+    /// When the point was recorded
+    pub time: Option<NaiveDateTime>,
+    /// Latitude in decimal degrees
+    pub latitude: f64,
+    /// Longitude in decimal degrees
+    pub longitude: f64,
+    /// Elevation in meters
+    pub elevation: Option<f64>,
+    /// The name of the point (usually only set for waypoints)
+    #[cfg_attr(feature = "serde", serde(borrow))]
+    pub name: Option<Cow<'r, str>>,
+    /// Whether this is a `waypoint`, a point on a `route`, or a point on a `track`
+    pub point_type: &'r str,
+    /// The name of the route or track the point is on
+    #[cfg_attr(feature = "serde", serde(borrow))]
+    pub track: Option<&'r str>,
+    /// The (0-based) number of the segment of the track the point is in
+    pub segment: Option<u64>,
+}
+
+impl_record!(GpxRecord<'r>: time, latitude, longitude, elevation, name, point_type, track, segment);
+
+impl<'b: 's, 's> FromSlice<'b, 's> for GpxRecord<'s> {
+    type State = GpxState;
+
+    fn parse(
+        buffer: &[u8],
+        eof: bool,
+        consumed: &mut usize,
+        state: &mut Self::State,
+    ) -> Result<bool, EtError> {
+        // everything before `skipped` has already been added to `consumed`; we update that as
+        // we go so we don't need to hold elements we're skipping over in the buffer
+        let mut skipped = 0;
+        loop {
+            let start = match memchr(b'<', &buffer[skipped..]) {
+                Some(s) => skipped + s,
+                None if eof => return Ok(false),
+                None => return Err(EtError::from("Could not find next GPX tag").incomplete()),
+            };
+            let con = &mut start.clone();
+            let tag: XmlTag = extract_with(buffer, con, &())?;
+            match (tag.id, tag.tag_type) {
+                ("gpx", XmlTagType::Close) => return Ok(false),
+                ("wpt" | "rtept" | "trkpt", XmlTagType::Open | XmlTagType::SelfClose) => {
+                    if tag.tag_type == XmlTagType::Open {
+                        skip_element(buffer, eof, con, tag.id)?;
+                    }
+                    state.point_type = match tag.id {
+                        "wpt" => "waypoint",
+                        "rtept" => "route",
+                        _ => "track",
+                    };
+                    state.in_track_header = false;
+                    state.record_len = *con - start;
+                    *consumed += *con - skipped;
+                    return Ok(true);
+                }
+                ("trk" | "rte", XmlTagType::Open) => {
+                    state.track = None;
+                    state.segment = None;
+                    state.in_track_header = true;
+                }
+                ("trkseg", XmlTagType::Open | XmlTagType::SelfClose) => {
+                    state.segment = Some(state.segment.map_or(0, |s| s + 1));
+                    state.in_track_header = false;
+                }
+                ("name", XmlTagType::Open) if state.in_track_header => {
+                    let XmlText(text) = extract_with(buffer, con, &())?;
+                    state.track = Some(text.trim().to_string());
+                }
+                // extensions can have their own e.g. <name>s, so don't look inside them
+                ("extensions", XmlTagType::Open) => skip_element(buffer, eof, con, tag.id)?,
+                _ => {}
+            }
+            *consumed += *con - skipped;
+            skipped = *con;
+        }
+    }
+
+    fn get(&mut self, buffer: &'b [u8], state: &'s Self::State) -> Result<(), EtError> {
+        let buffer = &buffer[buffer.len() - state.record_len..];
+        let con = &mut 0;
+        let tag: XmlTag = extract_with(buffer, con, &())?;
+        let coordinate = |key: &str| -> Result<f64, EtError> {
+            let text = tag
+                .attribute(key)
+                .ok_or_else(|| format!("GPX point is missing its {}", key))?;
+            Ok(text.trim().parse()?)
+        };
+        self.latitude = coordinate("lat")?;
+        self.longitude = coordinate("lon")?;
+        self.point_type = state.point_type;
+        if state.point_type == "waypoint" {
+            self.track = None;
+            self.segment = None;
+        } else {
+            self.track = state.track.as_deref();
+            self.segment = if state.point_type == "track" {
+                state.segment
+            } else {
+                None
+            };
+        }
+        self.time = None;
+        self.elevation = None;
+        self.name = None;
+
+        // only look at the elements that are direct children of the point
+        let mut depth = 0;
+        let mut current = None;
+        while *con < buffer.len() {
+            if buffer[*con] != b'<' {
+                let XmlText(text) = extract_with(buffer, con, &())?;
+                match (depth, current) {
+                    (1, Some("ele")) => self.elevation = Some(text.trim().parse()?),
+                    (1, Some("time")) => self.time = Some(state.dates.parse_utc(&text)?),
+                    (1, Some("name")) => self.name = Some(text),
+                    _ => {}
+                }
+                continue;
+            }
+            let tag: XmlTag = extract_with(buffer, con, &())?;
+            match tag.tag_type {
+                XmlTagType::Open => {
+                    if depth == 0 {
+                        current = Some(tag.id);
+                    }
+                    depth += 1;
+                }
+                XmlTagType::Close if depth > 0 => depth -= 1,
+                XmlTagType::Close | XmlTagType::SelfClose | XmlTagType::Declaration => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+impl_reader!(
+    /// A reader for the waypoints and route and track points in a GPX file
+    GpxReader,
+    GpxRecord,
+    GpxRecord<'r>,
+    GpxState,
+    ()
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    use crate::readers::RecordReader;
+
+    static TEST_GPX: &[u8] = br#"<?xml version="1.0" encoding="UTF-8"?>
+<gpx version="1.1" creator="Logger 2000" xmlns="http://www.topografix.com/GPX/1/1">
+  <metadata>
+    <name>Morning survey</name>
+    <author><name>Someone</name></author>
+    <time>2021-05-01T09:00:00Z</time>
+  </metadata>
+  <wpt lat="47.6" lon="-122.3"><name>Station &amp; A</name></wpt>
+  <trk>
+    <name>Transect 1</name>
+    <trkseg>
+      <trkpt lat="47.61" lon="-122.31"><ele>12.5</ele><time>2021-05-01T10:00:00Z</time></trkpt>
+      <trkpt lat="47.62" lon="-122.32"><ele>13</ele><time>2021-05-01T10:00:05Z</time><extensions><name>x</name></extensions></trkpt>
+    </trkseg>
+    <trkseg>
+      <trkpt lat="47.63" lon="-122.33"/>
+    </trkseg>
+  </trk>
+</gpx>
+"#;
+
+    #[test]
+    fn test_gpx_reader() -> Result<(), EtError> {
+        let mut reader = GpxReader::new(TEST_GPX, None)?;
+        let metadata = reader.metadata();
+        assert_eq!(metadata["name"], "Morning survey".into());
+        assert_eq!(metadata["creator"], "Logger 2000".into());
+        assert_eq!(
+            metadata["date"],
+            NaiveDate::from_ymd_opt(2021, 5, 1)
+                .unwrap()
+                .and_hms_opt(9, 0, 0)
+                .unwrap()
+                .into()
+        );
+
+        let waypoint = reader.next()?.expect("a waypoint");
+        assert_eq!(waypoint.point_type, "waypoint");
+        assert_eq!(waypoint.name, Some("Station & A".into()));
+        assert_eq!(waypoint.track, None);
+
+        let point = reader.next()?.expect("a track point");
+        assert_eq!(point.point_type, "track");
+        assert_eq!(point.track, Some("Transect 1"));
+        assert_eq!(point.segment, Some(0));
+        assert_eq!(point.elevation, Some(12.5));
+        assert_eq!(
+            point.time,
+            NaiveDate::from_ymd_opt(2021, 5, 1)
+                .unwrap()
+                .and_hms_opt(10, 0, 0)
+        );
+
+        let point = reader.next()?.expect("a track point");
+        assert_eq!(point.latitude, 47.62);
+        assert_eq!(point.name, None);
+
+        let point = reader.next()?.expect("a track point");
+        assert_eq!(point.segment, Some(1));
+        assert_eq!(point.elevation, None);
+        assert!(reader.next()?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_gpx_bad_points() {
+        let data = b"<gpx><wpt lon=\"1\"></wpt></gpx>";
+        let mut reader = GpxReader::new(&data[..], None).unwrap();
+        assert!(reader.next().is_err());
+
+        assert!(GpxReader::new(&b"<kml></kml>"[..], None).is_err());
+    }
+}
@@ -0,0 +1,801 @@
+use alloc::borrow::Cow;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::str::from_utf8;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::str::FromStr;
+
+use memchr::memchr;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::capabilities::Capabilities;
+use crate::impl_reader;
+use crate::parsers::xml::{next_tag, skip_element, unescape, XmlTag, XmlTagType, XmlText};
+use crate::parsers::{extract_with, FromSlice};
+use crate::record::{PushFields, StateMetadata, Value};
+use crate::EtError;
+
+/// Which half of a graph a reader should return records for.
+///
+/// Graph formats interleave two different kinds of records so a reader only returns one of
+/// them; to get both, read the file twice with different `stream`s.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum GraphStream {
+    /// Return one record per node
+    #[default]
+    Nodes,
+    /// Return one record per edge
+    Edges,
+}
+
+impl GraphStream {
+    /// The name of the element (in both GraphML and GML) that holds this kind of record
+    fn element(self) -> &'static str {
+        match self {
+            GraphStream::Nodes => "node",
+            GraphStream::Edges => "edge",
+        }
+    }
+}
+
+impl FromStr for GraphStream {
+    type Err = EtError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "node" | "nodes" => Ok(GraphStream::Nodes),
+            "edge" | "edges" => Ok(GraphStream::Edges),
+            x => Err(format!("Graph stream must be \"nodes\" or \"edges\", not {}", x).into()),
+        }
+    }
+}
+
+/// Parameters for parsing graph formats
+#[derive(Clone, Debug, Default)]
+pub struct GraphParams {
+    /// Whether nodes or edges should be returned
+    pub stream: GraphStream,
+    /// Attribute names found in the GML while sniffing
+    gml_keys: Vec<String>,
+    /// Graph-level attributes found in the GML while sniffing
+    gml_metadata: BTreeMap<String, Value<'static>>,
+}
+
+impl GraphParams {
+    /// Set which records should be returned
+    #[must_use]
+    pub fn stream(mut self, stream: GraphStream) -> Self {
+        self.stream = stream;
+        self
+    }
+}
+
+/// The data type of a GraphML attribute
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum GraphMlType {
+    Boolean,
+    Integer,
+    Float,
+    String,
+}
+
+impl GraphMlType {
+    fn coerce<'a>(self, key: &str, text: Cow<'a, str>) -> Result<Value<'a>, EtError> {
+        let trimmed = text.trim();
+        Ok(match self {
+            GraphMlType::String => Value::String(text),
+            _ if trimmed.is_empty() => Value::Null,
+            GraphMlType::Boolean => match trimmed {
+                "true" | "True" | "TRUE" | "1" => Value::Boolean(true),
+                "false" | "False" | "FALSE" | "0" => Value::Boolean(false),
+                x => return Err(format!("Value {} for key {} is not a boolean", x, key).into()),
+            },
+            GraphMlType::Integer => Value::Integer(trimmed.parse()?),
+            GraphMlType::Float => Value::Float(trimmed.parse()?),
+        })
+    }
+}
+
+/// An attribute declared by a `<key>` element in the GraphML header
+#[derive(Clone, Debug)]
+struct GraphMlKey {
+    id: String,
+    name: String,
+    key_type: GraphMlType,
+    default: Option<String>,
+}
+
+/// The current state of the GraphML parser
+#[derive(Clone, Debug, Default)]
+pub struct GraphMlState {
+    stream: GraphStream,
+    keys: Vec<GraphMlKey>,
+    graph_id: Option<String>,
+    edge_default: Option<String>,
+    /// The length of the element for the current record (the end of the buffer passed to `get`)
+    record_len: usize,
+}
+
+impl StateMetadata for GraphMlState {
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        let mut metadata = BTreeMap::new();
+        if let Some(graph_id) = &self.graph_id {
+            drop(metadata.insert("graph_id".to_string(), graph_id.clone().into()));
+        }
+        if let Some(edge_default) = &self.edge_default {
+            drop(metadata.insert("edge_default".to_string(), edge_default.clone().into()));
+        }
+        metadata
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::HAS_METADATA
+    }
+
+    fn header(&self) -> Vec<&str> {
+        let mut headers = match self.stream {
+            GraphStream::Nodes => vec!["id"],
+            GraphStream::Edges => vec!["id", "source", "target"],
+        };
+        headers.extend(self.keys.iter().map(|k| k.name.as_str()));
+        headers
+    }
+}
+
+impl<'b: 's, 's> FromSlice<'b, 's> for GraphMlState {
+    type State = GraphParams;
+
+    fn parse(
+        buffer: &[u8],
+        eof: bool,
+        consumed: &mut usize,
+        _state: &mut Self::State,
+    ) -> Result<bool, EtError> {
+        let con = &mut 0;
+        loop {
+            match next_tag(buffer, eof, con)? {
+                Some(tag) if tag.id == "graph" && tag.tag_type != XmlTagType::Close => break,
+                Some(_) => {}
+                None => return Err("No <graph> element found in GraphML".into()),
+            }
+        }
+        *consumed += *con;
+        Ok(true)
+    }
+
+    fn get(&mut self, buffer: &'b [u8], state: &'s Self::State) -> Result<(), EtError> {
+        self.stream = state.stream;
+        let element = state.stream.element();
+        let mut keys: Vec<(bool, GraphMlKey)> = Vec::new();
+        let mut in_default = false;
+        let con = &mut 0;
+        while *con < buffer.len() {
+            if buffer[*con] != b'<' {
+                let XmlText(text) = extract_with(buffer, con, &())?;
+                if let (true, Some((_, key))) = (in_default, keys.last_mut()) {
+                    key.default = Some(text.into_owned());
+                }
+                continue;
+            }
+            let tag: XmlTag = extract_with(buffer, con, &())?;
+            match (tag.id, tag.tag_type) {
+                ("key", XmlTagType::Open | XmlTagType::SelfClose) => {
+                    let domain = tag.attribute("for").unwrap_or("all");
+                    let id = tag
+                        .attribute("id")
+                        .ok_or("GraphML key is missing an id")?
+                        .to_string();
+                    let key_type = match tag.attribute("attr.type").unwrap_or("string") {
+                        "boolean" => GraphMlType::Boolean,
+                        "int" | "long" => GraphMlType::Integer,
+                        "float" | "double" => GraphMlType::Float,
+                        "string" => GraphMlType::String,
+                        x => return Err(format!("Unknown GraphML key type {}", x).into()),
+                    };
+                    let key = GraphMlKey {
+                        name: tag.attribute("attr.name").unwrap_or(&id).to_string(),
+                        id,
+                        key_type,
+                        default: None,
+                    };
+                    // keep a placeholder for keys that aren't for our stream so defaults
+                    // are attached to the right key, but then drop them below
+                    keys.push((domain == element || domain == "all", key));
+                }
+                ("default", XmlTagType::Open) => in_default = true,
+                ("default", XmlTagType::Close) => in_default = false,
+                ("graph", XmlTagType::Open | XmlTagType::SelfClose) => {
+                    self.graph_id = tag.attribute("id").map(String::from);
+                    self.edge_default = tag.attribute("edgedefault").map(String::from);
+                }
+                _ => {}
+            }
+        }
+        self.keys = keys
+            .into_iter()
+            .filter_map(|(used, key)| if used { Some(key) } else { None })
+            .collect();
+        Ok(())
+    }
+}
+
+/// A single node or edge from a GraphML file
+#[derive(Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct GraphMlRecord<'r> {
+    /// The values for each of the columns in the header
+    #[cfg_attr(feature = "serde", serde(borrow))]
+    pub values: Vec<Value<'r>>,
+}
+
+impl<'b: 's, 's> FromSlice<'b, 's> for GraphMlRecord<'s> {
+    type State = GraphMlState;
+
+    fn parse(
+        buffer: &[u8],
+        eof: bool,
+        consumed: &mut usize,
+        state: &mut Self::State,
+    ) -> Result<bool, EtError> {
+        let element = state.stream.element();
+        // everything before `skipped` has already been added to `consumed`; we update that as
+        // we go so we don't need to hold elements we're skipping over in the buffer
+        let mut skipped = 0;
+        loop {
+            let start = match memchr(b'<', &buffer[skipped..]) {
+                Some(s) => skipped + s,
+                None if eof => return Ok(false),
+                None => return Err(EtError::from("Could not find next GraphML tag").incomplete()),
+            };
+            let con = &mut start.clone();
+            let tag: XmlTag = extract_with(buffer, con, &())?;
+            match tag.tag_type {
+                XmlTagType::Close if tag.id == "graph" => return Ok(false),
+                XmlTagType::Open if tag.id == "node" || tag.id == "edge" || tag.id == "graph" => {
+                    skip_element(buffer, eof, con, tag.id)?;
+                }
+                _ => {}
+            }
+            if tag.id == element && tag.tag_type != XmlTagType::Close {
+                state.record_len = *con - start;
+                *consumed += *con - skipped;
+                return Ok(true);
+            }
+            *consumed += *con - skipped;
+            skipped = *con;
+        }
+    }
+
+    fn get(&mut self, buffer: &'b [u8], state: &'s Self::State) -> Result<(), EtError> {
+        let buffer = &buffer[buffer.len() - state.record_len..];
+        let n_fixed = match state.stream {
+            GraphStream::Nodes => 1,
+            GraphStream::Edges => 3,
+        };
+        self.values = Vec::with_capacity(n_fixed + state.keys.len());
+        let con = &mut 0;
+        let tag: XmlTag = extract_with(buffer, con, &())?;
+        self.values
+            .push(tag.attribute("id").map(String::from).into());
+        if state.stream == GraphStream::Edges {
+            for endpoint in ["source", "target"] {
+                let node = tag
+                    .attribute(endpoint)
+                    .ok_or_else(|| format!("GraphML edge has no {}", endpoint))?;
+                self.values.push(node.to_string().into());
+            }
+        }
+        for key in &state.keys {
+            self.values.push(match &key.default {
+                Some(default) => key.key_type.coerce(&key.id, Cow::Borrowed(default))?,
+                None => Value::Null,
+            });
+        }
+
+        // now fill in the <data> elements inside this element; we only pay attention to the
+        // data elements that are direct children (and not e.g. ones in a nested graph)
+        let mut depth = 0;
+        let mut current_key = None;
+        while *con < buffer.len() {
+            if buffer[*con] != b'<' {
+                let XmlText(text) = extract_with(buffer, con, &())?;
+                if let (0, Some(ix)) = (depth, current_key) {
+                    let key: &GraphMlKey = &state.keys[ix];
+                    self.values[n_fixed + ix] = key.key_type.coerce(&key.id, text)?;
+                }
+                continue;
+            }
+            let tag: XmlTag = extract_with(buffer, con, &())?;
+            match tag.tag_type {
+                XmlTagType::Open if depth == 0 && tag.id == "data" => {
+                    let key_id = tag
+                        .attribute("key")
+                        .ok_or("GraphML data is missing a key")?;
+                    current_key = state.keys.iter().position(|k| k.id == key_id);
+                }
+                XmlTagType::Open => depth += 1,
+                XmlTagType::Close if depth == 0 => current_key = None,
+                XmlTagType::Close => depth -= 1,
+                XmlTagType::SelfClose | XmlTagType::Declaration => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'r> From<GraphMlRecord<'r>> for Vec<Value<'r>> {
+    fn from(record: GraphMlRecord<'r>) -> Self {
+        record.values
+    }
+}
+
+impl<'r> PushFields<'r> for GraphMlRecord<'r> {
+    fn push_fields<E: Extend<Value<'r>>>(self, fields: &mut E) {
+        fields.extend(self.values);
+    }
+}
+
+impl_reader!(
+    /// A reader for the nodes or edges in a GraphML file
+    GraphMlReader,
+    GraphMlRecord,
+    GraphMlRecord<'r>,
+    GraphMlState,
+    GraphParams
+);
+
+/// A single token from a GML file
+#[derive(Clone, Debug, PartialEq)]
+enum GmlToken<'a> {
+    Key(&'a str),
+    Integer(i64),
+    Float(f64),
+    Text(Cow<'a, str>),
+    Open,
+    Close,
+}
+
+impl<'a> GmlToken<'a> {
+    fn into_value(self) -> Value<'a> {
+        match self {
+            GmlToken::Integer(i) => Value::Integer(i),
+            GmlToken::Float(f) => Value::Float(f),
+            GmlToken::Text(s) => Value::String(s),
+            GmlToken::Key(_) | GmlToken::Open | GmlToken::Close => Value::Null,
+        }
+    }
+}
+
+/// Read the next token from `buffer` (after `consumed`), skipping whitespace and comments.
+fn next_gml_token<'a>(
+    buffer: &'a [u8],
+    eof: bool,
+    consumed: &mut usize,
+) -> Result<Option<GmlToken<'a>>, EtError> {
+    let mut pos = *consumed;
+    loop {
+        while pos < buffer.len() && buffer[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        if pos < buffer.len() && buffer[pos] == b'#' {
+            if let Some(e) = memchr(b'\n', &buffer[pos..]) {
+                pos += e;
+                continue;
+            } else if eof {
+                pos = buffer.len();
+            } else {
+                return Err(EtError::from("Incomplete GML comment").incomplete());
+            }
+        }
+        break;
+    }
+    if pos >= buffer.len() {
+        if eof {
+            *consumed = pos;
+            return Ok(None);
+        }
+        return Err(EtError::from("Incomplete GML").incomplete());
+    }
+    let (token, end) = match buffer[pos] {
+        b'[' => (GmlToken::Open, pos + 1),
+        b']' => (GmlToken::Close, pos + 1),
+        b'"' => {
+            let end = if let Some(e) = memchr(b'"', &buffer[pos + 1..]) {
+                pos + 1 + e
+            } else if eof {
+                return Err("Unterminated GML string".into());
+            } else {
+                return Err(EtError::from("Incomplete GML string").incomplete());
+            };
+            let text = unescape(from_utf8(&buffer[pos + 1..end])?);
+            (GmlToken::Text(text), end + 1)
+        }
+        _ => {
+            let end = match buffer[pos..]
+                .iter()
+                .position(|c| c.is_ascii_whitespace() || b"[]\"#".contains(c))
+            {
+                Some(e) => pos + e,
+                None if eof => buffer.len(),
+                None => return Err(EtError::from("Incomplete GML token").incomplete()),
+            };
+            let word = from_utf8(&buffer[pos..end])?;
+            let token = if word.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+                GmlToken::Key(word)
+            } else if let Ok(i) = word.parse() {
+                GmlToken::Integer(i)
+            } else if let Ok(f) = word.parse() {
+                GmlToken::Float(f)
+            } else {
+                return Err(format!("Invalid GML value {}", word).into());
+            };
+            (token, end)
+        }
+    };
+    *consumed = end;
+    Ok(Some(token))
+}
+
+/// Read a value from `buffer` (a scalar or a whole bracketed list). Returns `None` for lists.
+fn next_gml_value<'a>(
+    buffer: &'a [u8],
+    eof: bool,
+    consumed: &mut usize,
+) -> Result<Option<GmlToken<'a>>, EtError> {
+    match next_gml_token(buffer, eof, consumed)? {
+        Some(GmlToken::Open) => {
+            let mut depth = 1;
+            while depth > 0 {
+                match next_gml_token(buffer, eof, consumed)? {
+                    Some(GmlToken::Open) => depth += 1,
+                    Some(GmlToken::Close) => depth -= 1,
+                    Some(_) => {}
+                    None => return Err("GML list was never closed".into()),
+                }
+            }
+            Ok(None)
+        }
+        Some(GmlToken::Close | GmlToken::Key(_)) | None => Err("GML key has no value".into()),
+        scalar => Ok(scalar),
+    }
+}
+
+/// Scan through the graph list to find all of the attribute names for `params.stream` and any
+/// graph-level attributes. Stops at the end of the buffer, so very late-appearing attributes may
+/// be missed (and are ignored while parsing).
+fn sniff_gml(buffer: &[u8], mut pos: usize, params: &mut GraphParams) {
+    let element = params.stream.element();
+    let fixed: &[&str] = match params.stream {
+        GraphStream::Nodes => &["id"],
+        GraphStream::Edges => &["source", "target"],
+    };
+    let con = &mut pos;
+    while let Ok(Some(GmlToken::Key(key))) = next_gml_token(buffer, false, con) {
+        let list_start = *con;
+        match next_gml_value(buffer, false, con) {
+            Ok(Some(value)) => {
+                let _ = params
+                    .gml_metadata
+                    .entry(key.to_string())
+                    .or_insert_with(|| value.into_value().into_owned());
+            }
+            Ok(None) if key == element => {
+                let inner = &mut list_start.clone();
+                let _ = next_gml_token(buffer, false, inner);
+                while let Ok(Some(GmlToken::Key(attr))) = next_gml_token(buffer, false, inner) {
+                    if let Ok(Some(_)) = next_gml_value(buffer, false, inner) {
+                        if !fixed.contains(&attr) && !params.gml_keys.iter().any(|k| k == attr) {
+                            params.gml_keys.push(attr.to_string());
+                        }
+                    }
+                }
+            }
+            Ok(None) => {}
+            Err(_) => break,
+        }
+    }
+}
+
+/// The current state of the GML parser
+#[derive(Clone, Debug, Default)]
+pub struct GmlState {
+    stream: GraphStream,
+    headers: Vec<String>,
+    metadata: BTreeMap<String, Value<'static>>,
+    /// The length of the list for the current record (the end of the buffer passed to `get`)
+    record_len: usize,
+}
+
+impl StateMetadata for GmlState {
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        self.metadata.clone()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::HAS_METADATA
+    }
+
+    fn header(&self) -> Vec<&str> {
+        self.headers.iter().map(AsRef::as_ref).collect()
+    }
+}
+
+impl<'b: 's, 's> FromSlice<'b, 's> for GmlState {
+    type State = GraphParams;
+
+    fn parse(
+        buffer: &[u8],
+        eof: bool,
+        consumed: &mut usize,
+        params: &mut Self::State,
+    ) -> Result<bool, EtError> {
+        let con = &mut 0;
+        loop {
+            match next_gml_token(buffer, eof, con)? {
+                Some(GmlToken::Key("graph")) => {
+                    if next_gml_token(buffer, eof, con)? != Some(GmlToken::Open) {
+                        return Err("GML graph key must be followed by a list".into());
+                    }
+                    break;
+                }
+                Some(GmlToken::Key(key)) => {
+                    // top-level keys like "Creator" go into the metadata too
+                    if let Some(value) = next_gml_value(buffer, eof, con)? {
+                        drop(
+                            params
+                                .gml_metadata
+                                .insert(key.to_string(), value.into_value().into_owned()),
+                        );
+                    }
+                }
+                Some(_) => return Err("Unexpected value in GML".into()),
+                None => return Err("No graph found in GML".into()),
+            }
+        }
+        sniff_gml(buffer, *con, params);
+        *consumed += *con;
+        Ok(true)
+    }
+
+    fn get(&mut self, _buffer: &'b [u8], params: &'s Self::State) -> Result<(), EtError> {
+        self.stream = params.stream;
+        self.headers = match params.stream {
+            GraphStream::Nodes => vec!["id".to_string()],
+            GraphStream::Edges => vec!["source".to_string(), "target".to_string()],
+        };
+        self.headers.extend(params.gml_keys.iter().cloned());
+        self.metadata = params.gml_metadata.clone();
+        Ok(())
+    }
+}
+
+/// A single node or edge from a GML file
+#[derive(Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct GmlRecord<'r> {
+    /// The values for each of the columns in the header
+    #[cfg_attr(feature = "serde", serde(borrow))]
+    pub values: Vec<Value<'r>>,
+}
+
+impl<'b: 's, 's> FromSlice<'b, 's> for GmlRecord<'s> {
+    type State = GmlState;
+
+    fn parse(
+        buffer: &[u8],
+        eof: bool,
+        consumed: &mut usize,
+        state: &mut Self::State,
+    ) -> Result<bool, EtError> {
+        let element = state.stream.element();
+        // as with GraphML, we add skipped-over attributes to `consumed` as we go
+        let mut skipped = 0;
+        loop {
+            let con = &mut skipped.clone();
+            let key = match next_gml_token(buffer, eof, con)? {
+                Some(GmlToken::Key(key)) => key,
+                Some(GmlToken::Close) | None => return Ok(false),
+                Some(_) => return Err("GML value has no key".into()),
+            };
+            let start = *con - key.len();
+            let is_list = next_gml_value(buffer, eof, con)?.is_none();
+            if is_list && key == element {
+                state.record_len = *con - start;
+                *consumed += *con - skipped;
+                return Ok(true);
+            }
+            *consumed += *con - skipped;
+            skipped = *con;
+        }
+    }
+
+    fn get(&mut self, buffer: &'b [u8], state: &'s Self::State) -> Result<(), EtError> {
+        let buffer = &buffer[buffer.len() - state.record_len..];
+        self.values = vec![Value::Null; state.headers.len()];
+        // skip the "node"/"edge" key and the opening bracket
+        let con = &mut 0;
+        let _ = next_gml_token(buffer, true, con)?;
+        let _ = next_gml_token(buffer, true, con)?;
+        while let Some(GmlToken::Key(key)) = next_gml_token(buffer, true, con)? {
+            // nested lists (e.g. "graphics") are skipped
+            if let Some(value) = next_gml_value(buffer, true, con)? {
+                if let Some(ix) = state.headers.iter().position(|h| h == key) {
+                    self.values[ix] = value.into_value();
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'r> From<GmlRecord<'r>> for Vec<Value<'r>> {
+    fn from(record: GmlRecord<'r>) -> Self {
+        record.values
+    }
+}
+
+impl<'r> PushFields<'r> for GmlRecord<'r> {
+    fn push_fields<E: Extend<Value<'r>>>(self, fields: &mut E) {
+        fields.extend(self.values);
+    }
+}
+
+impl_reader!(
+    /// A reader for the nodes or edges in a GML file
+    GmlReader,
+    GmlRecord,
+    GmlRecord<'r>,
+    GmlState,
+    GraphParams
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::readers::RecordReader;
+
+    const TEST_GRAPHML: &[u8] = br#"<?xml version="1.0" encoding="UTF-8"?>
+<graphml xmlns="http://graphml.graphdrawing.org/xmlns">
+  <!-- a <comment> -->
+  <key id="d0" for="node" attr.name="color" attr.type="string">
+    <default>yellow</default>
+  </key>
+  <key id="d1" for="edge" attr.name="weight" attr.type="double"/>
+  <graph id="G" edgedefault="undirected">
+    <node id="n0">
+      <data key="d0">green</data>
+    </node>
+    <node id="n1"/>
+    <edge id="e0" source="n0" target="n1">
+      <data key="d1">1.5</data>
+    </edge>
+    <node id="n2">
+      <data key="d0">blue &amp; red</data>
+    </node>
+    <edge source="n1" target="n2"/>
+  </graph>
+</graphml>"#;
+
+    #[test]
+    fn test_graphml_nodes() -> Result<(), EtError> {
+        let mut reader = GraphMlReader::new(TEST_GRAPHML, None)?;
+        assert_eq!(reader.headers(), ["id", "color"]);
+        assert_eq!(reader.metadata()["edge_default"], "undirected".into());
+
+        let rec = reader.next()?.unwrap();
+        assert_eq!(rec.values, ["n0".into(), "green".into()]);
+        let rec = reader.next()?.unwrap();
+        assert_eq!(rec.values, ["n1".into(), "yellow".into()]);
+        let rec = reader.next()?.unwrap();
+        assert_eq!(rec.values, ["n2".into(), "blue & red".into()]);
+        assert!(reader.next()?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_graphml_edges() -> Result<(), EtError> {
+        let params = GraphParams::default().stream(GraphStream::Edges);
+        let mut reader = GraphMlReader::new(TEST_GRAPHML, Some(params))?;
+        assert_eq!(reader.headers(), ["id", "source", "target", "weight"]);
+
+        let rec = reader.next()?.unwrap();
+        assert_eq!(
+            rec.values,
+            ["e0".into(), "n0".into(), "n1".into(), 1.5.into()]
+        );
+        let rec = reader.next()?.unwrap();
+        assert_eq!(
+            rec.values,
+            [Value::Null, "n1".into(), "n2".into(), Value::Null]
+        );
+        assert!(reader.next()?.is_none());
+        Ok(())
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_graph_small_buffer() -> Result<(), EtError> {
+        use crate::buffer::ReadBuffer;
+        use alloc::boxed::Box;
+        use std::io::Cursor;
+
+        let params = GraphParams::default().stream(GraphStream::Edges);
+        let rb = ReadBuffer::from_reader(Box::new(Cursor::new(TEST_GRAPHML)), Some(16))?;
+        let mut reader = GraphMlReader::new(rb, Some(params.clone()))?;
+        assert_eq!(reader.next()?.unwrap().values[0], "e0".into());
+        assert_eq!(reader.next()?.unwrap().values[1], "n1".into());
+        assert!(reader.next()?.is_none());
+
+        let rb = ReadBuffer::from_reader(Box::new(Cursor::new(TEST_GML)), Some(16))?;
+        let mut reader = GmlReader::new(rb, Some(params))?;
+        assert_eq!(reader.next()?.unwrap().values[0], 1.into());
+        assert_eq!(reader.next()?.unwrap().values[0], 2.into());
+        assert!(reader.next()?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_graphml_bad() {
+        assert!(GraphMlReader::new(&b"<graphml></graphml>"[..], None).is_err());
+        let data = b"<graphml><graph><node id=\"n0\"></graph></graphml>";
+        let mut reader = GraphMlReader::new(&data[..], None).unwrap();
+        assert!(reader.next().is_err());
+    }
+
+    const TEST_GML: &[u8] = br#"Creator "test"
+graph [
+  # an example network
+  directed 1
+  node [ id 1 label "A" ]
+  node [
+    id 2
+    label "B"
+    graphics [ x 1.0 y 2.0 ]
+    score 3.5
+  ]
+  edge [ source 1 target 2 weight 0.5 ]
+  edge [ source 2 target 1 ]
+]
+"#;
+
+    #[test]
+    fn test_gml_nodes() -> Result<(), EtError> {
+        let mut reader = GmlReader::new(TEST_GML, None)?;
+        assert_eq!(reader.headers(), ["id", "label", "score"]);
+        let metadata = reader.metadata();
+        assert_eq!(metadata["Creator"], "test".into());
+        assert_eq!(metadata["directed"], 1.into());
+
+        let rec = reader.next()?.unwrap();
+        assert_eq!(rec.values, [1.into(), "A".into(), Value::Null]);
+        let rec = reader.next()?.unwrap();
+        assert_eq!(rec.values, [2.into(), "B".into(), 3.5.into()]);
+        assert!(reader.next()?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_gml_edges() -> Result<(), EtError> {
+        let params = GraphParams::default().stream("edges".parse()?);
+        let mut reader = GmlReader::new(TEST_GML, Some(params))?;
+        assert_eq!(reader.headers(), ["source", "target", "weight"]);
+
+        let rec = reader.next()?.unwrap();
+        assert_eq!(rec.values, [1.into(), 2.into(), 0.5.into()]);
+        let rec = reader.next()?.unwrap();
+        assert_eq!(rec.values, [2.into(), 1.into(), Value::Null]);
+        assert!(reader.next()?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_gml_bad() {
+        assert!(GmlReader::new(&b"node [ id 1 ]"[..], None).is_err());
+        let mut reader = GmlReader::new(&b"graph [ node [ id \"1 ] ]"[..], None).unwrap();
+        assert!(reader.next().is_err());
+        assert!("vertices".parse::<GraphStream>().is_err());
+    }
+}
@@ -3,8 +3,12 @@ use alloc::{format, vec};
 use core::convert::TryFrom;
 use core::marker::Copy;
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::capabilities::Capabilities;
 use crate::parsers::common::SeekPattern;
-use crate::parsers::{extract, extract_opt, Endian, FromSlice};
+use crate::parsers::{extract, extract_opt, extract_with, sub_or_err, Endian, FromSlice};
 use crate::record::StateMetadata;
 use crate::EtError;
 use crate::{impl_reader, impl_record};
@@ -22,6 +26,10 @@ pub struct InficonState {
 }
 
 impl StateMetadata for InficonState {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::TYPED_SCHEMA
+    }
+
     fn header(&self) -> Vec<&str> {
         vec!["time", "mz", "intensity"]
     }
@@ -49,7 +57,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for InficonState {
             return Err("Could not find m/z header list".into());
         }
         let _ = extract::<&[u8]>(rb, con, &mut 148)?;
-        let n_segments = extract::<u32>(rb, con, &mut Endian::Little)? as usize;
+        let n_segments = extract_with::<u32>(rb, con, &Endian::Little)? as usize;
         if n_segments > 10000 {
             return Err("Inficon file has too many segments".into());
         }
@@ -60,20 +68,20 @@ impl<'b: 's, 's> FromSlice<'b, 's> for InficonState {
             // first 4 bytes appear to be an name/identifier? not sure what
             // the rest is.
             let _ = extract::<&[u8]>(rb, con, &mut 96)?;
-            let n_mzs = extract::<u32>(rb, con, &mut Endian::Little)?;
+            let n_mzs = extract_with::<u32>(rb, con, &Endian::Little)?;
             if n_mzs > 100_000 {
                 return Err("Too many m/z ranges".into());
             }
             for _ in 0..n_mzs {
-                let start_mz = extract::<u32>(rb, con, &mut Endian::Little)?;
-                let end_mz = extract::<u32>(rb, con, &mut Endian::Little)?;
+                let start_mz = extract_with::<u32>(rb, con, &Endian::Little)?;
+                let end_mz = extract_with::<u32>(rb, con, &Endian::Little)?;
                 if end_mz > 4_000_000_000u32 {
                     // only malformed data should hit this
                     return Err("End of m/z range is invalid".into());
                 }
                 // then dwell time (u32; microseconds) and three more u32s
                 let _ = extract::<&[u8]>(rb, con, &mut 16)?;
-                let i_type = extract::<u32>(rb, con, &mut Endian::Little)?;
+                let i_type = extract_with::<u32>(rb, con, &Endian::Little)?;
                 let _ = extract::<&[u8]>(rb, con, &mut 4)?;
                 if i_type == 0 {
                     // this is a SIM
@@ -99,7 +107,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for InficonState {
         // seek to right before the "HapsScan" section because the section
         // length is encoded in the four bytes before the header for that
         let _ = extract::<&[u8]>(rb, con, &mut 180)?;
-        let data_length = u64::from(extract::<u32>(rb, con, &mut Endian::Little)?);
+        let data_length = u64::from(extract_with::<u32>(rb, con, &Endian::Little)?);
         let _ = extract::<&[u8]>(rb, con, &mut 8)?;
         if extract::<&[u8]>(rb, con, &mut 8)? != b"HapsScan" {
             return Err("Data header was malformed".into());
@@ -119,6 +127,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for InficonState {
 
 /// A single record from an Inficon Hapsite file.
 #[derive(Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct InficonRecord {
     time: f64,
     mz: f64,
@@ -143,16 +152,16 @@ impl<'b: 's, 's> FromSlice<'b, 's> for InficonRecord {
         let mut mzs_left = state.mzs_left;
         if mzs_left == 0 {
             // the first u32 is the number of the record (i.e. from 1 to r_scans)
-            let _ = extract::<u32>(rb, con, &mut Endian::Little)?;
-            state.cur_time = f64::from(extract::<i32>(rb, con, &mut Endian::Little)?) / 60000.;
+            let _ = extract_with::<u32>(rb, con, &Endian::Little)?;
+            state.cur_time = f64::from(extract_with::<i32>(rb, con, &Endian::Little)?) / 60000.;
             // next value always seems to be 1
-            let _ = extract::<u16>(rb, con, &mut Endian::Little)?;
-            let n_mzs = usize::from(extract::<u16>(rb, con, &mut Endian::Little)?);
+            let _ = extract_with::<u16>(rb, con, &Endian::Little)?;
+            let n_mzs = usize::from(extract_with::<u16>(rb, con, &Endian::Little)?);
             // next value always seems to be 0xFFFF
-            let _ = extract::<u16>(rb, con, &mut Endian::Little)?;
+            let _ = extract_with::<u16>(rb, con, &Endian::Little)?;
             // the segment is only contained in the top nibble? the bottom is
             // F (e.g. values seem to be 0x0F, 0x1F, 0x2F...)
-            state.cur_segment = usize::from(extract::<u16>(rb, con, &mut Endian::Little)? >> 4);
+            state.cur_segment = usize::from(extract_with::<u16>(rb, con, &Endian::Little)? >> 4);
             if state.cur_segment >= state.mz_segments.len() {
                 return Err(
                     format!("Invalid segment number ({}) specified", state.cur_segment).into(),
@@ -168,15 +177,15 @@ impl<'b: 's, 's> FromSlice<'b, 's> for InficonRecord {
             }
             mzs_left = n_mzs;
         }
-        state.cur_intensity = f64::from(extract::<f32>(rb, con, &mut Endian::Little)?);
+        state.cur_intensity = f64::from(extract_with::<f32>(rb, con, &Endian::Little)?);
         let cur_mz_segment = &state.mz_segments[state.cur_segment];
-        if mzs_left > cur_mz_segment.len() {
-            // i think this is probably more likely an error where mz_segments have 0 length, but I
-            // don't know enough about the format above to know if we should error when we parse
-            // the initial state instead of here.
-            return Err("Invalid m/z segment".into());
-        }
-        state.cur_mz = cur_mz_segment[cur_mz_segment.len() - mzs_left];
+        // i think this is probably more likely an error where mz_segments have 0 length, but I
+        // don't know enough about the format above to know if we should error when we parse
+        // the initial state instead of here.
+        let mz_ix = sub_or_err(cur_mz_segment.len(), mzs_left, "m/z segment")?;
+        state.cur_mz = *cur_mz_segment
+            .get(mz_ix)
+            .ok_or_else(|| EtError::from("Invalid m/z segment"))?;
         state.mzs_left = mzs_left - 1;
         state.data_left = state.data_left.saturating_sub(*con);
         *consumed += *con;
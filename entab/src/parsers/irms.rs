@@ -0,0 +1,381 @@
+use alloc::borrow::Cow;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::str::from_utf8;
+
+use memchr::memrchr;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::capabilities::Capabilities;
+use crate::impl_reader;
+use crate::parsers::common::NewLine;
+use crate::parsers::datetime::{parse_or_warn, DateParser};
+use crate::parsers::tsv_inference::split;
+use crate::parsers::{extract_opt, FromSlice};
+use crate::record::{PushFields, StateMetadata, Value};
+use crate::EtError;
+
+/// The instrument software that produced an export
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum IrmsVendor {
+    /// Elementar (ionOS or IonVantage for Isoprime instruments)
+    #[default]
+    Elementar,
+    /// Sercon (Callisto)
+    Sercon,
+}
+
+impl IrmsVendor {
+    fn name(self) -> &'static str {
+        match self {
+            IrmsVendor::Elementar => "Elementar",
+            IrmsVendor::Sercon => "Sercon",
+        }
+    }
+}
+
+/// Map the names Elementar and Sercon use for sample metadata onto a common set of keys so
+/// exports from either can be combined.
+fn metadata_key(key: &str) -> String {
+    match key.trim().to_ascii_lowercase().as_str() {
+        "sample" | "sample name" | "sample id" | "identifier" | "identifier 1" => "sample".into(),
+        "weight" | "weight (mg)" | "sample weight" | "amount" | "amount (mg)" => "weight".into(),
+        "method" | "method name" | "analysis method" => "method".into(),
+        "date" | "analysis date" | "acquisition date" | "date/time" => "date".into(),
+        "position" | "tray position" | "autosampler position" => "position".into(),
+        _ => key.trim().into(),
+    }
+}
+
+/// Normalize the column names of the cycle table; beam intensities become e.g. `i44`, ratios
+/// become e.g. `r45/44`, and the column identifying the gas becomes `gas`.
+fn column_name(header: &str) -> String {
+    let header = header.trim();
+    let lower = header.to_ascii_lowercase();
+    if lower == "cycle" || lower == "cycle no" || lower == "cycle #" {
+        return "cycle".into();
+    }
+    if ["ref/sam", "ref/sample", "gas", "type", "side"].contains(&lower.as_str()) {
+        return "gas".into();
+    }
+    for prefix in ["ion ", "beam ", "m/z ", "mass "] {
+        if let Some(mass) = lower.strip_prefix(prefix) {
+            if mass.trim().parse::<u16>().is_ok() {
+                return format!("i{}", mass.trim());
+            }
+        }
+    }
+    let ratio = lower
+        .strip_prefix("ratio ")
+        .or_else(|| lower.strip_prefix("r "))
+        .unwrap_or(&lower)
+        .trim();
+    if let Some((num, den)) = ratio.split_once('/') {
+        if num.trim().parse::<u16>().is_ok() && den.trim().parse::<u16>().is_ok() {
+            return format!("r{}/{}", num.trim(), den.trim());
+        }
+    }
+    header.into()
+}
+
+/// Convert a field into a number if possible
+fn coerce(field: Cow<'_, str>) -> Value<'_> {
+    let trimmed = field.trim();
+    if let Ok(i) = trimmed.parse::<i64>() {
+        Value::Integer(i)
+    } else if let Ok(f) = trimmed.parse::<f64>() {
+        Value::Float(f)
+    } else if trimmed.is_empty() {
+        Value::Null
+    } else {
+        Value::from(field)
+    }
+}
+
+/// Split a line into (trimmed) fields
+fn split_line<'a>(
+    fields: &mut Vec<Cow<'a, str>>,
+    line: &'a [u8],
+    delim: u8,
+) -> Result<usize, EtError> {
+    let mut n_fields = split(fields, line, delim, b'"')?;
+    // exports often pad out lines with trailing delimiters
+    while n_fields > 0 && fields[n_fields - 1].trim().is_empty() {
+        n_fields -= 1;
+    }
+    Ok(n_fields)
+}
+
+/// The current state of the IRMS export reader
+#[derive(Clone, Debug, Default)]
+pub struct IrmsState {
+    vendor: IrmsVendor,
+    delim: u8,
+    headers: Vec<String>,
+    gas_col: Option<usize>,
+    metadata: BTreeMap<String, Value<'static>>,
+}
+
+impl StateMetadata for IrmsState {
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        let mut metadata = self.metadata.clone();
+        let _ = metadata.insert("vendor".into(), self.vendor.name().into());
+        metadata
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::HAS_METADATA | Capabilities::TYPED_SCHEMA
+    }
+
+    fn header(&self) -> Vec<&str> {
+        self.headers.iter().map(AsRef::as_ref).collect()
+    }
+}
+
+impl<'b: 's, 's> FromSlice<'b, 's> for IrmsState {
+    type State = IrmsVendor;
+
+    fn parse(
+        buffer: &[u8],
+        eof: bool,
+        consumed: &mut usize,
+        _state: &mut Self::State,
+    ) -> Result<bool, EtError> {
+        // read through the sample metadata until we find the start of the cycle table
+        let con = &mut 0;
+        while let Some(NewLine(line)) = extract_opt::<NewLine>(buffer, eof, con, &mut 0)? {
+            let first = line
+                .split(|c| *c == b',' || *c == b'\t' || *c == b';')
+                .next()
+                .unwrap_or_default();
+            if from_utf8(first)?
+                .trim_matches(|c: char| c == '"' || c.is_whitespace())
+                .to_ascii_lowercase()
+                .starts_with("cycle")
+            {
+                *consumed += *con;
+                return Ok(true);
+            }
+        }
+        if eof {
+            return Err("Could not find a table of cycles in the export".into());
+        }
+        Err(EtError::from("Export header ended early").incomplete())
+    }
+
+    fn get(&mut self, buffer: &'b [u8], vendor: &'s Self::State) -> Result<(), EtError> {
+        self.vendor = *vendor;
+        let con = &mut 0;
+        let mut lines = Vec::new();
+        while let Some(NewLine(line)) = extract_opt::<NewLine>(buffer, true, con, &mut 0)? {
+            lines.push(line);
+        }
+        let header = lines.pop().ok_or("No header for cycle table")?;
+        self.delim = if header.contains(&b'\t') {
+            b'\t'
+        } else if header.contains(&b';') && !header.contains(&b',') {
+            b';'
+        } else {
+            b','
+        };
+
+        let mut fields = Vec::new();
+        let mut warnings = Vec::new();
+        for line in lines {
+            let n_fields = split_line(&mut fields, line, self.delim)?;
+            if n_fields < 2 || fields[0].trim().is_empty() {
+                continue;
+            }
+            let key = metadata_key(&fields[0]);
+            let value = if key == "date" {
+                parse_or_warn(&DateParser::default(), "date", &fields[1], &mut warnings).into()
+            } else {
+                coerce(fields[1].clone()).into_owned()
+            };
+            let _ = self.metadata.insert(key, value);
+        }
+        if !warnings.is_empty() {
+            let _ = self.metadata.insert("warnings".into(), warnings.into());
+        }
+
+        let n_fields = split_line(&mut fields, header, self.delim)?;
+        self.headers = fields[..n_fields].iter().map(|h| column_name(h)).collect();
+        self.gas_col = self.headers.iter().position(|h| h == "gas");
+        Ok(())
+    }
+}
+
+/// A single cycle (of either the reference or the sample gas) from an IRMS export
+#[derive(Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct IrmsRecord<'r> {
+    #[cfg_attr(feature = "serde", serde(borrow))]
+    values: Vec<Value<'r>>,
+}
+
+impl<'b: 's, 's> FromSlice<'b, 's> for IrmsRecord<'s> {
+    type State = IrmsState;
+
+    fn parse(
+        buffer: &[u8],
+        eof: bool,
+        consumed: &mut usize,
+        _state: &mut Self::State,
+    ) -> Result<bool, EtError> {
+        // skip over blank lines
+        let mut con = 0;
+        loop {
+            let start = con;
+            let mut end = 0;
+            if !NewLine::parse(&buffer[start..], eof, &mut con, &mut end)? {
+                return Ok(false);
+            }
+            if !buffer[start..start + end]
+                .iter()
+                .all(u8::is_ascii_whitespace)
+            {
+                *consumed += con;
+                return Ok(true);
+            }
+        }
+    }
+
+    fn get(&mut self, buffer: &'b [u8], state: &'s Self::State) -> Result<(), EtError> {
+        // the buffer includes any blank lines before the cycle
+        let mut line = buffer.strip_suffix(b"\n").unwrap_or(buffer);
+        line = line.strip_suffix(b"\r").unwrap_or(line);
+        if let Some(start) = memrchr(b'\n', line) {
+            line = &line[start + 1..];
+        }
+        let mut fields = vec![Cow::Borrowed(""); state.headers.len()];
+        let n_fields = split_line(&mut fields, line, state.delim)?;
+        if n_fields > state.headers.len() {
+            return Err(format!(
+                "Cycle has {} fields, but the table only has {} columns",
+                n_fields,
+                state.headers.len()
+            )
+            .into());
+        }
+        fields.truncate(state.headers.len());
+        self.values = fields
+            .into_iter()
+            .enumerate()
+            .map(|(ix, field)| {
+                if Some(ix) == state.gas_col {
+                    match field.trim().to_ascii_lowercase().as_str() {
+                        "r" | "ref" | "reference" | "standard" => "reference".into(),
+                        "s" | "sam" | "sample" => "sample".into(),
+                        _ => field.trim().to_string().into(),
+                    }
+                } else {
+                    coerce(field)
+                }
+            })
+            .collect();
+        Ok(())
+    }
+}
+
+impl<'r> From<IrmsRecord<'r>> for Vec<Value<'r>> {
+    fn from(record: IrmsRecord<'r>) -> Self {
+        record.values
+    }
+}
+
+impl<'r> PushFields<'r> for IrmsRecord<'r> {
+    fn push_fields<E: Extend<Value<'r>>>(self, fields: &mut E) {
+        fields.extend(self.values);
+    }
+}
+
+impl_reader!(
+    /// A reader for the cycle-level text exports of Elementar (ionOS/IonVantage) and Sercon
+    /// (Callisto) isotope ratio mass specs.
+    ///
+    /// These start with lines of sample metadata (`key,value`) followed by a table of cycles
+    /// with a header line starting with `Cycle`. Sample metadata keys and table columns are
+    /// normalized so exports from different vendors can be combined (e.g. `Ion 44` becomes
+    /// `i44`, `Ratio 45/44` becomes `r45/44`, and the reference/sample column becomes `gas`).
+    IrmsReader,
+    IrmsRecord,
+    IrmsRecord<'r>,
+    IrmsState,
+    IrmsVendor
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::readers::RecordReader;
+
+    const ELEMENTAR: &[u8] = b"Sample Name,Alanine std,,\r
+Weight (mg),0.512,,\r
+Method,CN_flash,,\r
+Analysis Date,2021-03-04 10:22,,\r
+\r
+Cycle,Ref/Sam,Ion 44,Ion 45,Ion 46,Ratio 45/44,Ratio 46/44\r
+1,Ref,4.012,4.795,5.541,0.011951,0.001381\r
+1,Sam,3.987,4.762,5.512,0.011944,0.001383\r
+2,Ref,4.011,4.794,5.540,0.011952,0.001381\r
+\r
+";
+
+    const SERCON: &[u8] = b"Sample ID\tB12\nSample Weight\t1.02\n\nCycle\tGas\tBeam 28\tBeam 29\tR 29/28\n1\tReference\t2.5\t0.018\t0.0073\n1\tSample\t2.4\t0.017\t0.0072";
+
+    #[test]
+    fn test_elementar_export() -> Result<(), EtError> {
+        let mut reader = IrmsReader::new(ELEMENTAR, None)?;
+        assert_eq!(
+            reader.headers(),
+            ["cycle", "gas", "i44", "i45", "i46", "r45/44", "r46/44"]
+        );
+        let metadata = reader.metadata();
+        assert_eq!(metadata["sample"], "Alanine std".into());
+        assert_eq!(metadata["weight"], 0.512.into());
+        assert_eq!(metadata["method"], "CN_flash".into());
+        assert_eq!(
+            metadata["date"],
+            Value::from_iso_date("2021-03-04T10:22:00Z")?
+        );
+        assert_eq!(metadata["vendor"], "Elementar".into());
+
+        let record = reader.next_record()?.unwrap();
+        assert_eq!(record[0], 1.into());
+        assert_eq!(record[1], "reference".into());
+        assert_eq!(record[5], 0.011951.into());
+        assert_eq!(reader.next_record()?.unwrap()[1], "sample".into());
+        assert_eq!(reader.next_record()?.unwrap()[0], 2.into());
+        assert!(reader.next_record()?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sercon_export() -> Result<(), EtError> {
+        let mut reader = IrmsReader::new(SERCON, Some(IrmsVendor::Sercon))?;
+        assert_eq!(reader.headers(), ["cycle", "gas", "i28", "i29", "r29/28"]);
+        let metadata = reader.metadata();
+        assert_eq!(metadata["sample"], "B12".into());
+        assert_eq!(metadata["weight"], 1.02.into());
+        assert_eq!(metadata["vendor"], "Sercon".into());
+        assert!(!metadata.contains_key("warnings"));
+        let mut n_cycles = 0;
+        while let Some(record) = reader.next_record()? {
+            assert_eq!(record.len(), 5);
+            n_cycles += 1;
+        }
+        assert_eq!(n_cycles, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_bad_exports() {
+        assert!(IrmsReader::new(&b"Sample Name,test\n1,2,3\n"[..], None).is_err());
+        let mut reader = IrmsReader::new(&b"Cycle,Gas\n1,Ref,2,3\n"[..], None).unwrap();
+        assert!(reader.next_record().is_err());
+    }
+}
@@ -1,33 +1,67 @@
 use alloc::format;
 use core::any::type_name;
+use core::fmt::Display;
+use core::ops::Sub;
+use core::slice::SliceIndex;
 
 use crate::EtError;
 
+/// Reader for Applied Biosystems capillary electrophoresis formats
+pub mod abif;
 /// Readers for formats generated by Agilent instruments
 pub mod agilent;
+/// Reader for BAI indices of BAM files
+pub mod bai;
+/// Reader for Microsoft Compound File Binary (OLE2) containers
+pub mod cfb;
 /// Common low-level readers (ints, slices, etc)
 pub mod common;
+/// Helpers for parsing the many date formats instruments use
+pub mod datetime;
 /// Reader for FASTA bioinformatics format
 pub mod fasta;
 /// Reader for FASTQ bioinformatics format
 pub mod fastq;
 /// Reader for flow data
 pub mod flow;
+/// Reader for GPX (GPS exchange format) tracks, routes, and waypoints
+pub mod gpx;
+/// Readers for graph/network formats (GraphML and GML)
+pub mod graph;
 /// Reader for Inficon Hapsite MS formats
 pub mod inficon;
+/// Reader for text exports from Elementar and Sercon isotope ratio mass specs
+pub mod irms;
+/// Reader for the peptide-spectrum matches in mzTab proteomics results
+pub mod mztab;
+/// Reader for mzXML mass spectrometry format
+#[cfg(feature = "std")]
+pub mod mzxml;
+/// Reader for the search hits in pepXML proteomics results
+pub mod pepxml;
 /// Reader for PNG image format
 #[cfg(feature = "std")]
 pub mod png;
 /// Reader for BAM/SAM bioinformatics formats
 pub mod sam;
+/// Reader for delimited logs of timestamped sensor readings
+pub mod sensor_log;
+/// Decoding of text fields that may not be validly encoded
+pub mod text;
 /// Readers for Thermo formats
 pub mod thermo;
+/// Reader for TIFF (and OME-TIFF) microscopy images
+#[cfg(feature = "std")]
+pub mod tiff;
 /// Readers for tab-seperated text format
 pub mod tsv;
 /// Helpers for TSV parsing
 pub mod tsv_inference;
-// /// Reader for generic XML
-// pub mod xml;
+/// Reader for Waters MassLynx mass spectrometry format
+#[cfg(feature = "std")]
+pub mod waters;
+/// Reader for generic XML
+pub mod xml;
 
 /// The default implementation is `impl FromSlice for ()` to simplify implementations for
 /// e.g. state or other objects that don't read from the buffer.
@@ -59,33 +93,18 @@ pub trait FromSlice<'b: 's, 's>: Sized + Default {
     fn get(&mut self, _buffer: &'b [u8], _state: &'s Self::State) -> Result<(), EtError> {
         Ok(())
     }
-
-    /// Essentially the same as `extract` below, but doesn't update the state or consume any space.
-    ///
-    /// Use only for simple types with defined sizes like u8, i32, &[u8], etc. Using this with more
-    /// complex types that rely upon updating `state` in between reads will cause bad and confusing
-    /// things to happen!
-    ///
-    /// # Errors
-    /// If parsing fails, an error will be returned.
-    fn extract(buffer: &'b [u8], state: &'s Self::State) -> Result<Self, EtError>
-    where
-        Self::State: 'static,
-        Self: 's,
-    {
-        let mut val = Self::default();
-        Self::get(&mut val, buffer, state)?;
-        Ok(val)
-    }
 }
 
 /// Pull a `T` out of the slice, updating state appropriately and incrementing `consumed` to
 /// account for bytes used.
 ///
+/// State is passed mutably because `parse` may update it; for values like numbers whose state
+/// never changes, `extract_with` takes it immutably instead.
+///
 /// # Errors
 /// If an error extracting a value occured or if slice needs to be extended, return `EtError`.
 #[inline]
-pub(crate) fn extract<'b: 's, 's, T>(
+pub fn extract<'b: 's, 's, T>(
     buffer: &'b [u8],
     consumed: &mut usize,
     state: &'s mut <T as FromSlice<'b, 's>>::State,
@@ -103,13 +122,51 @@ where
     }
 }
 
+/// State that `parse` never updates (e.g. the `Endian` of a number or the `()` of types that
+/// don't need any state), so values that use it can be read with `extract_with`.
+pub trait ConstState: Copy {}
+
+impl ConstState for () {}
+
+impl ConstState for Endian {}
+
+/// Pull a `T` out of the slice like `extract`, but for types whose state never changes, so it
+/// can be passed immutably, e.g. `extract_with::<u32>(buffer, &mut 4, &Endian::Little)` to read
+/// the u32 at byte 4.
+///
+/// # Errors
+/// If an error extracting a value occured or if slice needs to be extended, return `EtError`.
+#[inline]
+pub fn extract_with<'b: 's, 's, T>(
+    buffer: &'b [u8],
+    consumed: &mut usize,
+    state: &'s <T as FromSlice<'b, 's>>::State,
+) -> Result<T, EtError>
+where
+    T: FromSlice<'b, 's> + Default,
+    <T as FromSlice<'b, 's>>::State: ConstState,
+{
+    let start = *consumed;
+    let remaining = slice_or_incomplete(buffer, start..)?;
+    if !T::parse(remaining, false, consumed, &mut { *state })? {
+        return Err(format!(
+            "Tried to extract {}, but parser indicated no more.",
+            type_name::<T>()
+        )
+        .into());
+    }
+    let mut value = T::default();
+    T::get(&mut value, &buffer[start..*consumed], state)?;
+    Ok(value)
+}
+
 /// Pull a `T` out of the slice, updating state appropriately and incrementing `consumed` to
 /// account for bytes used.
 ///
 /// # Errors
 /// If an error extracting a value occured or if slice needs to be extended, return `EtError`.
 #[inline]
-pub(crate) fn extract_opt<'b: 's, 's, T>(
+pub fn extract_opt<'b: 's, 's, T>(
     buffer: &'b [u8],
     eof: bool,
     consumed: &mut usize,
@@ -119,7 +176,8 @@ where
     T: FromSlice<'b, 's> + Default,
 {
     let start = *consumed;
-    if !T::parse(&buffer[start..], eof, consumed, state)? {
+    let remaining = slice_or_incomplete(buffer, start..)?;
+    if !T::parse(remaining, eof, consumed, state)? {
         return Ok(None);
     }
     let mut record = T::default();
@@ -127,6 +185,38 @@ where
     Ok(Some(record))
 }
 
+/// Subtract `b` from `a` where either was read out of a file (e.g. a length minus the size of
+/// a header), returning an error about the invalid `what` instead of underflowing if a corrupt
+/// file makes `b` larger.
+///
+/// # Errors
+/// If `b` is larger than `a`, an error is returned.
+#[inline]
+pub fn sub_or_err<T>(a: T, b: T, what: &str) -> Result<T, EtError>
+where
+    T: Copy + Display + PartialOrd + Sub<Output = T>,
+{
+    if b > a {
+        return Err(format!("Invalid {} ({} is less than {})", what, a, b).into());
+    }
+    Ok(a - b)
+}
+
+/// Take `range` out of the buffer, returning an incomplete error (so the buffer will be refilled
+/// and the parse retried) instead of panicking if the buffer doesn't extend that far.
+///
+/// # Errors
+/// If `range` isn't inside `buffer` (or starts after it ends), an incomplete error is returned.
+#[inline]
+pub fn slice_or_incomplete<I>(buffer: &[u8], range: I) -> Result<&I::Output, EtError>
+where
+    I: SliceIndex<[u8]>,
+{
+    buffer
+        .get(range)
+        .ok_or_else(|| EtError::from("Tried to read past the end of the data").incomplete())
+}
+
 /// The endianness of a number used to extract such a number.
 #[derive(Clone, Copy, Debug, Default)]
 pub enum Endian {
@@ -136,3 +226,48 @@ pub enum Endian {
     #[default]
     Little,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checked_helpers() -> Result<(), EtError> {
+        assert_eq!(sub_or_err(10usize, 4, "record length")?, 6);
+        assert_eq!(sub_or_err(4u32, 4, "record length")?, 0);
+        let err = sub_or_err(4usize, 10, "record length").unwrap_err();
+        assert!(err.msg.contains("record length"));
+        assert!(!err.incomplete);
+
+        let buf = b"abcdef";
+        assert_eq!(slice_or_incomplete(buf, 1..3)?, b"bc");
+        assert_eq!(slice_or_incomplete(buf, 6..)?, b"");
+        assert!(slice_or_incomplete(buf, 4..8).unwrap_err().incomplete);
+        assert!(slice_or_incomplete(buf, 7..).unwrap_err().incomplete);
+        // a corrupt length can put the end of a range before its start
+        #[allow(clippy::reversed_empty_ranges)]
+        let err = slice_or_incomplete(buf, 3..2).unwrap_err();
+        assert!(err.incomplete);
+        Ok(())
+    }
+
+    #[test]
+    fn test_extract_with() -> Result<(), EtError> {
+        let buf = b"\x00\x00\x00\x01\x02";
+        let con = &mut 0;
+        assert_eq!(extract_with::<u32>(buf, con, &Endian::Big)?, 1);
+        assert_eq!(*con, 4);
+        assert_eq!(extract_with::<u8>(buf, con, &Endian::Little)?, 2);
+        // the same as passing the state mutably
+        assert_eq!(
+            extract_with::<u32>(buf, &mut 1, &Endian::Little)?,
+            extract::<u32>(buf, &mut 1, &mut Endian::Little)?
+        );
+        assert!(
+            extract_with::<u32>(buf, con, &Endian::Big)
+                .unwrap_err()
+                .incomplete
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,247 @@
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::str::from_utf8;
+
+use memchr::memrchr;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::capabilities::Capabilities;
+use crate::impl_reader;
+use crate::parsers::common::NewLine;
+use crate::parsers::{extract_opt, FromSlice};
+use crate::record::{PushFields, StateMetadata, Value};
+use crate::EtError;
+
+/// Convert an mzTab field into a number if possible (mzTab writes missing values as `null`)
+fn coerce(field: &str) -> Value<'_> {
+    let trimmed = field.trim();
+    if let Ok(i) = trimmed.parse::<i64>() {
+        Value::Integer(i)
+    } else if let Ok(f) = trimmed.parse::<f64>() {
+        Value::Float(f)
+    } else if trimmed.is_empty() || trimmed == "null" {
+        Value::Null
+    } else {
+        Value::from(trimmed)
+    }
+}
+
+/// The current state of the mzTab reader
+#[derive(Clone, Debug, Default)]
+pub struct MztabState {
+    headers: Vec<String>,
+    metadata: BTreeMap<String, Value<'static>>,
+}
+
+impl StateMetadata for MztabState {
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        self.metadata.clone()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::HAS_METADATA | Capabilities::TYPED_SCHEMA
+    }
+
+    fn header(&self) -> Vec<&str> {
+        self.headers.iter().map(AsRef::as_ref).collect()
+    }
+}
+
+impl<'b: 's, 's> FromSlice<'b, 's> for MztabState {
+    type State = ();
+
+    fn parse(
+        buffer: &[u8],
+        eof: bool,
+        consumed: &mut usize,
+        _state: &mut Self::State,
+    ) -> Result<bool, EtError> {
+        // read through the metadata (and the protein and peptide sections) until we find the
+        // header for the PSM section
+        let con = &mut 0;
+        while let Some(NewLine(line)) = extract_opt::<NewLine>(buffer, eof, con, &mut 0)? {
+            if line.starts_with(b"PSH\t") {
+                *consumed += *con;
+                return Ok(true);
+            }
+        }
+        if eof {
+            return Err("Could not find a PSM section (PSH line) in the mzTab file".into());
+        }
+        Err(EtError::from("mzTab header ended early").incomplete())
+    }
+
+    fn get(&mut self, buffer: &'b [u8], _state: &'s Self::State) -> Result<(), EtError> {
+        let con = &mut 0;
+        let mut header = None;
+        while let Some(NewLine(line)) = extract_opt::<NewLine>(buffer, true, con, &mut 0)? {
+            let line = from_utf8(line)?;
+            let mut fields = line.split('\t');
+            match fields.next() {
+                Some("MTD") => {
+                    if let (Some(key), Some(value)) = (fields.next(), fields.next()) {
+                        let value = coerce(value).into_owned();
+                        drop(self.metadata.insert(key.trim().to_string(), value));
+                    }
+                }
+                Some("PSH") => header = Some(fields),
+                // comments, protein and peptide sections
+                _ => {}
+            }
+        }
+        let header = header.ok_or("No header for PSM section")?;
+        self.headers = header.map(|h| h.trim().to_string()).collect();
+        while self.headers.last().is_some_and(String::is_empty) {
+            drop(self.headers.pop());
+        }
+        Ok(())
+    }
+}
+
+/// A single peptide-spectrum match from an mzTab file
+#[derive(Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MztabRecord<'r> {
+    #[cfg_attr(feature = "serde", serde(borrow))]
+    values: Vec<Value<'r>>,
+}
+
+impl<'b: 's, 's> FromSlice<'b, 's> for MztabRecord<'s> {
+    type State = MztabState;
+
+    fn parse(
+        buffer: &[u8],
+        eof: bool,
+        consumed: &mut usize,
+        _state: &mut Self::State,
+    ) -> Result<bool, EtError> {
+        // skip over comments, blank lines, and any sections after the PSMs
+        let mut con = 0;
+        loop {
+            let start = con;
+            let mut end = 0;
+            if !NewLine::parse(&buffer[start..], eof, &mut con, &mut end)? {
+                return Ok(false);
+            }
+            if buffer[start..start + end].starts_with(b"PSM\t") {
+                *consumed += con;
+                return Ok(true);
+            }
+        }
+    }
+
+    fn get(&mut self, buffer: &'b [u8], state: &'s Self::State) -> Result<(), EtError> {
+        // the buffer includes any lines skipped before the PSM
+        let mut line = buffer.strip_suffix(b"\n").unwrap_or(buffer);
+        line = line.strip_suffix(b"\r").unwrap_or(line);
+        if let Some(start) = memrchr(b'\n', line) {
+            line = &line[start + 1..];
+        }
+        let line = from_utf8(&line[4..])?;
+        self.values.clear();
+        for field in line.split('\t') {
+            self.values.push(coerce(field));
+        }
+        while self.values.len() > state.headers.len() && self.values.last() == Some(&Value::Null) {
+            drop(self.values.pop());
+        }
+        if self.values.len() > state.headers.len() {
+            return Err(format!(
+                "PSM has {} fields, but the PSH line only has {} columns",
+                self.values.len(),
+                state.headers.len()
+            )
+            .into());
+        }
+        self.values.resize(state.headers.len(), Value::Null);
+        Ok(())
+    }
+}
+
+impl<'r> From<MztabRecord<'r>> for Vec<Value<'r>> {
+    fn from(record: MztabRecord<'r>) -> Self {
+        record.values
+    }
+}
+
+impl<'r> PushFields<'r> for MztabRecord<'r> {
+    fn push_fields<E: Extend<Value<'r>>>(self, fields: &mut E) {
+        fields.extend(self.values);
+    }
+}
+
+impl_reader!(
+    /// A reader for the peptide-spectrum matches (the `PSM` lines) in an mzTab file.
+    ///
+    /// The columns are the ones in the `PSH` line (e.g. `sequence`, `PSM_ID`,
+    /// `search_engine_score[1]`) and the `MTD` lines are returned as metadata; the protein and
+    /// peptide sections are skipped.
+    MztabReader,
+    MztabRecord,
+    MztabRecord<'r>,
+    MztabState,
+    ()
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::readers::RecordReader;
+
+    const TEST_MZTAB: &[u8] = b"MTD\tmzTab-version\t1.0.0\n\
+MTD\tmzTab-mode\tSummary\n\
+MTD\tpsm_search_engine_score[1]\t[MS, MS:1001171, Mascot:score, ]\n\
+COM\tthe protein section\n\
+PRH\taccession\tdescription\n\
+PRT\tP02768\tSerum albumin\n\
+\n\
+PSH\tsequence\tPSM_ID\taccession\tunique\tcharge\texp_mass_to_charge\tsearch_engine_score[1]\tspectra_ref\n\
+PSM\tKVPQVSTPTLVEVSR\t1\tP02768\t1\t2\t820.47\t46.2\tms_run[1]:scan=1296\n\
+COM\ta PSM without a score\n\
+PSM\tLVNEVTEFAK\t2\tP02768\tnull\t2\t575.31\tnull\tms_run[1]:scan=1314\n";
+
+    #[test]
+    fn test_mztab_reader() -> Result<(), EtError> {
+        let mut reader = MztabReader::new(TEST_MZTAB, None)?;
+        assert_eq!(
+            reader.headers(),
+            [
+                "sequence",
+                "PSM_ID",
+                "accession",
+                "unique",
+                "charge",
+                "exp_mass_to_charge",
+                "search_engine_score[1]",
+                "spectra_ref"
+            ]
+        );
+        let metadata = reader.metadata();
+        assert_eq!(metadata["mzTab-version"], "1.0.0".into());
+        assert_eq!(
+            metadata["psm_search_engine_score[1]"],
+            "[MS, MS:1001171, Mascot:score, ]".into()
+        );
+
+        let record = reader.next_record()?.unwrap();
+        assert_eq!(record[0], "KVPQVSTPTLVEVSR".into());
+        assert_eq!(record[4], 2.into());
+        assert_eq!(record[6], 46.2.into());
+        assert_eq!(record[7], "ms_run[1]:scan=1296".into());
+        let record = reader.next_record()?.unwrap();
+        assert_eq!(record[3], Value::Null);
+        assert_eq!(record[6], Value::Null);
+        assert!(reader.next_record()?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_bad_mztab() {
+        assert!(MztabReader::new(&b"MTD\tmzTab-version\t1.0.0\n"[..], None).is_err());
+        let mut reader = MztabReader::new(&b"PSH\tsequence\nPSM\tPEPTIDE\t1\n"[..], None).unwrap();
+        assert!(reader.next_record().is_err());
+    }
+}
@@ -0,0 +1,467 @@
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use std::io::Read;
+
+use flate2::read::ZlibDecoder;
+use memchr::memchr;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::capabilities::Capabilities;
+use crate::parsers::xml::{next_tag, XmlTag, XmlTagType, XmlText};
+use crate::parsers::{extract, extract_with, Endian, FromSlice};
+use crate::record::{StateMetadata, Value};
+use crate::EtError;
+use crate::{impl_reader, impl_record};
+
+/// Convert an `xs:duration` (e.g. `PT12.5S`, the format mzXML stores times in) into minutes.
+///
+/// Plain numbers (which some older converters wrote) are read as seconds.
+fn duration_minutes(text: &str) -> Result<f64, EtError> {
+    let text = text.trim();
+    if let Ok(seconds) = text.parse::<f64>() {
+        return Ok(seconds / 60.);
+    }
+    let bad_duration = || EtError::from(format!("Invalid mzXML duration {}", text));
+    let (sign, unsigned) = match text.strip_prefix('-') {
+        Some(rest) => (-1., rest),
+        None => (1., text),
+    };
+    let rest = unsigned.strip_prefix('P').ok_or_else(bad_duration)?;
+    let mut minutes = 0.;
+    let mut n_parts = 0;
+    let mut in_time = false;
+    let mut number_start = 0;
+    for (ix, c) in rest.char_indices() {
+        if c == 'T' && !in_time && number_start == ix {
+            in_time = true;
+            number_start = ix + 1;
+            continue;
+        }
+        if !c.is_ascii_alphabetic() {
+            continue;
+        }
+        let scale = match (in_time, c) {
+            (false, 'D') => 1440.,
+            (true, 'H') => 60.,
+            (true, 'M') => 1.,
+            (true, 'S') => 1. / 60.,
+            // years and months don't have a fixed length (and runs aren't that long)
+            _ => return Err(bad_duration()),
+        };
+        let value: f64 = rest[number_start..ix].parse().map_err(|_| bad_duration())?;
+        minutes += value * scale;
+        n_parts += 1;
+        number_start = ix + 1;
+    }
+    if number_start != rest.len() || n_parts == 0 {
+        return Err(bad_duration());
+    }
+    Ok(sign * minutes)
+}
+
+/// Decode base64 `text`, ignoring any whitespace in it.
+fn decode_base64(text: &str) -> Result<Vec<u8>, EtError> {
+    let mut data = Vec::with_capacity(text.len() / 4 * 3);
+    let mut bits: u32 = 0;
+    let mut n_bits = 0;
+    for &c in text.as_bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => break,
+            c if c.is_ascii_whitespace() => continue,
+            _ => return Err("Invalid character in mzXML base64 data".into()),
+        };
+        bits = (bits << 6) | u32::from(value);
+        n_bits += 6;
+        if n_bits >= 8 {
+            n_bits -= 8;
+            data.push(((bits >> n_bits) & 0xFF) as u8);
+            bits &= (1 << n_bits) - 1;
+        }
+    }
+    Ok(data)
+}
+
+/// Decode the m/z and intensity pairs out of the text of a `<peaks>` element, using the
+/// encoding described by the element's attributes.
+fn decode_peaks(tag: &XmlTag, text: &str, peaks: &mut Vec<(f64, f64)>) -> Result<(), EtError> {
+    peaks.clear();
+    let width = match tag.attribute("precision").unwrap_or("32") {
+        "32" => 4,
+        "64" => 8,
+        x => return Err(format!("Unknown mzXML peak precision {}", x).into()),
+    };
+    // `pairOrder` is from mzXML 2 and `contentType` replaced it in mzXML 3
+    let order = tag
+        .attribute("contentType")
+        .or_else(|| tag.attribute("pairOrder"))
+        .unwrap_or("m/z-int");
+    if order != "m/z-int" {
+        return Err(format!("mzXML peaks with {} content are not supported", order).into());
+    }
+    // the spec only allows "network" (big-endian) order, but be lenient
+    let endian = match tag.attribute("byteOrder").unwrap_or("network") {
+        "network" | "big" => Endian::Big,
+        "little" => Endian::Little,
+        x => return Err(format!("Unknown mzXML byte order {}", x).into()),
+    };
+
+    let mut data = decode_base64(text)?;
+    match tag.attribute("compressionType").unwrap_or("none") {
+        "none" => {}
+        "zlib" => {
+            let mut decompressed = Vec::new();
+            let _ = ZlibDecoder::new(&data[..]).read_to_end(&mut decompressed)?;
+            data = decompressed;
+        }
+        x => return Err(format!("Unknown mzXML peak compression {}", x).into()),
+    }
+    if data.len() % (2 * width) != 0 {
+        return Err("mzXML peaks aren't a whole number of m/z-intensity pairs".into());
+    }
+    peaks.reserve(data.len() / (2 * width));
+    for pair in data.chunks_exact(2 * width) {
+        let con = &mut 0;
+        let (mz, intensity) = if width == 4 {
+            (
+                f64::from(extract::<f32>(pair, con, &mut endian.clone())?),
+                f64::from(extract::<f32>(pair, con, &mut endian.clone())?),
+            )
+        } else {
+            (
+                extract::<f64>(pair, con, &mut endian.clone())?,
+                extract::<f64>(pair, con, &mut endian.clone())?,
+            )
+        };
+        peaks.push((mz, intensity));
+    }
+    Ok(())
+}
+
+/// The current state of the mzXML reader
+#[derive(Clone, Debug, Default)]
+pub struct MzXmlState {
+    metadata: BTreeMap<String, Value<'static>>,
+    /// The retention time of the current scan (in minutes)
+    cur_time: Option<f64>,
+    /// The number of the current scan
+    cur_scan: Option<u64>,
+    /// The MS level of the current scan (e.g. 2 for MS/MS)
+    cur_ms_level: Option<u8>,
+    /// The decoded peaks of the current scan
+    peaks: Vec<(f64, f64)>,
+    /// The index of the next peak to return from `peaks`
+    peak_ix: usize,
+}
+
+impl StateMetadata for MzXmlState {
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        self.metadata.clone()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::HAS_METADATA | Capabilities::TYPED_SCHEMA
+    }
+
+    fn header(&self) -> Vec<&str> {
+        vec!["time", "mz", "intensity", "ms_level", "scan"]
+    }
+}
+
+impl<'b: 's, 's> FromSlice<'b, 's> for MzXmlState {
+    type State = ();
+
+    fn parse(
+        buffer: &[u8],
+        eof: bool,
+        consumed: &mut usize,
+        _state: &mut Self::State,
+    ) -> Result<bool, EtError> {
+        let con = &mut 0;
+        loop {
+            match next_tag(buffer, eof, con)? {
+                Some(tag) if tag.id == "msRun" && tag.tag_type == XmlTagType::Open => break,
+                Some(_) => {}
+                None => return Err("No <msRun> element found".into()),
+            }
+        }
+        // include everything up to the first scan so the instrument and source file can be read
+        // out of it in `get`
+        loop {
+            let start = *con;
+            match next_tag(buffer, eof, con)? {
+                Some(tag) if tag.id == "scan" || tag.id == "msRun" => {
+                    *con = start;
+                    break;
+                }
+                Some(_) => {}
+                None => return Err("mzXML file ended before its first scan".into()),
+            }
+        }
+        *consumed += *con;
+        Ok(true)
+    }
+
+    fn get(&mut self, buffer: &'b [u8], _state: &'s Self::State) -> Result<(), EtError> {
+        let con = &mut 0;
+        while let Some(tag) = next_tag(buffer, true, con)? {
+            if tag.tag_type == XmlTagType::Close || tag.tag_type == XmlTagType::Declaration {
+                continue;
+            }
+            let mut values: Vec<(&str, Value<'static>)> = Vec::new();
+            match tag.id {
+                "msRun" => {
+                    if let Some(count) = tag.attribute("scanCount") {
+                        let count: u64 = count.trim().parse()?;
+                        values.push(("scan_count", count.into()));
+                    }
+                    for (attr, key) in [("startTime", "start_time"), ("endTime", "end_time")] {
+                        if let Some(time) = tag.attribute(attr) {
+                            values.push((key, duration_minutes(time)?.into()));
+                        }
+                    }
+                }
+                // only the first source file (the raw file the others were converted from)
+                "parentFile" if !self.metadata.contains_key("parent_file") => {
+                    if let Some(name) = tag.attribute("fileName") {
+                        values.push(("parent_file", name.to_string().into()));
+                    }
+                }
+                "msManufacturer" | "msModel" => {
+                    let key = if tag.id == "msModel" {
+                        "instrument"
+                    } else {
+                        "manufacturer"
+                    };
+                    if let Some(value) = tag.attribute("value") {
+                        values.push((key, value.to_string().into()));
+                    }
+                }
+                _ => {}
+            }
+            for (key, value) in values {
+                drop(self.metadata.insert(key.to_string(), value));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A single m/z and intensity from a scan in an mzXML file
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MzXmlRecord {
+    /// The retention time of the scan (in minutes)
+    pub time: Option<f64>,
+    /// The mass-to-charge ratio
+    pub mz: f64,
+    /// The intensity at that m/z
+    pub intensity: f64,
+    /// The MS level of the scan (e.g. 1 for survey scans and 2 for MS/MS scans)
+    pub ms_level: Option<u8>,
+    /// The number of the scan
+    pub scan: Option<u64>,
+}
+
+impl_record!(MzXmlRecord: time, mz, intensity, ms_level, scan);
+
+impl<'b: 's, 's> FromSlice<'b, 's> for MzXmlRecord {
+    type State = MzXmlState;
+
+    fn parse(
+        buffer: &[u8],
+        eof: bool,
+        consumed: &mut usize,
+        state: &mut Self::State,
+    ) -> Result<bool, EtError> {
+        // the rest of the current scan's peaks are already decoded
+        if state.peak_ix < state.peaks.len() {
+            state.peak_ix += 1;
+            return Ok(true);
+        }
+        // everything before `skipped` has already been added to `consumed` so the scans (and
+        // their peaks) don't need to be held in the buffer after they've been read
+        let mut skipped = 0;
+        loop {
+            let start = match memchr(b'<', &buffer[skipped..]) {
+                Some(s) => skipped + s,
+                None if eof => return Ok(false),
+                None => return Err(EtError::from("Could not find next mzXML tag").incomplete()),
+            };
+            let con = &mut start.clone();
+            let tag: XmlTag = extract_with(buffer, con, &())?;
+            match (tag.id, tag.tag_type) {
+                ("msRun", XmlTagType::Close) => return Ok(false),
+                // MS/MS scans are nested inside the scans they fragmented, so only the start of
+                // each scan is used
+                ("scan", XmlTagType::Open | XmlTagType::SelfClose) => {
+                    state.cur_time = tag
+                        .attribute("retentionTime")
+                        .map(duration_minutes)
+                        .transpose()?;
+                    state.cur_scan = tag.attribute("num").map(|n| n.trim().parse()).transpose()?;
+                    state.cur_ms_level = tag
+                        .attribute("msLevel")
+                        .map(|l| l.trim().parse())
+                        .transpose()?;
+                }
+                ("peaks", XmlTagType::Open) => {
+                    let XmlText(text) = extract_with(buffer, con, &())?;
+                    match next_tag(buffer, eof, con)? {
+                        Some(close) if close.id == "peaks" => {}
+                        _ => return Err("mzXML <peaks> element was not closed".into()),
+                    }
+                    decode_peaks(&tag, &text, &mut state.peaks)?;
+                    *consumed += *con - skipped;
+                    if !state.peaks.is_empty() {
+                        state.peak_ix = 1;
+                        return Ok(true);
+                    }
+                    state.peak_ix = 0;
+                    skipped = *con;
+                    continue;
+                }
+                _ => {}
+            }
+            *consumed += *con - skipped;
+            skipped = *con;
+        }
+    }
+
+    fn get(&mut self, _buffer: &'b [u8], state: &'s Self::State) -> Result<(), EtError> {
+        let (mz, intensity) = state
+            .peaks
+            .get(state.peak_ix.wrapping_sub(1))
+            .copied()
+            .ok_or("mzXML peak was read out of order")?;
+        self.time = state.cur_time;
+        self.scan = state.cur_scan;
+        self.ms_level = state.cur_ms_level;
+        self.mz = mz;
+        self.intensity = intensity;
+        Ok(())
+    }
+}
+
+impl_reader!(
+    /// A reader for the peaks in each scan of an mzXML file
+    MzXmlReader,
+    MzXmlRecord,
+    MzXmlRecord,
+    MzXmlState,
+    ()
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::ReadBuffer;
+    use crate::readers::RecordReader;
+
+    static TEST_MZXML: &[u8] = br#"<?xml version="1.0" encoding="ISO-8859-1"?>
+<mzXML xmlns="http://sashimi.sourceforge.net/schema_revision/mzXML_3.2">
+  <msRun scanCount="3" startTime="PT0.5S" endTime="PT1M30S">
+    <parentFile fileName="file://C:/data/run1.RAW" fileType="RAWData" fileSha1="0"/>
+    <msInstrument msInstrumentID="1">
+      <msManufacturer category="msManufacturer" value="Thermo Scientific"/>
+      <msModel category="msModel" value="Q Exactive"/>
+    </msInstrument>
+    <scan num="1" msLevel="1" peaksCount="2" retentionTime="PT30S">
+      <peaks precision="32" byteOrder="network" contentType="m/z-int" compressionType="none" compressedLen="0">QskAAER6AABDSEAAQkgAAA==</peaks>
+      <scan num="2" msLevel="2" peaksCount="2" retentionTime="PT1M">
+        <precursorMz precursorIntensity="1000">100.5</precursorMz>
+        <peaks precision="64" byteOrder="network" contentType="m/z-int" compressionType="zlib" compressedLen="24">eJxzSDrCAAIOJgwQuugghBaD8AFNeAOk</peaks>
+      </scan>
+    </scan>
+    <scan num="3" msLevel="1" peaksCount="0" retentionTime="PT90S">
+      <peaks precision="32" byteOrder="network" pairOrder="m/z-int"></peaks>
+    </scan>
+  </msRun>
+  <index name="scan"><offset id="1">310</offset></index>
+</mzXML>
+"#;
+
+    #[test]
+    fn test_mzxml_reader() -> Result<(), EtError> {
+        let mut reader = MzXmlReader::new(TEST_MZXML, None)?;
+        let metadata = reader.metadata();
+        assert_eq!(metadata["scan_count"], Value::Integer(3));
+        assert_eq!(metadata["end_time"], 1.5.into());
+        assert_eq!(metadata["parent_file"], "file://C:/data/run1.RAW".into());
+        assert_eq!(metadata["instrument"], "Q Exactive".into());
+        assert_eq!(metadata["manufacturer"], "Thermo Scientific".into());
+        assert_eq!(
+            reader.headers(),
+            ["time", "mz", "intensity", "ms_level", "scan"]
+        );
+
+        // the MS/MS peaks can be told apart from the survey scan's by their level and scan
+        let expected = [
+            (0.5, 100.5, 1000., 1, 1),
+            (0.5, 200.25, 50., 1, 1),
+            (1., 150.125, 20., 2, 2),
+            (1., 300.0625, 5.5, 2, 2),
+        ];
+        for (time, mz, intensity, ms_level, scan) in expected {
+            let record = reader.next()?.expect("a peak");
+            assert_eq!(record.time, Some(time));
+            assert_eq!(record.mz, mz);
+            assert_eq!(record.intensity, intensity);
+            assert_eq!(record.ms_level, Some(ms_level));
+            assert_eq!(record.scan, Some(scan));
+        }
+        assert!(reader.next()?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_mzxml_from_stream() -> Result<(), EtError> {
+        // the peaks are split between reads of the buffer
+        let rb = ReadBuffer::from_reader(Box::new(std::io::Cursor::new(TEST_MZXML)), Some(64))?;
+        let mut reader = MzXmlReader::new(rb, None)?;
+        let mut n_peaks = 0;
+        while reader.next()?.is_some() {
+            n_peaks += 1;
+        }
+        assert_eq!(n_peaks, 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_durations() -> Result<(), EtError> {
+        assert_eq!(duration_minutes("PT90S")?, 1.5);
+        assert_eq!(duration_minutes("PT1H2M30S")?, 62.5);
+        assert_eq!(duration_minutes("P1DT0.5M")?, 1440.5);
+        assert_eq!(duration_minutes("-PT60S")?, -1.);
+        assert_eq!(duration_minutes("30")?, 0.5);
+        for bad in ["", "P", "PT", "PT5", "P1M", "T5S", "PTxS"] {
+            assert!(duration_minutes(bad).is_err(), "{}", bad);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_mzxml_bad_peaks() {
+        for peaks in [
+            r#"<peaks precision="16">AAAA</peaks>"#,
+            r#"<peaks contentType="m/z ruler">AAAA</peaks>"#,
+            r#"<peaks compressionType="lzma">AAAA</peaks>"#,
+            r#"<peaks>AAA!</peaks>"#,
+            // only half of a pair
+            r#"<peaks>QskAAA==</peaks>"#,
+        ] {
+            let data = format!("<mzXML><msRun><scan>{}</scan></msRun></mzXML>", peaks);
+            let mut reader = MzXmlReader::new(data.as_bytes(), None).unwrap();
+            assert!(reader.next().is_err(), "{}", peaks);
+        }
+        assert!(MzXmlReader::new(&b"<mzML></mzML>"[..], None).is_err());
+    }
+}
@@ -0,0 +1,408 @@
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use memchr::memchr;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::capabilities::Capabilities;
+use crate::impl_reader;
+use crate::parsers::xml::{next_tag, skip_element, XmlTag, XmlTagType};
+use crate::parsers::{extract_with, FromSlice};
+use crate::record::{PushFields, StateMetadata, Value};
+use crate::EtError;
+
+/// The columns every pepXML record has (before the score columns)
+const FIXED_HEADERS: [&str; 12] = [
+    "spectrum",
+    "start_scan",
+    "charge",
+    "precursor_neutral_mass",
+    "retention_time_sec",
+    "hit_rank",
+    "peptide",
+    "modified_peptide",
+    "protein",
+    "calc_neutral_pep_mass",
+    "massdiff",
+    "num_missed_cleavages",
+];
+
+/// Convert an attribute into a number if possible
+fn coerce(value: Option<&str>) -> Value<'static> {
+    let value = match value.map(str::trim) {
+        Some(v) if !v.is_empty() => v,
+        _ => return Value::Null,
+    };
+    if let Ok(i) = value.parse::<i64>() {
+        Value::Integer(i)
+    } else if let Ok(f) = value.parse::<f64>() {
+        Value::Float(f)
+    } else {
+        Value::from(value.to_string())
+    }
+}
+
+/// The names of the scores found while reading the pepXML header
+#[derive(Clone, Debug, Default)]
+pub struct PepXmlParams {
+    score_names: Vec<String>,
+    has_probability: bool,
+}
+
+/// The current state of the pepXML reader
+#[derive(Clone, Debug, Default)]
+pub struct PepXmlState {
+    metadata: BTreeMap<String, Value<'static>>,
+    score_names: Vec<String>,
+    has_probability: bool,
+    /// The values from the `<spectrum_query>` the current hit is in
+    query: Vec<Value<'static>>,
+    /// The length of the `<search_hit>` for the current record (the end of the buffer passed
+    /// to `get`)
+    record_len: usize,
+}
+
+impl StateMetadata for PepXmlState {
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        self.metadata.clone()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::HAS_METADATA | Capabilities::TYPED_SCHEMA
+    }
+
+    fn header(&self) -> Vec<&str> {
+        let mut headers = FIXED_HEADERS.to_vec();
+        headers.extend(self.score_names.iter().map(String::as_str));
+        if self.has_probability {
+            headers.push("probability");
+        }
+        headers
+    }
+}
+
+impl<'b: 's, 's> FromSlice<'b, 's> for PepXmlState {
+    type State = PepXmlParams;
+
+    fn parse(
+        buffer: &[u8],
+        eof: bool,
+        consumed: &mut usize,
+        params: &mut Self::State,
+    ) -> Result<bool, EtError> {
+        let con = &mut 0;
+        loop {
+            match next_tag(buffer, eof, con)? {
+                Some(tag)
+                    if tag.id == "msms_pipeline_analysis" && tag.tag_type == XmlTagType::Open =>
+                {
+                    break
+                }
+                Some(_) => {}
+                None => return Err("No <msms_pipeline_analysis> element found".into()),
+            }
+        }
+        // the header is everything before the first spectrum (or the end of the file if there
+        // aren't any)
+        loop {
+            let start = *con;
+            match next_tag(buffer, eof, con)? {
+                Some(tag) if tag.id == "spectrum_query" => {
+                    *con = start;
+                    break;
+                }
+                Some(_) => {}
+                None => break,
+            }
+        }
+
+        // the score columns are the ones reported for the first hit
+        let mut score_names = Vec::new();
+        let mut has_probability = false;
+        let peek = &mut con.clone();
+        while let Some(tag) = next_tag(buffer, eof, peek)? {
+            match (tag.id, tag.tag_type) {
+                ("search_score", _) => {
+                    if let Some(name) = tag.attribute("name") {
+                        score_names.push(name.to_string());
+                    }
+                }
+                ("peptideprophet_result", XmlTagType::Open | XmlTagType::SelfClose) => {
+                    has_probability = true;
+                }
+                ("search_hit", XmlTagType::Close | XmlTagType::SelfClose) => break,
+                _ => {}
+            }
+        }
+        params.score_names = score_names;
+        params.has_probability = has_probability;
+        *consumed += *con;
+        Ok(true)
+    }
+
+    fn get(&mut self, buffer: &'b [u8], params: &'s Self::State) -> Result<(), EtError> {
+        self.score_names = params.score_names.clone();
+        self.has_probability = params.has_probability;
+        let con = &mut 0;
+        while let Some(tag) = next_tag(buffer, true, con)? {
+            let keys: &[(&str, &str)] = match tag.id {
+                "msms_run_summary" => &[("base_name", "base_name"), ("raw_data", "raw_data")],
+                "search_summary" => &[
+                    ("search_engine", "search_engine"),
+                    ("search_engine_version", "search_engine_version"),
+                    ("precursor_mass_type", "precursor_mass_type"),
+                ],
+                "sample_enzyme" => &[("name", "enzyme")],
+                "search_database" => &[("local_path", "database")],
+                _ => continue,
+            };
+            for (attr, key) in keys {
+                if let Some(value) = tag.attribute(attr) {
+                    let _ = self
+                        .metadata
+                        .entry(key.to_string())
+                        .or_insert_with(|| value.to_string().into());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A single search hit (peptide-spectrum match) from a pepXML file
+#[derive(Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PepXmlRecord<'r> {
+    #[cfg_attr(feature = "serde", serde(borrow))]
+    values: Vec<Value<'r>>,
+}
+
+impl<'b: 's, 's> FromSlice<'b, 's> for PepXmlRecord<'s> {
+    type State = PepXmlState;
+
+    fn parse(
+        buffer: &[u8],
+        eof: bool,
+        consumed: &mut usize,
+        state: &mut Self::State,
+    ) -> Result<bool, EtError> {
+        // everything before `skipped` has already been added to `consumed`; we update that as
+        // we go so we don't need to hold elements we're skipping over in the buffer
+        let mut skipped = 0;
+        loop {
+            let start = match memchr(b'<', &buffer[skipped..]) {
+                Some(s) => skipped + s,
+                None if eof => return Ok(false),
+                None => return Err(EtError::from("Could not find next pepXML tag").incomplete()),
+            };
+            let con = &mut start.clone();
+            let tag: XmlTag = extract_with(buffer, con, &())?;
+            match (tag.id, tag.tag_type) {
+                ("msms_pipeline_analysis", XmlTagType::Close) => return Ok(false),
+                ("spectrum_query", XmlTagType::Open | XmlTagType::SelfClose) => {
+                    state.query = [
+                        "spectrum",
+                        "start_scan",
+                        "assumed_charge",
+                        "precursor_neutral_mass",
+                        "retention_time_sec",
+                    ]
+                    .iter()
+                    .map(|attr| coerce(tag.attribute(attr)))
+                    .collect();
+                    // the spectrum name is e.g. `run.00005.00005.2` so don't turn it into a number
+                    if let Some(spectrum) = tag.attribute("spectrum") {
+                        state.query[0] = spectrum.to_string().into();
+                    }
+                }
+                ("search_hit", XmlTagType::Open | XmlTagType::SelfClose) => {
+                    if tag.tag_type == XmlTagType::Open {
+                        skip_element(buffer, eof, con, tag.id)?;
+                    }
+                    state.record_len = *con - start;
+                    *consumed += *con - skipped;
+                    return Ok(true);
+                }
+                _ => {}
+            }
+            *consumed += *con - skipped;
+            skipped = *con;
+        }
+    }
+
+    fn get(&mut self, buffer: &'b [u8], state: &'s Self::State) -> Result<(), EtError> {
+        let buffer = &buffer[buffer.len() - state.record_len..];
+        let n_scores = state.score_names.len();
+        self.values.clear();
+        self.values.extend(state.query.iter().cloned());
+        self.values.resize(5, Value::Null);
+
+        let con = &mut 0;
+        let tag: XmlTag = extract_with(buffer, con, &())?;
+        self.values.extend(
+            ["hit_rank", "peptide"]
+                .iter()
+                .map(|attr| coerce(tag.attribute(attr))),
+        );
+        // peptides are always text (even if they look like numbers)
+        if let Some(peptide) = tag.attribute("peptide") {
+            self.values[6] = peptide.to_string().into();
+        }
+        self.values.push(Value::Null);
+        self.values.push(
+            tag.attribute("protein")
+                .map_or(Value::Null, |p| p.to_string().into()),
+        );
+        self.values.extend(
+            ["calc_neutral_pep_mass", "massdiff", "num_missed_cleavages"]
+                .iter()
+                .map(|attr| coerce(tag.attribute(attr))),
+        );
+        let mut scores = vec![Value::Null; n_scores + usize::from(state.has_probability)];
+
+        // only look at the hit's own scores (not e.g. the ones in a <search_score_summary>)
+        let mut depth = 0;
+        while let Some(tag) = next_tag(buffer, true, con)? {
+            match tag.id {
+                "modification_info" => {
+                    if let Some(peptide) = tag.attribute("modified_peptide") {
+                        self.values[7] = peptide.to_string().into();
+                    }
+                }
+                "search_score" if depth == 0 => {
+                    let name = tag.attribute("name").unwrap_or_default();
+                    if let Some(ix) = state.score_names.iter().position(|n| n == name) {
+                        scores[ix] = coerce(tag.attribute("value"));
+                    }
+                }
+                "peptideprophet_result" if state.has_probability => {
+                    if let Some(probability) = tag.attribute("probability") {
+                        scores[n_scores] = coerce(Some(probability));
+                    }
+                }
+                _ => {}
+            }
+            match tag.tag_type {
+                XmlTagType::Open => depth += 1,
+                XmlTagType::Close if depth > 0 => depth -= 1,
+                XmlTagType::Close | XmlTagType::SelfClose | XmlTagType::Declaration => {}
+            }
+        }
+        self.values.extend(scores);
+        Ok(())
+    }
+}
+
+impl<'r> From<PepXmlRecord<'r>> for Vec<Value<'r>> {
+    fn from(record: PepXmlRecord<'r>) -> Self {
+        record.values
+    }
+}
+
+impl<'r> PushFields<'r> for PepXmlRecord<'r> {
+    fn push_fields<E: Extend<Value<'r>>>(self, fields: &mut E) {
+        fields.extend(self.values);
+    }
+}
+
+impl_reader!(
+    /// A reader for the search hits (peptide-spectrum matches) in a pepXML file.
+    ///
+    /// Each hit is returned with the details of the spectrum it matched, the peptide and
+    /// protein, and then a column for each of the scores (e.g. `hyperscore`, `expect`) reported
+    /// for the first hit in the file and a `probability` column if the hits have been through
+    /// PeptideProphet.
+    PepXmlReader,
+    PepXmlRecord,
+    PepXmlRecord<'r>,
+    PepXmlState,
+    PepXmlParams
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::readers::RecordReader;
+
+    static TEST_PEPXML: &[u8] = br#"<?xml version="1.0" encoding="UTF-8"?>
+<msms_pipeline_analysis date="2021-05-01T10:00:00" xmlns="http://regis-web.systemsbiology.net/pepXML">
+  <msms_run_summary base_name="/data/run1" raw_data=".mzML">
+    <sample_enzyme name="trypsin"><specificity cut="KR" no_cut="P" sense="C"/></sample_enzyme>
+    <search_summary base_name="/data/run1" search_engine="X! Tandem" precursor_mass_type="monoisotopic">
+      <search_database local_path="/db/human.fasta" type="AA"/>
+    </search_summary>
+    <spectrum_query spectrum="run1.00005.00005.2" start_scan="5" end_scan="5" precursor_neutral_mass="1639.93" assumed_charge="2" index="1" retention_time_sec="1201.5">
+      <search_result>
+        <search_hit hit_rank="1" peptide="KVPQVSTPTLVEVSR" protein="sp|P02768|ALBU_HUMAN" calc_neutral_pep_mass="1639.93" massdiff="0.002" num_missed_cleavages="1">
+          <search_score name="hyperscore" value="45.2"/>
+          <search_score name="expect" value="1.2e-05"/>
+          <analysis_result analysis="peptideprophet">
+            <peptideprophet_result probability="0.998">
+              <search_score_summary><parameter name="fval" value="3.1"/></search_score_summary>
+            </peptideprophet_result>
+          </analysis_result>
+        </search_hit>
+        <search_hit hit_rank="2" peptide="LVNEVTEFAK" protein="sp|P02768|ALBU_HUMAN" calc_neutral_pep_mass="1148.6" massdiff="491.3" num_missed_cleavages="0">
+          <modification_info modified_peptide="LVNEVTEFAK[136]"><mod_aminoacid_mass position="10" mass="136.11"/></modification_info>
+          <search_score name="hyperscore" value="12"/>
+        </search_hit>
+      </search_result>
+    </spectrum_query>
+    <spectrum_query spectrum="run1.00007.00007.3" start_scan="7" end_scan="7" precursor_neutral_mass="2001.1" assumed_charge="3" index="2">
+      <search_result/>
+    </spectrum_query>
+  </msms_run_summary>
+</msms_pipeline_analysis>
+"#;
+
+    #[test]
+    fn test_pepxml_reader() -> Result<(), EtError> {
+        let mut reader = PepXmlReader::new(TEST_PEPXML, None)?;
+        let headers = reader.headers();
+        assert_eq!(headers.len(), 15);
+        assert_eq!(&headers[12..], ["hyperscore", "expect", "probability"]);
+        let metadata = reader.metadata();
+        assert_eq!(metadata["base_name"], "/data/run1".into());
+        assert_eq!(metadata["search_engine"], "X! Tandem".into());
+        assert_eq!(metadata["enzyme"], "trypsin".into());
+        assert_eq!(metadata["database"], "/db/human.fasta".into());
+
+        let record = reader.next_record()?.unwrap();
+        assert_eq!(record.len(), 15);
+        assert_eq!(record[0], "run1.00005.00005.2".into());
+        assert_eq!(record[1], 5.into());
+        assert_eq!(record[2], 2.into());
+        assert_eq!(record[4], 1201.5.into());
+        assert_eq!(record[5], 1.into());
+        assert_eq!(record[6], "KVPQVSTPTLVEVSR".into());
+        assert_eq!(record[7], Value::Null);
+        assert_eq!(record[8], "sp|P02768|ALBU_HUMAN".into());
+        assert_eq!(record[12], 45.2.into());
+        assert_eq!(record[13], 1.2e-5.into());
+        assert_eq!(record[14], 0.998.into());
+
+        let record = reader.next_record()?.unwrap();
+        assert_eq!(record[5], 2.into());
+        assert_eq!(record[7], "LVNEVTEFAK[136]".into());
+        assert_eq!(record[12], 12.into());
+        assert_eq!(record[13], Value::Null);
+        assert_eq!(record[14], Value::Null);
+        // the second spectrum has no hits
+        assert!(reader.next_record()?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_pepxml_without_hits() -> Result<(), EtError> {
+        let data = b"<msms_pipeline_analysis><msms_run_summary/></msms_pipeline_analysis>";
+        let mut reader = PepXmlReader::new(&data[..], None)?;
+        assert_eq!(reader.headers(), FIXED_HEADERS);
+        assert!(reader.next_record()?.is_none());
+
+        assert!(PepXmlReader::new(&b"<mzXML></mzXML>"[..], None).is_err());
+        Ok(())
+    }
+}
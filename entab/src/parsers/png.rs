@@ -1,12 +1,16 @@
 use alloc::collections::BTreeMap;
+use alloc::format;
 use core::convert::TryFrom;
 use core::marker::Copy;
 use std::io::Read;
 
 use flate2::read::ZlibDecoder;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
+use crate::capabilities::Capabilities;
 use crate::parsers::common::Skip;
-use crate::parsers::{extract, Endian, FromSlice};
+use crate::parsers::{extract, extract_with, Endian, FromSlice};
 use crate::record::{StateMetadata, Value};
 use crate::EtError;
 use crate::{impl_reader, impl_record};
@@ -141,6 +145,10 @@ impl StateMetadata for PngState {
         metadata
     }
 
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::HAS_METADATA | Capabilities::TYPED_SCHEMA
+    }
+
     fn header(&self) -> Vec<&str> {
         vec!["x", "y", "red", "green", "blue", "alpha"]
     }
@@ -165,19 +173,19 @@ impl<'b: 's, 's> FromSlice<'b, 's> for PngState {
         // skip width/height/etc for now
         let _ = extract::<Skip>(rb, con, &mut 10)?;
         // skip the compression, filter, and interlace bytes
-        if extract::<u8>(rb, con, &mut Endian::Big)? != 0 {
+        if extract_with::<u8>(rb, con, &Endian::Big)? != 0 {
             return Err("PNG compression must be type 0".into());
         }
-        if extract::<u8>(rb, con, &mut Endian::Big)? != 0 {
+        if extract_with::<u8>(rb, con, &Endian::Big)? != 0 {
             return Err("PNG filtering must be type 0".into());
         }
-        if extract::<u8>(rb, con, &mut Endian::Big)? != 0 {
+        if extract_with::<u8>(rb, con, &Endian::Big)? != 0 {
             return Err("PNG interlacing not supported yet".into());
         }
 
         loop {
             let _ = extract::<&[u8]>(rb, con, &mut 4)?;
-            let mut chunk_size = extract::<u32>(rb, con, &mut Endian::Big)? as usize;
+            let mut chunk_size = extract_with::<u32>(rb, con, &Endian::Big)? as usize;
             let chunk_header = extract::<&[u8]>(rb, con, &mut 4)?;
             if &chunk_header == b"IEND" {
                 break;
@@ -191,10 +199,16 @@ impl<'b: 's, 's> FromSlice<'b, 's> for PngState {
 
     fn get(&mut self, rb: &'b [u8], _state: &'s Self::State) -> Result<(), EtError> {
         let con = &mut 16;
-        self.width = extract::<u32>(rb, con, &mut Endian::Big)? as usize;
-        self.height = extract::<u32>(rb, con, &mut Endian::Big)? as usize;
-        self.bit_depth = extract(rb, con, &mut Endian::Big)?;
-        self.color_type = PngColorType::from_byte(extract(rb, con, &mut Endian::Big)?)?;
+        self.width = extract_with::<u32>(rb, con, &Endian::Big)? as usize;
+        self.height = extract_with::<u32>(rb, con, &Endian::Big)? as usize;
+        self.bit_depth = extract_with(rb, con, &Endian::Big)?;
+        self.color_type = PngColorType::from_byte(extract_with(rb, con, &Endian::Big)?)?;
+        if !matches!(self.bit_depth, 1 | 2 | 4 | 8 | 16) {
+            return Err(format!("Invalid PNG bit depth {}", self.bit_depth).into());
+        }
+        if self.width == 0 || self.height == 0 {
+            return Err("PNG width and height must be greater than 0".into());
+        }
         *con += 3;
 
         // parse through the entire file beforehand; because the data is compressed into multiple
@@ -206,15 +220,15 @@ impl<'b: 's, 's> FromSlice<'b, 's> for PngState {
             // throw away the checksum from the previous chunk
             let _ = extract::<&[u8]>(rb, con, &mut 4)?;
             // now read the header for the current chunk
-            let mut chunk_size = extract::<u32>(rb, con, &mut Endian::Big)? as usize;
+            let mut chunk_size = extract_with::<u32>(rb, con, &Endian::Big)? as usize;
             let chunk_header = extract::<&[u8]>(rb, con, &mut 4)?;
             match chunk_header {
                 b"PLTE" => {
                     let mut raw_palette = Vec::new();
                     for _ in 0..chunk_size / 3 {
-                        let r: u8 = extract(rb, con, &mut Endian::Big)?;
-                        let g: u8 = extract(rb, con, &mut Endian::Big)?;
-                        let b: u8 = extract(rb, con, &mut Endian::Big)?;
+                        let r: u8 = extract_with(rb, con, &Endian::Big)?;
+                        let g: u8 = extract_with(rb, con, &Endian::Big)?;
+                        let b: u8 = extract_with(rb, con, &Endian::Big)?;
                         raw_palette.push((
                             257 * u16::from(r),
                             257 * u16::from(g),
@@ -237,6 +251,10 @@ impl<'b: 's, 's> FromSlice<'b, 's> for PngState {
             }
         }
         let _ = ZlibDecoder::new(&compressed_data[..]).read_to_end(&mut self.image_data)?;
+        let image_len = self.line_len().checked_mul(self.height);
+        if image_len.is_none_or(|len| self.image_data.len() < len) {
+            return Err("PNG image data is shorter than its width and height".into());
+        }
         // initialize x to MAX to sentinel we haven't started yet
         self.cur_x = usize::MAX;
         self.cur_y = 0;
@@ -247,6 +265,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for PngState {
 
 /// A single pixel from a PNG file
 #[derive(Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct PngRecord {
     x: u32,
     y: u32,
@@ -260,7 +279,7 @@ impl_record!(PngRecord: x, y, red, green, blue, alpha);
 
 fn get_bits(data: &[u8], pos: usize, n_bits: usize, rescale: bool) -> Result<u16, EtError> {
     if n_bits == 16 {
-        u16::extract(&data[pos * 2..], &Endian::Big)
+        extract_with::<u16>(data, &mut (pos * 2), &Endian::Big)
     } else {
         let shift = n_bits * (pos % (8 / n_bits));
         let mask = u8::try_from(2u16.pow(u32::try_from(n_bits)?) - 1)?;
@@ -362,6 +381,39 @@ impl<'b: 's, 's> FromSlice<'b, 's> for PngRecord {
     }
 }
 
+/// The data in all of the chunks of type `kind` (e.g. `tEXt` or `iCCP`) in a PNG file, one
+/// after another (so e.g. the `IDAT` chunks together are the compressed image).
+///
+/// # Errors
+/// If the file isn't a PNG, is truncated, or doesn't have any chunks of that type, an error is
+/// returned.
+pub fn png_chunks(data: &[u8], kind: &str) -> Result<Vec<u8>, EtError> {
+    let con = &mut 0;
+    if extract::<&[u8]>(data, con, &mut 8)? != b"\x89PNG\r\n\x1A\n" {
+        return Err("Invalid PNG magic".into());
+    }
+    let mut chunks = Vec::new();
+    let mut found = false;
+    loop {
+        let mut chunk_size = extract_with::<u32>(data, con, &Endian::Big)? as usize;
+        let chunk_header = extract::<&[u8]>(data, con, &mut 4)?;
+        let chunk = extract::<&[u8]>(data, con, &mut chunk_size)?;
+        // and the checksum
+        let _ = extract::<&[u8]>(data, con, &mut 4)?;
+        if chunk_header == kind.as_bytes() {
+            chunks.extend_from_slice(chunk);
+            found = true;
+        }
+        if chunk_header == b"IEND" {
+            break;
+        }
+    }
+    if !found {
+        return Err(format!("PNG has no {} chunks", kind).into());
+    }
+    Ok(chunks)
+}
+
 impl_reader!(PngReader, PngRecord, PngRecord, PngState, ());
 
 #[cfg(test)]
@@ -1,43 +1,478 @@
 use alloc::borrow::Cow;
+#[cfg(feature = "std")]
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+#[cfg(feature = "std")]
+use alloc::rc::Rc;
 use alloc::string::{String, ToString};
 use alloc::vec;
 use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use core::cell::RefCell;
+use core::cmp::Ordering;
 use core::convert::TryFrom;
 use core::marker::Copy;
-
-use crate::parsers::common::{NewLine, Skip};
-use crate::parsers::{extract, extract_opt, Endian, FromSlice};
-use crate::record::StateMetadata;
+use core::str::FromStr;
+#[cfg(feature = "std")]
+use std::io::{self, Read, Seek};
+
+use memchr::memchr_iter;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "std")]
+use crate::bgzf::{BgzfReader, VirtualOffset};
+#[cfg(feature = "std")]
+use crate::buffer::ReadBuffer;
+use crate::capabilities::Capabilities;
+#[cfg(feature = "std")]
+use crate::parsers::bai::{BaiChunk, BaiIndex};
+use crate::parsers::common::{
+    strip_line_ending, BoundedLine, DelimitedToken, LengthPrefixed, Skip,
+};
+use crate::parsers::{extract, extract_opt, extract_with, slice_or_incomplete, Endian, FromSlice};
+use crate::record::{PushFields, StateMetadata, Value};
 use crate::EtError;
 use crate::{impl_reader, impl_record};
 
+/// Which kind of record a BAM reader returns
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum BamStream {
+    /// Return one record per alignment
+    #[default]
+    Alignments,
+    /// Return one record (name and length) per reference sequence in the header
+    References,
+}
+
+impl FromStr for BamStream {
+    type Err = EtError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "alignment" | "alignments" => Ok(BamStream::Alignments),
+            "reference" | "references" => Ok(BamStream::References),
+            x => Err(format!(
+                "BAM stream must be \"alignments\" or \"references\", not {}",
+                x
+            )
+            .into()),
+        }
+    }
+}
+
+/// Parameters for reading SAM and BAM files
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SamParams {
+    /// Add columns derived from each alignment's CIGAR (and MD tag, if it has one); see
+    /// `AlignmentStats`
+    pub alignment_stats: bool,
+}
+
+impl SamParams {
+    /// Add the `ref_end`, `matches`, `mismatches`, `soft_clip_start`, and `soft_clip_end`
+    /// columns to each alignment
+    #[must_use]
+    pub fn alignment_stats(mut self, alignment_stats: bool) -> Self {
+        self.alignment_stats = alignment_stats;
+        self
+    }
+}
+
+/// The columns `SamParams.alignment_stats` adds
+const ALIGNMENT_STATS_HEADERS: [&str; 5] = [
+    "ref_end",
+    "matches",
+    "mismatches",
+    "soft_clip_start",
+    "soft_clip_end",
+];
+
+/// Values derived from the CIGAR (and MD tag) of an alignment, so basic QC doesn't need to parse
+/// them again.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AlignmentStats {
+    /// Where the alignment ends on the reference (0-based and exclusive, so it's the end of a
+    /// range starting at `pos`); null for alignments without a position or a CIGAR
+    pub ref_end: Option<u64>,
+    /// The number of aligned bases that match the reference; null if this can't be told (i.e.
+    /// the CIGAR uses `M` and there's no MD tag)
+    pub matches: Option<u64>,
+    /// The number of aligned bases that don't match the reference (also null if this can't be
+    /// told)
+    pub mismatches: Option<u64>,
+    /// The number of bases soft-clipped off the start of the read
+    pub soft_clip_start: u64,
+    /// The number of bases soft-clipped off the end of the read
+    pub soft_clip_end: u64,
+}
+
+impl AlignmentStats {
+    /// Calculate the stats for an alignment starting at `pos` with a (text) `cigar` and the
+    /// value of its MD tag (if it has one).
+    ///
+    /// # Errors
+    /// If the CIGAR or MD tag is malformed, an error is returned.
+    pub fn new(pos: Option<u64>, cigar: &[u8], md: Option<&[u8]>) -> Result<Self, EtError> {
+        let mut stats = AlignmentStats::default();
+        if cigar.is_empty() {
+            return Ok(stats);
+        }
+        let mut ref_len = 0;
+        // the number of bases aligned with `M`, `=`, and `X`
+        let mut n_ops = [0; 3];
+        let mut started = false;
+        let mut len: Option<u64> = None;
+        for &c in cigar {
+            if c.is_ascii_digit() {
+                len = len
+                    .unwrap_or(0)
+                    .checked_mul(10)
+                    .and_then(|l| l.checked_add(u64::from(c - b'0')));
+                if len.is_none() {
+                    return Err("CIGAR operation is too long".into());
+                }
+                continue;
+            }
+            let n = len.take().ok_or("CIGAR operations need a length")?;
+            match c {
+                b'M' | b'=' | b'X' => {
+                    n_ops[usize::from(c == b'=') + 2 * usize::from(c == b'X')] += n;
+                    ref_len += n;
+                }
+                b'D' | b'N' => ref_len += n,
+                b'S' if started => stats.soft_clip_end += n,
+                b'S' => stats.soft_clip_start += n,
+                b'I' | b'H' | b'P' => {}
+                x => return Err(format!("Invalid CIGAR operation {}", char::from(x)).into()),
+            }
+            if !matches!(c, b'S' | b'H') {
+                started = true;
+            }
+        }
+        if len.is_some() {
+            return Err("CIGAR ends with a length instead of an operation".into());
+        }
+        stats.ref_end = pos.map(|p| p + ref_len);
+        stats.mismatches = match md {
+            Some(md) => Some(md_mismatches(md)?),
+            // with only `=` and `X` the CIGAR says which bases match
+            None if n_ops[0] == 0 => Some(n_ops[2]),
+            None => None,
+        };
+        let n_aligned: u64 = n_ops.iter().sum();
+        stats.matches = stats.mismatches.map(|m| n_aligned.saturating_sub(m));
+        Ok(stats)
+    }
+}
+
+/// The number of mismatched bases in an MD tag (e.g. `10A5^AC6` has one; the bases after a
+/// `^` were deleted from the reference)
+fn md_mismatches(md: &[u8]) -> Result<u64, EtError> {
+    let mut deleted = false;
+    let mut mismatches = 0;
+    for b in md {
+        match b {
+            b'0'..=b'9' => deleted = false,
+            b'^' => deleted = true,
+            b'A'..=b'Z' | b'a'..=b'z' if !deleted => mismatches += 1,
+            b'A'..=b'Z' | b'a'..=b'z' => {}
+            _ => return Err("Invalid MD tag".into()),
+        }
+    }
+    Ok(mismatches)
+}
+
+/// Find the value of a string tag (e.g. `MD`) in the auxiliary data of a BAM alignment
+fn bam_string_tag<'a>(mut aux: &'a [u8], tag: &[u8]) -> Result<Option<&'a [u8]>, EtError> {
+    while aux.len() >= 3 {
+        let (name, tag_type) = (&aux[..2], aux[2]);
+        aux = &aux[3..];
+        let len = match tag_type {
+            b'A' | b'c' | b'C' => 1,
+            b's' | b'S' => 2,
+            b'i' | b'I' | b'f' => 4,
+            b'Z' | b'H' => {
+                let end = aux
+                    .iter()
+                    .position(|b| *b == 0)
+                    .ok_or("BAM tag is missing its terminating NUL")?;
+                if name == tag && tag_type == b'Z' {
+                    return Ok(Some(&aux[..end]));
+                }
+                end + 1
+            }
+            b'B' => {
+                let n = match aux.get(1..5) {
+                    Some(n) => u32::from_le_bytes([n[0], n[1], n[2], n[3]]) as usize,
+                    None => return Err("BAM array tag ended abruptly".into()),
+                };
+                let size = match aux[0] {
+                    b'c' | b'C' => 1,
+                    b's' | b'S' => 2,
+                    b'i' | b'I' | b'f' => 4,
+                    _ => return Err("Invalid BAM array tag type".into()),
+                };
+                n.saturating_mul(size).saturating_add(5)
+            }
+            _ => return Err("Invalid BAM tag type".into()),
+        };
+        aux = aux.get(len..).ok_or("BAM tag ended abruptly")?;
+    }
+    Ok(None)
+}
+
+/// Add the `AlignmentStats` columns (if they're being added) onto the end of a record's values
+fn push_alignment_stats<'r, E: Extend<Value<'r>>>(values: &mut E, stats: Option<AlignmentStats>) {
+    if let Some(stats) = stats {
+        values.extend([
+            stats.ref_end.into(),
+            stats.matches.into(),
+            stats.mismatches.into(),
+            stats.soft_clip_start.into(),
+            stats.soft_clip_end.into(),
+        ]);
+    }
+}
+
+/// The names of the columns of an alignment record
+fn alignment_headers(alignment_stats: bool) -> Vec<&'static str> {
+    let mut headers = vec![
+        "query_name",
+        "flag",
+        "ref_name",
+        "pos",
+        "mapq",
+        "cigar",
+        "rnext",
+        "pnext",
+        "tlen",
+        "sequence",
+        "quality",
+        "extra",
+    ];
+    if alignment_stats {
+        headers.extend(ALIGNMENT_STATS_HEADERS);
+    }
+    headers
+}
+
+/// Pull the commonly used fields out of the text of a SAM header (which BAM files also have).
+///
+/// Reference names and lengths are taken from the `@SQ` lines here, but for BAM files these
+/// are replaced with the (authoritative) binary reference list. The full text is kept under
+/// `header` for anything else.
+fn header_metadata(text: &str) -> BTreeMap<String, Value<'static>> {
+    let mut metadata = BTreeMap::new();
+    let mut references: Vec<(String, usize)> = Vec::new();
+    let mut read_groups = Vec::new();
+    let mut samples: Vec<String> = Vec::new();
+    let mut programs = Vec::new();
+    for line in text.lines() {
+        let tag = |key: &str| {
+            line.split('\t')
+                .skip(1)
+                .find_map(|field| field.strip_prefix(key)?.strip_prefix(':'))
+                .map(String::from)
+        };
+        match line.split('\t').next() {
+            Some("@HD") => {
+                if let Some(version) = tag("VN") {
+                    let _ = metadata.insert("sam_version".to_string(), version.into());
+                }
+                if let Some(sort_order) = tag("SO") {
+                    let _ = metadata.insert("sort_order".to_string(), sort_order.into());
+                }
+            }
+            Some("@SQ") => {
+                if let Some(name) = tag("SN") {
+                    let length = tag("LN").and_then(|l| l.parse().ok()).unwrap_or(0);
+                    references.push((name, length));
+                }
+            }
+            Some("@RG") => {
+                if let Some(id) = tag("ID") {
+                    read_groups.push(id);
+                }
+                if let Some(sample) = tag("SM") {
+                    if !samples.contains(&sample) {
+                        samples.push(sample);
+                    }
+                }
+            }
+            Some("@PG") => {
+                if let Some(id) = tag("ID") {
+                    programs.push(id);
+                }
+            }
+            _ => {}
+        }
+    }
+    add_references(&mut metadata, &references);
+    for (key, values) in [
+        ("read_groups", read_groups),
+        ("samples", samples),
+        ("programs", programs),
+    ] {
+        if !values.is_empty() {
+            let _ = metadata.insert(key.to_string(), values.into());
+        }
+    }
+    if !text.is_empty() {
+        let _ = metadata.insert("header".to_string(), text.to_string().into());
+    }
+    metadata
+}
+
+/// Set the `references` and `reference_lengths` metadata (as two parallel lists so they can be
+/// used by e.g. coverage calculations without the original header).
+fn add_references(metadata: &mut BTreeMap<String, Value<'static>>, references: &[(String, usize)]) {
+    if references.is_empty() {
+        return;
+    }
+    let names: Vec<Value> = references
+        .iter()
+        .map(|(name, _)| name.clone().into())
+        .collect();
+    let lengths: Vec<Value> = references
+        .iter()
+        .map(|(_, length)| (*length as u64).into())
+        .collect();
+    let _ = metadata.insert("references".to_string(), names.into());
+    let _ = metadata.insert("reference_lengths".to_string(), lengths.into());
+}
+
+/// Anything that can be read from and seeked in (e.g. a `File`)
+#[cfg(feature = "std")]
+trait ReadSeek: Read + Seek {}
+
+#[cfg(feature = "std")]
+impl<T: Read + Seek> ReadSeek for T {}
+
+/// A BAM file and its index, shared between a `BamReader` and the `ReadBuffer` it reads from so
+/// `seek_region` can change which parts of the file the buffer gets its data from
+#[cfg(feature = "std")]
+#[derive(Debug)]
+struct IndexedBam {
+    bgzf: BgzfReader<Box<dyn ReadSeek>>,
+    index: BaiIndex,
+    /// The chunks of the current region left to read (last one first)
+    chunks: Vec<BaiChunk>,
+    /// Where the chunk being read ends (`None` until a region is chosen)
+    chunk_end: Option<VirtualOffset>,
+}
+
+/// A `Read` over the chunks of the current region of an `IndexedBam`
+#[cfg(feature = "std")]
+struct IndexedBamRead(Rc<RefCell<IndexedBam>>);
+
+#[cfg(feature = "std")]
+impl Read for IndexedBamRead {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut bam = self.0.borrow_mut();
+        let end = loop {
+            let end = match bam.chunk_end {
+                Some(end) => end,
+                None => return bam.bgzf.read(buf),
+            };
+            if bam.bgzf.virtual_offset() < end {
+                break end;
+            }
+            let chunk = match bam.chunks.pop() {
+                Some(chunk) => chunk,
+                None => return Ok(0),
+            };
+            bam.bgzf
+                .seek_virtual(chunk.start)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+            bam.chunk_end = Some(chunk.end);
+        };
+        // reads never go past the end of the current block so only the last one needs limiting
+        let cur = bam.bgzf.virtual_offset();
+        let len = if cur.block_start() == end.block_start() {
+            buf.len()
+                .min(usize::from(end.block_offset() - cur.block_offset()))
+        } else {
+            buf.len()
+        };
+        bam.bgzf.read(&mut buf[..len])
+    }
+}
+
+/// Where an alignment (without its length prefix) is relative to a region of a reference
+/// (`Equal` if it overlaps it).
+fn region_order(
+    record: &[u8],
+    (ref_id, start, end): (usize, u64, u64),
+) -> Result<Ordering, EtError> {
+    let con = &mut 0;
+    let raw_ref_id: i32 = extract_with(record, con, &Endian::Little)?;
+    let raw_pos: i32 = extract_with(record, con, &Endian::Little)?;
+    // alignments without a reference are all at the end of the file
+    let record_ref_id = match usize::try_from(raw_ref_id) {
+        Ok(record_ref_id) => record_ref_id,
+        Err(_) => return Ok(Ordering::Greater),
+    };
+    match record_ref_id.cmp(&ref_id) {
+        Ordering::Equal => {}
+        order => return Ok(order),
+    }
+    let pos = u64::try_from(raw_pos).unwrap_or(0);
+    if pos >= end {
+        return Ok(Ordering::Greater);
+    }
+
+    // find how much of the reference the alignment covers from its CIGAR
+    let query_name_len = usize::from(extract_with::<u8>(record, con, &Endian::Little)?);
+    *con = 12;
+    let n_cigar_op = usize::from(extract_with::<u16>(record, con, &Endian::Little)?);
+    *con = 32 + query_name_len;
+    let mut ref_len = 0;
+    for _ in 0..n_cigar_op {
+        let cigar_op = extract_with::<u32>(record, con, &Endian::Little)?;
+        // M, D, N, =, and X all consume the reference
+        if [0, 2, 3, 7, 8].contains(&(cigar_op & 15)) {
+            ref_len += u64::from(cigar_op >> 4);
+        }
+    }
+    if pos + ref_len.max(1) <= start {
+        return Ok(Ordering::Less);
+    }
+    Ok(Ordering::Equal)
+}
+
 /// The internal state of the `BamReader`.
 #[derive(Clone, Debug, Default)]
 pub struct BamState {
     references: Vec<(String, usize)>,
+    metadata: BTreeMap<String, Value<'static>>,
+    /// The reference (by its index), start, and end of the region to return alignments from
+    region: Option<(usize, u64, u64)>,
+    /// The length of the current alignment (the end of the buffer passed to `get`)
+    record_len: usize,
+    alignment_stats: bool,
+    #[cfg(feature = "std")]
+    indexed: Option<Rc<RefCell<IndexedBam>>>,
 }
 
 impl StateMetadata for BamState {
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        self.metadata.clone()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::HAS_METADATA | Capabilities::TYPED_SCHEMA
+    }
+
     fn header(&self) -> Vec<&str> {
-        vec![
-            "query_name",
-            "flag",
-            "ref_name",
-            "pos",
-            "mapq",
-            "cigar",
-            "rnext",
-            "pnext",
-            "tlen",
-            "sequence",
-            "quality",
-            "extra",
-        ]
+        alignment_headers(self.alignment_stats)
     }
 }
 
 impl<'b: 's, 's> FromSlice<'b, 's> for BamState {
-    type State = ();
+    type State = SamParams;
 
     fn parse(
         buffer: &[u8],
@@ -50,42 +485,48 @@ impl<'b: 's, 's> FromSlice<'b, 's> for BamState {
         if extract::<&[u8]>(buffer, con, &mut 4)? != b"BAM\x01" {
             return Err("Not a valid BAM file".into());
         }
-        let mut header_len = extract::<u32>(buffer, con, &mut Endian::Little)? as usize;
-        let _ = Skip::parse(&buffer[*con..], eof, con, &mut header_len)?;
+        let _ = extract_with::<LengthPrefixed<u32>>(buffer, con, &Endian::Little)?;
 
         // read the reference sequence data
-        let mut n_references = extract::<u32>(buffer, con, &mut Endian::Little)? as usize;
+        let mut n_references = extract_with::<u32>(buffer, con, &Endian::Little)? as usize;
         while n_references > 0 {
-            let name_len = extract::<u32>(buffer, con, &mut Endian::Little)? as usize;
-            let _ = Skip::parse(&buffer[*con..], eof, con, &mut (4 + name_len))?;
+            let _ = extract_with::<LengthPrefixed<u32>>(buffer, con, &Endian::Little)?;
+            let _ = Skip::parse(slice_or_incomplete(buffer, *con..)?, eof, con, &mut 4)?;
             n_references -= 1;
         }
         *consumed += *con;
         Ok(true)
     }
 
-    fn get(&mut self, buffer: &'b [u8], _state: &Self::State) -> Result<(), EtError> {
+    fn get(&mut self, buffer: &'b [u8], params: &Self::State) -> Result<(), EtError> {
+        self.alignment_stats = params.alignment_stats;
         let con = &mut 4;
-        let mut header_len = extract::<u32>(buffer, con, &mut Endian::Little)? as usize;
-        // TODO: we should read the headers and pass them along
-        // to the Reader as metadata once we support that
-        let _ = extract::<Skip>(buffer, con, &mut header_len)?;
+        let LengthPrefixed(raw_header, _) =
+            extract_with::<LengthPrefixed<u32>>(buffer, con, &Endian::Little)?;
+        // the header text is often padded out with NULs
+        let header_end = raw_header
+            .iter()
+            .rposition(|b| *b != b'\x00')
+            .map_or(0, |i| i + 1);
+        let header = String::from_utf8_lossy(&raw_header[..header_end]);
 
         // read the reference sequence data
-        let mut n_references = extract::<u32>(buffer, con, &mut Endian::Little)? as usize;
+        let mut n_references = extract_with::<u32>(buffer, con, &Endian::Little)? as usize;
 
         let mut references = Vec::new();
         while n_references > 0 {
-            let mut name_len = extract::<u32>(buffer, con, &mut Endian::Little)? as usize;
-            let mut raw_ref_name = extract::<&[u8]>(buffer, con, &mut name_len)?;
+            let LengthPrefixed(mut raw_ref_name, _) =
+                extract_with::<LengthPrefixed<u32>>(buffer, con, &Endian::Little)?;
             if raw_ref_name.last() == Some(&b'\x00') {
-                raw_ref_name = &raw_ref_name[..name_len - 1];
+                raw_ref_name = &raw_ref_name[..raw_ref_name.len() - 1];
             };
             let ref_name = String::from(alloc::str::from_utf8(raw_ref_name)?);
-            let ref_len = extract::<u32>(buffer, con, &mut Endian::Little)? as usize;
+            let ref_len = extract_with::<u32>(buffer, con, &Endian::Little)? as usize;
             references.push((ref_name, ref_len));
             n_references -= 1;
         }
+        self.metadata = header_metadata(&header);
+        add_references(&mut self.metadata, &references);
         self.references = references;
         Ok(())
     }
@@ -93,6 +534,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for BamState {
 
 /// A single record from a BAM file.
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct BamRecord<'r> {
     /// The name of the mapped sequence.
     pub query_name: &'r str,
@@ -113,6 +555,7 @@ pub struct BamRecord<'r> {
     /// `M` - Match (may be either a `=` or an `X`),
     /// `=` - Identical match
     /// `X` - Near-match (e.g. a SNP)
+    #[cfg_attr(feature = "serde", serde(with = "crate::record::text_bytes"))]
     pub cigar: Vec<u8>,
     /// Next read's name
     pub rnext: &'r str,
@@ -121,14 +564,45 @@ pub struct BamRecord<'r> {
     /// Template length
     pub tlen: i32,
     /// The sequence of the query, if present.
+    #[cfg_attr(feature = "serde", serde(with = "crate::record::text_bytes"))]
     pub sequence: Vec<u8>,
     /// The quality scores of the query, if present.
+    #[cfg_attr(feature = "serde", serde(with = "crate::record::text_bytes"))]
     pub quality: Vec<u8>,
     /// Extra metadata about the mapping.
+    #[cfg_attr(feature = "serde", serde(borrow, with = "crate::record::text_bytes"))]
     pub extra: Cow<'r, [u8]>,
+    /// Values derived from the CIGAR (if `SamParams.alignment_stats` is set)
+    pub stats: Option<AlignmentStats>,
 }
 
-impl_record!(BamRecord<'r>: query_name, flag, ref_name, pos, mapq, cigar, rnext, pnext, tlen, sequence, quality, extra);
+impl<'r> From<BamRecord<'r>> for Vec<Value<'r>> {
+    fn from(record: BamRecord<'r>) -> Self {
+        let mut values = Vec::with_capacity(12 + ALIGNMENT_STATS_HEADERS.len());
+        record.push_fields(&mut values);
+        values
+    }
+}
+
+impl<'r> PushFields<'r> for BamRecord<'r> {
+    fn push_fields<E: Extend<Value<'r>>>(self, fields: &mut E) {
+        fields.extend([
+            self.query_name.into(),
+            self.flag.into(),
+            self.ref_name.into(),
+            self.pos.into(),
+            self.mapq.into(),
+            self.cigar.into(),
+            self.rnext.into(),
+            self.pnext.into(),
+            self.tlen.into(),
+            self.sequence.into(),
+            self.quality.into(),
+            self.extra.into(),
+        ]);
+        push_alignment_stats(fields, self.stats);
+    }
+}
 
 impl<'b: 's, 's> FromSlice<'b, 's> for BamRecord<'s> {
     type State = BamState;
@@ -137,33 +611,50 @@ impl<'b: 's, 's> FromSlice<'b, 's> for BamRecord<'s> {
         rb: &[u8],
         eof: bool,
         consumed: &mut usize,
-        _state: &mut Self::State,
+        state: &mut Self::State,
     ) -> Result<bool, EtError> {
-        // each record in a BAM is a different gzip chunk so we
-        // have to do a refill before each record
-        if rb.is_empty() {
-            if eof {
-                return Ok(false);
-            }
-            return Err(EtError::new("BAM file is incomplete").incomplete());
-        }
-        // now read the record itself
         let con = &mut 0;
-        let mut record_len = extract::<u32>(rb, con, &mut Endian::Little)? as usize;
-        if record_len < 32 {
-            return Err("Record is unexpectedly short".into());
+        loop {
+            // each record in a BAM is a different gzip chunk so we
+            // have to do a refill before each record
+            let record = &rb[*con..];
+            if record.is_empty() {
+                if eof {
+                    return Ok(false);
+                }
+                return Err(EtError::new("BAM file is incomplete").incomplete());
+            }
+            // now read the record itself
+            if record.len() >= 4
+                && u32::from_le_bytes([record[0], record[1], record[2], record[3]]) < 32
+            {
+                return Err("Record is unexpectedly short".into());
+            }
+            let start = *con;
+            let _ = LengthPrefixed::<u32>::parse(record, eof, con, &mut Endian::Little)?;
+            // skip over the alignments from the region's chunks that aren't in the region
+            let order = match state.region {
+                Some(region) => region_order(&rb[start + 4..*con], region)?,
+                None => Ordering::Equal,
+            };
+            match order {
+                Ordering::Less => continue,
+                Ordering::Equal => {
+                    state.record_len = *con - start;
+                    *consumed += *con;
+                    return Ok(true);
+                }
+                Ordering::Greater => return Ok(false),
+            }
         }
-        let _ = Skip::parse(&rb[*con..], eof, con, &mut record_len)?;
-        *consumed += *con;
-
-        Ok(true)
     }
 
     fn get(&mut self, rb: &'b [u8], state: &'s Self::State) -> Result<(), EtError> {
+        let rb = &rb[rb.len() - state.record_len..];
+        let LengthPrefixed(rb, _) =
+            extract_with::<LengthPrefixed<u32>>(rb, &mut 0, &Endian::Little)?;
         let con = &mut 0;
-        let record_len = extract::<u32>(rb, con, &mut Endian::Little)? as usize;
-
-        let raw_ref_name_id: i32 = extract(rb, con, &mut Endian::Little)?;
+        let raw_ref_name_id: i32 = extract_with(rb, con, &Endian::Little)?;
         self.ref_name = if raw_ref_name_id < 0 {
             ""
         } else if usize::try_from(raw_ref_name_id)? >= state.references.len() {
@@ -171,14 +662,14 @@ impl<'b: 's, 's> FromSlice<'b, 's> for BamRecord<'s> {
         } else {
             &state.references[usize::try_from(raw_ref_name_id)?].0
         };
-        let raw_pos: i32 = extract(rb, con, &mut Endian::Little)?;
+        let raw_pos: i32 = extract_with(rb, con, &Endian::Little)?;
         self.pos = if raw_pos == -1 {
             None
         } else {
             Some(u64::try_from(raw_pos)?)
         };
-        let query_name_len = usize::from(extract::<u8>(rb, con, &mut Endian::Little)?);
-        let raw_mapq: u8 = extract(rb, con, &mut Endian::Little)?;
+        let query_name_len = usize::from(extract_with::<u8>(rb, con, &Endian::Little)?);
+        let raw_mapq: u8 = extract_with(rb, con, &Endian::Little)?;
         self.mapq = if raw_mapq == 255 {
             None
         } else {
@@ -186,10 +677,10 @@ impl<'b: 's, 's> FromSlice<'b, 's> for BamRecord<'s> {
         };
         // don't care about the BAI index bin - &data[10..12]
         let _ = extract::<&[u8]>(rb, con, &mut 2_usize)?;
-        let n_cigar_op = usize::from(extract::<u16>(rb, con, &mut Endian::Little)?);
-        self.flag = extract::<u16>(rb, con, &mut Endian::Little)?;
-        let seq_len = extract::<u32>(rb, con, &mut Endian::Little)? as usize;
-        let raw_rnext_id: i32 = extract(rb, con, &mut Endian::Little)?;
+        let n_cigar_op = usize::from(extract_with::<u16>(rb, con, &Endian::Little)?);
+        self.flag = extract_with::<u16>(rb, con, &Endian::Little)?;
+        let seq_len = extract_with::<u32>(rb, con, &Endian::Little)? as usize;
+        let raw_rnext_id: i32 = extract_with(rb, con, &Endian::Little)?;
         self.rnext = if raw_rnext_id < 0 {
             ""
         } else if usize::try_from(raw_rnext_id)? >= state.references.len() {
@@ -197,17 +688,17 @@ impl<'b: 's, 's> FromSlice<'b, 's> for BamRecord<'s> {
         } else {
             &state.references[usize::try_from(raw_rnext_id)?].0
         };
-        let raw_pnext: i32 = extract(rb, con, &mut Endian::Little)?;
+        let raw_pnext: i32 = extract_with(rb, con, &Endian::Little)?;
         self.pnext = if raw_pnext == -1 {
             None
         } else {
             Some(u32::try_from(raw_pnext)?)
         };
-        self.tlen = extract::<i32>(rb, con, &mut Endian::Little)?;
+        self.tlen = extract_with::<i32>(rb, con, &Endian::Little)?;
 
         // now parse the variable length records
-        let data = extract::<&[u8]>(rb, con, &mut (record_len - 32))?;
-        if query_name_len + n_cigar_op * 8 + (3 * seq_len + 2) / 2 > data.len() {
+        let data = slice_or_incomplete(rb, *con..)?;
+        if query_name_len + n_cigar_op * 4 + (3 * seq_len + 2) / 2 > data.len() {
             // there's not enough space for the query name, cigar, and sequence/quality?
             return Err("Record ended abruptly while reading variable-length data".into());
         }
@@ -221,10 +712,12 @@ impl<'b: 's, 's> FromSlice<'b, 's> for BamRecord<'s> {
 
         self.cigar = Vec::new();
         for _ in 0..n_cigar_op {
-            let cigar_op = extract::<u32>(data, &mut start, &mut Endian::Little)? as usize;
+            let cigar_op = extract_with::<u32>(data, &mut start, &Endian::Little)? as usize;
             self.cigar.extend((cigar_op >> 4).to_string().as_bytes());
-            self.cigar.push(b"MIDNSHP=X"[cigar_op & 7]);
-            start += 4;
+            let op = b"MIDNSHP=X"
+                .get(cigar_op & 15)
+                .ok_or("Invalid CIGAR operation")?;
+            self.cigar.push(*op);
         }
         self.sequence = vec![0; seq_len];
         for idx in 0..seq_len {
@@ -239,39 +732,213 @@ impl<'b: 's, 's> FromSlice<'b, 's> for BamRecord<'s> {
             let raw_qual = &data[start..start + seq_len];
             raw_qual.iter().map(|m| m.saturating_add(33)).collect()
         };
+        self.stats = if state.alignment_stats {
+            let md = bam_string_tag(&data[start + seq_len..], b"MD")?;
+            Some(AlignmentStats::new(self.pos, &self.cigar, md)?)
+        } else {
+            None
+        };
         // TODO: parse the extra flags some day?
         // self.extra = Cow::Borrowed(b"");
         Ok(())
     }
 }
 
-impl_reader!(BamReader, BamRecord, BamRecord<'r>, BamState, ());
+/// The SAM-formatted text header (the `@HD`, `@SQ`, `@RG`, etc. lines) of a BAM file (after it's
+/// been decompressed) without the NULs it's sometimes padded with.
+///
+/// # Errors
+/// If the data isn't a BAM file or the header is truncated, an error is returned.
+pub fn bam_header(data: &[u8]) -> Result<&[u8], EtError> {
+    let con = &mut 0;
+    if extract::<&[u8]>(data, con, &mut 4)? != b"BAM\x01" {
+        return Err("Not a valid BAM file".into());
+    }
+    let LengthPrefixed(raw_header, _) =
+        extract_with::<LengthPrefixed<u32>>(data, con, &Endian::Little)?;
+    let header_end = raw_header
+        .iter()
+        .rposition(|b| *b != b'\x00')
+        .map_or(0, |i| i + 1);
+    Ok(&raw_header[..header_end])
+}
 
-/// The internal state of the `SamReader`.
+impl_reader!(BamReader, BamRecord, BamRecord<'r>, BamState, SamParams);
+
+#[cfg(feature = "std")]
+impl<'r> BamReader<'r> {
+    /// Create a reader for a (BGZF-compressed) BAM file that can jump to the alignments in a
+    /// region with `seek_region` using the file's BAI index.
+    ///
+    /// Until `seek_region` is called, all of the alignments in the file are read.
+    ///
+    /// # Errors
+    /// If the file isn't BGZF-compressed or its header can't be read, an error is returned.
+    pub fn with_index<R>(data: R, index: BaiIndex) -> Result<Self, EtError>
+    where
+        R: Read + Seek + 'static,
+    {
+        let indexed = Rc::new(RefCell::new(IndexedBam {
+            bgzf: BgzfReader::new(Box::new(data)),
+            index,
+            chunks: Vec::new(),
+            chunk_end: None,
+        }));
+        let rb = ReadBuffer::from_reader(Box::new(IndexedBamRead(Rc::clone(&indexed))), None)?;
+        let mut reader = BamReader::new(rb, None)?;
+        reader.state.indexed = Some(indexed);
+        Ok(reader)
+    }
+
+    /// Jump to the alignments on `chrom` that overlap `start..end` (0-based and end-exclusive
+    /// like the `pos` of the records); `next` returns `None` after the last one. This can be
+    /// called again to read another region.
+    ///
+    /// # Errors
+    /// If the reader wasn't created with `with_index`, if `chrom` isn't one of the references,
+    /// or if the start of the region can't be read, an error is returned.
+    pub fn seek_region(&mut self, chrom: &str, start: u64, end: u64) -> Result<(), EtError> {
+        let indexed = self
+            .state
+            .indexed
+            .clone()
+            .ok_or("Seeking to a region needs an index (see `BamReader::with_index`)")?;
+        let ref_id = self
+            .state
+            .references
+            .iter()
+            .position(|(name, _)| name == chrom)
+            .ok_or_else(|| format!("{} is not one of the references in the BAM file", chrom))?;
+        {
+            let mut bam = indexed.borrow_mut();
+            let mut chunks = bam.index.chunks(ref_id, start, end)?;
+            chunks.reverse();
+            bam.chunks = chunks;
+            // this makes the next read start on the first chunk
+            bam.chunk_end = Some(VirtualOffset(0));
+        }
+        self.state.region = Some((ref_id, start, end));
+        self.rb = ReadBuffer::from_reader(Box::new(IndexedBamRead(indexed)), None)?;
+        Ok(())
+    }
+}
+
+/// The internal state of the `BamReferenceReader`.
+#[derive(Clone, Debug, Default)]
+pub struct BamReferenceState {
+    bam: BamState,
+    cur_reference: usize,
+}
+
+impl StateMetadata for BamReferenceState {
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        self.bam.metadata()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n_left = self.bam.references.len() - self.cur_reference;
+        (n_left, Some(n_left))
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::SIZED | Capabilities::HAS_METADATA | Capabilities::TYPED_SCHEMA
+    }
+
+    fn header(&self) -> Vec<&str> {
+        vec!["ref_name", "length"]
+    }
+}
+
+impl<'b: 's, 's> FromSlice<'b, 's> for BamReferenceState {
+    type State = ();
+
+    fn parse(
+        buffer: &[u8],
+        eof: bool,
+        consumed: &mut usize,
+        _state: &mut Self::State,
+    ) -> Result<bool, EtError> {
+        BamState::parse(buffer, eof, consumed, &mut SamParams::default())
+    }
+
+    fn get(&mut self, buffer: &'b [u8], _state: &Self::State) -> Result<(), EtError> {
+        self.bam.get(buffer, &SamParams::default())?;
+        self.cur_reference = 0;
+        Ok(())
+    }
+}
+
+/// A reference sequence from the header of a BAM file.
 #[derive(Clone, Copy, Debug, Default)]
-pub struct SamState {}
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BamReference<'r> {
+    /// The name of the reference (as used in the `ref_name` of alignments)
+    pub ref_name: &'r str,
+    /// The length of the reference sequence
+    pub length: u64,
+}
+
+impl_record!(BamReference<'r>: ref_name, length);
+
+impl<'b: 's, 's> FromSlice<'b, 's> for BamReference<'s> {
+    type State = BamReferenceState;
+
+    fn parse(
+        _buffer: &[u8],
+        _eof: bool,
+        _consumed: &mut usize,
+        state: &mut Self::State,
+    ) -> Result<bool, EtError> {
+        if state.cur_reference >= state.bam.references.len() {
+            return Ok(false);
+        }
+        state.cur_reference += 1;
+        Ok(true)
+    }
+
+    fn get(&mut self, _buffer: &'b [u8], state: &'s Self::State) -> Result<(), EtError> {
+        let (name, length) = &state.bam.references[state.cur_reference - 1];
+        self.ref_name = name;
+        self.length = *length as u64;
+        Ok(())
+    }
+}
+
+impl_reader!(
+    BamReferenceReader,
+    BamReference,
+    BamReference<'r>,
+    BamReferenceState,
+    ()
+);
+
+/// The longest line a SAM file can have (long reads can have sequences and quality scores that
+/// are each hundreds of megabases, but anything longer is likely not a SAM file).
+const MAX_LINE_LEN: usize = 1 << 30;
+
+/// The internal state of the `SamReader`.
+#[derive(Clone, Debug, Default)]
+pub struct SamState {
+    metadata: BTreeMap<String, Value<'static>>,
+    alignment_stats: bool,
+}
 
 impl StateMetadata for SamState {
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        self.metadata.clone()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::HAS_METADATA | Capabilities::TYPED_SCHEMA
+    }
+
     fn header(&self) -> Vec<&str> {
-        vec![
-            "query_name",
-            "flag",
-            "ref_name",
-            "pos",
-            "mapq",
-            "cigar",
-            "rnext",
-            "pnext",
-            "tlen",
-            "sequence",
-            "quality",
-            "extra",
-        ]
+        alignment_headers(self.alignment_stats)
     }
 }
 
 impl<'b: 's, 's> FromSlice<'b, 's> for SamState {
-    type State = ();
+    type State = SamParams;
 
     fn parse(
         rb: &[u8],
@@ -279,13 +946,12 @@ impl<'b: 's, 's> FromSlice<'b, 's> for SamState {
         consumed: &mut usize,
         _state: &mut Self::State,
     ) -> Result<bool, EtError> {
-        // eventually we should read the headers and pass them along
-        // to the Reader as metadata once we support that
         let con = &mut 0;
         // we're using `to_read` to keep track of how much *only* the header lines take up since
         // the final extracted line we don't want to consumed
         let mut to_read = 0;
-        while let Some(header) = extract_opt::<NewLine>(rb, eof, con, &mut 0)? {
+        let mut max_len = MAX_LINE_LEN;
+        while let Some(header) = extract_opt::<BoundedLine>(rb, eof, con, &mut max_len)? {
             if header.0.first() != Some(&b'@') {
                 break;
             }
@@ -296,13 +962,16 @@ impl<'b: 's, 's> FromSlice<'b, 's> for SamState {
         Ok(true)
     }
 
-    fn get(&mut self, _buf: &'b [u8], _state: &Self::State) -> Result<(), EtError> {
+    fn get(&mut self, buf: &'b [u8], params: &Self::State) -> Result<(), EtError> {
+        self.alignment_stats = params.alignment_stats;
+        self.metadata = header_metadata(&String::from_utf8_lossy(buf));
         Ok(())
     }
 }
 
 /// A single record from a SAM file.
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SamRecord<'r> {
     /// The name of the mapped sequence.
     pub query_name: &'r str,
@@ -323,6 +992,7 @@ pub struct SamRecord<'r> {
     /// `M` - Match (may be either a `=` or an `X`),
     /// `=` - Identical match
     /// `X` - Near-match (e.g. a SNP)
+    #[cfg_attr(feature = "serde", serde(borrow, with = "crate::record::text_bytes"))]
     pub cigar: &'r [u8],
     /// Next read's name
     pub rnext: &'r str,
@@ -331,14 +1001,45 @@ pub struct SamRecord<'r> {
     /// Template length
     pub tlen: i32,
     /// The sequence of the query, if present.
+    #[cfg_attr(feature = "serde", serde(borrow, with = "crate::record::text_bytes"))]
     pub sequence: &'r [u8],
     /// The quality scores of the query, if present.
+    #[cfg_attr(feature = "serde", serde(borrow, with = "crate::record::text_bytes"))]
     pub quality: &'r [u8],
     /// Extra metadata about the mapping.
+    #[cfg_attr(feature = "serde", serde(borrow, with = "crate::record::text_bytes"))]
     pub extra: Cow<'r, [u8]>,
+    /// Values derived from the CIGAR (if `SamParams.alignment_stats` is set)
+    pub stats: Option<AlignmentStats>,
+}
+
+impl<'r> From<SamRecord<'r>> for Vec<Value<'r>> {
+    fn from(record: SamRecord<'r>) -> Self {
+        let mut values = Vec::with_capacity(12 + ALIGNMENT_STATS_HEADERS.len());
+        record.push_fields(&mut values);
+        values
+    }
 }
 
-impl_record!(SamRecord<'r>: query_name, flag, ref_name, pos, mapq, cigar, rnext, pnext, tlen, sequence, quality, extra);
+impl<'r> PushFields<'r> for SamRecord<'r> {
+    fn push_fields<E: Extend<Value<'r>>>(self, fields: &mut E) {
+        fields.extend([
+            self.query_name.into(),
+            self.flag.into(),
+            self.ref_name.into(),
+            self.pos.into(),
+            self.mapq.into(),
+            self.cigar.into(),
+            self.rnext.into(),
+            self.pnext.into(),
+            self.tlen.into(),
+            self.sequence.into(),
+            self.quality.into(),
+            self.extra.into(),
+        ]);
+        push_alignment_stats(fields, self.stats);
+    }
+}
 
 impl<'b: 's, 's> FromSlice<'b, 's> for SamRecord<'s> {
     type State = SamState;
@@ -350,18 +1051,30 @@ impl<'b: 's, 's> FromSlice<'b, 's> for SamRecord<'s> {
         _state: &mut Self::State,
     ) -> Result<bool, EtError> {
         let con = &mut 0;
-        Ok(match extract_opt::<NewLine>(rb, eof, con, &mut 0)? {
-            Some(_) => {
-                *consumed += *con;
-                true
-            }
-            None => false,
-        })
+        let mut max_len = MAX_LINE_LEN;
+        let line = match extract_opt::<BoundedLine>(rb, eof, con, &mut max_len)? {
+            Some(BoundedLine(line)) => line,
+            None => return Ok(false),
+        };
+        // a last line without a line ending that's missing fields was likely cut off
+        if !rb[..*con].ends_with(b"\n") && memchr_iter(b'\t', line).count() < 10 {
+            return Err(EtError::from("Sam record too short").incomplete());
+        }
+        *consumed += *con;
+        Ok(true)
     }
 
-    fn get(&mut self, buf: &'b [u8], _state: &Self::State) -> Result<(), EtError> {
-        // TODO: need to remove terminal newline?
-        let chunks: Vec<&[u8]> = buf.split(|c| *c == b'\t').collect();
+    fn get(&mut self, buf: &'b [u8], state: &Self::State) -> Result<(), EtError> {
+        let line = strip_line_ending(buf);
+        let con = &mut 0;
+        let mut chunks: Vec<&[u8]> = Vec::with_capacity(12);
+        while let Some(DelimitedToken(chunk)) = extract_opt(line, true, con, &mut b'\t')? {
+            chunks.push(chunk);
+        }
+        // a tab at the end of the line still ends a (blank) field
+        if line.last() == Some(&b'\t') {
+            chunks.push(b"");
+        }
         if chunks.len() < 11 {
             return Err("Sam record too short".into());
         }
@@ -412,15 +1125,24 @@ impl<'b: 's, 's> FromSlice<'b, 's> for SamRecord<'s> {
             }
             joined.into()
         };
+        self.stats = if state.alignment_stats {
+            let md = chunks[11.min(chunks.len())..]
+                .iter()
+                .find_map(|tag| tag.strip_prefix(b"MD:Z:"));
+            Some(AlignmentStats::new(self.pos, self.cigar, md)?)
+        } else {
+            None
+        };
         Ok(())
     }
 }
 
-impl_reader!(SamReader, SamRecord, SamRecord<'r>, SamState, ());
+impl_reader!(SamReader, SamRecord, SamRecord<'r>, SamState, SamParams);
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::error::EtErrorKind;
 
     #[cfg(all(feature = "compression", feature = "std"))]
     use crate::readers::RecordReader;
@@ -433,7 +1155,18 @@ mod tests {
         let rb = include_bytes!("../../tests/data/test.sam");
         let mut reader = SamReader::new(&rb[..], None)?;
         #[cfg(all(feature = "compression", feature = "std"))]
-        let _ = reader.metadata();
+        {
+            let metadata = reader.metadata();
+            assert_eq!(
+                metadata["references"],
+                vec![Value::from("gi|9626372|ref|NC_001422.1|")].into()
+            );
+            assert_eq!(
+                metadata["reference_lengths"],
+                vec![Value::from(5386u64)].into()
+            );
+            assert_eq!(metadata["programs"], vec!["minimap2".to_string()].into());
+        }
         if let Some(SamRecord {
             query_name,
             sequence,
@@ -454,6 +1187,85 @@ mod tests {
         Ok(())
     }
 
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_alignment_stats() -> Result<(), EtError> {
+        assert_eq!(
+            AlignmentStats::new(Some(10), b"2H3S4M2I3M1D2M5S", Some(b"4A2^C1T0"))?,
+            AlignmentStats {
+                ref_end: Some(20),
+                matches: Some(7),
+                mismatches: Some(2),
+                soft_clip_start: 3,
+                soft_clip_end: 5,
+            }
+        );
+        let stats = AlignmentStats::new(Some(0), b"5=1X4=", None)?;
+        assert_eq!(
+            (stats.ref_end, stats.matches, stats.mismatches),
+            (Some(10), Some(9), Some(1))
+        );
+        // there's no way to tell which `M`s match without an MD tag
+        let stats = AlignmentStats::new(Some(0), b"10M", None)?;
+        assert_eq!((stats.matches, stats.mismatches), (None, None));
+        assert_eq!(
+            AlignmentStats::new(None, b"", None)?,
+            AlignmentStats::default()
+        );
+        assert!(AlignmentStats::new(Some(0), b"4", None).is_err());
+        assert!(AlignmentStats::new(Some(0), b"M", None).is_err());
+        assert!(AlignmentStats::new(Some(0), b"4Q", None).is_err());
+        assert!(AlignmentStats::new(Some(0), b"4M", Some(b"4!")).is_err());
+
+        let data = b"@SQ\tSN:chr1\tLN:100\nr1\t0\tchr1\t11\t60\t3S5M\t*\t0\t0\tACGTACGT\t*\tNM:i:1\tMD:Z:2G2\n";
+        let params = SamParams::default().alignment_stats(true);
+        let mut reader = SamReader::new(&data[..], Some(params))?;
+        assert_eq!(
+            reader.headers().last().map(String::as_str),
+            Some("soft_clip_end")
+        );
+        let stats = AlignmentStats {
+            ref_end: Some(15),
+            matches: Some(4),
+            mismatches: Some(1),
+            soft_clip_start: 3,
+            soft_clip_end: 0,
+        };
+        assert_eq!(reader.next()?.unwrap().stats, Some(stats));
+
+        // and the same alignment in a BAM file
+        let mut bam = b"BAM\x01".to_vec();
+        bam.extend(0_u32.to_le_bytes());
+        bam.extend(1_u32.to_le_bytes());
+        bam.extend(5_u32.to_le_bytes());
+        bam.extend(b"chr1\x00");
+        bam.extend(100_u32.to_le_bytes());
+        let mut record = Vec::new();
+        record.extend(0_i32.to_le_bytes());
+        record.extend(10_i32.to_le_bytes());
+        record.extend([3, 60, 0, 0, 2, 0, 0, 0]);
+        record.extend(8_u32.to_le_bytes());
+        record.extend((-1_i32).to_le_bytes());
+        record.extend((-1_i32).to_le_bytes());
+        record.extend(0_i32.to_le_bytes());
+        record.extend(b"r1\x00");
+        record.extend(((3_u32 << 4) | 4).to_le_bytes());
+        record.extend((5_u32 << 4).to_le_bytes());
+        record.extend([0x12, 0x48, 0x12, 0x48]);
+        record.extend([30; 8]);
+        record.extend(b"NMC\x01XBBc\x02\x00\x00\x00\x01\x02MDZ2G2\x00");
+        bam.extend((record.len() as u32).to_le_bytes());
+        bam.extend(record);
+        let mut reader = BamReader::new(&bam[..], Some(params))?;
+        let record = reader.next()?.unwrap();
+        assert_eq!(record.cigar, b"3S5M");
+        assert_eq!(record.stats, Some(stats));
+        let values: Vec<Value> = record.into();
+        assert_eq!(values.len(), 17);
+        assert_eq!(values[12], Value::Integer(15));
+        Ok(())
+    }
+
     #[test]
     fn test_sam_no_data() -> Result<(), EtError> {
         let data = b"@HD\ttest\n";
@@ -462,6 +1274,28 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_sam_trailing_empty_field() -> Result<(), EtError> {
+        // the quality is the (empty) last field
+        let data = b"r1\t0\t*\t0\t255\t*\t*\t0\t0\tACGT\t\n";
+        let mut reader = SamReader::new(&data[..], None)?;
+        let record = reader.next()?.unwrap();
+        assert_eq!(record.sequence, b"ACGT");
+        assert_eq!(record.quality, b"");
+        assert_eq!(record.extra, &b""[..]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sam_truncated() -> Result<(), EtError> {
+        let data = b"r1\t0\t*\t0\t255\t*\t*\t0\t0\tACGT\tIIII\nr2\t0\t*\t0";
+        let mut reader = SamReader::new(&data[..], None)?;
+        assert!(reader.next()?.is_some());
+        let err = reader.next().unwrap_err();
+        assert!(matches!(err.kind, EtErrorKind::Truncated { .. }));
+        Ok(())
+    }
+
     #[test]
     fn test_sam_bad_fuzzes() -> Result<(), EtError> {
         const TEST_SAM: &[u8] = b"@HD\t\n\n";
@@ -503,7 +1337,26 @@ mod tests {
         assert_eq!(rb.sniff_filetype()?, FileType::Bam);
         assert_eq!(compress, Some(FileType::Gzip));
         let mut reader = BamReader::new(rb, None)?;
-        let _ = reader.metadata();
+        let metadata = reader.metadata();
+        assert_eq!(metadata["sam_version"], "1.6".into());
+        assert_eq!(metadata["sort_order"], "coordinate".into());
+        assert_eq!(
+            metadata["references"],
+            vec![Value::from("gi|9626372|ref|NC_001422.1|")].into()
+        );
+        assert_eq!(
+            metadata["reference_lengths"],
+            vec![Value::from(5386u64)].into()
+        );
+        assert_eq!(
+            metadata["programs"],
+            vec![
+                "minimap2".to_string(),
+                "samtools".to_string(),
+                "samtools.1".to_string()
+            ]
+            .into()
+        );
 
         if let Some(BamRecord {
             query_name,
@@ -526,6 +1379,154 @@ mod tests {
         Ok(())
     }
 
+    #[cfg(all(feature = "compression", feature = "std", feature = "serde"))]
+    #[test]
+    fn test_bam_serde() -> Result<(), EtError> {
+        use std::fs::File;
+
+        use crate::compression::decompress;
+
+        let (rb, _) = decompress(File::open("tests/data/test.bam")?)?;
+        let mut reader = BamReader::new(rb, None)?;
+        let record = reader.next()?.unwrap();
+        let packed = rmp_serde::to_vec_named(&record).unwrap();
+        let unpacked: BamRecord = rmp_serde::from_slice(&packed).unwrap();
+        assert_eq!(unpacked.query_name, "SRR062634.1");
+        assert_eq!(unpacked.sequence, KNOWN_SEQ);
+        assert_eq!(unpacked.cigar, record.cigar);
+        assert_eq!(unpacked.pos, record.pos);
+        Ok(())
+    }
+
+    /// An unpaired alignment of a three base read named `name` (with a CIGAR of `len` matches)
+    #[cfg(feature = "std")]
+    fn bam_record(name: &str, ref_id: i32, pos: i32, len: u32) -> Vec<u8> {
+        let mut record = Vec::new();
+        record.extend(ref_id.to_le_bytes());
+        record.extend(pos.to_le_bytes());
+        record.extend([name.len() as u8 + 1, 60, 0, 0, 1, 0, 0, 0]);
+        record.extend(3_u32.to_le_bytes());
+        record.extend((-1_i32).to_le_bytes());
+        record.extend((-1_i32).to_le_bytes());
+        record.extend(0_i32.to_le_bytes());
+        record.extend(name.as_bytes());
+        record.push(0);
+        record.extend((len << 4).to_le_bytes());
+        record.extend([0x12, 0x40, 30, 30, 30]);
+        let mut data = (record.len() as u32).to_le_bytes().to_vec();
+        data.extend(record);
+        data
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_bam_seek_region() -> Result<(), EtError> {
+        use std::io::Cursor;
+
+        use crate::bgzf::{compress_block, VirtualOffset};
+
+        let mut header = b"BAM\x01\x00\x00\x00\x00\x02\x00\x00\x00".to_vec();
+        for (name, len) in [("chr1", 100_000_u32), ("chr2", 50_000)] {
+            header.extend((name.len() as u32 + 1).to_le_bytes());
+            header.extend(name.as_bytes());
+            header.push(0);
+            header.extend(len.to_le_bytes());
+        }
+        let r1 = bam_record("r1", 0, 100, 50);
+        let r2 = bam_record("r2", 0, 1000, 50);
+        let r3 = bam_record("r3", 0, 20_000, 50);
+        let r4 = bam_record("r4", 1, 10, 50);
+        let mut data = compress_block(&header);
+        let b1 = data.len() as u64;
+        data.extend(compress_block(&[&r1[..], &r2].concat()));
+        let b2 = data.len() as u64;
+        data.extend(compress_block(&[&r3[..], &r4].concat()));
+        let b3 = data.len() as u64;
+        data.extend(compress_block(b""));
+
+        // r1 and r2 are in the first 16 kb bin, r3 is in the second, and r4 is on chr2
+        let v = |block: u64, offset: usize| VirtualOffset::new(block, offset as u16).0;
+        let mut index = b"BAI\x01\x02\x00\x00\x00".to_vec();
+        for (bins, intervals) in [
+            (
+                vec![
+                    (4681_u32, v(b1, 0), v(b2, 0)),
+                    (4682, v(b2, 0), v(b2, r3.len())),
+                ],
+                vec![v(b1, 0), v(b2, 0)],
+            ),
+            (
+                vec![(4681, v(b2, r3.len()), v(b3, 0))],
+                vec![v(b2, r3.len())],
+            ),
+        ] {
+            index.extend((bins.len() as u32).to_le_bytes());
+            for (bin, start, end) in bins {
+                index.extend(bin.to_le_bytes());
+                index.extend(1_u32.to_le_bytes());
+                index.extend(start.to_le_bytes());
+                index.extend(end.to_le_bytes());
+            }
+            index.extend((intervals.len() as u32).to_le_bytes());
+            for offset in intervals {
+                index.extend(offset.to_le_bytes());
+            }
+        }
+        let index = BaiIndex::from_slice(&index)?;
+
+        let names = |reader: &mut BamReader| -> Result<Vec<String>, EtError> {
+            let mut names = Vec::new();
+            while let Some(record) = reader.next()? {
+                names.push(format!("{}:{}", record.query_name, record.ref_name));
+            }
+            Ok(names)
+        };
+        let mut reader = BamReader::with_index(Cursor::new(data), index)?;
+        assert_eq!(
+            names(&mut reader)?,
+            ["r1:chr1", "r2:chr1", "r3:chr1", "r4:chr2"]
+        );
+        // r1 is in the same chunk as r2, but doesn't overlap the region
+        reader.seek_region("chr1", 900, 1100)?;
+        assert_eq!(names(&mut reader)?, ["r2:chr1"]);
+        reader.seek_region("chr1", 0, 30_000)?;
+        assert_eq!(names(&mut reader)?, ["r1:chr1", "r2:chr1", "r3:chr1"]);
+        reader.seek_region("chr2", 0, 100)?;
+        assert_eq!(names(&mut reader)?, ["r4:chr2"]);
+        reader.seek_region("chr2", 1000, 2000)?;
+        assert!(reader.next()?.is_none());
+        assert!(reader.seek_region("chr3", 0, 100).is_err());
+
+        // readers without an index can't seek
+        let data = [&header[..], &r1].concat();
+        let mut reader = BamReader::new(&data[..], None)?;
+        assert!(reader.seek_region("chr1", 0, 1).is_err());
+        let record = reader.next()?.unwrap();
+        assert_eq!(record.cigar, b"50M");
+        assert_eq!(record.sequence, b"ACG");
+        Ok(())
+    }
+
+    #[cfg(all(feature = "compression", feature = "std"))]
+    #[test]
+    fn test_bam_references() -> Result<(), EtError> {
+        use std::fs::File;
+
+        use crate::compression::decompress;
+
+        let f = File::open("tests/data/test.bam")?;
+        let (rb, _) = decompress(f)?;
+        let mut reader = BamReferenceReader::new(rb, None)?;
+        assert_eq!(reader.headers(), ["ref_name", "length"]);
+        assert_eq!(reader.size_hint(), (1, Some(1)));
+        let reference = reader.next()?.expect("a reference");
+        assert_eq!(reference.ref_name, "gi|9626372|ref|NC_001422.1|");
+        assert_eq!(reference.length, 5386);
+        assert!(reader.next()?.is_none());
+        assert!("contigs".parse::<BamStream>().is_err());
+        Ok(())
+    }
+
     #[cfg(all(feature = "compression", feature = "std"))]
     #[test]
     fn test_bam_fuzz_errors() -> Result<(), EtError> {
@@ -0,0 +1,261 @@
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use core::mem;
+
+use crate::buffer::ReadBuffer;
+use crate::capabilities::Capabilities;
+use crate::parsers::datetime::DateParser;
+use crate::parsers::tsv::{TsvParams, TsvReader};
+use crate::readers::RecordReader;
+use crate::record::Value;
+use crate::EtError;
+
+/// Headers (compared case-insensitively) that are recognized as the time of each reading
+const TIME_HEADERS: &[&str] = &["time", "timestamp", "datetime", "date_time", "date"];
+/// Headers that are recognized as the name of the sensor in a file with one reading per line
+const SENSOR_HEADERS: &[&str] = &["sensor", "sensor_id", "sensor_name", "channel", "variable"];
+/// Headers that are recognized as the reading in a file with one reading per line
+const VALUE_HEADERS: &[&str] = &["value", "reading", "measurement"];
+
+/// Parameters for reading sensor logs
+#[derive(Clone, Debug, Default)]
+pub struct SensorLogParams {
+    /// How to split the file into fields
+    pub tsv: TsvParams,
+    /// The column with the time of each reading (if not set, the first column with a name like
+    /// `time` or `timestamp` is used, or else the first column)
+    pub time_column: Option<String>,
+}
+
+impl SensorLogParams {
+    /// Set which column has the time of each reading
+    #[must_use]
+    pub fn time_column(mut self, column: &str) -> Self {
+        self.time_column = Some(column.into());
+        self
+    }
+}
+
+/// How the readings are laid out in the file
+#[derive(Clone, Debug)]
+enum Layout {
+    /// One reading per line, with columns for the name of the sensor and its value
+    Long { sensor: usize, value: usize },
+    /// One line per time, with a column for each sensor (the index and name of each)
+    Wide(Vec<(usize, String)>),
+}
+
+/// Is `value` missing (i.e. null or an empty field)?
+fn is_missing(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::String(s) => s.trim().is_empty(),
+        _ => false,
+    }
+}
+
+/// Reads delimited logs of timestamped sensor readings into `time`, `sensor`, and `value`
+/// columns.
+///
+/// Logs can either have one reading per line (with `sensor` and `value` columns) or one line per
+/// time with a column for every sensor (which is split into one record per reading, skipping
+/// empty fields). Text times are parsed into dates (in UTC if they have an offset); numeric
+/// times (e.g. seconds since the logger started) are kept as-is.
+#[derive(Debug)]
+pub struct SensorLogReader<'r> {
+    reader: TsvReader<'r>,
+    time_col: usize,
+    layout: Layout,
+    dates: DateParser,
+    /// The current line of a wide file, with its time already parsed
+    row: Vec<Value<'static>>,
+    /// The next sensor column to read from `row`
+    next_sensor: usize,
+}
+
+impl<'r> SensorLogReader<'r> {
+    /// Create a new `SensorLogReader`
+    ///
+    /// # Errors
+    /// If the file can't be read as a delimited file or doesn't have the time column given in
+    /// `params`, an error is returned.
+    pub fn new<B>(data: B, params: Option<SensorLogParams>) -> Result<Self, EtError>
+    where
+        B: TryInto<ReadBuffer<'r>>,
+        EtError: From<<B as TryInto<ReadBuffer<'r>>>::Error>,
+    {
+        let params = params.unwrap_or_default();
+        let reader = TsvReader::new(data, Some(params.tsv))?;
+        let headers = reader.headers();
+        let find = |names: &[&str]| {
+            headers
+                .iter()
+                .position(|h| names.iter().any(|n| h.trim().eq_ignore_ascii_case(n)))
+        };
+        let time_col = match &params.time_column {
+            Some(column) => headers
+                .iter()
+                .position(|h| h == column)
+                .ok_or_else(|| format!("No time column {} in the sensor log", column))?,
+            None => find(TIME_HEADERS).unwrap_or(0),
+        };
+        let layout = match (find(SENSOR_HEADERS), find(VALUE_HEADERS)) {
+            (Some(sensor), Some(value)) => Layout::Long { sensor, value },
+            _ => Layout::Wide(
+                headers
+                    .iter()
+                    .enumerate()
+                    .filter(|(ix, _)| *ix != time_col)
+                    .map(|(ix, header)| (ix, header.clone()))
+                    .collect(),
+            ),
+        };
+        Ok(SensorLogReader {
+            reader,
+            time_col,
+            layout,
+            dates: DateParser::default(),
+            row: Vec::new(),
+            next_sensor: 0,
+        })
+    }
+}
+
+/// Make all numeric readings floats (so the `value` column has one type even if some sensors
+/// only report integers), including numbers in text (columns with missing values aren't typed as
+/// numbers when the file is read)
+fn parse_reading(value: Value<'_>) -> Value<'_> {
+    match value {
+        Value::Integer(i) => Value::Float(i as f64),
+        Value::String(s) => match s.trim().parse::<f64>() {
+            Ok(number) => Value::Float(number),
+            Err(_) => Value::String(s),
+        },
+        v => v,
+    }
+}
+
+/// Parse text times into dates
+fn parse_time<'a>(dates: &DateParser, value: Value<'a>) -> Result<Value<'a>, EtError> {
+    match value {
+        Value::String(s) if s.trim().is_empty() => Ok(Value::Null),
+        Value::String(s) => Ok(dates.parse_utc(&s)?.into()),
+        v => Ok(v),
+    }
+}
+
+impl<'r> RecordReader for SensorLogReader<'r> {
+    fn next_record(&mut self) -> Result<Option<Vec<Value<'_>>>, EtError> {
+        let columns = match &self.layout {
+            Layout::Long { sensor, value } => {
+                let (sensor, value) = (*sensor, *value);
+                let mut record = match self.reader.next_record()? {
+                    Some(record) => record,
+                    None => return Ok(None),
+                };
+                let mut take = |ix: usize| {
+                    record
+                        .get_mut(ix)
+                        .map_or(Value::Null, |v| mem::replace(v, Value::Null))
+                };
+                let time = parse_time(&self.dates, take(self.time_col))?;
+                return Ok(Some(vec![time, take(sensor), parse_reading(take(value))]));
+            }
+            Layout::Wide(columns) => columns,
+        };
+        loop {
+            while let Some((ix, name)) = columns.get(self.next_sensor) {
+                self.next_sensor += 1;
+                let value = self.row.get(*ix).unwrap_or(&Value::Null);
+                if is_missing(value) {
+                    continue;
+                }
+                return Ok(Some(vec![
+                    self.row[self.time_col].clone(),
+                    name.as_str().into(),
+                    parse_reading(value.clone()),
+                ]));
+            }
+            let record = match self.reader.next_record()? {
+                Some(record) => record,
+                None => return Ok(None),
+            };
+            let mut row: Vec<Value<'static>> = record.into_iter().map(Value::into_owned).collect();
+            if let Some(time) = row.get_mut(self.time_col) {
+                *time = parse_time(&self.dates, mem::replace(time, Value::Null))?;
+            }
+            self.row = row;
+            self.next_sensor = 0;
+        }
+    }
+
+    fn bytes_read(&self) -> Option<u64> {
+        self.reader.bytes_read()
+    }
+
+    fn headers(&self) -> Vec<String> {
+        vec!["time".into(), "sensor".into(), "value".into()]
+    }
+
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        self.reader.metadata()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.reader.capabilities()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_wide_sensor_log() -> Result<(), EtError> {
+        let data =
+            b"timestamp,temp_c,humidity\n2021-05-01T10:00:00Z,12.5,80\n2021-05-01T10:01:00Z,,81\n";
+        let mut reader = SensorLogReader::new(&data[..], None)?;
+        assert_eq!(reader.headers(), ["time", "sensor", "value"]);
+        let time = NaiveDate::from_ymd_opt(2021, 5, 1)
+            .unwrap()
+            .and_hms_opt(10, 0, 0)
+            .unwrap();
+        assert_eq!(
+            reader.next_record()?,
+            Some(vec![time.into(), "temp_c".into(), 12.5.into()])
+        );
+        assert_eq!(
+            reader.next_record()?,
+            Some(vec![time.into(), "humidity".into(), 80.0.into()])
+        );
+        // the missing temperature is skipped
+        let record = reader.next_record()?.unwrap();
+        assert_eq!(record[1], "humidity".into());
+        assert!(reader.next_record()?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_long_sensor_log() -> Result<(), EtError> {
+        let data = b"Sensor,Value,Time\nT1,12.5,0.5\nT2,3,1.5\n";
+        let mut reader = SensorLogReader::new(&data[..], None)?;
+        assert_eq!(
+            reader.next_record()?,
+            Some(vec![0.5.into(), "T1".into(), 12.5.into()])
+        );
+        assert_eq!(
+            reader.next_record()?,
+            Some(vec![1.5.into(), "T2".into(), 3.0.into()])
+        );
+        assert!(reader.next_record()?.is_none());
+
+        let params = SensorLogParams::default().time_column("when");
+        assert!(SensorLogReader::new(&data[..], Some(params)).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,98 @@
+use alloc::borrow::Cow;
+use alloc::format;
+use alloc::string::String;
+use core::char::decode_utf16;
+use core::str::{from_utf8, FromStr};
+
+use crate::EtError;
+
+/// How to handle text fields (e.g. sample or operator names) that aren't validly encoded
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum TextDecoding {
+    /// Return an error for any invalid text
+    Strict,
+    /// Read 8-bit text that isn't valid UTF-8 as Latin-1 (which most instrument software running
+    /// on Windows writes) and replace invalid UTF-16 with U+FFFD
+    #[default]
+    Lossy,
+}
+
+impl FromStr for TextDecoding {
+    type Err = EtError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "strict" => Ok(TextDecoding::Strict),
+            "lossy" => Ok(TextDecoding::Lossy),
+            x => Err(format!("Unknown text decoding {}; expected strict or lossy", x).into()),
+        }
+    }
+}
+
+impl TextDecoding {
+    /// Decode 8-bit text; this is only copied if it isn't valid UTF-8.
+    ///
+    /// # Errors
+    /// If the text is not valid UTF-8 and decoding is `Strict`, an error is returned.
+    pub fn decode<'a>(self, bytes: &'a [u8]) -> Result<Cow<'a, str>, EtError> {
+        match (from_utf8(bytes), self) {
+            (Ok(text), _) => Ok(Cow::Borrowed(text)),
+            (Err(e), TextDecoding::Strict) => Err(e.into()),
+            // every byte in Latin-1 maps to the Unicode code point with the same value
+            (Err(_), TextDecoding::Lossy) => {
+                Ok(Cow::Owned(bytes.iter().map(|b| char::from(*b)).collect()))
+            }
+        }
+    }
+
+    /// Decode little-endian UTF-16 text (a trailing odd byte is ignored).
+    ///
+    /// # Errors
+    /// If the text contains unpaired surrogates and decoding is `Strict`, an error is returned.
+    pub fn decode_utf16le(self, bytes: &[u8]) -> Result<String, EtError> {
+        let units = bytes
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]));
+        let mut text = String::with_capacity(bytes.len() / 2);
+        for c in decode_utf16(units) {
+            match (c, self) {
+                (Ok(c), _) => text.push(c),
+                (Err(e), TextDecoding::Strict) => {
+                    return Err(format!("Invalid UTF-16 text: {}", e).into())
+                }
+                (Err(_), TextDecoding::Lossy) => text.push(char::REPLACEMENT_CHARACTER),
+            }
+        }
+        Ok(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() -> Result<(), EtError> {
+        assert!(matches!(
+            TextDecoding::Strict.decode(b"Jos\xC3\xA9")?,
+            Cow::Borrowed("José")
+        ));
+        // Latin-1
+        assert!(TextDecoding::Strict.decode(b"Jos\xE9").is_err());
+        assert_eq!(TextDecoding::Lossy.decode(b"Jos\xE9")?, "José");
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_utf16le() -> Result<(), EtError> {
+        let text = b"J\x00o\x00s\x00\xE9\x00";
+        assert_eq!(TextDecoding::Strict.decode_utf16le(text)?, "José");
+        assert_eq!(TextDecoding::Strict.decode_utf16le(&text[..7])?, "Jos");
+
+        // an unpaired surrogate
+        let text = b"J\x00\x00\xD8o\x00";
+        assert!(TextDecoding::Strict.decode_utf16le(text).is_err());
+        assert_eq!(TextDecoding::Lossy.decode_utf16le(text)?, "J\u{FFFD}o");
+        Ok(())
+    }
+}
@@ -1,3 +1,6 @@
+/// Builders for synthetic Thermo files to test parsing against
+#[cfg(test)]
+mod synthetic;
 /// Parsers for files from Thermo's isotopic mass specs
 pub mod thermo_iso;
 /// Parsers for Thermo "RAW" format
@@ -0,0 +1,201 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// A scan in a `SyntheticRaw` file
+#[derive(Clone, Debug, Default)]
+pub(crate) struct SyntheticScan {
+    pub(crate) time: f64,
+    /// The frequency (or m/z if there are no coefficients) of the first point
+    pub(crate) first_freq: f64,
+    pub(crate) freq_step: f64,
+    /// The intensity of each point; if there are none, the scan is written without any data
+    pub(crate) intensities: Vec<f32>,
+    /// An offset added to the m/z of every point in the scan
+    pub(crate) adjustment: Option<f32>,
+    /// The MS level of the scan (the scan event is written as a positive-mode ESI FTMS scan)
+    pub(crate) ms_level: u8,
+    /// The precursor m/z and collision energy for an MS2 scan
+    pub(crate) precursor: Option<(f64, f64)>,
+}
+
+/// A builder for minimal Thermo RAW files laid out the way `ThermoRawReader` reads them, to
+/// test the parts of the format that change between versions without needing (unshareable)
+/// example files for each one.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct SyntheticRaw {
+    pub(crate) version: u32,
+    /// The `a`, `b`, and `c` terms for converting frequencies into m/zs (with four
+    /// coefficients); if `None`, the frequencies are the m/zs
+    pub(crate) coeffs: Option<[f64; 3]>,
+    pub(crate) scans: Vec<SyntheticScan>,
+}
+
+fn push_u32(data: &mut Vec<u8>, value: u32) {
+    data.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_f64(data: &mut Vec<u8>, value: f64) {
+    data.extend_from_slice(&value.to_le_bytes());
+}
+
+/// Write a file offset into `data` at `pos` (as a u64 in newer versions and a u32 in older)
+fn set_offset(data: &mut [u8], pos: usize, offset: usize, version: u32) {
+    if version >= 64 {
+        data[pos..pos + 8].copy_from_slice(&(offset as u64).to_le_bytes());
+    } else {
+        data[pos..pos + 4].copy_from_slice(&(offset as u32).to_le_bytes());
+    }
+}
+
+impl SyntheticRaw {
+    /// Lay out the header, the scan data, the scan metadata and coefficients, and then the
+    /// trailer.
+    pub(crate) fn build(&self) -> Vec<u8> {
+        let version = self.version;
+        let mut data = vec![0; 1420];
+        data[..2].copy_from_slice(b"\x01\xA1");
+        data[36..40].copy_from_slice(&version.to_le_bytes());
+        // empty strings for the file info (and an extra u32 in version 50 and up)
+        let n_strings = match version {
+            0..=49 => 13,
+            50..=59 => 16,
+            _ => 31,
+        };
+        data.resize(data.len() + 4 * n_strings, 0);
+        if version >= 50 {
+            push_u32(&mut data, 0);
+        }
+        let (data_start_pos, trailer_start_pos) = if version >= 64 {
+            data.resize(data.len() + 836, 0);
+            let data_start_pos = data.len();
+            data.resize(data.len() + 16, 0);
+            let trailer_start_pos = data.len();
+            data.resize(data.len() + 8, 0);
+            (data_start_pos, trailer_start_pos)
+        } else {
+            data.resize(data.len() + 52, 0);
+            let data_start_pos = data.len();
+            data.resize(data.len() + 20, 0);
+            let trailer_start_pos = data.len();
+            data.resize(data.len() + 4, 0);
+            (data_start_pos, trailer_start_pos)
+        };
+        let data_start = data.len();
+        set_offset(&mut data, data_start_pos, data_start, version);
+
+        for scan in &self.scans {
+            let n_points = scan.intensities.len() as u32;
+            push_u32(&mut data, 0);
+            // the size of the data (only checked for being 0)
+            let size_data = if n_points == 0 {
+                0
+            } else {
+                24 + 12 + 4 * n_points
+            };
+            push_u32(&mut data, size_data);
+            // one word of other data after the points
+            push_u32(&mut data, 1);
+            push_u32(&mut data, u32::from(scan.adjustment.is_some()) << 6);
+            data.resize(data.len() + 3 * 4 + 12, 0);
+            if n_points == 0 {
+                data.resize(data.len() + 4, 0);
+                continue;
+            }
+            // the first point is the chunk's offset (in steps) past the base frequency
+            push_f64(&mut data, scan.first_freq - scan.freq_step);
+            push_f64(&mut data, scan.freq_step);
+            // a single chunk with an offset of 1
+            push_u32(&mut data, 1);
+            push_u32(&mut data, 0);
+            push_u32(&mut data, 1);
+            push_u32(&mut data, n_points);
+            if let Some(adjustment) = scan.adjustment {
+                data.extend_from_slice(&adjustment.to_le_bytes());
+            }
+            for intensity in &scan.intensities {
+                data.extend_from_slice(&intensity.to_le_bytes());
+            }
+            data.resize(data.len() + 4, 0);
+        }
+
+        let metadata_start = data.len();
+        let metadata_len = match version {
+            0..=63 => 72,
+            64 | 65 => 80,
+            _ => 88,
+        };
+        for scan in &self.scans {
+            let start = data.len();
+            data.resize(start + metadata_len, 0);
+            data[start + 24..start + 32].copy_from_slice(&scan.time.to_le_bytes());
+            let high_mz = scan.first_freq + scan.freq_step * scan.intensities.len() as f64;
+            data[start + 56..start + 64].copy_from_slice(&scan.first_freq.to_le_bytes());
+            data[start + 64..start + 72].copy_from_slice(&high_mz.to_le_bytes());
+        }
+
+        let coeffs_start = data.len();
+        push_u32(&mut data, self.scans.len() as u32);
+        let coeffs_len = match version {
+            0..=56 => 44,
+            57..=61 => 84,
+            62 => 124,
+            63..=65 => 132,
+            _ => 140,
+        };
+        let reaction_len = if version >= 66 { 56 } else { 32 };
+        for scan in &self.scans {
+            let start = data.len();
+            data.resize(start + coeffs_len, 0);
+            // positive mode, profile, the MS level, and ESI
+            data[start + 4] = 1;
+            data[start + 5] = 1;
+            data[start + 6] = scan.ms_level;
+            data[start + 11] = 3;
+            if coeffs_len > 44 {
+                // FTMS
+                data[start + 40] = 4;
+            }
+            if let Some((precursor_mz, energy)) = scan.precursor {
+                data[start + coeffs_len - 4] = 1;
+                push_f64(&mut data, precursor_mz);
+                push_f64(&mut data, 2.);
+                push_f64(&mut data, energy);
+                data.resize(data.len() + reaction_len - 24, 0);
+            }
+            data.resize(data.len() + 20, 0);
+            match self.coeffs {
+                Some([a, b, c]) => {
+                    push_u32(&mut data, 4);
+                    data.resize(data.len() + 8, 0);
+                    for coeff in [a, b, c, 0.] {
+                        push_f64(&mut data, coeff);
+                    }
+                }
+                None => {
+                    push_u32(&mut data, 0);
+                    data.resize(data.len() + 8, 0);
+                }
+            }
+            if version >= 66 {
+                data.resize(data.len() + 4, 0);
+            }
+        }
+
+        let trailer_start = data.len();
+        set_offset(&mut data, trailer_start_pos, trailer_start, version);
+        data.resize(
+            trailer_start + 592 + if version >= 64 { 6980 } else { 6816 },
+            0,
+        );
+        let trailer = &mut data[trailer_start..];
+        trailer[12..16].copy_from_slice(&(self.scans.len() as u32).to_le_bytes());
+        if version >= 64 {
+            set_offset(trailer, 7448, coeffs_start, version);
+            set_offset(trailer, 7408, metadata_start, version);
+        } else {
+            set_offset(trailer, 7368, coeffs_start, version);
+            set_offset(trailer, 28, metadata_start, version);
+        }
+        data
+    }
+}
@@ -1,13 +1,16 @@
-use alloc::borrow::{Cow, ToOwned};
+use alloc::borrow::Cow;
 use alloc::format;
-use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
-use core::char::{decode_utf16, REPLACEMENT_CHARACTER};
 use core::marker::Copy;
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::capabilities::Capabilities;
 use crate::parsers::common::{SeekPattern, Skip};
-use crate::parsers::{extract, extract_opt, Endian, FromSlice};
+use crate::parsers::text::TextDecoding;
+use crate::parsers::{extract, extract_opt, extract_with, Endian, FromSlice};
 use crate::record::StateMetadata;
 use crate::EtError;
 use crate::{impl_reader, impl_record};
@@ -17,7 +20,7 @@ use crate::{impl_reader, impl_record};
 pub struct MfcString<'r>(Cow<'r, str>);
 
 impl<'b: 's, 's> FromSlice<'b, 's> for MfcString<'s> {
-    type State = ();
+    type State = TextDecoding;
 
     fn parse(
         rb: &[u8],
@@ -48,7 +51,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for MfcString<'s> {
         Ok(true)
     }
 
-    fn get(&mut self, rb: &'b [u8], _state: &'s Self::State) -> Result<(), EtError> {
+    fn get(&mut self, rb: &'b [u8], decoding: &'s Self::State) -> Result<(), EtError> {
         let (start, utf16) = if rb[0] != 0xFF {
             (1, false)
         } else if rb[1..3] == [0xFF, 0xFF] {
@@ -59,18 +62,11 @@ impl<'b: 's, 's> FromSlice<'b, 's> for MfcString<'s> {
             return Err("Unknown string header".into());
         };
 
-        let string = if utf16 {
-            let iter = (start..rb.len())
-                .step_by(2)
-                .map(|i| u16::from_le_bytes([rb[i], rb[i + 1]]));
-            decode_utf16(iter)
-                .map(|r| r.unwrap_or(REPLACEMENT_CHARACTER))
-                .collect::<String>()
-                .into()
+        self.0 = if utf16 {
+            decoding.decode_utf16le(&rb[start..])?.into()
         } else {
-            alloc::str::from_utf8(&rb[start..])?.into()
+            decoding.decode(&rb[start..])?
         };
-        self.0 = string;
         Ok(())
     }
 }
@@ -96,6 +92,7 @@ pub struct ThermoDxfState {
     cur_time: f64,
     cur_mz: f64,
     cur_intensity: f64,
+    text_decoding: TextDecoding,
 }
 
 impl Default for ThermoDxfState {
@@ -108,22 +105,33 @@ impl Default for ThermoDxfState {
             cur_time: 0.,
             cur_mz: 0.,
             cur_intensity: 0.,
+            text_decoding: TextDecoding::default(),
         }
     }
 }
 
 impl StateMetadata for ThermoDxfState {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::TYPED_SCHEMA
+    }
+
     fn header(&self) -> Vec<&str> {
         vec!["time", "mz", "intensity"]
     }
 }
 
 impl<'b: 's, 's> FromSlice<'b, 's> for ThermoDxfState {
-    type State = ();
+    type State = TextDecoding;
+
+    fn get(&mut self, _buf: &'b [u8], text_decoding: &'s Self::State) -> Result<(), EtError> {
+        self.text_decoding = *text_decoding;
+        Ok(())
+    }
 }
 
 /// A single data point from a Thermo DXF file
 #[derive(Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ThermoDxfRecord {
     /// The time the reading was taken at
     pub time: f64,
@@ -149,6 +157,10 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ThermoDxfRecord {
             // it appears the last u32 before the `FFFF04`... CRawData header
             // is the number of sections in the data, but
             if state.first {
+                if eof && rb.is_empty() {
+                    // an empty file just doesn't have any scans
+                    return Ok(false);
+                }
                 if extract_opt::<SeekPattern>(rb, eof, con, &mut &b"CRawData"[..])?.is_none() {
                     return Err("Could not find CRawData section".into());
                 }
@@ -173,7 +185,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ThermoDxfRecord {
                 let _ = extract::<&[u8]>(rb, con, &mut 16)?;
             }
 
-            let mfc_state = &mut ();
+            let mfc_state = &mut { state.text_decoding };
             let MfcString(gas_name) = extract(rb, con, mfc_state)?;
             if gas_name == "" {
                 return Ok(false);
@@ -184,7 +196,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ThermoDxfRecord {
             // `FFFEFF00` and then three u32s (values 0, 1, 1)
             let _ = extract::<Skip>(rb, con, &mut 16)?;
 
-            if extract::<u8>(rb, con, &mut Endian::Little)? == 0xFF {
+            if extract_with::<u8>(rb, con, &Endian::Little)? == 0xFF {
                 // CEvalGasData header and the u32 (value 1)
                 let _ = extract::<Skip>(rb, con, &mut 20)?;
             } else {
@@ -192,7 +204,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ThermoDxfRecord {
                 let _ = extract::<Skip>(rb, con, &mut 6)?;
             }
 
-            let bytes_data = extract::<u32>(rb, con, &mut Endian::Little)? as usize;
+            let bytes_data = extract_with::<u32>(rb, con, &Endian::Little)? as usize;
             state.n_scans_left = bytes_data / (4 + 8 * state.mzs.len());
             if state.n_scans_left == 0 {
                 // this was caught by fuzzing; not sure if real files have this issue
@@ -202,11 +214,11 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ThermoDxfRecord {
         }
         state.n_scans_left -= 1;
         if state.cur_mz_idx == 0 {
-            state.cur_time = f64::from(extract::<f32>(rb, con, &mut Endian::Little)?);
+            state.cur_time = f64::from(extract_with::<f32>(rb, con, &Endian::Little)?);
         }
 
         state.cur_mz = state.mzs[state.cur_mz_idx];
-        state.cur_intensity = extract::<f64>(rb, con, &mut Endian::Little)?;
+        state.cur_intensity = extract_with::<f64>(rb, con, &Endian::Little)?;
         state.cur_mz_idx = (state.cur_mz_idx + 1) % state.mzs.len();
         *consumed += *con;
         Ok(true)
@@ -225,7 +237,7 @@ impl_reader!(
     ThermoDxfRecord,
     ThermoDxfRecord,
     ThermoDxfState,
-    ()
+    TextDecoding
 );
 
 /// The current state of the `ThermoCfReader`
@@ -237,20 +249,31 @@ pub struct ThermoCfState {
     cur_time: f64,
     cur_mz: f64,
     cur_intensity: f64,
+    text_decoding: TextDecoding,
 }
 
 impl StateMetadata for ThermoCfState {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::TYPED_SCHEMA
+    }
+
     fn header(&self) -> Vec<&str> {
         vec!["time", "mz", "intensity"]
     }
 }
 
 impl<'b: 's, 's> FromSlice<'b, 's> for ThermoCfState {
-    type State = ();
+    type State = TextDecoding;
+
+    fn get(&mut self, _buf: &'b [u8], text_decoding: &'s Self::State) -> Result<(), EtError> {
+        self.text_decoding = *text_decoding;
+        Ok(())
+    }
 }
 
 /// A single data point from a Thermo CF file
 #[derive(Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ThermoCfRecord {
     /// The time the reading was taken at
     pub time: f64,
@@ -286,7 +309,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ThermoCfRecord {
             // pattern and then 3 u32's (values 0, 2, 2)
             let _ = extract::<&[u8]>(rb, con, &mut 36)?;
             // read the title and an additional `030000002C00`
-            if extract::<u8>(rb, con, &mut Endian::Little)? == 0xFF {
+            if extract_with::<u8>(rb, con, &Endian::Little)? == 0xFF {
                 // CRawDataScanStorage title
                 let _ = extract::<&[u8]>(rb, con, &mut 34)?;
             } else {
@@ -295,14 +318,16 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ThermoCfRecord {
             }
             // Now there's a CString with the type of the gas
             // remove "Trace Data" from the front of the string
-            let gas_type = extract::<MfcString>(rb, con, &mut ())?.0[11..].to_owned();
-            state.mzs = mzs_from_gas(&gas_type)?;
+            let mfc_state = &mut { state.text_decoding };
+            let MfcString(gas_type) = extract(rb, con, mfc_state)?;
+            let gas_type = gas_type.get(11..).ok_or("Gas type name too short")?;
+            state.mzs = mzs_from_gas(gas_type)?;
 
             // then 4 u32's (0, 2, 0, 4) and a FEF0 block
             let _ = extract::<&[u8]>(rb, con, &mut 20)?;
-            state.n_scans_left = extract::<u32>(rb, con, &mut Endian::Little)? as usize;
+            state.n_scans_left = extract_with::<u32>(rb, con, &Endian::Little)? as usize;
             // sanity check our guess for the masses
-            let n_mzs = extract::<u32>(rb, con, &mut Endian::Little)? as usize;
+            let n_mzs = extract_with::<u32>(rb, con, &Endian::Little)? as usize;
             if n_mzs != state.mzs.len() {
                 return Err(format!("Gas type {} has bad information", gas_type).into());
             }
@@ -310,7 +335,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ThermoCfRecord {
             // then a CBinary header (or replacement sentinel) followed by a u32
             // (value 2), a FEF0 block, another u32 (value 2), and then the number
             // of bytes of data that follow (value = n_scans * (4 + 8 * n_mzs))
-            if extract::<u8>(rb, con, &mut Endian::Little)? == 0xFF {
+            if extract_with::<u8>(rb, con, &Endian::Little)? == 0xFF {
                 // CBinary title
                 let _ = extract::<&[u8]>(rb, con, &mut 28)?;
             } else {
@@ -320,11 +345,11 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ThermoCfRecord {
         }
         state.n_scans_left -= 1;
         if state.cur_mz_idx == 0 {
-            state.cur_time = f64::from(extract::<f32>(rb, con, &mut Endian::Little)?);
+            state.cur_time = f64::from(extract_with::<f32>(rb, con, &Endian::Little)?);
         }
 
         state.cur_mz = state.mzs[state.cur_mz_idx];
-        state.cur_intensity = extract::<f64>(rb, con, &mut Endian::Little)?;
+        state.cur_intensity = extract_with::<f64>(rb, con, &Endian::Little)?;
         state.cur_mz_idx = (state.cur_mz_idx + 1) % state.mzs.len();
         *consumed += *con;
         Ok(true)
@@ -343,7 +368,7 @@ impl_reader!(
     ThermoCfRecord,
     ThermoCfRecord,
     ThermoCfState,
-    ()
+    TextDecoding
 );
 
 #[cfg(test)]
@@ -372,6 +397,22 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_thermo_dxf_text_decoding() -> Result<(), EtError> {
+        let mut data = include_bytes!("../../../tests/data/b3_alkanes.dxf").to_vec();
+        // swap the "C" in the "CO2" gas name for an unpaired UTF-16 surrogate
+        data[36174..36176].copy_from_slice(&[0x00, 0xD8]);
+
+        let mut reader = ThermoDxfReader::new(&data[..], Some(TextDecoding::Strict))?;
+        let e = reader.next().unwrap_err();
+        assert!(e.msg.contains("Invalid UTF-16"));
+
+        let mut reader = ThermoDxfReader::new(&data[..], Some(TextDecoding::Lossy))?;
+        let e = reader.next().unwrap_err();
+        assert!(e.msg.contains("Gas type \u{FFFD}O2 not supported"));
+        Ok(())
+    }
+
     #[test]
     fn test_thermo_dxf_bad_fuzzes() -> Result<(), EtError> {
         let test_data = [
@@ -2,21 +2,30 @@ use alloc::collections::BTreeMap;
 use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 use alloc::{format, vec};
-use core::char::{decode_utf16, REPLACEMENT_CHARACTER};
 use core::convert::TryFrom;
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::capabilities::Capabilities;
 use crate::parsers::common::{EndOfFile, Skip};
-use crate::parsers::{extract, Endian, FromSlice};
+use crate::parsers::text::TextDecoding;
+use crate::parsers::{extract, extract_with, sub_or_err, Endian, FromSlice};
 use crate::record::{StateMetadata, Value};
 use crate::EtError;
 use crate::{impl_reader, impl_record};
 
+/// The length of the trailer for files with a version >= 64
+const TRAILER_LEN_V64: usize = 592 + 6980;
+/// The length of the trailer for files with a version >= 50
+const TRAILER_LEN_V50: usize = 592 + 6816;
+
 /// A UTF-16 string with a u32 header describing its length
 #[derive(Debug, Default)]
 pub struct PascalString16(String);
 
 impl<'b: 's, 's> FromSlice<'b, 's> for PascalString16 {
-    type State = ();
+    type State = TextDecoding;
 
     fn parse(
         buffer: &[u8],
@@ -24,30 +33,41 @@ impl<'b: 's, 's> FromSlice<'b, 's> for PascalString16 {
         consumed: &mut usize,
         _state: &mut Self::State,
     ) -> Result<bool, EtError> {
-        let length = usize::try_from(extract::<u32>(buffer, &mut 0, &mut Endian::Little)?)?;
-        if buffer.len() < 4 * 2 * length * 2 {
+        let length = usize::try_from(extract_with::<u32>(buffer, &mut 0, &Endian::Little)?)?;
+        // a u32 length followed by `length` UTF-16 code units
+        let total_len = length
+            .checked_mul(2)
+            .and_then(|l| l.checked_add(4))
+            .ok_or_else(|| EtError::from("PascalString length too large"))?;
+        if buffer.len() < total_len {
             return Err(EtError::from("PascalString ended abruptly").incomplete());
         }
-        *consumed += 4 + 2 * length;
+        *consumed += total_len;
         Ok(true)
     }
 
-    fn get(&mut self, buffer: &'b [u8], _state: &'s Self::State) -> Result<(), EtError> {
-        let iter = (4..buffer.len())
-            .step_by(2)
-            .map(|i| u16::from_le_bytes([buffer[i], buffer[i + 1]]));
-        self.0 = decode_utf16(iter)
-            .map(|r| r.unwrap_or(REPLACEMENT_CHARACTER))
-            .collect::<String>();
+    fn get(&mut self, buffer: &'b [u8], decoding: &'s Self::State) -> Result<(), EtError> {
+        self.0 = decoding.decode_utf16le(&buffer[4..])?;
         Ok(())
     }
 }
 
+/// Convert an offset into the file into a position in the buffer (which holds the whole file)
+fn buffer_pos(offset: u64) -> Result<usize, EtError> {
+    usize::try_from(offset).map_err(|_| {
+        format!(
+            "Offset {} is past the end of the largest file that can be read on this platform",
+            offset
+        )
+        .into()
+    })
+}
+
 /// The post-data trailer for a Thermo RAW file
 #[derive(Clone, Copy, Debug, Default)]
 pub struct ThermoRawTrailer {
-    metadata_start: usize,
-    coeffs_start: usize,
+    metadata_start: u64,
+    coeffs_start: u64,
     n_scans: usize,
     min_mz: f64,
     max_mz: f64,
@@ -64,14 +84,14 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ThermoRawTrailer {
         consumed: &mut usize,
         version: &mut Self::State,
     ) -> Result<bool, EtError> {
-        if *version >= 64 && buffer.len() < 592 + 6980 {
+        if *version >= 64 && buffer.len() < TRAILER_LEN_V64 {
             return Err(EtError::from("Trailer too short for version >= 64").incomplete());
         } else if *version >= 64 {
-            *consumed += 592 + 6980;
-        } else if *version >= 50 && buffer.len() < 592 + 6816 {
+            *consumed += TRAILER_LEN_V64;
+        } else if *version >= 50 && buffer.len() < TRAILER_LEN_V50 {
             return Err(EtError::from("Trailer too short for version >= 50").incomplete());
         } else if *version >= 50 {
-            *consumed += 592 + 6816;
+            *consumed += TRAILER_LEN_V50;
         } else {
             return Err("Version must be >= 50".into());
         }
@@ -81,21 +101,21 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ThermoRawTrailer {
 
     fn get(&mut self, buffer: &'b [u8], version: &'s Self::State) -> Result<(), EtError> {
         // 592 bytes for misc information
-        self.n_scans = usize::try_from(u32::extract(&buffer[12..16], &Endian::Little)?)?;
-        // OR? these seem to be the same: u32::extract(&buffer[7376..7380], &Endian::Little)?
-        self.min_mz = f64::extract(&buffer[56..64], &Endian::Little)?;
-        self.max_mz = f64::extract(&buffer[64..72], &Endian::Little)?;
-        self.min_time = f64::extract(&buffer[72..80], &Endian::Little)?;
-        self.max_time = f64::extract(&buffer[80..88], &Endian::Little)?;
+        self.n_scans = usize::try_from(extract_with::<u32>(buffer, &mut 12, &Endian::Little)?)?;
+        // OR? these seem to be the same: extract_with::<u32>(buffer, &mut 7376, &Endian::Little)?
+        self.min_mz = extract_with::<f64>(buffer, &mut 56, &Endian::Little)?;
+        self.max_mz = extract_with::<f64>(buffer, &mut 64, &Endian::Little)?;
+        self.min_time = extract_with::<f64>(buffer, &mut 72, &Endian::Little)?;
+        self.max_time = extract_with::<f64>(buffer, &mut 80, &Endian::Little)?;
         self.coeffs_start = if *version >= 64 {
-            usize::try_from(u64::extract(&buffer[7448..7456], &Endian::Little)?)?
+            extract_with::<u64>(buffer, &mut 7448, &Endian::Little)?
         } else {
-            usize::try_from(u32::extract(&buffer[7368..7372], &Endian::Little)?)?
+            u64::from(extract_with::<u32>(buffer, &mut 7368, &Endian::Little)?)
         };
         self.metadata_start = if *version >= 64 {
-            usize::try_from(u64::extract(&buffer[7408..7416], &Endian::Little)?)?
+            extract_with::<u64>(buffer, &mut 7408, &Endian::Little)?
         } else {
-            usize::try_from(u32::extract(&buffer[28..32], &Endian::Little)?)?
+            u64::from(extract_with::<u32>(buffer, &mut 28, &Endian::Little)?)
         };
 
         Ok(())
@@ -134,32 +154,104 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ThermoRawScanMetadata {
     }
 
     fn get(&mut self, buffer: &'b [u8], _version: &'s Self::State) -> Result<(), EtError> {
-        self.time = f64::extract(&buffer[24..32], &Endian::Little)?;
-        self.low_mz = f64::extract(&buffer[56..64], &Endian::Little)?;
-        self.high_mz = f64::extract(&buffer[64..72], &Endian::Little)?;
+        self.time = extract_with::<f64>(buffer, &mut 24, &Endian::Little)?;
+        self.low_mz = extract_with::<f64>(buffer, &mut 56, &Endian::Little)?;
+        self.high_mz = extract_with::<f64>(buffer, &mut 64, &Endian::Little)?;
         Ok(())
     }
 }
 
-/// Coefficients and other data about a scan
-#[derive(Clone, Copy, Debug, Default)]
+/// The names of the mass analyzers in scan events (in the order they're numbered)
+const ANALYZERS: &[&str] = &["ITMS", "TQMS", "SQMS", "TOFMS", "FTMS", "Sector"];
+/// The names of the ionization sources in scan events
+const IONIZATIONS: &[&str] = &[
+    "EI", "CI", "FAB", "ESI", "APCI", "NSI", "TSP", "FD", "MALDI", "GD",
+];
+/// The names of the scan types in scan events
+const SCAN_TYPES: &[&str] = &["Full", "Z", "SIM", "SRM", "CRM", "", "Q1MS", "Q3MS"];
+
+/// The length of the fixed part of a scan event (before its reactions) in each version
+fn scan_event_len(version: u32) -> usize {
+    if version >= 66 {
+        140
+    } else if version >= 63 {
+        132
+    } else if version >= 62 {
+        124
+    } else if version >= 57 {
+        84
+    } else {
+        44
+    }
+}
+
+/// The scan event for a scan: how it was acquired (its MS level, polarity, any precursors,
+/// etc.) and the coefficients to convert its frequencies into m/zs
+#[derive(Clone, Debug, Default)]
 pub struct ThermoRawScanCoeffs {
     n_coeffs: u32,
     a: f64,
     b: f64,
     c: f64,
+    /// Whether the scan was of positive ions
+    positive: bool,
+    /// Whether the scan is in profile mode (instead of centroided)
+    profile: bool,
+    /// The MS level (1 for MS1, 2 for MS2, etc.; 0 if it's unknown)
+    ms_level: u8,
+    scan_type: u8,
+    /// Whether the scan was data dependent
+    dependent: bool,
+    ionization: u8,
+    analyzer: Option<u8>,
+    /// The precursor m/z and collision energy of each reaction (for MSn scans)
+    reactions: Vec<(f64, f64)>,
 }
 
 impl ThermoRawScanCoeffs {
     /// Convert a raw signal to a m/z value
-    #[must_use]
-    pub fn to_mz(&self, n: f64) -> f64 {
-        match self.n_coeffs {
+    ///
+    /// # Errors
+    /// If the number of coefficients isn't one that's understood, an error is returned.
+    pub fn to_mz(&self, n: f64) -> Result<f64, EtError> {
+        Ok(match self.n_coeffs {
             0 => n,
             4 => self.a + self.b / n + self.c / n.powi(2),
             5 | 7 => self.a + self.b / n.powi(2) + self.c / n.powi(4),
-            _ => unreachable!("Unparseable number of coefficients"),
+            x => return Err(format!("Unparseable number of coefficients {}", x).into()),
+        })
+    }
+
+    /// A description of the scan like the filter lines Thermo's software shows, e.g.
+    /// `FTMS + p ESI d Full ms2 445.1200@35.00 [110.00-1000.00]` (the activation type of each
+    /// reaction isn't known, so it's left out of the precursors).
+    #[must_use]
+    pub fn filter(&self, low_mz: f64, high_mz: f64) -> String {
+        let mut parts = Vec::new();
+        if let Some(analyzer) = self.analyzer.and_then(|a| ANALYZERS.get(usize::from(a))) {
+            parts.push((*analyzer).to_string());
+        }
+        parts.push(if self.positive { "+" } else { "-" }.to_string());
+        parts.push(if self.profile { "p" } else { "c" }.to_string());
+        if let Some(ionization) = IONIZATIONS.get(usize::from(self.ionization)) {
+            parts.push((*ionization).to_string());
         }
+        if self.dependent {
+            parts.push("d".to_string());
+        }
+        if let Some(scan_type) = SCAN_TYPES.get(usize::from(self.scan_type)) {
+            parts.push((*scan_type).to_string());
+        }
+        parts.push(match self.ms_level {
+            0 | 1 => "ms".to_string(),
+            n => format!("ms{}", n),
+        });
+        for (precursor_mz, energy) in &self.reactions {
+            parts.push(format!("{:.4}@{:.2}", precursor_mz, energy));
+        }
+        parts.push(format!("[{:.2}-{:.2}]", low_mz, high_mz));
+        parts.retain(|p| !p.is_empty());
+        parts.join(" ")
     }
 }
 
@@ -172,43 +264,47 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ThermoRawScanCoeffs {
         consumed: &mut usize,
         (version, coeff_pos): &mut Self::State,
     ) -> Result<bool, EtError> {
-        let mut record_len = if *version >= 66 {
-            140
-        } else if *version >= 63 {
-            132
-        } else if *version >= 62 {
-            124
-        } else if *version >= 57 {
-            84
-        } else {
-            44
-        };
+        let mut record_len = scan_event_len(*version);
         if buffer.len() < record_len {
             return Err(EtError::from("Coefficient data incomplete").incomplete());
         }
 
-        let n_reactions =
-            usize::try_from(u32::extract(&buffer[record_len - 4..], &Endian::Little)?)?;
-        if *version >= 66 {
-            record_len += n_reactions * 56;
-        } else {
-            record_len += n_reactions * 32;
-        }
-        record_len += 24;
+        let too_large = || EtError::from("Coefficient data length too large");
+        let n_reactions = usize::try_from(extract_with::<u32>(
+            buffer,
+            &mut (record_len - 4),
+            &Endian::Little,
+        )?)?;
+        let reaction_len = if *version >= 66 { 56 } else { 32 };
+        record_len = n_reactions
+            .checked_mul(reaction_len)
+            .and_then(|l| l.checked_add(record_len + 24))
+            .ok_or_else(too_large)?;
         if buffer.len() < record_len {
             return Err(EtError::from("Coefficient reactions incomplete").incomplete());
         }
 
         *coeff_pos = record_len - 4;
-        let n_coeffs = usize::try_from(u32::extract(&buffer[*coeff_pos..], &Endian::Little)?)?;
-        record_len += n_coeffs * 8 + 8;
+        let mut pos = *coeff_pos;
+        let n_coeffs = usize::try_from(extract_with::<u32>(buffer, &mut pos, &Endian::Little)?)?;
+        record_len = n_coeffs
+            .checked_mul(8)
+            .and_then(|l| l.checked_add(record_len + 8))
+            .ok_or_else(too_large)?;
         if buffer.len() < record_len {
             return Err(EtError::from("Coefficients incomplete").incomplete());
         }
 
         if *version >= 66 {
-            let extra = usize::try_from(u32::extract(&buffer[record_len - 8..], &Endian::Little)?)?;
-            record_len += 4 + 8 * extra;
+            let extra = usize::try_from(extract_with::<u32>(
+                buffer,
+                &mut (record_len - 8),
+                &Endian::Little,
+            )?)?;
+            record_len = extra
+                .checked_mul(8)
+                .and_then(|l| l.checked_add(record_len + 4))
+                .ok_or_else(too_large)?;
             if buffer.len() < record_len {
                 return Err(EtError::from("Coefficients incomplete").incomplete());
             }
@@ -217,16 +313,44 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ThermoRawScanCoeffs {
         Ok(true)
     }
 
-    fn get(&mut self, buffer: &'b [u8], (_, coeff_pos): &'s Self::State) -> Result<(), EtError> {
-        self.n_coeffs = u32::extract(&buffer[*coeff_pos..], &Endian::Little)?;
+    fn get(
+        &mut self,
+        buffer: &'b [u8],
+        (version, coeff_pos): &'s Self::State,
+    ) -> Result<(), EtError> {
+        let event_len = scan_event_len(*version);
+        self.positive = buffer[4] == 1;
+        self.profile = buffer[5] == 1;
+        self.ms_level = buffer[6];
+        self.scan_type = buffer[7];
+        self.dependent = buffer[10] == 1;
+        self.ionization = buffer[11];
+        // older versions don't have room for the analyzer
+        self.analyzer = if event_len > 44 {
+            Some(buffer[40])
+        } else {
+            None
+        };
+        let n_reactions = extract_with::<u32>(buffer, &mut (event_len - 4), &Endian::Little)?;
+        let reaction_len = if *version >= 66 { 56 } else { 32 };
+        self.reactions.clear();
+        for i in 0..n_reactions as usize {
+            let start = event_len + i * reaction_len;
+            let precursor_mz = extract_with::<f64>(buffer, &mut { start }, &Endian::Little)?;
+            let energy = extract_with::<f64>(buffer, &mut (start + 16), &Endian::Little)?;
+            self.reactions.push((precursor_mz, energy));
+        }
+
+        let mut pos = *coeff_pos;
+        self.n_coeffs = extract_with::<u32>(buffer, &mut pos, &Endian::Little)?;
         if self.n_coeffs == 4 {
-            self.a = f64::extract(&buffer[*coeff_pos + 12..], &Endian::Little)?;
-            self.b = f64::extract(&buffer[*coeff_pos + 20..], &Endian::Little)?;
-            self.c = f64::extract(&buffer[*coeff_pos + 28..], &Endian::Little)?;
+            self.a = extract_with::<f64>(buffer, &mut (*coeff_pos + 12), &Endian::Little)?;
+            self.b = extract_with::<f64>(buffer, &mut (*coeff_pos + 20), &Endian::Little)?;
+            self.c = extract_with::<f64>(buffer, &mut (*coeff_pos + 28), &Endian::Little)?;
         } else if self.n_coeffs == 5 || self.n_coeffs == 7 {
-            self.a = f64::extract(&buffer[*coeff_pos + 20..], &Endian::Little)?;
-            self.b = f64::extract(&buffer[*coeff_pos + 28..], &Endian::Little)?;
-            self.c = f64::extract(&buffer[*coeff_pos + 36..], &Endian::Little)?;
+            self.a = extract_with::<f64>(buffer, &mut (*coeff_pos + 20), &Endian::Little)?;
+            self.b = extract_with::<f64>(buffer, &mut (*coeff_pos + 28), &Endian::Little)?;
+            self.c = extract_with::<f64>(buffer, &mut (*coeff_pos + 36), &Endian::Little)?;
         } else if self.n_coeffs != 0 {
             return Err("Unexpected number of coefficients".into());
         }
@@ -238,13 +362,23 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ThermoRawScanCoeffs {
 #[derive(Clone, Copy, Debug, Default)]
 pub struct ThermoRawParams {
     version: u32,
-    data_start: usize,
-    trailer_start: usize,
+    data_start: u64,
+    trailer_start: u64,
     trailer: Option<ThermoRawTrailer>,
+    text_decoding: TextDecoding,
+}
+
+impl ThermoRawParams {
+    /// Set how the text fields in the file header that aren't validly encoded are handled
+    #[must_use]
+    pub fn text_decoding(mut self, text_decoding: TextDecoding) -> Self {
+        self.text_decoding = text_decoding;
+        self
+    }
 }
 
 /// The state of a parser that handles Thermo RAW files
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct ThermoRawState {
     version: u32,
     metadata_pos: usize,
@@ -260,6 +394,8 @@ pub struct ThermoRawState {
     freq_step: f64,
     cur_coeffs: ThermoRawScanCoeffs,
     cur_adjustment: f64,
+    /// The filter line describing the current scan
+    cur_filter: String,
 }
 
 impl ThermoRawState {
@@ -286,8 +422,20 @@ impl StateMetadata for ThermoRawState {
         map
     }
 
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::HAS_METADATA | Capabilities::TYPED_SCHEMA
+    }
+
     fn header(&self) -> Vec<&str> {
-        vec!["time", "mz", "intensity"]
+        vec![
+            "time",
+            "mz",
+            "intensity",
+            "ms_level",
+            "polarity",
+            "precursor_mz",
+            "filter",
+        ]
     }
 }
 
@@ -318,24 +466,25 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ThermoRawState {
         }
         if state.version == 0 {
             // get the version number
-            state.version = u32::extract(&buffer[36..40], &Endian::Little)?;
+            state.version = extract_with::<u32>(buffer, &mut 36, &Endian::Little)?;
         }
 
         if state.data_start == 0 && state.trailer_start == 0 {
             // the next value (40..48) is a u64 with the run time (?) in "windows time"
             let con = &mut 1420;
+            let decoding = &mut { state.text_decoding };
             for _ in 1..=13 {
-                let _ = PascalString16::parse(&buffer[*con..], eof, con, &mut ())?;
+                let _ = PascalString16::parse(&buffer[*con..], eof, con, decoding)?;
             }
             if state.version >= 50 {
                 for _ in 14..=16 {
-                    let _ = PascalString16::parse(&buffer[*con..], eof, con, &mut ())?;
+                    let _ = PascalString16::parse(&buffer[*con..], eof, con, decoding)?;
                 }
-                let _ = extract::<u32>(buffer, con, &mut Endian::Little)?;
+                let _ = extract_with::<u32>(buffer, con, &Endian::Little)?;
             }
             if state.version >= 60 {
                 for _ in 17..=31 {
-                    let _ = PascalString16::parse(&buffer[*con..], eof, con, &mut ())?;
+                    let _ = PascalString16::parse(&buffer[*con..], eof, con, decoding)?;
                 }
             }
 
@@ -348,18 +497,22 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ThermoRawState {
                 .into());
             } else if state.version < 64 {
                 let _: Skip = extract(buffer, con, &mut 52)?;
-                state.data_start =
-                    usize::try_from(extract::<u32>(buffer, con, &mut Endian::Little)?)?;
+                state.data_start = u64::from(extract_with::<u32>(buffer, con, &Endian::Little)?);
                 let _: Skip = extract(buffer, con, &mut 16)?;
-                state.trailer_start =
-                    usize::try_from(extract::<u32>(buffer, con, &mut Endian::Little)?)?;
+                state.trailer_start = u64::from(extract_with::<u32>(buffer, con, &Endian::Little)?);
             } else {
                 let _: Skip = extract(buffer, con, &mut 836)?;
-                state.data_start =
-                    usize::try_from(extract::<u64>(buffer, con, &mut Endian::Little)?)?;
+                state.data_start = extract_with::<u64>(buffer, con, &Endian::Little)?;
                 let _: Skip = extract(buffer, con, &mut 8)?;
-                state.trailer_start =
-                    usize::try_from(extract::<u64>(buffer, con, &mut Endian::Little)?)?;
+                state.trailer_start = extract_with::<u64>(buffer, con, &Endian::Little)?;
+            }
+            // the data has to come after the header and the trailer after the data
+            if state.data_start < *con as u64 || state.trailer_start < state.data_start {
+                return Err(format!(
+                    "Bad data ({}) or trailer ({}) position",
+                    state.data_start, state.trailer_start
+                )
+                .into());
             }
         }
 
@@ -367,8 +520,8 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ThermoRawState {
             // load everything until the run_header_start; annoyingly, we need to parse the trailer
             // *after* all of the data to convert the "signal id" into a m/z for Orbtraps and other
             // instruments
-            let _: Skip = extract(buffer, &mut 0, &mut state.trailer_start)?;
-            let mut trailer_start = state.trailer_start;
+            let mut trailer_start = buffer_pos(state.trailer_start)?;
+            let _: Skip = extract(buffer, &mut 0, &mut { trailer_start })?;
             state.trailer = Some(extract::<ThermoRawTrailer>(
                 buffer,
                 &mut trailer_start,
@@ -376,17 +529,27 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ThermoRawState {
             )?);
         }
 
-        *consumed += state.data_start;
+        *consumed += buffer_pos(state.data_start)?;
         Ok(true)
     }
 
     fn get(&mut self, buffer: &'b [u8], state: &'s Self::State) -> Result<(), EtError> {
-        self.version = u32::extract(&buffer[36..40], &Endian::Little)?;
+        self.version = extract_with::<u32>(buffer, &mut 36, &Endian::Little)?;
         let trailer = state
             .trailer
             .ok_or_else(|| EtError::from("Trailer missing?"))?;
-        self.metadata_pos = trailer.metadata_start - state.data_start;
-        self.coeffs_pos = trailer.coeffs_start - state.data_start + 4;
+        self.metadata_pos = buffer_pos(
+            trailer
+                .metadata_start
+                .checked_sub(state.data_start)
+                .ok_or_else(|| EtError::from("Scan metadata starts before the data"))?,
+        )?;
+        self.coeffs_pos = buffer_pos(
+            trailer
+                .coeffs_start
+                .checked_sub(state.data_start)
+                .ok_or_else(|| EtError::from("Scan coefficients start before the data"))?,
+        )? + 4;
         self.n_scans_left = trailer.n_scans;
         Ok(())
     }
@@ -394,18 +557,27 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ThermoRawState {
 
 /// A single data point from a Thermo RAW file
 #[derive(Clone, Copy, Debug, Default)]
-pub struct ThermoRawRecord {
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ThermoRawRecord<'r> {
     /// The time the reading was taken at
     pub time: f64,
     /// The mz value of the reading
     pub mz: f64,
     /// The intensity recorded
     pub intensity: f32,
+    /// The MS level of the scan (1 for MS1, 2 for MS2, etc.), if it's known
+    pub ms_level: Option<u8>,
+    /// The polarity of the scan (`+` or `-`)
+    pub polarity: &'r str,
+    /// The m/z of the precursor that was fragmented for MSn scans
+    pub precursor_mz: Option<f64>,
+    /// A description of the scan like Thermo's filter lines (see `ThermoRawScanCoeffs::filter`)
+    pub filter: &'r str,
 }
 
-impl_record!(ThermoRawRecord: time, mz, intensity);
+impl_record!(ThermoRawRecord<'r>: time, mz, intensity, ms_level, polarity, precursor_mz, filter);
 
-impl<'b: 's, 's> FromSlice<'b, 's> for ThermoRawRecord {
+impl<'b: 's, 's> FromSlice<'b, 's> for ThermoRawRecord<'s> {
     type State = ThermoRawState;
 
     fn parse(
@@ -424,6 +596,11 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ThermoRawRecord {
         if state.n_chunks_left == 0 && state.n_points_left == 0 {
             let mut size_data = 0;
             while size_data == 0 {
+                // the rest of the scans (if any) don't have any data
+                if n_scans_left == 0 {
+                    state.n_scans_left = 0;
+                    return Ok(false);
+                }
                 // skip the trailer from the last scan
                 let _ = extract::<Skip>(buffer, &mut con, &mut extra_bytes)?;
 
@@ -432,47 +609,48 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ThermoRawRecord {
                     extract(buffer, &mut state.metadata_pos, &mut state.version)?;
                 state.cur_time = scan_metadata.time;
                 state.cur_coeffs = extract(buffer, &mut state.coeffs_pos, &mut (state.version, 0))?;
+                state.cur_filter = state
+                    .cur_coeffs
+                    .filter(scan_metadata.low_mz, scan_metadata.high_mz);
 
                 // now read the record header itself
                 let _ = extract::<Skip>(buffer, &mut con, &mut 4)?;
-                size_data = extract::<u32>(buffer, &mut con, &mut Endian::Little)?;
+                size_data = extract_with::<u32>(buffer, &mut con, &Endian::Little)?;
                 extra_bytes =
-                    4 * usize::try_from(extract::<u32>(buffer, &mut con, &mut Endian::Little)?)?;
+                    4 * usize::try_from(extract_with::<u32>(buffer, &mut con, &Endian::Little)?)?;
                 // only the second bit `01000000` is ever set here?
                 state.chunk_has_adjustment =
-                    extract::<u32>(buffer, &mut con, &mut Endian::Little)? != 0;
+                    extract_with::<u32>(buffer, &mut con, &Endian::Little)? != 0;
                 // three more sections we need to skip
                 extra_bytes +=
-                    4 * usize::try_from(extract::<u32>(buffer, &mut con, &mut Endian::Little)?)?;
+                    4 * usize::try_from(extract_with::<u32>(buffer, &mut con, &Endian::Little)?)?;
                 extra_bytes +=
-                    4 * usize::try_from(extract::<u32>(buffer, &mut con, &mut Endian::Little)?)?;
+                    4 * usize::try_from(extract_with::<u32>(buffer, &mut con, &Endian::Little)?)?;
                 extra_bytes +=
-                    4 * usize::try_from(extract::<u32>(buffer, &mut con, &mut Endian::Little)?)?;
+                    4 * usize::try_from(extract_with::<u32>(buffer, &mut con, &Endian::Little)?)?;
                 let _ = extract::<Skip>(buffer, &mut con, &mut 12)?;
 
                 n_scans_left -= 1;
-                if n_scans_left == 0 {
-                    state.n_scans_left = n_scans_left;
-                    return Ok(false);
-                }
             }
-            state.base_freq = extract(buffer, &mut con, &mut Endian::Little)?;
-            state.freq_step = extract(buffer, &mut con, &mut Endian::Little)?;
+            state.base_freq = extract_with(buffer, &mut con, &Endian::Little)?;
+            state.freq_step = extract_with(buffer, &mut con, &Endian::Little)?;
             n_chunks_left =
-                usize::try_from(extract::<u32>(buffer, &mut con, &mut Endian::Little)?)?;
+                usize::try_from(extract_with::<u32>(buffer, &mut con, &Endian::Little)?)?;
             let _ = extract::<Skip>(buffer, &mut con, &mut 4)?;
         }
         if state.n_points_left == 0 {
             // read a chunk header
-            let freq_offset = f64::from(extract::<u32>(buffer, &mut con, &mut Endian::Little)?);
+            let freq_offset = f64::from(extract_with::<u32>(buffer, &mut con, &Endian::Little)?);
             state.cur_freq = state.base_freq + state.freq_step * freq_offset - state.freq_step;
             state.n_points_left =
-                usize::try_from(extract::<u32>(buffer, &mut con, &mut Endian::Little)?)?;
+                usize::try_from(extract_with::<u32>(buffer, &mut con, &Endian::Little)?)?;
             if state.chunk_has_adjustment {
                 state.cur_adjustment =
-                    f64::from(extract::<f32>(buffer, &mut con, &mut Endian::Little)?);
+                    f64::from(extract_with::<f32>(buffer, &mut con, &Endian::Little)?);
             }
-            n_chunks_left -= 1;
+            n_chunks_left = n_chunks_left
+                .checked_sub(1)
+                .ok_or_else(|| EtError::from("Scan has no data chunks"))?;
         }
         // include the point itself
         let _ = extract::<Skip>(buffer, &mut con, &mut 4)?;
@@ -480,7 +658,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ThermoRawRecord {
 
         state.n_scans_left = n_scans_left;
         state.n_chunks_left = n_chunks_left;
-        state.n_points_left -= 1;
+        state.n_points_left = sub_or_err(state.n_points_left, 1, "number of points in scan chunk")?;
         state.extra_bytes = extra_bytes;
         state.data_consumed(con)?;
         *consumed += con;
@@ -489,8 +667,14 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ThermoRawRecord {
 
     fn get(&mut self, buffer: &'b [u8], state: &'s Self::State) -> Result<(), EtError> {
         self.time = state.cur_time;
-        self.mz = state.cur_coeffs.to_mz(state.cur_freq) + state.cur_adjustment;
-        self.intensity = f32::extract(&buffer[buffer.len() - 4..], &Endian::Little)?;
+        self.mz = state.cur_coeffs.to_mz(state.cur_freq)? + state.cur_adjustment;
+        let pos = &mut sub_or_err(buffer.len(), 4, "scan point")?;
+        self.intensity = extract_with::<f32>(buffer, pos, &Endian::Little)?;
+        let event = &state.cur_coeffs;
+        self.ms_level = Some(event.ms_level).filter(|l| *l > 0);
+        self.polarity = if event.positive { "+" } else { "-" };
+        self.precursor_mz = event.reactions.last().map(|(mz, _)| *mz);
+        self.filter = &state.cur_filter;
         Ok(())
     }
 }
@@ -498,7 +682,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ThermoRawRecord {
 impl_reader!(
     ThermoRawReader,
     ThermoRawRecord,
-    ThermoRawRecord,
+    ThermoRawRecord<'r>,
     ThermoRawState,
     ThermoRawParams
 );
@@ -513,8 +697,27 @@ impl_reader!(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::parsers::thermo::synthetic::{SyntheticRaw, SyntheticScan};
     use crate::readers::RecordReader;
 
+    #[test]
+    fn test_scan_coeffs() -> Result<(), EtError> {
+        let coeffs = ThermoRawScanCoeffs {
+            n_coeffs: 4,
+            a: 1.,
+            b: 2.,
+            c: 4.,
+            ..ThermoRawScanCoeffs::default()
+        };
+        assert_eq!(coeffs.to_mz(2.)?, 3.);
+        let coeffs = ThermoRawScanCoeffs {
+            n_coeffs: 3,
+            ..coeffs
+        };
+        assert!(coeffs.to_mz(2.).is_err());
+        Ok(())
+    }
+
     #[test]
     fn test_thermo_raw() -> Result<(), EtError> {
         let rb: &[u8] = include_bytes!("../../../tests/data/small.RAW");
@@ -525,15 +728,176 @@ mod tests {
             time,
             mz,
             intensity,
+            ms_level,
+            filter,
+            ..
         }) = reader.next()?
         {
             assert!((time - 0.004935).abs() < 0.000001);
             assert!((mz - 202.60682348271376).abs() < 0.000001);
             assert!((intensity - 1938.1174).abs() < 0.000001);
+            assert_eq!(ms_level, Some(1));
+            assert_eq!(filter, "FTMS + p ESI Full ms [200.00-2000.00]");
         } else {
             panic!("Thermo Raw reader returned bad record");
         }
         while reader.next()?.is_some() {}
         Ok(())
     }
+
+    #[test]
+    fn test_thermo_raw_versions() -> Result<(), EtError> {
+        let scan = SyntheticScan {
+            time: 0.5,
+            first_freq: 100.,
+            freq_step: 0.5,
+            intensities: vec![10., 20.],
+            adjustment: None,
+            ms_level: 1,
+            precursor: None,
+        };
+        let mut raw = SyntheticRaw {
+            version: 0,
+            coeffs: None,
+            scans: vec![
+                scan.clone(),
+                SyntheticScan {
+                    time: 0.6,
+                    intensities: Vec::new(),
+                    ..scan.clone()
+                },
+                SyntheticScan {
+                    time: 0.7,
+                    intensities: vec![30.],
+                    adjustment: Some(0.25),
+                    ..scan
+                },
+            ],
+        };
+        for version in [57, 60, 62, 63, 64, 65, 66] {
+            raw.version = version;
+            let data = raw.build();
+            let mut reader = ThermoRawReader::new(&data[..], None)?;
+            assert_eq!(reader.metadata()["version"], version.into());
+            let mut records = Vec::new();
+            while let Some(ThermoRawRecord {
+                time,
+                mz,
+                intensity,
+                ..
+            }) = reader.next()?
+            {
+                records.push((time, mz, intensity));
+            }
+            // the empty scan is skipped, but the last scan is still read
+            assert_eq!(
+                records,
+                [(0.5, 100., 10.), (0.5, 100.5, 20.), (0.7, 100.25, 30.)],
+                "version {}",
+                version
+            );
+        }
+
+        // frequencies are converted with the coefficients
+        raw.coeffs = Some([1., 200., 0.]);
+        let data = raw.build();
+        let mut reader = ThermoRawReader::new(&data[..], None)?;
+        let ThermoRawRecord { mz, .. } = reader.next()?.unwrap();
+        assert_eq!(mz, 3.);
+
+        raw.version = 50;
+        assert!(ThermoRawReader::new(&raw.build()[..], None).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_thermo_raw_scan_events() -> Result<(), EtError> {
+        let ms1 = SyntheticScan {
+            time: 0.5,
+            first_freq: 400.,
+            freq_step: 0.5,
+            intensities: vec![10.],
+            ms_level: 1,
+            ..SyntheticScan::default()
+        };
+        let ms2 = SyntheticScan {
+            time: 0.6,
+            first_freq: 100.,
+            ms_level: 2,
+            precursor: Some((445.12, 35.)),
+            ..ms1.clone()
+        };
+        for version in [57, 63, 66] {
+            let raw = SyntheticRaw {
+                version,
+                coeffs: None,
+                scans: vec![ms1.clone(), ms2.clone()],
+            };
+            let data = raw.build();
+            let mut reader = ThermoRawReader::new(&data[..], None)?;
+            let record = reader.next()?.unwrap();
+            assert_eq!(record.ms_level, Some(1));
+            assert_eq!(record.polarity, "+");
+            assert_eq!(record.precursor_mz, None);
+            assert_eq!(record.filter, "FTMS + p ESI Full ms [400.00-400.50]");
+            let record = reader.next()?.unwrap();
+            assert_eq!(record.ms_level, Some(2));
+            assert_eq!(record.precursor_mz, Some(445.12));
+            assert_eq!(
+                record.filter, "FTMS + p ESI Full ms2 445.1200@35.00 [100.00-100.50]",
+                "version {}",
+                version
+            );
+            assert!(reader.next()?.is_none());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_pascal_string16() -> Result<(), EtError> {
+        let data = b"\x02\x00\x00\x00h\x00i\x00extra";
+        let mut consumed = 0;
+        let PascalString16(s) = extract(data, &mut consumed, &mut TextDecoding::Lossy)?;
+        assert_eq!(s, "hi");
+        assert_eq!(consumed, 8);
+
+        // the length is in UTF-16 code units so this is one byte short
+        let e =
+            extract::<PascalString16>(&data[..7], &mut 0, &mut TextDecoding::Lossy).unwrap_err();
+        assert!(e.incomplete);
+
+        let e = extract::<PascalString16>(b"\xFF\xFF\xFF\xFFab", &mut 0, &mut TextDecoding::Lossy)
+            .unwrap_err();
+        assert!(e.incomplete);
+        Ok(())
+    }
+
+    #[test]
+    fn test_thermo_raw_bad_fuzzes() -> Result<(), EtError> {
+        let rb: &[u8] = include_bytes!("../../../tests/data/small.RAW");
+
+        // truncated files used to index past the end of the buffer when reading scan metadata
+        let mut reader = ThermoRawReader::new(&rb[..1_432_309], None)?;
+        let mut result = reader.next();
+        while let Ok(Some(_)) = result {
+            result = reader.next();
+        }
+        assert!(result.is_err());
+
+        // a data start inside the header
+        let mut data = rb.to_vec();
+        data[1764..1768].copy_from_slice(&[0, 0, 0, 0]);
+        assert!(ThermoRawReader::new(&data[..], None).is_err());
+
+        // a trailer start before the data start
+        let mut data = rb.to_vec();
+        data[1784..1788].copy_from_slice(&[0, 1, 0, 0]);
+        assert!(ThermoRawReader::new(&data[..], None).is_err());
+
+        // a trailer start past the end of the file
+        let mut data = rb.to_vec();
+        data[1784..1788].copy_from_slice(&[255, 255, 255, 0]);
+        assert!(ThermoRawReader::new(&data[..], None).is_err());
+        Ok(())
+    }
 }
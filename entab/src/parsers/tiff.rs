@@ -0,0 +1,933 @@
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::str::from_utf8;
+use std::io::Read;
+
+use flate2::read::ZlibDecoder;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::capabilities::Capabilities;
+use crate::parsers::xml::{next_tag, XmlTagType};
+use crate::parsers::{extract, slice_or_incomplete, Endian, FromSlice};
+use crate::record::{StateMetadata, Value};
+use crate::EtError;
+use crate::{impl_reader, impl_record};
+
+// the tags used to read the image data (and its description)
+const IMAGE_WIDTH: u16 = 256;
+const IMAGE_LENGTH: u16 = 257;
+const BITS_PER_SAMPLE: u16 = 258;
+const COMPRESSION: u16 = 259;
+const IMAGE_DESCRIPTION: u16 = 270;
+const STRIP_OFFSETS: u16 = 273;
+const SAMPLES_PER_PIXEL: u16 = 277;
+const ROWS_PER_STRIP: u16 = 278;
+const STRIP_BYTE_COUNTS: u16 = 279;
+const PLANAR_CONFIGURATION: u16 = 284;
+const SOFTWARE: u16 = 305;
+const PREDICTOR: u16 = 317;
+const TILE_WIDTH: u16 = 322;
+const SAMPLE_FORMAT: u16 = 339;
+
+/// Stop reading image file directories after this many (in case they loop)
+const MAX_PLANES: usize = 1 << 20;
+
+/// A field in an image file directory
+#[derive(Clone, Copy, Debug, Default)]
+struct TiffField {
+    field_type: u16,
+    count: usize,
+    /// Where the values start in the file
+    pos: usize,
+}
+
+impl TiffField {
+    /// Read the values of a field holding (unsigned) integers
+    fn numbers(&self, data: &[u8], mut endian: Endian) -> Result<Vec<u64>, EtError> {
+        let con = &mut self.pos.clone();
+        let mut values = Vec::new();
+        for _ in 0..self.count {
+            values.push(match self.field_type {
+                1 | 7 => u64::from(extract::<u8>(data, con, &mut endian)?),
+                3 => u64::from(extract::<u16>(data, con, &mut endian)?),
+                4 => u64::from(extract::<u32>(data, con, &mut endian)?),
+                t => return Err(format!("TIFF field of type {} isn't a number", t).into()),
+            });
+        }
+        Ok(values)
+    }
+
+    /// Read the value of a field holding text
+    fn text<'a>(&self, data: &'a [u8]) -> Result<&'a str, EtError> {
+        let text = slice_or_incomplete(data, self.pos..self.pos + self.count)?;
+        Ok(from_utf8(text)?.trim_end_matches('\0'))
+    }
+}
+
+/// The size of each of the values in a field of `field_type`
+fn field_type_size(field_type: u16) -> usize {
+    match field_type {
+        3 | 8 => 2,
+        4 | 9 | 11 => 4,
+        5 | 10 | 12 => 8,
+        _ => 1,
+    }
+}
+
+/// Read the image file directory at `offset`, returning its fields and the offset of the next
+/// directory (0 if it's the last one)
+fn read_ifd(
+    data: &[u8],
+    offset: usize,
+    mut endian: Endian,
+) -> Result<(BTreeMap<u16, TiffField>, usize), EtError> {
+    let con = &mut offset.clone();
+    let n_fields = extract::<u16>(data, con, &mut endian)?;
+    let mut fields = BTreeMap::new();
+    for _ in 0..n_fields {
+        let tag = extract::<u16>(data, con, &mut endian)?;
+        let field_type = extract::<u16>(data, con, &mut endian)?;
+        let count = extract::<u32>(data, con, &mut endian)? as usize;
+        // values that fit in four bytes are stored in the field itself
+        let pos = if count.saturating_mul(field_type_size(field_type)) <= 4 {
+            *con
+        } else {
+            extract::<u32>(data, &mut con.clone(), &mut endian)? as usize
+        };
+        *con += 4;
+        let _ = fields.insert(
+            tag,
+            TiffField {
+                field_type,
+                count,
+                pos,
+            },
+        );
+    }
+    let next = extract::<u32>(data, con, &mut endian)? as usize;
+    Ok((fields, next))
+}
+
+/// Undo PackBits run-length encoding
+fn unpack_bits(data: &[u8], out: &mut Vec<u8>) -> Result<(), EtError> {
+    let mut pos = 0;
+    while pos < data.len() {
+        let n = data[pos] as i8;
+        pos += 1;
+        if n >= 0 {
+            let len = n as usize + 1;
+            out.extend_from_slice(slice_or_incomplete(data, pos..pos + len)?);
+            pos += len;
+        } else if n != -128 {
+            let byte = *data.get(pos).ok_or("PackBits run is truncated")?;
+            out.extend(core::iter::repeat_n(byte, 1 + n.unsigned_abs() as usize));
+            pos += 1;
+        }
+    }
+    Ok(())
+}
+
+/// A single image (page) in a TIFF file; its strips are only decompressed once records are
+/// read from it (see `decode`) so only one plane is in memory at a time.
+#[derive(Clone, Debug, Default)]
+struct TiffPlane {
+    width: usize,
+    height: usize,
+    samples_per_pixel: usize,
+    /// The number of bytes in each sample
+    sample_size: usize,
+    /// 1 for unsigned integers, 2 for signed integers, and 3 for floats
+    sample_format: u16,
+    compression: u64,
+    predictor: u64,
+    /// The (still compressed) bytes of each strip of the image
+    strips: Vec<Vec<u8>>,
+    /// The decompressed samples (empty unless this is the plane being read)
+    data: Vec<u8>,
+}
+
+impl TiffPlane {
+    /// Read the layout of the image with `fields` and copy out its (compressed) data
+    fn new(
+        data: &[u8],
+        fields: &BTreeMap<u16, TiffField>,
+        endian: Endian,
+    ) -> Result<Self, EtError> {
+        let number = |tag: u16, default: u64| -> Result<u64, EtError> {
+            match fields.get(&tag) {
+                Some(field) => field
+                    .numbers(data, endian)?
+                    .first()
+                    .copied()
+                    .ok_or_else(|| format!("TIFF field {} is empty", tag).into()),
+                None => Ok(default),
+            }
+        };
+        if fields.contains_key(&TILE_WIDTH) {
+            return Err("Tiled TIFFs aren't supported yet".into());
+        }
+        let width = usize::try_from(number(IMAGE_WIDTH, 0)?)?;
+        let height = usize::try_from(number(IMAGE_LENGTH, 0)?)?;
+        let samples_per_pixel = usize::try_from(number(SAMPLES_PER_PIXEL, 1)?)?;
+        if samples_per_pixel > 1 && number(PLANAR_CONFIGURATION, 1)? != 1 {
+            return Err("TIFFs with each channel stored separately aren't supported yet".into());
+        }
+        let bits = number(BITS_PER_SAMPLE, 1)?;
+        let sample_format = u16::try_from(number(SAMPLE_FORMAT, 1)?)?;
+        let sample_size = match (sample_format, bits) {
+            (1 | 2, 8 | 16 | 32) | (3, 32 | 64) => (bits / 8) as usize,
+            (1..=3, _) => return Err(format!("{}-bit TIFF samples aren't supported", bits).into()),
+            _ => return Err(format!("Unknown TIFF sample format {}", sample_format).into()),
+        };
+        let row_len = width
+            .checked_mul(samples_per_pixel * sample_size)
+            .ok_or("TIFF image is too wide")?;
+        let plane_len = row_len
+            .checked_mul(height)
+            .ok_or("TIFF image is too large")?;
+        let compression = number(COMPRESSION, 1)?;
+        match compression {
+            1 | 8 | 32946 | 32773 => {}
+            5 => return Err("LZW-compressed TIFFs aren't supported yet".into()),
+            c => return Err(format!("TIFF compression type {} isn't supported", c).into()),
+        }
+        let predictor = number(PREDICTOR, 1)?;
+        match predictor {
+            1 => {}
+            2 if sample_format != 3 => {}
+            p => return Err(format!("TIFF predictor {} isn't supported", p).into()),
+        }
+
+        let offsets = fields
+            .get(&STRIP_OFFSETS)
+            .ok_or("TIFF image has no strips")?
+            .numbers(data, endian)?;
+        let rows_per_strip = usize::try_from(number(ROWS_PER_STRIP, u64::from(u32::MAX))?)?;
+        let byte_counts = match fields.get(&STRIP_BYTE_COUNTS) {
+            Some(field) => field.numbers(data, endian)?,
+            // uncompressed strips are all full (except maybe the last)
+            None => vec![(row_len.saturating_mul(rows_per_strip)) as u64; offsets.len()],
+        };
+        let mut strips = Vec::with_capacity(offsets.len());
+        for (&offset, &len) in offsets.iter().zip(&byte_counts) {
+            let start = usize::try_from(offset)?;
+            let end = start.saturating_add(usize::try_from(len)?).min(data.len());
+            let strip = data
+                .get(start..end)
+                .ok_or("TIFF strip is past the end of the file")?;
+            strips.push(strip.to_vec());
+        }
+        let plane = TiffPlane {
+            width,
+            height,
+            samples_per_pixel,
+            sample_size,
+            sample_format,
+            compression,
+            predictor,
+            strips,
+            data: Vec::new(),
+        };
+        // uncompressed images can be checked now instead of partway through the records
+        let data_len: usize = plane.strips.iter().map(Vec::len).sum();
+        if compression == 1 && data_len < plane_len {
+            return Err(plane.too_short(data_len));
+        }
+        Ok(plane)
+    }
+
+    /// The error for image data that's `data_len` bytes, but is too short for the image
+    fn too_short(&self, data_len: usize) -> EtError {
+        format!(
+            "TIFF image data is {} bytes, but its size needs {}",
+            data_len,
+            self.len() * self.sample_size
+        )
+        .into()
+    }
+
+    /// Decompress the image data so its samples can be read
+    fn decode(&mut self, endian: Endian) -> Result<(), EtError> {
+        let plane_len = self.len() * self.sample_size;
+        let mut plane_data = Vec::new();
+        for strip in &self.strips {
+            match self.compression {
+                8 | 32946 => {
+                    let _ = ZlibDecoder::new(&strip[..]).read_to_end(&mut plane_data)?;
+                }
+                32773 => unpack_bits(strip, &mut plane_data)?,
+                _ => plane_data.extend_from_slice(strip),
+            }
+        }
+        if plane_data.len() < plane_len {
+            return Err(self.too_short(plane_data.len()));
+        }
+        plane_data.truncate(plane_len);
+        self.data = plane_data;
+        if self.predictor == 2 {
+            self.undo_differencing(endian)?;
+        }
+        Ok(())
+    }
+
+    /// The number of samples (pixels times channels) in the plane
+    fn len(&self) -> usize {
+        self.width * self.height * self.samples_per_pixel
+    }
+
+    /// Reverse the horizontal differencing predictor (each sample is stored as the difference
+    /// from the one to its left)
+    fn undo_differencing(&mut self, endian: Endian) -> Result<(), EtError> {
+        let spp = self.samples_per_pixel;
+        let row_samples = self.width * spp;
+        for ix in 0..self.len() {
+            if ix % row_samples < spp {
+                continue;
+            }
+            let (prev, cur) = (ix - spp, ix);
+            let size = self.sample_size;
+            let value = self
+                .raw_sample(cur, endian)?
+                .wrapping_add(self.raw_sample(prev, endian)?);
+            let bytes = &mut self.data[cur * size..(cur + 1) * size];
+            match (size, endian) {
+                (1, _) => bytes[0] = value as u8,
+                (2, Endian::Big) => bytes.copy_from_slice(&(value as u16).to_be_bytes()),
+                (2, Endian::Little) => bytes.copy_from_slice(&(value as u16).to_le_bytes()),
+                (_, Endian::Big) => bytes.copy_from_slice(&value.to_be_bytes()),
+                (_, Endian::Little) => bytes.copy_from_slice(&value.to_le_bytes()),
+            }
+        }
+        Ok(())
+    }
+
+    /// The bits of the `ix`th (integer) sample in the plane
+    fn raw_sample(&self, ix: usize, mut endian: Endian) -> Result<u32, EtError> {
+        let con = &mut (ix * self.sample_size);
+        let data = &self.data[..];
+        Ok(match self.sample_size {
+            1 => u32::from(extract::<u8>(data, con, &mut endian)?),
+            2 => u32::from(extract::<u16>(data, con, &mut endian)?),
+            _ => extract::<u32>(data, con, &mut endian)?,
+        })
+    }
+
+    /// The value of the `ix`th sample in the plane
+    fn sample(&self, ix: usize, mut endian: Endian) -> Result<f64, EtError> {
+        let con = &mut (ix * self.sample_size);
+        let data = &self.data[..];
+        Ok(match (self.sample_format, self.sample_size) {
+            (1, 1) => f64::from(extract::<u8>(data, con, &mut endian)?),
+            (1, 2) => f64::from(extract::<u16>(data, con, &mut endian)?),
+            (1, _) => f64::from(extract::<u32>(data, con, &mut endian)?),
+            (2, 1) => f64::from(extract::<i8>(data, con, &mut endian)?),
+            (2, 2) => f64::from(extract::<i16>(data, con, &mut endian)?),
+            (2, _) => f64::from(extract::<i32>(data, con, &mut endian)?),
+            (_, 4) => f64::from(extract::<f32>(data, con, &mut endian)?),
+            _ => extract::<f64>(data, con, &mut endian)?,
+        })
+    }
+}
+
+/// How the planes of a multi-dimensional image are laid out
+#[derive(Clone, Debug)]
+struct Dimensions {
+    /// The order the channel, z, and time dimensions vary in (fastest first), e.g. `ZCT`
+    order: String,
+    size_c: usize,
+    size_z: usize,
+    size_t: usize,
+}
+
+impl Dimensions {
+    /// The channel, z, and time point of the `ix`th plane
+    fn position(&self, ix: usize) -> (usize, usize, usize) {
+        let (mut c, mut z, mut t) = (0, 0, 0);
+        let mut rest = ix;
+        for dim in self.order.chars() {
+            let (size, value) = match dim {
+                'C' => (self.size_c, &mut c),
+                'Z' => (self.size_z, &mut z),
+                'T' => (self.size_t, &mut t),
+                _ => continue,
+            };
+            *value = rest % size.max(1);
+            rest /= size.max(1);
+        }
+        (c, z, t)
+    }
+}
+
+/// Read the dimensions and the channel, plane, and physical size metadata out of an OME-XML
+/// image description
+fn read_ome_xml(
+    xml: &str,
+    metadata: &mut BTreeMap<String, Value<'static>>,
+) -> Result<Dimensions, EtError> {
+    let mut dims = Dimensions {
+        order: "ZCT".to_string(),
+        size_c: 1,
+        size_z: 1,
+        size_t: 1,
+    };
+    let mut channels = Vec::new();
+    let mut planes = Vec::new();
+    let data = xml.as_bytes();
+    let con = &mut 0;
+    let mut in_pixels = false;
+    while let Some(tag) = next_tag(data, true, con)? {
+        // ignore any namespace prefixes (e.g. `ome:Pixels`)
+        let id = tag.id.rsplit(':').next().unwrap_or(tag.id);
+        if tag.tag_type == XmlTagType::Close {
+            if id == "Pixels" {
+                // only the first image's planes are in this file
+                break;
+            }
+            continue;
+        }
+        match id {
+            "Pixels" => {
+                in_pixels = true;
+                if let Some(order) = tag.attribute("DimensionOrder") {
+                    dims.order = order.trim_start_matches("XY").to_string();
+                    drop(metadata.insert("dimension_order".to_string(), order.to_string().into()));
+                }
+                for (attr, size) in [
+                    ("SizeC", &mut dims.size_c),
+                    ("SizeZ", &mut dims.size_z),
+                    ("SizeT", &mut dims.size_t),
+                ] {
+                    if let Some(value) = tag.attribute(attr).and_then(|v| v.parse().ok()) {
+                        *size = value;
+                    }
+                }
+                for (attr, key) in [
+                    ("PhysicalSizeX", "physical_size_x"),
+                    ("PhysicalSizeY", "physical_size_y"),
+                    ("PhysicalSizeZ", "physical_size_z"),
+                    ("PhysicalSizeXUnit", "physical_size_unit"),
+                ] {
+                    if let Some(value) = tag.attribute(attr) {
+                        let value = match value.parse::<f64>() {
+                            Ok(f) => Value::Float(f),
+                            Err(_) => value.to_string().into(),
+                        };
+                        drop(metadata.insert(key.to_string(), value));
+                    }
+                }
+            }
+            "Channel" if in_pixels => {
+                let name = tag.attribute("Name").or_else(|| tag.attribute("ID"));
+                channels.push(name.map_or(Value::Null, |n| n.to_string().into()));
+            }
+            "Plane" if in_pixels => {
+                let mut plane = BTreeMap::new();
+                for (attr, value) in &tag.attributes {
+                    let value = match value.parse::<f64>() {
+                        Ok(f) => Value::Float(f),
+                        Err(_) => value.to_string().into(),
+                    };
+                    drop(plane.insert(attr.to_string(), value));
+                }
+                planes.push(Value::Record(plane));
+            }
+            _ => {}
+        }
+    }
+    if !in_pixels {
+        return Err("OME-XML description has no Pixels element".into());
+    }
+    drop(metadata.insert("size_c".to_string(), (dims.size_c as u64).into()));
+    drop(metadata.insert("size_z".to_string(), (dims.size_z as u64).into()));
+    drop(metadata.insert("size_t".to_string(), (dims.size_t as u64).into()));
+    if !channels.is_empty() {
+        drop(metadata.insert("channels".to_string(), Value::List(channels)));
+    }
+    if !planes.is_empty() {
+        drop(metadata.insert("planes".to_string(), Value::List(planes)));
+    }
+    Ok(dims)
+}
+
+/// Read the dimensions out of an ImageJ image description (`key=value` lines)
+fn read_imagej_description(description: &str) -> Dimensions {
+    let mut dims = Dimensions {
+        // ImageJ hyperstacks are always stored channels first
+        order: "CZT".to_string(),
+        size_c: 1,
+        size_z: 1,
+        size_t: 1,
+    };
+    for line in description.lines() {
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key, value.parse().unwrap_or(1)),
+            None => continue,
+        };
+        match key {
+            "channels" => dims.size_c = value,
+            "slices" => dims.size_z = value,
+            "frames" => dims.size_t = value,
+            _ => {}
+        }
+    }
+    dims
+}
+
+/// The state of the TIFF parser
+#[derive(Clone, Debug, Default)]
+pub struct TiffState {
+    endian: Endian,
+    planes: Vec<TiffPlane>,
+    dimensions: Option<Dimensions>,
+    metadata: BTreeMap<String, Value<'static>>,
+    /// The plane that's decoded (if any)
+    decoded: Option<usize>,
+    /// The plane and sample the next record is from
+    next: (usize, usize),
+    /// The plane and sample of the current record
+    cur: (usize, usize),
+}
+
+impl StateMetadata for TiffState {
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        self.metadata.clone()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::HAS_METADATA | Capabilities::TYPED_SCHEMA
+    }
+
+    fn header(&self) -> Vec<&str> {
+        vec!["plane", "channel", "z", "t", "x", "y", "intensity"]
+    }
+}
+
+impl<'b: 's, 's> FromSlice<'b, 's> for TiffState {
+    type State = ();
+
+    fn parse(
+        buf: &[u8],
+        eof: bool,
+        consumed: &mut usize,
+        _state: &mut Self::State,
+    ) -> Result<bool, EtError> {
+        match buf.get(..4) {
+            Some(b"II*\0" | b"MM\0*") => {}
+            Some(b"II+\0" | b"MM\0+") => return Err("BigTIFF files aren't supported yet".into()),
+            Some(_) => return Err("Invalid TIFF magic".into()),
+            None if !eof => {}
+            None => return Err("TIFF file is truncated".into()),
+        }
+        // the images can be anywhere in the file so we need all of it (the strips of each
+        // image are copied into the state, but only decompressed once they're read)
+        if !eof {
+            return Err(EtError::from("TIFF files are read all at once").incomplete());
+        }
+        *consumed += buf.len();
+        Ok(true)
+    }
+
+    fn get(&mut self, buf: &'b [u8], _state: &'s Self::State) -> Result<(), EtError> {
+        self.endian = if buf.starts_with(b"MM") {
+            Endian::Big
+        } else {
+            Endian::Little
+        };
+        let mut offset = extract::<u32>(buf, &mut 4, &mut self.endian)? as usize;
+        let mut description = None;
+        while offset != 0 {
+            if self.planes.len() >= MAX_PLANES {
+                return Err("TIFF file has too many images (or they loop)".into());
+            }
+            let (fields, next) = read_ifd(buf, offset, self.endian).map_err(|e| {
+                if e.incomplete {
+                    "TIFF image directory is past the end of the file".into()
+                } else {
+                    e
+                }
+            })?;
+            if self.planes.is_empty() {
+                if let Some(field) = fields.get(&IMAGE_DESCRIPTION) {
+                    description = Some(field.text(buf)?);
+                }
+                if let Some(field) = fields.get(&SOFTWARE) {
+                    let software = field.text(buf)?.to_string();
+                    drop(
+                        self.metadata
+                            .insert("software".to_string(), software.into()),
+                    );
+                }
+            }
+            self.planes.push(TiffPlane::new(buf, &fields, self.endian)?);
+            offset = next;
+        }
+
+        self.dimensions = match description {
+            Some(text) if text.contains("<OME") => Some(read_ome_xml(text, &mut self.metadata)?),
+            Some(text) if text.starts_with("ImageJ=") => {
+                let dims = read_imagej_description(text);
+                drop(
+                    self.metadata
+                        .insert("size_c".to_string(), (dims.size_c as u64).into()),
+                );
+                drop(
+                    self.metadata
+                        .insert("size_z".to_string(), (dims.size_z as u64).into()),
+                );
+                drop(
+                    self.metadata
+                        .insert("size_t".to_string(), (dims.size_t as u64).into()),
+                );
+                Some(dims)
+            }
+            Some(text) => {
+                let text = text.to_string();
+                drop(self.metadata.insert("description".to_string(), text.into()));
+                None
+            }
+            None => None,
+        };
+        if let Some(plane) = self.planes.first() {
+            drop(
+                self.metadata
+                    .insert("width".to_string(), (plane.width as u64).into()),
+            );
+            drop(
+                self.metadata
+                    .insert("height".to_string(), (plane.height as u64).into()),
+            );
+            drop(self.metadata.insert(
+                "bits_per_sample".to_string(),
+                (plane.sample_size as u64 * 8).into(),
+            ));
+            drop(self.metadata.insert(
+                "samples_per_pixel".to_string(),
+                (plane.samples_per_pixel as u64).into(),
+            ));
+        }
+        drop(
+            self.metadata
+                .insert("n_planes".to_string(), (self.planes.len() as u64).into()),
+        );
+        self.decoded = None;
+        self.next = (0, 0);
+        Ok(())
+    }
+}
+
+/// A single sample (one channel of a pixel) from a TIFF file
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TiffRecord {
+    /// Which image in the file the sample is from
+    pub plane: u32,
+    /// The channel of the sample (the sample in the pixel for e.g. RGB images and the channel
+    /// of the plane for OME-TIFF and ImageJ hyperstacks)
+    pub channel: u32,
+    /// The z slice the plane is (for OME-TIFF and ImageJ hyperstacks)
+    pub z: u32,
+    /// The time point of the plane (for OME-TIFF and ImageJ hyperstacks)
+    pub t: u32,
+    /// The column of the pixel
+    pub x: u32,
+    /// The row of the pixel
+    pub y: u32,
+    /// The value of the sample
+    pub intensity: f64,
+}
+
+impl_record!(TiffRecord: plane, channel, z, t, x, y, intensity);
+
+impl<'b: 's, 's> FromSlice<'b, 's> for TiffRecord {
+    type State = TiffState;
+
+    fn parse(
+        _buf: &[u8],
+        _eof: bool,
+        _consumed: &mut usize,
+        state: &mut Self::State,
+    ) -> Result<bool, EtError> {
+        // the images were copied into the state so records don't use the buffer
+        let (mut plane, mut sample) = state.next;
+        while state.planes.get(plane).is_some_and(|p| sample >= p.len()) {
+            plane += 1;
+            sample = 0;
+        }
+        if plane >= state.planes.len() {
+            return Ok(false);
+        }
+        if state.decoded != Some(plane) {
+            if let Some(old) = state.decoded.and_then(|ix| state.planes.get_mut(ix)) {
+                old.data = Vec::new();
+            }
+            state.decoded = None;
+            state.planes[plane].decode(state.endian)?;
+            state.decoded = Some(plane);
+        }
+        state.cur = (plane, sample);
+        state.next = (plane, sample + 1);
+        Ok(true)
+    }
+
+    fn get(&mut self, _buf: &'b [u8], state: &'s Self::State) -> Result<(), EtError> {
+        let (plane_ix, sample) = state.cur;
+        let plane = &state.planes[plane_ix];
+        let spp = plane.samples_per_pixel;
+        let pixel = sample / spp;
+        let (c, z, t) = match &state.dimensions {
+            Some(dims) => dims.position(plane_ix),
+            None => (0, 0, 0),
+        };
+        self.plane = u32::try_from(plane_ix)?;
+        self.channel = u32::try_from(c * spp + sample % spp)?;
+        self.z = u32::try_from(z)?;
+        self.t = u32::try_from(t)?;
+        self.x = u32::try_from(pixel % plane.width)?;
+        self.y = u32::try_from(pixel / plane.width)?;
+        self.intensity = plane.sample(sample, state.endian)?;
+        Ok(())
+    }
+}
+
+impl_reader!(
+    /// A reader for TIFF images (including OME-TIFF and ImageJ stacks from microscopes) that
+    /// returns each channel of each pixel in each plane as a record.
+    ///
+    /// OME-XML and ImageJ image descriptions are used to find the channel, z slice, and time
+    /// point of each plane; the channel names, physical pixel sizes, and the OME `Plane`
+    /// elements (e.g. exposure times and stage positions) are in the metadata. Only the plane
+    /// that's being read is kept decompressed.
+    TiffReader,
+    TiffRecord,
+    TiffRecord,
+    TiffState,
+    ()
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::readers::RecordReader;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    /// Build a little-endian TIFF with one 16-bit grayscale plane for each of `planes` (with
+    /// their compression types); the first plane's directory gets `description` (if there is one)
+    fn build_tiff(
+        width: u32,
+        height: u32,
+        planes: &[(Vec<u8>, u16)],
+        description: Option<&str>,
+    ) -> Vec<u8> {
+        let mut data = b"II*\0\0\0\0\0".to_vec();
+        let mut ifd_pos = 4;
+        for (ix, (plane, compression)) in planes.iter().enumerate() {
+            let strip_pos = data.len() as u32;
+            data.extend_from_slice(plane);
+            let text_pos = data.len() as u32;
+            let text = description.filter(|_| ix == 0).map(|d| format!("{}\0", d));
+            if let Some(text) = &text {
+                data.extend_from_slice(text.as_bytes());
+            }
+            if data.len() % 2 == 1 {
+                data.push(0);
+            }
+            let mut fields: Vec<(u16, u16, u32, u32)> = vec![
+                (IMAGE_WIDTH, 4, 1, width),
+                (IMAGE_LENGTH, 4, 1, height),
+                (BITS_PER_SAMPLE, 3, 1, 16),
+                (COMPRESSION, 3, 1, u32::from(*compression)),
+                (STRIP_OFFSETS, 4, 1, strip_pos),
+                (ROWS_PER_STRIP, 4, 1, height),
+                (STRIP_BYTE_COUNTS, 4, 1, plane.len() as u32),
+            ];
+            if let Some(text) = &text {
+                fields.push((IMAGE_DESCRIPTION, 2, text.len() as u32, text_pos));
+            }
+            fields.sort_unstable();
+            let pos = data.len() as u32;
+            data[ifd_pos..ifd_pos + 4].copy_from_slice(&pos.to_le_bytes());
+            data.extend_from_slice(&(fields.len() as u16).to_le_bytes());
+            for (tag, field_type, count, value) in fields {
+                data.extend_from_slice(&tag.to_le_bytes());
+                data.extend_from_slice(&field_type.to_le_bytes());
+                data.extend_from_slice(&count.to_le_bytes());
+                if field_type == 3 {
+                    data.extend_from_slice(&(value as u16).to_le_bytes());
+                    data.extend_from_slice(&[0, 0]);
+                } else {
+                    data.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            ifd_pos = data.len();
+            data.extend_from_slice(&[0; 4]);
+        }
+        data
+    }
+
+    fn plane(values: &[u16]) -> (Vec<u8>, u16) {
+        (values.iter().flat_map(|v| v.to_le_bytes()).collect(), 1)
+    }
+
+    #[test]
+    fn test_tiff_reader() -> Result<(), EtError> {
+        let data = build_tiff(2, 2, &[plane(&[1, 2, 3, 400])], Some("a test image"));
+        let mut reader = TiffReader::new(&data[..], None)?;
+        assert_eq!(
+            reader.headers(),
+            ["plane", "channel", "z", "t", "x", "y", "intensity"]
+        );
+        let metadata = reader.metadata();
+        assert_eq!(metadata["width"], 2.into());
+        assert_eq!(metadata["bits_per_sample"], 16.into());
+        assert_eq!(metadata["description"], "a test image".into());
+
+        let record = reader.next()?.unwrap();
+        assert_eq!((record.x, record.y, record.intensity), (0, 0, 1.));
+        let _ = reader.next()?.unwrap();
+        let record = reader.next()?.unwrap();
+        assert_eq!((record.x, record.y, record.intensity), (0, 1, 3.));
+        let record = reader.next()?.unwrap();
+        assert_eq!((record.x, record.y, record.intensity), (1, 1, 400.));
+        assert!(reader.next()?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_rgba_tiff() -> Result<(), EtError> {
+        // a 16x16 RGBA image (the same as the PNG version in CPython's imghdr test data)
+        let data: &[u8] = include_bytes!("../../tests/data/python.tiff");
+        let (mut reader, parser) = crate::readers::get_reader(data, None, None)?;
+        assert_eq!(parser, "tiff");
+        let metadata = reader.metadata();
+        assert_eq!(metadata["width"], 16.into());
+        assert_eq!(metadata["height"], 16.into());
+        assert_eq!(metadata["samples_per_pixel"], 4.into());
+        let mut pixel = Vec::new();
+        let mut n_records = 0;
+        while let Some(record) = reader.next_record()? {
+            if record[4] == 4.into() && record[5] == 0.into() {
+                pixel.push(record[6].clone().into_owned());
+            }
+            n_records += 1;
+        }
+        assert_eq!(n_records, 16 * 16 * 4);
+        assert_eq!(
+            pixel,
+            [78.0.into(), 141.0.into(), 192.0.into(), 175.0.into()]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_ome_tiff() -> Result<(), EtError> {
+        let xml = "<?xml version=\"1.0\"?><OME xmlns=\"http://www.openmicroscopy.org/Schemas/OME/2016-06\">\
+            <Image ID=\"Image:0\"><Pixels DimensionOrder=\"XYCZT\" SizeX=\"1\" SizeY=\"1\" SizeC=\"2\" SizeZ=\"2\" SizeT=\"1\" PhysicalSizeX=\"0.65\" Type=\"uint16\">\
+            <Channel ID=\"Channel:0:0\" Name=\"DAPI\"/><Channel ID=\"Channel:0:1\" Name=\"GFP\"/>\
+            <Plane TheC=\"0\" TheZ=\"0\" TheT=\"0\" ExposureTime=\"50\"/>\
+            </Pixels></Image></OME>";
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&plane(&[40]).0)?;
+        let planes = [
+            plane(&[10]),
+            plane(&[20]),
+            plane(&[30]),
+            (encoder.finish()?, 8),
+        ];
+        let data = build_tiff(1, 1, &planes, Some(xml));
+
+        let (mut reader, _) = crate::readers::get_reader(&data[..], None, None)?;
+        let metadata = reader.metadata();
+        assert_eq!(metadata["size_c"], 2.into());
+        assert_eq!(metadata["physical_size_x"], 0.65.into());
+        assert_eq!(
+            metadata["channels"],
+            Value::List(vec!["DAPI".into(), "GFP".into()])
+        );
+        let mut records = Vec::new();
+        while let Some(record) = reader.next_record()? {
+            records.push(
+                record[1..]
+                    .iter()
+                    .map(|v| v.clone().into_owned())
+                    .collect::<Vec<_>>(),
+            );
+        }
+        // channels vary fastest, then z slices
+        assert_eq!(
+            records,
+            [
+                vec![
+                    0.into(),
+                    0.into(),
+                    0.into(),
+                    0.into(),
+                    0.into(),
+                    10.0.into()
+                ],
+                vec![
+                    1.into(),
+                    0.into(),
+                    0.into(),
+                    0.into(),
+                    0.into(),
+                    20.0.into()
+                ],
+                vec![
+                    0.into(),
+                    1.into(),
+                    0.into(),
+                    0.into(),
+                    0.into(),
+                    30.0.into()
+                ],
+                vec![
+                    1.into(),
+                    1.into(),
+                    0.into(),
+                    0.into(),
+                    0.into(),
+                    40.0.into()
+                ],
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_planes_decoded_lazily() -> Result<(), EtError> {
+        // the second plane isn't valid zlib data, but that's not found until it's read
+        let data = build_tiff(1, 2, &[plane(&[1, 2]), (vec![0xFF; 4], 8)], None);
+        let mut reader = TiffReader::new(&data[..], None)?;
+        assert_eq!(reader.metadata()["n_planes"], 2.into());
+        assert_eq!(reader.next()?.map(|r| r.intensity), Some(1.));
+        assert_eq!(reader.next()?.map(|r| r.intensity), Some(2.));
+        assert!(reader.next().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_packbits() -> Result<(), EtError> {
+        let mut out = Vec::new();
+        unpack_bits(&[0xFE, 0xAA, 0x02, 0x80, 0x00, 0x2A, 0x80], &mut out)?;
+        assert_eq!(out, [0xAA, 0xAA, 0xAA, 0x80, 0x00, 0x2A]);
+        assert!(unpack_bits(&[0x04, 0x01], &mut Vec::new()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_bad_tiffs() {
+        let data = build_tiff(2, 2, &[plane(&[1, 2, 3])], None);
+        assert!(TiffReader::new(&data[..], None).is_err());
+        let data = build_tiff(1, 1, &[(plane(&[1]).0, 5)], None);
+        assert!(TiffReader::new(&data[..], None).is_err());
+        assert!(TiffReader::new(&b"II*\0\xFF\xFF\0\0"[..], None).is_err());
+        assert!(TiffReader::new(&b"II+\0"[..], None).is_err());
+    }
+}
@@ -1,18 +1,23 @@
 use alloc::borrow::Cow;
+use alloc::format;
 use alloc::str::from_utf8;
 use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
 
+use memchr::memrchr;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
 use crate::impl_reader;
 use crate::parsers::common::NewLine;
 use crate::parsers::extract_opt;
 use crate::parsers::tsv_inference::{
-    sniff_params_from_data, sniff_types_from_data, split, TsvFieldType, DEFAULT_DELIM,
+    is_comment, sniff_params_from_data, sniff_types_from_data, split, TsvFieldType, DEFAULT_DELIM,
     DEFAULT_QUOTE,
 };
 use crate::parsers::FromSlice;
-use crate::record::{StateMetadata, Value};
+use crate::record::{PushFields, StateMetadata, Value};
 use crate::EtError;
 
 /// Parameters for parsing TSVs
@@ -26,14 +31,25 @@ pub struct TsvParams {
     pub delim_char: Option<u8>,
     /// The character used to quote text fields or fields containing the delimiter.
     pub quote_char: Option<u8>,
-    /// The number of lines to skip before the column titles and data start.
+    /// Lines starting with this character are skipped.
+    pub comment_char: Option<u8>,
+    /// The number of lines (not counting comments) to skip before the column titles and data
+    /// start.
     pub skip_lines: Option<usize>,
+    /// Whether the first line holds the column titles; if not, the columns are named
+    /// `column_1`, `column_2`, etc.
+    pub has_header: bool,
     /// Automatically determine the delimiter, quoting character, and number of lines to skip.
     pub sniff_file: bool,
+    /// Guess whether there's a header from the start of the file instead of using
+    /// `has_header` (if `sniff_file` is on).
+    pub sniff_header: bool,
     /// Automatically determine the types of each of the fields in the TSV.
     pub infer_types: bool,
     /// The data types of each of the fields in the TSV
     pub types: Vec<TsvFieldType>,
+    /// For files without a header, the number of fields in the first line
+    n_columns: usize,
 }
 
 impl Default for TsvParams {
@@ -41,10 +57,14 @@ impl Default for TsvParams {
         TsvParams {
             delim_char: None,
             quote_char: None,
+            comment_char: None,
             skip_lines: None,
+            has_header: true,
             sniff_file: true,
+            sniff_header: false,
             infer_types: true,
             types: vec![],
+            n_columns: 0,
         }
     }
 }
@@ -63,6 +83,57 @@ impl TsvParams {
         self.quote_char = Some(c);
         self
     }
+
+    /// Set the character that starts comment lines
+    #[must_use]
+    pub fn comment(mut self, c: u8) -> Self {
+        self.comment_char = Some(c);
+        self
+    }
+
+    /// Set the number of lines to skip before the column titles
+    #[must_use]
+    pub fn skip_lines(mut self, n: usize) -> Self {
+        self.skip_lines = Some(n);
+        self
+    }
+
+    /// Set whether the first line holds the column titles (instead of guessing)
+    #[must_use]
+    pub fn has_header(mut self, has_header: bool) -> Self {
+        self.has_header = has_header;
+        self.sniff_header = false;
+        self
+    }
+
+    /// Set whether to guess if the first line holds the column titles
+    #[must_use]
+    pub fn sniff_header(mut self, sniff_header: bool) -> Self {
+        self.sniff_header = sniff_header;
+        self
+    }
+
+    /// Set whether the types of the fields are inferred (otherwise every field is a string)
+    #[must_use]
+    pub fn infer_types(mut self, infer_types: bool) -> Self {
+        self.infer_types = infer_types;
+        self
+    }
+}
+
+/// Extract the next line that isn't a comment (or `None` if there isn't a complete line left)
+fn next_line<'a>(
+    buffer: &'a [u8],
+    eof: bool,
+    consumed: &mut usize,
+    comment_char: Option<u8>,
+) -> Result<Option<&'a [u8]>, EtError> {
+    while let Some(NewLine(line)) = extract_opt::<NewLine>(buffer, eof, consumed, &mut 0)? {
+        if !is_comment(line, comment_char) {
+            return Ok(Some(line));
+        }
+    }
+    Ok(None)
 }
 
 /// Track the current state of the TSV parser
@@ -72,6 +143,7 @@ pub struct TsvState {
     types: Option<Vec<TsvFieldType>>,
     delim_char: u8,
     quote_char: u8,
+    comment_char: Option<u8>,
 }
 
 impl<'b: 's, 's> FromSlice<'b, 's> for TsvState {
@@ -91,12 +163,26 @@ impl<'b: 's, 's> FromSlice<'b, 's> for TsvState {
         }
         let con = &mut 0;
         for _ in 0..state.skip_lines.unwrap_or(0) {
-            if extract_opt::<NewLine>(buffer, false, con, &mut 0)?.is_none() {
+            if next_line(buffer, false, con, state.comment_char)?.is_none() {
                 return Err("could not skip header lines".into());
             }
         }
-        if !NewLine::parse(&buffer[*con..], eof, con, &mut 0)? {
-            return Ok(false);
+        let start = *con;
+        let line = match next_line(buffer, eof, con, state.comment_char)? {
+            Some(line) => line,
+            // an empty file is a table without any columns (or rows)
+            None => return Ok(eof && buffer.is_empty()),
+        };
+        if !state.has_header {
+            // the first line is data, so leave it to be read as a record
+            let mut fields = Vec::new();
+            state.n_columns = split(
+                &mut fields,
+                line,
+                state.delim_char.unwrap_or(DEFAULT_DELIM),
+                state.quote_char.unwrap_or(DEFAULT_QUOTE),
+            )?;
+            *con = start;
         }
         *consumed += *con;
         Ok(true)
@@ -105,21 +191,29 @@ impl<'b: 's, 's> FromSlice<'b, 's> for TsvState {
     fn get(&mut self, buffer: &'b [u8], state: &'s Self::State) -> Result<(), EtError> {
         let con = &mut 0;
         for _ in 0..state.skip_lines.unwrap_or(0) {
-            if extract_opt::<NewLine>(buffer, false, con, &mut 0)?.is_none() {
+            if next_line(buffer, false, con, state.comment_char)?.is_none() {
                 return Err("could not skip header lines".into());
             }
         }
-        let header = if let Some(NewLine(h)) = extract_opt::<NewLine>(buffer, false, con, &mut 0)? {
-            h
-        } else {
-            return Err("could not read headers from TSV".into());
-        };
+        self.delim_char = state.delim_char.unwrap_or(DEFAULT_DELIM);
+        self.quote_char = state.quote_char.unwrap_or(DEFAULT_QUOTE);
+        self.comment_char = state.comment_char;
         if state.infer_types {
             self.types = Some(state.types.clone());
         }
+        if !state.has_header {
+            self.headers = (1..=state.n_columns)
+                .map(|i| format!("column_{}", i))
+                .collect();
+            return Ok(());
+        }
+        if buffer.is_empty() {
+            self.headers = Vec::new();
+            return Ok(());
+        }
 
-        self.delim_char = state.delim_char.unwrap_or(DEFAULT_DELIM);
-        self.quote_char = state.quote_char.unwrap_or(DEFAULT_QUOTE);
+        let header = next_line(buffer, true, con, state.comment_char)?
+            .ok_or("could not read headers from TSV")?;
 
         // prefill with something impossible so we can tell how big the header is
         let delim_slice = [self.delim_char];
@@ -148,7 +242,9 @@ impl StateMetadata for TsvState {
 
 /// Values from the current line of the TSV
 #[derive(Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct TsvRecord<'r> {
+    #[cfg_attr(feature = "serde", serde(borrow))]
     values: Vec<Value<'r>>,
 }
 
@@ -159,9 +255,22 @@ impl<'b: 's, 's> FromSlice<'b, 's> for TsvRecord<'s> {
         buffer: &[u8],
         eof: bool,
         consumed: &mut usize,
-        _state: &mut Self::State,
+        state: &mut Self::State,
     ) -> Result<bool, EtError> {
-        NewLine::parse(buffer, eof, consumed, &mut 0)
+        let con = &mut 0;
+        let line = match next_line(buffer, eof, con, state.comment_char)? {
+            Some(line) => line,
+            None => return Ok(false),
+        };
+        // a last line without a line ending that's missing fields was likely cut off
+        if !buffer[..*con].ends_with(b"\n") {
+            let mut fields = Vec::new();
+            if split(&mut fields, line, state.delim_char, state.quote_char)? < state.headers.len() {
+                return Err(EtError::from("Line had a bad number of records").incomplete());
+            }
+        }
+        *consumed += *con;
+        Ok(true)
     }
 
     fn get(&mut self, mut buffer: &'b [u8], state: &'s Self::State) -> Result<(), EtError> {
@@ -171,16 +280,27 @@ impl<'b: 's, 's> FromSlice<'b, 's> for TsvRecord<'s> {
         if buffer.last() == Some(&b'\r') {
             buffer = &buffer[..buffer.len() - 1];
         }
+        // the buffer includes any comments before the line
+        if state.comment_char.is_some() {
+            if let Some(start) = memrchr(b'\n', buffer) {
+                buffer = &buffer[start + 1..];
+            }
+        }
         let mut records = vec![Cow::Borrowed(""); state.headers.len()];
         let n_records = split(&mut records, buffer, state.delim_char, state.quote_char)?;
         if n_records != state.headers.len() {
             return Err("Line had a bad number of records".into());
         }
         if let Some(types) = &state.types {
+            // fields past the ones seen when inferring types (e.g. if the buffer ended before
+            // the first full line of data) are left as strings instead of being dropped
             self.values = records
                 .into_iter()
-                .zip(types)
-                .map(|(v, ty)| ty.coerce(v))
+                .enumerate()
+                .map(|(ix, v)| match types.get(ix) {
+                    Some(ty) => ty.coerce(v),
+                    None => Value::from(v),
+                })
                 .collect();
         } else {
             self.values = records.into_iter().map(Value::from).collect();
@@ -195,12 +315,19 @@ impl<'r> From<TsvRecord<'r>> for Vec<Value<'r>> {
     }
 }
 
+impl<'r> PushFields<'r> for TsvRecord<'r> {
+    fn push_fields<E: Extend<Value<'r>>>(self, fields: &mut E) {
+        fields.extend(self.values);
+    }
+}
+
 impl_reader!(TsvReader, TsvRecord, TsvRecord<'r>, TsvState, TsvParams);
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    use crate::error::EtErrorKind;
     use crate::readers::RecordReader;
 
     #[test]
@@ -255,6 +382,49 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_comments() -> Result<(), EtError> {
+        const TEST_TEXT: &[u8] = b"# instrument: A, B, C\nid,value\n# blank\nx,1\n#\ny,2.5\n";
+        let mut pt = TsvReader::new(TEST_TEXT, Some(TsvParams::default().comment(b'#')))?;
+        assert_eq!(&pt.headers(), &["id", "value"]);
+        assert_eq!(pt.next()?.unwrap().values, ["x".into(), 1.0.into()]);
+        assert_eq!(pt.next()?.unwrap().values, ["y".into(), 2.5.into()]);
+        assert!(pt.next()?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_header() -> Result<(), EtError> {
+        const TEST_TEXT: &[u8] = b"x\t1\ty\ny\t2.5\t\"a\tb\"\n";
+        let params = TsvParams::default().has_header(false);
+        let mut pt = TsvReader::new(TEST_TEXT, Some(params))?;
+        assert_eq!(&pt.headers(), &["column_1", "column_2", "column_3"]);
+        assert_eq!(
+            pt.next()?.unwrap().values,
+            ["x".into(), 1.0.into(), "y".into()]
+        );
+        assert_eq!(
+            pt.next()?.unwrap().values,
+            ["y".into(), 2.5.into(), "a\tb".into()]
+        );
+        assert!(pt.next()?.is_none());
+
+        let params = TsvParams::default().has_header(false).infer_types(false);
+        let mut pt = TsvReader::new(TEST_TEXT, Some(params))?;
+        assert_eq!(pt.next()?.unwrap().values[1], "1".into());
+        Ok(())
+    }
+
+    #[test]
+    fn test_truncated() -> Result<(), EtError> {
+        const TEST_TEXT: &[u8] = b"id\tvalue\tunit\nx\t1\tmg\ny\t2";
+        let mut pt = TsvReader::new(TEST_TEXT, None)?;
+        assert!(pt.next()?.is_some());
+        let err = pt.next().unwrap_err();
+        assert!(matches!(err.kind, EtErrorKind::Truncated { .. }));
+        Ok(())
+    }
+
     #[test]
     fn test_bad_fuzzes() -> Result<(), EtError> {
         const TEST_TEXT: &[u8] = b"U,\n\n\n";
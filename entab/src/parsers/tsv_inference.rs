@@ -3,8 +3,7 @@ use alloc::str::from_utf8;
 use alloc::vec;
 use alloc::vec::Vec;
 
-use bytecount::count;
-use memchr::memchr;
+use memchr::{memchr, memrchr};
 
 use crate::error::EtError;
 use crate::parsers::common::NewLine;
@@ -133,17 +132,47 @@ pub(crate) fn split<'a>(
     Ok(token_num)
 }
 
-/// Determine the delimiter, quoting character, and number of comment lines to skip.
+/// How many bytes at the start of the data are looked at to determine its dialect
+const SNIFF_LEN: usize = 8192;
+
+/// The part of `data` that's sniffed: the first `SNIFF_LEN` bytes, cut back to the end of the
+/// last whole line in them.
+fn sniff_sample(data: &[u8]) -> &[u8] {
+    if data.len() <= SNIFF_LEN {
+        return data;
+    }
+    let sample = &data[..SNIFF_LEN];
+    memrchr(b'\n', sample).map_or(sample, |end| &sample[..=end])
+}
+
+/// Determine the delimiter, quoting character, number of comment lines to skip, and whether
+/// there's a header line from the first few KB of the data.
 pub fn sniff_params_from_data(params: &mut TsvParams, data: &[u8]) {
+    let data = sniff_sample(data);
+    if params.quote_char.is_none() {
+        // the quote is whichever one is used to wrap whole fields (so e.g. apostrophes in
+        // text don't count)
+        let mut n_quoted = [0; 2];
+        let con = &mut 0;
+        while let Ok(NewLine(line)) = extract(data, con, &mut 0) {
+            if !is_comment(line, params.comment_char) {
+                count_quoted(line, &mut n_quoted);
+            }
+        }
+        params.quote_char = Some(if n_quoted[1] > n_quoted[0] {
+            b'\''
+        } else {
+            b'"'
+        });
+    }
+    let quote_char = params.quote_char.unwrap_or(DEFAULT_QUOTE);
+
     let con = &mut 0;
     let mut stats = [StreamingStats::new(); N_DELIMS];
-    let mut quote_diff = 0;
     while let Ok(NewLine(line)) = extract(data, con, &mut 0) {
-        count_bytes(line, &mut stats, &mut quote_diff);
-    }
-
-    if params.quote_char.is_none() {
-        params.quote_char = Some(if quote_diff < 0 { b'\'' } else { b'"' });
+        if !is_comment(line, params.comment_char) {
+            count_bytes(line, quote_char, &mut stats);
+        }
     }
 
     let mut possible_delims = Vec::new();
@@ -175,7 +204,10 @@ pub fn sniff_params_from_data(params: &mut TsvParams, data: &[u8]) {
     let mut skip_lines = 0;
     let mut in_data = 0;
     while let Ok(NewLine(line)) = extract(data, con, &mut 0) {
-        let n_delims = count(line, delim_char);
+        if is_comment(line, params.comment_char) {
+            continue;
+        }
+        let n_delims = count_delims(line, delim_char, quote_char);
         #[allow(clippy::cast_precision_loss)]
         if (n_delims as f64 - avg_delims).abs() < 1. {
             if in_data == 0 {
@@ -192,6 +224,90 @@ pub fn sniff_params_from_data(params: &mut TsvParams, data: &[u8]) {
     if params.skip_lines.is_none() {
         params.skip_lines = Some(skip_lines);
     }
+
+    if params.sniff_header {
+        params.has_header = sniff_header(params, data);
+    }
+}
+
+/// Guess if the first line (after any skipped lines) holds the column titles.
+///
+/// It does unless the columns that have a consistent type (e.g. numbers) in the rest of the
+/// lines mostly have a value of that type in the first line too; if every column is text,
+/// there's no way to tell so a header is assumed.
+fn sniff_header(params: &TsvParams, data: &[u8]) -> bool {
+    let delim_char = params.delim_char.unwrap_or(DEFAULT_DELIM);
+    let quote_char = params.quote_char.unwrap_or(DEFAULT_QUOTE);
+    let mut first = Vec::new();
+    let mut fields = Vec::new();
+    let mut types: Vec<TsvFieldType> = Vec::new();
+    let mut line_ix = 0;
+    let con = &mut 0;
+    while let Ok(NewLine(line)) = extract(data, con, &mut 0) {
+        if is_comment(line, params.comment_char) {
+            continue;
+        }
+        line_ix += 1;
+        if line_ix <= params.skip_lines.unwrap_or(0) {
+            continue;
+        }
+        if line_ix == params.skip_lines.unwrap_or(0) + 1 {
+            if split(&mut first, line, delim_char, quote_char).is_err() {
+                return true;
+            }
+            continue;
+        }
+        if split(&mut fields, line, delim_char, quote_char).is_err() {
+            continue;
+        }
+        if fields.len() > types.len() {
+            types.resize(fields.len(), TsvFieldType::default());
+        }
+        for (ty, field) in types.iter_mut().zip(&fields) {
+            ty.infer(field);
+        }
+    }
+
+    let mut votes = 0;
+    for (ty, title) in types.iter().zip(&first) {
+        if !ty.is_typed() {
+            continue;
+        }
+        let mut with_title = *ty;
+        with_title.infer(title);
+        votes += if with_title.is_typed() { -1 } else { 1 };
+    }
+    votes >= 0
+}
+
+/// Does the start of a file (that no other format matched) look like delimited text?
+///
+/// This is true if it's text where every line (ignoring a partial line at the end) has the
+/// same number (at least one) of tabs, commas, semicolons, or pipes outside of quotes.
+pub(crate) fn looks_delimited(data: &[u8]) -> bool {
+    if data
+        .iter()
+        .any(|b| matches!(b, 0..=8 | 11 | 12 | 14..=31 | 127))
+    {
+        return false;
+    }
+    // the data may end partway through a multibyte character
+    if let Err(e) = from_utf8(data) {
+        if e.error_len().is_some() {
+            return false;
+        }
+    }
+    let data = match memrchr(b'\n', data) {
+        Some(end) => &data[..end],
+        None => data,
+    };
+    b"\t,;|".iter().any(|delim| {
+        let mut n_delims = data
+            .split(|b| *b == b'\n')
+            .map(|line| count_delims(line, *delim, DEFAULT_QUOTE));
+        let first = n_delims.next().unwrap_or(0);
+        first > 0 && n_delims.all(|n| n == first)
+    })
 }
 
 /// Determine the types of the fields in the data.
@@ -202,8 +318,11 @@ pub fn sniff_types_from_data(params: &mut TsvParams, data: &[u8]) {
     let mut line_ix = 0;
     let con = &mut 0;
     while let Ok(NewLine(line)) = extract(data, con, &mut 0) {
-        // TODO: + 1 for the "headers" line; this should probably be configurable
-        if line_ix < params.skip_lines.unwrap_or(0) + 1 {
+        if is_comment(line, params.comment_char) {
+            continue;
+        }
+        // skip the header line too (if there is one)
+        if line_ix < params.skip_lines.unwrap_or(0) + usize::from(params.has_header) {
             line_ix += 1;
             continue;
         }
@@ -235,9 +354,24 @@ pub const DEFAULT_DELIM: u8 = b'\t';
 /// The default quoting character if one is not provided.
 pub const DEFAULT_QUOTE: u8 = b'"';
 
-fn count_bytes(line: &[u8], stats: &mut [StreamingStats; N_DELIMS], quote_diff: &mut i32) {
+/// Is `line` a comment (i.e. does it start with `comment_char`)?
+#[inline]
+pub(crate) fn is_comment(line: &[u8], comment_char: Option<u8>) -> bool {
+    comment_char.is_some() && line.first() == comment_char.as_ref()
+}
+
+/// Count the number of times each of the possible delimiters appears (outside of quotes) in
+/// `line`.
+fn count_bytes(line: &[u8], quote_char: u8, stats: &mut [StreamingStats; N_DELIMS]) {
     let mut counts = [0u16; N_DELIMS];
+    let mut quoted = false;
     for b in line {
+        if *b == quote_char {
+            quoted = !quoted;
+            continue;
+        } else if quoted {
+            continue;
+        }
         counts[match b {
             // possible delimiters
             b'\t' => 0,
@@ -248,14 +382,6 @@ fn count_bytes(line: &[u8], stats: &mut [StreamingStats; N_DELIMS], quote_diff:
             b',' => 5,
             b'^' => 6,
             b' ' => 7,
-            b'\'' => {
-                *quote_diff = quote_diff.saturating_sub(1);
-                8
-            }
-            b'"' => {
-                *quote_diff = quote_diff.saturating_add(1);
-                8
-            }
             // everything else
             _ => 8,
         }] += 1;
@@ -265,6 +391,53 @@ fn count_bytes(line: &[u8], stats: &mut [StreamingStats; N_DELIMS], quote_diff:
     }
 }
 
+/// The number of `delim`s in `line` that aren't inside of quotes
+fn count_delims(line: &[u8], delim: u8, quote_char: u8) -> usize {
+    let mut quoted = false;
+    let mut n_delims = 0;
+    for b in line {
+        if *b == quote_char {
+            quoted = !quoted;
+        } else if *b == delim && !quoted {
+            n_delims += 1;
+        }
+    }
+    n_delims
+}
+
+/// Count the fields in `line` that are wrapped in double quotes (`n_quoted[0]`) and in single
+/// quotes (`n_quoted[1]`), i.e. that start and end next to one of the possible delimiters.
+fn count_quoted(line: &[u8], n_quoted: &mut [usize; 2]) {
+    let is_boundary = |pos: Option<&u8>| pos.is_none_or(|b| DELIMS.contains(b));
+    let mut pos = 0;
+    while pos < line.len() {
+        let quote_ix = match line[pos] {
+            b'"' => 0,
+            b'\'' => 1,
+            _ => {
+                pos += 1;
+                continue;
+            }
+        };
+        if !is_boundary(pos.checked_sub(1).map(|p| &line[p])) {
+            pos += 1;
+            continue;
+        }
+        let quote = line[pos];
+        let close = line[pos + 1..]
+            .iter()
+            .enumerate()
+            .position(|(ix, b)| *b == quote && is_boundary(line.get(pos + ix + 2)));
+        match close {
+            Some(close) => {
+                n_quoted[quote_ix] += 1;
+                pos += close + 2;
+            }
+            None => pos += 1,
+        }
+    }
+}
+
 const TSV_STR: u8 = 1;
 const TSV_BOOL: u8 = 2;
 const TSV_FLOAT: u8 = 4;
@@ -318,21 +491,41 @@ impl TsvFieldType {
         if numeric && !nonnumeric {
             if has_comma || has_period {
                 possible_type |= TSV_FLOAT;
-            } else if !(has_comma || has_period) {
-                possible_type |= TSV_INT;
+            } else {
+                // integers can also be floats so a column with a mix of both is read as floats
+                possible_type |= TSV_INT | TSV_FLOAT;
             }
         }
+        // instruments often write out glitches and overflows as NaN or infinity
+        let unsigned = field.trim_start_matches(['+', '-']);
+        if ["nan", "inf", "infinity"]
+            .iter()
+            .any(|token| unsigned.eq_ignore_ascii_case(token))
+        {
+            possible_type |= TSV_FLOAT;
+        }
 
         // TODO: check for dates?
         self.ty &= possible_type;
     }
 
+    /// The highest bit that's set in the type, i.e. the most specific type that every value
+    /// seen so far could be (or 128 if no values have been seen since every bit is still set)
+    fn best_type(&self) -> u8 {
+        1 << (7 - self.ty.leading_zeros().min(7))
+    }
+
+    /// Have the values seen so far all been a type other than text (e.g. numbers)?
+    fn is_typed(&self) -> bool {
+        !matches!(self.best_type(), TSV_STR | 128)
+    }
+
     /// Coerce a string into a Value
     #[allow(clippy::match_same_arms)] // TODO: remove when dates are supported
     #[must_use]
     pub fn coerce<'a>(&self, field: Cow<'a, str>) -> Value<'a> {
         let f = field.trim();
-        match 128 >> self.ty.leading_zeros() {
+        match self.best_type() {
             // TODO: we can't use `trim` because that requires a borrow inside this function :/
             TSV_STR => Value::from(field),
             TSV_BOOL => {
@@ -408,6 +601,72 @@ mod test {
         assert_eq!(params.delim_char, Some(b','));
         assert_eq!(params.quote_char, Some(b'\''));
         assert_eq!(params.skip_lines, Some(1));
+
+        // apostrophes inside of fields aren't quotes, and delimiters inside quotes don't count
+        let mut params = TsvParams::default().sniff_header(true);
+        sniff_params_from_data(
+            &mut params,
+            b"name;note\n\"a;b\";don't\n\"c\";it's 1,5\n\"d\";x\n",
+        );
+        assert_eq!(params.delim_char, Some(b';'));
+        assert_eq!(params.quote_char, Some(b'"'));
+        assert!(params.has_header);
+
+        // the first line is data if it has numbers where the rest of the lines do
+        let mut params = TsvParams::default().sniff_header(true);
+        sniff_params_from_data(&mut params, b"a,1,2.5\nb,2,3\nc,3,4.5\n");
+        assert!(!params.has_header);
+        let mut params = TsvParams::default().sniff_header(true);
+        sniff_params_from_data(&mut params, b"id,count,mass\nb,2,3\nc,3,4.5\n");
+        assert!(params.has_header);
+        // but only if asked to (e.g. a CSV or TSV is assumed to have one)
+        let mut params = TsvParams::default();
+        sniff_params_from_data(&mut params, b"a,1,2.5\nb,2,3\nc,3,4.5\n");
+        assert!(params.has_header);
+        // and an explicit setting isn't overridden
+        let mut params = TsvParams::default().sniff_header(true).has_header(true);
+        sniff_params_from_data(&mut params, b"a,1,2.5\nb,2,3\nc,3,4.5\n");
+        assert!(params.has_header);
+
+        // only the start of large files is looked at
+        let mut data = b"a\tb\n".to_vec();
+        data.extend(b"1\t2\n".repeat(SNIFF_LEN));
+        assert_eq!(sniff_sample(&data).len(), SNIFF_LEN);
         Ok(())
     }
+
+    #[test]
+    fn test_looks_delimited() {
+        assert!(looks_delimited(b"a,b,c\n1,2,3\n4,5,6"));
+        assert!(looks_delimited(b"a;\"b;c\"\n1;2\n"));
+        assert!(looks_delimited(b"sample\tvalue"));
+        assert!(!looks_delimited(
+            b"Some text, which is prose.\nAnd more of it\n"
+        ));
+        assert!(!looks_delimited(b"a,b\n1,\x002\n"));
+        assert!(!looks_delimited(b"a,b\n1,\xff\n"));
+        // a character cut off at the end isn't an error
+        assert!(looks_delimited(b"a,b\n\xc3\xa9,1\n\xc3"));
+    }
+
+    #[test]
+    fn test_non_finite_floats() {
+        let mut ty = TsvFieldType::default();
+        for field in ["1", "NaN", "-inf", "2.5"] {
+            ty.infer(field);
+        }
+        assert_eq!(ty.coerce("3".into()), Value::Float(3.));
+        assert_eq!(
+            ty.coerce("-Infinity".into()),
+            Value::Float(f64::NEG_INFINITY)
+        );
+        assert!(matches!(ty.coerce("nan".into()), Value::Float(f) if f.is_nan()));
+
+        let mut ty = TsvFieldType::default();
+        ty.infer("1");
+        ty.infer("2");
+        assert_eq!(ty.coerce("3".into()), Value::Integer(3));
+        ty.infer("nano");
+        assert_eq!(ty.coerce("nano".into()), Value::String("nano".into()));
+    }
 }
@@ -0,0 +1,443 @@
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::capabilities::Capabilities;
+use crate::parsers::{extract_with, Endian, FromSlice};
+use crate::record::{StateMetadata, Value};
+use crate::EtError;
+use crate::{impl_reader, impl_record};
+
+/// The size of each scan's entry in a function's `.IDX` file
+const IDX_ENTRY_LEN: usize = 22;
+/// The size of each function's entry in a run's `_FUNCTNS.INF` file
+const FUNCTION_ENTRY_LEN: usize = 416;
+
+/// Is `path` a Waters run, i.e. a `.raw` directory or one with a `_FUNCTNS.INF` in it?
+pub fn is_run(path: &Path) -> bool {
+    path.is_dir()
+        && (path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("raw"))
+            || path.join("_FUNCTNS.INF").is_file())
+}
+
+/// Find the data to read for `path`: the path itself if it's a file or the first function
+/// (`_FUNC001.DAT`) if it's a Waters `.raw` directory.
+///
+/// # Errors
+/// If `path` is a directory without a `_FUNC001.DAT` in it, an error is returned.
+pub fn data_path(path: &Path) -> Result<PathBuf, EtError> {
+    if !path.is_dir() {
+        return Ok(path.to_path_buf());
+    }
+    let data = path.join("_FUNC001.DAT");
+    if data.is_file() {
+        Ok(data)
+    } else {
+        Err(format!(
+            "{} is a directory, but not a Waters run (it has no _FUNC001.DAT)",
+            path.display()
+        )
+        .into())
+    }
+}
+
+/// Where a scan's peaks are, from its entry in the function's `.IDX` file
+#[derive(Clone, Copy, Debug, Default)]
+struct WatersScan {
+    offset: u64,
+    n_peaks: usize,
+    time: f64,
+}
+
+/// Read the entry for each scan out of a function's `.IDX` file.
+///
+/// Each entry is 22 bytes: the offset of the scan's peaks in the `.DAT` file, the number of
+/// peaks (in the low 22 bits; the rest are flags), the TIC, the retention time in minutes, and
+/// then the base peak.
+fn read_index(idx: &[u8]) -> Result<Vec<WatersScan>, EtError> {
+    let entries = idx.chunks_exact(IDX_ENTRY_LEN);
+    if !entries.remainder().is_empty() {
+        return Err("Waters index file is truncated".into());
+    }
+    entries
+        .map(|entry| {
+            let offset = extract_with::<u32>(entry, &mut 0, &Endian::Little)?;
+            let n_peaks = extract_with::<u32>(entry, &mut 4, &Endian::Little)? & 0x3F_FFFF;
+            let time = extract_with::<f32>(entry, &mut 12, &Endian::Little)?;
+            Ok(WatersScan {
+                offset: u64::from(offset),
+                n_peaks: n_peaks as usize,
+                time: f64::from(time),
+            })
+        })
+        .collect()
+}
+
+/// How the peaks of a function are stored in its `.DAT` file
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum PeakFormat {
+    /// 6 bytes: the intensity (13 bits of mantissa and a 3 bit power of 4) and then the m/z
+    /// (as a fixed-point number with 16 fractional bits)
+    #[default]
+    Packed,
+    /// 8 bytes: the intensity and then the m/z, both as 32-bit floats
+    Float,
+}
+
+impl PeakFormat {
+    /// The size of each peak in bytes
+    fn len(self) -> usize {
+        match self {
+            PeakFormat::Packed => 6,
+            PeakFormat::Float => 8,
+        }
+    }
+
+    /// Decode a peak into its m/z and intensity
+    fn decode(self, peak: &[u8]) -> Result<(f64, f64), EtError> {
+        Ok(match self {
+            PeakFormat::Packed => {
+                let raw_intensity = extract_with::<u16>(peak, &mut 0, &Endian::Little)?;
+                let raw_mz = extract_with::<u32>(peak, &mut 2, &Endian::Little)?;
+                (
+                    f64::from(raw_mz) / 65536.,
+                    f64::from(raw_intensity & 0x1FFF) * 4_f64.powi(i32::from(raw_intensity >> 13)),
+                )
+            }
+            PeakFormat::Float => {
+                let intensity = extract_with::<f32>(peak, &mut 0, &Endian::Little)?;
+                let mz = extract_with::<f32>(peak, &mut 4, &Endian::Little)?;
+                (f64::from(mz), f64::from(intensity))
+            }
+        })
+    }
+}
+
+/// Read how the peaks of `function` (counting from 1) are stored out of a run's `_FUNCTNS.INF`.
+///
+/// Each function has a 416 byte entry that starts with a little-endian word with the type of
+/// the function in its low 5 bits and how its peaks are packed in bits 10 to 13 (0 for 6 byte
+/// peaks and 1 for 8 byte ones).
+fn read_peak_format(functions: &[u8], function: usize) -> Result<PeakFormat, EtError> {
+    let start = function.saturating_sub(1) * FUNCTION_ENTRY_LEN;
+    let entry = functions
+        .get(start..start + FUNCTION_ENTRY_LEN)
+        .ok_or_else(|| format!("Waters function {} isn't in _FUNCTNS.INF", function))?;
+    let word = extract_with::<u16>(entry, &mut 0, &Endian::Little)?;
+    match (word >> 10) & 0xF {
+        0 => Ok(PeakFormat::Packed),
+        1 => Ok(PeakFormat::Float),
+        x => Err(format!(
+            "Waters function {} has peaks in an unsupported format ({})",
+            function, x
+        )
+        .into()),
+    }
+}
+
+/// Check that the scans in the index are as far apart as `format`'s peaks need (so a format
+/// that doesn't match the data is an error instead of garbage values)
+fn check_peak_format(scans: &[WatersScan], format: PeakFormat) -> Result<(), EtError> {
+    for pair in scans.windows(2) {
+        let len = (pair[0].n_peaks * format.len()) as u64;
+        if pair[1].offset > pair[0].offset && pair[1].offset - pair[0].offset != len {
+            return Err(format!(
+                "Waters scan at {} min doesn't have {} byte peaks",
+                pair[0].time,
+                format.len()
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// Read the `$$ Name: value` lines of a run's `_HEADER.TXT` into metadata (with the names
+/// lowercased and their spaces replaced with underscores)
+fn read_header(text: &str) -> BTreeMap<String, Value<'static>> {
+    text.lines()
+        .filter_map(|line| line.strip_prefix("$$ ")?.split_once(':'))
+        .filter(|(_, value)| !value.trim().is_empty())
+        .map(|(key, value)| {
+            let key = key.trim().to_lowercase().replace(' ', "_");
+            (key, value.trim().to_string().into())
+        })
+        .collect()
+}
+
+/// The internal state for the `WatersReader`
+#[derive(Clone, Debug, Default)]
+pub struct WatersState {
+    scans: Vec<WatersScan>,
+    format: PeakFormat,
+    scan_ix: usize,
+    n_peaks_left: usize,
+    /// How far into the `.DAT` file the reader is
+    pos: u64,
+    cur_time: f64,
+    cur_mz: f64,
+    cur_intensity: f64,
+    metadata: BTreeMap<String, Value<'static>>,
+}
+
+impl StateMetadata for WatersState {
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        self.metadata.clone()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n_left = self.n_peaks_left
+            + self
+                .scans
+                .get(self.scan_ix..)
+                .map_or(0, |scans| scans.iter().map(|s| s.n_peaks).sum());
+        (n_left, Some(n_left))
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::SIZED | Capabilities::TYPED_SCHEMA | Capabilities::HAS_METADATA
+    }
+
+    fn header(&self) -> Vec<&str> {
+        vec!["time", "mz", "intensity"]
+    }
+}
+
+impl<'b: 's, 's> FromSlice<'b, 's> for WatersState {
+    type State = String;
+
+    fn parse(
+        _rb: &[u8],
+        _eof: bool,
+        _consumed: &mut usize,
+        _state: &mut Self::State,
+    ) -> Result<bool, EtError> {
+        // the `.DAT` file doesn't have a header; everything is in the other files in the run
+        Ok(true)
+    }
+
+    fn get(&mut self, _rb: &'b [u8], filename: &'s Self::State) -> Result<(), EtError> {
+        if filename.is_empty() {
+            return Err("Parser requires a filename; streams can not be parsed.".into());
+        }
+        let path = Path::new(filename);
+        let idx_path = ["IDX", "idx"]
+            .iter()
+            .map(|ext| path.with_extension(ext))
+            .find(|p| p.is_file())
+            .ok_or_else(|| format!("No index file found for {}", path.display()))?;
+        self.scans = read_index(&std::fs::read(idx_path)?)?;
+
+        // the function number is in the file name (e.g. `_FUNC002.DAT`)
+        let function = path
+            .file_stem()
+            .and_then(|stem| stem.to_str()?.strip_prefix("_FUNC")?.parse::<usize>().ok());
+
+        let mut metadata = BTreeMap::new();
+        // runs without a `_FUNCTNS.INF` are assumed to have the usual 6 byte peaks
+        self.format = PeakFormat::Packed;
+        if let Some(dir) = path.parent() {
+            if let Ok(header) = std::fs::read(dir.join("_HEADER.TXT")) {
+                metadata = read_header(&String::from_utf8_lossy(&header));
+            }
+            if let Ok(functions) = std::fs::read(dir.join("_FUNCTNS.INF")) {
+                self.format = read_peak_format(&functions, function.unwrap_or(1))?;
+            }
+        }
+        check_peak_format(&self.scans, self.format)?;
+        if let Some(function) = function {
+            drop(metadata.insert("function".to_string(), (function as u64).into()));
+        }
+        self.metadata = metadata;
+        Ok(())
+    }
+}
+
+/// A single peak from a Waters MassLynx scan
+#[derive(Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WatersRecord {
+    /// The time the scan was taken at (in minutes)
+    pub time: f64,
+    /// The mass-to-charge ratio of the peak
+    pub mz: f64,
+    /// The intensity of the peak
+    pub intensity: f64,
+}
+
+impl_record!(WatersRecord: time, mz, intensity);
+
+impl<'b: 's, 's> FromSlice<'b, 's> for WatersRecord {
+    type State = WatersState;
+
+    /// Each peak is 6 or 8 bytes, depending on the function's `PeakFormat`.
+    fn parse(
+        rb: &[u8],
+        _eof: bool,
+        consumed: &mut usize,
+        state: &mut Self::State,
+    ) -> Result<bool, EtError> {
+        // find the scan this peak is in without changing the state in case there isn't enough
+        // data to read it yet
+        let mut scan_ix = state.scan_ix;
+        let mut n_peaks_left = state.n_peaks_left;
+        let mut time = state.cur_time;
+        let mut skip = 0;
+        while n_peaks_left == 0 {
+            let scan = match state.scans.get(scan_ix) {
+                Some(scan) => scan,
+                None => return Ok(false),
+            };
+            if scan.offset < state.pos {
+                return Err(format!(
+                    "Waters scan at {} min overlaps the one before it",
+                    scan.time
+                )
+                .into());
+            }
+            skip = usize::try_from(scan.offset - state.pos)?;
+            scan_ix += 1;
+            n_peaks_left = scan.n_peaks;
+            time = scan.time;
+        }
+        let peak_len = state.format.len();
+        let peak = rb.get(skip..skip + peak_len).ok_or_else(|| {
+            EtError::from("Incomplete Waters peak").incomplete_needing(skip + peak_len)
+        })?;
+        let (mz, intensity) = state.format.decode(peak)?;
+
+        state.scan_ix = scan_ix;
+        state.n_peaks_left = n_peaks_left - 1;
+        state.cur_time = time;
+        state.cur_mz = mz;
+        state.cur_intensity = intensity;
+        state.pos += (skip + peak_len) as u64;
+        *consumed += skip + peak_len;
+        Ok(true)
+    }
+
+    fn get(&mut self, _rb: &'b [u8], state: &'s Self::State) -> Result<(), EtError> {
+        self.time = state.cur_time;
+        self.mz = state.cur_mz;
+        self.intensity = state.cur_intensity;
+        Ok(())
+    }
+}
+
+impl_reader!(
+    /// A reader for the data of one function in a Waters MassLynx `.raw` directory.
+    ///
+    /// The reader is given the function's `_FUNC*.DAT` file and its path (to find the scans
+    /// in the `.IDX` file, how the peaks are stored in `_FUNCTNS.INF`, and the run's metadata
+    /// in `_HEADER.TXT` next to it).
+    WatersReader,
+    WatersRecord,
+    WatersRecord,
+    WatersState,
+    String
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::readers::RecordReader;
+
+    #[test]
+    fn test_waters_reader() -> Result<(), EtError> {
+        let mut dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        dir.push("tests/data/waters_example.raw");
+        let filename = data_path(&dir)?;
+        assert!(filename.ends_with("_FUNC001.DAT"));
+        assert_eq!(data_path(&filename)?, filename);
+
+        let data: &[u8] = include_bytes!("../../tests/data/waters_example.raw/_FUNC001.DAT");
+        let mut reader = WatersReader::new(data, Some(filename.to_str().unwrap().to_string()))?;
+        assert_eq!(reader.headers(), ["time", "mz", "intensity"]);
+        assert_eq!(reader.size_hint(), (3, Some(3)));
+        let metadata = reader.metadata();
+        assert_eq!(metadata["acquired_name"], "waters_example".into());
+        assert_eq!(metadata["instrument"], "Xevo G2-XS QTof".into());
+        assert_eq!(metadata["function"], Value::Integer(1));
+        assert!(!metadata.contains_key("job_code"));
+
+        let WatersRecord {
+            time,
+            mz,
+            intensity,
+        } = reader.next()?.unwrap();
+        assert!((time - 0.5).abs() < 0.0001);
+        assert!((mz - 100.5).abs() < 0.0001);
+        assert_eq!(intensity, 1000.);
+        let WatersRecord { mz, intensity, .. } = reader.next()?.unwrap();
+        assert!((mz - 200.25).abs() < 0.0001);
+        assert_eq!(intensity, 65536.);
+        // the second scan is empty so the last peak is from the third
+        let WatersRecord { time, mz, .. } = reader.next()?.unwrap();
+        assert!((time - 0.7).abs() < 0.0001);
+        assert!((mz - 150.).abs() < 0.0001);
+        assert!(reader.next()?.is_none());
+
+        // the second function's peaks are floats
+        let filename = dir.join("_FUNC002.DAT");
+        let data: &[u8] = include_bytes!("../../tests/data/waters_example.raw/_FUNC002.DAT");
+        let mut reader = WatersReader::new(data, Some(filename.to_str().unwrap().to_string()))?;
+        assert_eq!(reader.metadata()["function"], Value::Integer(2));
+        let mut peaks = Vec::new();
+        while let Some(WatersRecord {
+            time,
+            mz,
+            intensity,
+        }) = reader.next()?
+        {
+            peaks.push(((time * 100.).round(), mz, intensity));
+        }
+        assert_eq!(
+            peaks,
+            [(55., 50.25, 10.), (55., 75.5, 2.5), (75., 60.125, 7.)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_waters_reader_errors() {
+        let data: &[u8] = include_bytes!("../../tests/data/waters_example.raw/_FUNC001.DAT");
+        // the index can't be found without the filename
+        assert!(WatersReader::new(data, None).is_err());
+        assert!(data_path(Path::new(env!("CARGO_MANIFEST_DIR"))).is_err());
+        assert!(read_index(&[0; 23]).is_err());
+        assert!(is_run(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("tests/data/waters_example.raw")
+                .as_path()
+        ));
+        assert!(!is_run(Path::new(env!("CARGO_MANIFEST_DIR"))));
+    }
+
+    #[test]
+    fn test_peak_formats() -> Result<(), EtError> {
+        let mut functions = vec![0; 2 * FUNCTION_ENTRY_LEN];
+        functions[FUNCTION_ENTRY_LEN + 1] = 0x04;
+        assert_eq!(read_peak_format(&functions, 1)?, PeakFormat::Packed);
+        assert_eq!(read_peak_format(&functions, 2)?, PeakFormat::Float);
+        assert!(read_peak_format(&functions, 3).is_err());
+        functions[1] = 0x08;
+        assert!(read_peak_format(&functions, 1).is_err());
+
+        // two 6 byte peaks in the first scan can't be read as 8 byte ones
+        let scans = read_index(include_bytes!(
+            "../../tests/data/waters_example.raw/_FUNC001.IDX"
+        ))?;
+        assert!(check_peak_format(&scans, PeakFormat::Packed).is_ok());
+        assert!(check_peak_format(&scans, PeakFormat::Float).is_err());
+        Ok(())
+    }
+}
@@ -1,45 +1,59 @@
-use core::marker::Copy;
-// use alloc::collections::BTreeMap;
-use alloc::borrow::ToOwned;
+use alloc::borrow::Cow;
 use alloc::format;
 use alloc::str::from_utf8;
-use alloc::string::String;
+use alloc::string::{String, ToString};
 use alloc::vec;
 use alloc::vec::Vec;
 
-use memchr::{memchr, memchr3_iter};
+use memchr::{memchr, memchr3_iter, memmem};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
-use crate::parsers::{extract, FromSlice};
+use crate::parsers::{extract_with, FromSlice};
 use crate::record::StateMetadata;
 use crate::EtError;
 use crate::{impl_reader, impl_record};
 
+/// The largest tag we'll try to read before giving up.
+const MAX_TAG_LEN: usize = 65_536;
+
 /// What kind of XML tag this is
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
 pub enum XmlTagType {
     /// An opening tag, e.g. <a>
+    #[default]
     Open,
     /// An closing tag, e.g. </a>
     Close,
     /// A self-closing tag, e.g. <br />
     SelfClose,
-}
-// TODO: maybe CDATA, DOCTYPE, comments too?
-
-impl Default for XmlTagType {
-    fn default() -> Self {
-        XmlTagType::Open
-    }
+    /// A processing instruction, comment, DOCTYPE, or CDATA section, e.g. <?xml ?>
+    Declaration,
 }
 
 /// Convenience struct for tokenizing tags out of XML streams
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct XmlTag<'r> {
-    tag_type: XmlTagType,
-    id: &'r str,
+    /// The type of the tag
+    pub tag_type: XmlTagType,
+    /// The name of the tag
+    pub id: &'r str,
+    /// Any key/value attributes inside the tag
+    pub attributes: Vec<(&'r str, Cow<'r, str>)>,
 }
 
-impl<'b: 's, 's> FromSlice<'b, 's> for XmlTag<'r> {
+impl<'r> XmlTag<'r> {
+    /// Return the value of the attribute with the name `key`, if present.
+    #[must_use]
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| v.as_ref())
+    }
+}
+
+impl<'b: 's, 's> FromSlice<'b, 's> for XmlTag<'b> {
     type State = ();
 
     fn parse(
@@ -48,62 +62,164 @@ impl<'b: 's, 's> FromSlice<'b, 's> for XmlTag<'r> {
         consumed: &mut usize,
         _state: &mut Self::State,
     ) -> Result<bool, EtError> {
-        let mut cur_quote = b' ';
-        let mut start = 0;
-        let end = 'read: loop {
-            // we're parsing a tag
-            for i in memchr3_iter(b'>', b'"', b'\'', &rb[start..]) {
+        if rb.is_empty() {
+            if eof {
+                return Ok(false);
+            }
+            return Err(EtError::from("No XML tag could be read").incomplete());
+        }
+        if rb[0] != b'<' {
+            return Err("XML tags must start with '<'".into());
+        }
+        // comments and CDATA can contain unquoted '>' so they need special handling
+        let special_end = if rb.starts_with(b"<!--") {
+            Some(&b"-->"[..])
+        } else if rb.starts_with(b"<![CDATA[") {
+            Some(&b"]]>"[..])
+        } else {
+            None
+        };
+        if let Some(end_pat) = special_end {
+            if let Some(e) = memmem::find(rb, end_pat) {
+                *consumed += e + end_pat.len();
+                return Ok(true);
+            }
+        } else {
+            let mut cur_quote = None;
+            for i in memchr3_iter(b'>', b'"', b'\'', rb) {
                 match (rb[i], cur_quote) {
-                    // if we're not in quotes and see a >, break
-                    (b'>', b' ') => break 'read i + 1,
+                    // if we're not in quotes and see a >, we're done
+                    (b'>', None) => {
+                        *consumed += i + 1;
+                        return Ok(true);
+                    }
                     // if we're not in quotes and see a quote, start "quoting"
-                    (b'\'', b' ') => cur_quote = b'\'',
-                    (b'"', b' ') => cur_quote = b'"',
-                    // if we're in quotes and see a quote, stop "quoting"
-                    (b'\'', b'\'') => cur_quote = b' ',
-                    (b'"', b'"') => cur_quote = b' ',
+                    (q, None) if q != b'>' => cur_quote = Some(q),
+                    // if we're in quotes and see the same quote, stop "quoting"
+                    (q, Some(c)) if q == c => cur_quote = None,
                     _ => {}
                 }
             }
-            if rb.len() > 1024 {
-                return Err(format!("Tags larger than {} not supported", 1024).into());
-            }
-            if eof {
-                return Err("Tag was never closed".into());
-            }
-            start = rb.len() - 1;
-        };
-        *consumed += end;
-        Ok(true)
+        }
+        if rb.len() > MAX_TAG_LEN {
+            return Err(format!("Tags larger than {} not supported", MAX_TAG_LEN).into());
+        }
+        if eof {
+            return Err("Tag was never closed".into());
+        }
+        Err(EtError::from("Tag was incomplete").incomplete())
     }
 
-    fn get(
-        &mut self,
-        buf: &'r [u8],
-        _state: &Self::State,
-    ) -> Result<(), EtError> {
-        let is_closing = buf.get(1) == Some(&b'/');
-        let is_self_closing = buf.last() == Some(&b'/');
+    fn get(&mut self, buf: &'b [u8], _state: &Self::State) -> Result<(), EtError> {
+        self.attributes.clear();
+        if buf.len() < 2 {
+            return Err("Tag was too short".into());
+        }
+        if buf[1] == b'?' || buf[1] == b'!' {
+            self.tag_type = XmlTagType::Declaration;
+            let data = &buf[1..buf.len() - 1];
+            let id_end = data
+                .iter()
+                .position(u8::is_ascii_whitespace)
+                .unwrap_or(data.len());
+            self.id = from_utf8(&data[..id_end])?;
+            return Ok(());
+        }
+        let is_closing = buf[1] == b'/';
+        let is_self_closing = buf.len() > 2 && buf[buf.len() - 2] == b'/';
         let (tag_type, data) = match (is_closing, is_self_closing) {
-            // TODO: we should be able to use EtError::new here
-            (true, true) => return Err(EtError::from("Tag can not start and end with '/'")),
+            (true, true) => return Err("Tag can not start and end with '/'".into()),
             (true, false) => (XmlTagType::Close, &buf[2..buf.len() - 1]),
             (false, true) => (XmlTagType::SelfClose, &buf[1..buf.len() - 2]),
             (false, false) => (XmlTagType::Open, &buf[1..buf.len() - 1]),
         };
-        let id_end = memchr(b' ', data).unwrap_or(data.len());
+        let id_end = data
+            .iter()
+            .position(u8::is_ascii_whitespace)
+            .unwrap_or(data.len());
         self.tag_type = tag_type;
         self.id = from_utf8(&data[..id_end])?;
-        // TODO: parse attributes
+
+        // now read out all the key="value" pairs
+        let mut pos = id_end;
+        loop {
+            while pos < data.len() && data[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            if pos >= data.len() {
+                break;
+            }
+            let key_end = if let Some(e) = memchr(b'=', &data[pos..]) {
+                pos + e
+            } else {
+                return Err(format!("Attribute in tag {} has no value", self.id).into());
+            };
+            let key = from_utf8(&data[pos..key_end])?.trim();
+            pos = key_end + 1;
+            while pos < data.len() && data[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            let quote = match data.get(pos) {
+                Some(q @ (b'"' | b'\'')) => *q,
+                _ => return Err(format!("Attribute {} is not quoted", key).into()),
+            };
+            let value_end = if let Some(e) = memchr(quote, &data[pos + 1..]) {
+                pos + 1 + e
+            } else {
+                return Err(format!("Attribute {} is not terminated", key).into());
+            };
+            let value = unescape(from_utf8(&data[pos + 1..value_end])?);
+            self.attributes.push((key, value));
+            pos = value_end + 1;
+        }
         Ok(())
     }
 }
 
+/// Find the next tag in `buffer` (after `consumed`), skipping any text before it.
+pub(crate) fn next_tag<'b>(
+    buffer: &'b [u8],
+    eof: bool,
+    consumed: &mut usize,
+) -> Result<Option<XmlTag<'b>>, EtError> {
+    if let Some(start) = memchr(b'<', &buffer[*consumed..]) {
+        *consumed += start;
+    } else if eof {
+        return Ok(None);
+    } else {
+        return Err(EtError::from("Could not find next XML tag").incomplete());
+    }
+    extract_with::<XmlTag>(buffer, consumed, &()).map(Some)
+}
+
+/// Given that `consumed` is just past the opening tag for `name`, advance `consumed` to just
+/// past its matching closing tag.
+pub(crate) fn skip_element(
+    buffer: &[u8],
+    eof: bool,
+    consumed: &mut usize,
+    name: &str,
+) -> Result<(), EtError> {
+    let mut depth = 1;
+    while depth > 0 {
+        let tag = match next_tag(buffer, eof, consumed)? {
+            Some(tag) => tag,
+            None => return Err(format!("Closing tag for {} not present?", name).into()),
+        };
+        match tag.tag_type {
+            XmlTagType::Open if tag.id == name => depth += 1,
+            XmlTagType::Close if tag.id == name => depth -= 1,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
 /// Convenience struct for tokenizing text out of XML streams
-#[derive(Clone, Copy, Debug, Default)]
-pub struct XmlText<'r>(&'r str);
+#[derive(Clone, Debug, Default)]
+pub struct XmlText<'r>(pub Cow<'r, str>);
 
-impl<'b: 's, 's> FromSlice<'b, 's> for XmlText<'r> {
+impl<'b: 's, 's> FromSlice<'b, 's> for XmlText<'b> {
     type State = ();
 
     fn parse(
@@ -112,38 +228,72 @@ impl<'b: 's, 's> FromSlice<'b, 's> for XmlText<'r> {
         consumed: &mut usize,
         _state: &mut Self::State,
     ) -> Result<bool, EtError> {
+        if rb.is_empty() {
+            if eof {
+                return Ok(false);
+            }
+            return Err(EtError::from("No XML text could be read").incomplete());
+        }
         // we're parsing a text element
         if let Some(e) = memchr(b'<', rb) {
             *consumed += e;
             return Ok(true);
         }
-        if rb.len() > 65536 {
-            return Err(
-                format!("XML text larger than {} not supported", 65536).into()
-            );
-        }
         if eof {
-            // TODO: add test for this case
             *consumed += rb.len();
             return Ok(true);
         }
-        Ok(false)
+        Err(EtError::from("XML text was incomplete").incomplete())
     }
 
-    fn get(
-        &mut self,
-        buf: &'r [u8],
-        _state: &Self::State,
-    ) -> Result<(), EtError> {
-        self.0 = from_utf8(buf)?;
+    fn get(&mut self, buf: &'b [u8], _state: &Self::State) -> Result<(), EtError> {
+        self.0 = unescape(from_utf8(buf)?);
         Ok(())
     }
 }
 
+/// Replace the predefined XML entities (and numeric character references) in `text`.
+pub(crate) fn unescape(text: &str) -> Cow<'_, str> {
+    if memchr(b'&', text.as_bytes()).is_none() {
+        return Cow::Borrowed(text);
+    }
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = if let Some(e) = rest.find(';') {
+            e
+        } else {
+            break;
+        };
+        let chr = match &rest[1..end] {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            e if e.starts_with("#x") => u32::from_str_radix(&e[2..], 16)
+                .ok()
+                .and_then(char::from_u32),
+            e if e.starts_with('#') => e[1..].parse().ok().and_then(char::from_u32),
+            _ => None,
+        };
+        if let Some(c) = chr {
+            unescaped.push(c);
+        } else {
+            // not an entity we know about so just pass it through
+            unescaped.push_str(&rest[..=end]);
+        }
+        rest = &rest[end + 1..];
+    }
+    unescaped.push_str(rest);
+    Cow::Owned(unescaped)
+}
+
 /// Current state of the XML parser
 #[derive(Clone, Debug, Default)]
 pub struct XmlState {
-    // token_counts: Vec<BTreeMap<String, usize>>,
     stack: Vec<String>,
     is_text: bool,
 }
@@ -160,74 +310,72 @@ impl<'b: 's, 's> FromSlice<'b, 's> for XmlState {
 
 /// A single record from an XML stream
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct XmlRecord<'r> {
-    tags: Vec<String>,
-    text: &'r str,
-    // TODO
-    // attributes: BTreeMap<String>
+    /// The stack of tags the current token is nested in
+    pub tags: Vec<String>,
+    /// The text inside the current tag (if any)
+    #[cfg_attr(feature = "serde", serde(borrow))]
+    pub text: Cow<'r, str>,
 }
 
-impl<'b: 's, 's> FromSlice<'b, 's> for XmlRecord<'r> {
-    type State = &'r mut XmlState;
+impl<'b: 's, 's> FromSlice<'b, 's> for XmlRecord<'b> {
+    type State = XmlState;
 
-    fn parse(rb: &[u8], eof: bool, consumed: &mut usize, state: &mut Self::State) -> Result<bool, EtError> {
+    fn parse(
+        rb: &[u8],
+        eof: bool,
+        consumed: &mut usize,
+        state: &mut Self::State,
+    ) -> Result<bool, EtError> {
         if rb.is_empty() {
-            if !state.stack.is_empty() {
-                return Err(format!("Closing tag for {} not present?", state.stack.pop().unwrap()).into());
-            } else {
-                return Ok(false);
+            if !eof {
+                return Err(EtError::from("No XML could be read").incomplete());
+            } else if let Some(tag) = state.stack.last() {
+                return Err(format!("Closing tag for {} not present?", tag).into());
             }
+            return Ok(false);
         }
         let con = &mut 0;
         if rb[0] == b'<' {
-            // it's a tag
-            let tag = extract::<XmlTag>(rb, con, ())?;
+            let tag = extract_with::<XmlTag>(rb, con, &())?;
             match tag.tag_type {
-                XmlTagType::Open => {
-                    state.stack.push(tag.id.to_owned());
-                }
+                XmlTagType::Open => state.stack.push(tag.id.to_string()),
                 XmlTagType::Close => {
                     if let Some(open_tag) = state.stack.pop() {
                         if open_tag != tag.id {
-                            return Err(
-                                format!("Closing tag {} found, but {} was open.", tag.id, open_tag).into()
-                            );
+                            return Err(format!(
+                                "Closing tag {} found, but {} was open.",
+                                tag.id, open_tag
+                            )
+                            .into());
                         }
                     } else {
-                        return Err(
-                            format!(
-                                "Closing tag {} found, but no tags opened before it.",
-                                tag.id
-                            ).into()
-                        );
+                        return Err(format!(
+                            "Closing tag {} found, but no tags opened before it.",
+                            tag.id
+                        )
+                        .into());
                     }
                 }
-                // TODO: we need to return the tag stack with this tag on it
-                XmlTagType::SelfClose => {}
+                XmlTagType::SelfClose | XmlTagType::Declaration => {}
             }
             state.is_text = false;
         } else {
-            // it's text; parse the length out
-            if XmlText::parse(rb, eof, con, &mut ())? {
-                state.is_text = true;
-            } else {
+            if !XmlText::parse(rb, eof, con, &mut ())? {
                 return Ok(false);
             }
+            state.is_text = true;
         }
         *consumed += *con;
-
         Ok(true)
     }
 
-    fn get(
-        &mut self,
-        rb: &'r [u8],
-        state: &Self::State,
-    ) -> Result<(), EtError> {
+    fn get(&mut self, rb: &'b [u8], state: &'s Self::State) -> Result<(), EtError> {
         self.text = if state.is_text {
-            from_utf8(rb)?
+            unescape(from_utf8(rb)?)
         } else {
-            ""
+            Cow::Borrowed("")
         };
         self.tags = state.stack.clone();
         Ok(())
@@ -245,16 +393,33 @@ mod tests {
     #[test]
     fn test_xml_reader() -> Result<(), EtError> {
         let data: &[u8] = b"<a>test</a>";
-        let mut reader = XmlReader::new(data, ())?;
+        let mut reader = XmlReader::new(data, None)?;
 
-        // TODO: don't emit on tag close? also emit the current tag?
         let rec = reader.next()?.unwrap();
         assert_eq!(rec.tags, &["a"]);
         let rec = reader.next()?.unwrap();
         assert_eq!(rec.tags, &["a"]);
+        assert_eq!(rec.text, "test");
         let rec = reader.next()?.unwrap();
         assert!(rec.tags.is_empty());
         assert!(reader.next()?.is_none());
         Ok(())
     }
+
+    #[test]
+    fn test_xml_tag_attributes() -> Result<(), EtError> {
+        let tag: XmlTag = extract_with(b"<node id=\"n0\" label='a &amp; b' />", &mut 0, &())?;
+        assert_eq!(tag.tag_type, XmlTagType::SelfClose);
+        assert_eq!(tag.id, "node");
+        assert_eq!(tag.attribute("id"), Some("n0"));
+        assert_eq!(tag.attribute("label"), Some("a & b"));
+        assert_eq!(tag.attribute("missing"), None);
+
+        let tag: XmlTag = extract_with(b"<!-- a <comment> -->", &mut 0, &())?;
+        assert_eq!(tag.tag_type, XmlTagType::Declaration);
+
+        assert!(XmlTag::parse(b"<a b=\"c>", false, &mut 0, &mut ()).is_err());
+        assert!(extract_with::<XmlTag>(b"<a b=c>", &mut 0, &()).is_err());
+        Ok(())
+    }
 }
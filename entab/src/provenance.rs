@@ -0,0 +1,159 @@
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::build_info::build_info;
+use crate::capabilities::Capabilities;
+use crate::error::EtError;
+use crate::readers::RecordReader;
+use crate::record::Value;
+use crate::summary::SummaryFields;
+
+/// The metadata key that holds the list of steps that produced a reader's records
+pub const PROVENANCE_KEY: &str = "provenance";
+
+/// A step that read records out of a file with `parser` (and the params it was given).
+///
+/// This is a record with `step` (always `parse`), `parser`, `version` (of the parser's output,
+/// see `build_info`), and `params` fields.
+#[must_use]
+pub fn parse_step(parser: &str, params: &BTreeMap<String, Value<'_>>) -> Value<'static> {
+    let mut step = BTreeMap::new();
+    drop(step.insert("step".to_string(), "parse".into()));
+    drop(step.insert("parser".to_string(), parser.to_string().into()));
+    if let Some(version) = build_info().parser_version(parser) {
+        drop(step.insert("version".to_string(), Value::Integer(version.into())));
+    }
+    let params = params
+        .iter()
+        .map(|(key, value)| (key.clone(), value.clone().into_owned()))
+        .collect();
+    drop(step.insert("params".to_string(), Value::Record(params)));
+    Value::Record(step)
+}
+
+/// A step that transformed the records of another reader (e.g. `aggregate`) with `params`.
+///
+/// This is a record with `step` and `params` fields.
+#[must_use]
+pub fn transform_step(name: &str, params: BTreeMap<String, Value<'static>>) -> Value<'static> {
+    let mut step = BTreeMap::new();
+    drop(step.insert("step".to_string(), name.to_string().into()));
+    drop(step.insert("params".to_string(), Value::Record(params)));
+    Value::Record(step)
+}
+
+/// Add `step` to the end of the provenance list in `metadata` (creating the list if there isn't
+/// one yet); wrapping readers call this on their inner reader's metadata so every step that
+/// produced the records is kept in order.
+pub fn add_step<'a>(metadata: &mut BTreeMap<String, Value<'a>>, step: Value<'a>) {
+    match metadata.get_mut(PROVENANCE_KEY) {
+        Some(Value::List(steps)) => steps.push(step),
+        _ => drop(metadata.insert(PROVENANCE_KEY.to_string(), Value::List(vec![step]))),
+    }
+}
+
+/// Wraps a `RecordReader` to add a step to the provenance in its metadata without changing its
+/// records (e.g. to record the parser a file was read with before its records are transformed).
+#[derive(Debug)]
+pub struct ProvenanceReader<'r> {
+    reader: Box<dyn RecordReader + 'r>,
+    step: Value<'static>,
+}
+
+impl<'r> ProvenanceReader<'r> {
+    /// Create a new `ProvenanceReader` that adds `step` to the provenance of `reader`
+    #[must_use]
+    pub fn new(reader: Box<dyn RecordReader + 'r>, step: Value<'static>) -> Self {
+        ProvenanceReader { reader, step }
+    }
+}
+
+impl<'r> RecordReader for ProvenanceReader<'r> {
+    fn next_record(&mut self) -> Result<Option<Vec<Value<'_>>>, EtError> {
+        self.reader.next_record()
+    }
+
+    fn skip_records(&mut self, n: usize) -> Result<usize, EtError> {
+        self.reader.skip_records(n)
+    }
+
+    fn bytes_read(&self) -> Option<u64> {
+        self.reader.bytes_read()
+    }
+
+    fn headers(&self) -> Vec<String> {
+        self.reader.headers()
+    }
+
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        let mut metadata = self.reader.metadata();
+        add_step(&mut metadata, self.step.clone());
+        metadata
+    }
+
+    fn column_metadata(&self) -> BTreeMap<String, BTreeMap<String, Value<'_>>> {
+        self.reader.column_metadata()
+    }
+
+    fn summary_fields(&self) -> SummaryFields {
+        self.reader.summary_fields()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.reader.size_hint()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.reader.capabilities()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::readers::get_reader;
+
+    #[test]
+    fn test_provenance() -> Result<(), EtError> {
+        let data: &[u8] = b"time\tintensity\n0.1\t2\n0.5\t3\n1.5\t1\n";
+        // readers that haven't been transformed don't have any provenance
+        let (reader, _) = get_reader(data, Some("tsv"), None)?;
+        assert!(!reader.metadata().contains_key(PROVENANCE_KEY));
+
+        let mut params = BTreeMap::new();
+        drop(params.insert("aggregate".to_string(), "max".into()));
+        drop(params.insert("bin".to_string(), "1min".into()));
+        drop(params.insert("hash".to_string(), Value::Boolean(true)));
+        drop(params.insert("delimiter".to_string(), "\t".into()));
+        let (reader, _) = get_reader(data, Some("csv"), Some(params))?;
+        let steps = match reader.metadata().remove(PROVENANCE_KEY) {
+            Some(Value::List(steps)) => steps,
+            x => panic!("Provenance should be a list, not {:?}", x),
+        };
+        let names: Vec<Value> = steps
+            .iter()
+            .map(|step| match step {
+                Value::Record(step) => step["step"].clone(),
+                x => panic!("Steps should be records, not {:?}", x),
+            })
+            .collect();
+        assert_eq!(names, ["parse".into(), "aggregate".into(), "hash".into()]);
+        match &steps[0] {
+            Value::Record(step) => {
+                assert_eq!(step["parser"], "csv".into());
+                assert_eq!(step["version"], Value::Integer(2));
+                match &step["params"] {
+                    Value::Record(params) => {
+                        assert_eq!(params.keys().collect::<Vec<_>>(), ["delimiter"]);
+                    }
+                    x => panic!("Params should be a record, not {:?}", x),
+                }
+            }
+            x => panic!("Steps should be records, not {:?}", x),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,265 @@
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::rc::Rc;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use std::io::{self, Read};
+
+use crate::readers::{get_reader, RecordReader};
+use crate::record::Value;
+use crate::EtError;
+
+/// The bytes pushed into a `PushParser` that its reader hasn't read yet
+#[derive(Debug, Default)]
+struct PushQueue {
+    data: VecDeque<u8>,
+    /// Has the host said there's no more data coming?
+    closed: bool,
+}
+
+/// A `Read` over a `PushQueue` that returns a `WouldBlock` error (instead of ending) when it's
+/// out of data but more could still be pushed; the `ReadBuffer` keeps everything it's buffered
+/// when a refill fails so parsing can pick up where it left off on the next push.
+#[derive(Clone, Debug, Default)]
+struct PushSource(Rc<RefCell<PushQueue>>);
+
+impl Read for PushSource {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut queue = self.0.borrow_mut();
+        if queue.data.is_empty() && !queue.closed {
+            return Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                "Waiting for more data to be pushed",
+            ));
+        }
+        queue.data.read(buf)
+    }
+}
+
+/// Parses data that's pushed in as it arrives instead of pulling it from a `Read`, for
+/// embedding in e.g. acquisition software that already owns its I/O loop.
+///
+/// Each `push` returns all the records that could be completed with the data so far; the rest
+/// of the data is kept until the next `push` (or `finish`, once there's no more data) fills out
+/// the record. Until the header's been read, it's only retried once the data pushed has doubled
+/// so records may come back a few pushes later than they could have. Parsers that look ahead at
+/// the data while reading the header (e.g. to infer the types of CSV columns) only see what was
+/// pushed by then, and parsers that need the whole file before they can return anything (e.g.
+/// for files with an index at the end) won't return records until `finish`.
+///
+/// ```
+/// use entab::push::PushParser;
+///
+/// let mut parser = PushParser::new(Some("fasta"), None);
+/// assert!(parser.push(b">seq1\nACG")?.is_empty());
+/// let records = parser.push(b"T\n>seq2\nTT")?;
+/// assert_eq!(records.len(), 1);
+/// assert_eq!(records[0][1], "ACGT".into());
+/// assert_eq!(parser.finish()?.len(), 1);
+/// # use entab::EtError;
+/// # Ok::<(), EtError>(())
+/// ```
+#[derive(Debug)]
+pub struct PushParser {
+    parser: Option<String>,
+    params: BTreeMap<String, Value<'static>>,
+    /// Everything pushed before there was enough data to read the header (so detecting the
+    /// file type and reading the header can be retried from the start)
+    pending: Vec<u8>,
+    /// How much data needs to be pending before reading the header is retried
+    retry_at: usize,
+    source: PushSource,
+    reader: Option<(Box<dyn RecordReader>, String)>,
+    finished: bool,
+}
+
+impl PushParser {
+    /// Create a new `PushParser`; as in `get_reader`, the parser is detected from the data if
+    /// one isn't given and the params set parser options.
+    #[must_use]
+    pub fn new(parser: Option<&str>, params: Option<BTreeMap<String, Value<'_>>>) -> Self {
+        let params = params
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(key, value)| (key, value.into_owned()))
+            .collect();
+        PushParser {
+            parser: parser.map(String::from),
+            params,
+            pending: Vec::new(),
+            retry_at: 0,
+            source: PushSource::default(),
+            reader: None,
+            finished: false,
+        }
+    }
+
+    /// Add more data and return any records it completes.
+    ///
+    /// # Errors
+    /// If the data can't be parsed or `finish` has already been called, an error is returned.
+    pub fn push(&mut self, data: &[u8]) -> Result<Vec<Vec<Value<'static>>>, EtError> {
+        if self.finished {
+            return Err("Can't push more data into a parser that's been finished".into());
+        }
+        if self.reader.is_none() {
+            self.pending.extend_from_slice(data);
+        } else {
+            self.source.0.borrow_mut().data.extend(data);
+        }
+        self.read_available()
+    }
+
+    /// Mark the end of the data and return the records that were waiting on it.
+    ///
+    /// # Errors
+    /// If the data ends partway through a record (or the header), an error is returned.
+    pub fn finish(&mut self) -> Result<Vec<Vec<Value<'static>>>, EtError> {
+        if self.finished {
+            return Ok(Vec::new());
+        }
+        self.finished = true;
+        self.source.0.borrow_mut().closed = true;
+        self.read_available()
+    }
+
+    /// The name of the parser being used (once enough data's been pushed to detect it)
+    #[must_use]
+    pub fn parser_name(&self) -> Option<&str> {
+        self.reader.as_ref().map(|(_, name)| name.as_str())
+    }
+
+    /// The headers of the records (empty until enough data's been pushed to read them)
+    #[must_use]
+    pub fn headers(&self) -> Vec<String> {
+        self.reader
+            .as_ref()
+            .map_or_else(Vec::new, |(reader, _)| reader.headers())
+    }
+
+    /// The metadata of the file (empty until enough data's been pushed to read the header)
+    #[must_use]
+    pub fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        self.reader
+            .as_ref()
+            .map_or_else(BTreeMap::new, |(reader, _)| reader.metadata())
+    }
+
+    /// Read as many records as the data pushed so far holds
+    fn read_available(&mut self) -> Result<Vec<Vec<Value<'static>>>, EtError> {
+        if self.reader.is_none() {
+            // each try reads everything pushed so far again, so wait until there's twice as
+            // much data as last time (or all of it) before retrying to keep the total linear
+            if !self.finished && self.pending.len() < self.retry_at {
+                return Ok(Vec::new());
+            }
+            if !self.start_reader()? {
+                self.retry_at = 2 * self.pending.len();
+                return Ok(Vec::new());
+            }
+        }
+        let mut records = Vec::new();
+        if let Some((reader, _)) = &mut self.reader {
+            loop {
+                match reader.next_record() {
+                    Ok(Some(record)) => {
+                        records.push(record.into_iter().map(Value::into_owned).collect());
+                    }
+                    Ok(None) => break,
+                    Err(e) if e.is_would_block() => break,
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        Ok(records)
+    }
+
+    /// Try to create the reader out of everything pushed so far; returns `false` if more data
+    /// is needed to detect the file type or read the header.
+    fn start_reader(&mut self) -> Result<bool, EtError> {
+        let source = PushSource::default();
+        {
+            let mut queue = source.0.borrow_mut();
+            queue.data.extend(&self.pending);
+            queue.closed = self.finished;
+        }
+        let data: Box<dyn Read> = Box::new(source.clone());
+        match get_reader(data, self.parser.as_deref(), Some(self.params.clone())) {
+            Ok((reader, parser_name)) => {
+                self.reader = Some((reader, parser_name.to_string()));
+                self.source = source;
+                self.pending = Vec::new();
+                Ok(true)
+            }
+            Err(e) if e.is_would_block() => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_byte_by_byte() -> Result<(), EtError> {
+        let data = b"@id1\nACGT\n+\n!!!!\n@id2\nTTTT\n+\nAAAA\n";
+        let mut parser = PushParser::new(None, None);
+        let mut records = Vec::new();
+        for byte in data.chunks(1) {
+            records.extend(parser.push(byte)?);
+        }
+        assert_eq!(parser.parser_name(), Some("fastq"));
+        assert_eq!(parser.headers(), ["id", "sequence", "quality"]);
+        // the quality line is as long as the sequence so the last record is already complete
+        assert_eq!(records.len(), 2);
+        assert!(parser.finish()?.is_empty());
+        assert_eq!(records[1][0], "id2".into());
+        assert_eq!(records[1][2], "AAAA".into());
+        assert!(parser.push(b"@id3\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_push_tsv() -> Result<(), EtError> {
+        let mut params = BTreeMap::new();
+        let _ = params.insert("comment_char".to_string(), "#".into());
+        let mut parser = PushParser::new(Some("tsv"), Some(params));
+        assert!(parser.push(b"# run 1\nti")?.is_empty());
+        assert!(parser.headers().is_empty());
+        // the types are inferred from the lines that came in with the header
+        let records = parser.push(b"me\tsignal\n0.5\t12\n1.0\t")?;
+        assert_eq!(parser.headers(), ["time", "signal"]);
+        assert_eq!(records, [[0.5.into(), 12.into()]]);
+        let records = parser.push(b"14\n1.5")?;
+        assert_eq!(records, [[1.0.into(), 14.into()]]);
+        let records = parser.push(b"\t9\n")?;
+        assert_eq!(records, [[1.5.into(), 9.into()]]);
+        assert!(parser.finish()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_push_slow_header() -> Result<(), EtError> {
+        let mut params = BTreeMap::new();
+        let _ = params.insert("comment_char".to_string(), "#".into());
+        let mut parser = PushParser::new(Some("tsv"), Some(params));
+        let mut data = b"# a long comment before the header\n".repeat(20);
+        data.extend_from_slice(b"x\ty\n1\t2\n3\t4\n");
+        let mut records = Vec::new();
+        for byte in data.chunks(1) {
+            records.extend(parser.push(byte)?);
+        }
+        records.extend(parser.finish()?);
+        assert_eq!(parser.headers(), ["x", "y"]);
+        assert_eq!(records, [[1.into(), 2.into()], [3.into(), 4.into()]]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_push_truncated() {
+        let mut parser = PushParser::new(Some("fastq"), None);
+        assert!(parser.push(b"@id1\nACGT\n+\n!!").unwrap().is_empty());
+        assert!(parser.finish().is_err());
+    }
+}
@@ -2,15 +2,38 @@ use alloc::boxed::Box;
 use alloc::collections::BTreeMap;
 use alloc::format;
 use alloc::string::String;
+use alloc::vec;
 use alloc::vec::Vec;
-use core::convert::TryInto;
+use core::convert::{TryFrom, TryInto};
+use core::str::FromStr;
+#[cfg(feature = "std")]
+use std::io::Read;
 
+use chrono::NaiveDate;
+
+use crate::aggregate::{parse_bin_width, AggregateReader, Aggregation};
+#[cfg(feature = "std")]
+use crate::archive::TarReader;
+use crate::bom::{is_text_parser, strip_bom, Bom};
 use crate::buffer::ReadBuffer;
+use crate::build_info::is_parser;
+#[cfg(feature = "std")]
+use crate::cancel::{CancelToken, CancellableReader};
+use crate::capabilities::Capabilities;
+use crate::columns::{push_record, Column};
 use crate::compression::decompress;
-use crate::error::EtError;
+#[cfg(feature = "std")]
+use crate::compression::decompress_threaded;
+use crate::error::{EtError, EtErrorKind};
+use crate::hash::HashReader;
 use crate::parsers;
+use crate::parsers::datetime::DateParser;
+use crate::parsers::text::TextDecoding;
 use crate::parsers::FromSlice;
-use crate::record::Value;
+use crate::provenance::{parse_step, ProvenanceReader};
+use crate::record::{HeaderMap, Value};
+use crate::report::ReportReader;
+use crate::summary::SummaryFields;
 
 /// Turn `rb` into a Reader of type `parser`.
 ///
@@ -27,46 +50,251 @@ where
     B: TryInto<ReadBuffer<'r>>,
     EtError: From<<B as TryInto<ReadBuffer<'r>>>::Error>,
 {
-    let (mut rb, _): (ReadBuffer<'r>, _) = decompress(data)?;
-    let parser_name = rb.sniff_filetype()?.to_parser_name(parser)?;
+    let (rb, _): (ReadBuffer<'r>, _) = decompress(data)?;
+    let (rb, parser_name) = sniff_parser(rb, parser)?;
+    _get_reader(rb, parser_name, params.unwrap_or_default())
+}
+
+/// Like `get_reader`, but compressed data is decompressed on a background thread so inflating
+/// it overlaps with parsing (which is why `data` has to be `Send` and `'static`).
+///
+/// # Errors
+/// If an error happens during decompression or parser detection, an `EtError` is returned.
+#[cfg(feature = "std")]
+pub fn get_reader_threaded<'n, 'p, R>(
+    data: R,
+    parser: Option<&'n str>,
+    params: Option<BTreeMap<String, Value<'p>>>,
+) -> Result<(Box<dyn RecordReader>, &'n str), EtError>
+where
+    R: Read + Send + 'static,
+{
+    let (rb, _) = decompress_threaded(data)?;
+    let (rb, parser_name) = sniff_parser(rb, parser)?;
     _get_reader(rb, parser_name, params.unwrap_or_default())
 }
 
+/// Like `get_reader_threaded`, but reading stops with a `Cancelled` error once `token` is
+/// cancelled; the token's checked every few records and while reading the data for a record (so
+/// even a huge record or a stalled stream can be interrupted).
+///
+/// # Errors
+/// If an error happens during decompression or parser detection (or the token's already been
+/// cancelled), an `EtError` is returned.
+#[cfg(feature = "std")]
+pub fn get_reader_cancellable<'n, 'p, R>(
+    data: R,
+    parser: Option<&'n str>,
+    params: Option<BTreeMap<String, Value<'p>>>,
+    token: CancelToken,
+) -> Result<(Box<dyn RecordReader>, &'n str), EtError>
+where
+    R: Read + Send + 'static,
+{
+    let (rb, _) = decompress_threaded(data)?;
+    let (rb, parser_name) = sniff_parser(rb.with_cancel_token(token.clone()), parser)?;
+    let (reader, parser_name) = _get_reader(rb, parser_name, params.unwrap_or_default())?;
+    Ok((Box::new(CancellableReader::new(reader, token)), parser_name))
+}
+
+/// Work out which parser reads `rb` (`parser` if one's given, otherwise from what the data looks
+/// like) and strip any byte order mark off the front of the data if it's a text parser (see
+/// `strip_bom`).
+///
+/// # Errors
+/// If no parser can be found for the data, an error is returned.
+pub(crate) fn sniff_parser<'n, 'r>(
+    mut rb: ReadBuffer<'r>,
+    parser: Option<&'n str>,
+) -> Result<(ReadBuffer<'r>, &'n str), EtError> {
+    let sniffed = rb.sniff_filetype()?.to_parser_name(parser);
+    match sniffed {
+        Ok(parser_name) if !is_text_parser(parser_name) => return Ok((rb, parser_name)),
+        // the mark can hide what kind of text it is (e.g. in front of a FASTA file), so without a
+        // parser to go by the data has to be looked at again once it's stripped off
+        _ if parser.is_none() && Bom::detect(rb.as_ref()).is_some() => {}
+        Ok(parser_name) => return Ok((strip_bom(rb)?, parser_name)),
+        Err(e) => return Err(e),
+    }
+    let mut rb = strip_bom(rb)?;
+    let parser_name = rb.sniff_filetype()?.to_parser_name(None)?;
+    Ok((rb, parser_name))
+}
+
 /// Internal function to handle `get_reader` not inferring that the Reader constructors need to be
 /// created using `ReadBuffer` and not `B`.
-fn _get_reader<'n, 'p, 'r>(
-    rb: ReadBuffer<'r>,
+pub(crate) fn _get_reader<'n, 'p, 'r>(
+    mut rb: ReadBuffer<'r>,
     parser_name: &'n str,
     mut params: BTreeMap<String, Value<'p>>,
 ) -> Result<(Box<dyn RecordReader + 'r>, &'n str), EtError> {
-    let reader: Box<dyn RecordReader + 'r> = match parser_name {
-        "bam" => Box::new(parsers::sam::BamReader::new(rb, None)?),
-        "chemstation_array" => Box::new(parsers::agilent::chemstation_new::ChemstationArrayReader::new(
-            rb, None,
+    // whatever the parser takes out of the params is what it was configured with
+    let mut parser_params = params.clone();
+    let empty = rb.is_exhausted()?;
+    let reader = match parser_reader(rb, parser_name, &mut params) {
+        Ok(reader) => reader,
+        // most binary formats can't read their header out of an empty file, but that's still
+        // just a file without any records in it (other errors, e.g. from bad parameters, aren't
+        // about the data so they're still returned)
+        Err(e)
+            if empty
+                && is_parser(parser_name)
+                && matches!(e.kind, EtErrorKind::Truncated { available: 0, .. }) =>
+        {
+            Box::new(EmptyReader::new(Vec::new()))
+        }
+        Err(e) => return Err(e),
+    };
+    parser_params.retain(|key, _| key != "filename" && !params.contains_key(key));
+    // the parser is only recorded in the provenance if the records are transformed afterwards
+    let mut parse = Some(parse_step(parser_name, &parser_params));
+    let reader = match aggregate_params(&mut params)? {
+        Some((aggregation, bin_width)) => Box::new(AggregateReader::new(
+            with_parse_step(reader, &mut parse),
+            aggregation,
+            bin_width,
         )?),
+        None => reader,
+    };
+    let reader = match params.remove("hash") {
+        Some(Value::Boolean(true)) => {
+            Box::new(HashReader::new(with_parse_step(reader, &mut parse))?)
+        }
+        Some(Value::Boolean(false)) | None => reader,
+        Some(_) => return Err("The hash param must be true or false".into()),
+    };
+    let reader = match params.remove("report") {
+        Some(Value::Boolean(true)) => Box::new(ReportReader::new(reader)),
+        Some(Value::Boolean(false)) | None => reader,
+        Some(_) => return Err("The report param must be true or false".into()),
+    };
+    drop(params.remove("filename"));
+    if !params.is_empty() {
+        let keys: Vec<&str> = params.keys().map(AsRef::as_ref).collect();
+        return Err(format!("Unused params remain: {}", keys.join(",")).into());
+    }
+    Ok((reader, parser_name))
+}
+
+/// Create the reader for `parser_name`, taking its params out of `params`
+fn parser_reader<'r>(
+    rb: ReadBuffer<'r>,
+    parser_name: &str,
+    params: &mut BTreeMap<String, Value<'_>>,
+) -> Result<Box<dyn RecordReader + 'r>, EtError> {
+    Ok(match parser_name {
+        "abif" => Box::new(parsers::abif::AbifReader::new(
+            rb,
+            Some(text_decoding(params)?),
+        )?),
+        "bam" => match stream_param(params)? {
+            parsers::sam::BamStream::Alignments => {
+                Box::new(parsers::sam::BamReader::new(rb, Some(sam_params(params)?))?)
+            }
+            parsers::sam::BamStream::References => {
+                Box::new(parsers::sam::BamReferenceReader::new(rb, None)?)
+            }
+        },
+        "cfb" => Box::new(parsers::cfb::CfbReader::new(rb, None)?),
+        "depth" => Box::new(crate::depth::DepthReader::new(
+            rb,
+            count_param(params, "skip_flags")?
+                .map(u16::try_from)
+                .transpose()
+                .map_err(|_| "skip_flags must be a 16-bit integer")?,
+        )?),
+        "chemstation_array" => Box::new(
+            parsers::agilent::chemstation_new::ChemstationArrayReader::new(
+                rb,
+                Some(chemstation_params(params)?),
+            )?,
+        ),
         "chemstation_dad" => Box::new(parsers::agilent::chemstation::ChemstationDadReader::new(
-            rb, None,
+            rb,
+            Some(chemstation_params(params)?),
         )?),
         "chemstation_fid" => Box::new(parsers::agilent::chemstation::ChemstationFidReader::new(
-            rb, None,
-        )?),
-        "chemstation_ms" => Box::new(parsers::agilent::chemstation::ChemstationMsReader::new(
-            rb, None,
+            rb,
+            Some(chemstation_params(params)?),
         )?),
+        "chemstation_ms" => {
+            let params = chemstation_params(params)?;
+            if params.integer_values {
+                Box::new(
+                    parsers::agilent::chemstation::ChemstationMsIntegerReader::new(
+                        rb,
+                        Some(params),
+                    )?,
+                )
+            } else {
+                Box::new(parsers::agilent::chemstation::ChemstationMsReader::new(
+                    rb,
+                    Some(params),
+                )?)
+            }
+        }
         "chemstation_mwd" => Box::new(parsers::agilent::chemstation::ChemstationMwdReader::new(
-            rb, None,
+            rb,
+            Some(chemstation_params(params)?),
         )?),
         "chemstation_uv" => Box::new(parsers::agilent::chemstation_new::ChemstationUvReader::new(
-            rb, None,
+            rb,
+            Some(chemstation_params(params)?),
         )?),
         "csv" => Box::new(parsers::tsv::TsvReader::new(
             rb,
-            Some(parsers::tsv::TsvParams::default().delim(b',')),
+            Some(tsv_params(
+                params,
+                parsers::tsv::TsvParams::default().delim(b','),
+            )?),
         )?),
-        "fasta" => Box::new(parsers::fasta::FastaReader::new(rb, None)?),
-        "fastq" => Box::new(parsers::fastq::FastqReader::new(rb, None)?),
-        "flow" => Box::new(parsers::flow::FcsReader::new(rb, None)?),
+        // the delimiter (and whether there's a header) is detected from the data
+        "delimited" => Box::new(parsers::tsv::TsvReader::new(
+            rb,
+            Some(tsv_params(
+                params,
+                parsers::tsv::TsvParams::default().sniff_header(true),
+            )?),
+        )?),
+        "elementar_irms" => Box::new(parsers::irms::IrmsReader::new(
+            rb,
+            Some(parsers::irms::IrmsVendor::Elementar),
+        )?),
+        "fasta" => {
+            if line_lengths_param(params)? {
+                Box::new(parsers::fasta::WrappedFastaReader::new(rb, None)?)
+            } else {
+                Box::new(parsers::fasta::FastaReader::new(rb, None)?)
+            }
+        }
+        "fastq" => Box::new(parsers::fastq::FastqReader::new(
+            rb,
+            Some(fastq_params(params)?),
+        )?),
+        "flow" => match stream_param(params)? {
+            parsers::flow::FcsStream::Events => Box::new(parsers::flow::FcsReader::new(
+                rb,
+                Some(fcs_params(params)?),
+            )?),
+            parsers::flow::FcsStream::EventsGaps => Box::new(parsers::flow::FcsGapReader::new(
+                rb,
+                Some(fcs_params(params)?),
+            )?),
+        },
+        "gml" => Box::new(parsers::graph::GmlReader::new(
+            rb,
+            Some(graph_params(params)?),
+        )?),
+        "graphml" => Box::new(parsers::graph::GraphMlReader::new(
+            rb,
+            Some(graph_params(params)?),
+        )?),
+        "gpx" => Box::new(parsers::gpx::GpxReader::new(rb, None)?),
         "inficon" => Box::new(parsers::inficon::InficonReader::new(rb, None)?),
+        "kmers" => Box::new(crate::kmers::KmerReader::new(
+            rb,
+            Some(kmer_params(params)?),
+        )?),
         #[cfg(feature = "std")]
         "masshunter_dad" => Box::new(parsers::agilent::masshunter::MasshunterDadReader::new(
             rb,
@@ -78,23 +306,363 @@ fn _get_reader<'n, 'p, 'r>(
             ),
         )?),
         #[cfg(feature = "std")]
+        "masshunter_ms" => Box::new(parsers::agilent::masshunter::MasshunterMsReader::new(
+            rb,
+            Some(
+                params
+                    .remove("filename")
+                    .ok_or_else(|| "No filename found".into())
+                    .and_then(Value::into_string)?,
+            ),
+        )?),
+        #[cfg(feature = "std")]
+        "mzxml" => Box::new(parsers::mzxml::MzXmlReader::new(rb, None)?),
+        "mztab" => Box::new(parsers::mztab::MztabReader::new(rb, None)?),
+        "pepxml" => Box::new(parsers::pepxml::PepXmlReader::new(rb, None)?),
+        #[cfg(feature = "std")]
         "png" => Box::new(parsers::png::PngReader::new(rb, None)?),
-        "sam" => Box::new(parsers::sam::SamReader::new(rb, None)?),
-        "thermo_cf" => Box::new(parsers::thermo::thermo_iso::ThermoCfReader::new(rb, None)?),
-        "thermo_dxf" => Box::new(parsers::thermo::thermo_iso::ThermoDxfReader::new(rb, None)?),
-        "thermo_raw" => Box::new(parsers::thermo::thermo_raw::ThermoRawReader::new(rb, None)?),
+        "sam" => Box::new(parsers::sam::SamReader::new(rb, Some(sam_params(params)?))?),
+        "sensor_log" => Box::new(parsers::sensor_log::SensorLogReader::new(
+            rb,
+            Some(sensor_log_params(params)?),
+        )?),
+        "sercon_irms" => Box::new(parsers::irms::IrmsReader::new(
+            rb,
+            Some(parsers::irms::IrmsVendor::Sercon),
+        )?),
+        "thermo_cf" => Box::new(parsers::thermo::thermo_iso::ThermoCfReader::new(
+            rb,
+            Some(text_decoding(params)?),
+        )?),
+        "thermo_dxf" => Box::new(parsers::thermo::thermo_iso::ThermoDxfReader::new(
+            rb,
+            Some(text_decoding(params)?),
+        )?),
+        "thermo_raw" => Box::new(parsers::thermo::thermo_raw::ThermoRawReader::new(
+            rb,
+            Some(
+                parsers::thermo::thermo_raw::ThermoRawParams::default()
+                    .text_decoding(text_decoding(params)?),
+            ),
+        )?),
+        #[cfg(feature = "std")]
+        "tiff" => Box::new(parsers::tiff::TiffReader::new(rb, None)?),
+        #[cfg(feature = "std")]
+        "tar" => {
+            // everything (except the filename) is passed on to the readers for each member
+            drop(params.remove("filename"));
+            Box::new(TarReader::new(rb, core::mem::take(params))?)
+        }
         "tsv" => Box::new(parsers::tsv::TsvReader::new(
             rb,
-            Some(parsers::tsv::TsvParams::default().delim(b'\t')),
+            Some(tsv_params(
+                params,
+                parsers::tsv::TsvParams::default().delim(b'\t'),
+            )?),
+        )?),
+        #[cfg(feature = "std")]
+        "waters" => Box::new(parsers::waters::WatersReader::new(
+            rb,
+            Some(
+                params
+                    .remove("filename")
+                    .ok_or_else(|| "No filename found".into())
+                    .and_then(Value::into_string)?,
+            ),
         )?),
         x => return Err(format!("No parser available for the parser {}", x).into()),
+    })
+}
+
+/// Wrap `reader` to add the `parse` step to its provenance (if it hasn't been added already).
+fn with_parse_step<'r>(
+    reader: Box<dyn RecordReader + 'r>,
+    parse: &mut Option<Value<'static>>,
+) -> Box<dyn RecordReader + 'r> {
+    match parse.take() {
+        Some(step) => Box::new(ProvenanceReader::new(reader, step)),
+        None => reader,
+    }
+}
+
+/// Build the `FastqParams` for the FASTQ reader out of the `tags` param (if present); this can
+/// be either a single tag specification (e.g. `umi=read1[0:12]`) or a list of them.
+fn fastq_params(
+    params: &mut BTreeMap<String, Value<'_>>,
+) -> Result<parsers::fastq::FastqParams, EtError> {
+    let mut fastq_params = parsers::fastq::FastqParams::default();
+    let tags = match params.remove("tags") {
+        Some(Value::List(tags)) => tags,
+        Some(tag) => vec![tag],
+        None => Vec::new(),
     };
-    drop(params.remove("filename"));
-    if !params.is_empty() {
-        let keys: Vec<&str> = params.keys().map(AsRef::as_ref).collect();
-        return Err(format!("Unused params remain: {}", keys.join(",")).into());
+    for tag in tags {
+        fastq_params = fastq_params.tag(tag.into_string()?.parse()?);
     }
-    Ok((reader, parser_name))
+    Ok(fastq_params)
+}
+
+/// Read the `line_lengths` param that keeps how FASTA sequences were split over lines.
+fn line_lengths_param(params: &mut BTreeMap<String, Value<'_>>) -> Result<bool, EtError> {
+    match params.remove("line_lengths") {
+        Some(Value::Boolean(b)) => Ok(b),
+        Some(_) => Err("The line_lengths param must be true or false".into()),
+        None => Ok(false),
+    }
+}
+
+/// Build the `FcsParams` for the FCS reader out of the `first_event`, `last_event`,
+/// `sample_n`, `abs_time`, `strict_dates`, and `default_date` params (if present).
+fn fcs_params(
+    params: &mut BTreeMap<String, Value<'_>>,
+) -> Result<parsers::flow::FcsParams, EtError> {
+    let mut fcs_params = parsers::flow::FcsParams::default();
+    if let Some(n) = count_param(params, "first_event")? {
+        fcs_params = fcs_params.first_event(n);
+    }
+    if let Some(n) = count_param(params, "last_event")? {
+        fcs_params = fcs_params.last_event(n);
+    }
+    if let Some(n) = count_param(params, "sample_n")? {
+        fcs_params = fcs_params.sample_n(n);
+    }
+    match params.remove("abs_time") {
+        Some(Value::Boolean(b)) => fcs_params = fcs_params.abs_time(b),
+        Some(_) => return Err("The abs_time param must be true or false".into()),
+        None => {}
+    }
+    match params.remove("gap_threshold") {
+        Some(Value::Float(f)) => fcs_params = fcs_params.gap_threshold(f),
+        #[allow(clippy::cast_precision_loss)]
+        Some(Value::Integer(i)) => fcs_params = fcs_params.gap_threshold(i as f64),
+        Some(Value::String(s)) => fcs_params = fcs_params.gap_threshold(s.trim().parse()?),
+        Some(_) => return Err("The gap_threshold param must be a number of seconds".into()),
+        None => {}
+    }
+    let (strict_dates, default_date) = date_params(params)?;
+    fcs_params = fcs_params.strict_dates(strict_dates);
+    if let Some(date) = default_date {
+        fcs_params = fcs_params.default_date(date);
+    }
+    Ok(fcs_params)
+}
+
+/// Read a param that should be a non-negative integer (strings of digits are also accepted).
+fn count_param(
+    params: &mut BTreeMap<String, Value<'_>>,
+    key: &str,
+) -> Result<Option<usize>, EtError> {
+    let count = match params.remove(key) {
+        Some(Value::Integer(i)) => usize::try_from(i).ok(),
+        Some(Value::String(s)) => s.trim().parse().ok(),
+        Some(_) => None,
+        None => return Ok(None),
+    };
+    match count {
+        Some(count) => Ok(Some(count)),
+        None => Err(format!("{} must be a non-negative integer", key).into()),
+    }
+}
+
+/// Read the `strict_dates` param (whether missing or unparseable dates are reported as null)
+/// and the `default_date` param (the date to use for them instead, as a date or a string).
+fn date_params(
+    params: &mut BTreeMap<String, Value<'_>>,
+) -> Result<(bool, Option<NaiveDate>), EtError> {
+    let strict_dates = match params.remove("strict_dates") {
+        Some(Value::Boolean(b)) => b,
+        Some(_) => return Err("The strict_dates param must be true or false".into()),
+        None => false,
+    };
+    let default_date = match params.remove("default_date") {
+        Some(Value::Datetime(d)) => Some(d.date()),
+        Some(Value::String(s)) => Some(DateParser::default().parse_date(&s)?),
+        Some(_) => return Err("The default_date param must be a date".into()),
+        None => None,
+    };
+    Ok((strict_dates, default_date))
+}
+
+/// Read the `text_decoding` param (`strict` or `lossy`) that controls how text fields that
+/// aren't validly encoded (e.g. Latin-1 sample names) are handled.
+fn text_decoding(params: &mut BTreeMap<String, Value<'_>>) -> Result<TextDecoding, EtError> {
+    match params.remove("text_decoding") {
+        Some(decoding) => decoding.into_string()?.parse(),
+        None => Ok(TextDecoding::default()),
+    }
+}
+
+/// Build the `ChemstationParams` out of the `text_decoding`, `canonical_units`, `raw_values`,
+/// `integer_values` (which only changes `chemstation_ms`), `strict_dates`, and `default_date`
+/// params (if present).
+fn chemstation_params(
+    params: &mut BTreeMap<String, Value<'_>>,
+) -> Result<parsers::agilent::metadata::ChemstationParams, EtError> {
+    let canonical_units = match params.remove("canonical_units") {
+        Some(Value::Boolean(b)) => b,
+        Some(_) => return Err("The canonical_units param must be true or false".into()),
+        None => false,
+    };
+    let raw_values = match params.remove("raw_values") {
+        Some(Value::Boolean(b)) => b,
+        Some(_) => return Err("The raw_values param must be true or false".into()),
+        None => false,
+    };
+    let integer_values = match params.remove("integer_values") {
+        Some(Value::Boolean(b)) => b,
+        Some(_) => return Err("The integer_values param must be true or false".into()),
+        None => false,
+    };
+    let (strict_dates, default_date) = date_params(params)?;
+    let mut chemstation_params = parsers::agilent::metadata::ChemstationParams::default()
+        .text_decoding(text_decoding(params)?)
+        .canonical_units(canonical_units)
+        .raw_values(raw_values)
+        .integer_values(integer_values)
+        .strict_dates(strict_dates);
+    if let Some(date) = default_date {
+        chemstation_params = chemstation_params.default_date(date);
+    }
+    Ok(chemstation_params)
+}
+
+/// Override the delimiter and quoting/comment characters of `tsv_params` with the
+/// `delimiter`, `quote_char`, and `comment_char` params and whether the file has a header
+/// line, how many lines to skip before it, and whether to infer field types with the
+/// `has_header`, `skip_lines`, and `infer_types` params (if present).
+fn tsv_params(
+    params: &mut BTreeMap<String, Value<'_>>,
+    mut tsv_params: parsers::tsv::TsvParams,
+) -> Result<parsers::tsv::TsvParams, EtError> {
+    if let Some(c) = char_param(params, "delimiter")? {
+        tsv_params = tsv_params.delim(c);
+    }
+    if let Some(c) = char_param(params, "quote_char")? {
+        tsv_params = tsv_params.quote(c);
+    }
+    if let Some(c) = char_param(params, "comment_char")? {
+        tsv_params = tsv_params.comment(c);
+    }
+    if let Some(n) = count_param(params, "skip_lines")? {
+        tsv_params = tsv_params.skip_lines(n);
+    }
+    match params.remove("has_header") {
+        Some(Value::Boolean(b)) => tsv_params = tsv_params.has_header(b),
+        Some(_) => return Err("The has_header param must be true or false".into()),
+        None => {}
+    }
+    match params.remove("infer_types") {
+        Some(Value::Boolean(b)) => tsv_params = tsv_params.infer_types(b),
+        Some(_) => return Err("The infer_types param must be true or false".into()),
+        None => {}
+    }
+    Ok(tsv_params)
+}
+
+/// Build the `SensorLogParams` out of the `time_column` param and the delimiter and quoting
+/// params (see `tsv_params`).
+fn sensor_log_params(
+    params: &mut BTreeMap<String, Value<'_>>,
+) -> Result<parsers::sensor_log::SensorLogParams, EtError> {
+    let mut sensor_log_params = parsers::sensor_log::SensorLogParams {
+        tsv: tsv_params(params, parsers::tsv::TsvParams::default())?,
+        ..Default::default()
+    };
+    if let Some(column) = params.remove("time_column") {
+        sensor_log_params = sensor_log_params.time_column(&column.into_string()?);
+    }
+    Ok(sensor_log_params)
+}
+
+/// Read a param that should be a single ASCII character (`\t` is also accepted for tabs).
+fn char_param(params: &mut BTreeMap<String, Value<'_>>, key: &str) -> Result<Option<u8>, EtError> {
+    let text = match params.remove(key) {
+        Some(value) => value.into_string()?,
+        None => return Ok(None),
+    };
+    match text.as_bytes() {
+        [c] if c.is_ascii() => Ok(Some(*c)),
+        b"\\t" => Ok(Some(b'\t')),
+        _ => Err(format!("{} must be a single character, not {:?}", key, text).into()),
+    }
+}
+
+/// Build the `GraphParams` for the graph readers out of the `stream` param (if present).
+fn graph_params(
+    params: &mut BTreeMap<String, Value<'_>>,
+) -> Result<parsers::graph::GraphParams, EtError> {
+    let mut graph_params = parsers::graph::GraphParams::default();
+    if let Some(stream) = params.remove("stream") {
+        graph_params = graph_params.stream(stream.into_string()?.parse()?);
+    }
+    Ok(graph_params)
+}
+
+/// Read the `alignment_stats` param for SAM and BAM files
+fn sam_params(
+    params: &mut BTreeMap<String, Value<'_>>,
+) -> Result<parsers::sam::SamParams, EtError> {
+    let alignment_stats = match params.remove("alignment_stats") {
+        Some(Value::Boolean(b)) => b,
+        Some(_) => return Err("The alignment_stats param must be true or false".into()),
+        None => false,
+    };
+    Ok(parsers::sam::SamParams::default().alignment_stats(alignment_stats))
+}
+
+/// Read the params for the `kmers` parser: the `kmer_size`, whether they're normalized with
+/// `canonical_kmers`, the `minimizer_window`, and whether `kmer_counts` are returned instead
+fn kmer_params(
+    params: &mut BTreeMap<String, Value<'_>>,
+) -> Result<crate::kmers::KmerParams, EtError> {
+    let mut kmer_params = crate::kmers::KmerParams::default();
+    if let Some(kmer_size) = count_param(params, "kmer_size")? {
+        kmer_params = kmer_params.kmer_size(kmer_size);
+    }
+    let canonical = match params.remove("canonical_kmers") {
+        Some(Value::Boolean(b)) => b,
+        Some(_) => return Err("The canonical_kmers param must be true or false".into()),
+        None => false,
+    };
+    let counts = match params.remove("kmer_counts") {
+        Some(Value::Boolean(b)) => b,
+        Some(_) => return Err("The kmer_counts param must be true or false".into()),
+        None => false,
+    };
+    Ok(kmer_params
+        .canonical(canonical)
+        .minimizer_window(count_param(params, "minimizer_window")?)
+        .counts(counts))
+}
+
+/// Read the `stream` param that picks which kind of record a reader returns (e.g. alignments
+/// or references for BAM files)
+fn stream_param<S: FromStr<Err = EtError> + Default>(
+    params: &mut BTreeMap<String, Value<'_>>,
+) -> Result<S, EtError> {
+    match params.remove("stream") {
+        Some(stream) => stream.into_string()?.parse(),
+        None => Ok(S::default()),
+    }
+}
+
+/// Read the `aggregate` (e.g. `mean`) and `bin` (e.g. `0.1min`) params used to downsample
+/// trace data; if only `bin` is given, the intensities are averaged.
+fn aggregate_params(
+    params: &mut BTreeMap<String, Value<'_>>,
+) -> Result<Option<(Aggregation, f64)>, EtError> {
+    let aggregation = match params.remove("aggregate") {
+        Some(a) => Some(a.into_string()?.parse()?),
+        None => None,
+    };
+    let bin_width = match params.remove("bin") {
+        Some(Value::Float(f)) => f,
+        Some(Value::Integer(i)) => i as f64,
+        Some(b) => parse_bin_width(&b.into_string()?)?,
+        None if aggregation.is_some() => return Err("Aggregation requires a bin width".into()),
+        None => return Ok(None),
+    };
+    Ok(Some((aggregation.unwrap_or(Aggregation::Mean), bin_width)))
 }
 
 /// The trait that maps over "generic" `RecordReader`s
@@ -118,6 +686,425 @@ pub trait RecordReader: ::core::fmt::Debug {
 
     /// Extra metadata about the file or data in the file
     fn metadata(&self) -> BTreeMap<String, Value>;
+
+    /// A `HeaderMap` of the headers, for looking up fields in each record by name (e.g.
+    /// `reader.header_map().view(&record).get("mz")`)
+    fn header_map(&self) -> HeaderMap {
+        HeaderMap::new(self.headers())
+    }
+
+    /// Extra metadata about each of the fields (e.g. the detector settings used for a channel),
+    /// keyed by header; fields without any metadata are left out.
+    fn column_metadata(&self) -> BTreeMap<String, BTreeMap<String, Value<'_>>> {
+        BTreeMap::new()
+    }
+
+    /// The columns and metadata keys that hold the values for a `RunSummary` (e.g. which
+    /// column has the time of each record)
+    fn summary_fields(&self) -> SummaryFields {
+        SummaryFields::default()
+    }
+
+    /// Returns the bounds on the number of records left to read, like `Iterator::size_hint`
+    /// (e.g. for showing progress); most readers can't know this without reading the whole
+    /// file so the default is `(0, None)`.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, None)
+    }
+
+    /// What this reader can do beyond returning records in order (e.g. if it knows how many
+    /// records there are or has metadata); readers that don't know report none.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::empty()
+    }
+
+    /// Reads up to `n` records into `batch`, returning how many were read (fewer than `n` only
+    /// once the file is finished).
+    ///
+    /// The records are owned so they can be kept after the next call and any `Vec`s already in
+    /// `batch` are reused; reading records in batches like this is much faster than calling
+    /// `next_record` for each one from e.g. language bindings.
+    ///
+    /// # Errors
+    /// If a record can't be read, an error is returned and `batch` holds the records read
+    /// before it.
+    fn next_batch(
+        &mut self,
+        batch: &mut Vec<Vec<Value<'static>>>,
+        n: usize,
+    ) -> Result<usize, EtError> {
+        let mut n_read = 0;
+        while n_read < n {
+            let record = match self.next_record() {
+                Ok(Some(record)) => record,
+                Ok(None) => break,
+                Err(e) => {
+                    batch.truncate(n_read);
+                    return Err(e);
+                }
+            };
+            fill_batch_slot(batch, n_read, record);
+            n_read += 1;
+        }
+        batch.truncate(n_read);
+        Ok(n_read)
+    }
+
+    /// Reads up to `n` records onto the end of `columns` (starting with one for each header if
+    /// it's empty), returning how many were read (fewer than `n` only once the file is
+    /// finished).
+    ///
+    /// Unlike `next_batch`, no `Vec` is kept for each record and numbers are stored in typed
+    /// vectors (see `Column`) so this is much lighter for building data frames out of dense
+    /// numeric files; calling it repeatedly with the same `columns` reads the whole file.
+    ///
+    /// # Errors
+    /// If a record can't be read, an error is returned and `columns` holds the records read
+    /// before it.
+    fn next_columns(&mut self, columns: &mut Vec<Column>, n: usize) -> Result<usize, EtError> {
+        if columns.is_empty() {
+            columns.resize_with(self.headers().len(), Column::default);
+        }
+        let mut n_read = 0;
+        while n_read < n {
+            match self.next_record()? {
+                Some(record) => push_record(columns, record),
+                None => break,
+            }
+            n_read += 1;
+        }
+        Ok(n_read)
+    }
+
+    /// Skips over the next `n` records, returning how many were skipped (fewer than `n` only
+    /// once the file is finished).
+    ///
+    /// By default the records are read and thrown away, but readers for formats where records
+    /// can be found without parsing the ones before them (e.g. fixed-size events in an FCS
+    /// file) skip over their data instead; these report `Capabilities::SEEKABLE`.
+    ///
+    /// # Errors
+    /// If a record can't be read (or the data ends partway through the skipped records), an
+    /// error is returned.
+    fn skip_records(&mut self, n: usize) -> Result<usize, EtError> {
+        let mut n_skipped = 0;
+        while n_skipped < n && self.next_record()?.is_some() {
+            n_skipped += 1;
+        }
+        Ok(n_skipped)
+    }
+
+    /// How many bytes of the (decompressed) data have been parsed so far, if the reader knows;
+    /// readers that don't parse from a single buffer return `None`.
+    fn bytes_read(&self) -> Option<u64> {
+        None
+    }
+
+    /// Reads (and throws away) any records left and returns the final metadata.
+    ///
+    /// Readers that keep statistics about the records they return (see `ReportReader`) add
+    /// them to the metadata once every record has been read, so calling this after converting
+    /// a file gets those without a second pass over it.
+    ///
+    /// # Errors
+    /// If any of the remaining records can't be read, an error is returned.
+    fn finalize(&mut self) -> Result<BTreeMap<String, Value<'static>>, EtError> {
+        while self.next_record()?.is_some() {}
+        Ok(self
+            .metadata()
+            .into_iter()
+            .map(|(key, value)| (key, value.into_owned()))
+            .collect())
+    }
+
+    /// Returns the next record from the file as a list of named fields.
+    ///
+    /// Unlike `next_record`, this allows different records to have different fields; any
+    /// `Value::Record` in the record is flattened out into fields named `header.key` so
+    /// fields that are only present for some records can be handled as separate columns.
+    ///
+    /// # Errors
+    /// If the record can't be read, an error is returned.
+    fn next_fields(&mut self) -> Result<Option<Vec<(String, Value<'_>)>>, EtError> {
+        let headers = self.headers();
+        let record = match self.next_record()? {
+            Some(record) => record,
+            None => return Ok(None),
+        };
+        let mut fields = Vec::with_capacity(record.len());
+        for (header, value) in headers.into_iter().zip(record) {
+            if let Value::Record(subfields) = value {
+                fields.extend(
+                    subfields
+                        .into_iter()
+                        .map(|(key, value)| (format!("{}.{}", header, key), value)),
+                );
+            } else {
+                fields.push((header, value));
+            }
+        }
+        Ok(Some(fields))
+    }
+}
+
+/// Put an owned copy of `record` into `batch[ix]` (reusing the `Vec` that's there, if any).
+#[inline]
+fn fill_batch_slot(batch: &mut Vec<Vec<Value<'static>>>, ix: usize, record: Vec<Value<'_>>) {
+    let record = record.into_iter().map(Value::into_owned);
+    match batch.get_mut(ix) {
+        Some(slot) => {
+            slot.clear();
+            slot.extend(record);
+        }
+        None => batch.push(record.collect()),
+    }
+}
+
+/// A reader for data without any records in it (e.g. an empty file)
+#[derive(Debug, Default)]
+pub struct EmptyReader {
+    headers: Vec<String>,
+}
+
+impl EmptyReader {
+    /// Create a new `EmptyReader` with the given headers
+    #[must_use]
+    pub fn new(headers: Vec<String>) -> Self {
+        EmptyReader { headers }
+    }
+}
+
+impl RecordReader for EmptyReader {
+    fn next_record(&mut self) -> Result<Option<Vec<Value<'_>>>, EtError> {
+        Ok(None)
+    }
+
+    fn headers(&self) -> Vec<String> {
+        self.headers.clone()
+    }
+
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        BTreeMap::new()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(0))
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::SIZED
+    }
+}
+
+/// Wraps a `RecordReader` to read records that may not all have the same fields.
+///
+/// The headers are the union of the fields seen in every record read so far (in the order
+/// they were first seen) and `fill` can be used to line a record's fields up with them.
+#[derive(Debug)]
+pub struct RaggedReader<'r> {
+    reader: Box<dyn RecordReader + 'r>,
+    headers: Vec<String>,
+    positions: BTreeMap<String, usize>,
+    /// How many headers there were before the last record was read
+    n_old_headers: usize,
+}
+
+impl<'r> RaggedReader<'r> {
+    /// Create a new `RaggedReader` around a `RecordReader`
+    #[must_use]
+    pub fn new(reader: Box<dyn RecordReader + 'r>) -> Self {
+        RaggedReader {
+            reader,
+            headers: Vec::new(),
+            positions: BTreeMap::new(),
+            n_old_headers: 0,
+        }
+    }
+
+    /// Returns the next record's fields, adding any new field names to the headers.
+    ///
+    /// # Errors
+    /// If the record can't be read, an error is returned.
+    pub fn next_fields(&mut self) -> Result<Option<Vec<(String, Value<'_>)>>, EtError> {
+        self.n_old_headers = self.headers.len();
+        let fields = match self.reader.next_fields()? {
+            Some(fields) => fields,
+            None => return Ok(None),
+        };
+        for (name, _) in &fields {
+            if !self.positions.contains_key(name) {
+                let _ = self.positions.insert(name.clone(), self.headers.len());
+                self.headers.push(name.clone());
+            }
+        }
+        Ok(Some(fields))
+    }
+
+    /// The names of every field seen so far
+    #[must_use]
+    pub fn headers(&self) -> &[String] {
+        &self.headers
+    }
+
+    /// The names of the fields that were first seen in the last record read (e.g. for writing
+    /// a new header before it); these are always at the end of `headers`
+    #[must_use]
+    pub fn new_headers(&self) -> &[String] {
+        &self.headers[self.n_old_headers..]
+    }
+
+    /// Extra metadata about the file or data in the file
+    #[must_use]
+    pub fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        self.reader.metadata()
+    }
+
+    /// Extra metadata about each of the fields
+    #[must_use]
+    pub fn column_metadata(&self) -> BTreeMap<String, BTreeMap<String, Value<'_>>> {
+        self.reader.column_metadata()
+    }
+
+    /// Return the underlying `RecordReader`
+    #[must_use]
+    pub fn into_inner(self) -> Box<dyn RecordReader + 'r> {
+        self.reader
+    }
+
+    /// Line a record's fields up with the current headers, filling any fields the record
+    /// doesn't have with `Value::Null`.
+    ///
+    /// Fields that haven't been seen by this reader are left out.
+    #[must_use]
+    pub fn fill<'v>(&self, fields: Vec<(String, Value<'v>)>) -> Vec<Value<'v>> {
+        let mut record = vec![Value::Null; self.headers.len()];
+        for (name, value) in fields {
+            if let Some(&pos) = self.positions.get(&name) {
+                record[pos] = value;
+            }
+        }
+        record
+    }
+}
+
+/// How many records `OwnedRecords` reads from its reader at a time
+const OWNED_BATCH_SIZE: usize = 1024;
+
+/// An iterator over owned copies of the records from a `RecordReader`.
+///
+/// Each record is a `Vec<Value<'static>>` that doesn't borrow from the reader (or its parser
+/// state) so records can be kept around or sent to other threads. Records are read from the
+/// reader in batches (see `RecordReader::next_batch`) and iteration stops after an error.
+#[derive(Debug)]
+pub struct OwnedRecords<'r> {
+    reader: Box<dyn RecordReader + 'r>,
+    batch: Vec<Vec<Value<'static>>>,
+    /// The position of the next record to return in `batch`
+    pos: usize,
+    done: bool,
+    /// The error that stopped reading, returned once the records read before it are
+    error: Option<EtError>,
+}
+
+impl<'r> OwnedRecords<'r> {
+    /// Create a new `OwnedRecords` that reads from `reader`
+    #[must_use]
+    pub fn new(reader: Box<dyn RecordReader + 'r>) -> Self {
+        OwnedRecords {
+            reader,
+            batch: Vec::new(),
+            pos: 0,
+            done: false,
+            error: None,
+        }
+    }
+
+    /// The header titles that correspond to every item in the records
+    #[must_use]
+    pub fn headers(&self) -> Vec<String> {
+        self.reader.headers()
+    }
+
+    /// Extra metadata about the file or data in the file
+    #[must_use]
+    pub fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        self.reader.metadata()
+    }
+
+    /// Return the underlying `RecordReader` (any records already read into the current batch
+    /// are dropped)
+    #[must_use]
+    pub fn into_inner(self) -> Box<dyn RecordReader + 'r> {
+        self.reader
+    }
+
+    /// Read the remaining records and run `f` on each of them on rayon's thread pool, returning
+    /// the results in the same order as the records.
+    ///
+    /// Reading still happens on the calling thread, a batch at a time, while the records in
+    /// each batch are handled in parallel.
+    ///
+    /// # Errors
+    /// If a record can't be read, an error is returned.
+    #[cfg(feature = "rayon")]
+    pub fn par_map<F, T>(mut self, f: F) -> Result<Vec<T>, EtError>
+    where
+        F: Fn(Vec<Value<'static>>) -> T + Send + Sync,
+        T: Send,
+    {
+        use rayon::prelude::*;
+
+        let mut results = Vec::new();
+        // finish off anything left in the current batch first
+        let rest = self.batch.split_off(self.pos);
+        results.par_extend(rest.into_par_iter().map(&f));
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        while !self.done {
+            let n_read = self.reader.next_batch(&mut self.batch, OWNED_BATCH_SIZE)?;
+            self.done = n_read < OWNED_BATCH_SIZE;
+            results.par_extend(core::mem::take(&mut self.batch).into_par_iter().map(&f));
+        }
+        Ok(results)
+    }
+}
+
+impl<'r> Iterator for OwnedRecords<'r> {
+    type Item = Result<Vec<Value<'static>>, EtError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.batch.len() {
+            if self.done {
+                return self.error.take().map(Err);
+            }
+            match self.reader.next_batch(&mut self.batch, OWNED_BATCH_SIZE) {
+                // `next_batch` only reads less than a full batch once the file's finished
+                Ok(n_read) => self.done = n_read < OWNED_BATCH_SIZE,
+                // the records before the error are still in the batch, so they go out first
+                Err(e) => {
+                    self.done = true;
+                    self.error = Some(e);
+                }
+            }
+            self.pos = 0;
+            if self.batch.is_empty() {
+                return self.error.take().map(Err);
+            }
+        }
+        let record = core::mem::take(&mut self.batch[self.pos]);
+        self.pos += 1;
+        Some(Ok(record))
+    }
+}
+
+impl<'r> dyn RecordReader + 'r {
+    /// Convert this reader into an iterator of owned records (that can e.g. be sent to other
+    /// threads for processing).
+    #[must_use]
+    pub fn into_owned_iter(self: Box<Self>) -> OwnedRecords<'r> {
+        OwnedRecords::new(self)
+    }
 }
 
 /// Generates a `...Reader` struct for the associated state-based file parsers
@@ -168,6 +1155,72 @@ macro_rules! impl_reader {
                 Ok(self.next()?.map(|r| r.into()))
             }
 
+            /// Reads records into columns without going through `next_record` for each one
+            /// (pushing each field straight into its column, or decoding straight out of the
+            /// buffer if the state knows how).
+            fn next_columns(
+                &mut self,
+                columns: &mut ::alloc::vec::Vec<$crate::columns::Column>,
+                n: usize,
+            ) -> Result<usize, EtError> {
+                use $crate::record::StateMetadata;
+                if columns.is_empty() {
+                    columns.resize_with(self.headers().len(), Default::default);
+                }
+                let mut n_read = 0;
+                while n_read < n {
+                    let decoded = self
+                        .state
+                        .read_columns(self.rb.unparsed(), columns, n - n_read)
+                        .map_err(|e| e.add_context_from_readbuffer(&self.rb))?;
+                    match decoded {
+                        (0, _) => {
+                            match self.next()? {
+                                Some(record) => {
+                                    use $crate::record::PushFields;
+                                    let mut row = $crate::columns::ColumnRow::new(columns);
+                                    record.push_fields(&mut row);
+                                    row.finish();
+                                }
+                                None => break,
+                            }
+                            n_read += 1;
+                        }
+                        (n_records, n_bytes) => {
+                            self.rb.skip(n_bytes as u64, n_records)?;
+                            n_read += n_records;
+                        }
+                    }
+                }
+                Ok(n_read)
+            }
+
+            /// Skips records by their size if the state knows it or by parsing them otherwise.
+            fn skip_records(&mut self, n: usize) -> Result<usize, EtError> {
+                use $crate::record::StateMetadata;
+                let mut n_skipped = 0;
+                while n_skipped < n {
+                    match self.state.skip_records(n - n_skipped) {
+                        (0, _) => {
+                            if self.next()?.is_none() {
+                                break;
+                            }
+                            n_skipped += 1;
+                        }
+                        (n_records, n_bytes) => {
+                            self.rb.skip(n_bytes, n_records)?;
+                            n_skipped += n_records;
+                        }
+                    }
+                }
+                Ok(n_skipped)
+            }
+
+            /// How far into the data this Reader has parsed.
+            fn bytes_read(&self) -> ::core::option::Option<u64> {
+                ::core::option::Option::Some(self.rb.reader_pos + self.rb.consumed as u64)
+            }
+
             /// The headers for this Reader.
             fn headers(&self) -> ::alloc::vec::Vec<::alloc::string::String> {
                 use $crate::record::StateMetadata;
@@ -178,7 +1231,40 @@ macro_rules! impl_reader {
             /// The metadata for this Reader.
             fn metadata(&self) -> ::alloc::collections::BTreeMap<::alloc::string::String, $crate::record::Value> {
                 use $crate::record::StateMetadata;
-                self.state.metadata()
+                let mut metadata = self.state.metadata();
+                if let Some(bom) = self.rb.bom {
+                    drop(metadata.insert("text_encoding".into(), bom.encoding().into()));
+                }
+                metadata
+            }
+
+            /// The metadata for each field of this Reader.
+            fn column_metadata(
+                &self,
+            ) -> ::alloc::collections::BTreeMap<
+                ::alloc::string::String,
+                ::alloc::collections::BTreeMap<::alloc::string::String, $crate::record::Value<'_>>,
+            > {
+                use $crate::record::StateMetadata;
+                self.state.column_metadata()
+            }
+
+            /// Where the values for a run summary are for this Reader.
+            fn summary_fields(&self) -> $crate::summary::SummaryFields {
+                use $crate::record::StateMetadata;
+                self.state.summary_fields()
+            }
+
+            /// The bounds on the number of records left in this Reader.
+            fn size_hint(&self) -> (usize, ::core::option::Option<usize>) {
+                use $crate::record::StateMetadata;
+                self.state.size_hint()
+            }
+
+            /// What this Reader can do.
+            fn capabilities(&self) -> $crate::capabilities::Capabilities {
+                use $crate::record::StateMetadata;
+                self.state.capabilities()
             }
         }
     };
@@ -194,8 +1280,19 @@ where
     S: for<'s> FromSlice<'s, 's, State = P>,
     P: Default,
 {
-    let mut buffer = data.try_into()?;
-    if let Some(state) = buffer.next::<S>(&mut params.unwrap_or_default())? {
+    let mut buffer: ReadBuffer<'r> = data.try_into()?;
+    let empty = buffer.is_exhausted()?;
+    let parsed = match buffer.next::<S>(&mut params.unwrap_or_default()) {
+        // a header can't be found in data that isn't there, which is marked as the data ending
+        // at byte 0 (so an empty file can be told apart from other errors, see `_get_reader`)
+        Err(e) if empty && e.kind == EtErrorKind::Other => Err(e.truncated(0, 0, 0)),
+        parsed => parsed,
+    };
+    if let Some(state) = parsed? {
+        // the header isn't a record so the first record's at 0 in error messages (and its size
+        // shouldn't skew how much is read ahead for the records)
+        buffer.record_pos = 0;
+        buffer.avg_record_len = 0;
         Ok((buffer, state))
     } else {
         Err(format!(
@@ -210,6 +1307,18 @@ where
 mod test {
     use super::*;
 
+    #[test]
+    fn test_init_state_record_len() -> Result<(), EtError> {
+        let buf: &[u8] =
+            include_bytes!("../tests/data/HTS_BD_LSR_II_Mixed_Specimen_001_D6_D06.fcs");
+        let (mut rb, mut state) = init_state::<parsers::flow::FcsState, _, _>(buf, None)?;
+        assert_eq!(rb.avg_record_len(), 0);
+        let _ = rb.next::<parsers::flow::FcsRecord>(&mut state)?;
+        // 11 parameters of 4 bytes each
+        assert_eq!(rb.avg_record_len(), 44);
+        Ok(())
+    }
+
     #[test]
     #[cfg(all(feature = "compression", feature = "std"))]
     fn test_bad_fuzzes() -> Result<(), EtError> {
@@ -227,4 +1336,398 @@ mod test {
         assert!(reader.next_record().is_err());
         Ok(())
     }
+
+    #[test]
+    #[cfg(all(feature = "compression", feature = "std"))]
+    fn test_crasher_corpus() -> Result<(), EtError> {
+        // minimized inputs that used to panic (or try to allocate terabytes); every parser
+        // should return either records or an error for all of them
+        for entry in std::fs::read_dir("tests/data/crashers")? {
+            let data = std::fs::read(entry?.path())?;
+            for parser in crate::build_info::build_info().parsers.keys() {
+                let mut reader = match get_reader(&data[..], Some(*parser), None) {
+                    Ok((reader, _)) => reader,
+                    Err(_) => continue,
+                };
+                let _ = reader.metadata();
+                while let Ok(Some(_)) = reader.next_record() {}
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_inputs() -> Result<(), EtError> {
+        for parser in crate::build_info::build_info().parsers.keys() {
+            let result = get_reader(&b""[..], Some(*parser), None);
+            // parsers that read the other files in a run still need to be told where it is
+            if ["masshunter_dad", "masshunter_ms", "waters"].contains(parser) {
+                assert!(result.is_err(), "{}", parser);
+                continue;
+            }
+            let (mut reader, _) =
+                result.map_err(|e| format!("{} failed on empty data: {}", parser, e))?;
+            assert!(reader.next_record()?.is_none(), "{}", parser);
+        }
+        // and bad parameters are still an error
+        let mut params = BTreeMap::new();
+        let _ = params.insert("skip_flags".into(), "lots".into());
+        assert!(get_reader(&b""[..], Some("depth"), Some(params)).is_err());
+        // the headers are kept for formats that always have the same ones
+        let (reader, _) = get_reader(&b""[..], Some("fastq"), None)?;
+        assert_eq!(reader.headers(), ["id", "sequence", "quality"]);
+
+        let (mut reader, parser) = get_reader(&b""[..], None, None)?;
+        assert_eq!(parser, "tsv");
+        assert!(reader.headers().is_empty());
+        assert!(reader.next_record()?.is_none());
+        // a file that's only a byte order mark is empty too
+        let (mut reader, _) = get_reader(&b"\xEF\xBB\xBF"[..], None, None)?;
+        assert!(reader.next_record()?.is_none());
+
+        assert!(get_reader(&b""[..], Some("not_a_parser"), None).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_fastq_tag_params() -> Result<(), EtError> {
+        let mut params = BTreeMap::new();
+        let _ = params.insert("tags".into(), "umi=read1[0:2]".into());
+        let (mut reader, _) = get_reader(&b"@id\nACGT\n+\n!!!!\n"[..], None, Some(params))?;
+        assert_eq!(reader.headers(), ["id", "sequence", "quality", "umi"]);
+        assert_eq!(reader.next_record()?.unwrap()[3], "AC".into());
+
+        let mut params = BTreeMap::new();
+        let _ = params.insert("tags".into(), "umi=read1".into());
+        assert!(get_reader(&b"@id\nACGT\n+\n!!!!\n"[..], None, Some(params)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_tsv_params() -> Result<(), EtError> {
+        let data = &b"# exported by hand\n'a';'b'\n'x;y';2\n# a note\n'z';4\n"[..];
+        let mut params = BTreeMap::new();
+        let _ = params.insert("delimiter".into(), ";".into());
+        let _ = params.insert("quote_char".into(), "'".into());
+        let _ = params.insert("comment_char".into(), "#".into());
+        let (mut reader, _) = get_reader(data, Some("tsv"), Some(params))?;
+        assert_eq!(reader.headers(), ["a", "b"]);
+        assert_eq!(reader.next_record()?.unwrap(), ["x;y".into(), 2.into()]);
+        assert_eq!(reader.next_record()?.unwrap(), ["z".into(), 4.into()]);
+        assert!(reader.next_record()?.is_none());
+
+        let mut params = BTreeMap::new();
+        let _ = params.insert("delimiter".into(), "\\t".into());
+        let (reader, _) = get_reader(&b"a\tb\n1\t2\n"[..], Some("csv"), Some(params))?;
+        assert_eq!(reader.headers(), ["a", "b"]);
+
+        let mut params = BTreeMap::new();
+        let _ = params.insert("delimiter".into(), "::".into());
+        assert!(get_reader(data, Some("csv"), Some(params)).is_err());
+
+        let mut params = BTreeMap::new();
+        let _ = params.insert("skip_lines".into(), 1.into());
+        let _ = params.insert("has_header".into(), Value::Boolean(false));
+        let _ = params.insert("infer_types".into(), Value::Boolean(false));
+        let data = &b"exported 2021-03-04
+x,1
+y,2.5
+"[..];
+        let (mut reader, _) = get_reader(data, Some("csv"), Some(params))?;
+        assert_eq!(reader.headers(), ["column_1", "column_2"]);
+        assert_eq!(reader.next_record()?.unwrap(), ["x".into(), "1".into()]);
+        assert_eq!(reader.next_record()?.unwrap(), ["y".into(), "2.5".into()]);
+        assert!(reader.next_record()?.is_none());
+
+        let mut params = BTreeMap::new();
+        let _ = params.insert("has_header".into(), "no".into());
+        assert!(get_reader(data, Some("csv"), Some(params)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_delimited_dialects() -> Result<(), EtError> {
+        // e.g. from Excel in a locale that uses semicolons
+        let data = &b"\xEF\xBB\xBFSample;Note;Value\r\n\"A1\";\"it's; fine\";1.5\r\nA2;;2\r\n"[..];
+        let (mut reader, parser) = get_reader(data, None, None)?;
+        assert_eq!(parser, "delimited");
+        assert_eq!(reader.headers(), ["Sample", "Note", "Value"]);
+        assert_eq!(
+            reader.next_record()?.unwrap(),
+            ["A1".into(), "it's; fine".into(), 1.5.into()]
+        );
+
+        let (mut reader, _) = get_reader(&b"1\t2\n3\t4\n"[..], None, None)?;
+        assert_eq!(reader.headers(), ["column_1", "column_2"]);
+        assert_eq!(reader.next_record()?.unwrap(), [1.into(), 2.into()]);
+
+        // the delimiter can still be given
+        let mut params = BTreeMap::new();
+        let _ = params.insert("delimiter".into(), ",".into());
+        let (reader, _) = get_reader(&b"a,b;c\n1,2\n"[..], None, Some(params))?;
+        assert_eq!(reader.headers(), ["a", "b;c"]);
+
+        // but the tsv and csv parsers always read a header unless they're told not to
+        let data = &b"gene\t0\t1\nA\t5\t6\n"[..];
+        let (mut reader, _) = get_reader(data, Some("tsv"), None)?;
+        assert_eq!(reader.headers(), ["gene", "0", "1"]);
+        assert_eq!(
+            reader.next_record()?.unwrap(),
+            ["A".into(), 5.into(), 6.into()]
+        );
+        let (reader, _) = get_reader(&b"1,2\n3,4\n"[..], Some("csv"), None)?;
+        assert_eq!(reader.headers(), ["1", "2"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sensor_log_params() -> Result<(), EtError> {
+        let data = &b"start;logged;t1\n0;5;1.5\n"[..];
+        let mut params = BTreeMap::new();
+        let _ = params.insert("delimiter".into(), ";".into());
+        let _ = params.insert("time_column".into(), "logged".into());
+        let (mut reader, _) = get_reader(data, Some("sensor_log"), Some(params))?;
+        assert_eq!(reader.headers(), ["time", "sensor", "value"]);
+        assert_eq!(
+            reader.next_record()?.unwrap(),
+            [5.into(), "start".into(), 0.0.into()]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_date_params() -> Result<(), EtError> {
+        let mut data = include_bytes!("../tests/data/test_fid.ch").to_vec();
+        data[180] = b'?';
+        let mut params = BTreeMap::new();
+        let _ = params.insert("default_date".into(), "2000-01-01".into());
+        let (reader, _) = get_reader(&data[..], None, Some(params))?;
+        assert_eq!(
+            reader.metadata()["run_date"],
+            NaiveDate::from_ymd_opt(2000, 1, 1)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+                .into()
+        );
+
+        let mut params = BTreeMap::new();
+        let _ = params.insert("default_date".into(), "2000-01-01".into());
+        let _ = params.insert("strict_dates".into(), true.into());
+        let (reader, _) = get_reader(&data[..], None, Some(params))?;
+        assert_eq!(reader.metadata()["run_date"], Value::Null);
+
+        let mut params = BTreeMap::new();
+        let _ = params.insert("default_date".into(), "someday".into());
+        assert!(get_reader(&data[..], None, Some(params)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_next_batch() -> Result<(), EtError> {
+        let data = &b">a\nACGT\n>b\nTT\n>c\nGG\n"[..];
+        let (mut reader, _) = get_reader(data, None, None)?;
+        let mut batch = Vec::new();
+        assert_eq!(reader.next_batch(&mut batch, 2)?, 2);
+        assert_eq!(batch[1], ["b".into(), "TT".into()]);
+        assert_eq!(reader.next_batch(&mut batch, 2)?, 1);
+        assert_eq!(batch, [["c".into(), "GG".into()]]);
+        assert_eq!(reader.next_batch(&mut batch, 2)?, 0);
+        assert!(batch.is_empty());
+
+        // readers that don't override `next_batch` get the default
+        let (reader, _) = get_reader(data, None, None)?;
+        let mut reader = HashReader::new(reader)?;
+        assert_eq!(reader.next_batch(&mut batch, 10)?, 3);
+        assert_eq!(batch[2][0], "c".into());
+        Ok(())
+    }
+
+    #[test]
+    fn test_skip_records() -> Result<(), EtError> {
+        let data = &b">a\nACGT\n>b\nTT\n>c\nGG\n"[..];
+        // FASTA records have to be parsed to be skipped
+        let (mut reader, _) = get_reader(data, None, None)?;
+        assert!(!reader.capabilities().contains(Capabilities::SEEKABLE));
+        assert_eq!(reader.skip_records(2)?, 2);
+        assert_eq!(reader.next_record()?.unwrap()[0], "c".into());
+        assert_eq!(reader.skip_records(2)?, 0);
+
+        // and wrappers skip the records of the reader they wrap
+        let (reader, _) = get_reader(data, None, None)?;
+        let mut reader = HashReader::new(reader)?;
+        assert_eq!(reader.skip_records(1)?, 1);
+        assert_eq!(reader.next_record()?.unwrap()[0], "b".into());
+        Ok(())
+    }
+
+    #[test]
+    fn test_owned_iter() -> Result<(), EtError> {
+        let data = &b">a\nACGT\n>b\nTT\n>c\nGG\n"[..];
+        let (reader, _) = get_reader(data, None, None)?;
+        let records: Vec<Vec<Value<'static>>> =
+            reader.into_owned_iter().collect::<Result<_, _>>()?;
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[2], ["c".into(), "GG".into()]);
+
+        // the records can outlive the reader (and be sent off to other threads)
+        #[cfg(feature = "std")]
+        {
+            let (reader, _) = get_reader(data, None, None)?;
+            let handles: Vec<_> = reader
+                .into_owned_iter()
+                .map(|record| std::thread::spawn(move || record.map(|r| r.len())))
+                .collect();
+            for handle in handles {
+                assert_eq!(handle.join().unwrap()?, 2);
+            }
+        }
+
+        // errors stop the iteration (after the records read before them)
+        let (reader, _) = get_reader(&b">a\nACGT\n>b"[..], Some("fasta"), None)?;
+        let mut iter = reader.into_owned_iter();
+        assert_eq!(iter.next().unwrap()?, ["a".into(), "ACGT".into()]);
+        assert!(iter.next().unwrap().is_err());
+        assert!(iter.next().is_none());
+        Ok(())
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_par_map() -> Result<(), EtError> {
+        let data = b">a\nACGT\n".repeat(3000);
+        let (reader, _) = get_reader(&data[..], None, None)?;
+        let mut iter = reader.into_owned_iter();
+        assert!(iter.next().is_some());
+        let lengths = iter.par_map(|record| match &record[1] {
+            Value::String(s) => s.len(),
+            _ => 0,
+        })?;
+        assert_eq!(lengths.len(), 2999);
+        assert!(lengths.iter().all(|&l| l == 4));
+        Ok(())
+    }
+
+    #[test]
+    fn test_capabilities() -> Result<(), EtError> {
+        let (reader, _) = get_reader(&b">a\nACGT\n"[..], None, None)?;
+        assert_eq!(reader.capabilities(), Capabilities::TYPED_SCHEMA);
+        let (reader, _) = get_reader(&b"a\tb\n1\t2\n"[..], Some("tsv"), None)?;
+        assert!(reader.capabilities().is_empty());
+
+        let data: &[u8] =
+            include_bytes!("../tests/data/HTS_BD_LSR_II_Mixed_Specimen_001_D6_D06.fcs");
+        let (reader, _) = get_reader(data, None, None)?;
+        assert!(reader
+            .capabilities()
+            .contains(Capabilities::SIZED | Capabilities::HAS_METADATA));
+        // wrappers pass the capabilities of the reader they wrap through
+        let mut params = BTreeMap::new();
+        let _ = params.insert("hash".into(), Value::Boolean(true));
+        let (hashed, _) = get_reader(data, None, Some(params))?;
+        assert_eq!(hashed.capabilities(), reader.capabilities());
+        Ok(())
+    }
+
+    /// A reader with an `attrs` column that has different keys in each record
+    #[derive(Debug)]
+    struct SparseReader(Vec<BTreeMap<String, Value<'static>>>);
+
+    impl RecordReader for SparseReader {
+        fn next_record(&mut self) -> Result<Option<Vec<Value<'_>>>, EtError> {
+            if self.0.is_empty() {
+                return Ok(None);
+            }
+            Ok(Some(vec!["x".into(), Value::Record(self.0.remove(0))]))
+        }
+
+        fn headers(&self) -> Vec<String> {
+            vec!["id".into(), "attrs".into()]
+        }
+
+        fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+            BTreeMap::new()
+        }
+    }
+
+    #[test]
+    fn test_ragged_reader() -> Result<(), EtError> {
+        let mut first = BTreeMap::new();
+        let _ = first.insert("a".into(), 1.into());
+        let mut second = BTreeMap::new();
+        let _ = second.insert("b".into(), 2.into());
+        let mut reader = RaggedReader::new(Box::new(SparseReader(vec![first, second])));
+        assert!(reader.headers().is_empty());
+
+        let fields = reader.next_fields()?.unwrap();
+        assert_eq!(fields[1], (String::from("attrs.a"), 1.into()));
+        assert_eq!(reader.headers(), ["id", "attrs.a"]);
+        assert_eq!(reader.new_headers(), ["id", "attrs.a"]);
+
+        let fields: Vec<(String, Value<'static>)> = reader
+            .next_fields()?
+            .unwrap()
+            .into_iter()
+            .map(|(k, v)| (k, v.into_owned()))
+            .collect();
+        assert_eq!(reader.headers(), ["id", "attrs.a", "attrs.b"]);
+        assert_eq!(reader.new_headers(), ["attrs.b"]);
+        assert_eq!(reader.fill(fields), vec!["x".into(), Value::Null, 2.into()]);
+        assert!(reader.next_fields()?.is_none());
+        assert!(reader.new_headers().is_empty());
+
+        // readers without nested records have the same fields as their headers
+        let (reader, _) = get_reader(&b">a\nAC\n"[..], None, None)?;
+        let mut reader = RaggedReader::new(reader);
+        let fields = reader.next_fields()?.unwrap();
+        assert_eq!(fields.len(), 2);
+        let fields = fields
+            .into_iter()
+            .map(|(k, v)| (k, v.into_owned()))
+            .collect();
+        assert_eq!(reader.fill(fields), vec!["a".into(), "AC".into()]);
+        assert_eq!(reader.headers(), ["id", "sequence"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_kmer_params() -> Result<(), EtError> {
+        let mut params = BTreeMap::new();
+        let _ = params.insert("kmer_size".into(), Value::Integer(2));
+        let _ = params.insert("kmer_counts".into(), Value::Boolean(true));
+        let _ = params.insert("canonical_kmers".into(), Value::Boolean(true));
+        let (mut reader, _) = get_reader(&b">a\nAATT\n"[..], Some("kmers"), Some(params))?;
+        assert_eq!(reader.headers(), ["kmer", "count"]);
+        assert_eq!(
+            reader.next_record()?.unwrap(),
+            ["AA".into(), Value::Integer(2)]
+        );
+        assert_eq!(
+            reader.next_record()?.unwrap(),
+            ["AT".into(), Value::Integer(1)]
+        );
+
+        let mut params = BTreeMap::new();
+        let _ = params.insert("kmer_counts".into(), Value::Integer(1));
+        assert!(get_reader(&b">a\nAATT\n"[..], Some("kmers"), Some(params)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_positions() -> Result<(), EtError> {
+        // errors parsing a record
+        let (mut reader, _) = get_reader(&b"@a\nA\n+\n!\nXb\nA\n+\n!\n"[..], None, None)?;
+        assert!(reader.next_record()?.is_some());
+        let err = reader.next_record().unwrap_err();
+        assert_eq!((err.byte(), err.record()), (Some(9), Some(1)));
+
+        // and errors reading the record once it's been parsed
+        let (mut reader, _) = get_reader(&b">a\nAC\n>\xFF\nGG\n"[..], None, None)?;
+        assert!(reader.next_record()?.is_some());
+        let err = reader.next_record().unwrap_err();
+        assert_eq!((err.byte(), err.record()), (Some(6), Some(1)));
+
+        assert_eq!(EtError::from("no context").byte(), None);
+        Ok(())
+    }
 }
@@ -3,11 +3,20 @@ use alloc::collections::BTreeMap;
 use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 use core::convert::TryFrom;
+#[cfg(feature = "serde")]
+use core::fmt;
+#[cfg(feature = "serde")]
+use core::marker::PhantomData;
 
-use chrono::{NaiveDate, NaiveDateTime};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+#[cfg(feature = "serde")]
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
 use serde::{Serialize, Serializer};
 
+use crate::capabilities::Capabilities;
+use crate::columns::Column;
 use crate::error::EtError;
+use crate::summary::SummaryFields;
 
 /// For a given state struct, the metadata associated with that struct.
 ///
@@ -19,6 +28,54 @@ pub trait StateMetadata {
         BTreeMap::new()
     }
 
+    /// Metadata about individual fields (e.g. instrument settings), keyed by their header
+    fn column_metadata(&self) -> BTreeMap<String, BTreeMap<String, Value<'_>>> {
+        BTreeMap::new()
+    }
+
+    /// Where the values for a `RunSummary` are stored
+    fn summary_fields(&self) -> SummaryFields {
+        SummaryFields::default()
+    }
+
+    /// The bounds on the number of records left to read (like `Iterator::size_hint`)
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, None)
+    }
+
+    /// What the reader for this state can do (e.g. if it has metadata)
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::empty()
+    }
+
+    /// Skip up to `n` of the next records without parsing them, if they're laid out so that's
+    /// possible (e.g. they're all the same size), updating the state as if they'd been read.
+    ///
+    /// Returns how many records were skipped and how many bytes of the data they take up (which
+    /// the reader then skips over, so this can be more than fits in memory); the default of
+    /// skipping none makes the reader parse and throw away the records instead.
+    fn skip_records(&mut self, _n: usize) -> (usize, u64) {
+        (0, 0)
+    }
+
+    /// Decode up to `n` of the next records straight out of `buf` (the data that hasn't been
+    /// parsed yet) into `columns`, if they're laid out so that's possible (e.g. rows of integers
+    /// that can be cast in one go), updating the state as if they'd been read.
+    ///
+    /// Returns how many records were decoded and how many bytes of `buf` they took up; the
+    /// default of decoding none makes the reader parse the records one at a time instead.
+    ///
+    /// # Errors
+    /// If the records can't be decoded, an error is returned.
+    fn read_columns(
+        &mut self,
+        _buf: &[u8],
+        _columns: &mut Vec<Column>,
+        _n: usize,
+    ) -> Result<(usize, usize), EtError> {
+        Ok((0, 0))
+    }
+
     /// The fields in the associated struct
     fn header(&self) -> Vec<&str>;
 }
@@ -29,6 +86,14 @@ impl StateMetadata for () {
     }
 }
 
+/// Records that can add their fields onto the end of anything that `Value`s can be pushed
+/// onto, e.g. a `Vec` or a `ColumnRow` (so the values can go straight into columns without a
+/// `Vec` for every record).
+pub trait PushFields<'r> {
+    /// Add the values of each field, in the same order as the reader's headers
+    fn push_fields<E: Extend<Value<'r>>>(self, fields: &mut E);
+}
+
 /// Autogenerates the conversion from a struct into the matching `Vec` of
 /// headers and the corresponding `Vec` of `Value`s to allow decomposing
 /// these raw structs into a common Record system that allows abstracting
@@ -41,6 +106,15 @@ macro_rules! impl_record {
                 ::alloc::vec![$(record.$key.into(),)*]
             }
         }
+
+        impl<'r> $crate::record::PushFields<'r> for $type {
+            fn push_fields<E: ::core::iter::Extend<$crate::record::Value<'r>>>(
+                self,
+                fields: &mut E,
+            ) {
+                fields.extend([$(self.$key.into(),)*]);
+            }
+        }
     };
     ($type:ty : $($key:ident)+ ) => { record!($($key),+) };
 }
@@ -59,6 +133,10 @@ pub enum Value<'a> {
     /// A date with associated time
     Datetime(NaiveDateTime),
     /// A floating point number
+    ///
+    /// NaN and infinities (which instruments often use for glitches or overflows) are kept as-is;
+    /// when serialized, serde decides how to represent them (e.g. `serde_json` writes `null`
+    /// because JSON has no way to represent them).
     Float(f64),
     /// An integer
     Integer(i64),
@@ -91,6 +169,24 @@ impl<'a> Value<'a> {
         }
         Err(EtError::from("Value was not a string"))
     }
+
+    /// Copy any data borrowed from the parser so the `Value` can outlive it (e.g. to send it
+    /// to another thread).
+    #[must_use]
+    pub fn into_owned(self) -> Value<'static> {
+        match self {
+            Value::Null => Value::Null,
+            Value::Boolean(b) => Value::Boolean(b),
+            Value::Datetime(d) => Value::Datetime(d),
+            Value::Float(f) => Value::Float(f),
+            Value::Integer(i) => Value::Integer(i),
+            Value::String(s) => Value::String(Cow::Owned(s.into_owned())),
+            Value::List(l) => Value::List(l.into_iter().map(Value::into_owned).collect()),
+            Value::Record(r) => {
+                Value::Record(r.into_iter().map(|(k, v)| (k, v.into_owned())).collect())
+            }
+        }
+    }
 }
 
 impl<'a, T: Into<Value<'a>>> From<Option<T>> for Value<'a> {
@@ -152,12 +248,8 @@ impl<'a> From<i64> for Value<'a> {
 
 impl<'a> From<u64> for Value<'a> {
     fn from(x: u64) -> Self {
-        if x.leading_zeros() == 0 {
-            // handle u64 -> i64 overflow by saturating; maybe someday this should be a try_from?
-            Value::Integer(i64::MAX)
-        } else {
-            Value::Integer(i64::try_from(x).unwrap())
-        }
+        // handle u64 -> i64 overflow by saturating; maybe someday this should be a try_from?
+        Value::Integer(i64::try_from(x).unwrap_or(i64::MAX))
     }
 }
 
@@ -208,7 +300,7 @@ impl<'a> From<NaiveDateTime> for Value<'a> {
 
 impl<'a> From<NaiveDate> for Value<'a> {
     fn from(d: NaiveDate) -> Self {
-        Value::Datetime(d.and_hms_opt(0, 0, 0).unwrap())
+        Value::Datetime(d.and_time(NaiveTime::MIN))
     }
 }
 
@@ -253,3 +345,286 @@ impl<'a> Serialize for Value<'a> {
         }
     }
 }
+
+/// Values are deserialized into the closest variant of the data (so e.g. dates, which are
+/// serialized as strings, come back as `Value::String`); strings are borrowed from the input
+/// when the deserializer allows it.
+#[cfg(feature = "serde")]
+impl<'de: 'a, 'a> Deserialize<'de> for Value<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ValueVisitor(PhantomData))
+    }
+}
+
+#[cfg(feature = "serde")]
+struct ValueVisitor<'a>(PhantomData<Value<'a>>);
+
+#[cfg(feature = "serde")]
+impl<'de: 'a, 'a> Visitor<'de> for ValueVisitor<'a> {
+    type Value = Value<'a>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a value")
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        Value::deserialize(deserializer)
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<Self::Value, E> {
+        Ok(Value::Boolean(v))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+        Ok(Value::Integer(v))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        i64::try_from(v)
+            .map(Value::Integer)
+            .map_err(|_| E::custom("integer is too large"))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+        Ok(Value::Float(v))
+    }
+
+    fn visit_borrowed_str<E: de::Error>(self, v: &'de str) -> Result<Self::Value, E> {
+        Ok(Value::String(Cow::Borrowed(v)))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(Value::String(Cow::Owned(v.to_string())))
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<Self::Value, E> {
+        Ok(Value::String(Cow::Owned(v)))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut list = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(value) = seq.next_element()? {
+            list.push(value);
+        }
+        Ok(Value::List(list))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut record = BTreeMap::new();
+        while let Some((key, value)) = map.next_entry()? {
+            drop(record.insert(key, value));
+        }
+        Ok(Value::Record(record))
+    }
+}
+
+/// (De)serializes byte fields (e.g. sequences and quality scores) as strings when they're
+/// valid UTF-8 so they're readable in e.g. JSON instead of being lists of numbers; they can be
+/// deserialized from either strings or bytes, but only ones borrowed from the input.
+#[cfg(feature = "serde")]
+pub(crate) mod text_bytes {
+    use core::fmt;
+    use core::marker::PhantomData;
+
+    use serde::de::{self, Deserialize, Deserializer, Visitor};
+    use serde::{Serialize, Serializer};
+
+    /// Bytes that are serialized as a string if possible
+    pub(crate) struct TextBytes<'a>(pub(crate) &'a [u8]);
+
+    impl<'a> Serialize for TextBytes<'a> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            match core::str::from_utf8(self.0) {
+                Ok(text) => serializer.serialize_str(text),
+                Err(_) => serializer.serialize_bytes(self.0),
+            }
+        }
+    }
+
+    impl<'de: 'a, 'a> Deserialize<'de> for TextBytes<'a> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct TextBytesVisitor<'a>(PhantomData<&'a [u8]>);
+
+            impl<'de: 'a, 'a> Visitor<'de> for TextBytesVisitor<'a> {
+                type Value = TextBytes<'a>;
+
+                fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    f.write_str("a borrowed string or bytes")
+                }
+
+                fn visit_borrowed_str<E: de::Error>(self, v: &'de str) -> Result<Self::Value, E> {
+                    Ok(TextBytes(v.as_bytes()))
+                }
+
+                fn visit_borrowed_bytes<E: de::Error>(
+                    self,
+                    v: &'de [u8],
+                ) -> Result<Self::Value, E> {
+                    Ok(TextBytes(v))
+                }
+            }
+
+            deserializer.deserialize_str(TextBytesVisitor(PhantomData))
+        }
+    }
+
+    pub(crate) fn serialize<T: AsRef<[u8]>, S: Serializer>(
+        bytes: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        TextBytes(bytes.as_ref()).serialize(serializer)
+    }
+
+    pub(crate) fn deserialize<'de: 'a, 'a, T: From<&'a [u8]>, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<T, D::Error> {
+        Ok(TextBytes::deserialize(deserializer)?.0.into())
+    }
+
+    /// The same, but for a list of optional byte fields
+    pub(crate) mod list {
+        use alloc::vec::Vec;
+
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        use super::TextBytes;
+
+        pub(crate) fn serialize<S: Serializer>(
+            list: &[Option<&[u8]>],
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            serializer.collect_seq(list.iter().map(|bytes| bytes.map(TextBytes)))
+        }
+
+        pub(crate) fn deserialize<'de: 'a, 'a, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Vec<Option<&'a [u8]>>, D::Error> {
+            let list = Vec::<Option<TextBytes<'a>>>::deserialize(deserializer)?;
+            Ok(list.into_iter().map(|bytes| bytes.map(|b| b.0)).collect())
+        }
+    }
+}
+
+/// The positions of a reader's headers, so fields can be looked up by name without searching
+/// through the headers for every record.
+///
+/// If a name is in the headers more than once, lookups find the first one.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct HeaderMap {
+    headers: Vec<String>,
+    positions: BTreeMap<String, usize>,
+}
+
+impl HeaderMap {
+    /// Build a `HeaderMap` for records with the fields in `headers`
+    #[must_use]
+    pub fn new(headers: Vec<String>) -> Self {
+        let mut positions = BTreeMap::new();
+        for (ix, header) in headers.iter().enumerate() {
+            let _ = positions.entry(header.clone()).or_insert(ix);
+        }
+        HeaderMap { headers, positions }
+    }
+
+    /// The position of the field called `name` in each record
+    #[must_use]
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.positions.get(name).copied()
+    }
+
+    /// The headers, in the order they're in each record
+    #[must_use]
+    pub fn headers(&self) -> &[String] {
+        &self.headers
+    }
+
+    /// A view of `values` (a record with these headers) that can look up fields by name
+    #[must_use]
+    pub fn view<'a, 'v>(&'a self, values: &'a [Value<'v>]) -> Record<'a, 'v> {
+        Record {
+            headers: self,
+            values,
+        }
+    }
+}
+
+/// A record's values along with the `HeaderMap` for them (see `HeaderMap::view`)
+#[derive(Clone, Copy, Debug)]
+pub struct Record<'a, 'v> {
+    headers: &'a HeaderMap,
+    values: &'a [Value<'v>],
+}
+
+impl<'a, 'v> Record<'a, 'v> {
+    /// The value of the field called `name`, or `None` if the record doesn't have one
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&'a Value<'v>> {
+        self.headers
+            .position(name)
+            .and_then(|ix| self.values.get(ix))
+    }
+
+    /// The values of every field, in header order
+    #[must_use]
+    pub fn values(&self) -> &'a [Value<'v>] {
+        self.values
+    }
+
+    /// The name and value of every field, in header order
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, &'a Value<'v>)> {
+        self.headers
+            .headers
+            .iter()
+            .map(String::as_str)
+            .zip(self.values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    #[test]
+    fn test_record_view() {
+        let headers = HeaderMap::new(vec!["time".into(), "mz".into(), "time".into()]);
+        let values = [Value::Float(1.5), Value::Float(100.1), Value::Null];
+        let record = headers.view(&values);
+        assert_eq!(record.get("mz"), Some(&Value::Float(100.1)));
+        assert_eq!(record.get("time"), Some(&Value::Float(1.5)));
+        assert_eq!(record.get("intensity"), None);
+        assert_eq!(record.iter().nth(1), Some(("mz", &Value::Float(100.1))));
+
+        // short records just don't have the missing fields
+        assert_eq!(headers.view(&values[..1]).get("mz"), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_value_serde() {
+        let json = r#"{"id":"a1","n":[1,-2.5,null,true]}"#;
+        let value: Value = serde_json::from_str(json).unwrap();
+        let mut record = BTreeMap::new();
+        let _ = record.insert("id".to_string(), "a1".into());
+        let _ = record.insert(
+            "n".to_string(),
+            Value::List(vec![
+                Value::Integer(1),
+                Value::Float(-2.5),
+                Value::Null,
+                Value::Boolean(true),
+            ]),
+        );
+        assert_eq!(value, Value::Record(record));
+        assert_eq!(serde_json::to_string(&value).unwrap(), json);
+    }
+}
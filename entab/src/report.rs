@@ -0,0 +1,251 @@
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+use crate::capabilities::Capabilities;
+use crate::error::EtError;
+use crate::readers::RecordReader;
+use crate::record::Value;
+use crate::summary::SummaryFields;
+
+/// The metadata key `ReportReader` adds its statistics under
+pub const REPORT_KEY: &str = "summary";
+
+/// Statistics about records as they're read or written: how many there were, how many nulls
+/// were in each column, and (if built with `std`) how long they took.
+///
+/// `ReportReader` keeps one of these for the records it returns, but it can also be used
+/// directly for records that go through other steps first (e.g. to only count the records
+/// that pass a validation).
+#[derive(Debug)]
+pub struct Report {
+    n_records: u64,
+    null_counts: Vec<u64>,
+    #[cfg(feature = "std")]
+    start: Instant,
+    /// How long reading every record took
+    #[cfg(feature = "std")]
+    elapsed: Duration,
+    /// Have all the records been read?
+    done: bool,
+}
+
+impl Default for Report {
+    fn default() -> Self {
+        Report {
+            n_records: 0,
+            null_counts: Vec::new(),
+            #[cfg(feature = "std")]
+            start: Instant::now(),
+            #[cfg(feature = "std")]
+            elapsed: Duration::ZERO,
+            done: false,
+        }
+    }
+}
+
+impl Report {
+    /// Start a new report (the time taken is counted from now)
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a record to the statistics
+    pub fn add(&mut self, record: &[Value]) {
+        self.n_records += 1;
+        if self.null_counts.len() < record.len() {
+            self.null_counts.resize(record.len(), 0);
+        }
+        for (count, value) in self.null_counts.iter_mut().zip(record) {
+            if matches!(value, Value::Null) {
+                *count += 1;
+            }
+        }
+    }
+
+    /// Mark that every record has been added (which stops the clock)
+    pub fn finish(&mut self) {
+        if !self.done {
+            self.done = true;
+            #[cfg(feature = "std")]
+            {
+                self.elapsed = self.start.elapsed();
+            }
+        }
+    }
+
+    /// Has `finish` been called?
+    #[must_use]
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// The statistics as a record with `n_records`, `bytes_read` (if it's known), `wall_time`
+    /// (in seconds, if built with `std`), and `null_counts` (keyed by `headers`)
+    #[must_use]
+    pub fn to_value(&self, headers: Vec<String>, bytes_read: Option<u64>) -> Value<'static> {
+        let mut report = BTreeMap::new();
+        drop(report.insert(
+            "n_records".to_string(),
+            Value::Integer(self.n_records as i64),
+        ));
+        if let Some(bytes_read) = bytes_read {
+            drop(report.insert("bytes_read".to_string(), Value::Integer(bytes_read as i64)));
+        }
+        #[cfg(feature = "std")]
+        {
+            let elapsed = if self.done {
+                self.elapsed
+            } else {
+                self.start.elapsed()
+            };
+            drop(report.insert("wall_time".to_string(), Value::Float(elapsed.as_secs_f64())));
+        }
+        let null_counts = headers
+            .into_iter()
+            .enumerate()
+            .map(|(ix, header)| {
+                let count = self.null_counts.get(ix).copied().unwrap_or(0);
+                (header, Value::Integer(count as i64))
+            })
+            .collect();
+        drop(report.insert("null_counts".to_string(), Value::Record(null_counts)));
+        Value::Record(report)
+    }
+}
+
+/// Wraps a `RecordReader` to keep statistics about the records it returns as they're read
+/// (e.g. for checking a conversion without a second pass over the file).
+///
+/// Once every record has been read, a `summary` record is added to the metadata with
+/// `n_records`, `bytes_read` (if the reader knows it), `wall_time` (in seconds, if built with
+/// `std`), and `null_counts` (the number of nulls in each column).
+#[derive(Debug)]
+pub struct ReportReader<'r> {
+    reader: Box<dyn RecordReader + 'r>,
+    report: Report,
+}
+
+impl<'r> ReportReader<'r> {
+    /// Create a new `ReportReader` around a `RecordReader`
+    #[must_use]
+    pub fn new(reader: Box<dyn RecordReader + 'r>) -> Self {
+        ReportReader {
+            reader,
+            report: Report::new(),
+        }
+    }
+
+    /// The statistics about the records read so far (the same record that's added to the
+    /// metadata once they've all been read)
+    #[must_use]
+    pub fn report(&self) -> Value<'static> {
+        self.report
+            .to_value(self.reader.headers(), self.reader.bytes_read())
+    }
+}
+
+impl<'r> RecordReader for ReportReader<'r> {
+    fn next_record(&mut self) -> Result<Option<Vec<Value<'_>>>, EtError> {
+        let record = match self.reader.next_record()? {
+            Some(record) => record,
+            None => {
+                self.report.finish();
+                return Ok(None);
+            }
+        };
+        self.report.add(&record);
+        Ok(Some(record))
+    }
+
+    /// Skipped records aren't returned so they aren't counted in the report.
+    fn skip_records(&mut self, n: usize) -> Result<usize, EtError> {
+        let n_skipped = self.reader.skip_records(n)?;
+        if n_skipped < n {
+            self.report.finish();
+        }
+        Ok(n_skipped)
+    }
+
+    fn bytes_read(&self) -> Option<u64> {
+        self.reader.bytes_read()
+    }
+
+    fn headers(&self) -> Vec<String> {
+        self.reader.headers()
+    }
+
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        let mut metadata = self.reader.metadata();
+        if self.report.is_done() {
+            drop(metadata.insert(REPORT_KEY.to_string(), self.report()));
+        }
+        metadata
+    }
+
+    fn column_metadata(&self) -> BTreeMap<String, BTreeMap<String, Value<'_>>> {
+        self.reader.column_metadata()
+    }
+
+    fn summary_fields(&self) -> SummaryFields {
+        self.reader.summary_fields()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.reader.size_hint()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.reader.capabilities()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::readers::get_reader;
+
+    #[test]
+    fn test_report() -> Result<(), EtError> {
+        let data: &[u8] = br#"<gpx><trk><trkseg>
+<trkpt lat="1" lon="2"/><trkpt lat="1.5" lon="2"><ele>3</ele></trkpt><trkpt lat="2" lon="2"/>
+</trkseg></trk></gpx>"#;
+        let (reader, _) = get_reader(data, Some("gpx"), None)?;
+        let mut reader = ReportReader::new(reader);
+        assert!(reader.next_record()?.is_some());
+        // the summary isn't added until the whole file's been read
+        assert!(!reader.metadata().contains_key(REPORT_KEY));
+
+        let metadata = reader.finalize()?;
+        let report = match &metadata[REPORT_KEY] {
+            Value::Record(report) => report.clone(),
+            x => panic!("The summary should be a record, not {:?}", x),
+        };
+        assert_eq!(report["n_records"], Value::Integer(3));
+        // the reader stops parsing after the last point, before the closing tags
+        assert!(
+            matches!(report["bytes_read"], Value::Integer(b) if b > 100 && b <= data.len() as i64)
+        );
+        #[cfg(feature = "std")]
+        assert!(matches!(report["wall_time"], Value::Float(t) if t >= 0.));
+        #[cfg(not(feature = "std"))]
+        assert!(!report.contains_key("wall_time"));
+        let null_counts = match &report["null_counts"] {
+            Value::Record(counts) => counts.clone(),
+            x => panic!("The null counts should be a record, not {:?}", x),
+        };
+        assert_eq!(null_counts["latitude"], Value::Integer(0));
+        assert_eq!(null_counts["elevation"], Value::Integer(2));
+
+        // the report can also be asked for through a param
+        let mut params = BTreeMap::new();
+        drop(params.insert("report".to_string(), Value::Boolean(true)));
+        let (mut reader, _) = get_reader(data, Some("gpx"), Some(params))?;
+        assert!(reader.finalize()?.contains_key(REPORT_KEY));
+        Ok(())
+    }
+}
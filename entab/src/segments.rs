@@ -0,0 +1,86 @@
+use alloc::format;
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use std::io::Read;
+
+use crate::buffer::ReadBuffer;
+use crate::compression::decompress;
+use crate::parsers::flow::fcs_segment;
+use crate::parsers::png::png_chunks;
+use crate::parsers::sam::bam_header;
+use crate::EtError;
+
+/// Extract the raw bytes of one of the segments embedded in a file (e.g. the ANALYSIS segment
+/// of an FCS file, a chunk of a PNG, or the text header of a BAM file) without parsing them,
+/// for debugging vendor quirks or archiving the original metadata next to a converted table.
+///
+/// The parser is detected from the data (after decompressing it) unless one is given.
+///
+/// # Errors
+/// If the data can't be read, the parser doesn't know about any segments, or the file doesn't
+/// have the segment, an error is returned.
+pub fn extract_segment<'r, B>(
+    data: B,
+    parser: Option<&str>,
+    segment: &str,
+) -> Result<Vec<u8>, EtError>
+where
+    B: TryInto<ReadBuffer<'r>>,
+    EtError: From<<B as TryInto<ReadBuffer<'r>>>::Error>,
+{
+    let (mut rb, _): (ReadBuffer<'r>, _) = decompress(data)?;
+    let parser_name = rb.sniff_filetype()?.to_parser_name(parser)?;
+    let mut raw = Vec::new();
+    let _ = rb.into_box_read().read_to_end(&mut raw)?;
+    match parser_name {
+        "bam" if segment == "header" => Ok(bam_header(&raw)?.to_vec()),
+        "bam" => Err(format!("Unknown BAM segment {:?} (try header)", segment).into()),
+        "flow" => Ok(fcs_segment(&raw, segment)?.to_vec()),
+        "png" => png_chunks(&raw, segment),
+        _ => Err(format!("Can't extract segments from {} files", parser_name).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_fcs_segments() -> Result<(), EtError> {
+        let data: &[u8] =
+            include_bytes!("../tests/data/HTS_BD_LSR_II_Mixed_Specimen_001_D6_D06.fcs");
+        let header = extract_segment(data, None, "header")?;
+        assert_eq!(header.len(), 58);
+        assert!(header.starts_with(b"FCS3.0"));
+        let text = extract_segment(data, None, "text")?;
+        assert!(text.windows(5).any(|w| w == b"$DATE"));
+        assert!(extract_segment(data, None, "nonsense").is_err());
+        assert!(extract_segment(data, None, "analysis").is_err());
+
+        // FCS 2.0 files only have the ANALYSIS offsets in the HEADER
+        let mut data = data.to_vec();
+        data[42..58].copy_from_slice(b"    2360    2364");
+        let analysis = extract_segment(&data[..], None, "analysis")?;
+        assert_eq!(analysis, &data[2360..2365]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_extract_bam_header() -> Result<(), EtError> {
+        let data: &[u8] = include_bytes!("../tests/data/test.bam");
+        let header = extract_segment(data, None, "header")?;
+        assert!(header.starts_with(b"@"));
+        assert_ne!(header.last(), Some(&0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_extract_png_chunks() -> Result<(), EtError> {
+        let data: &[u8] = include_bytes!("../tests/data/bmp_24.png");
+        let ihdr = extract_segment(data, Some("png"), "IHDR")?;
+        assert_eq!(ihdr.len(), 13);
+        assert!(extract_segment(data, None, "abcd").is_err());
+        assert!(extract_segment(&b">seq\nACGT\n"[..], None, "header").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,186 @@
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::error::EtError;
+use crate::readers::RecordReader;
+use crate::record::Value;
+
+/// The names of the columns and metadata keys a reader stores the values for a `RunSummary` in.
+///
+/// Most readers use the defaults (`time` and `mz` columns and `sample`, `operator`, and `method`
+/// metadata); readers that store them elsewhere override `summary_fields`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SummaryFields {
+    /// The column with the time of each record
+    pub time: &'static str,
+    /// The column with the mass-to-charge ratio of each record
+    pub mz: &'static str,
+    /// The metadata key with the sample name
+    pub sample: &'static str,
+    /// The metadata key with the operator's name
+    pub operator: &'static str,
+    /// The metadata key with the method name
+    pub method: &'static str,
+}
+
+impl Default for SummaryFields {
+    fn default() -> Self {
+        SummaryFields {
+            time: "time",
+            mz: "mz",
+            sample: "sample",
+            operator: "operator",
+            method: "method",
+        }
+    }
+}
+
+/// A single-row overview of a file (e.g. for cataloging a directory of instrument runs)
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RunSummary {
+    /// The name of the parser that read the file
+    pub parser: String,
+    /// The number of records in the file
+    pub n_records: u64,
+    /// The smallest and largest times in the file
+    pub time_range: Option<(f64, f64)>,
+    /// The smallest and largest m/z values in the file (for mass spec data)
+    pub mz_range: Option<(f64, f64)>,
+    /// The sample name from the metadata
+    pub sample: Option<String>,
+    /// The operator's name from the metadata
+    pub operator: Option<String>,
+    /// The method name from the metadata
+    pub method: Option<String>,
+}
+
+/// Widen `range` to include `value` (if it's a number)
+fn update_range(range: &mut Option<(f64, f64)>, value: &Value) {
+    let value = match value {
+        Value::Float(f) if !f.is_nan() => *f,
+        Value::Integer(i) => *i as f64,
+        _ => return,
+    };
+    *range = Some(match *range {
+        Some((min, max)) => (min.min(value), max.max(value)),
+        None => (value, value),
+    });
+}
+
+impl RunSummary {
+    /// The names of the fields in `to_record`
+    pub const HEADERS: &'static [&'static str] = &[
+        "parser",
+        "n_records",
+        "time_min",
+        "time_max",
+        "mz_min",
+        "mz_max",
+        "sample",
+        "operator",
+        "method",
+    ];
+
+    /// Summarize the file `reader` is reading by reading all of its records.
+    ///
+    /// # Errors
+    /// If any of the records can't be read, an error is returned.
+    pub fn from_reader(reader: &mut dyn RecordReader, parser: &str) -> Result<Self, EtError> {
+        let fields = reader.summary_fields();
+        let metadata = reader.metadata();
+        let text = |key: &str| match metadata.get(key) {
+            Some(Value::String(s)) if !s.trim().is_empty() => Some(String::from(s.trim())),
+            _ => None,
+        };
+        let mut summary = RunSummary {
+            parser: parser.into(),
+            sample: text(fields.sample),
+            operator: text(fields.operator),
+            method: text(fields.method),
+            ..RunSummary::default()
+        };
+        drop(metadata);
+
+        let headers = reader.headers();
+        let time_col = headers.iter().position(|h| h == fields.time);
+        let mz_col = headers.iter().position(|h| h == fields.mz);
+        while let Some(record) = reader.next_record()? {
+            summary.n_records += 1;
+            if let Some(value) = time_col.and_then(|ix| record.get(ix)) {
+                update_range(&mut summary.time_range, value);
+            }
+            if let Some(value) = mz_col.and_then(|ix| record.get(ix)) {
+                update_range(&mut summary.mz_range, value);
+            }
+        }
+        Ok(summary)
+    }
+
+    /// Convert the summary into a record with the fields in `HEADERS`
+    #[must_use]
+    pub fn to_record(&self) -> Vec<Value<'_>> {
+        let (time_min, time_max) = self
+            .time_range
+            .map_or((None, None), |(a, b)| (Some(a), Some(b)));
+        let (mz_min, mz_max) = self
+            .mz_range
+            .map_or((None, None), |(a, b)| (Some(a), Some(b)));
+        vec![
+            self.parser.as_str().into(),
+            self.n_records.into(),
+            time_min.into(),
+            time_max.into(),
+            mz_min.into(),
+            mz_max.into(),
+            self.sample.as_deref().into(),
+            self.operator.as_deref().into(),
+            self.method.as_deref().into(),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::readers::get_reader;
+
+    #[test]
+    fn test_ms_summary() -> Result<(), EtError> {
+        let data: &[u8] = include_bytes!("../tests/data/carotenoid_extract.d/MSD1.MS");
+        let (mut reader, parser) = get_reader(data, None, None)?;
+        let summary = RunSummary::from_reader(&mut *reader, parser)?;
+        assert_eq!(summary.parser, "chemstation_ms");
+        assert_eq!(summary.n_records, 95471);
+        let (time_min, time_max) = summary.time_range.unwrap();
+        assert!(time_min < time_max);
+        let (mz_min, mz_max) = summary.mz_range.unwrap();
+        assert!(mz_min < mz_max);
+        assert_eq!(summary.to_record().len(), RunSummary::HEADERS.len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_fid_summary() -> Result<(), EtError> {
+        let data: &[u8] = include_bytes!("../tests/data/test_fid.ch");
+        let (mut reader, parser) = get_reader(data, None, None)?;
+        let summary = RunSummary::from_reader(&mut *reader, parser)?;
+        assert!(summary.time_range.is_some());
+        assert!(summary.mz_range.is_none());
+        assert_eq!(summary.sample.as_deref(), Some("5970 mix 10nG"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_fcs_summary() -> Result<(), EtError> {
+        let data: &[u8] =
+            include_bytes!("../tests/data/HTS_BD_LSR_II_Mixed_Specimen_001_D6_D06.fcs");
+        let (mut reader, parser) = get_reader(data, None, None)?;
+        let summary = RunSummary::from_reader(&mut *reader, parser)?;
+        assert_eq!(summary.parser, "flow");
+        assert!(summary.n_records > 0);
+        assert!(summary.time_range.is_some());
+        assert_eq!(summary.sample.as_deref(), Some("Specimen_001"));
+        Ok(())
+    }
+}
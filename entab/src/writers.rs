@@ -0,0 +1,347 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write as _;
+use std::io::Write;
+
+use memchr::memchr3;
+
+use crate::error::EtError;
+use crate::readers::RecordReader;
+use crate::record::Value;
+
+#[cfg(feature = "arrow")]
+mod arrow;
+mod sequence;
+
+#[cfg(feature = "arrow")]
+pub use self::arrow::{cast_batch, empty_batch, ArrowRecordBatchWriter, DEFAULT_BATCH_SIZE};
+pub use self::sequence::{LineWrap, SequenceFormat, SequenceWriter};
+
+/// The trait for writing "generic" records out (the counterpart of `RecordReader`)
+///
+/// Writers are given the headers once, then each record in turn, and then `finish` is called
+/// once all of the records have been written.
+pub trait RecordWriter: ::core::fmt::Debug {
+    /// Write out the names of the fields in each record; this has to be called once before any
+    /// records are written.
+    ///
+    /// # Errors
+    /// If the headers can't be written, an error is returned.
+    fn write_headers(&mut self, headers: &[String]) -> Result<(), EtError>;
+
+    /// Write out a record (with the same fields, in the same order, as the headers).
+    ///
+    /// # Errors
+    /// If a value can't be represented in the output or can't be written, an error is returned.
+    fn write_record(&mut self, record: &[Value]) -> Result<(), EtError>;
+
+    /// Write out anything that's still buffered once all the records have been written.
+    ///
+    /// # Errors
+    /// If the output can't be written, an error is returned.
+    fn finish(&mut self) -> Result<(), EtError>;
+}
+
+/// Write the headers and every record from `reader` into `writer` (and then finish it).
+/// Returns the number of records written.
+///
+/// # Errors
+/// If a record can't be read or written, an error is returned.
+pub fn copy_records(
+    reader: &mut dyn RecordReader,
+    writer: &mut dyn RecordWriter,
+) -> Result<usize, EtError> {
+    writer.write_headers(&reader.headers())?;
+    let mut n_records = 0;
+    while let Some(record) = reader.next_record()? {
+        writer.write_record(&record)?;
+        n_records += 1;
+    }
+    writer.finish()?;
+    Ok(n_records)
+}
+
+/// Writes records out as lines of delimited text: TSVs with `TsvWriter::new` and CSVs with
+/// `TsvWriter::csv`.
+///
+/// Fields that contain the delimiter, the quote character, or a line break are quoted (with any
+/// quotes inside them doubled) so they can be read back in by the TSV/CSV parsers. Lists are
+/// written with their items separated by commas and nested records are written out as JSON
+/// (quoted like any other field).
+#[derive(Debug)]
+pub struct TsvWriter<W: Write> {
+    writer: W,
+    delim: u8,
+    quote: u8,
+    null_value: String,
+    nan_value: String,
+    inf_value: String,
+    n_fields: Option<usize>,
+    /// The line currently being built, so each record is written out with one call
+    line: Vec<u8>,
+    /// Scratch space for formatting each field before it's quoted
+    field: String,
+}
+
+impl<W: Write> TsvWriter<W> {
+    /// Create a new `TsvWriter` that writes tab-separated lines into `writer`
+    pub fn new(writer: W) -> Self {
+        TsvWriter {
+            writer,
+            delim: b'\t',
+            quote: b'"',
+            null_value: "null".into(),
+            nan_value: "NaN".into(),
+            inf_value: "inf".into(),
+            n_fields: None,
+            line: Vec::new(),
+            field: String::new(),
+        }
+    }
+
+    /// Create a new `TsvWriter` that writes comma-separated lines into `writer` (with nulls
+    /// written as empty fields like most CSV readers expect)
+    pub fn csv(writer: W) -> Self {
+        TsvWriter::new(writer).delim(b',').null_value("")
+    }
+
+    /// Separate fields with this character
+    #[must_use]
+    pub fn delim(mut self, c: u8) -> Self {
+        self.delim = c;
+        self
+    }
+
+    /// Quote fields with this character
+    #[must_use]
+    pub fn quote(mut self, c: u8) -> Self {
+        self.quote = c;
+        self
+    }
+
+    /// Write null values as this (`null` by default)
+    #[must_use]
+    pub fn null_value(mut self, value: &str) -> Self {
+        self.null_value = value.into();
+        self
+    }
+
+    /// Write NaNs as this (`NaN` by default)
+    #[must_use]
+    pub fn nan_value(mut self, value: &str) -> Self {
+        self.nan_value = value.into();
+        self
+    }
+
+    /// Write infinities as this (`inf` by default; negative infinities have a `-` in front)
+    #[must_use]
+    pub fn inf_value(mut self, value: &str) -> Self {
+        self.inf_value = value.into();
+        self
+    }
+
+    /// Get a mutable reference to the underlying writer
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// Stop writing and return the underlying writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Format `value` the way it would be written out (before it's quoted)
+    ///
+    /// # Errors
+    /// If a nested record can't be converted into JSON, an error is returned.
+    pub fn format_field(&mut self, value: &Value) -> Result<&str, EtError> {
+        self.field.clear();
+        self.format_value(value)?;
+        Ok(&self.field)
+    }
+
+    /// Format `value` onto the end of the current field
+    fn format_value(&mut self, value: &Value) -> Result<(), EtError> {
+        match value {
+            Value::Null => self.field.push_str(&self.null_value),
+            Value::Boolean(b) => self.field.push_str(if *b { "true" } else { "false" }),
+            Value::Datetime(d) => drop(write!(self.field, "{:?}", d)),
+            Value::Float(f) if f.is_nan() => self.field.push_str(&self.nan_value),
+            Value::Float(f) if f.is_infinite() => {
+                if f.is_sign_negative() {
+                    self.field.push('-');
+                }
+                self.field.push_str(&self.inf_value);
+            }
+            Value::Float(f) => drop(write!(self.field, "{}", f)),
+            Value::Integer(i) => drop(write!(self.field, "{}", i)),
+            Value::String(s) => self.field.push_str(s),
+            Value::List(items) => {
+                for (ix, item) in items.iter().enumerate() {
+                    if ix > 0 {
+                        self.field.push(',');
+                    }
+                    self.format_value(item)?;
+                }
+            }
+            // there's no TSV convention for nested fields, so they're written out as JSON
+            Value::Record(_) => self.field.push_str(
+                &serde_json::to_string(value)
+                    .map_err(|e| EtError::from(format!("Could not write record: {}", e)))?,
+            ),
+        }
+        Ok(())
+    }
+
+    /// Add `field` to the end of the current line, quoting it if needed
+    fn push_field(&mut self, field: &str) {
+        let bytes = field.as_bytes();
+        let needs_quotes =
+            memchr3(self.delim, self.quote, b'\n', bytes).is_some() || bytes.contains(&b'\r');
+        if !needs_quotes {
+            self.line.extend_from_slice(bytes);
+            return;
+        }
+        self.line.push(self.quote);
+        for &b in bytes {
+            if b == self.quote {
+                self.line.push(self.quote);
+            }
+            self.line.push(b);
+        }
+        self.line.push(self.quote);
+    }
+
+    /// Write out the current line
+    fn write_line(&mut self) -> Result<(), EtError> {
+        self.line.push(b'\n');
+        self.writer.write_all(&self.line)?;
+        self.line.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write + ::core::fmt::Debug> RecordWriter for TsvWriter<W> {
+    fn write_headers(&mut self, headers: &[String]) -> Result<(), EtError> {
+        if self.n_fields.is_some() {
+            return Err("The headers have already been written".into());
+        }
+        for (ix, header) in headers.iter().enumerate() {
+            if ix > 0 {
+                self.line.push(self.delim);
+            }
+            self.push_field(header);
+        }
+        self.n_fields = Some(headers.len());
+        self.write_line()
+    }
+
+    fn write_record(&mut self, record: &[Value]) -> Result<(), EtError> {
+        match self.n_fields {
+            None => return Err("The headers must be written before any records".into()),
+            Some(n) if n != record.len() => {
+                return Err(format!(
+                    "Record has {} fields, but there are {} headers",
+                    record.len(),
+                    n
+                )
+                .into());
+            }
+            Some(_) => {}
+        }
+        for (ix, value) in record.iter().enumerate() {
+            if ix > 0 {
+                self.line.push(self.delim);
+            }
+            self.field.clear();
+            if let Err(e) = self.format_value(value) {
+                self.line.clear();
+                return Err(e);
+            }
+            let field = core::mem::take(&mut self.field);
+            self.push_field(&field);
+            self.field = field;
+        }
+        self.write_line()
+    }
+
+    fn finish(&mut self) -> Result<(), EtError> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::readers::get_reader;
+    use alloc::collections::BTreeMap;
+    use alloc::vec;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_tsv_writer() -> Result<(), EtError> {
+        let mut writer = TsvWriter::new(Vec::new());
+        assert!(writer.write_record(&[Value::Null]).is_err());
+        writer.write_headers(&["a".into(), "b\tc".into()])?;
+        writer.write_record(&["x\ty".into(), Value::Null])?;
+        writer.write_record(&[
+            f64::NEG_INFINITY.into(),
+            vec![Value::Integer(1), Value::from(2.5)].into(),
+        ])?;
+        writer.write_record(&[
+            NaiveDate::from_ymd_opt(2001, 2, 3)
+                .unwrap()
+                .and_hms_opt(4, 5, 6)
+                .unwrap()
+                .into(),
+            Value::Boolean(true),
+        ])?;
+        let mut nested = BTreeMap::new();
+        let _ = nested.insert("n".into(), 1.into());
+        writer.write_record(&["r".into(), Value::Record(nested)])?;
+        assert!(writer.write_record(&[Value::Null]).is_err());
+        writer.finish()?;
+        assert_eq!(
+            writer.into_inner(),
+            &b"a\t\"b\tc\"\n\"x\ty\"\tnull\n-inf\t1,2.5\n2001-02-03T04:05:06\ttrue\nr\t\"{\"\"n\"\":1}\"\n"
+                [..]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_non_finite_values() -> Result<(), EtError> {
+        let record = [
+            f64::NAN.into(),
+            f64::INFINITY.into(),
+            f64::NEG_INFINITY.into(),
+        ];
+        let mut writer = TsvWriter::new(Vec::new())
+            .nan_value("")
+            .inf_value("Infinity");
+        writer.write_headers(&["a".into(), "b".into(), "c".into()])?;
+        writer.write_record(&record)?;
+        assert_eq!(writer.into_inner(), b"a\tb\tc\n\tInfinity\t-Infinity\n");
+        assert_eq!(TsvWriter::new(Vec::new()).format_field(&record[2])?, "-inf");
+        Ok(())
+    }
+
+    #[test]
+    fn test_csv_round_trip() -> Result<(), EtError> {
+        let mut writer = TsvWriter::csv(Vec::new());
+        writer.write_headers(&["id".into(), "note".into()])?;
+        writer.write_record(&["a".into(), "says \"hi\", twice".into()])?;
+        writer.write_record(&["b".into(), Value::Null])?;
+        writer.finish()?;
+        let out = writer.into_inner();
+        assert_eq!(out, b"id,note\na,\"says \"\"hi\"\", twice\"\nb,\n");
+
+        let (mut reader, _) = get_reader(&out[..], Some("csv"), None)?;
+        let mut round_trip = TsvWriter::csv(Vec::new());
+        assert_eq!(copy_records(&mut *reader, &mut round_trip)?, 2);
+        assert_eq!(round_trip.into_inner(), out);
+        Ok(())
+    }
+}
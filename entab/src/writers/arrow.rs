@@ -0,0 +1,551 @@
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use arrow_array::builder::{
+    BooleanBuilder, Float64Builder, Int64Builder, NullBufferBuilder, OffsetBufferBuilder,
+    StringBuilder, TimestampMicrosecondBuilder,
+};
+use arrow_array::{new_empty_array, ArrayRef, ListArray, RecordBatch};
+use arrow_cast::cast;
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+
+use crate::error::EtError;
+use crate::record::Value;
+use crate::writers::RecordWriter;
+
+/// The number of records in each `RecordBatch` unless `batch_size` is set
+pub const DEFAULT_BATCH_SIZE: usize = 8192;
+
+/// A short name for the type of `value` (for error messages)
+fn value_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Boolean(_) => "boolean",
+        Value::Datetime(_) => "datetime",
+        Value::Float(_) => "float",
+        Value::Integer(_) => "integer",
+        Value::String(_) => "string",
+        Value::List(_) => "list",
+        Value::Record(_) => "record",
+    }
+}
+
+/// The Arrow type for `value` (`DataType::Null` for nulls and empty lists, whose types
+/// have to come from the other values in the column)
+fn infer_type(value: &Value) -> Result<DataType, EtError> {
+    Ok(match value {
+        Value::Null => DataType::Null,
+        Value::Boolean(_) => DataType::Boolean,
+        Value::Datetime(_) => DataType::Timestamp(TimeUnit::Microsecond, None),
+        Value::Float(_) => DataType::Float64,
+        Value::Integer(_) => DataType::Int64,
+        Value::String(_) => DataType::Utf8,
+        Value::List(items) => {
+            let mut item_type = DataType::Null;
+            for item in items {
+                item_type = combine_types(item_type, infer_type(item)?)?;
+            }
+            DataType::List(Arc::new(Field::new("item", item_type, true)))
+        }
+        Value::Record(_) => return Err("Records can not be written as a field".into()),
+    })
+}
+
+/// Combine two types inferred for the same column
+fn combine_types(a: DataType, b: DataType) -> Result<DataType, EtError> {
+    Ok(match (a, b) {
+        (DataType::Null, other) | (other, DataType::Null) => other,
+        (DataType::List(a), DataType::List(b)) => {
+            let item_type = combine_types(a.data_type().clone(), b.data_type().clone())?;
+            DataType::List(Arc::new(Field::new("item", item_type, true)))
+        }
+        (DataType::List(_), _) | (_, DataType::List(_)) => {
+            return Err("Lists can not be written in a column with other values".into());
+        }
+        (DataType::Int64, DataType::Float64) | (DataType::Float64, DataType::Int64) => {
+            DataType::Float64
+        }
+        (a, b) if a == b => a,
+        // columns with a mix of types are written as text
+        _ => DataType::Utf8,
+    })
+}
+
+/// Columns (or list items) with only nulls are written as (null) strings
+fn finalize_type(dtype: DataType) -> DataType {
+    match dtype {
+        DataType::Null => DataType::Utf8,
+        DataType::List(item) => DataType::List(Arc::new(Field::new(
+            "item",
+            finalize_type(item.data_type().clone()),
+            true,
+        ))),
+        dtype => dtype,
+    }
+}
+
+/// The text for `value` in a column of mixed types
+fn value_text(value: &Value) -> Result<String, EtError> {
+    Ok(match value {
+        Value::Boolean(b) => b.to_string(),
+        Value::Datetime(d) => format!("{:?}", d),
+        Value::Float(f) => f.to_string(),
+        Value::Integer(i) => i.to_string(),
+        Value::String(s) => s.to_string(),
+        _ => {
+            return Err(format!("A {} can not be written as text", value_type(value)).into());
+        }
+    })
+}
+
+/// Build an array of type `dtype` out of `values`.
+fn build_array<'v, I>(dtype: &DataType, values: I, len: usize) -> Result<ArrayRef, EtError>
+where
+    I: Iterator<Item = &'v Value<'v>>,
+{
+    let mismatch = |value: &Value| -> EtError {
+        format!(
+            "A {} can not be written in a {} column",
+            value_type(value),
+            dtype
+        )
+        .into()
+    };
+    Ok(match dtype {
+        DataType::Boolean => {
+            let mut builder = BooleanBuilder::with_capacity(len);
+            for value in values {
+                match value {
+                    Value::Null => builder.append_null(),
+                    Value::Boolean(b) => builder.append_value(*b),
+                    _ => return Err(mismatch(value)),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        DataType::Int64 => {
+            let mut builder = Int64Builder::with_capacity(len);
+            for value in values {
+                match value {
+                    Value::Null => builder.append_null(),
+                    Value::Integer(i) => builder.append_value(*i),
+                    _ => return Err(mismatch(value)),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        DataType::Float64 => {
+            let mut builder = Float64Builder::with_capacity(len);
+            for value in values {
+                match value {
+                    Value::Null => builder.append_null(),
+                    Value::Float(f) => builder.append_value(*f),
+                    Value::Integer(i) => builder.append_value(*i as f64),
+                    _ => return Err(mismatch(value)),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        DataType::Timestamp(TimeUnit::Microsecond, None) => {
+            let mut builder = TimestampMicrosecondBuilder::with_capacity(len);
+            for value in values {
+                match value {
+                    Value::Null => builder.append_null(),
+                    Value::Datetime(d) => builder.append_value(d.and_utc().timestamp_micros()),
+                    _ => return Err(mismatch(value)),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        DataType::Utf8 => {
+            let mut builder = StringBuilder::with_capacity(len, len * 8);
+            for value in values {
+                match value {
+                    Value::Null => builder.append_null(),
+                    Value::String(s) => builder.append_value(s),
+                    _ => builder.append_value(value_text(value)?),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        DataType::List(item) => {
+            let mut offsets = OffsetBufferBuilder::new(len);
+            let mut nulls = NullBufferBuilder::new(len);
+            let mut items = Vec::new();
+            for value in values {
+                match value {
+                    Value::Null => {
+                        offsets.push_length(0);
+                        nulls.append_null();
+                    }
+                    Value::List(list) => {
+                        offsets.push_length(list.len());
+                        nulls.append_non_null();
+                        items.extend(list);
+                    }
+                    _ => return Err(mismatch(value)),
+                }
+            }
+            let n_items = items.len();
+            let item_array = build_array(item.data_type(), items.into_iter(), n_items)?;
+            Arc::new(ListArray::try_new(
+                Arc::clone(item),
+                offsets.finish(),
+                item_array,
+                nulls.finish(),
+            )?)
+        }
+        _ => return Err(format!("{} columns can not be written", dtype).into()),
+    })
+}
+
+/// Builds Arrow `RecordBatch`es out of records (e.g. to write them into Parquet or Arrow IPC
+/// files or hand them to a dataframe library).
+///
+/// Records are collected until there are `batch_size` of them and then converted into a batch
+/// that can be taken out with `next_batch`. The type of each column is decided from its values:
+/// integers, floats (or a mix of integers and floats), booleans, datetimes (as microsecond
+/// timestamps), and lists of these keep their types, while columns with other mixes of types
+/// (or with only nulls) are written as strings. If a later batch needs a wider type for a
+/// column (e.g. a float in a column of integers), the schema is widened and the batches that
+/// haven't been taken yet are cast to it; batches that were already taken out can be brought up
+/// to the final `schema` with `cast_batch`.
+#[derive(Debug)]
+pub struct ArrowRecordBatchWriter {
+    batch_size: usize,
+    headers: Option<Vec<String>>,
+    types: Vec<DataType>,
+    schema: Option<SchemaRef>,
+    records: Vec<Vec<Value<'static>>>,
+    batches: VecDeque<RecordBatch>,
+}
+
+impl Default for ArrowRecordBatchWriter {
+    fn default() -> Self {
+        ArrowRecordBatchWriter {
+            batch_size: DEFAULT_BATCH_SIZE,
+            headers: None,
+            types: Vec::new(),
+            schema: None,
+            records: Vec::new(),
+            batches: VecDeque::new(),
+        }
+    }
+}
+
+impl ArrowRecordBatchWriter {
+    /// Create a new writer for records
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set how many records are put into each batch
+    #[must_use]
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// The schema of the batches, once it's been decided (i.e. after the first batch has been
+    /// built or the writer has been finished); this can be widened by later batches
+    pub fn schema(&self) -> Option<SchemaRef> {
+        self.schema.clone()
+    }
+
+    /// Take out the oldest batch that's been built
+    pub fn next_batch(&mut self) -> Option<RecordBatch> {
+        self.batches.pop_front()
+    }
+
+    /// Take out all of the batches that haven't been taken yet
+    pub fn into_batches(self) -> Vec<RecordBatch> {
+        self.batches.into()
+    }
+
+    /// Convert the collected records into a batch
+    fn build_batch(&mut self) -> Result<(), EtError> {
+        let headers = self
+            .headers
+            .as_ref()
+            .ok_or("The headers must be written before any records")?;
+        // widen the types of the columns to fit the new records
+        let mut types = if self.schema.is_some() {
+            self.types.clone()
+        } else {
+            vec![DataType::Null; headers.len()]
+        };
+        for (ix, (header, dtype)) in headers.iter().zip(&mut types).enumerate() {
+            for record in &self.records {
+                *dtype = combine_types(dtype.clone(), infer_type(&record[ix])?)
+                    .map_err(|e| EtError::from(format!("Column {}: {}", header, e.msg)))?;
+            }
+        }
+        let schema = match &self.schema {
+            Some(schema) if types == self.types => Arc::clone(schema),
+            _ => {
+                let fields: Vec<_> = headers
+                    .iter()
+                    .zip(&types)
+                    .map(|(header, dtype)| Field::new(header, finalize_type(dtype.clone()), true))
+                    .collect();
+                let schema = Arc::new(Schema::new(fields));
+                // bring the batches that haven't been taken yet up to the new types
+                for batch in &mut self.batches {
+                    *batch = cast_batch(batch, Arc::clone(&schema))?;
+                }
+                self.types = types;
+                self.schema = Some(Arc::clone(&schema));
+                schema
+            }
+        };
+        if self.records.is_empty() {
+            return Ok(());
+        }
+        let n_records = self.records.len();
+        let mut columns = Vec::with_capacity(headers.len());
+        for (ix, field) in schema.fields().iter().enumerate() {
+            let values = self.records.iter().map(|record| &record[ix]);
+            let column = build_array(field.data_type(), values, n_records)
+                .map_err(|e| EtError::from(format!("Column {}: {}", field.name(), e.msg)))?;
+            columns.push(column);
+        }
+        self.batches
+            .push_back(RecordBatch::try_new(schema, columns)?);
+        self.records.clear();
+        Ok(())
+    }
+}
+
+impl RecordWriter for ArrowRecordBatchWriter {
+    fn write_headers(&mut self, headers: &[String]) -> Result<(), EtError> {
+        if self.headers.is_some() {
+            return Err("The headers have already been written".into());
+        }
+        self.headers = Some(headers.to_vec());
+        Ok(())
+    }
+
+    fn write_record(&mut self, record: &[Value]) -> Result<(), EtError> {
+        match &self.headers {
+            None => return Err("The headers must be written before any records".into()),
+            Some(headers) if headers.len() != record.len() => {
+                return Err(format!(
+                    "Record has {} fields, but there are {} headers",
+                    record.len(),
+                    headers.len()
+                )
+                .into());
+            }
+            Some(_) => {}
+        }
+        self.records
+            .push(record.iter().map(|v| v.clone().into_owned()).collect());
+        if self.records.len() >= self.batch_size {
+            self.build_batch()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), EtError> {
+        if self.headers.is_none() {
+            self.headers = Some(Vec::new());
+        }
+        self.build_batch()
+    }
+}
+
+/// Cast the columns of `batch` to the (wider) types in `schema`, e.g. for batches that were
+/// taken out of an `ArrowRecordBatchWriter` before its schema was widened
+pub fn cast_batch(batch: &RecordBatch, schema: SchemaRef) -> Result<RecordBatch, EtError> {
+    if batch.schema() == schema {
+        return Ok(batch.clone());
+    }
+    let mut columns = Vec::with_capacity(batch.num_columns());
+    for (column, field) in batch.columns().iter().zip(schema.fields()) {
+        columns.push(cast(column, field.data_type())?);
+    }
+    Ok(RecordBatch::try_new(schema, columns)?)
+}
+
+/// An empty array for each column of `schema` (e.g. to write out a file without any records)
+pub fn empty_batch(schema: SchemaRef) -> Result<RecordBatch, EtError> {
+    let columns = schema
+        .fields()
+        .iter()
+        .map(|field| new_empty_array(field.data_type()))
+        .collect();
+    Ok(RecordBatch::try_new(schema, columns)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use arrow_array::{Array, Float64Array, StringArray, TimestampMicrosecondArray};
+    use chrono::NaiveDate;
+
+    use crate::readers::get_reader;
+    use crate::writers::copy_records;
+
+    fn date_value() -> Value<'static> {
+        NaiveDate::from_ymd_opt(2001, 2, 3)
+            .unwrap()
+            .and_hms_opt(4, 5, 6)
+            .unwrap()
+            .into()
+    }
+
+    #[test]
+    fn test_arrow_writer() -> Result<(), EtError> {
+        let mut writer = ArrowRecordBatchWriter::new().batch_size(2);
+        assert!(writer.write_record(&[Value::Null]).is_err());
+        writer.write_headers(&["a".into(), "b".into(), "c".into(), "d".into()])?;
+        let date = NaiveDate::from_ymd_opt(2001, 2, 3)
+            .unwrap()
+            .and_hms_opt(4, 5, 6)
+            .unwrap();
+        writer.write_record(&[
+            Value::Integer(1),
+            "x".into(),
+            date.into(),
+            vec![Value::Integer(1), Value::Null].into(),
+        ])?;
+        writer.write_record(&[
+            Value::from(2.5),
+            Value::Integer(3),
+            Value::Null,
+            Value::Null,
+        ])?;
+        writer.write_record(&[
+            Value::Integer(4),
+            Value::Boolean(true),
+            date.into(),
+            Value::List(vec![]),
+        ])?;
+        // the third column was made into timestamps, but this widens it into strings
+        writer.write_record(&[Value::Null, Value::Null, "x".into(), Value::Null])?;
+        writer.finish()?;
+
+        let schema = writer.schema().unwrap();
+        let types: Vec<_> = schema
+            .fields()
+            .iter()
+            .map(|f| f.data_type().clone())
+            .collect();
+        assert_eq!(types[0], DataType::Float64);
+        assert_eq!(types[1], DataType::Utf8);
+        assert_eq!(types[2], DataType::Utf8);
+        assert!(matches!(&types[3], DataType::List(item) if item.data_type() == &DataType::Int64));
+
+        let batches = writer.into_batches();
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].num_rows(), 2);
+        assert_eq!(batches[1].num_rows(), 2);
+        assert!(batches.iter().all(|b| b.schema() == schema));
+        let a = batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        assert_eq!(a.values(), &[1., 2.5]);
+        let b = batches[1]
+            .column(1)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(b.value(0), "true");
+        let c = batches[0]
+            .column(2)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(c.value(0), "2001-02-03T04:05:06");
+        assert!(c.is_null(1));
+        assert_eq!(
+            batches[1]
+                .column(2)
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .unwrap()
+                .value(1),
+            "x"
+        );
+        assert!(batches[0].column(3).is_null(1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_arrow_widening() -> Result<(), EtError> {
+        let mut writer = ArrowRecordBatchWriter::new().batch_size(1);
+        writer.write_headers(&["a".into(), "b".into()])?;
+        writer.write_record(&[Value::Integer(1), Value::Null])?;
+        let first = writer.next_batch().unwrap();
+        writer.write_record(&[Value::from(0.5), date_value()])?;
+        writer.finish()?;
+
+        // a column that only had nulls takes the type of its later values
+        let schema = writer.schema().unwrap();
+        assert_eq!(schema.field(0).data_type(), &DataType::Float64);
+        assert_eq!(
+            schema.field(1).data_type(),
+            &DataType::Timestamp(TimeUnit::Microsecond, None)
+        );
+
+        // the batch that was already taken out can be cast up to the new schema
+        assert_eq!(first.schema().field(0).data_type(), &DataType::Int64);
+        let first = cast_batch(&first, Arc::clone(&schema))?;
+        let a = first
+            .column(0)
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        assert_eq!(a.value(0), 1.);
+        assert!(first
+            .column(1)
+            .as_any()
+            .downcast_ref::<TimestampMicrosecondArray>()
+            .unwrap()
+            .is_null(0));
+        assert_eq!(writer.next_batch().unwrap().schema(), schema);
+
+        // but lists can't be mixed with other values
+        let mut writer = ArrowRecordBatchWriter::new().batch_size(1);
+        writer.write_headers(&["a".into()])?;
+        writer.write_record(&[Value::Integer(1)])?;
+        assert!(writer
+            .write_record(&[vec![Value::Integer(1)].into()])
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_arrow_from_reader() -> Result<(), EtError> {
+        let data = b"@id1\nACGT\n+\n!!!!\n@id2\nTGCA\n+\n!!!!\n";
+        let (mut reader, _) = get_reader(&data[..], Some("fastq"), None)?;
+        let mut writer = ArrowRecordBatchWriter::new();
+        assert_eq!(copy_records(&mut *reader, &mut writer)?, 2);
+        let batch = writer.next_batch().unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        let ids = batch
+            .column_by_name("id")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(ids.value(1), "id2");
+        assert!(writer.next_batch().is_none());
+
+        // no records still has a schema
+        let (mut reader, _) = get_reader(&b"a\tb\n"[..], Some("tsv"), None)?;
+        let mut writer = ArrowRecordBatchWriter::new();
+        assert_eq!(copy_records(&mut *reader, &mut writer)?, 0);
+        let schema = writer.schema().unwrap();
+        assert_eq!(schema.fields().len(), 2);
+        assert_eq!(empty_batch(schema)?.num_rows(), 0);
+        Ok(())
+    }
+}
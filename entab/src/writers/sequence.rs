@@ -0,0 +1,356 @@
+use alloc::borrow::Cow;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::str::FromStr;
+use std::io::Write;
+
+use crate::error::EtError;
+use crate::record::Value;
+use crate::writers::RecordWriter;
+
+/// The sequence file formats records can be written out as
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SequenceFormat {
+    /// `>id` lines followed by the sequence
+    Fasta,
+    /// `@id`, sequence, `+`, and quality lines
+    Fastq,
+}
+
+/// How the sequences in FASTA output are split over lines
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LineWrap {
+    /// Split the sequence into lines of (at most) this many bases; 0 for one line per sequence
+    Width(usize),
+    /// Split the sequence the same way it was in the file it was read from (using the lengths
+    /// in the `line_lengths` column)
+    Preserve,
+}
+
+impl Default for LineWrap {
+    fn default() -> Self {
+        LineWrap::Width(60)
+    }
+}
+
+impl FromStr for LineWrap {
+    type Err = EtError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "preserve" => Ok(LineWrap::Preserve),
+            x => x
+                .parse()
+                .map(LineWrap::Width)
+                .map_err(|_| format!("Line width must be a number or preserve, not {}", x).into()),
+        }
+    }
+}
+
+/// Where the fields of a sequence record are in each record
+#[derive(Clone, Copy, Debug)]
+struct SequenceColumns {
+    id: usize,
+    sequence: usize,
+    quality: Option<usize>,
+    line_lengths: Option<usize>,
+    n_fields: usize,
+}
+
+/// Writes records with `id` and `sequence` (and optionally `quality`) columns out as FASTA or
+/// FASTQ records, e.g. to write a FASTQ back out after filtering it.
+///
+/// Any other columns are dropped. FASTQ records without quality scores are given
+/// `default_quality` for every base; FASTA records drop their quality scores unless
+/// `embed_quality` is set.
+#[derive(Debug)]
+pub struct SequenceWriter<W: Write> {
+    writer: W,
+    format: SequenceFormat,
+    /// For FASTA output, how to split sequences over lines
+    wrap: LineWrap,
+    /// For FASTA output, add the quality scores to the header line instead of dropping them
+    embed_quality: bool,
+    /// For FASTQ output, the quality score to use for records without any
+    default_quality: u8,
+    columns: Option<SequenceColumns>,
+    /// The record currently being built, so each one is written out with one call
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> SequenceWriter<W> {
+    /// Create a new `SequenceWriter` that writes records in `format` into `writer`
+    pub fn new(writer: W, format: SequenceFormat) -> Self {
+        SequenceWriter {
+            writer,
+            format,
+            wrap: LineWrap::default(),
+            embed_quality: false,
+            default_quality: b'I',
+            columns: None,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Create a new `SequenceWriter` that writes FASTA records into `writer`
+    pub fn fasta(writer: W) -> Self {
+        SequenceWriter::new(writer, SequenceFormat::Fasta)
+    }
+
+    /// Create a new `SequenceWriter` that writes FASTQ records into `writer`
+    pub fn fastq(writer: W) -> Self {
+        SequenceWriter::new(writer, SequenceFormat::Fastq)
+    }
+
+    /// Split FASTA sequences over lines like this (lines of 60 bases by default)
+    #[must_use]
+    pub fn line_wrap(mut self, wrap: LineWrap) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    /// Add the quality scores to FASTA header lines (as `quality=...`) instead of dropping them
+    #[must_use]
+    pub fn embed_quality(mut self, embed_quality: bool) -> Self {
+        self.embed_quality = embed_quality;
+        self
+    }
+
+    /// Give every base of FASTQ records without quality scores this score (`I` by default)
+    #[must_use]
+    pub fn default_quality(mut self, quality: u8) -> Self {
+        self.default_quality = quality;
+        self
+    }
+
+    /// Get a mutable reference to the underlying writer
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// Stop writing and return the underlying writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Serialize `record` onto the end of the buffer as a FASTA or FASTQ record
+    fn format_record(&mut self, record: &[Value], columns: SequenceColumns) -> Result<(), EtError> {
+        let id = text(&record[columns.id], "id")?;
+        let sequence = text(&record[columns.sequence], "sequence")?;
+        let quality = match columns.quality.map(|ix| &record[ix]) {
+            None | Some(Value::Null) => None,
+            Some(value) => Some(text(value, "quality")?).filter(|q| !q.is_empty()),
+        };
+        match self.format {
+            SequenceFormat::Fasta => {
+                self.buffer.push(b'>');
+                self.buffer.extend(id.as_bytes());
+                if let (true, Some(quality)) = (self.embed_quality, &quality) {
+                    self.buffer.extend(b" quality=");
+                    self.buffer.extend(quality.as_bytes());
+                }
+                self.buffer.push(b'\n');
+                let line_lengths = columns.line_lengths.map(|ix| &record[ix]);
+                self.write_wrapped(sequence.as_bytes(), line_lengths)?;
+            }
+            SequenceFormat::Fastq => {
+                self.buffer.push(b'@');
+                self.buffer.extend(id.as_bytes());
+                self.buffer.push(b'\n');
+                self.buffer.extend(sequence.as_bytes());
+                self.buffer.extend(b"\n+\n");
+                match quality {
+                    Some(quality) if quality.len() == sequence.len() => {
+                        self.buffer.extend(quality.as_bytes());
+                    }
+                    Some(_) => {
+                        return Err(format!(
+                            "Record {} has a different number of quality scores than bases",
+                            id
+                        )
+                        .into())
+                    }
+                    None => {
+                        let len = self.buffer.len() + sequence.len();
+                        self.buffer.resize(len, self.default_quality);
+                    }
+                }
+                self.buffer.push(b'\n');
+            }
+        }
+        Ok(())
+    }
+
+    /// Write out a FASTA `sequence` split over lines as set by `wrap`
+    fn write_wrapped(
+        &mut self,
+        sequence: &[u8],
+        line_lengths: Option<&Value>,
+    ) -> Result<(), EtError> {
+        let width = match (self.wrap, line_lengths) {
+            (LineWrap::Preserve, Some(lengths)) => {
+                let lengths = match lengths {
+                    Value::List(lengths) => lengths,
+                    _ => return Err("The line_lengths column must be a list".into()),
+                };
+                let mut start = 0;
+                for length in lengths {
+                    let end = match length {
+                        Value::Integer(l) => usize::try_from(*l).ok().map(|l| start + l),
+                        _ => None,
+                    }
+                    .filter(|end| *end <= sequence.len())
+                    .ok_or("The line lengths don't match the sequence")?;
+                    self.buffer.extend(&sequence[start..end]);
+                    self.buffer.push(b'\n');
+                    start = end;
+                }
+                if start != sequence.len() {
+                    return Err("The line lengths don't match the sequence".into());
+                }
+                return Ok(());
+            }
+            // there's nothing to preserve (e.g. for FASTQ input) so keep the sequence together
+            (LineWrap::Preserve, None) | (LineWrap::Width(0), _) => sequence.len().max(1),
+            (LineWrap::Width(width), _) => width,
+        };
+        if sequence.is_empty() {
+            self.buffer.push(b'\n');
+        }
+        for line in sequence.chunks(width) {
+            self.buffer.extend(line);
+            self.buffer.push(b'\n');
+        }
+        Ok(())
+    }
+}
+
+/// Get the text out of a `value` in the `column` column
+fn text<'a>(value: &'a Value<'_>, column: &str) -> Result<Cow<'a, str>, EtError> {
+    match value {
+        Value::String(s) => Ok(Cow::Borrowed(s.as_ref())),
+        Value::Integer(i) => Ok(Cow::Owned(i.to_string())),
+        Value::Null => Ok(Cow::Borrowed("")),
+        _ => Err(format!(
+            "The {} column must be text to be written as a sequence",
+            column
+        )
+        .into()),
+    }
+}
+
+impl<W: Write + ::core::fmt::Debug> RecordWriter for SequenceWriter<W> {
+    fn write_headers(&mut self, headers: &[String]) -> Result<(), EtError> {
+        if self.columns.is_some() {
+            return Err("The headers have already been written".into());
+        }
+        let column = |name: &str| headers.iter().position(|h| h == name);
+        let missing = |name: &str| {
+            EtError::from(format!(
+                "Records need a column named {} to be written as sequences",
+                name
+            ))
+        };
+        // sequence files don't have a header line so this just finds the columns
+        self.columns = Some(SequenceColumns {
+            id: column("id").ok_or_else(|| missing("id"))?,
+            sequence: column("sequence").ok_or_else(|| missing("sequence"))?,
+            quality: column("quality"),
+            line_lengths: column("line_lengths"),
+            n_fields: headers.len(),
+        });
+        Ok(())
+    }
+
+    fn write_record(&mut self, record: &[Value]) -> Result<(), EtError> {
+        let columns = match self.columns {
+            None => return Err("The headers must be written before any records".into()),
+            Some(columns) if columns.n_fields != record.len() => {
+                return Err(format!(
+                    "Record has {} fields, but there are {} headers",
+                    record.len(),
+                    columns.n_fields
+                )
+                .into());
+            }
+            Some(columns) => columns,
+        };
+        self.buffer.clear();
+        self.format_record(record, columns)?;
+        self.writer.write_all(&self.buffer)?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), EtError> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::readers::get_reader;
+    use crate::writers::copy_records;
+    use alloc::vec;
+
+    #[test]
+    fn test_sequence_writer() -> Result<(), EtError> {
+        let headers = vec!["id".to_string(), "sequence".into(), "quality".into()];
+        let record = ["a".into(), "ACGT".into(), "IIII".into()];
+        let mut writer = SequenceWriter::fasta(Vec::new());
+        assert!(writer.write_record(&record).is_err());
+        writer.write_headers(&headers)?;
+        writer.write_record(&record)?;
+        let mut out = writer.into_inner();
+        let mut writer = SequenceWriter::fasta(out)
+            .line_wrap(LineWrap::Width(3))
+            .embed_quality(true);
+        writer.write_headers(&headers)?;
+        writer.write_record(&record)?;
+        assert!(writer.write_record(&record[..2]).is_err());
+        out = writer.into_inner();
+        assert_eq!(&out[..], b">a\nACGT\n>a quality=IIII\nACG\nT\n");
+
+        let mut writer = SequenceWriter::fastq(Vec::new()).default_quality(b'5');
+        writer.write_headers(&headers[..2])?;
+        writer.write_record(&record[..2])?;
+        assert_eq!(&writer.into_inner()[..], b"@a\nACGT\n+\n5555\n");
+
+        let headers = vec!["id".to_string(), "sequence".into(), "line_lengths".into()];
+        let line_lengths = Value::List(vec![1.into(), 0.into(), 3.into()]);
+        let mut writer = SequenceWriter::fasta(Vec::new()).line_wrap(LineWrap::Preserve);
+        writer.write_headers(&headers)?;
+        writer.write_record(&["a".into(), "ACGT".into(), line_lengths])?;
+        assert_eq!(&writer.into_inner()[..], b">a\nA\n\nCGT\n");
+
+        assert!(SequenceWriter::fasta(Vec::new())
+            .write_headers(&headers[..1])
+            .is_err());
+        assert_eq!("preserve".parse::<LineWrap>()?, LineWrap::Preserve);
+        assert_eq!("80".parse::<LineWrap>()?, LineWrap::Width(80));
+        assert!("wide".parse::<LineWrap>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_fastq_round_trip() -> Result<(), EtError> {
+        let data = b"@r1 sample=a\nACGT\n+\nII#I\n@r2\nGG\n+\n!!\n";
+        let (mut reader, _) = get_reader(&data[..], None, None)?;
+        let mut writer = SequenceWriter::fastq(Vec::new());
+        assert_eq!(copy_records(&mut *reader, &mut writer)?, 2);
+        assert_eq!(&writer.into_inner()[..], &data[..]);
+
+        // and a FASTA can be written back out with its original line wrapping
+        let data = b">a\nACG\nTA\n>b\nTTTT\n";
+        let mut params = alloc::collections::BTreeMap::new();
+        let _ = params.insert("line_lengths".to_string(), Value::Boolean(true));
+        let (mut reader, _) = get_reader(&data[..], Some("fasta"), Some(params))?;
+        let mut writer = SequenceWriter::fasta(Vec::new()).line_wrap(LineWrap::Preserve);
+        assert_eq!(copy_records(&mut *reader, &mut writer)?, 2);
+        assert_eq!(&writer.into_inner()[..], &data[..]);
+        Ok(())
+    }
+}